@@ -0,0 +1,56 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Run the standard scenes against the platform's default backend.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use piet::{Error, RenderContext};
+use piet_bench::{Backend, Scene};
+use piet_common::Device;
+
+/// The backend selected by `piet-common` for the current platform.
+struct CommonBackend {
+    device: Device,
+    scale: f64,
+}
+
+impl Backend for CommonBackend {
+    fn name(&self) -> &str {
+        if cfg!(target_os = "windows") {
+            "direct2d"
+        } else if cfg!(any(target_os = "macos", target_os = "ios")) {
+            "coregraphics"
+        } else {
+            "cairo"
+        }
+    }
+
+    fn render(&mut self, scene: Scene) -> Result<(), Error> {
+        let size = scene.size() * self.scale;
+        let mut target =
+            self.device
+                .bitmap_target(size.width as usize, size.height as usize, self.scale)?;
+        let mut rc = target.render_context();
+        scene.draw(&mut rc)?;
+        rc.finish()
+    }
+}
+
+fn backends() -> Vec<Box<dyn Backend>> {
+    let device = Device::new().expect("failed to create device");
+    vec![Box::new(CommonBackend { device, scale: 1.0 })]
+}
+
+pub fn bench_scenes(c: &mut Criterion) {
+    for mut backend in backends() {
+        for scene in Scene::ALL {
+            let name = format!("{}/{}", backend.name(), scene.name());
+            c.bench_function(&name, |b| {
+                b.iter(|| backend.render(scene).expect("failed to render scene"))
+            });
+        }
+    }
+}
+
+criterion_group!(benches, bench_scenes);
+criterion_main!(benches);
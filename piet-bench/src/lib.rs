@@ -0,0 +1,205 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! A standard set of scenes for benchmarking piet backends.
+//!
+//! Each [`Scene`] exercises one area of the API (paths, gradients, text,
+//! images) and can be drawn with any [`RenderContext`]. Backends are driven
+//! through the object-safe [`Backend`] trait, so that the same benchmark code
+//! can produce comparable numbers for every backend.
+//!
+//! The benchmarks themselves live in `benches/scenes.rs` and can be run with
+//! `cargo bench -p piet-bench`.
+
+#![warn(missing_docs)]
+
+use piet::kurbo::{Affine, BezPath, Circle, Point, Rect, Size, Vec2};
+use piet::{
+    Color, Error, FixedGradient, FixedLinearGradient, FixedRadialGradient, GradientStop,
+    ImageFormat, InterpolationMode, RenderContext, Text, TextAttribute, TextLayoutBuilder,
+};
+
+/// One of the standard benchmark scenes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scene {
+    /// Many filled and stroked curved paths.
+    Paths,
+    /// Rectangles and circles filled with linear and radial gradients.
+    Gradients,
+    /// Building and drawing text layouts with a variety of attributes.
+    Text,
+    /// Building and drawing a long list of short labels, which measures the
+    /// fixed cost of each layout.
+    Labels,
+    /// Uploading an image and drawing it at several scales.
+    Images,
+}
+
+/// A backend that can render the benchmark scenes.
+pub trait Backend {
+    /// A short name identifying the backend, used to label results.
+    fn name(&self) -> &str;
+
+    /// Render `scene` once, including any work needed to finish the frame.
+    fn render(&mut self, scene: Scene) -> Result<(), Error>;
+}
+
+const LOREM: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis \
+nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.";
+
+impl Scene {
+    /// All the scenes, in a stable order.
+    pub const ALL: [Scene; 5] = [
+        Scene::Paths,
+        Scene::Gradients,
+        Scene::Text,
+        Scene::Labels,
+        Scene::Images,
+    ];
+
+    /// The name of the scene, suitable for labelling results.
+    pub fn name(self) -> &'static str {
+        match self {
+            Scene::Paths => "paths",
+            Scene::Gradients => "gradients",
+            Scene::Text => "text",
+            Scene::Labels => "labels",
+            Scene::Images => "images",
+        }
+    }
+
+    /// The size of the context expected by this scene, in pixels.
+    pub fn size(self) -> Size {
+        Size::new(512., 512.)
+    }
+
+    /// Draw the scene.
+    pub fn draw<R: RenderContext>(self, rc: &mut R) -> Result<(), Error> {
+        rc.clear(None, Color::WHITE);
+        match self {
+            Scene::Paths => draw_paths(rc),
+            Scene::Gradients => draw_gradients(rc),
+            Scene::Text => draw_text(rc),
+            Scene::Labels => draw_labels(rc),
+            Scene::Images => draw_images(rc),
+        }
+    }
+}
+
+fn draw_paths<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    let fill = rc.solid_brush(Color::rgba8(0x40, 0x80, 0xc0, 0x80));
+    let stroke = rc.solid_brush(Color::BLACK);
+    for i in 0..100 {
+        let t = i as f64 * 0.1;
+        let center = Point::new(256. + 180. * t.cos(), 256. + 180. * (1.3 * t).sin());
+        let mut path = BezPath::new();
+        path.move_to(center + Vec2::new(-40., 0.));
+        for j in 0..8 {
+            let angle = (j as f64 + t) * std::f64::consts::FRAC_PI_4;
+            let radius = 20. + 20. * (angle * 3.).sin().abs();
+            let p = center + Vec2::from_angle(angle) * radius;
+            let ctrl = center + Vec2::from_angle(angle - 0.4) * (radius * 1.5);
+            path.quad_to(ctrl, p);
+        }
+        path.close_path();
+        rc.fill(&path, &fill);
+        rc.stroke(&path, &stroke, 1.0);
+    }
+    Ok(())
+}
+
+fn draw_gradients<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    let stops = vec![
+        GradientStop {
+            pos: 0.0,
+            color: Color::rgb8(0xff, 0x80, 0x00),
+        },
+        GradientStop {
+            pos: 0.5,
+            color: Color::WHITE,
+        },
+        GradientStop {
+            pos: 1.0,
+            color: Color::rgb8(0x00, 0x40, 0xff),
+        },
+    ];
+    for i in 0..8 {
+        for j in 0..8 {
+            let origin = Point::new(i as f64 * 64., j as f64 * 64.);
+            let rect = Rect::from_origin_size(origin, (60., 60.));
+            let gradient = if (i + j) % 2 == 0 {
+                FixedGradient::Linear(FixedLinearGradient {
+                    start: rect.origin(),
+                    end: Point::new(rect.x1, rect.y1),
+                    stops: stops.clone(),
+                })
+            } else {
+                FixedGradient::Radial(FixedRadialGradient {
+                    center: rect.center(),
+                    origin_offset: Vec2::new(-10., -10.),
+                    radius: 30.,
+                    start_radius: 0.0,
+                    stops: stops.clone(),
+                })
+            };
+            let brush = rc.gradient(gradient)?;
+            rc.fill(rect, &brush);
+            rc.fill(Circle::new(rect.center(), 20.), &brush);
+        }
+    }
+    Ok(())
+}
+
+fn draw_text<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    for i in 0..6 {
+        let size = 10. + i as f64 * 2.;
+        let layout = rc
+            .text()
+            .new_text_layout(LOREM)
+            .max_width(480.)
+            .default_attribute(TextAttribute::FontSize(size))
+            .range_attribute(6..11, TextAttribute::Weight(piet::FontWeight::BOLD))
+            .range_attribute(12..17, TextAttribute::Style(piet::FontStyle::Italic))
+            .range_attribute(18..21, TextAttribute::TextColor(Color::rgb8(0xc0, 0, 0)))
+            .range_attribute(22..26, TextAttribute::Underline(true))
+            .build()?;
+        rc.draw_text(&layout, (16., 16. + i as f64 * 80.));
+    }
+    Ok(())
+}
+
+fn draw_labels<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    for i in 0..200 {
+        let layout = rc
+            .text()
+            .new_text_layout(format!("Item {i}"))
+            .default_attribute(TextAttribute::FontSize(12.))
+            .build()?;
+        let (column, row) = (i / 40, i % 40);
+        rc.draw_text(&layout, (8. + column as f64 * 100., 4. + row as f64 * 12.5));
+    }
+    Ok(())
+}
+
+fn draw_images<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    const WIDTH: usize = 256;
+    const HEIGHT: usize = 256;
+    let mut buf = vec![0u8; WIDTH * HEIGHT * 4];
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % WIDTH, i / WIDTH);
+        px.copy_from_slice(&[x as u8, y as u8, (x ^ y) as u8, 0xff]);
+    }
+    let image = rc.make_image(WIDTH, HEIGHT, &buf, ImageFormat::RgbaSeparate)?;
+    for (i, scale) in [0.25, 0.5, 1.0, 1.5].into_iter().enumerate() {
+        rc.with_save(|rc| {
+            rc.transform(Affine::translate((i as f64 * 24., i as f64 * 24.)));
+            let dst =
+                Rect::from_origin_size(Point::ZERO, Size::new(WIDTH as f64, HEIGHT as f64) * scale);
+            rc.draw_image(&image, dst, InterpolationMode::Bilinear);
+            rc.draw_image(&image, dst, InterpolationMode::NearestNeighbor);
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
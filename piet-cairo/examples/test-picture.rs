@@ -28,6 +28,8 @@ fn run_sample(
     let mut device = Device::new()?;
     let mut target = device.bitmap_target(size.width as usize, size.height as usize, scale)?;
     let mut piet_context = target.render_context();
+    // place underlines and strikethroughs the way the other backends do
+    piet_context.text().set_draw_decorations(true);
 
     sample.draw(&mut piet_context)?;
 
@@ -0,0 +1,139 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! A private fontconfig configuration, for hermetic font sets.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_void;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{fs, process, ptr};
+
+use fontconfig_sys as fc;
+use pango::glib::translate::ToGlibPtr;
+
+use piet::Error;
+
+// Not exposed by pango-sys; the fontmap created by pangocairo for the
+// FreeType font type is a `PangoFcFontMap`.
+#[link(name = "pangoft2-1.0")]
+extern "C" {
+    fn pango_fc_font_map_set_config(fcfontmap: *mut c_void, fcconfig: *mut fc::FcConfig);
+    fn pango_fc_font_map_config_changed(fcfontmap: *mut c_void);
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A pango fontmap backed by its own fontconfig configuration.
+///
+/// Fonts added with [`IsolatedFonts::add_font`] are only visible through this
+/// fontmap, not to the rest of the process.
+pub(crate) struct IsolatedFonts {
+    config: *mut fc::FcConfig,
+    font_map: pango::FontMap,
+    /// Fontconfig only loads fonts from files, so loaded font data is
+    /// written here; removed on drop.
+    dir: PathBuf,
+    n_fonts: usize,
+}
+
+impl IsolatedFonts {
+    /// A configuration with no fonts at all.
+    pub(crate) fn empty() -> Result<IsolatedFonts, Error> {
+        // SAFETY: a newly created config has no fonts and no font directories.
+        IsolatedFonts::new(unsafe { fc::FcConfigCreate() })
+    }
+
+    /// A configuration with the fonts installed on the system.
+    pub(crate) fn with_system_fonts() -> Result<IsolatedFonts, Error> {
+        // SAFETY: this loads a new config, separate from the current one.
+        IsolatedFonts::new(unsafe { fc::FcInitLoadConfigAndFonts() })
+    }
+
+    /// Takes ownership of `config`.
+    fn new(config: *mut fc::FcConfig) -> Result<IsolatedFonts, Error> {
+        if config.is_null() {
+            return Err(Error::OutOfMemory);
+        }
+        let Some(font_map) = pangocairo::FontMap::for_font_type(cairo::FontType::FontTypeFt) else {
+            // SAFETY: we own the only reference.
+            unsafe { fc::FcConfigDestroy(config) };
+            return Err(Error::NotSupported);
+        };
+        let dir = std::env::temp_dir().join(format!(
+            "piet-cairo-fonts-{}-{}",
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = fs::create_dir_all(&dir) {
+            // SAFETY: we own the only reference.
+            unsafe { fc::FcConfigDestroy(config) };
+            return Err(Error::BackendError(Box::new(e)));
+        }
+
+        // SAFETY: the fontmap takes its own reference to the config.
+        unsafe {
+            let raw_map: *mut pango::ffi::PangoFontMap = font_map.to_glib_none().0;
+            pango_fc_font_map_set_config(raw_map.cast(), config);
+        }
+        Ok(IsolatedFonts {
+            config,
+            font_map,
+            dir,
+            n_fonts: 0,
+        })
+    }
+
+    pub(crate) fn font_map(&self) -> &pango::FontMap {
+        &self.font_map
+    }
+
+    /// Add a font to the configuration, returning its family name.
+    pub(crate) fn add_font(&mut self, data: &[u8]) -> Result<String, Error> {
+        let path = self.dir.join(format!("font-{}", self.n_fonts));
+        fs::write(&path, data).map_err(|e| Error::BackendError(Box::new(e)))?;
+        self.n_fonts += 1;
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|_| Error::InvalidInput)?;
+        let c_path = c_path.as_ptr() as *const fc::FcChar8;
+
+        // SAFETY: `c_path` is a valid nul-terminated string, and the pattern
+        // returned by `FcFreeTypeQuery` is destroyed once the family name has
+        // been copied out of it.
+        let family = unsafe {
+            let mut count = 0;
+            let pattern = fc::FcFreeTypeQuery(c_path, 0, ptr::null_mut(), &mut count);
+            if pattern.is_null() {
+                return Err(Error::FontLoadingFailed);
+            }
+            let mut name: *mut fc::FcChar8 = ptr::null_mut();
+            let result =
+                fc::FcPatternGetString(pattern, fc::constants::FC_FAMILY.as_ptr(), 0, &mut name);
+            let family = (result == fc::FcResultMatch && !name.is_null()).then(|| {
+                CStr::from_ptr(name as *const _)
+                    .to_string_lossy()
+                    .into_owned()
+            });
+            fc::FcPatternDestroy(pattern);
+            family.ok_or(Error::FontLoadingFailed)?
+        };
+
+        // SAFETY: the config is owned by us and still alive.
+        unsafe {
+            if fc::FcConfigAppFontAddFile(self.config, c_path) == 0 {
+                return Err(Error::FontLoadingFailed);
+            }
+            let raw_map: *mut pango::ffi::PangoFontMap = self.font_map.to_glib_none().0;
+            pango_fc_font_map_config_changed(raw_map.cast());
+        }
+        Ok(family)
+    }
+}
+
+impl Drop for IsolatedFonts {
+    fn drop(&mut self) {
+        // SAFETY: this releases our reference; the fontmap holds its own.
+        unsafe { fc::FcConfigDestroy(self.config) };
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
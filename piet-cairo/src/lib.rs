@@ -6,21 +6,26 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 #![deny(clippy::trivially_copy_pass_by_ref)]
 
+#[cfg(all(unix, not(target_vendor = "apple")))]
+mod fontconfig;
 mod text;
 
+use std::any::Any;
 use std::borrow::Cow;
+use std::rc::Rc;
 
-use cairo::{Context, Filter, Format, ImageSurface, Matrix, Rectangle, SurfacePattern};
+use cairo::{Context, Extend, Filter, Format, ImageSurface, Matrix, Rectangle, SurfacePattern};
 
-use piet::kurbo::{Affine, PathEl, Point, QuadBez, Rect, Shape, Size};
+use piet::kurbo::{self, Affine, PathEl, Point, QuadBez, Rect, Shape, Size};
+use piet::util::premul;
 use piet::{
-    Color, Error, FixedGradient, Image, ImageFormat, InterpolationMode, IntoBrush, LineCap,
-    LineJoin, RenderContext, StrokeStyle,
+    CachedShape, Color, Error, FixedGradient, Image, ImageArgsError, ImageFormat,
+    InterpolationMode, IntoBrush, LineCap, LineJoin, RenderContext, StrokeStyle, TileMode,
 };
 
 pub use cairo;
 
-pub use crate::text::{CairoText, CairoTextLayout, CairoTextLayoutBuilder};
+pub use crate::text::{CairoText, CairoTextLayout, CairoTextLayoutBuilder, DecorationMetrics};
 
 pub struct CairoRenderContext<'a> {
     // Cairo has this as Clone and with &self methods, but we do this to avoid
@@ -33,6 +38,9 @@ pub struct CairoRenderContext<'a> {
     // only those transforms applied by us.
     transform_stack: Vec<Affine>,
     error: Result<(), cairo::Error>,
+    // Whether the target is a vector surface, such as PDF or SVG.
+    vector: bool,
+    scale: f64,
 }
 
 #[derive(Clone)]
@@ -70,10 +78,12 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
 
     type Image = CairoImage;
 
+    type CachedShape = CachedShape<Rc<cairo::Path>>;
+
     fn status(&mut self) -> Result<(), Error> {
         match self.error {
             Ok(_) => Ok(()),
-            Err(err) => Err(Error::BackendError(err.into())),
+            Err(err) => Err(convert_error(err)),
         }
     }
 
@@ -95,7 +105,14 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
                 byte_to_frac(rgba >> 8),
                 byte_to_frac(rgba),
             );
-            rc.ctx.set_operator(cairo::Operator::Source);
+            // Vector surfaces can't represent the source operator, and fall
+            // back to rasterizing. For an opaque color, over is equivalent.
+            let operator = if rc.vector && rgba & 0xff == 0xff {
+                cairo::Operator::Over
+            } else {
+                cairo::Operator::Source
+            };
+            rc.ctx.set_operator(operator);
             rc.ctx.paint().map_err(convert_error)
         });
     }
@@ -116,12 +133,66 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
             FixedGradient::Radial(radial) => {
                 let (xc, yc) = (radial.center.x, radial.center.y);
                 let (xo, yo) = (radial.origin_offset.x, radial.origin_offset.y);
-                let r = radial.radius;
-                let rg = cairo::RadialGradient::new(xc + xo, yc + yo, 0.0, xc, yc, r);
+                let (r0, r) = (radial.start_radius, radial.radius);
+                let rg = cairo::RadialGradient::new(xc + xo, yc + yo, r0, xc, yc, r);
                 set_gradient_stops!(&rg, &radial.stops);
                 Ok(Brush::Radial(rg))
             }
+            FixedGradient::Elliptical(elliptical) => {
+                let (radial, transform) = elliptical.to_radial();
+                self.gradient_with_transform(radial, transform)
+            }
+        }
+    }
+
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Brush, Error> {
+        if transform.determinant() == 0.0 {
+            return Err(Error::InvalidInput);
+        }
+        let gradient = match gradient.into() {
+            FixedGradient::Elliptical(elliptical) => {
+                let (radial, radial_transform) = elliptical.to_radial();
+                return self.gradient_with_transform(radial, transform * radial_transform);
+            }
+            gradient => gradient,
+        };
+        // A pattern's matrix maps user space to pattern space.
+        let matrix = affine_to_matrix(transform.inverse());
+        let brush = self.gradient(gradient)?;
+        match &brush {
+            Brush::Linear(lg) => lg.set_matrix(matrix),
+            Brush::Radial(rg) => rg.set_matrix(matrix),
+            Brush::Solid(_) => (),
         }
+        Ok(brush)
+    }
+
+    fn brush_with_opacity(&mut self, brush: &Brush, opacity: f64) -> Brush {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let result = match brush {
+            Brush::Solid(rgba) => {
+                let alpha = ((rgba & 0xff) as f64 * opacity).round() as u32;
+                Ok(Brush::Solid((rgba & !0xff) | alpha))
+            }
+            Brush::Linear(lg) => lg.linear_points().and_then(|(x0, y0, x1, y1)| {
+                let faded = cairo::LinearGradient::new(x0, y0, x1, y1);
+                copy_faded_gradient(lg, &faded, opacity)?;
+                Ok(Brush::Linear(faded))
+            }),
+            Brush::Radial(rg) => rg.radial_circles().and_then(|(x0, y0, r0, x1, y1, r1)| {
+                let faded = cairo::RadialGradient::new(x0, y0, r0, x1, y1, r1);
+                copy_faded_gradient(rg, &faded, opacity)?;
+                Ok(Brush::Radial(faded))
+            }),
+        };
+        result.unwrap_or_else(|e| {
+            self.error = Err(e);
+            brush.clone()
+        })
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
@@ -140,12 +211,50 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         self.error = self.ctx.fill();
     }
 
+    fn fill_mesh(&mut self, vertices: &[(Point, Color)], indices: &[u16]) {
+        let mesh = cairo::Mesh::new();
+        let corners = [
+            cairo::MeshCorner::MeshCorner0,
+            cairo::MeshCorner::MeshCorner1,
+            cairo::MeshCorner::MeshCorner2,
+        ];
+        for tri in indices.chunks_exact(3) {
+            let Some(tri) = tri
+                .iter()
+                .map(|&i| vertices.get(i as usize))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            mesh.begin_patch();
+            mesh.move_to(tri[0].0.x, tri[0].0.y);
+            mesh.line_to(tri[1].0.x, tri[1].0.y);
+            mesh.line_to(tri[2].0.x, tri[2].0.y);
+            for (&corner, (_, color)) in corners.iter().zip(tri) {
+                let (r, g, b, a) = color.as_rgba();
+                mesh.set_corner_color_rgba(corner, r, g, b, a);
+            }
+            mesh.end_patch();
+        }
+        if let Err(e) = self.ctx.set_source(&mesh) {
+            self.error = Err(e);
+            return;
+        }
+        self.error = self.ctx.paint();
+    }
+
     fn clip(&mut self, shape: impl Shape) {
         self.set_path(shape);
         self.ctx.set_fill_rule(cairo::FillRule::Winding);
         self.ctx.clip();
     }
 
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        self.set_path(shape);
+        self.ctx.set_fill_rule(cairo::FillRule::EvenOdd);
+        self.ctx.clip();
+    }
+
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
@@ -154,6 +263,26 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         self.error = self.ctx.stroke();
     }
 
+    fn stroke_hairline(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_path(shape);
+        // one device pixel, before the scale factor
+        self.set_stroke(self.scale.recip(), None);
+        // Set the brush first, so gradients stay in user space.
+        self.set_brush(&brush);
+        // The path is already in device space, so undoing our transforms
+        // only affects the line width.
+        let transform = self.current_transform();
+        if transform.determinant() != 0.0 {
+            let matrix = self.ctx.matrix();
+            self.ctx.transform(affine_to_matrix(transform.inverse()));
+            self.error = self.ctx.stroke();
+            self.ctx.set_matrix(matrix);
+        } else {
+            self.error = self.ctx.stroke();
+        }
+    }
+
     fn stroke_styled(
         &mut self,
         shape: impl Shape,
@@ -168,6 +297,64 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         self.error = self.ctx.stroke();
     }
 
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error> {
+        // Cairo converts paths between user and device space when copying and
+        // appending them, so build the path under the identity matrix to get
+        // it back in shape coordinates.
+        let matrix = self.ctx.matrix();
+        self.ctx.identity_matrix();
+        let path = shape.into_path(1e-3);
+        self.set_path(&path);
+        let geometry = self.ctx.copy_path();
+        self.ctx.new_path();
+        self.ctx.set_matrix(matrix);
+        Ok(CachedShape::new(
+            path,
+            Rc::new(geometry.map_err(convert_error)?),
+        ))
+    }
+
+    fn fill_cached(&mut self, shape: &Self::CachedShape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_cached_path(shape);
+        self.set_brush(&brush);
+        self.ctx.set_fill_rule(cairo::FillRule::Winding);
+        self.error = self.ctx.fill();
+    }
+
+    fn stroke_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_cached_path(shape);
+        self.set_stroke(width, None);
+        self.set_brush(&brush);
+        self.error = self.ctx.stroke();
+    }
+
+    fn stroke_styled_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_cached_path(shape);
+        self.set_stroke(width, Some(style));
+        self.set_brush(&brush);
+        self.error = self.ctx.stroke();
+    }
+
+    fn clip_cached(&mut self, shape: &Self::CachedShape) {
+        self.set_cached_path(shape);
+        self.ctx.set_fill_rule(cairo::FillRule::Winding);
+        self.ctx.clip();
+    }
+
     fn text(&mut self) -> &mut Self::Text {
         &mut self.text
     }
@@ -177,6 +364,9 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         let offset = layout.pango_offset();
         self.ctx.move_to(pos.x - offset.x, pos.y - offset.y);
         pangocairo::functions::show_layout(self.ctx, layout.pango_layout());
+        for (path, color, width) in layout.decoration_paths() {
+            self.stroke(Affine::translate(pos.to_vec2()) * path, &color, width);
+        }
     }
 
     fn save(&mut self) -> Result<(), Error> {
@@ -214,8 +404,6 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         self.transform_stack.last().copied().unwrap_or_default()
     }
 
-    // allows e.g. raw_data[dst_off + x * 4 + 2] = buf[src_off + x * 4 + 0];
-    #[allow(clippy::identity_op)]
     fn make_image_with_stride(
         &mut self,
         width: usize,
@@ -224,15 +412,12 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
-        let cairo_fmt = match format {
-            ImageFormat::Rgb | ImageFormat::Grayscale => Format::Rgb24,
-            ImageFormat::RgbaSeparate | ImageFormat::RgbaPremul => Format::ARgb32,
-            _ => return Err(Error::NotSupported),
-        };
-        let width_int = width as i32;
-        let height_int = height as i32;
-        let mut image = ImageSurface::create(cairo_fmt, width_int, height_int)
-            .map_err(|e| Error::BackendError(Box::new(e)))?;
+        piet::util::validate_image_args(width, height, stride, format, buf.len())?;
+        let cairo_fmt = cairo_format(format)?;
+        let width_int = i32::try_from(width).map_err(|_| ImageArgsError::TooLarge)?;
+        let height_int = i32::try_from(height).map_err(|_| ImageArgsError::TooLarge)?;
+        let mut image =
+            ImageSurface::create(cairo_fmt, width_int, height_int).map_err(convert_error)?;
 
         // early-return if the image has no data in it
         if width_int == 0 || height_int == 0 {
@@ -242,82 +427,64 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         // Confident no borrow errors because we just created it.
         let image_stride = image.stride() as usize;
         {
-            if buf.len()
-                < piet::util::expected_image_buffer_size(
-                    width * format.bytes_per_pixel(),
-                    height,
-                    stride,
-                )
-            {
-                return Err(Error::InvalidInput);
-            }
-
             let mut data = image.data().map_err(|e| Error::BackendError(Box::new(e)))?;
-            for y in 0..height {
-                let src_off = y * stride;
-                let data = &mut data[y * image_stride..];
-                match format {
-                    ImageFormat::Rgb => {
-                        for x in 0..width {
-                            write_rgb(
-                                data,
-                                x,
-                                buf[src_off + x * 3 + 0],
-                                buf[src_off + x * 3 + 1],
-                                buf[src_off + x * 3 + 2],
-                            );
-                        }
-                    }
-                    ImageFormat::RgbaPremul => {
-                        // It's annoying that Cairo exposes only ARGB. Ah well. Let's
-                        // hope that LLVM generates pretty good code for this.
-                        // TODO: consider adding BgraPremul format.
-                        for x in 0..width {
-                            write_rgba(
-                                data,
-                                x,
-                                buf[src_off + x * 4 + 0],
-                                buf[src_off + x * 4 + 1],
-                                buf[src_off + x * 4 + 2],
-                                buf[src_off + x * 4 + 3],
-                            );
-                        }
-                    }
-                    ImageFormat::RgbaSeparate => {
-                        fn premul(x: u8, a: u8) -> u8 {
-                            let y = (x as u16) * (a as u16);
-                            ((y + (y >> 8) + 0x80) >> 8) as u8
-                        }
-                        for x in 0..width {
-                            let a = buf[src_off + x * 4 + 3];
-                            write_rgba(
-                                data,
-                                x,
-                                premul(buf[src_off + x * 4 + 0], a),
-                                premul(buf[src_off + x * 4 + 1], a),
-                                premul(buf[src_off + x * 4 + 2], a),
-                                a,
-                            );
-                        }
-                    }
-                    ImageFormat::Grayscale => {
-                        for x in 0..width {
-                            write_rgb(
-                                data,
-                                x,
-                                buf[src_off + x],
-                                buf[src_off + x],
-                                buf[src_off + x],
-                            );
-                        }
-                    }
-                    _ => return Err(Error::NotSupported),
-                }
-            }
+            write_image_data(&mut data, image_stride, width, height, stride, buf, format)?;
         }
         Ok(CairoImage(image))
     }
 
+    fn update_image(
+        &mut self,
+        image: &mut Self::Image,
+        buf: &[u8],
+        format: ImageFormat,
+        region: Option<Rect>,
+    ) -> Result<(), Error> {
+        let surface = &image.0;
+        if cairo_format(format)? != surface.format() {
+            return Err(Error::InvalidInput);
+        }
+        let width = surface.width() as usize;
+        let height = surface.height() as usize;
+        let stride = width * format.bytes_per_pixel();
+        piet::util::validate_image_args(width, height, stride, format, buf.len())?;
+        let region = piet::util::image_region(region, width, height);
+        if region.area() == 0.0 {
+            return Ok(());
+        }
+        let (x0, y0) = (region.x0 as usize, region.y0 as usize);
+        let image_stride = surface.stride() as usize;
+
+        surface.flush();
+        // `ImageSurface::data` refuses surfaces that are shared, as this one
+        // may be with clones of the image. Writing through the pointer is
+        // still sound: a borrow from `data` would need unique ownership of
+        // the surface, and we have a reference to it.
+        let data = unsafe {
+            let ptr = cairo::ffi::cairo_image_surface_get_data(surface.to_raw_none());
+            if ptr.is_null() {
+                return Err(Error::BackendError("image surface has no data".into()));
+            }
+            std::slice::from_raw_parts_mut(ptr, image_stride * height)
+        };
+        let result = write_image_data(
+            &mut data[y0 * image_stride + x0 * 4..],
+            image_stride,
+            region.width() as usize,
+            region.height() as usize,
+            stride,
+            &buf[y0 * stride + x0 * format.bytes_per_pixel()..],
+            format,
+        );
+        surface.mark_dirty_rectangle(
+            x0 as i32,
+            y0 as i32,
+            region.width() as i32,
+            region.height() as i32,
+        );
+        result
+    }
+
     #[inline]
     fn draw_image(
         &mut self,
@@ -325,7 +492,7 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        self.draw_image_inner(&image.0, None, dst_rect.into(), interp);
+        self.draw_image_inner(&image.0, None, dst_rect.into(), interp, 1.0);
     }
 
     #[inline]
@@ -336,7 +503,58 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        self.draw_image_inner(&image.0, Some(src_rect.into()), dst_rect.into(), interp);
+        self.draw_image_inner(
+            &image.0,
+            Some(src_rect.into()),
+            dst_rect.into(),
+            interp,
+            1.0,
+        );
+    }
+
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    ) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        self.draw_image_inner(
+            &image.0,
+            Some(src_rect.into()),
+            dst_rect.into(),
+            interp,
+            opacity,
+        );
+    }
+
+    fn draw_image_tiled(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        tile_mode: TileMode,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into().abs();
+        // As in `draw_image_inner`, painting an empty image is an error.
+        if dst_rect.is_zero_area() || image.0.width() == 0 || image.0.height() == 0 {
+            return;
+        }
+        let _ = self.with_save(|rc| {
+            let surface_pattern = SurfacePattern::create(&image.0);
+            surface_pattern.set_filter(convert_interpolation(interp));
+            surface_pattern.set_extend(match tile_mode {
+                TileMode::Repeat => Extend::Repeat,
+                TileMode::Mirror => Extend::Reflect,
+            });
+            rc.clip(dst_rect);
+            rc.ctx.translate(dst_rect.x0, dst_rect.y0);
+            rc.error = rc.ctx.set_source(&surface_pattern);
+            rc.error = rc.ctx.paint();
+            Ok(())
+        });
     }
 
     fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
@@ -382,6 +600,14 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         Ok(CairoImage(target_surface))
     }
 
+    fn scale_factor(&self) -> f64 {
+        self.scale
+    }
+
+    fn backend_handle(&self) -> Option<&dyn Any> {
+        Some(self.ctx)
+    }
+
     fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl IntoBrush<Self>) {
         let brush = brush.make_brush(self, || rect);
         match compute_blurred_rect(rect, blur_radius) {
@@ -411,6 +637,18 @@ impl Image for CairoImage {
     fn size(&self) -> Size {
         Size::new(self.0.width().into(), self.0.height().into())
     }
+
+    fn format(&self) -> ImageFormat {
+        // `Rgb24` stores each pixel in 32 bits, with the top byte unused
+        match self.0.format() {
+            Format::Rgb24 => ImageFormat::Rgb,
+            _ => ImageFormat::RgbaPremul,
+        }
+    }
+
+    fn stride(&self) -> usize {
+        self.0.stride() as usize
+    }
 }
 
 impl<'a> CairoRenderContext<'a> {
@@ -425,9 +663,54 @@ impl<'a> CairoRenderContext<'a> {
             text: CairoText::new(),
             transform_stack: Vec::new(),
             error: Ok(()),
+            vector: false,
+            scale: 1.0,
         }
     }
 
+    /// Create a new Cairo back-end for drawing to a vector surface.
+    ///
+    /// This should be used when the target of `ctx` is a PDF, PostScript or
+    /// SVG surface. Text is laid out without hinting, so that it can be
+    /// embedded as positioned glyphs, and operations that a vector surface
+    /// would otherwise rasterize are avoided where possible.
+    ///
+    /// Images are embedded as images, and [`blurred_rect`] is drawn as an
+    /// image mask, as these have no vector representation.
+    ///
+    /// [`blurred_rect`]: RenderContext::blurred_rect
+    pub fn new_for_vector_surface(ctx: &Context) -> CairoRenderContext<'_> {
+        CairoRenderContext {
+            ctx,
+            text: CairoText::new().into_vector(),
+            transform_stack: Vec::new(),
+            error: Ok(()),
+            vector: true,
+            scale: 1.0,
+        }
+    }
+
+    /// Replace the [`CairoText`] used for text layout.
+    ///
+    /// This can be used to draw with a fontmap other than the default one,
+    /// such as one made with [`CairoText::new_isolated`]. If this context
+    /// draws to a vector surface, `text` is switched to unhinted layout.
+    pub fn set_text(&mut self, text: CairoText) {
+        self.text = if self.vector && !text.is_vector() {
+            text.into_vector()
+        } else {
+            text
+        };
+    }
+
+    /// Set the scale factor reported by [`RenderContext::scale_factor`].
+    ///
+    /// This does not change how anything is drawn; the caller is expected to
+    /// have applied the same scale to the cairo context.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+
     /// Set the source pattern to the brush.
     ///
     /// Cairo is super stateful, and we're trying to have more retained stuff.
@@ -464,6 +747,10 @@ impl<'a> CairoRenderContext<'a> {
         // This shouldn't be necessary, we always leave the context in no-path
         // state. But just in case, and it should be harmless.
         self.ctx.new_path();
+        if let Some(arc) = piet::util::as_arc(&shape) {
+            self.set_arc_path(&arc);
+            return;
+        }
         let mut last = Point::ZERO;
         for el in shape.path_elements(1e-3) {
             match el {
@@ -491,12 +778,39 @@ impl<'a> CairoRenderContext<'a> {
         }
     }
 
+    /// Set the path to an elliptical arc, with cairo's own arc rather than
+    /// Béziers.
+    fn set_arc_path(&mut self, arc: &kurbo::Arc) {
+        // The path is kept in device space, so it isn't changed by
+        // restoring the transform used to build it.
+        let matrix = self.ctx.matrix();
+        self.ctx.translate(arc.center.x, arc.center.y);
+        self.ctx.rotate(arc.x_rotation);
+        self.ctx.scale(arc.radii.x, arc.radii.y);
+        let end = arc.start_angle + arc.sweep_angle;
+        if arc.sweep_angle < 0.0 {
+            self.ctx.arc_negative(0.0, 0.0, 1.0, arc.start_angle, end);
+        } else {
+            self.ctx.arc(0.0, 0.0, 1.0, arc.start_angle, end);
+        }
+        if arc.sweep_angle.abs() == std::f64::consts::TAU {
+            self.ctx.close_path();
+        }
+        self.ctx.set_matrix(matrix);
+    }
+
+    fn set_cached_path(&mut self, shape: &CachedShape<Rc<cairo::Path>>) {
+        self.ctx.new_path();
+        self.ctx.append_path(shape.geometry());
+    }
+
     fn draw_image_inner(
         &mut self,
         image: &ImageSurface,
         src_rect: Option<Rect>,
         dst_rect: Rect,
         interp: InterpolationMode,
+        opacity: f64,
     ) {
         let src_rect = match src_rect {
             Some(src_rect) => src_rect,
@@ -510,11 +824,7 @@ impl<'a> CairoRenderContext<'a> {
 
         let _ = self.with_save(|rc| {
             let surface_pattern = SurfacePattern::create(image);
-            let filter = match interp {
-                InterpolationMode::NearestNeighbor => Filter::Nearest,
-                InterpolationMode::Bilinear => Filter::Bilinear,
-            };
-            surface_pattern.set_filter(filter);
+            surface_pattern.set_filter(convert_interpolation(interp));
             let scale_x = dst_rect.width() / src_rect.width();
             let scale_y = dst_rect.height() / src_rect.height();
             rc.clip(dst_rect);
@@ -524,7 +834,11 @@ impl<'a> CairoRenderContext<'a> {
             );
             rc.ctx.scale(scale_x, scale_y);
             rc.error = rc.ctx.set_source(&surface_pattern);
-            rc.error = rc.ctx.paint();
+            rc.error = if opacity < 1.0 {
+                rc.ctx.paint_with_alpha(opacity)
+            } else {
+                rc.ctx.paint()
+            };
             Ok(())
         });
     }
@@ -559,6 +873,29 @@ fn byte_to_frac(byte: u32) -> f64 {
     ((byte & 255) as f64) * (1.0 / 255.0)
 }
 
+fn convert_interpolation(interp: InterpolationMode) -> Filter {
+    match interp {
+        InterpolationMode::NearestNeighbor => Filter::Nearest,
+        InterpolationMode::Bilinear => Filter::Bilinear,
+    }
+}
+
+/// Copy the stops, matrix and extend of a gradient to another, with their
+/// alpha multiplied by `opacity`.
+fn copy_faded_gradient(
+    src: &cairo::Gradient,
+    dst: &cairo::Gradient,
+    opacity: f64,
+) -> Result<(), cairo::Error> {
+    for i in 0..src.color_stop_count()? {
+        let (offset, r, g, b, a) = src.color_stop_rgba(i)?;
+        dst.add_color_stop_rgba(offset, r, g, b, a * opacity);
+    }
+    dst.set_matrix(src.matrix());
+    dst.set_extend(src.extend());
+    Ok(())
+}
+
 /// Can't implement RoundFrom here because both types belong to other crates.
 fn affine_to_matrix(affine: Affine) -> Matrix {
     let a = affine.as_coeffs();
@@ -588,7 +925,94 @@ fn compute_blurred_rect(rect: Rect, radius: f64) -> Result<(ImageSurface, Point)
 }
 
 fn convert_error(err: cairo::Error) -> Error {
-    Error::BackendError(err.into())
+    match err {
+        cairo::Error::NoMemory => Error::OutOfMemory,
+        cairo::Error::FreetypeError => Error::FontLoadingFailed,
+        err => Error::BackendError(err.into()),
+    }
+}
+
+fn cairo_format(format: ImageFormat) -> Result<Format, Error> {
+    match format {
+        ImageFormat::Rgb | ImageFormat::Grayscale => Ok(Format::Rgb24),
+        ImageFormat::RgbaSeparate | ImageFormat::RgbaPremul => Ok(Format::ARgb32),
+        _ => Err(Error::NotSupported),
+    }
+}
+
+/// Convert the pixels in `buf` to Cairo's format, writing them to `data`,
+/// which has rows of `image_stride` bytes.
+// allows e.g. raw_data[dst_off + x * 4 + 2] = buf[src_off + x * 4 + 0];
+#[allow(clippy::identity_op)]
+fn write_image_data(
+    data: &mut [u8],
+    image_stride: usize,
+    width: usize,
+    height: usize,
+    stride: usize,
+    buf: &[u8],
+    format: ImageFormat,
+) -> Result<(), Error> {
+    piet::util::validate_image_args(width, height, stride, format, buf.len())?;
+
+    for y in 0..height {
+        let src_off = y * stride;
+        let data = &mut data[y * image_stride..];
+        match format {
+            ImageFormat::Rgb => {
+                for x in 0..width {
+                    write_rgb(
+                        data,
+                        x,
+                        buf[src_off + x * 3 + 0],
+                        buf[src_off + x * 3 + 1],
+                        buf[src_off + x * 3 + 2],
+                    );
+                }
+            }
+            ImageFormat::RgbaPremul => {
+                // It's annoying that Cairo exposes only ARGB. Ah well. Let's
+                // hope that LLVM generates pretty good code for this.
+                // TODO: consider adding BgraPremul format.
+                for x in 0..width {
+                    write_rgba(
+                        data,
+                        x,
+                        buf[src_off + x * 4 + 0],
+                        buf[src_off + x * 4 + 1],
+                        buf[src_off + x * 4 + 2],
+                        buf[src_off + x * 4 + 3],
+                    );
+                }
+            }
+            ImageFormat::RgbaSeparate => {
+                for x in 0..width {
+                    let a = buf[src_off + x * 4 + 3];
+                    write_rgba(
+                        data,
+                        x,
+                        premul(buf[src_off + x * 4 + 0], a),
+                        premul(buf[src_off + x * 4 + 1], a),
+                        premul(buf[src_off + x * 4 + 2], a),
+                        a,
+                    );
+                }
+            }
+            ImageFormat::Grayscale => {
+                for x in 0..width {
+                    write_rgb(
+                        data,
+                        x,
+                        buf[src_off + x],
+                        buf[src_off + x],
+                        buf[src_off + x],
+                    );
+                }
+            }
+            _ => return Err(Error::NotSupported),
+        }
+    }
+    Ok(())
 }
 
 fn write_rgba(data: &mut [u8], column: usize, r: u8, g: u8, b: u8, a: u8) {
@@ -606,3 +1030,41 @@ fn write_rgb(data: &mut [u8], column: usize, r: u8, g: u8, b: u8) {
     //  each pixel is a 32-bit quantity, with the upper 8 bits unused.
     write_rgba(data, column, r, g, b, 0);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use piet::{Text, TextLayoutBuilder};
+
+    fn write_pdf(draw: impl FnOnce(&mut CairoRenderContext)) -> Vec<u8> {
+        let surface = cairo::PdfSurface::for_stream(200., 100., Vec::<u8>::new()).unwrap();
+        {
+            let ctx = Context::new(&surface).unwrap();
+            let mut rc = CairoRenderContext::new_for_vector_surface(&ctx);
+            draw(&mut rc);
+            rc.finish().unwrap();
+            rc.status().unwrap();
+        }
+        let stream = surface.finish_output_stream().unwrap();
+        *stream.downcast::<Vec<u8>>().unwrap()
+    }
+
+    #[test]
+    fn vector_surface_keeps_text_as_glyphs() {
+        let pdf = write_pdf(|rc| {
+            rc.clear(None, Color::WHITE);
+            rc.fill(Rect::new(10., 10., 50., 50.), &Color::rgb8(0, 0, 0xff));
+            let layout = rc
+                .text()
+                .new_text_layout("Hello PDF")
+                .default_attribute(piet::TextAttribute::FontSize(16.))
+                .build()
+                .unwrap();
+            rc.draw_text(&layout, (10., 60.));
+        });
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with("%PDF"));
+        assert!(pdf.contains("/Font"));
+        assert!(!pdf.contains("/Subtype /Image"));
+    }
+}
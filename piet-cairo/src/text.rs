@@ -3,21 +3,29 @@
 
 //! Text functionality for Piet cairo backend
 
+use std::cell::RefCell;
 use std::convert::TryInto;
 use std::fmt;
 use std::ops::{Range, RangeBounds};
 use std::rc::Rc;
 
-use pango::prelude::FontMapExt;
-use pango::{AttrColor, AttrInt, AttrList, AttrSize, AttrString};
+use pango::glib::translate::IntoGlib;
+use pango::prelude::{FontExt, FontFamilyExt, FontMapExt};
+use pango::{AttrColor, AttrFontFeatures, AttrInt, AttrList, AttrSize, AttrString};
 use pangocairo::FontMap;
+use unicode_segmentation::UnicodeSegmentation;
 
-use piet::kurbo::{Point, Rect, Size, Vec2};
+use piet::kurbo::{BezPath, Point, Rect, Size, Vec2};
+use piet::util::UnderlineRun;
 use piet::{
-    util, Error, FontFamily, FontStyle, HitTestPoint, HitTestPosition, LineMetric, Text,
-    TextAlignment, TextAttribute, TextLayout, TextLayoutBuilder, TextStorage,
+    util, Affinity, CaretHeight, Color, CursorPosition, Error, FontDescription, FontFamily,
+    FontStyle, FontWeight, HitTestPoint, HitTestPosition, LineMetric, Text, TextAlignment,
+    TextAntialiasing, TextAttribute, TextLayout, TextLayoutBuilder, TextStorage, UnderlineStyle,
 };
 
+#[cfg(all(unix, not(target_vendor = "apple")))]
+use crate::fontconfig::IsolatedFonts;
+
 type PangoLayout = pango::Layout;
 type PangoContext = pango::Context;
 type PangoAttribute = pango::Attribute;
@@ -29,9 +37,42 @@ type PangoAlignment = pango::Alignment;
 const PANGO_SCALE: f64 = pango::SCALE as f64;
 const UNBOUNDED_WRAP_WIDTH: i32 = -1;
 
+/// The number of layouts kept by the layout cache, unless changed with
+/// [`CairoText::set_cache_size`].
+const DEFAULT_LAYOUT_CACHE_SIZE: usize = 128;
+
+thread_local! {
+    // Pango objects can't be shared between threads, and a new `CairoText` is
+    // created along with each render context, so the cache lives here rather
+    // than in `CairoText`, where it would not survive from frame to frame.
+    static LAYOUT_CACHE: RefCell<LayoutCache> =
+        RefCell::new(LayoutCache::new(DEFAULT_LAYOUT_CACHE_SIZE));
+}
+
 #[derive(Clone)]
 pub struct CairoText {
     pango_context: PangoContext,
+    vector: bool,
+    draw_decorations: bool,
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    isolated: Option<Rc<RefCell<IsolatedFonts>>>,
+}
+
+/// The placement of underlines and strikethroughs.
+///
+/// Positions are the distance from the baseline to the top of the line,
+/// with positive values below the baseline; a strikethrough's position is
+/// usually negative. All values are in display points.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DecorationMetrics {
+    /// The distance from the baseline to the top of an underline.
+    pub underline_position: f64,
+    /// The thickness of an underline.
+    pub underline_thickness: f64,
+    /// The distance from the baseline to the top of a strikethrough.
+    pub strikethrough_position: f64,
+    /// The thickness of a strikethrough.
+    pub strikethrough_thickness: f64,
 }
 
 #[derive(Clone)]
@@ -45,7 +86,17 @@ pub struct CairoTextLayout {
 
     line_metrics: Rc<[LineMetric]>,
     x_offsets: Rc<[i32]>,
+    /// The width of each line without trailing whitespace, in pango units.
+    line_widths: Rc<[i32]>,
     pango_layout: PangoLayout,
+
+    /// Underlines with a style Pango can't draw, which we draw ourselves.
+    underlines: Rc<[UnderlineRun]>,
+    /// Strikethroughs we draw ourselves; empty unless decorations are drawn
+    /// by piet.
+    strikethroughs: Rc<[UnderlineRun]>,
+    decorations: DecorationMetrics,
+    links: util::TextLinks,
 }
 
 pub struct CairoTextLayoutBuilder {
@@ -54,16 +105,104 @@ pub struct CairoTextLayoutBuilder {
     attributes: Vec<AttributeWithRange>,
     last_range_start_pos: usize,
     width_constraint: f64,
-    pango_layout: PangoLayout,
+    alignment: TextAlignment,
+    locale: Option<String>,
+    antialiasing: TextAntialiasing,
+    decoration_metrics: Option<DecorationMetrics>,
+    draw_decorations: bool,
+    pango_context: PangoContext,
+    vector: bool,
 }
 
+#[derive(Clone, PartialEq)]
 struct AttributeWithRange {
     attribute: TextAttribute,
     range: Option<Range<usize>>, //No range == entire layout
 }
 
+/// Everything that determines the result of building a layout.
+struct LayoutKey {
+    text: Rc<dyn TextStorage>,
+    attributes: Vec<AttributeWithRange>,
+    alignment: TextAlignment,
+    locale: Option<String>,
+    antialiasing: TextAntialiasing,
+    decoration_metrics: Option<DecorationMetrics>,
+    draw_decorations: bool,
+    pango_width: i32,
+    vector: bool,
+    font_map: Option<pango::FontMap>,
+}
+
+/// A least-recently-used cache of built layouts.
+///
+/// Layouts are immutable once built, so a cached layout can be handed out
+/// any number of times.
+struct LayoutCache {
+    capacity: usize,
+    // Ordered from least to most recently used.
+    entries: Vec<(LayoutKey, CairoTextLayout)>,
+}
+
+impl LayoutKey {
+    fn matches(&self, other: &LayoutKey) -> bool {
+        self.pango_width == other.pango_width
+            && self.vector == other.vector
+            && self.font_map == other.font_map
+            && self.alignment == other.alignment
+            && self.locale == other.locale
+            && self.antialiasing == other.antialiasing
+            && self.decoration_metrics == other.decoration_metrics
+            && self.draw_decorations == other.draw_decorations
+            && self.text.as_str() == other.text.as_str()
+            && self.attributes == other.attributes
+    }
+}
+
+impl LayoutCache {
+    fn new(capacity: usize) -> Self {
+        LayoutCache {
+            capacity,
+            entries: Vec::new(),
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn get(&mut self, key: &LayoutKey) -> Option<CairoTextLayout> {
+        let idx = self.entries.iter().rposition(|(k, _)| k.matches(key))?;
+        let entry = self.entries.remove(idx);
+        let layout = entry.1.clone();
+        self.entries.push(entry);
+        Some(layout)
+    }
+
+    fn insert(&mut self, key: LayoutKey, layout: CairoTextLayout) {
+        if self.capacity > 0 {
+            self.entries.push((key, layout));
+            self.evict();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.capacity);
+        self.entries.drain(..excess);
+    }
+}
+
 impl AttributeWithRange {
-    fn into_pango(self) -> PangoAttribute {
+    /// Convert to a Pango attribute.
+    ///
+    /// Underlines are handled separately, since Pango combines their
+    /// attributes into one.
+    fn to_pango(&self) -> Option<PangoAttribute> {
         let mut pango_attribute: PangoAttribute = match &self.attribute {
             TextAttribute::FontFamily(family) => {
                 let family = family.name();
@@ -111,13 +250,8 @@ impl AttributeWithRange {
             }
 
             TextAttribute::TextColor(text_color) => {
-                let (r, g, b, _) = text_color.as_rgba8();
-                AttrColor::new_foreground(
-                    (r as u16 * 256) + (r as u16),
-                    (g as u16 * 256) + (g as u16),
-                    (b as u16 * 256) + (b as u16),
-                )
-                .into()
+                let (r, g, b) = pango_color(*text_color);
+                AttrColor::new_foreground(r, g, b).into()
             }
 
             TextAttribute::Style(style) => {
@@ -128,38 +262,158 @@ impl AttributeWithRange {
                 AttrInt::new_style(style).into()
             }
 
-            &TextAttribute::Underline(underline) => {
-                let underline = if underline {
-                    PangoUnderline::Single
-                } else {
-                    PangoUnderline::None
-                };
-                AttrInt::new_underline(underline).into()
-            }
-
             &TextAttribute::Strikethrough(strikethrough) => {
                 AttrInt::new_strikethrough(strikethrough).into()
             }
+
+            TextAttribute::BaselineOffset(offset) => {
+                AttrInt::new_rise((offset * PANGO_SCALE) as i32).into()
+            }
+
+            TextAttribute::FontFeatures(features) => {
+                AttrFontFeatures::new(&util::css_font_features(features)).into()
+            }
+
+            TextAttribute::Underline(_)
+            | TextAttribute::UnderlineColor(_)
+            | TextAttribute::UnderlineStyle(_)
+            | TextAttribute::UnderlineThickness(_)
+            | TextAttribute::UnderlineSkipInk(_) => return None,
+
+            // links are recorded by the layout, and don't change the text
+            TextAttribute::Link(_) => return None,
         };
 
-        if let Some(range) = self.range {
-            pango_attribute.set_start_index(range.start.try_into().unwrap());
-            pango_attribute.set_end_index(range.end.try_into().unwrap());
+        if let Some(range) = &self.range {
+            set_range(&mut pango_attribute, range);
         }
 
-        pango_attribute
+        Some(pango_attribute)
+    }
+}
+
+fn set_range(pango_attribute: &mut PangoAttribute, range: &Range<usize>) {
+    pango_attribute.set_start_index(range.start.try_into().unwrap());
+    pango_attribute.set_end_index(range.end.try_into().unwrap());
+}
+
+/// Turn off hinting, for text that will be drawn to a vector surface.
+fn set_vector_font_options(context: &PangoContext) {
+    set_font_options(context, true, TextAntialiasing::Default);
+}
+
+fn set_font_options(context: &PangoContext, vector: bool, antialiasing: TextAntialiasing) {
+    if let Ok(mut options) = cairo::FontOptions::new() {
+        if vector {
+            options.set_hint_style(cairo::HintStyle::None);
+            options.set_hint_metrics(cairo::HintMetrics::Off);
+        }
+        options.set_antialias(match antialiasing {
+            TextAntialiasing::Default => cairo::Antialias::Default,
+            TextAntialiasing::Grayscale => cairo::Antialias::Gray,
+            TextAntialiasing::Subpixel => cairo::Antialias::Subpixel,
+            TextAntialiasing::Aliased => cairo::Antialias::None,
+        });
+        pangocairo::functions::context_set_font_options(context, Some(&options));
     }
 }
 
+fn pango_color(color: Color) -> (u16, u16, u16) {
+    let (r, g, b, _) = color.as_rgba8();
+    (
+        (r as u16 * 256) + (r as u16),
+        (g as u16 * 256) + (g as u16),
+        (b as u16 * 256) + (b as u16),
+    )
+}
+
 impl CairoText {
     /// Create a new factory that satisfies the piet `Text` trait.
     #[allow(clippy::new_without_default)]
     pub fn new() -> CairoText {
-        let fontmap = FontMap::default();
+        CairoText::with_font_map(&FontMap::default())
+    }
+
+    /// Create a factory that finds fonts with the given fontmap, instead of
+    /// the default pango fontmap.
+    ///
+    /// Use this with [`CairoRenderContext::set_text`] to draw with a font set
+    /// other than the fonts installed on the system. Loading a font with
+    /// [`Text::load_font`] replaces this fontmap with a private copy of the
+    /// system's fonts.
+    ///
+    /// [`CairoRenderContext::set_text`]: crate::CairoRenderContext::set_text
+    pub fn with_font_map(font_map: &pango::FontMap) -> CairoText {
         CairoText {
-            pango_context: fontmap.create_context(),
+            pango_context: font_map.create_context(),
+            vector: false,
+            draw_decorations: false,
+            #[cfg(all(unix, not(target_vendor = "apple")))]
+            isolated: None,
         }
     }
+
+    /// Create a factory that sees no fonts except the ones added with
+    /// [`Text::load_font`].
+    ///
+    /// The fonts are found with a private fontconfig configuration, so that
+    /// output does not depend on the fonts installed on the system; this is
+    /// useful for rendering that should be the same on every machine, such as
+    /// snapshot tests. Text in a family that has not been loaded is drawn with
+    /// the first font that was.
+    ///
+    /// Returns [`Error::NotSupported`] if cairo was built without FreeType.
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    pub fn new_isolated() -> Result<CairoText, Error> {
+        let isolated = IsolatedFonts::empty()?;
+        let text = CairoText::with_font_map(isolated.font_map());
+        Ok(CairoText {
+            isolated: Some(Rc::new(RefCell::new(isolated))),
+            ..text
+        })
+    }
+
+    /// Convert to a factory for layouts that will be drawn to a vector surface.
+    ///
+    /// Glyph metrics are not hinted, so that glyphs are positioned exactly
+    /// rather than snapped to the pixel grid of some nominal resolution.
+    pub(crate) fn into_vector(self) -> CairoText {
+        set_vector_font_options(&self.pango_context);
+        CairoText {
+            vector: true,
+            ..self
+        }
+    }
+
+    /// Whether this factory lays out text for a vector surface.
+    pub(crate) fn is_vector(&self) -> bool {
+        self.vector
+    }
+
+    /// Draw underlines and strikethroughs with piet instead of Pango.
+    ///
+    /// Pango rounds the position and thickness of decorations differently
+    /// from the other backends. When this is set, decorations are placed
+    /// using the font's unrounded metrics and stroked with the same code
+    /// the other backends use for styles they can't draw, so that output
+    /// is easier to compare across platforms. It is off by default.
+    ///
+    /// See also [`CairoTextLayoutBuilder::decoration_metrics`].
+    pub fn set_draw_decorations(&mut self, draw: bool) {
+        self.draw_decorations = draw;
+    }
+
+    /// Set the number of built layouts to keep around for reuse.
+    ///
+    /// Building a layout whose text, attributes, alignment and width match a
+    /// recently built one returns the earlier layout instead of shaping the
+    /// text again, which makes redrawing mostly unchanged text cheap. A size
+    /// of `0` disables the cache. The default size is 128.
+    ///
+    /// The cache is shared by every `CairoText` on the current thread.
+    pub fn set_cache_size(&mut self, size: usize) {
+        LAYOUT_CACHE.with(|cache| cache.borrow_mut().set_capacity(size));
+    }
 }
 
 impl Text for CairoText {
@@ -171,6 +425,47 @@ impl Text for CairoText {
         Some(FontFamily::new_unchecked(family_name))
     }
 
+    // `font_family` doesn't check that the family exists, so check here.
+    fn best_family(&mut self, families: &[&str]) -> FontFamily {
+        let installed = self
+            .pango_context
+            .font_map()
+            .map(|map| map.list_families())
+            .unwrap_or_default();
+        families
+            .iter()
+            .find_map(|&name| {
+                FontFamily::generic(name).or_else(|| {
+                    installed
+                        .iter()
+                        .any(|family| family.name().eq_ignore_ascii_case(name))
+                        .then(|| FontFamily::new_unchecked(name))
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    // Pango can only use fonts known to fontconfig, so the font is added to a
+    // private fontconfig config, which this factory switches to the first
+    // time a font is loaded. Other factories never see the font.
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    fn load_font(&mut self, data: &[u8]) -> Result<FontFamily, Error> {
+        if self.isolated.is_none() {
+            let isolated = IsolatedFonts::with_system_fonts()?;
+            self.pango_context = isolated.font_map().create_context();
+            if self.vector {
+                set_vector_font_options(&self.pango_context);
+            }
+            self.isolated = Some(Rc::new(RefCell::new(isolated)));
+        }
+        let isolated = self.isolated.as_ref().unwrap();
+        let family = isolated.borrow_mut().add_font(data)?;
+        // Layouts built before the font was added may have used a fallback.
+        LAYOUT_CACHE.with(|cache| cache.borrow_mut().clear());
+        Ok(FontFamily::new_unchecked(family))
+    }
+
+    #[cfg(not(all(unix, not(target_vendor = "apple"))))]
     fn load_font(&mut self, _data: &[u8]) -> Result<FontFamily, Error> {
         /*
          * NOTE(ForLoveOfCats): It does not appear that Pango natively supports loading font
@@ -183,19 +478,19 @@ impl Text for CairoText {
     }
 
     fn new_text_layout(&mut self, text: impl TextStorage) -> Self::TextLayoutBuilder {
-        let pango_layout = PangoLayout::new(&self.pango_context);
-        pango_layout.set_text(text.as_str());
-
-        pango_layout.set_alignment(PangoAlignment::Left);
-        pango_layout.set_justify(false);
-
         CairoTextLayoutBuilder {
             text: Rc::new(text),
             defaults: util::LayoutDefaults::default(),
             attributes: Vec::new(),
             last_range_start_pos: 0,
             width_constraint: f64::INFINITY,
-            pango_layout,
+            alignment: TextAlignment::Start,
+            locale: None,
+            antialiasing: TextAntialiasing::Default,
+            decoration_metrics: None,
+            draw_decorations: self.draw_decorations,
+            pango_context: self.pango_context.clone(),
+            vector: self.vector,
         }
     }
 }
@@ -214,39 +509,18 @@ impl TextLayoutBuilder for CairoTextLayoutBuilder {
         self
     }
 
-    fn alignment(self, alignment: TextAlignment) -> Self {
-        /*
-         * NOTE: Pango has `auto_dir` enabled by default. This means that
-         * when it encounters a paragraph starting with a left-to-right
-         * character the meanings of `Left` and `Right` are switched for
-         * that paragraph. As a result the meaning of Piet's own `Start`
-         * and `End` are preserved
-         *
-         * See: http://gtk-rs.org/docs/pango/struct.Layout.html#method.set_auto_dir
-         */
-
-        match alignment {
-            TextAlignment::Start => {
-                self.pango_layout.set_justify(false);
-                self.pango_layout.set_alignment(PangoAlignment::Left);
-            }
-
-            TextAlignment::End => {
-                self.pango_layout.set_justify(false);
-                self.pango_layout.set_alignment(PangoAlignment::Right);
-            }
-
-            TextAlignment::Center => {
-                self.pango_layout.set_justify(false);
-                self.pango_layout.set_alignment(PangoAlignment::Center);
-            }
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
 
-            TextAlignment::Justified => {
-                self.pango_layout.set_alignment(PangoAlignment::Left);
-                self.pango_layout.set_justify(true);
-            }
-        }
+    fn locale(mut self, locale: &str) -> Self {
+        self.locale = Some(locale.to_owned());
+        self
+    }
 
+    fn antialiasing(mut self, antialiasing: TextAntialiasing) -> Self {
+        self.antialiasing = antialiasing;
         self
     }
 
@@ -278,85 +552,207 @@ impl TextLayoutBuilder for CairoTextLayoutBuilder {
     }
 
     fn build(self) -> Result<Self::Out, Error> {
-        let pango_attributes = AttrList::new();
-
-        pango_attributes.insert(pango::AttrInt::new_insert_hyphens(false));
-        pango_attributes.insert(
-            AttributeWithRange {
-                attribute: TextAttribute::FontFamily(self.defaults.font),
-                range: None,
-            }
-            .into_pango(),
-        );
-        pango_attributes.insert(
-            AttributeWithRange {
-                attribute: TextAttribute::FontSize(self.defaults.font_size),
-                range: None,
-            }
-            .into_pango(),
-        );
-        pango_attributes.insert(
-            AttributeWithRange {
-                attribute: TextAttribute::Weight(self.defaults.weight),
-                range: None,
-            }
-            .into_pango(),
-        );
-        pango_attributes.insert(
-            AttributeWithRange {
-                attribute: TextAttribute::TextColor(self.defaults.fg_color),
+        let defaults = self.defaults;
+        let mut font_desc = pango::FontDescription::new();
+        font_desc.set_family(defaults.font.name());
+        font_desc.set_absolute_size(defaults.font_size * PANGO_SCALE);
+        let default_attributes = [
+            Some(TextAttribute::FontFamily(defaults.font)),
+            Some(TextAttribute::FontSize(defaults.font_size)),
+            Some(TextAttribute::Weight(defaults.weight)),
+            Some(TextAttribute::TextColor(defaults.fg_color)),
+            Some(TextAttribute::Style(defaults.style)),
+            Some(TextAttribute::Underline(defaults.underline)),
+            Some(TextAttribute::Strikethrough(defaults.strikethrough)),
+            Some(TextAttribute::BaselineOffset(defaults.baseline_offset)),
+            Some(TextAttribute::FontFeatures(defaults.font_features)),
+            Some(TextAttribute::UnderlineStyle(defaults.underline_style)),
+            Some(TextAttribute::UnderlineSkipInk(defaults.underline_skip_ink)),
+            defaults.underline_color.map(TextAttribute::UnderlineColor),
+            defaults
+                .underline_thickness
+                .map(TextAttribute::UnderlineThickness),
+            defaults.link.map(TextAttribute::Link),
+        ];
+        let mut attributes: Vec<_> = default_attributes
+            .into_iter()
+            .flatten()
+            .map(|attribute| AttributeWithRange {
+                attribute,
                 range: None,
+            })
+            .collect();
+        attributes.extend(self.attributes);
+
+        let key = LayoutKey {
+            text: self.text,
+            attributes,
+            alignment: self.alignment,
+            locale: self.locale,
+            antialiasing: self.antialiasing,
+            decoration_metrics: self.decoration_metrics,
+            draw_decorations: self.draw_decorations || self.decoration_metrics.is_some(),
+            pango_width: pango_width(self.width_constraint),
+            vector: self.vector,
+            font_map: self.pango_context.font_map(),
+        };
+        if let Some(layout) = LAYOUT_CACHE.with(|cache| cache.borrow_mut().get(&key)) {
+            return Ok(layout);
+        }
+
+        // font options belong to the context, so a layout that overrides
+        // the antialiasing gets a context of its own.
+        let pango_context = match (&key.antialiasing, &key.font_map) {
+            (TextAntialiasing::Default, _) | (_, None) => self.pango_context.clone(),
+            (antialiasing, Some(font_map)) => {
+                let context = font_map.create_context();
+                set_font_options(&context, key.vector, *antialiasing);
+                context
             }
-            .into_pango(),
-        );
-        pango_attributes.insert(
-            AttributeWithRange {
-                attribute: TextAttribute::Style(self.defaults.style),
-                range: None,
+        };
+        let pango_layout = PangoLayout::new(&pango_context);
+        pango_layout.set_text(key.text.as_str());
+
+        /*
+         * NOTE: Pango has `auto_dir` enabled by default. This means that
+         * when it encounters a paragraph starting with a left-to-right
+         * character the meanings of `Left` and `Right` are switched for
+         * that paragraph. As a result the meaning of Piet's own `Start`
+         * and `End` are preserved
+         *
+         * See: http://gtk-rs.org/docs/pango/struct.Layout.html#method.set_auto_dir
+         */
+        let (alignment, justify) = match key.alignment {
+            TextAlignment::Start => (PangoAlignment::Left, false),
+            TextAlignment::End => (PangoAlignment::Right, false),
+            TextAlignment::Center => (PangoAlignment::Center, false),
+            TextAlignment::Justified => (PangoAlignment::Left, true),
+        };
+        pango_layout.set_alignment(alignment);
+        pango_layout.set_justify(justify);
+
+        let text_len = key.text.len();
+        let pango_attributes = AttrList::new();
+        pango_attributes.insert(pango::AttrInt::new_insert_hyphens(false));
+        // without this, Pango uses the context's language, which is the
+        // system locale.
+        if let Some(locale) = &key.locale {
+            let language = pango::Language::from_string(locale);
+            pango_attributes.insert(pango::AttrLanguage::new(&language));
+        }
+        let mut underline_attrs = util::UnderlineAttributes::default();
+        // strikethroughs are collected like underlines, but have no styling
+        // attributes of their own.
+        let mut strikethrough_attrs = util::UnderlineAttributes::default();
+        let mut links = util::TextLinks::default();
+        for attribute in &key.attributes {
+            let range = attribute.range.clone().unwrap_or(0..text_len);
+            underline_attrs.add(range.clone(), &attribute.attribute);
+            if let TextAttribute::Link(url) = &attribute.attribute {
+                links.add(range.clone(), url);
             }
-            .into_pango(),
-        );
-        pango_attributes.insert(
-            AttributeWithRange {
-                attribute: TextAttribute::Underline(self.defaults.underline),
-                range: None,
+            if key.draw_decorations {
+                match attribute.attribute {
+                    TextAttribute::Strikethrough(flag) => {
+                        strikethrough_attrs.add(range, &TextAttribute::Underline(flag));
+                        continue;
+                    }
+                    TextAttribute::TextColor(_) => {
+                        strikethrough_attrs.add(range, &attribute.attribute)
+                    }
+                    _ => (),
+                }
             }
-            .into_pango(),
-        );
-        pango_attributes.insert(
-            AttributeWithRange {
-                attribute: TextAttribute::Strikethrough(self.defaults.strikethrough),
-                range: None,
+            if let Some(pango_attribute) = attribute.to_pango() {
+                pango_attributes.insert(pango_attribute);
             }
-            .into_pango(),
-        );
+        }
+        let strikethroughs = if key.draw_decorations {
+            strikethrough_attrs.runs(text_len)
+        } else {
+            Vec::new()
+        };
 
-        for attribute in self.attributes {
-            pango_attributes.insert(attribute.into_pango());
+        // Pango can draw solid and wavy underlines in any color, but has no
+        // dotted or dashed style, always uses the font's thickness, and
+        // never skips ink.
+        let mut underlines = Vec::new();
+        for run in underline_attrs.runs(text_len) {
+            let underline = match run.style {
+                _ if key.draw_decorations || run.skip_ink => {
+                    underlines.push(run);
+                    continue;
+                }
+                UnderlineStyle::Solid if run.thickness.is_none() => PangoUnderline::Single,
+                UnderlineStyle::Wavy if run.thickness.is_none() => PangoUnderline::Error,
+                _ => {
+                    underlines.push(run);
+                    continue;
+                }
+            };
+            let mut attr: PangoAttribute = AttrInt::new_underline(underline).into();
+            set_range(&mut attr, &run.range);
+            pango_attributes.insert(attr);
+            let (r, g, b) = pango_color(run.color);
+            let mut attr: PangoAttribute = AttrColor::new_underline_color(r, g, b).into();
+            set_range(&mut attr, &run.range);
+            pango_attributes.insert(attr);
         }
+        let decorations = match key.decoration_metrics {
+            Some(metrics) => metrics,
+            None if underlines.is_empty() && strikethroughs.is_empty() => Default::default(),
+            None => {
+                let metrics = self.pango_context.metrics(Some(&font_desc), None);
+                DecorationMetrics {
+                    underline_position: -metrics.underline_position() as f64 / PANGO_SCALE,
+                    underline_thickness: metrics.underline_thickness() as f64 / PANGO_SCALE,
+                    strikethrough_position: -metrics.strikethrough_position() as f64 / PANGO_SCALE,
+                    strikethrough_thickness: metrics.strikethrough_thickness() as f64 / PANGO_SCALE,
+                }
+            }
+        };
 
-        self.pango_layout.set_attributes(Some(&pango_attributes));
-        self.pango_layout.set_wrap(pango::WrapMode::WordChar);
-        self.pango_layout.set_ellipsize(pango::EllipsizeMode::None);
+        pango_layout.set_attributes(Some(&pango_attributes));
+        pango_layout.set_wrap(pango::WrapMode::WordChar);
+        pango_layout.set_ellipsize(pango::EllipsizeMode::None);
 
         // invalid until update_width() is called
         let mut layout = CairoTextLayout {
-            is_rtl: util::first_strong_rtl(self.text.as_str()),
-            text: self.text,
+            is_rtl: util::first_strong_rtl(key.text.as_str()),
+            text: key.text.clone(),
             size: Size::ZERO,
             ink_rect: Rect::ZERO,
             pango_offset: Vec2::ZERO,
             trailing_ws_width: 0.0,
             line_metrics: Rc::new([]),
             x_offsets: Rc::new([]),
-            pango_layout: self.pango_layout,
+            line_widths: Rc::new([]),
+            pango_layout,
+            underlines: underlines.into(),
+            strikethroughs: strikethroughs.into(),
+            decorations,
+            links,
         };
 
         layout.update_width(self.width_constraint);
+        LAYOUT_CACHE.with(|cache| cache.borrow_mut().insert(key, layout.clone()));
         Ok(layout)
     }
 }
 
+impl CairoTextLayoutBuilder {
+    /// Place underlines and strikethroughs using these metrics, instead of
+    /// the metrics of the default font.
+    ///
+    /// Setting this means decorations are drawn by piet, as with
+    /// [`CairoText::set_draw_decorations`]. An underline's
+    /// [`TextAttribute::UnderlineThickness`] still takes precedence.
+    pub fn decoration_metrics(mut self, metrics: DecorationMetrics) -> Self {
+        self.decoration_metrics = Some(metrics);
+        self
+    }
+}
+
 impl fmt::Debug for CairoTextLayoutBuilder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CairoTextLayoutBuilder").finish()
@@ -376,6 +772,37 @@ impl TextLayout for CairoTextLayout {
         self.ink_rect
     }
 
+    fn with_max_width(&self, width: f64) -> Self {
+        let mut layout = self.clone();
+        // the Pango layout may be shared, not least with the layout cache
+        layout.pango_layout = self.pango_layout.copy();
+        layout.update_width(width);
+        layout
+    }
+
+    fn with_color_override(&self, range: impl RangeBounds<usize>, color: Color) -> Self {
+        let range = util::resolve_range(range, self.text.len());
+        let mut layout = self.clone();
+        // the Pango layout may be shared, not least with the layout cache
+        layout.pango_layout = self.pango_layout.copy();
+        let attributes = self
+            .pango_layout
+            .attributes()
+            .and_then(|attributes| attributes.copy())
+            .unwrap_or_else(AttrList::new);
+        let (r, g, b) = pango_color(color);
+        let mut fg_color: PangoAttribute = AttrColor::new_foreground(r, g, b).into();
+        set_range(&mut fg_color, &range);
+        attributes.insert(fg_color);
+        // Pango treats an alpha of zero as unset, i.e. opaque
+        let alpha = (color.as_rgba8().3 as u16 * 257).max(1);
+        let mut fg_alpha: PangoAttribute = AttrInt::new_foreground_alpha(alpha).into();
+        set_range(&mut fg_alpha, &range);
+        attributes.insert(fg_alpha);
+        layout.pango_layout.set_attributes(Some(&attributes));
+        layout
+    }
+
     fn text(&self) -> &str {
         &self.text
     }
@@ -394,6 +821,14 @@ impl TextLayout for CairoTextLayout {
         self.line_metrics.len()
     }
 
+    fn line_bounds(&self, line_number: usize) -> Option<Rect> {
+        let metric = self.line_metrics.get(line_number)?;
+        let x0 = self.x_offsets[line_number] as f64 / PANGO_SCALE - self.pango_offset.x;
+        let width = self.line_widths[line_number] as f64 / PANGO_SCALE;
+        let y1 = metric.y_offset + metric.height;
+        Some(Rect::new(x0, metric.y_offset, x0 + width, y1))
+    }
+
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
         let point = point + self.pango_offset;
 
@@ -415,6 +850,7 @@ impl TextLayout for CairoTextLayout {
         let line_start_idx = self.line_metric(line_number).unwrap().start_offset;
 
         let hitpos = line.x_to_index(x);
+        let mut affinity = Affinity::Downstream;
         let rel_idx = if hitpos.is_inside() {
             let idx = hitpos.index() as usize - line_start_idx;
             let trailing_len: usize = line_text[idx..]
@@ -422,6 +858,9 @@ impl TextLayout for CairoTextLayout {
                 .take(hitpos.trailing() as usize)
                 .map(char::len_utf8)
                 .sum();
+            if trailing_len > 0 {
+                affinity = Affinity::Upstream;
+            }
             idx + trailing_len
         } else {
             let hit_is_left = x <= 0;
@@ -431,6 +870,7 @@ impl TextLayout for CairoTextLayout {
                 _ => 0,
             };
             if hit_is_left == self.is_rtl {
+                affinity = Affinity::Upstream;
                 line_text.len().saturating_sub(hard_break_len)
             } else {
                 0
@@ -440,6 +880,7 @@ impl TextLayout for CairoTextLayout {
         let is_inside_y = point.y >= 0. && point.y <= self.size.height;
 
         HitTestPoint::new(line_start_idx + rel_idx, hitpos.is_inside() && is_inside_y)
+            .with_affinity(affinity)
     }
 
     fn hit_test_text_position(&self, idx: usize) -> HitTestPosition {
@@ -453,7 +894,79 @@ impl TextLayout for CairoTextLayout {
             .find(|(_, metric)| metric.start_offset <= idx && idx < metric.end_offset)
             .map(|(idx, _)| idx)
             .unwrap_or_else(|| self.line_metrics.len() - 1);
-        let metric = self.line_metric(line_number).unwrap();
+        self.position_on_line(idx, line_number)
+    }
+
+    // The line is found while walking the text, so that this is linear
+    // rather than quadratic in the length of the text.
+    fn cursor_positions(&self) -> Vec<CursorPosition> {
+        let mut result = Vec::new();
+        let mut line_number = 0;
+        let boundaries = self.text.grapheme_indices(true).map(|(idx, _)| idx);
+        for idx in boundaries.chain(Some(self.text.len())) {
+            while line_number + 1 < self.line_metrics.len()
+                && idx >= self.line_metrics[line_number].end_offset
+            {
+                line_number += 1;
+            }
+            let x = self.position_on_line(idx, line_number).point.x;
+            result.push(CursorPosition::new(idx, line_number, x));
+        }
+        result
+    }
+
+    fn caret_rect(&self, idx: usize, height: CaretHeight) -> Rect {
+        let position = self.hit_test_text_position(idx);
+        let metric = &self.line_metrics[position.line];
+        let line = metric.y_offset..metric.y_offset + metric.height;
+        let y = match height {
+            CaretHeight::Line => line,
+            CaretHeight::Glyph => self.font_extent(idx).unwrap_or(line),
+        };
+        let is_rtl = util::is_rtl_at(self.text.as_str(), idx);
+        util::caret_rect(position.point.x, y, is_rtl, self.trailing_ws_width)
+    }
+
+    fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.links.links()
+    }
+
+    fn resolved_fonts(&self) -> Vec<(Range<usize>, FontDescription)> {
+        let mut fonts = Vec::new();
+        let mut iterator = self.pango_layout.iter();
+        loop {
+            if let Some(run) = iterator.run_readonly() {
+                let item = run.item();
+                let start = item.offset() as usize;
+                let range = start..start + item.length() as usize;
+                let font = item.analysis().font().describe_with_absolute_size();
+                fonts.push((range, font_description(&font)));
+            }
+            if !iterator.next_run() {
+                break;
+            }
+        }
+        util::merge_font_runs(fonts)
+    }
+}
+
+/// Convert a width constraint to the width passed to pango.
+fn pango_width(width: f64) -> i32 {
+    pango::SCALE.saturating_mul(width as i32)
+}
+
+impl CairoTextLayout {
+    pub(crate) fn pango_layout(&self) -> &PangoLayout {
+        &self.pango_layout
+    }
+
+    pub(crate) fn pango_offset(&self) -> Vec2 {
+        self.pango_offset
+    }
+
+    /// The position of `idx`, which is known to be on line `line_number`.
+    fn position_on_line(&self, idx: usize, line_number: usize) -> HitTestPosition {
+        let metric = &self.line_metrics[line_number];
 
         // in RTL text, pango mishandles the very last position in the layout
         // https://gitlab.gnome.org/GNOME/pango/-/issues/544
@@ -480,26 +993,109 @@ impl TextLayout for CairoTextLayout {
 
         HitTestPosition::new(point, line_number)
     }
-}
 
-impl CairoTextLayout {
-    pub(crate) fn pango_layout(&self) -> &PangoLayout {
-        &self.pango_layout
+    /// The decorations that Pango doesn't draw, with their colors and widths.
+    pub(crate) fn decoration_paths(&self) -> impl Iterator<Item = (BezPath, Color, f64)> + '_ {
+        let metrics = self.decorations;
+        let ink = if self.underlines.iter().any(|run| run.skip_ink) {
+            self.glyph_ink_bounds()
+        } else {
+            Vec::new()
+        };
+        let underlines = self.underlines.iter().map(move |run| {
+            let thickness = run.thickness.unwrap_or(metrics.underline_thickness);
+            let ink = if run.skip_ink { &ink[..] } else { &[] };
+            let path = util::underline_path_skipping_ink(
+                self,
+                run,
+                metrics.underline_position,
+                thickness,
+                ink,
+            );
+            (path, run.color, thickness)
+        });
+        let strikethroughs = self.strikethroughs.iter().map(move |run| {
+            let thickness = metrics.strikethrough_thickness;
+            let path = util::underline_path(self, run, metrics.strikethrough_position, thickness);
+            (path, run.color, thickness)
+        });
+        underlines.chain(strikethroughs)
     }
 
-    pub(crate) fn pango_offset(&self) -> Vec2 {
-        self.pango_offset
+    /// The vertical extent of the font used at the text position `idx`, from
+    /// its ascent to its descent.
+    ///
+    /// This is the font of the run containing `idx`, or else of the run
+    /// ending there, as at the end of a line.
+    fn font_extent(&self, idx: usize) -> Option<Range<f64>> {
+        let mut extent = None;
+        let mut iterator = self.pango_layout.iter();
+        loop {
+            if let Some(run) = iterator.run_readonly() {
+                let item = run.item();
+                let start = item.offset() as usize;
+                let end = start + item.length() as usize;
+                if start <= idx && idx <= end {
+                    let metrics = item.analysis().font().metrics(None);
+                    let baseline = iterator.baseline();
+                    let y0 = (baseline - metrics.ascent()) as f64 / PANGO_SCALE;
+                    let y1 = (baseline + metrics.descent()) as f64 / PANGO_SCALE;
+                    let y = y0 - self.pango_offset.y..y1 - self.pango_offset.y;
+                    if idx < end {
+                        return Some(y);
+                    }
+                    extent = Some(y);
+                }
+            }
+            if !iterator.next_run() {
+                break;
+            }
+        }
+        extent
+    }
+
+    /// The ink bounds of every glyph in the layout, for underlines that skip
+    /// ink.
+    fn glyph_ink_bounds(&self) -> Vec<Rect> {
+        let mut bounds = Vec::new();
+        let mut iterator = self.pango_layout.iter();
+        loop {
+            if let Some(run) = iterator.run_readonly() {
+                let baseline = iterator.baseline();
+                let mut x = iterator.run_extents().1.x();
+                let font = run.item().analysis().font();
+                for info in run.glyph_string().glyph_info() {
+                    let geometry = info.geometry();
+                    let ink = font.glyph_extents(info.glyph()).0;
+                    let x0 = x + geometry.x_offset() + ink.x();
+                    let y0 = baseline + geometry.y_offset() + ink.y();
+                    let rect = Rect::new(
+                        x0 as f64 / PANGO_SCALE,
+                        y0 as f64 / PANGO_SCALE,
+                        (x0 + ink.width()) as f64 / PANGO_SCALE,
+                        (y0 + ink.height()) as f64 / PANGO_SCALE,
+                    );
+                    bounds.push(rect - self.pango_offset);
+                    x += geometry.width();
+                }
+            }
+            if !iterator.next_run() {
+                break;
+            }
+        }
+        bounds
     }
 
     fn update_width(&mut self, new_width: impl Into<Option<f64>>) {
         let new_width = new_width
             .into()
-            .map(|w| pango::SCALE.saturating_mul(w as i32))
+            .map(pango_width)
             .unwrap_or(UNBOUNDED_WRAP_WIDTH);
         self.pango_layout.set_width(new_width);
 
         let mut line_metrics = Vec::new();
         let mut x_offsets = Vec::new();
+        let mut line_widths = Vec::new();
         let mut y_offset = 0.;
         let mut widest_logical_width = 0;
         let mut widest_whitespaceless_width = 0;
@@ -537,6 +1133,7 @@ impl CairoTextLayout {
             widest_whitespaceless_width = widest_whitespaceless_width.max(non_ws_width);
 
             x_offsets.push(logical_rect.x());
+            line_widths.push(non_ws_width);
             line_metrics.push(LineMetric {
                 start_offset,
                 end_offset,
@@ -555,6 +1152,7 @@ impl CairoTextLayout {
         //NOTE: Pango appears to always give us at least one line even with empty input
         self.line_metrics = line_metrics.into();
         self.x_offsets = x_offsets.into();
+        self.line_widths = line_widths.into();
 
         let (ink_extent, logical_extent) = self.pango_layout.extents();
         let ink_extent = to_kurbo_rect(ink_extent);
@@ -571,6 +1169,24 @@ impl CairoTextLayout {
     }
 }
 
+/// Convert the description of a loaded font, with an absolute size.
+fn font_description(font: &pango::FontDescription) -> FontDescription {
+    let family = font
+        .family()
+        .map(|family| FontFamily::new_unchecked(family.as_str()))
+        .unwrap_or_default();
+    let style = match font.style() {
+        PangoStyle::Normal => FontStyle::Regular,
+        _ => FontStyle::Italic,
+    };
+    FontDescription {
+        family,
+        weight: FontWeight::new(font.weight().into_glib().clamp(1, 1000) as u16),
+        style,
+        size: font.size() as f64 / PANGO_SCALE,
+    }
+}
+
 fn to_kurbo_rect(r: pango::Rectangle) -> Rect {
     Rect::from_origin_size(
         (r.x() as f64 / PANGO_SCALE, r.y() as f64 / PANGO_SCALE),
@@ -622,6 +1238,163 @@ mod test {
         assert_close!(line.height, 12.0, 3.0);
     }
 
+    #[test]
+    fn text_conformance() {
+        piet::conformance::check_all(&mut CairoText::new());
+    }
+
+    #[test]
+    fn layout_cache_reuses_layouts() {
+        let mut text = CairoText::new();
+        let build = |text: &mut CairoText, width| {
+            text.new_text_layout("hello world")
+                .max_width(width)
+                .range_attribute(0..5, TextAttribute::Underline(true))
+                .build()
+                .unwrap()
+        };
+        let one = build(&mut text, 100.0);
+        let two = build(&mut text, 100.0);
+        let three = build(&mut text, 50.0);
+        assert_eq!(one.pango_layout(), two.pango_layout());
+        assert_ne!(one.pango_layout(), three.pango_layout());
+
+        text.set_cache_size(0);
+        let four = build(&mut text, 100.0);
+        assert_ne!(one.pango_layout(), four.pango_layout());
+    }
+
+    #[test]
+    fn best_family_skips_missing_families() {
+        let family = CairoText::new().best_family(&["Not A Real Font Family", "Monospace"]);
+        assert_eq!(family, FontFamily::MONOSPACE);
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    fn isolated_text_sees_no_system_fonts() {
+        let mut text = CairoText::new_isolated().unwrap();
+        let family = text.best_family(&["DejaVu Sans", "Noto Sans"]);
+        assert_eq!(family, FontFamily::default());
+        assert!(text.load_font(&[0; 16]).is_err());
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    fn loaded_font_is_private_to_text() {
+        let mut text = CairoText::new();
+        let family = text
+            .load_font(include_bytes!(
+                "../../piet/snapshots/resources/Inconsolata-variable.ttf"
+            ))
+            .unwrap();
+        assert_eq!(family.name(), "Inconsolata");
+        assert_eq!(text.best_family(&["Inconsolata"]), family);
+
+        let other = CairoText::new();
+        assert_ne!(
+            text.pango_context.font_map(),
+            other.pango_context.font_map()
+        );
+    }
+
+    #[test]
+    fn draw_decorations_with_piet() {
+        let mut text = CairoText::new();
+        let build = |text: &mut CairoText| {
+            text.new_text_layout("hello world")
+                .range_attribute(0..5, TextAttribute::Underline(true))
+                .range_attribute(6..11, TextAttribute::Strikethrough(true))
+                .build()
+                .unwrap()
+        };
+        assert_eq!(build(&mut text).decoration_paths().count(), 0);
+        text.set_draw_decorations(true);
+        assert_eq!(build(&mut text).decoration_paths().count(), 2);
+    }
+
+    #[test]
+    fn layout_cache_distinguishes_locales() {
+        let mut text = CairoText::new();
+        let default = text.new_text_layout("istanbul").build().unwrap();
+        let turkish = text
+            .new_text_layout("istanbul")
+            .locale("tr")
+            .build()
+            .unwrap();
+        assert_ne!(default.pango_layout(), turkish.pango_layout());
+    }
+
+    #[test]
+    fn layout_cache_distinguishes_antialiasing() {
+        let mut text = CairoText::new();
+        let default = text.new_text_layout("aliased").build().unwrap();
+        let aliased = text
+            .new_text_layout("aliased")
+            .antialiasing(TextAntialiasing::Aliased)
+            .build()
+            .unwrap();
+        assert_ne!(default.pango_layout(), aliased.pango_layout());
+    }
+
+    #[test]
+    fn caret_rect_glyph_height() {
+        let mut text = CairoText::new();
+        let layout = text
+            .new_text_layout("small LARGE")
+            .font(FontFamily::SANS_SERIF, 12.0)
+            .range_attribute(6.., TextAttribute::FontSize(36.0))
+            .build()
+            .unwrap();
+        let line = layout.line_bounds(0).unwrap();
+
+        let caret = layout.caret_rect(2, CaretHeight::Line);
+        assert_eq!((caret.y0, caret.y1), (line.y0, line.y1));
+        assert_eq!(caret.width(), piet::CARET_WIDTH);
+
+        // next to the small text the caret is shorter than the line, but
+        // it still meets the baseline
+        let baseline = layout.line_metric(0).unwrap().baseline;
+        let small = layout.caret_rect(2, CaretHeight::Glyph);
+        let large = layout.caret_rect(8, CaretHeight::Glyph);
+        assert!(small.height() < large.height());
+        assert!(large.height() <= line.height() + 1.0);
+        assert!(small.y0 < baseline && baseline < small.y1);
+    }
+
+    #[test]
+    fn resolved_fonts_follow_size_changes() {
+        let mut text = CairoText::new();
+        let layout = text
+            .new_text_layout("small LARGE")
+            .font(FontFamily::SANS_SERIF, 12.0)
+            .range_attribute(6.., TextAttribute::FontSize(36.0))
+            .build()
+            .unwrap();
+        let fonts = layout.resolved_fonts();
+        assert_eq!(fonts.len(), 2);
+        assert_eq!((fonts[0].0.clone(), fonts[1].0.clone()), (0..6, 6..11));
+        assert_close!(fonts[0].1.size, 12.0, 0.01);
+        assert_close!(fonts[1].1.size, 36.0, 0.01);
+        assert!(!fonts[0].1.family.is_generic());
+    }
+
+    #[test]
+    fn links_are_recorded() {
+        let mut text = CairoText::new();
+        let layout = text
+            .new_text_layout("see the docs")
+            .default_attribute(TextAttribute::Link("a".into()))
+            .range_attribute(8.., TextAttribute::Link("b".into()))
+            .build()
+            .unwrap();
+        assert_eq!(layout.links(), [(0..8, "a"), (8..12, "b")]);
+
+        let rects = layout.link_rects();
+        assert_eq!(rects.len(), 2);
+        assert_eq!(layout.link_at_point(rects[1].0.center()), Some("b"));
+    }
+
     #[test]
     #[cfg(any(target_os = "linux", target_os = "openbsd"))]
     fn test_hit_test_point_complex_1() {
@@ -0,0 +1,189 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Rendering animations, and saving them as animated PNGs or GIFs.
+
+#[cfg(any(feature = "png", feature = "gif"))]
+use std::fs::File;
+#[cfg(any(feature = "png", feature = "gif"))]
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+use piet::{util, Color, Error, ImageFormat, RenderContext};
+
+use crate::{Device, Piet};
+
+/// An animation, drawn one frame at a time into a bitmap target.
+///
+/// This is meant for making animated images for documentation from the same
+/// code that draws a sample:
+///
+/// ```no_run
+/// # use piet_common::{Animation, Color, Device, RenderContext};
+/// # use piet_common::kurbo::{Affine, Rect};
+/// let mut device = Device::new().unwrap();
+/// Animation::new(100, 100, 30)
+///     .save_apng(&mut device, "spin.png", |rc, frame| {
+///         let angle = frame as f64 / 30.0 * std::f64::consts::TAU;
+///         rc.clear(None, Color::WHITE);
+///         rc.transform(Affine::rotate_about(angle, (50.0, 50.0).into()));
+///         rc.fill(Rect::new(30.0, 30.0, 70.0, 70.0), &Color::BLACK);
+///         Ok(())
+///     })
+///     .unwrap();
+/// ```
+///
+/// Each frame starts out transparent, with the transform and clip of a new
+/// render context, and is passed to the draw function with its number,
+/// counting from zero.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    width: usize,
+    height: usize,
+    pix_scale: f64,
+    frame_count: usize,
+    frame_delay: Duration,
+}
+
+impl Animation {
+    /// Create an animation of `frame_count` frames, `width` by `height`
+    /// pixels, each shown for a thirtieth of a second.
+    pub fn new(width: usize, height: usize, frame_count: usize) -> Animation {
+        Animation {
+            width,
+            height,
+            pix_scale: 1.0,
+            frame_count,
+            frame_delay: Duration::from_secs(1) / 30,
+        }
+    }
+
+    /// Set the scale of drawing to pixels, as for [`Device::bitmap_target`].
+    pub fn pix_scale(mut self, pix_scale: f64) -> Self {
+        self.pix_scale = pix_scale;
+        self
+    }
+
+    /// Set how long each frame is shown for.
+    ///
+    /// GIFs only have a resolution of a hundredth of a second, and animated
+    /// PNGs of a millisecond.
+    pub fn frame_delay(mut self, frame_delay: Duration) -> Self {
+        self.frame_delay = frame_delay;
+        self
+    }
+
+    /// Draw each frame in turn, and pass its pixels to `frame_done`.
+    ///
+    /// The pixels are RGBA, not premultiplied, in rows of `width * 4` bytes.
+    /// The same buffer is used for every frame.
+    pub fn render(
+        &self,
+        device: &mut Device,
+        mut draw: impl FnMut(&mut Piet, usize) -> Result<(), Error>,
+        mut frame_done: impl FnMut(&mut [u8]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut target = device.bitmap_target(self.width, self.height, self.pix_scale)?;
+        let mut buf = vec![0; self.width * self.height * 4];
+        for frame in 0..self.frame_count {
+            let mut rc = target.render_context();
+            rc.clear(None, Color::TRANSPARENT);
+            let drawn = rc.with_save(|rc| draw(rc, frame));
+            // `finish` is called even if drawing failed; see `BitmapTarget::render_context`.
+            let finished = rc.finish();
+            std::mem::drop(rc);
+            drawn.and(finished)?;
+
+            target.copy_raw_pixels(ImageFormat::RgbaPremul, &mut buf)?;
+            util::unpremultiply_rgba(&mut buf);
+            frame_done(&mut buf)?;
+        }
+        Ok(())
+    }
+
+    /// Render the animation and save it as an animated PNG, which plays in
+    /// a loop.
+    #[cfg(feature = "png")]
+    pub fn save_apng<P: AsRef<Path>>(
+        &self,
+        device: &mut Device,
+        path: P,
+        draw: impl FnMut(&mut Piet, usize) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let frame_count = u32::try_from(self.frame_count).map_err(|_| Error::InvalidInput)?;
+        let delay_ms = u16::try_from(self.frame_delay.as_millis()).unwrap_or(u16::MAX);
+        let file = BufWriter::new(File::create(path).map_err(Into::<Box<_>>::into)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(frame_count, 0)
+            .map_err(Into::<Box<_>>::into)?;
+        encoder
+            .set_frame_delay(delay_ms, 1000)
+            .map_err(Into::<Box<_>>::into)?;
+        let mut writer = encoder.write_header().map_err(Into::<Box<_>>::into)?;
+        self.render(device, draw, |data| {
+            writer
+                .write_image_data(data)
+                .map_err(|e| Error::BackendError(e.into()))
+        })?;
+        writer.finish().map_err(Into::<Box<_>>::into)?;
+        Ok(())
+    }
+
+    /// Stub for feature is missing
+    #[cfg(not(feature = "png"))]
+    pub fn save_apng<P: AsRef<Path>>(
+        &self,
+        _device: &mut Device,
+        _path: P,
+        _draw: impl FnMut(&mut Piet, usize) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        Err(Error::MissingFeature("png"))
+    }
+
+    /// Render the animation and save it as a GIF, which plays in a loop.
+    ///
+    /// The colors of each frame are reduced to a palette of 256, and pixels
+    /// are either fully transparent or fully opaque.
+    #[cfg(feature = "gif")]
+    pub fn save_gif<P: AsRef<Path>>(
+        &self,
+        device: &mut Device,
+        path: P,
+        draw: impl FnMut(&mut Piet, usize) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let width = u16::try_from(self.width).map_err(|_| Error::InvalidInput)?;
+        let height = u16::try_from(self.height).map_err(|_| Error::InvalidInput)?;
+        // in hundredths of a second
+        let delay = u16::try_from(self.frame_delay.as_millis() / 10).unwrap_or(u16::MAX);
+        let file = BufWriter::new(File::create(path).map_err(Into::<Box<_>>::into)?);
+        let mut encoder =
+            gif::Encoder::new(file, width, height, &[]).map_err(Into::<Box<_>>::into)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(Into::<Box<_>>::into)?;
+        self.render(device, draw, |data| {
+            let mut frame = gif::Frame::from_rgba_speed(width, height, data, 10);
+            frame.delay = delay;
+            // a frame replaces the last, rather than being drawn over it
+            frame.dispose = gif::DisposalMethod::Background;
+            encoder
+                .write_frame(&frame)
+                .map_err(|e| Error::BackendError(e.into()))
+        })
+    }
+
+    /// Stub for feature is missing
+    #[cfg(not(feature = "gif"))]
+    pub fn save_gif<P: AsRef<Path>>(
+        &self,
+        _device: &mut Device,
+        _path: P,
+        _draw: impl FnMut(&mut Piet, usize) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        Err(Error::MissingFeature("gif"))
+    }
+}
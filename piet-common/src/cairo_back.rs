@@ -18,7 +18,10 @@ use std::path::Path;
 
 #[cfg(feature = "png")]
 use piet::util;
-use piet::{ImageBuf, ImageFormat};
+use piet::{ColorSpace, ImageBuf, ImageFormat};
+
+use crate::DeviceOptions;
+
 #[doc(hidden)]
 pub use piet_cairo::*;
 
@@ -52,26 +55,57 @@ pub type PietImage = CairoImage;
 
 /// A struct that can be used to create bitmap render contexts.
 ///
-/// In the case of Cairo, being a software renderer, no state is needed.
+/// In the case of Cairo, being a software renderer, the only state is the
+/// options it was created with.
 pub struct Device {
+    format: Format,
     // Since not all backends can support `Device: Sync`, make it non-Sync here to, for fewer
     // portability surprises.
     marker: std::marker::PhantomData<*const ()>,
 }
 
+/// Options specific to the Cairo backend, for [`DeviceOptions::backend`].
+///
+/// [`DeviceOptions::backend`]: crate::DeviceOptions::backend
+#[derive(Debug, Clone, Default)]
+pub struct BackendOptions {
+    /// Whether bitmap targets have no alpha channel.
+    ///
+    /// An opaque target is an `Rgb24` image surface, which Cairo can draw to
+    /// a little faster; its pixels are always read back with full alpha.
+    pub opaque: bool,
+}
+
 unsafe impl Send for Device {}
 
 /// A struct provides a `RenderContext` and then can have its bitmap extracted.
 pub struct BitmapTarget<'a> {
     surface: ImageSurface,
     cr: Context,
+    pix_scale: f64,
     phantom: PhantomData<&'a ()>,
 }
 
 impl Device {
     /// Create a new device.
     pub fn new() -> Result<Device, piet::Error> {
+        Device::new_with_options(DeviceOptions::default())
+    }
+
+    /// Create a new device with the given options.
+    ///
+    /// Cairo surfaces are always sRGB, so other color spaces are not
+    /// supported.
+    pub fn new_with_options(options: DeviceOptions) -> Result<Device, piet::Error> {
+        if options.color_space != ColorSpace::Srgb {
+            return Err(piet::Error::NotSupported);
+        }
+        let format = match options.backend.opaque {
+            true => Format::Rgb24,
+            false => Format::ARgb32,
+        };
         Ok(Device {
+            format,
             marker: std::marker::PhantomData,
         })
     }
@@ -83,13 +117,14 @@ impl Device {
         height: usize,
         pix_scale: f64,
     ) -> Result<BitmapTarget, piet::Error> {
-        let surface = ImageSurface::create(Format::ARgb32, width as i32, height as i32).unwrap();
+        let surface = ImageSurface::create(self.format, width as i32, height as i32).unwrap();
         let cr = Context::new(&surface).unwrap();
         cr.scale(pix_scale, pix_scale);
         let phantom = Default::default();
         Ok(BitmapTarget {
             surface,
             cr,
+            pix_scale,
             phantom,
         })
     }
@@ -101,7 +136,9 @@ impl<'a> BitmapTarget<'a> {
     /// Note: caller is responsible for calling `finish` on the render
     /// context at the end of rendering.
     pub fn render_context(&mut self) -> CairoRenderContext {
-        CairoRenderContext::new(&self.cr)
+        let mut rc = CairoRenderContext::new(&self.cr);
+        rc.set_scale(self.pix_scale);
+        rc
     }
 
     /// Get raw RGBA pixels from the bitmap by copying them into `buf`. If all the pixels were
@@ -113,6 +150,26 @@ impl<'a> BitmapTarget<'a> {
         &mut self,
         fmt: ImageFormat,
         buf: &mut [u8],
+    ) -> Result<usize, piet::Error> {
+        let stride = self.surface.width() as usize * 4;
+        self.copy_raw_pixels_with_stride(fmt, buf, stride)
+    }
+
+    /// Get raw RGBA pixels from the bitmap by copying them into `buf`, with the start of each
+    /// row `dst_stride` bytes after the start of the previous one.
+    ///
+    /// This is [`copy_raw_pixels`] for buffers with padding at the end of each row, such as
+    /// frames for a video encoder; the padding is not written. If all the pixels were copied,
+    /// returns the number of bytes from the start of the first row to the end of the last. If
+    /// `dst_stride` is shorter than a row or `buf` wasn't big enough, returns an error and
+    /// doesn't write anything.
+    ///
+    /// [`copy_raw_pixels`]: BitmapTarget::copy_raw_pixels
+    pub fn copy_raw_pixels_with_stride(
+        &mut self,
+        fmt: ImageFormat,
+        buf: &mut [u8],
+        dst_stride: usize,
     ) -> Result<usize, piet::Error> {
         // TODO: convert other formats.
         if fmt != ImageFormat::RgbaPremul {
@@ -123,19 +180,20 @@ impl<'a> BitmapTarget<'a> {
         self.surface.flush();
 
         // Get the surface dimensions.
+        let opaque = self.surface.format() == Format::Rgb24;
         let stride = self.surface.stride() as usize;
         let width = self.surface.width() as usize;
         let height = self.surface.height() as usize;
 
-        // Get the expected destination and source buffer lengths.
-        let dst_len = width * height * 4;
-        let src_len = height.saturating_sub(1) * stride + width * 4;
-
         // Validate that the buffer we will be writing into has at least the
-        // required length to be filled.
-        if buf.len() < dst_len {
+        // required length to be filled. The length is computed with checked
+        // arithmetic, as a huge stride would otherwise wrap around.
+        if dst_stride < width * 4 {
             return Err(piet::Error::InvalidInput);
         }
+        let dst_len = piet::util::validate_image_args(width, height, dst_stride, fmt, buf.len())
+            .map_err(|_| piet::Error::InvalidInput)?;
+        let src_len = piet::util::expected_image_buffer_size(width * 4, height, stride);
 
         // Copy the the image surface data to the destination buffer.
         self.surface
@@ -147,11 +205,12 @@ impl<'a> BitmapTarget<'a> {
                 unsafe {
                     for y in 0..height {
                         let src_off = y * stride;
-                        let dst_off = y * width * 4;
+                        let dst_off = y * dst_stride;
                         for x in 0..width {
                             // These unchecked indexes allow the autovectorizer to shine.
-                            // Note that dst_off maxes out at (height - 1) * width * 4, and so
-                            // dst_off + x * 4 + 3 maxes out at height * width * 4 - 1, which is size - 1.
+                            // Note that dst_off maxes out at (height - 1) * dst_stride, and so
+                            // dst_off + x * 4 + 3 maxes out at (height - 1) * dst_stride + width * 4 - 1,
+                            // which is dst_len - 1.
                             // Also, src_off maxes out at (height - 1) * stride, and so
                             // src_off + x * 4 + 3 maxes out at (height - 1) * stride + width * 4 - 1,
                             // which is data_len - 1.
@@ -161,8 +220,12 @@ impl<'a> BitmapTarget<'a> {
                                 *src.get_unchecked(src_off + x * 4 + 1);
                             *buf.get_unchecked_mut(dst_off + x * 4 + 2) =
                                 *src.get_unchecked(src_off + x * 4 + 0);
-                            *buf.get_unchecked_mut(dst_off + x * 4 + 3) =
-                                *src.get_unchecked(src_off + x * 4 + 3);
+                            // The alpha byte of an opaque surface is undefined.
+                            *buf.get_unchecked_mut(dst_off + x * 4 + 3) = if opaque {
+                                0xff
+                            } else {
+                                *src.get_unchecked(src_off + x * 4 + 3)
+                            };
                         }
                     }
                 }
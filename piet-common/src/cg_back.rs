@@ -11,13 +11,17 @@ use std::path::Path;
 #[cfg(feature = "png")]
 use std::{fs::File, io::BufWriter};
 
-use core_graphics::{color_space::CGColorSpace, context::CGContext};
+use core_graphics::color_space::{kCGColorSpaceDisplayP3, kCGColorSpaceLinearSRGB, CGColorSpace};
+use core_graphics::context::CGContext;
 #[cfg(feature = "png")]
 use png::{ColorType, Encoder};
 
 #[cfg(feature = "png")]
 use piet::util;
-use piet::{Error, ImageBuf, ImageFormat};
+use piet::{ColorSpace, Error, ImageBuf, ImageFormat};
+
+use crate::DeviceOptions;
+
 #[doc(hidden)]
 pub use piet_coregraphics::*;
 
@@ -51,6 +55,7 @@ pub type PietImage = CoreGraphicsImage;
 
 /// A struct that can be used to create bitmap render contexts.
 pub struct Device {
+    color_space: ColorSpace,
     // Since not all backends can support `Device: Sync`, make it non-Sync here to, for fewer
     // portability surprises.
     marker: std::marker::PhantomData<*const ()>,
@@ -58,17 +63,37 @@ pub struct Device {
 
 unsafe impl Send for Device {}
 
+/// Options specific to the CoreGraphics backend, for
+/// [`DeviceOptions::backend`].
+///
+/// There are none yet.
+///
+/// [`DeviceOptions::backend`]: crate::DeviceOptions::backend
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct BackendOptions {}
+
 /// A struct provides a `RenderContext` and then can have its bitmap extracted.
 pub struct BitmapTarget<'a> {
     ctx: CGContext,
     height: f64,
+    pix_scale: f64,
     phantom: PhantomData<&'a ()>,
 }
 
 impl Device {
     /// Create a new device.
     pub fn new() -> Result<Device, piet::Error> {
+        Device::new_with_options(DeviceOptions::default())
+    }
+
+    /// Create a new device with the given options.
+    ///
+    /// Bitmap targets are in `options.color_space`, and their pixels are
+    /// read back in it.
+    pub fn new_with_options(options: DeviceOptions) -> Result<Device, piet::Error> {
         Ok(Device {
+            color_space: options.color_space,
             marker: std::marker::PhantomData,
         })
     }
@@ -86,7 +111,7 @@ impl Device {
             height,
             8,
             0,
-            &CGColorSpace::create_device_rgb(),
+            &bitmap_color_space(self.color_space),
             core_graphics::base::kCGImageAlphaPremultipliedLast,
         );
         ctx.scale(pix_scale, pix_scale);
@@ -94,18 +119,31 @@ impl Device {
         Ok(BitmapTarget {
             ctx,
             height,
+            pix_scale,
             phantom: PhantomData,
         })
     }
 }
 
+/// The `CGColorSpace` of a bitmap target in a piet [`ColorSpace`].
+fn bitmap_color_space(color_space: ColorSpace) -> CGColorSpace {
+    let name = match color_space {
+        ColorSpace::DisplayP3 => unsafe { kCGColorSpaceDisplayP3 },
+        ColorSpace::Linear => unsafe { kCGColorSpaceLinearSRGB },
+        _ => return CGColorSpace::create_device_rgb(),
+    };
+    CGColorSpace::create_with_name(name).unwrap_or_else(CGColorSpace::create_device_rgb)
+}
+
 impl<'a> BitmapTarget<'a> {
     /// Get a piet `RenderContext` for the bitmap.
     ///
     /// Note: caller is responsible for calling `finish` on the render
     /// context at the end of rendering.
     pub fn render_context(&mut self) -> CoreGraphicsContext {
-        CoreGraphicsContext::new_y_up(&mut self.ctx, self.height, None)
+        let mut rc = CoreGraphicsContext::new_y_up(&mut self.ctx, self.height, None);
+        rc.set_scale(self.pix_scale);
+        rc
     }
 
     /// Get an in-memory pixel buffer from the bitmap.
@@ -131,6 +169,26 @@ impl<'a> BitmapTarget<'a> {
         &mut self,
         fmt: ImageFormat,
         buf: &mut [u8],
+    ) -> Result<usize, piet::Error> {
+        let stride = self.ctx.width() * 4;
+        self.copy_raw_pixels_with_stride(fmt, buf, stride)
+    }
+
+    /// Get raw RGBA pixels from the bitmap by copying them into `buf`, with the start of each
+    /// row `dst_stride` bytes after the start of the previous one.
+    ///
+    /// This is [`copy_raw_pixels`] for buffers with padding at the end of each row, such as
+    /// frames for a video encoder; the padding is not written. If all the pixels were copied,
+    /// returns the number of bytes from the start of the first row to the end of the last. If
+    /// `dst_stride` is shorter than a row or `buf` wasn't big enough, returns an error and
+    /// doesn't write anything.
+    ///
+    /// [`copy_raw_pixels`]: BitmapTarget::copy_raw_pixels
+    pub fn copy_raw_pixels_with_stride(
+        &mut self,
+        fmt: ImageFormat,
+        buf: &mut [u8],
+        dst_stride: usize,
     ) -> Result<usize, piet::Error> {
         // TODO: convert other formats.
         if fmt != ImageFormat::RgbaPremul {
@@ -141,21 +199,23 @@ impl<'a> BitmapTarget<'a> {
         let height = self.ctx.height();
         let stride = self.ctx.bytes_per_row();
         let data = self.ctx.data();
-        let size = width * height * 4;
-        if buf.len() < size {
+        let used_stride = width * 4;
+        if dst_stride < used_stride {
             return Err(piet::Error::InvalidInput);
         }
-        let used_stride = width * 4;
-        if stride != used_stride {
+        // Checked, so that a huge stride can't wrap around to a small size.
+        let size = piet::util::validate_image_args(width, height, dst_stride, fmt, buf.len())
+            .map_err(|_| piet::Error::InvalidInput)?;
+        if stride == used_stride && dst_stride == used_stride {
+            buf[..size].copy_from_slice(&data[..size]);
+        } else {
             for y in 0..height {
                 let src_start = y * stride;
                 let src_end = src_start + used_stride;
-                let dst_start = y * used_stride;
+                let dst_start = y * dst_stride;
                 let dst_end = dst_start + used_stride;
                 buf[dst_start..dst_end].copy_from_slice(&data[src_start..src_end])
             }
-        } else {
-            buf.copy_from_slice(data);
         }
         Ok(size)
     }
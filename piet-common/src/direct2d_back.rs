@@ -15,11 +15,14 @@ use png::{ColorType, Encoder};
 
 #[cfg(feature = "png")]
 use piet::util;
-use piet::{ImageBuf, ImageFormat};
+use piet::{ColorSpace, ImageBuf, ImageFormat};
 use piet_direct2d::d2d::{Bitmap, Brush as D2DBrush};
 use piet_direct2d::d3d::{
     D3D11Device, D3D11DeviceContext, D3D11Texture2D, TextureMode, DXGI_MAP_READ,
 };
+
+use crate::DeviceOptions;
+
 #[doc(hidden)]
 pub use piet_direct2d::*;
 
@@ -60,6 +63,20 @@ pub struct Device {
     device: D2DDevice,
 }
 
+/// Options specific to the Direct2D backend, for [`DeviceOptions::backend`].
+///
+/// [`DeviceOptions::backend`]: crate::DeviceOptions::backend
+#[derive(Debug, Clone, Default)]
+pub struct BackendOptions {
+    /// Whether to enable the Direct3D and Direct2D debug layers, which report
+    /// misuse of the APIs to the debugger output.
+    ///
+    /// The Direct2D debug layer is also enabled in debug builds when this is
+    /// `false`. The debug layers are only available where the Windows SDK is
+    /// installed; elsewhere creating the device fails.
+    pub debug_layer: bool,
+}
+
 /// A struct provides a `RenderContext` and then can have its bitmap extracted.
 pub struct BitmapTarget<'a> {
     width: usize,
@@ -69,6 +86,7 @@ pub struct BitmapTarget<'a> {
     d3d: &'a D3D11Device,
     d3d_ctx: &'a D3D11DeviceContext,
     tex: D3D11Texture2D,
+    read_texture: Option<D3D11Texture2D>,
     context: D2DDeviceContext,
 }
 
@@ -78,11 +96,27 @@ impl Device {
     /// This creates new Direct2D and DirectWrite factories, a Direct3D
     /// device, and a Direct2D device.
     pub fn new() -> Result<Device, piet::Error> {
-        let d2d = D2DFactory::new().unwrap();
+        Device::new_with_options(DeviceOptions::default())
+    }
+
+    /// Create a new device with the given options.
+    ///
+    /// Bitmap targets are always sRGB, so other color spaces are not
+    /// supported.
+    pub fn new_with_options(options: DeviceOptions) -> Result<Device, piet::Error> {
+        if options.color_space != ColorSpace::Srgb {
+            return Err(piet::Error::NotSupported);
+        }
+        let debug_layer = options.backend.debug_layer;
+        let d2d = match debug_layer {
+            true => D2DFactory::new_with_debug_layer(true)?,
+            false => D2DFactory::new()?,
+        };
         let dwrite = DwriteFactory::new().unwrap();
 
         // Initialize a D3D Device
-        let (d3d, d3d_ctx) = D3D11Device::create().unwrap();
+        let (d3d, d3d_ctx) = D3D11Device::create_with_debug_layer(debug_layer)
+            .map_err(|e| piet::Error::BackendError(Box::new(e)))?;
 
         // Create the D2D Device
         let device = unsafe { d2d.create_device(d3d.as_dxgi().unwrap().as_raw()).unwrap() };
@@ -96,6 +130,33 @@ impl Device {
         })
     }
 
+    /// Whether the Direct3D device was lost, for instance because of a
+    /// driver update or a GPU reset.
+    ///
+    /// A lost device can not render anything; call [`recreate`] before
+    /// creating another bitmap target.
+    ///
+    /// [`recreate`]: Device::recreate
+    pub fn is_device_lost(&self) -> bool {
+        self.d3d.is_removed()
+    }
+
+    /// Recreate the Direct3D and Direct2D devices, keeping the factories.
+    ///
+    /// Images created by render contexts of the old device belong to it, and
+    /// must be made again from their source data. Fonts and text layouts do
+    /// not depend on the device and can be kept.
+    pub fn recreate(&mut self) -> Result<(), piet::Error> {
+        let (d3d, d3d_ctx) =
+            D3D11Device::create().map_err(|e| piet::Error::BackendError(Box::new(e)))?;
+        let dxgi = d3d.as_dxgi().ok_or(piet::Error::NotSupported)?;
+        let device = unsafe { self.d2d.create_device(dxgi.as_raw())? };
+        self.d3d = d3d;
+        self.d3d_ctx = d3d_ctx;
+        self.device = device;
+        Ok(())
+    }
+
     /// Create a new bitmap target.
     pub fn bitmap_target(
         &mut self,
@@ -131,6 +192,7 @@ impl Device {
             d3d: &self.d3d,
             d3d_ctx: &self.d3d_ctx,
             tex,
+            read_texture: None,
             context,
         })
     }
@@ -167,41 +229,69 @@ impl<'a> BitmapTarget<'a> {
         &mut self,
         fmt: ImageFormat,
         buf: &mut [u8],
+    ) -> Result<usize, piet::Error> {
+        self.copy_raw_pixels_with_stride(fmt, buf, self.width * 4)
+    }
+
+    /// Get raw RGBA pixels from the bitmap by copying them into `buf`, with the start of each
+    /// row `dst_stride` bytes after the start of the previous one.
+    ///
+    /// This is [`copy_raw_pixels`] for buffers with padding at the end of each row, such as
+    /// frames for a video encoder; the padding is not written. If all the pixels were copied,
+    /// returns the number of bytes from the start of the first row to the end of the last. If
+    /// `dst_stride` is shorter than a row or `buf` wasn't big enough, returns an error and
+    /// doesn't write anything.
+    ///
+    /// [`copy_raw_pixels`]: BitmapTarget::copy_raw_pixels
+    pub fn copy_raw_pixels_with_stride(
+        &mut self,
+        fmt: ImageFormat,
+        buf: &mut [u8],
+        dst_stride: usize,
     ) -> Result<usize, piet::Error> {
         // TODO: convert other formats.
         if fmt != ImageFormat::RgbaPremul {
             return Err(piet::Error::NotSupported);
         }
-        let draw_restarter = self.context.end_draw_temporarily()?;
-        let temp_texture = self
-            .d3d
-            .create_texture(self.width as u32, self.height as u32, TextureMode::Read)
-            .unwrap();
-
-        let size = self.width * self.height * 4;
-        if size > buf.len() {
+        let row_size = self.width * 4;
+        if dst_stride < row_size {
             return Err(piet::Error::InvalidInput);
         }
+        // Checked, so that a huge stride can't wrap around to a small size.
+        let size =
+            piet::util::validate_image_args(self.width, self.height, dst_stride, fmt, buf.len())
+                .map_err(|_| piet::Error::InvalidInput)?;
+
+        let draw_restarter = self.context.end_draw_temporarily()?;
+        // The texture the pixels are read through is kept, so that copying
+        // every frame doesn't make a new one.
+        if self.read_texture.is_none() {
+            let texture = self
+                .d3d
+                .create_texture(self.width as u32, self.height as u32, TextureMode::Read)
+                .map_err(|e| piet::Error::BackendError(Box::new(e)))?;
+            self.read_texture = Some(texture);
+        }
+        let read_texture = self.read_texture.as_ref().unwrap();
 
         // TODO: Have a safe way to accomplish this :D
         unsafe {
             self.d3d_ctx
                 .inner()
-                .CopyResource(temp_texture.as_raw() as *mut _, self.tex.as_raw() as *mut _);
+                .CopyResource(read_texture.as_raw() as *mut _, self.tex.as_raw() as *mut _);
             self.d3d_ctx.inner().Flush();
 
-            let surface = temp_texture.as_dxgi();
+            let surface = read_texture.as_dxgi();
             let mut mapped_rect = std::mem::zeroed();
             let _hr = surface.Map(&mut mapped_rect, DXGI_MAP_READ);
             for y in 0..self.height {
                 let src = mapped_rect
                     .pBits
                     .offset(mapped_rect.Pitch as isize * y as isize);
-                let dst = buf
-                    .as_mut_ptr()
-                    .offset(self.width as isize * 4 * y as isize);
-                std::ptr::copy_nonoverlapping(src, dst, self.width * 4);
+                let dst = buf.as_mut_ptr().add(dst_stride * y);
+                std::ptr::copy_nonoverlapping(src, dst, row_size);
             }
+            surface.Unmap();
         }
 
         drop(draw_restarter); // Make sure the restarter lives until this point for the happy path
@@ -53,6 +53,25 @@ cfg_if::cfg_if! {
 
 pub use backend::*;
 
+mod anim;
+
+pub use crate::anim::Animation;
+
+/// Options for creating a [`Device`], with [`Device::new_with_options`].
+///
+/// The options that aren't portable are in `backend`, whose fields depend on
+/// the selected backend.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceOptions {
+    /// The color space of bitmap targets.
+    ///
+    /// Backends that can't make a target in this color space fail to create
+    /// the device, with [`Error::NotSupported`].
+    pub color_space: ColorSpace,
+    /// Options specific to the selected backend.
+    pub backend: BackendOptions,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -71,5 +90,6 @@ mod test {
     }
 
     sa::assert_impl_all!(Device: Send);
+    sa::assert_impl_all!(DeviceOptions: Send, Sync, Clone, Default);
     sa::assert_not_impl_any!(Device: Sync);
 }
@@ -16,7 +16,10 @@ use std::io::BufWriter;
 use png::{ColorType, Encoder};
 use wasm_bindgen::JsCast;
 
-use piet::{ImageBuf, ImageFormat};
+use piet::{ColorSpace, ImageBuf, ImageFormat};
+
+use crate::DeviceOptions;
+
 #[doc(hidden)]
 pub use piet_web::*;
 
@@ -49,6 +52,7 @@ pub type PietImage = WebImage;
 
 /// A struct that can be used to create bitmap render contexts.
 pub struct Device {
+    options: DeviceOptions,
     // Since not all backends can support `Device: Sync`, make it non-Sync here to, for fewer
     // portability surprises.
     marker: std::marker::PhantomData<*const ()>,
@@ -56,17 +60,43 @@ pub struct Device {
 
 unsafe impl Send for Device {}
 
+/// Options specific to the web backend, for [`DeviceOptions::backend`].
+///
+/// [`DeviceOptions::backend`]: crate::DeviceOptions::backend
+#[derive(Debug, Clone, Default)]
+pub struct BackendOptions {
+    /// Whether the pixels of bitmap targets will be read back often.
+    ///
+    /// This is passed to the canvas as `willReadFrequently`, which makes the
+    /// browser keep the canvas in memory rather than on the GPU, so reading it
+    /// is cheaper and drawing to it is slower.
+    pub will_read_frequently: bool,
+}
+
 /// A struct provides a `RenderContext` and then can have its bitmap extracted.
 pub struct BitmapTarget<'a> {
     canvas: web_sys::HtmlCanvasElement,
     context: web_sys::CanvasRenderingContext2d,
+    pix_scale: f64,
     phantom: PhantomData<&'a ()>,
 }
 
 impl Device {
     /// Create a new device.
     pub fn new() -> Result<Device, piet::Error> {
+        Device::new_with_options(DeviceOptions::default())
+    }
+
+    /// Create a new device with the given options.
+    ///
+    /// Canvases can be sRGB or Display P3, so a linear color space is not
+    /// supported.
+    pub fn new_with_options(options: DeviceOptions) -> Result<Device, piet::Error> {
+        if options.color_space == ColorSpace::Linear {
+            return Err(piet::Error::NotSupported);
+        }
         Ok(Device {
+            options,
             marker: std::marker::PhantomData,
         })
     }
@@ -85,7 +115,7 @@ impl Device {
             .dyn_into::<web_sys::HtmlCanvasElement>()
             .unwrap();
         let context = canvas
-            .get_context("2d")
+            .get_context_with_context_options("2d", &self.context_options())
             .unwrap()
             .unwrap()
             .dyn_into::<web_sys::CanvasRenderingContext2d>()
@@ -98,15 +128,35 @@ impl Device {
         Ok(BitmapTarget {
             canvas,
             context,
+            pix_scale,
             phantom: Default::default(),
         })
     }
+
+    /// The settings for the 2D contexts of bitmap targets.
+    fn context_options(&self) -> js_sys::Object {
+        let color_space = match self.options.color_space {
+            ColorSpace::DisplayP3 => "display-p3",
+            _ => "srgb",
+        };
+        let will_read_frequently = self.options.backend.will_read_frequently;
+        let options = js_sys::Object::new();
+        let _ = js_sys::Reflect::set(&options, &"colorSpace".into(), &color_space.into());
+        let _ = js_sys::Reflect::set(
+            &options,
+            &"willReadFrequently".into(),
+            &will_read_frequently.into(),
+        );
+        options
+    }
 }
 
 impl<'a> BitmapTarget<'a> {
     /// Get a piet `RenderContext` for the bitmap.
     pub fn render_context(&mut self) -> WebRenderContext {
-        WebRenderContext::new(self.context.clone(), web_sys::window().unwrap())
+        let mut rc = WebRenderContext::new(self.context.clone(), web_sys::window().unwrap());
+        rc.set_scale(self.pix_scale);
+        rc
     }
 
     /// Get raw RGBA pixels from the bitmap.
@@ -149,12 +199,58 @@ impl<'a> BitmapTarget<'a> {
         fmt: ImageFormat,
         buf: &mut [u8],
     ) -> Result<usize, piet::Error> {
-        let data = self.raw_pixels(fmt)?;
-        if data.len() > buf.len() {
+        let stride = self.canvas.width() as usize * 4;
+        self.copy_raw_pixels_with_stride(fmt, buf, stride)
+    }
+
+    /// Get raw RGBA pixels from the bitmap by copying them into `buf`, with the start of each
+    /// row `dst_stride` bytes after the start of the previous one.
+    ///
+    /// This is [`copy_raw_pixels`] for buffers with padding at the end of each row, such as
+    /// frames for a video encoder; the padding is not written. If all the pixels were copied,
+    /// returns the number of bytes from the start of the first row to the end of the last. If
+    /// `dst_stride` is shorter than a row or `buf` wasn't big enough, returns an error and
+    /// doesn't write anything.
+    ///
+    /// [`copy_raw_pixels`]: BitmapTarget::copy_raw_pixels
+    pub fn copy_raw_pixels_with_stride(
+        &mut self,
+        fmt: ImageFormat,
+        buf: &mut [u8],
+        dst_stride: usize,
+    ) -> Result<usize, piet::Error> {
+        if fmt != ImageFormat::RgbaPremul {
+            return Err(piet::Error::NotSupported);
+        }
+
+        let width = self.canvas.width() as usize;
+        let height = self.canvas.height() as usize;
+        let row_size = width * 4;
+        if dst_stride < row_size {
             return Err(piet::Error::InvalidInput);
         }
-        buf.copy_from_slice(&data[..]);
-        Ok(data.len())
+        // Checked, so that a huge stride can't wrap around to a small size.
+        let size = piet::util::validate_image_args(width, height, dst_stride, fmt, buf.len())
+            .map_err(|_| piet::Error::InvalidInput)?;
+
+        let img_data = self
+            .context
+            .get_image_data(0.0, 0.0, width as f64, height as f64)
+            .map_err(|jsv| piet::Error::BackendError(Box::new(JsError::new(jsv))))?;
+        // Reading the JS array directly, rather than through `ImageData::data`,
+        // copies the pixels into `buf` without making a `Vec` of them first.
+        let data = js_sys::Reflect::get(&img_data, &"data".into())
+            .map_err(|jsv| piet::Error::BackendError(Box::new(JsError::new(jsv))))?
+            .unchecked_into::<js_sys::Uint8ClampedArray>();
+        if dst_stride == row_size {
+            data.copy_to(&mut buf[..size]);
+        } else {
+            for y in 0..height {
+                let row = data.subarray((y * row_size) as u32, ((y + 1) * row_size) as u32);
+                row.copy_to(&mut buf[y * dst_stride..][..row_size]);
+            }
+        }
+        Ok(size)
     }
 
     /// Save bitmap to RGBA PNG file
@@ -0,0 +1,38 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use piet_common::*;
+
+#[test]
+fn animation_renders_each_frame() {
+    let colors = [
+        Color::rgb8(0xff, 0x00, 0x00),
+        Color::rgb8(0x00, 0xff, 0x00),
+        Color::rgb8(0x00, 0x00, 0xff),
+    ];
+    let mut device = Device::new().unwrap();
+    let mut frames = Vec::new();
+    Animation::new(4, 4, colors.len())
+        .render(
+            &mut device,
+            |rc, frame| {
+                rc.clear(None, colors[frame]);
+                Ok(())
+            },
+            |data| {
+                assert_eq!(data.len(), 4 * 4 * 4);
+                frames.push(data[..4].to_vec());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+    let expected: Vec<_> = colors
+        .iter()
+        .map(|color| {
+            let (r, g, b, a) = color.as_rgba8();
+            vec![r, g, b, a]
+        })
+        .collect();
+    assert_eq!(frames, expected);
+}
@@ -0,0 +1,54 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use piet_common::*;
+
+#[test]
+fn device_with_default_options() {
+    let mut device = Device::new_with_options(DeviceOptions::default()).unwrap();
+    let mut target = device.bitmap_target(4, 4, 1.0).unwrap();
+    let mut ctx = target.render_context();
+    ctx.clear(None, Color::rgb8(0xff, 0x00, 0x00));
+    ctx.finish().unwrap();
+    std::mem::drop(ctx);
+
+    let image = target.to_image_buf(ImageFormat::RgbaPremul).unwrap();
+    assert_eq!(&image.raw_pixels()[..4], &[0xff, 0x00, 0x00, 0xff]);
+}
+
+#[test]
+fn copy_raw_pixels_with_stride_skips_padding() {
+    let mut device = Device::new().unwrap();
+    let mut target = device.bitmap_target(2, 2, 1.0).unwrap();
+    let mut ctx = target.render_context();
+    ctx.clear(None, Color::rgb8(0x00, 0x00, 0xff));
+    ctx.finish().unwrap();
+    std::mem::drop(ctx);
+
+    let mut buf = [0xaa; 12 + 8];
+    let written = target
+        .copy_raw_pixels_with_stride(ImageFormat::RgbaPremul, &mut buf, 12)
+        .unwrap();
+    assert_eq!(written, 20);
+    let blue = [0x00, 0x00, 0xff, 0xff];
+    for row in [&buf[..12], &buf[12..]] {
+        assert_eq!(&row[..8], [blue, blue].concat());
+        assert!(row[8..].iter().all(|&b| b == 0xaa));
+    }
+
+    let short = target.copy_raw_pixels_with_stride(ImageFormat::RgbaPremul, &mut buf, 4);
+    assert!(short.is_err());
+}
+
+#[test]
+fn copy_raw_pixels_with_stride_rejects_overflowing_stride() {
+    let mut device = Device::new().unwrap();
+    let mut target = device.bitmap_target(2, 2, 1.0).unwrap();
+
+    // `stride + 8` wraps around to 4, which would fit in the buffer
+    let mut buf = [0xaa; 20];
+    let result =
+        target.copy_raw_pixels_with_stride(ImageFormat::RgbaPremul, &mut buf, usize::MAX - 3);
+    assert!(result.is_err());
+    assert!(buf.iter().all(|&b| b == 0xaa));
+}
@@ -11,22 +11,27 @@ use std::rc::Rc;
 use core_foundation::{
     array::{CFArray, CFArrayRef, CFIndex},
     attributed_string::CFMutableAttributedString,
-    base::{CFTypeID, TCFType},
+    base::{CFType, CFTypeID, TCFType},
     declare_TCFType,
     dictionary::{CFDictionary, CFDictionaryRef},
     impl_TCFType,
     number::CFNumber,
     string::{CFString, CFStringRef},
 };
-use core_foundation_sys::base::CFRange;
+use core_foundation_sys::attributed_string::CFAttributedStringCreateMutableCopy;
+use core_foundation_sys::base::{kCFAllocatorDefault, CFRange};
 use core_graphics::{
     base::CGFloat,
     color::CGColor,
+    color_space::{
+        kCGColorSpaceDisplayP3, kCGColorSpaceLinearGray, kCGColorSpaceLinearSRGB, CGColorSpace,
+    },
     context::CGContextRef,
     data_provider::CGDataProvider,
-    font::CGFont,
+    font::{CGFont, CGGlyph},
     geometry::{CGAffineTransform, CGPoint, CGRect, CGSize},
-    path::CGPathRef,
+    image::CGImage,
+    path::{CGPath, CGPathRef},
 };
 use core_text::{
     font::{
@@ -38,12 +43,16 @@ use core_text::{
     frame::CTFrame,
     framesetter::CTFramesetter,
     line::{CTLine, CTLineRef, TypographicBounds},
+    run::CTRunRef,
     string_attributes,
 };
 use foreign_types::{ForeignType, ForeignTypeRef};
 
-use piet::kurbo::{Affine, Rect};
-use piet::{util, Color, FontFamily, FontFamilyInner, TextAlignment};
+use piet::kurbo::{self, Affine, PathEl, Rect, Shape};
+use piet::{
+    util, Color, ColorSpace, FontFamily, FontFamilyInner, FontFeature, TextAlignment,
+    UnderlineStyle,
+};
 
 #[derive(Clone)]
 pub(crate) struct AttributedString {
@@ -137,6 +146,23 @@ impl AttributedString {
         AttributedString { inner, rtl }
     }
 
+    /// Copy this string, so that the copy's attributes can be changed
+    /// without affecting it.
+    pub(crate) fn mutable_copy(&self) -> Self {
+        let inner = unsafe {
+            let ptr = CFAttributedStringCreateMutableCopy(
+                kCFAllocatorDefault,
+                0,
+                self.inner.as_concrete_TypeRef(),
+            );
+            CFMutableAttributedString::wrap_under_create_rule(ptr)
+        };
+        AttributedString {
+            inner,
+            rtl: self.rtl,
+        }
+    }
+
     pub(crate) fn set_alignment(&mut self, alignment: TextAlignment) {
         let alignment = CTParagraphStyleSetting::alignment(alignment, self.rtl);
         let settings = [alignment];
@@ -158,15 +184,19 @@ impl AttributedString {
         }
     }
 
+    /// Underline a range of the string.
+    ///
+    /// CoreText has no wavy underline; those are drawn as solid lines.
     #[allow(non_upper_case_globals)]
-    pub(crate) fn set_underline(&mut self, range: CFRange, underline: bool) {
-        const kCTUnderlineStyleNone: i32 = 0x00;
+    pub(crate) fn set_underline(&mut self, range: CFRange, style: UnderlineStyle) {
         const kCTUnderlineStyleSingle: i32 = 0x01;
+        const kCTUnderlinePatternDot: i32 = 0x0100;
+        const kCTUnderlinePatternDash: i32 = 0x0200;
 
-        let value = if underline {
-            kCTUnderlineStyleSingle
-        } else {
-            kCTUnderlineStyleNone
+        let value = match style {
+            UnderlineStyle::Dotted => kCTUnderlineStyleSingle | kCTUnderlinePatternDot,
+            UnderlineStyle::Dashed => kCTUnderlineStyleSingle | kCTUnderlinePatternDash,
+            _ => kCTUnderlineStyleSingle,
         };
         unsafe {
             self.inner.set_attribute(
@@ -177,6 +207,38 @@ impl AttributedString {
         }
     }
 
+    pub(crate) fn set_underline_color(&mut self, range: CFRange, color: Color) {
+        let (r, g, b, a) = color.as_rgba();
+        let color = CGColor::rgb(r, g, b, a);
+        unsafe {
+            self.inner.set_attribute(
+                range,
+                string_attributes::kCTUnderlineColorAttributeName,
+                &color.as_CFType(),
+            )
+        }
+    }
+
+    pub(crate) fn set_baseline_offset(&mut self, range: CFRange, offset: f64) {
+        unsafe {
+            self.inner.set_attribute(
+                range,
+                kCTBaselineOffsetAttributeName,
+                &CFNumber::from(offset).as_CFType(),
+            )
+        }
+    }
+
+    pub(crate) fn set_language(&mut self, range: CFRange, locale: &str) {
+        unsafe {
+            self.inner.set_attribute(
+                range,
+                kCTLanguageAttributeName,
+                &CFString::new(locale).as_CFType(),
+            )
+        }
+    }
+
     pub(crate) fn set_fg_color(&mut self, range: CFRange, color: Color) {
         let (r, g, b, a) = color.as_rgba();
         let color = CGColor::rgb(r, g, b, a);
@@ -265,6 +327,59 @@ impl Line {
         unsafe { CTLineDraw(self.0.as_concrete_TypeRef(), ctx.as_ptr()) }
     }
 
+    /// The ink bounds of each glyph, relative to the line's origin and, like
+    /// the image bounds, upside down.
+    pub(crate) fn get_glyph_bounds(&self) -> Vec<Rect> {
+        let font_key =
+            unsafe { CFString::wrap_under_get_rule(string_attributes::kCTFontAttributeName) };
+        let mut bounds = Vec::new();
+        for run in self.0.glyph_runs().iter() {
+            let font = run.attributes().and_then(|attrs| {
+                attrs
+                    .find(&font_key)
+                    .and_then(|font| font.downcast::<CTFont>())
+            });
+            let Some(font) = font else {
+                continue;
+            };
+            let glyphs = run.glyphs();
+            let positions = run.positions();
+            let mut rects =
+                vec![CGRect::new(&CGPoint::new(0.0, 0.0), &CGSize::new(0.0, 0.0)); glyphs.len()];
+            unsafe {
+                CTFontGetBoundingRectsForGlyphs(
+                    font.as_concrete_TypeRef(),
+                    font_descriptor::kCTFontOrientationDefault,
+                    glyphs.as_ptr(),
+                    rects.as_mut_ptr(),
+                    glyphs.len() as CFIndex,
+                );
+            }
+            for (r, position) in rects.iter().zip(positions.iter()) {
+                bounds.push(Rect::from_origin_size(
+                    (position.x + r.origin.x, position.y + r.origin.y),
+                    (r.size.width, r.size.height),
+                ));
+            }
+        }
+        bounds
+    }
+
+    /// The font of each glyph run, with the range of the string it draws.
+    pub(crate) fn get_font_runs(&self) -> Vec<(CFRange, CTFont)> {
+        let font_key =
+            unsafe { CFString::wrap_under_get_rule(string_attributes::kCTFontAttributeName) };
+        self.0
+            .glyph_runs()
+            .iter()
+            .filter_map(|run| {
+                let font = run.attributes()?.find(&font_key)?.downcast::<CTFont>()?;
+                let range = unsafe { CTRunGetStringRange(run.as_concrete_TypeRef()) };
+                Some((range, font))
+            })
+            .collect()
+    }
+
     pub(crate) fn get_string_index_for_position(&self, position: CGPoint) -> CFIndex {
         self.0.get_string_index_for_position(position)
     }
@@ -290,6 +405,32 @@ pub(crate) fn ct_family_name(family: &FontFamily, size: f64) -> CFString {
     }
 }
 
+/// Convert OpenType features to a value for `kCTFontFeatureSettingsAttribute`.
+///
+/// Features whose tags are not valid ASCII are skipped.
+pub(crate) fn ct_feature_settings(
+    features: &[FontFeature],
+) -> CFArray<CFDictionary<CFString, CFType>> {
+    let (tag_key, value_key) = unsafe {
+        (
+            CFString::wrap_under_get_rule(kCTFontOpenTypeFeatureTag),
+            CFString::wrap_under_get_rule(kCTFontOpenTypeFeatureValue),
+        )
+    };
+    let settings = features
+        .iter()
+        .filter_map(|feature| {
+            let tag = CFString::new(feature.tag_str()?);
+            let value = CFNumber::from(feature.value() as i64);
+            Some(CFDictionary::from_CFType_pairs(&[
+                (tag_key.clone(), tag.as_CFType()),
+                (value_key.clone(), value.as_CFType()),
+            ]))
+        })
+        .collect::<Vec<_>>();
+    CFArray::from_CFTypes(&settings)
+}
+
 /// Create a generic system font.
 fn system_font_family_name(family: &FontFamilyInner, size: f64) -> CFString {
     let font = system_font_impl(family, size).unwrap_or_else(create_font_comma_never_fail_period);
@@ -393,9 +534,182 @@ pub(crate) fn make_font(desc: &CTFontDescriptor, pt_size: f64, affine: Affine) -
     }
 }
 
+/// Build a `CGPath` from a shape.
+///
+/// The path is in the shape's own coordinate space.
+pub(crate) fn make_path(shape: impl Shape) -> CGPath {
+    if let Some(arc) = util::as_arc(&shape) {
+        return make_arc_path(&arc);
+    }
+    unsafe {
+        let path = CGPathCreateMutable();
+        let m = std::ptr::null();
+        for el in shape.path_elements(1e-3) {
+            match el {
+                PathEl::MoveTo(p) => CGPathMoveToPoint(path, m, p.x, p.y),
+                PathEl::LineTo(p) => CGPathAddLineToPoint(path, m, p.x, p.y),
+                PathEl::QuadTo(p1, p2) => {
+                    CGPathAddQuadCurveToPoint(path, m, p1.x, p1.y, p2.x, p2.y)
+                }
+                PathEl::CurveTo(p1, p2, p3) => {
+                    CGPathAddCurveToPoint(path, m, p1.x, p1.y, p2.x, p2.y, p3.x, p3.y)
+                }
+                PathEl::ClosePath => CGPathCloseSubpath(path),
+            }
+        }
+        CGPath::from_ptr(path)
+    }
+}
+
+/// Build a `CGPath` for an elliptical arc, with Core Graphics' own arc
+/// rather than Béziers.
+pub(crate) fn make_arc_path(arc: &kurbo::Arc) -> CGPath {
+    // a unit circle, mapped onto the ellipse
+    let [a, b, c, d, e, f] = (Affine::translate(arc.center.to_vec2())
+        * Affine::rotate(arc.x_rotation)
+        * Affine::scale_non_uniform(arc.radii.x, arc.radii.y))
+    .as_coeffs();
+    let m = CGAffineTransform::new(a, b, c, d, e, f);
+    let end = arc.start_angle + arc.sweep_angle;
+    unsafe {
+        let path = CGPathCreateMutable();
+        // angles increase towards the y axis, which Core Graphics calls
+        // counterclockwise
+        let clockwise = arc.sweep_angle < 0.0;
+        CGPathAddArc(path, &m, 0.0, 0.0, 1.0, arc.start_angle, end, clockwise);
+        if arc.sweep_angle.abs() == std::f64::consts::TAU {
+            CGPathCloseSubpath(path);
+        }
+        CGPath::from_ptr(path)
+    }
+}
+
+/// The `CGColorSpace` for RGB data in a piet [`ColorSpace`].
+pub(crate) fn rgb_color_space(color_space: ColorSpace) -> CGColorSpace {
+    let name = match color_space {
+        ColorSpace::DisplayP3 => unsafe { kCGColorSpaceDisplayP3 },
+        ColorSpace::Linear => unsafe { kCGColorSpaceLinearSRGB },
+        _ => return CGColorSpace::create_device_rgb(),
+    };
+    CGColorSpace::create_with_name(name).unwrap_or_else(CGColorSpace::create_device_rgb)
+}
+
+/// The `CGColorSpace` for grayscale data in a piet [`ColorSpace`].
+///
+/// Display P3 shares its transfer function and white point with sRGB, so
+/// only linear data needs a different space.
+pub(crate) fn gray_color_space(color_space: ColorSpace) -> CGColorSpace {
+    match color_space {
+        ColorSpace::Linear => CGColorSpace::create_with_name(unsafe { kCGColorSpaceLinearGray })
+            .unwrap_or_else(CGColorSpace::create_device_gray),
+        _ => CGColorSpace::create_device_gray(),
+    }
+}
+
+/// Create a `CGColor` from a color in the given [`ColorSpace`].
+pub(crate) fn make_color(color: Color, color_space: ColorSpace) -> CGColor {
+    let space = rgb_color_space(color_space);
+    let (r, g, b, a) = color.as_rgba();
+    let components: [CGFloat; 4] = [r, g, b, a];
+    unsafe {
+        let color = CGColorCreate(space.as_ptr(), components.as_ptr());
+        CGColor::wrap_under_create_rule(color)
+    }
+}
+
+/// Set the stroke color of a context from a `CGColor`.
+pub(crate) fn set_stroke_color(ctx: &CGContextRef, color: &CGColor) {
+    unsafe { CGContextSetStrokeColorWithColor(ctx.as_ptr(), color.as_concrete_TypeRef()) }
+}
+
+/// The `CGImageAlphaInfo` of an image, which says whether and how it stores
+/// alpha.
+pub(crate) fn image_alpha_info(image: &CGImage) -> u32 {
+    unsafe { CGImageGetAlphaInfo(image.as_ptr()) }
+}
+
+/// Begin a transparency layer; drawing is composited into the context as a
+/// single unit when the matching [`end_transparency_layer`] is called.
+pub(crate) fn begin_transparency_layer(ctx: &CGContextRef) {
+    unsafe { CGContextBeginTransparencyLayer(ctx.as_ptr(), std::ptr::null()) }
+}
+
+/// End a transparency layer started with [`begin_transparency_layer`].
+pub(crate) fn end_transparency_layer(ctx: &CGContextRef) {
+    unsafe { CGContextEndTransparencyLayer(ctx.as_ptr()) }
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGContextBeginTransparencyLayer(
+        c: *mut core_graphics::sys::CGContext,
+        aux_info: CFDictionaryRef,
+    );
+    fn CGContextEndTransparencyLayer(c: *mut core_graphics::sys::CGContext);
+    fn CGColorCreate(
+        space: *mut core_graphics::sys::CGColorSpace,
+        components: *const CGFloat,
+    ) -> core_graphics::sys::CGColorRef;
+    fn CGContextSetStrokeColorWithColor(
+        c: *mut core_graphics::sys::CGContext,
+        color: core_graphics::sys::CGColorRef,
+    );
+    fn CGPathCreateMutable() -> CGMutablePathRef;
+    fn CGPathMoveToPoint(
+        path: CGMutablePathRef,
+        m: *const CGAffineTransform,
+        x: CGFloat,
+        y: CGFloat,
+    );
+    fn CGPathAddLineToPoint(
+        path: CGMutablePathRef,
+        m: *const CGAffineTransform,
+        x: CGFloat,
+        y: CGFloat,
+    );
+    fn CGPathAddQuadCurveToPoint(
+        path: CGMutablePathRef,
+        m: *const CGAffineTransform,
+        cpx: CGFloat,
+        cpy: CGFloat,
+        x: CGFloat,
+        y: CGFloat,
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn CGPathAddCurveToPoint(
+        path: CGMutablePathRef,
+        m: *const CGAffineTransform,
+        cp1x: CGFloat,
+        cp1y: CGFloat,
+        cp2x: CGFloat,
+        cp2y: CGFloat,
+        x: CGFloat,
+        y: CGFloat,
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn CGPathAddArc(
+        path: CGMutablePathRef,
+        m: *const CGAffineTransform,
+        x: CGFloat,
+        y: CGFloat,
+        radius: CGFloat,
+        start_angle: CGFloat,
+        end_angle: CGFloat,
+        clockwise: bool,
+    );
+    fn CGPathCloseSubpath(path: CGMutablePathRef);
+    fn CGImageGetAlphaInfo(image: *mut core_graphics::sys::CGImage) -> u32;
+}
+
+type CGMutablePathRef = *mut core_graphics::sys::CGPath;
+
 #[link(name = "CoreText", kind = "framework")]
 extern "C" {
     static kCTFontFamilyNameKey: CFStringRef;
+    static kCTBaselineOffsetAttributeName: CFStringRef;
+    static kCTLanguageAttributeName: CFStringRef;
+    static kCTFontOpenTypeFeatureTag: CFStringRef;
+    static kCTFontOpenTypeFeatureValue: CFStringRef;
 
     pub static kCTFontVariationAxisIdentifierKey: CFStringRef;
     //static kCTFontVariationAxisMinimumValueKey: CFStringRef;
@@ -416,6 +730,14 @@ extern "C" {
     fn CTLineGetImageBounds(line: CTLineRef, ctx: *mut c_void) -> CGRect;
     fn CTLineDraw(line: CTLineRef, ctx: core_graphics::sys::CGContextRef);
     fn CTLineGetTrailingWhitespaceWidth(line: CTLineRef) -> f64;
+    fn CTRunGetStringRange(run: CTRunRef) -> CFRange;
+    fn CTFontGetBoundingRectsForGlyphs(
+        font: CTFontRef,
+        orientation: font_descriptor::CTFontOrientation,
+        glyphs: *const CGGlyph,
+        bounding_rects: *mut CGRect,
+        count: CFIndex,
+    ) -> CGRect;
     fn CTFontCollectionCreateMatchingFontDescriptorsForFamily(
         collection: CTFontCollectionRef,
         family: CFStringRef,
@@ -13,7 +13,7 @@ use core_graphics::{
     gradient::{CGGradient, CGGradientDrawingOptions},
 };
 
-use piet::kurbo::Point;
+use piet::kurbo::{Affine, Point};
 use piet::{Color, FixedGradient, FixedLinearGradient, FixedRadialGradient, GradientStop};
 
 /// A wrapper around CGGradient
@@ -21,6 +21,8 @@ use piet::{Color, FixedGradient, FixedLinearGradient, FixedRadialGradient, Gradi
 pub struct Gradient {
     cg_grad: CGGradient,
     piet_grad: FixedGradient,
+    transform: Affine,
+    opacity: f64,
 }
 
 impl Gradient {
@@ -28,39 +30,72 @@ impl Gradient {
         let cg_grad = match &gradient {
             FixedGradient::Linear(grad) => new_cg_gradient(&grad.stops),
             FixedGradient::Radial(grad) => new_cg_gradient(&grad.stops),
+            FixedGradient::Elliptical(grad) => new_cg_gradient(&grad.stops),
         };
         Gradient {
             cg_grad,
             piet_grad: gradient,
+            transform: Affine::IDENTITY,
+            opacity: 1.0,
         }
     }
 
+    /// Set the transform from the gradient's coordinates to user space.
+    pub(crate) fn with_transform(mut self, transform: Affine) -> Gradient {
+        self.transform = transform;
+        self
+    }
+
+    /// Multiply the opacity the gradient is drawn with.
+    pub(crate) fn with_opacity(mut self, opacity: f64) -> Gradient {
+        self.opacity *= opacity;
+        self
+    }
+
+    /// Draw the gradient, filling the current clip.
+    ///
+    /// The gradient's transform is concatenated to the CTM and its opacity
+    /// is set as the context's alpha, so the caller must save and restore
+    /// the graphics state around this call.
     pub(crate) fn fill(&self, ctx: &mut CGContextRef, options: CGGradientDrawingOptions) {
-        match self.piet_grad {
-            FixedGradient::Radial(FixedRadialGradient {
-                center,
-                origin_offset,
-                radius,
-                ..
-            }) => {
-                let start_center = to_cgpoint(center + origin_offset);
-                let end_center = to_cgpoint(center);
-                ctx.draw_radial_gradient(
-                    &self.cg_grad,
-                    start_center,
-                    0.0,
-                    end_center,
-                    radius as CGFloat,
-                    options,
-                );
+        if self.transform != Affine::IDENTITY {
+            ctx.concat_ctm(crate::to_cgaffine(self.transform));
+        }
+        if self.opacity < 1.0 {
+            ctx.set_alpha(self.opacity);
+        }
+        match &self.piet_grad {
+            FixedGradient::Radial(radial) => self.draw_radial(ctx, radial, options),
+            FixedGradient::Elliptical(elliptical) => {
+                let (radial, transform) = elliptical.to_radial();
+                ctx.concat_ctm(crate::to_cgaffine(transform));
+                self.draw_radial(ctx, &radial, options);
             }
             FixedGradient::Linear(FixedLinearGradient { start, end, .. }) => {
-                let start = to_cgpoint(start);
-                let end = to_cgpoint(end);
+                let start = to_cgpoint(*start);
+                let end = to_cgpoint(*end);
                 ctx.draw_linear_gradient(&self.cg_grad, start, end, options);
             }
         }
     }
+
+    fn draw_radial(
+        &self,
+        ctx: &mut CGContextRef,
+        radial: &FixedRadialGradient,
+        options: CGGradientDrawingOptions,
+    ) {
+        let start_center = to_cgpoint(radial.center + radial.origin_offset);
+        let end_center = to_cgpoint(radial.center);
+        ctx.draw_radial_gradient(
+            &self.cg_grad,
+            start_center,
+            radial.start_radius as CGFloat,
+            end_center,
+            radial.radius as CGFloat,
+            options,
+        );
+    }
 }
 
 fn new_cg_gradient(stops: &[GradientStop]) -> CGGradient {
@@ -8,28 +8,35 @@
 
 mod ct_helpers;
 mod gradient;
+mod pixel_buffer;
 mod text;
 
+use std::any::Any;
 use std::borrow::Cow;
 use std::sync::Arc;
 
 use core_graphics::base::{
-    kCGImageAlphaLast, kCGImageAlphaPremultipliedLast, kCGRenderingIntentDefault, CGFloat,
+    kCGImageAlphaFirst, kCGImageAlphaLast, kCGImageAlphaNone, kCGImageAlphaNoneSkipFirst,
+    kCGImageAlphaNoneSkipLast, kCGImageAlphaPremultipliedLast, kCGRenderingIntentDefault, CGFloat,
 };
 use core_graphics::color_space::CGColorSpace;
-use core_graphics::context::{CGContextRef, CGInterpolationQuality, CGLineCap, CGLineJoin};
+use core_graphics::context::{
+    CGBlendMode, CGContextRef, CGInterpolationQuality, CGLineCap, CGLineJoin,
+};
 use core_graphics::data_provider::CGDataProvider;
 use core_graphics::geometry::{CGAffineTransform, CGPoint, CGRect, CGSize};
 use core_graphics::gradient::CGGradientDrawingOptions;
 use core_graphics::image::CGImage;
+use core_graphics::path::CGPath;
 
 use piet::kurbo::{Affine, PathEl, Point, QuadBez, Rect, Shape, Size};
 
 use piet::{
-    Color, Error, FixedGradient, Image, ImageFormat, InterpolationMode, IntoBrush, LineCap,
-    LineJoin, RenderContext, RoundInto, StrokeStyle,
+    CachedShape, Color, ColorSpace, Error, FixedGradient, Image, ImageBuf, ImageFormat,
+    InterpolationMode, IntoBrush, LineCap, LineJoin, RenderContext, RoundInto, StrokeStyle,
 };
 
+pub use crate::pixel_buffer::{CVPixelBufferRef, IOSurfaceRef, PixelBufferTarget};
 pub use crate::text::{CoreGraphicsText, CoreGraphicsTextLayout, CoreGraphicsTextLayoutBuilder};
 
 use gradient::Gradient;
@@ -53,6 +60,7 @@ pub struct CoreGraphicsContext<'a> {
     transform_stack: Vec<Affine>,
     y_down: bool,
     height: f64,
+    scale: f64,
 }
 
 impl<'a> CoreGraphicsContext<'a> {
@@ -104,8 +112,17 @@ impl<'a> CoreGraphicsContext<'a> {
             transform_stack: Vec::new(),
             y_down,
             height: height.unwrap_or_default(),
+            scale: 1.0,
         }
     }
+
+    /// Set the scale factor reported by [`RenderContext::scale_factor`].
+    ///
+    /// This does not change how anything is drawn; the caller is expected to
+    /// have applied the same scale to the `CGContext`.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
 }
 
 impl<'a> Drop for CoreGraphicsContext<'a> {
@@ -116,7 +133,7 @@ impl<'a> Drop for CoreGraphicsContext<'a> {
 
 #[derive(Clone)]
 pub enum Brush {
-    Solid(Color),
+    Solid(Color, ColorSpace),
     Gradient(Gradient),
 }
 
@@ -130,6 +147,11 @@ pub enum CoreGraphicsImage {
     YDown(CGImage),
 }
 
+// SAFETY: a `CGImage` is immutable, and Core Graphics allows it to be used
+// from any thread.
+unsafe impl Send for CoreGraphicsImage {}
+unsafe impl Sync for CoreGraphicsImage {}
+
 impl CoreGraphicsImage {
     fn from_cgimage_and_ydir(image: CGImage, y_down: bool) -> Self {
         match y_down {
@@ -150,6 +172,7 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
     type Text = CoreGraphicsText;
     type TextLayout = CoreGraphicsTextLayout;
     type Image = CoreGraphicsImage;
+    type CachedShape = CachedShape<CGPath>;
 
     fn clear(&mut self, region: impl Into<Option<Rect>>, color: Color) {
         // save cannot fail
@@ -175,7 +198,11 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
     }
 
     fn solid_brush(&mut self, color: Color) -> Brush {
-        Brush::Solid(color)
+        Brush::Solid(color, ColorSpace::Srgb)
+    }
+
+    fn solid_brush_with_color_space(&mut self, color: Color, color_space: ColorSpace) -> Brush {
+        Brush::Solid(color, color_space)
     }
 
     fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Brush, Error> {
@@ -183,30 +210,42 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
         Ok(Brush::Gradient(gradient))
     }
 
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Brush, Error> {
+        if transform.determinant() == 0.0 {
+            return Err(Error::InvalidInput);
+        }
+        let gradient = Gradient::from_piet_gradient(gradient.into()).with_transform(transform);
+        Ok(Brush::Gradient(gradient))
+    }
+
+    fn brush_with_opacity(&mut self, brush: &Brush, opacity: f64) -> Brush {
+        let opacity = opacity.clamp(0.0, 1.0);
+        match brush {
+            Brush::Solid(color, space) => {
+                let alpha = color.as_rgba().3 * opacity;
+                Brush::Solid(color.with_alpha(alpha), *space)
+            }
+            Brush::Gradient(grad) => Brush::Gradient(grad.clone().with_opacity(opacity)),
+        }
+    }
+
     /// Fill a shape.
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
-        match brush.as_ref() {
-            Brush::Solid(color) => {
-                self.set_fill_color(*color);
-                self.ctx.fill_path();
-            }
-            Brush::Gradient(grad) => {
-                self.ctx.save();
-                self.ctx.clip();
-                grad.fill(self.ctx, GRADIENT_DRAW_BEFORE_AND_AFTER);
-                self.ctx.restore();
-            }
-        }
+        self.fill_current_path(&brush);
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         match brush.as_ref() {
-            Brush::Solid(color) => {
-                self.set_fill_color(*color);
+            Brush::Solid(color, space) => {
+                self.set_fill_color(*color, *space);
                 self.ctx.eo_fill_path();
             }
             Brush::Gradient(grad) => {
@@ -223,23 +262,39 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
         self.ctx.clip();
     }
 
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        self.set_path(shape);
+        self.ctx.eo_clip();
+    }
+
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_stroke(width.round_into(), None);
+        self.stroke_current_path(&brush);
+    }
+
+    fn stroke_hairline(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_path(shape);
+        // The path is already in device space, so undoing our transforms
+        // only affects the line width. The path is not part of the graphics
+        // state, and survives the restore.
+        let transform = self.current_transform();
+        self.ctx.save();
+        if transform.determinant() != 0.0 {
+            self.ctx.concat_ctm(to_cgaffine(transform.inverse()));
+        }
+        // one device pixel, before the scale factor
+        self.set_stroke(self.scale.recip(), None);
         match brush.as_ref() {
-            Brush::Solid(color) => {
-                self.set_stroke_color(*color);
+            Brush::Solid(color, space) => {
+                self.set_stroke_color(*color, *space);
                 self.ctx.stroke_path();
             }
-            Brush::Gradient(grad) => {
-                self.ctx.save();
-                self.ctx.replace_path_with_stroked_path();
-                self.ctx.clip();
-                grad.fill(self.ctx, GRADIENT_DRAW_BEFORE_AND_AFTER);
-                self.ctx.restore();
-            }
+            Brush::Gradient(grad) => self.stroke_current_path_with_gradient(grad, transform),
         }
+        self.ctx.restore();
     }
 
     fn stroke_styled(
@@ -252,19 +307,48 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_stroke(width.round_into(), Some(style));
-        match brush.as_ref() {
-            Brush::Solid(color) => {
-                self.set_stroke_color(*color);
-                self.ctx.stroke_path();
-            }
-            Brush::Gradient(grad) => {
-                self.ctx.save();
-                self.ctx.replace_path_with_stroked_path();
-                self.ctx.clip();
-                grad.fill(self.ctx, GRADIENT_DRAW_BEFORE_AND_AFTER);
-                self.ctx.restore();
-            }
-        }
+        self.stroke_current_path(&brush);
+    }
+
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error> {
+        let geometry = ct_helpers::make_path(&shape);
+        Ok(CachedShape::new(shape.into_path(1e-3), geometry))
+    }
+
+    fn fill_cached(&mut self, shape: &Self::CachedShape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_cached_path(shape);
+        self.fill_current_path(&brush);
+    }
+
+    fn stroke_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_cached_path(shape);
+        self.set_stroke(width.round_into(), None);
+        self.stroke_current_path(&brush);
+    }
+
+    fn stroke_styled_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.set_cached_path(shape);
+        self.set_stroke(width.round_into(), Some(style));
+        self.stroke_current_path(&brush);
+    }
+
+    fn clip_cached(&mut self, shape: &Self::CachedShape) {
+        self.set_cached_path(shape);
+        self.ctx.clip();
     }
 
     fn text(&mut self) -> &mut Self::Text {
@@ -273,13 +357,22 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
 
     fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
         let pos = pos.into();
+        // CoreText has y going up, and draws the frame from its bottom left
+        // corner. This is composed with the current transform, like any
+        // other drawing, and undone by the restore.
+        let text_transform =
+            Affine::translate((pos.x, pos.y + layout.frame_size.height)) * Affine::FLIP_Y;
         self.ctx.save();
-        // inverted coordinate system; text is drawn from bottom left corner,
-        // and (0, 0) in context is also bottom left.
-        self.ctx.translate(pos.x, layout.frame_size.height + pos.y);
-        self.ctx.scale(1.0, -1.0);
+        self.ctx.concat_ctm(to_cgaffine(text_transform));
+        // The text matrix isn't part of the graphics state, so it isn't
+        // restored; reset it, so that nothing left by earlier drawing moves
+        // the lines.
+        self.ctx.set_text_matrix(&to_cgaffine(Affine::IDENTITY));
         layout.draw(self.ctx);
         self.ctx.restore();
+        for (path, color, width) in layout.underline_paths() {
+            self.stroke(Affine::translate(pos.to_vec2()) * path, &color, width);
+        }
     }
 
     fn save(&mut self) -> Result<(), Error> {
@@ -321,42 +414,43 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
+        self.make_image_with_color_space(width, height, stride, buf, format, ColorSpace::Srgb)
+    }
+
+    fn make_image_with_color_space(
+        &mut self,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buf: &[u8],
+        format: ImageFormat,
+        color_space: ColorSpace,
+    ) -> Result<Self::Image, Error> {
+        piet::util::validate_image_args(width, height, stride, format, buf.len())?;
         if width == 0 || height == 0 {
             return Ok(CoreGraphicsImage::Empty);
         }
-        assert!(!buf.is_empty() && buf.len() <= format.bytes_per_pixel() * width * height);
         let data = Arc::new(piet::util::image_buffer_to_tightly_packed(
             buf, width, height, stride, format,
         )?);
         let data_provider = CGDataProvider::from_buffer(data);
-        let (colorspace, bitmap_info, bytes) = match format {
-            ImageFormat::Rgb => (CGColorSpace::create_device_rgb(), 0, 3),
-            ImageFormat::RgbaPremul => (
-                CGColorSpace::create_device_rgb(),
-                kCGImageAlphaPremultipliedLast,
-                4,
-            ),
-            ImageFormat::RgbaSeparate => (CGColorSpace::create_device_rgb(), kCGImageAlphaLast, 4),
-            ImageFormat::Grayscale => (CGColorSpace::create_device_gray(), 0, 1),
-            _ => unimplemented!(),
-        };
-        let bits_per_component = 8;
-        // this doesn't matter, we set interpolation mode manually in draw_image
-        let should_interpolate = false;
-        let rendering_intent = kCGRenderingIntentDefault;
-        let image = CGImage::new(
-            width,
-            height,
-            bits_per_component,
-            bytes * bits_per_component,
-            width * bytes,
-            &colorspace,
-            bitmap_info,
+        let image = make_cgimage(width, height, format, color_space, &data_provider);
+        Ok(CoreGraphicsImage::from_cgimage_and_ydir(image, self.y_down))
+    }
+
+    fn upload_image(&mut self, buf: &ImageBuf) -> Result<Self::Image, Error> {
+        if buf.width() == 0 || buf.height() == 0 {
+            return Ok(CoreGraphicsImage::Empty);
+        }
+        // the image shares the buffer's pixels instead of copying them
+        let data_provider = CGDataProvider::from_buffer(Arc::new(buf.raw_pixels_shared()));
+        let image = make_cgimage(
+            buf.width(),
+            buf.height(),
+            buf.format(),
+            buf.color_space(),
             &data_provider,
-            should_interpolate,
-            rendering_intent,
         );
-
         Ok(CoreGraphicsImage::from_cgimage_and_ydir(image, self.y_down))
     }
 
@@ -423,6 +517,20 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
         }
     }
 
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    ) {
+        self.ctx.save();
+        self.ctx.set_alpha(opacity.clamp(0.0, 1.0));
+        self.draw_image_area(image, src_rect, dst_rect, interp);
+        self.ctx.restore();
+    }
+
     fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
         let src_rect = src_rect.into();
 
@@ -515,6 +623,14 @@ impl<'a> RenderContext for CoreGraphicsContext<'a> {
         self.transform_stack.last().copied().unwrap_or_default()
     }
 
+    fn scale_factor(&self) -> f64 {
+        self.scale
+    }
+
+    fn backend_handle(&self) -> Option<&dyn Any> {
+        Some(&*self.ctx)
+    }
+
     fn status(&mut self) -> Result<(), Error> {
         Ok(())
     }
@@ -542,6 +658,32 @@ impl Image for CoreGraphicsImage {
             }
         }
     }
+
+    fn format(&self) -> ImageFormat {
+        let image = match self {
+            CoreGraphicsImage::Empty => return ImageFormat::RgbaPremul,
+            CoreGraphicsImage::YDown(image) | CoreGraphicsImage::YUp(image) => image,
+        };
+        if image.bits_per_pixel() == 8 {
+            return ImageFormat::Grayscale;
+        }
+        match ct_helpers::image_alpha_info(image) {
+            kCGImageAlphaNone | kCGImageAlphaNoneSkipLast | kCGImageAlphaNoneSkipFirst => {
+                ImageFormat::Rgb
+            }
+            kCGImageAlphaLast | kCGImageAlphaFirst => ImageFormat::RgbaSeparate,
+            _ => ImageFormat::RgbaPremul,
+        }
+    }
+
+    fn stride(&self) -> usize {
+        match self {
+            CoreGraphicsImage::Empty => 0,
+            CoreGraphicsImage::YDown(image) | CoreGraphicsImage::YUp(image) => {
+                image.bytes_per_row()
+            }
+        }
+    }
 }
 
 fn convert_line_join(line_join: LineJoin) -> CGLineJoin {
@@ -561,14 +703,24 @@ fn convert_line_cap(line_cap: LineCap) -> CGLineCap {
 }
 
 impl<'a> CoreGraphicsContext<'a> {
-    fn set_fill_color(&mut self, color: Color) {
-        let (r, g, b, a) = Color::as_rgba(color);
-        self.ctx.set_rgb_fill_color(r, g, b, a);
+    fn set_fill_color(&mut self, color: Color, color_space: ColorSpace) {
+        if color_space == ColorSpace::Srgb {
+            let (r, g, b, a) = Color::as_rgba(color);
+            self.ctx.set_rgb_fill_color(r, g, b, a);
+        } else {
+            self.ctx
+                .set_fill_color(&ct_helpers::make_color(color, color_space));
+        }
     }
 
-    fn set_stroke_color(&mut self, color: Color) {
-        let (r, g, b, a) = Color::as_rgba(color);
-        self.ctx.set_rgb_stroke_color(r, g, b, a);
+    fn set_stroke_color(&mut self, color: Color, color_space: ColorSpace) {
+        if color_space == ColorSpace::Srgb {
+            let (r, g, b, a) = Color::as_rgba(color);
+            self.ctx.set_rgb_stroke_color(r, g, b, a);
+        } else {
+            let color = ct_helpers::make_color(color, color_space);
+            ct_helpers::set_stroke_color(self.ctx, &color);
+        }
     }
 
     /// Set the stroke parameters.
@@ -588,10 +740,70 @@ impl<'a> CoreGraphicsContext<'a> {
             .set_line_dash(style.dash_offset, &style.dash_pattern);
     }
 
+    fn set_cached_path(&mut self, shape: &CachedShape<CGPath>) {
+        self.ctx.begin_path();
+        self.ctx.add_path(shape.geometry());
+    }
+
+    /// Fill the current path with the non-zero rule, consuming it.
+    fn fill_current_path(&mut self, brush: &Brush) {
+        match brush {
+            Brush::Solid(color, space) => {
+                self.set_fill_color(*color, *space);
+                self.ctx.fill_path();
+            }
+            Brush::Gradient(grad) => {
+                self.ctx.save();
+                self.ctx.clip();
+                grad.fill(self.ctx, GRADIENT_DRAW_BEFORE_AND_AFTER);
+                self.ctx.restore();
+            }
+        }
+    }
+
+    /// Stroke the current path with the current stroke style, consuming it.
+    fn stroke_current_path(&mut self, brush: &Brush) {
+        match brush {
+            Brush::Solid(color, space) => {
+                self.set_stroke_color(*color, *space);
+                self.ctx.stroke_path();
+            }
+            Brush::Gradient(grad) => {
+                self.ctx.save();
+                self.stroke_current_path_with_gradient(grad, Affine::IDENTITY);
+                self.ctx.restore();
+            }
+        }
+    }
+
+    /// Stroke the current path with a gradient, consuming it.
+    ///
+    /// The path is stroked into a transparency layer, which then masks the
+    /// gradient. Unlike replacing the path with its stroked outline, this
+    /// keeps the stroke's dashes and antialiasing exactly as CoreGraphics
+    /// would draw them, and is much cheaper for long paths.
+    ///
+    /// `gradient_transform` is concatenated to the CTM after stroking, before
+    /// the gradient is drawn. The caller must save and restore the graphics
+    /// state around this call.
+    fn stroke_current_path_with_gradient(&mut self, grad: &Gradient, gradient_transform: Affine) {
+        self.ctx.set_rgb_stroke_color(0.0, 0.0, 0.0, 1.0);
+        ct_helpers::begin_transparency_layer(self.ctx);
+        self.ctx.stroke_path();
+        self.ctx.concat_ctm(to_cgaffine(gradient_transform));
+        self.ctx.set_blend_mode(CGBlendMode::SourceIn);
+        grad.fill(self.ctx, GRADIENT_DRAW_BEFORE_AND_AFTER);
+        ct_helpers::end_transparency_layer(self.ctx);
+    }
+
     fn set_path(&mut self, shape: impl Shape) {
         // This shouldn't be necessary, we always leave the context in no-path
         // state. But just in case, and it should be harmless.
         self.ctx.begin_path();
+        if let Some(arc) = piet::util::as_arc(&shape) {
+            self.ctx.add_path(&ct_helpers::make_arc_path(&arc));
+            return;
+        }
         let mut last = Point::default();
         for el in shape.path_elements(1e-3) {
             match el {
@@ -621,6 +833,47 @@ impl<'a> CoreGraphicsContext<'a> {
     }
 }
 
+/// Create a tightly packed image from pixel data in `format`.
+fn make_cgimage(
+    width: usize,
+    height: usize,
+    format: ImageFormat,
+    color_space: ColorSpace,
+    data_provider: &CGDataProvider,
+) -> CGImage {
+    let (colorspace, bitmap_info, bytes) = match format {
+        ImageFormat::Rgb => (ct_helpers::rgb_color_space(color_space), 0, 3),
+        ImageFormat::RgbaPremul => (
+            ct_helpers::rgb_color_space(color_space),
+            kCGImageAlphaPremultipliedLast,
+            4,
+        ),
+        ImageFormat::RgbaSeparate => (
+            ct_helpers::rgb_color_space(color_space),
+            kCGImageAlphaLast,
+            4,
+        ),
+        ImageFormat::Grayscale => (ct_helpers::gray_color_space(color_space), 0, 1),
+        _ => unimplemented!(),
+    };
+    let bits_per_component = 8;
+    // this doesn't matter, we set interpolation mode manually in draw_image
+    let should_interpolate = false;
+    let rendering_intent = kCGRenderingIntentDefault;
+    CGImage::new(
+        width,
+        height,
+        bits_per_component,
+        bytes * bits_per_component,
+        width * bytes,
+        &colorspace,
+        bitmap_info,
+        data_provider,
+        should_interpolate,
+        rendering_intent,
+    )
+}
+
 fn compute_blurred_rect(rect: Rect, radius: f64) -> (CGImage, Rect) {
     let size = piet::util::size_for_blurred_rect(rect, radius);
     let width = size.width as usize;
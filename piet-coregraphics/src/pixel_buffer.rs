@@ -0,0 +1,241 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Drawing directly into `CVPixelBuffer` and `IOSurface` memory.
+
+use std::ffi::c_void;
+
+use core_foundation::base::{CFRelease, CFRetain};
+use core_graphics::base::{
+    kCGBitmapByteOrder32Big, kCGBitmapByteOrder32Little, kCGImageAlphaPremultipliedFirst,
+};
+use core_graphics::color_space::CGColorSpace;
+use core_graphics::context::CGContext;
+
+use piet::Error;
+
+use crate::{CoreGraphicsContext, CoreGraphicsText};
+
+/// A `CVPixelBufferRef`.
+pub type CVPixelBufferRef = *mut c_void;
+/// An `IOSurfaceRef`.
+pub type IOSurfaceRef = *mut c_void;
+
+// Four character codes for the supported pixel formats.
+const PIXEL_FORMAT_32_BGRA: u32 = u32::from_be_bytes(*b"BGRA");
+const PIXEL_FORMAT_32_ARGB: u32 = 0x20;
+
+/// A bitmap context that draws into the memory of a `CVPixelBuffer` or an
+/// `IOSurface`, for example to hand frames to AVFoundation.
+///
+/// The buffer is locked for as long as this target exists, and is retained
+/// until it is dropped. Buffers in the `32BGRA` and `32ARGB` pixel formats are
+/// supported; pixels are premultiplied.
+///
+/// Use [`CoreGraphicsContext::new_for_pixel_buffer`] to draw into the target.
+pub struct PixelBufferTarget {
+    ctx: CGContext,
+    buffer: LockedBuffer,
+    height: f64,
+}
+
+enum LockedBuffer {
+    PixelBuffer(CVPixelBufferRef),
+    Surface(IOSurfaceRef),
+}
+
+impl PixelBufferTarget {
+    /// Create a target drawing into a `CVPixelBuffer`.
+    ///
+    /// Returns [`Error::NotSupported`] if the buffer is planar or has an
+    /// unsupported pixel format.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must be a valid `CVPixelBufferRef`.
+    pub unsafe fn from_cv_pixel_buffer(buffer: CVPixelBufferRef) -> Result<Self, Error> {
+        if CVPixelBufferIsPlanar(buffer) != 0 {
+            return Err(Error::NotSupported);
+        }
+        let bitmap_info = bitmap_info_for_format(CVPixelBufferGetPixelFormatType(buffer))?;
+        if CVPixelBufferLockBaseAddress(buffer, 0) != 0 {
+            return Err(Error::BackendError("failed to lock pixel buffer".into()));
+        }
+        CFRetain(buffer);
+        Ok(Self::new(
+            LockedBuffer::PixelBuffer(buffer),
+            CVPixelBufferGetBaseAddress(buffer),
+            CVPixelBufferGetWidth(buffer),
+            CVPixelBufferGetHeight(buffer),
+            CVPixelBufferGetBytesPerRow(buffer),
+            bitmap_info,
+        ))
+    }
+
+    /// Create a target drawing into an `IOSurface`.
+    ///
+    /// Returns [`Error::NotSupported`] if the surface has an unsupported
+    /// pixel format.
+    ///
+    /// # Safety
+    ///
+    /// `surface` must be a valid `IOSurfaceRef`.
+    pub unsafe fn from_io_surface(surface: IOSurfaceRef) -> Result<Self, Error> {
+        let bitmap_info = bitmap_info_for_format(IOSurfaceGetPixelFormat(surface))?;
+        if IOSurfaceLock(surface, 0, std::ptr::null_mut()) != 0 {
+            return Err(Error::BackendError("failed to lock IOSurface".into()));
+        }
+        CFRetain(surface);
+        Ok(Self::new(
+            LockedBuffer::Surface(surface),
+            IOSurfaceGetBaseAddress(surface),
+            IOSurfaceGetWidth(surface),
+            IOSurfaceGetHeight(surface),
+            IOSurfaceGetBytesPerRow(surface),
+            bitmap_info,
+        ))
+    }
+
+    unsafe fn new(
+        buffer: LockedBuffer,
+        data: *mut c_void,
+        width: usize,
+        height: usize,
+        bytes_per_row: usize,
+        bitmap_info: u32,
+    ) -> Self {
+        let ctx = CGContext::create_bitmap_context(
+            Some(data),
+            width,
+            height,
+            8,
+            bytes_per_row,
+            &CGColorSpace::create_device_rgb(),
+            bitmap_info,
+        );
+        PixelBufferTarget {
+            ctx,
+            buffer,
+            height: height as f64,
+        }
+    }
+}
+
+impl<'a> CoreGraphicsContext<'a> {
+    /// Create a new context drawing into a [`PixelBufferTarget`].
+    ///
+    /// As with [`new_y_up`], the origin is at the top-left corner, which
+    /// matches the row order of the buffer's memory. Coordinates are in
+    /// pixels.
+    ///
+    /// [`new_y_up`]: CoreGraphicsContext::new_y_up
+    pub fn new_for_pixel_buffer(
+        target: &'a mut PixelBufferTarget,
+        text: Option<CoreGraphicsText>,
+    ) -> CoreGraphicsContext<'a> {
+        CoreGraphicsContext::new_y_up(&mut target.ctx, target.height, text)
+    }
+}
+
+impl Drop for PixelBufferTarget {
+    fn drop(&mut self) {
+        self.ctx.flush();
+        unsafe {
+            match self.buffer {
+                LockedBuffer::PixelBuffer(buffer) => {
+                    CVPixelBufferUnlockBaseAddress(buffer, 0);
+                    CFRelease(buffer);
+                }
+                LockedBuffer::Surface(surface) => {
+                    IOSurfaceUnlock(surface, 0, std::ptr::null_mut());
+                    CFRelease(surface);
+                }
+            }
+        }
+    }
+}
+
+fn bitmap_info_for_format(format: u32) -> Result<u32, Error> {
+    match format {
+        PIXEL_FORMAT_32_BGRA => Ok(kCGImageAlphaPremultipliedFirst | kCGBitmapByteOrder32Little),
+        PIXEL_FORMAT_32_ARGB => Ok(kCGImageAlphaPremultipliedFirst | kCGBitmapByteOrder32Big),
+        _ => Err(Error::NotSupported),
+    }
+}
+
+#[link(name = "CoreVideo", kind = "framework")]
+extern "C" {
+    fn CVPixelBufferIsPlanar(buffer: CVPixelBufferRef) -> u8;
+    fn CVPixelBufferGetPixelFormatType(buffer: CVPixelBufferRef) -> u32;
+    fn CVPixelBufferLockBaseAddress(buffer: CVPixelBufferRef, flags: u64) -> i32;
+    fn CVPixelBufferUnlockBaseAddress(buffer: CVPixelBufferRef, flags: u64) -> i32;
+    fn CVPixelBufferGetBaseAddress(buffer: CVPixelBufferRef) -> *mut c_void;
+    fn CVPixelBufferGetWidth(buffer: CVPixelBufferRef) -> usize;
+    fn CVPixelBufferGetHeight(buffer: CVPixelBufferRef) -> usize;
+    fn CVPixelBufferGetBytesPerRow(buffer: CVPixelBufferRef) -> usize;
+}
+
+#[link(name = "IOSurface", kind = "framework")]
+extern "C" {
+    fn IOSurfaceGetPixelFormat(surface: IOSurfaceRef) -> u32;
+    fn IOSurfaceLock(surface: IOSurfaceRef, options: u32, seed: *mut u32) -> i32;
+    fn IOSurfaceUnlock(surface: IOSurfaceRef, options: u32, seed: *mut u32) -> i32;
+    fn IOSurfaceGetBaseAddress(surface: IOSurfaceRef) -> *mut c_void;
+    fn IOSurfaceGetWidth(surface: IOSurfaceRef) -> usize;
+    fn IOSurfaceGetHeight(surface: IOSurfaceRef) -> usize;
+    fn IOSurfaceGetBytesPerRow(surface: IOSurfaceRef) -> usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piet::kurbo::Rect;
+    use piet::{Color, RenderContext};
+
+    #[link(name = "CoreVideo", kind = "framework")]
+    extern "C" {
+        fn CVPixelBufferCreate(
+            allocator: *const c_void,
+            width: usize,
+            height: usize,
+            pixel_format: u32,
+            attributes: *const c_void,
+            buffer_out: *mut CVPixelBufferRef,
+        ) -> i32;
+    }
+
+    #[test]
+    fn draws_top_row_first() {
+        unsafe {
+            let mut buffer = std::ptr::null_mut();
+            let status = CVPixelBufferCreate(
+                std::ptr::null(),
+                4,
+                4,
+                PIXEL_FORMAT_32_BGRA,
+                std::ptr::null(),
+                &mut buffer,
+            );
+            assert_eq!(status, 0);
+
+            let mut target = PixelBufferTarget::from_cv_pixel_buffer(buffer).unwrap();
+            {
+                let mut rc = CoreGraphicsContext::new_for_pixel_buffer(&mut target, None);
+                rc.clear(None, Color::BLACK);
+                rc.fill(Rect::new(0.0, 0.0, 4.0, 1.0), &Color::rgb8(255, 0, 0));
+                rc.finish().unwrap();
+            }
+            drop(target);
+
+            CVPixelBufferLockBaseAddress(buffer, 0);
+            let data = CVPixelBufferGetBaseAddress(buffer) as *const u8;
+            let stride = CVPixelBufferGetBytesPerRow(buffer);
+            let top = std::slice::from_raw_parts(data, 4);
+            let bottom = std::slice::from_raw_parts(data.add(stride * 3), 4);
+            assert_eq!(top, [0, 0, 255, 255]);
+            assert_eq!(bottom, [0, 0, 0, 255]);
+            CVPixelBufferUnlockBaseAddress(buffer, 0);
+            CFRelease(buffer);
+        }
+    }
+}
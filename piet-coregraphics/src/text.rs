@@ -9,7 +9,9 @@ use std::hash::{Hash, Hasher};
 use std::ops::{DerefMut, Range, RangeBounds};
 use std::rc::Rc;
 
-use associative_cache::{AssociativeCache, Capacity64, HashFourWay, RoundRobinReplacement};
+use associative_cache::{
+    AssociativeCache, Capacity256, Capacity64, HashFourWay, RoundRobinReplacement,
+};
 use core_foundation::base::TCFType;
 use core_foundation::dictionary::{CFDictionary, CFMutableDictionary};
 use core_foundation::number::CFNumber;
@@ -22,15 +24,18 @@ use core_graphics::path::CGPath;
 use core_text::{
     font,
     font::CTFont,
-    font_descriptor::{self, SymbolicTraitAccessors},
+    font_descriptor::{self, SymbolicTraitAccessors, TraitAccessors},
     string_attributes,
 };
 
-use piet::kurbo::{Affine, Point, Rect, Size};
+use piet::kurbo::{Affine, BezPath, Point, Rect, Size};
+use piet::util::{UnderlineAttributes, UnderlineRun};
 use piet::{
-    util, Error, FontFamily, FontStyle, FontWeight, HitTestPoint, HitTestPosition, LineMetric,
-    Text, TextAlignment, TextAttribute, TextLayout, TextLayoutBuilder, TextStorage,
+    util, Affinity, Color, CursorPosition, Error, FontDescription, FontFamily, FontFeature,
+    FontStyle, FontWeight, HitTestPoint, HitTestPosition, LineMetric, Text, TextAlignment,
+    TextAntialiasing, TextAttribute, TextLayout, TextLayoutBuilder, TextStorage, UnderlineStyle,
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::ct_helpers::{self, AttributedString, FontCollection, Frame, Framesetter, Line};
 
@@ -56,7 +61,10 @@ type Cache<K, V> = AssociativeCache<K, V, Capacity64, HashFourWay, RoundRobinRep
 struct TextState {
     collection: FontCollection,
     family_cache: Cache<String, Option<FontFamily>>,
-    font_cache: Cache<CoreTextFontKey, CTFont>,
+    /// Fonts are shared by every layout, and heavily attributed text can use
+    /// many combinations of size, weight and style, so this is larger.
+    font_cache:
+        AssociativeCache<CoreTextFontKey, CTFont, Capacity256, HashFourWay, RoundRobinReplacement>,
 }
 
 #[derive(Clone)]
@@ -78,13 +86,22 @@ pub struct CoreGraphicsTextLayout {
     default_line_height: f64,
     line_metrics: Rc<[LineMetric]>,
     x_offsets: Rc<[f64]>,
+    /// The width of each line without trailing whitespace.
+    line_widths: Rc<[f64]>,
     trailing_ws_width: f64,
+    /// Underlines with a style CoreText can't draw, which we draw ourselves.
+    underlines: Rc<[UnderlineRun]>,
+    underline_position: f64,
+    underline_thickness: f64,
+    antialiasing: TextAntialiasing,
+    links: util::TextLinks,
 }
 
 /// Building text layouts for `CoreGraphics`.
 pub struct CoreGraphicsTextLayoutBuilder {
     width: f64,
     alignment: TextAlignment,
+    antialiasing: TextAntialiasing,
     text: Rc<dyn TextStorage>,
     /// the end bound up to which we have already added attrs to our AttributedString
     last_resolved_pos: usize,
@@ -96,10 +113,24 @@ pub struct CoreGraphicsTextLayoutBuilder {
     has_set_default_attrs: bool,
     default_baseline: f64,
     default_line_height: f64,
+    default_underline: (f64, f64),
+    /// The font set on the whole string with the default attributes.
+    default_font: Option<CoreTextFontKey>,
+    /// The font of the text resolved since the font last changed, which is
+    /// added to the attributed string once the font changes again.
+    font_run: Option<FontRun>,
     attrs: Attributes,
+    underlines: UnderlineAttributes,
+    links: util::TextLinks,
     shared: SharedTextState,
 }
 
+/// A run of text with the same font, in UTF-16 code units.
+struct FontRun {
+    key: CoreTextFontKey,
+    start_utf16: usize,
+}
+
 /// A helper type for storing and resolving attributes
 #[derive(Default)]
 struct Attributes {
@@ -108,6 +139,7 @@ struct Attributes {
     size: Option<Span<f64>>,
     weight: Option<Span<FontWeight>>,
     style: Option<Span<FontStyle>>,
+    features: Option<Span<Vec<FontFeature>>>,
 }
 
 #[derive(Clone)]
@@ -116,6 +148,7 @@ struct CoreTextFontKey {
     weight: FontWeight,
     italic: bool,
     size: f64,
+    features: Vec<FontFeature>,
 }
 
 impl PartialEq for CoreTextFontKey {
@@ -124,6 +157,7 @@ impl PartialEq for CoreTextFontKey {
             && self.weight == other.weight
             && self.italic == other.italic
             && self.size.to_bits() == other.size.to_bits()
+            && self.features == other.features
     }
 }
 
@@ -135,6 +169,7 @@ impl Hash for CoreTextFontKey {
         self.weight.hash(state);
         self.italic.hash(state);
         self.size.to_bits().hash(state);
+        self.features.hash(state);
     }
 }
 
@@ -164,10 +199,17 @@ impl CoreTextFontKey {
                 traits.set(symbolic_traits_key, symbolic_traits.as_CFType());
             }
 
-            let attributes = CFDictionary::from_CFType_pairs(&[
+            let mut attributes = vec![
                 (family_key, family_name.as_CFType()),
                 (traits_key, traits.as_CFType()),
-            ]);
+            ];
+            if !self.features.is_empty() {
+                let features_key =
+                    CFString::wrap_under_get_rule(font_descriptor::kCTFontFeatureSettingsAttribute);
+                let settings = ct_helpers::ct_feature_settings(&self.features);
+                attributes.push((features_key, settings.as_CFType()));
+            }
+            let attributes = CFDictionary::from_CFType_pairs(&attributes);
             let descriptor = font_descriptor::new_from_attributes(&attributes);
             let font = font::new_from_descriptor(&descriptor, self.size);
 
@@ -257,11 +299,26 @@ impl CoreGraphicsTextLayoutBuilder {
         if !self.has_set_default_attrs {
             self.set_default_attrs();
         }
+        // Underlines are resolved when the layout is built.
+        self.underlines.add(range.clone(), &attr);
+        if matches!(
+            &attr,
+            TextAttribute::Underline(_)
+                | TextAttribute::UnderlineColor(_)
+                | TextAttribute::UnderlineStyle(_)
+                | TextAttribute::UnderlineThickness(_)
+                | TextAttribute::UnderlineSkipInk(_)
+        ) {
+            return;
+        }
+        if let TextAttribute::Link(url) = &attr {
+            return self.links.add(range, url);
+        }
         // Some attributes are 'standalone' and can just be added to the attributed string
         // immediately.
         if matches!(
             &attr,
-            TextAttribute::TextColor(_) | TextAttribute::Underline(_)
+            TextAttribute::TextColor(_) | TextAttribute::BaselineOffset(_)
         ) {
             return self.add_immediately(attr, range);
         }
@@ -280,35 +337,69 @@ impl CoreGraphicsTextLayoutBuilder {
     fn set_default_attrs(&mut self) {
         self.has_set_default_attrs = true;
         let whole_range = self.attr_string.range();
-        let font = self.current_font();
+        let key = self.current_font_key();
+        let font = self.shared.get_ct_font(&key);
+        self.default_font = Some(key);
         let height = compute_line_height(font.ascent(), font.descent(), font.leading());
         self.default_line_height = height;
         self.default_baseline = (font.ascent() + 0.5).floor();
+        let thickness = font.underline_thickness();
+        self.default_underline = (-font.underline_position() - thickness * 0.5, thickness);
         self.attr_string.set_font(whole_range, &font);
         self.attr_string
             .set_fg_color(whole_range, self.attrs.defaults.fg_color);
-        self.attr_string
-            .set_underline(whole_range, self.attrs.defaults.underline);
+        if self.attrs.defaults.baseline_offset != 0.0 {
+            self.attr_string
+                .set_baseline_offset(whole_range, self.attrs.defaults.baseline_offset);
+        }
+        self.underlines = UnderlineAttributes::new(&self.attrs.defaults);
+        self.links = util::TextLinks::new(&self.attrs.defaults, self.text.len());
     }
 
     fn add_immediately(&mut self, attr: TextAttribute, range: Range<usize>) {
-        let utf16_start = util::count_utf16(&self.text[..range.start]);
-        let utf16_len = util::count_utf16(&self.text[range]);
-        let range = CFRange::init(utf16_start as isize, utf16_len as isize);
+        let range = self.utf16_range(range);
         match attr {
             TextAttribute::TextColor(color) => {
                 self.attr_string.set_fg_color(range, color);
             }
-            TextAttribute::Underline(flag) => self.attr_string.set_underline(range, flag),
+            TextAttribute::BaselineOffset(offset) => {
+                self.attr_string.set_baseline_offset(range, offset);
+            }
             _ => unreachable!(),
         }
     }
 
+    fn utf16_range(&self, range: Range<usize>) -> CFRange {
+        let utf16_start = util::count_utf16(&self.text[..range.start]);
+        let utf16_len = util::count_utf16(&self.text[range]);
+        CFRange::init(utf16_start as isize, utf16_len as isize)
+    }
+
     fn finalize(&mut self) {
         if !self.has_set_default_attrs {
             self.set_default_attrs();
         }
         self.resolve_up_to(self.text.len());
+        self.end_font_run();
+    }
+
+    /// Add underlines to the attributed string, returning those that
+    /// CoreText can't draw.
+    ///
+    /// CoreText has dotted and dashed underlines, but no wavy ones, and only
+    /// supports a single and a 'thick' weight.
+    fn resolve_underlines(&mut self) -> Vec<UnderlineRun> {
+        let mut drawn = Vec::new();
+        for run in self.underlines.runs(self.text.len()) {
+            if run.style == UnderlineStyle::Wavy || run.thickness.is_some() {
+                drawn.push(run);
+                continue;
+            }
+            let range = self.utf16_range(run.range);
+            self.attr_string.set_underline(range, run.style);
+            self.attr_string.set_underline_color(range, run.color);
+        }
+        drawn
     }
 
     /// Add all font attributes up to a boundary.
@@ -319,15 +410,13 @@ impl CoreGraphicsTextLayoutBuilder {
             if next_span_end > self.last_resolved_pos {
                 let range_end_utf16 =
                     util::count_utf16(&self.text[self.last_resolved_pos..next_span_end]);
-                let range =
-                    CFRange::init(self.last_resolved_utf16 as isize, range_end_utf16 as isize);
-                let font = self.current_font();
-                unsafe {
-                    self.attr_string.inner.set_attribute(
-                        range,
-                        string_attributes::kCTFontAttributeName,
-                        &font,
-                    );
+                let key = self.current_font_key();
+                if self.font_run.as_ref().map(|run| &run.key) != Some(&key) {
+                    self.end_font_run();
+                    self.font_run = Some(FontRun {
+                        key,
+                        start_utf16: self.last_resolved_utf16,
+                    });
                 }
                 self.last_resolved_pos = next_span_end;
                 self.last_resolved_utf16 += range_end_utf16;
@@ -347,17 +436,44 @@ impl CoreGraphicsTextLayoutBuilder {
         self.attrs.next_span_end(max)
     }
 
-    /// Returns the fully constructed font object, including weight and size.
+    /// Add the font of the current run to the attributed string.
+    ///
+    /// Adjacent spans often share a font, when only their color or some
+    /// other attribute differs, so the font is set once for the whole run.
+    /// Runs in the default font are skipped, as it is already set on the
+    /// whole string.
+    fn end_font_run(&mut self) {
+        let Some(run) = self.font_run.take() else {
+            return;
+        };
+        if self.default_font.as_ref() == Some(&run.key) {
+            return;
+        }
+        let len = self.last_resolved_utf16 - run.start_utf16;
+        let range = CFRange::init(run.start_utf16 as isize, len as isize);
+        let font = self.shared.get_ct_font(&run.key);
+        unsafe {
+            self.attr_string.inner.set_attribute(
+                range,
+                string_attributes::kCTFontAttributeName,
+                &font,
+            );
+        }
+    }
+
+    /// Returns the key for the font of the current attributes, including
+    /// weight and size.
     ///
     /// This is stateful; it depends on the current attributes being correct
     /// for the range that begins at `self.last_resolved_pos`.
-    fn current_font(&self) -> CTFont {
-        self.shared.get_ct_font(&CoreTextFontKey {
+    fn current_font_key(&self) -> CoreTextFontKey {
+        CoreTextFontKey {
             font: self.attrs.font().to_owned(),
             weight: self.attrs.weight(),
             italic: self.attrs.italic(),
             size: self.attrs.size(),
-        })
+            features: self.attrs.features().to_owned(),
+        }
     }
 
     /// After we have added a span, check to see if any of our attributes are no
@@ -377,6 +493,7 @@ impl Attributes {
             TextAttribute::Weight(w) => self.weight = Some(Span::new(w, range)),
             TextAttribute::FontSize(s) => self.size = Some(Span::new(s, range)),
             TextAttribute::Style(s) => self.style = Some(Span::new(s, range)),
+            TextAttribute::FontFeatures(f) => self.features = Some(Span::new(f, range)),
             TextAttribute::Strikethrough(_) => { /* Unimplemented for now as coregraphics doesn't have native strikethrough support. */
             }
             _ => unreachable!(),
@@ -414,6 +531,13 @@ impl Attributes {
             .unwrap_or_else(|| &self.defaults.font)
     }
 
+    fn features(&self) -> &[FontFeature] {
+        self.features
+            .as_ref()
+            .map(|t| t.payload.as_slice())
+            .unwrap_or(&self.defaults.font_features)
+    }
+
     fn next_span_end(&self, max: usize) -> usize {
         self.font
             .as_ref()
@@ -422,6 +546,7 @@ impl Attributes {
             .min(self.size.as_ref().map(Span::range_end).unwrap_or(max))
             .min(self.weight.as_ref().map(Span::range_end).unwrap_or(max))
             .min(self.style.as_ref().map(Span::range_end).unwrap_or(max))
+            .min(self.features.as_ref().map(Span::range_end).unwrap_or(max))
             .min(max)
     }
 
@@ -439,6 +564,9 @@ impl Attributes {
         if self.size.as_ref().map(Span::range_end) == Some(last_pos) {
             self.size = None;
         }
+        if self.features.as_ref().map(Span::range_end) == Some(last_pos) {
+            self.features = None;
+        }
     }
 }
 
@@ -447,19 +575,47 @@ impl Attributes {
 ///
 /// <https://git.sailfishos.org/mer-core/qtbase/commit/9ba296cc4cefaeb9d6c5abc2e0c0b272f2288733#1b84d1913347bd20dd0a134247f8cd012a646261_44_55>
 //TODO: a better solution would be piecewise linear interpolation between these values
+const CORETEXT_WEIGHTS: [(u16, f64); 9] = [
+    (100, -0.8),
+    (200, -0.6),
+    (300, -0.4),
+    (400, 0.0),
+    (500, 0.23),
+    (600, 0.3),
+    (700, 0.4),
+    (800, 0.56),
+    (900, 0.62),
+];
+
 fn convert_to_coretext(weight: FontWeight) -> CFNumber {
-    match weight.to_raw() {
-        0..=199 => -0.8,
-        200..=299 => -0.6,
-        300..=399 => -0.4,
-        400..=499 => 0.0,
-        500..=599 => 0.23,
-        600..=699 => 0.3,
-        700..=799 => 0.4,
-        800..=899 => 0.56,
-        _ => 0.62,
-    }
-    .into()
+    let idx = (weight.to_raw() as usize / 100).saturating_sub(1);
+    CORETEXT_WEIGHTS[idx.min(CORETEXT_WEIGHTS.len() - 1)]
+        .1
+        .into()
+}
+
+/// The css-style weight closest to a coretext weight.
+fn convert_from_coretext(weight: f64) -> FontWeight {
+    let closest = CORETEXT_WEIGHTS
+        .iter()
+        .min_by(|a, b| (a.1 - weight).abs().total_cmp(&(b.1 - weight).abs()))
+        .unwrap();
+    FontWeight::new(closest.0)
+}
+
+/// Describe a font chosen by coretext, which may be a fallback font.
+fn font_description(font: &CTFont) -> FontDescription {
+    let style = if font.symbolic_traits().is_italic() {
+        FontStyle::Italic
+    } else {
+        FontStyle::Regular
+    };
+    FontDescription {
+        family: FontFamily::new_unchecked(font.family_name()),
+        weight: convert_from_coretext(font.all_traits().normalized_weight()),
+        style,
+        size: font.pt_size(),
+    }
 }
 
 impl CoreGraphicsText {
@@ -546,6 +702,7 @@ impl CoreGraphicsTextLayoutBuilder {
             shared,
             width: MAX_LAYOUT_CONSTRAINT,
             alignment: TextAlignment::default(),
+            antialiasing: TextAntialiasing::default(),
             attrs: Default::default(),
             text,
             last_resolved_pos: 0,
@@ -554,6 +711,11 @@ impl CoreGraphicsTextLayoutBuilder {
             has_set_default_attrs: false,
             default_baseline: 0.0,
             default_line_height: 0.0,
+            default_underline: (0.0, 0.0),
+            default_font: None,
+            font_run: None,
+            underlines: UnderlineAttributes::default(),
+            links: util::TextLinks::default(),
         }
     }
 }
@@ -577,6 +739,18 @@ impl TextLayoutBuilder for CoreGraphicsTextLayoutBuilder {
         self
     }
 
+    fn locale(mut self, locale: &str) -> Self {
+        // without a language attribute, CoreText uses the system locale.
+        let range = self.attr_string.range();
+        self.attr_string.set_language(range, locale);
+        self
+    }
+
+    fn antialiasing(mut self, antialiasing: TextAntialiasing) -> Self {
+        self.antialiasing = antialiasing;
+        self
+    }
+
     fn default_attribute(mut self, attribute: impl Into<TextAttribute>) -> Self {
         debug_assert!(
             !self.has_set_default_attrs,
@@ -600,14 +774,20 @@ impl TextLayoutBuilder for CoreGraphicsTextLayoutBuilder {
 
     fn build(mut self) -> Result<Self::Out, Error> {
         self.finalize();
+        let underlines = self.resolve_underlines();
         self.attr_string.set_alignment(self.alignment);
-        Ok(CoreGraphicsTextLayout::new(
+        let mut layout = CoreGraphicsTextLayout::new(
             self.text,
             self.attr_string,
             self.width,
             self.default_baseline,
             self.default_line_height,
-        ))
+        );
+        layout.underlines = underlines.into();
+        (layout.underline_position, layout.underline_thickness) = self.default_underline;
+        layout.antialiasing = self.antialiasing;
+        layout.links = self.links;
+        Ok(layout)
     }
 }
 
@@ -633,6 +813,29 @@ impl TextLayout for CoreGraphicsTextLayout {
         self.image_bounds
     }
 
+    fn with_max_width(&self, width: f64) -> Self {
+        let mut layout = self.clone();
+        layout.update_width(width);
+        layout
+    }
+
+    fn with_color_override(&self, range: impl RangeBounds<usize>, color: Color) -> Self {
+        let range = util::resolve_range(range, self.text.len());
+        let utf16_start = util::count_utf16(&self.text[..range.start]);
+        let utf16_len = util::count_utf16(&self.text[range]);
+        let range = CFRange::init(utf16_start as isize, utf16_len as isize);
+
+        let mut layout = self.clone();
+        // the attributed string is shared with the layout it was copied from
+        layout.attr_string = self.attr_string.mutable_copy();
+        layout.attr_string.set_fg_color(range, color);
+        layout.framesetter = Framesetter::new(&layout.attr_string);
+        // NaN to ensure the frame is recreated with the new framesetter
+        let width = std::mem::replace(&mut layout.width_constraint, f64::NAN);
+        layout.update_width(width);
+        layout
+    }
+
     fn text(&self) -> &str {
         &self.text
     }
@@ -650,6 +853,19 @@ impl TextLayout for CoreGraphicsTextLayout {
         self.line_metrics.len()
     }
 
+    fn line_bounds(&self, line_number: usize) -> Option<Rect> {
+        let metric = self.line_metrics.get(line_number)?;
+        // the empty string has no lines in the frame, and so no offsets
+        let x0 = self.x_offsets.get(line_number).copied().unwrap_or_default();
+        let width = self
+            .line_widths
+            .get(line_number)
+            .copied()
+            .unwrap_or_default();
+        let y1 = metric.y_offset + metric.height;
+        Some(Rect::new(x0, metric.y_offset, x0 + width, y1))
+    }
+
     // given a point on the screen, return an offset in the text, basically
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
         let line_num = self
@@ -701,7 +917,19 @@ impl TextLayout for CoreGraphicsTextLayout {
             point_in_string_space.x >= 0. && point_in_string_space.x <= typo_bounds.width;
         let is_inside = is_inside_x && is_inside_y;
 
-        HitTestPoint::new(offset, is_inside)
+        // CoreText returns the nearest caret position; if that is right of
+        // the point, the point was on the trailing half of a glyph.
+        let is_upstream = offset > metric.start_offset
+            && (offset_utf16 < 0
+                || point_in_string_space.x > typo_bounds.width
+                || point_in_string_space.x < line.get_offset_for_string_index(offset_utf16));
+        let affinity = if is_upstream {
+            Affinity::Upstream
+        } else {
+            Affinity::Downstream
+        };
+
+        HitTestPoint::new(offset, is_inside).with_affinity(affinity)
     }
 
     fn hit_test_text_position(&self, idx: usize) -> HitTestPosition {
@@ -731,6 +959,60 @@ impl TextLayout for CoreGraphicsTextLayout {
         let y_pos = metric.y_offset + metric.baseline;
         HitTestPosition::new(Point::new(x_pos, y_pos), line_num)
     }
+
+    // Each line's caret offsets come from its CTLine, counting utf-16 as we
+    // go rather than finding the line and counting from its start for each
+    // position.
+    fn cursor_positions(&self) -> Vec<CursorPosition> {
+        let mut result = Vec::new();
+        for (line_num, metric) in self.line_metrics.iter().enumerate() {
+            let text = &self.text[metric.range()];
+            let is_last = line_num + 1 == self.line_metrics.len();
+            let boundaries = text
+                .grapheme_indices(true)
+                .map(|(idx, _)| idx)
+                .chain(is_last.then_some(text.len()));
+            let Some(line) = self.unwrap_frame().get_line(line_num) else {
+                // the empty line of empty text, or after a trailing newline
+                for idx in boundaries {
+                    result.push(CursorPosition::new(
+                        metric.start_offset + idx,
+                        line_num,
+                        0.0,
+                    ));
+                }
+                continue;
+            };
+            let x_offset = self.x_offsets[line_num];
+            let mut utf16 = line.get_string_range().location;
+            let mut counted = 0;
+            for idx in boundaries {
+                utf16 += util::count_utf16(&text[counted..idx]) as isize;
+                counted = idx;
+                let x = line.get_offset_for_string_index(utf16) + x_offset;
+                result.push(CursorPosition::new(metric.start_offset + idx, line_num, x));
+            }
+        }
+        result
+    }
+
+    fn resolved_fonts(&self) -> Vec<(Range<usize>, FontDescription)> {
+        let text = self.text.as_str();
+        let utf8_offset = |utf16| util::count_until_utf16(text, utf16).unwrap_or(text.len());
+        let mut fonts = Vec::new();
+        for line in self.unwrap_frame().lines() {
+            for (range, font) in line.get_font_runs() {
+                let start = utf8_offset(range.location as usize);
+                let end = utf8_offset((range.location + range.length) as usize);
+                fonts.push((start..end, font_description(&font)));
+            }
+        }
+        util::merge_font_runs(fonts)
+    }
+
+    fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.links.links()
+    }
 }
 
 impl CoreGraphicsTextLayout {
@@ -758,12 +1040,60 @@ impl CoreGraphicsTextLayout {
             default_line_height,
             line_metrics: Rc::new([]),
             x_offsets: Rc::new([]),
+            line_widths: Rc::new([]),
             trailing_ws_width: 0.0,
+            underlines: Rc::new([]),
+            underline_position: 0.0,
+            underline_thickness: 0.0,
+            antialiasing: TextAntialiasing::default(),
+            links: util::TextLinks::default(),
         };
         layout.update_width(width_constraint);
         layout
     }
 
+    /// The underlines that CoreText doesn't draw, with their colors and widths.
+    pub(crate) fn underline_paths(&self) -> impl Iterator<Item = (BezPath, Color, f64)> + '_ {
+        let ink = if self.underlines.iter().any(|run| run.skip_ink) {
+            self.glyph_ink_bounds()
+        } else {
+            Vec::new()
+        };
+        self.underlines.iter().map(move |run| {
+            let thickness = run.thickness.unwrap_or(self.underline_thickness);
+            let ink = if run.skip_ink { &ink[..] } else { &[] };
+            let path = util::underline_path_skipping_ink(
+                self,
+                run,
+                self.underline_position,
+                thickness,
+                ink,
+            );
+            (path, run.color, thickness)
+        })
+    }
+
+    /// The ink bounds of every glyph in the layout, for underlines that skip
+    /// ink.
+    fn glyph_ink_bounds(&self) -> Vec<Rect> {
+        let mut bounds = Vec::new();
+        for (i, line) in self.unwrap_frame().lines().iter().enumerate() {
+            let x = self.x_offsets.get(i).copied().unwrap_or_default();
+            let y = self
+                .line_metrics
+                .get(i)
+                .map(|lm| lm.y_offset + lm.baseline)
+                .unwrap_or_default();
+            // relative to the baseline and upside down, as with the image bounds
+            bounds.extend(
+                line.get_glyph_bounds()
+                    .into_iter()
+                    .map(|r| Rect::new(x + r.x0, y - r.y1, x + r.x1, y - r.y0)),
+            );
+        }
+        bounds
+    }
+
     // this used to be part of the TextLayout trait; see https://github.com/linebender/piet/issues/298
     #[allow(clippy::float_cmp)]
     fn update_width(&mut self, new_width: impl Into<Option<f64>>) {
@@ -793,6 +1123,7 @@ impl CoreGraphicsTextLayout {
         );
         self.line_metrics = layout_metrics.line_metrics.into();
         self.x_offsets = layout_metrics.x_offsets.into();
+        self.line_widths = layout_metrics.line_widths.into();
         self.trailing_ws_width = layout_metrics.trailing_whitespace;
         self.frame_size = layout_metrics.layout_size;
         assert!(self.line_metrics.len() > 0);
@@ -822,6 +1153,21 @@ impl CoreGraphicsTextLayout {
         assert!(self.x_offsets.len() >= lines_len);
         assert!(self.line_metrics.len() >= lines_len);
 
+        // the caller saves and restores the graphics state around this.
+        match self.antialiasing {
+            TextAntialiasing::Default => (),
+            TextAntialiasing::Grayscale => {
+                ctx.set_should_antialias(true);
+                ctx.set_should_smooth_fonts(false);
+            }
+            TextAntialiasing::Subpixel => {
+                ctx.set_should_antialias(true);
+                ctx.set_allows_font_smoothing(true);
+                ctx.set_should_smooth_fonts(true);
+            }
+            TextAntialiasing::Aliased => ctx.set_should_antialias(false),
+        }
+
         for (i, line) in lines.iter().enumerate() {
             let x = self.x_offsets.get(i).copied().unwrap_or_default();
             // because coretext has an inverted coordinate system we have to manually flip lines
@@ -876,6 +1222,7 @@ struct LayoutMetrics {
     line_metrics: Vec<LineMetric>,
     trailing_whitespace: f64,
     x_offsets: Vec<f64>,
+    line_widths: Vec<f64>,
     layout_size: Size,
 }
 
@@ -892,6 +1239,7 @@ fn build_line_metrics(
 
     let mut metrics = Vec::with_capacity(frame.lines().len() + 1);
     let mut x_offsets = Vec::with_capacity(frame.lines().len() + 1);
+    let mut line_widths = Vec::with_capacity(frame.lines().len() + 1);
     let mut cumulative_height = 0.0;
     let mut max_width = 0f64;
     let mut max_width_with_ws = 0f64;
@@ -946,6 +1294,7 @@ fn build_line_metrics(
             y_offset,
         });
         x_offsets.push(line_origins[i].x);
+        line_widths.push(typo_bounds.width - ws_width);
     }
 
     // adjust our x_offsets so that we zero leading whitespace (relevant if right-aligned)
@@ -987,6 +1336,7 @@ fn build_line_metrics(
         let x_offset = x_offsets.last().copied().unwrap();
         metrics.push(newline_eof);
         x_offsets.push(x_offset);
+        line_widths.push(0.0);
     }
 
     let layout_size = Size::new(max_width, cumulative_height);
@@ -994,6 +1344,7 @@ fn build_line_metrics(
     LayoutMetrics {
         line_metrics: metrics,
         x_offsets,
+        line_widths,
         layout_size,
         trailing_whitespace: max_width_with_ws,
     }
@@ -1050,6 +1401,11 @@ mod tests {
         }};
     }
 
+    #[test]
+    fn text_conformance() {
+        piet::conformance::check_all(&mut CoreGraphicsText::new_with_unique_state());
+    }
+
     #[test]
     fn line_offsets() {
         let text = "hi\ni'm\n😀 four\nlines";
@@ -1212,6 +1568,22 @@ mod tests {
             .is_none());
     }
 
+    #[test]
+    fn resolved_fonts_include_fallback() {
+        let text = "hi \u{1F600}";
+        let layout = CoreGraphicsText::new_with_unique_state()
+            .new_text_layout(text)
+            .font(FontFamily::SYSTEM_UI, 14.0)
+            .build()
+            .unwrap();
+        let fonts = layout.resolved_fonts();
+        assert_eq!(fonts.len(), 2);
+        assert_eq!(fonts[0].0, 0..3);
+        assert_eq!(fonts[1].0, 3..text.len());
+        assert_eq!(fonts[1].1.family.name(), "Apple Color Emoji");
+        assert_eq!(fonts[0].1.size, 14.0);
+    }
+
     #[test]
     fn line_text_empty_string() {
         let layout = CoreGraphicsText::new_with_unique_state()
@@ -3,6 +3,10 @@
 
 //! Conversions of types into Direct2D
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
 use winapi::um::d2d1::{
     D2D1_CAP_STYLE, D2D1_CAP_STYLE_FLAT, D2D1_CAP_STYLE_ROUND, D2D1_CAP_STYLE_SQUARE, D2D1_COLOR_F,
     D2D1_DASH_STYLE_CUSTOM, D2D1_DASH_STYLE_SOLID, D2D1_ELLIPSE, D2D1_GRADIENT_STOP,
@@ -224,3 +228,19 @@ pub(crate) fn convert_stroke_style(
     };
     Ok(factory.create_stroke_style(&props, dashes.as_deref())?)
 }
+
+/// A hash of everything in a [`StrokeStyle`] that affects its conversion,
+/// for caching converted styles.
+pub(crate) fn stroke_style_hash(stroke_style: &StrokeStyle) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mem::discriminant(&stroke_style.line_join).hash(&mut hasher);
+    if let Some(limit) = stroke_style.miter_limit() {
+        limit.to_bits().hash(&mut hasher);
+    }
+    mem::discriminant(&stroke_style.line_cap).hash(&mut hasher);
+    for dash in stroke_style.dash_pattern.iter() {
+        dash.to_bits().hash(&mut hasher);
+    }
+    stroke_style.dash_offset.to_bits().hash(&mut hasher);
+    hasher.finish()
+}
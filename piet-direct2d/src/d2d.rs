@@ -22,21 +22,28 @@ use wio::com::ComPtr;
 use winapi::shared::dxgi::{IDXGIDevice, IDXGISurface};
 use winapi::shared::dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
 use winapi::shared::minwindef::TRUE;
-use winapi::shared::winerror::{HRESULT, SUCCEEDED};
+use winapi::shared::winerror::{
+    D2DERR_RECREATE_TARGET, DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET, E_OUTOFMEMORY,
+    HRESULT, SUCCEEDED,
+};
 use winapi::um::d2d1::{
     D2D1CreateFactory, ID2D1Bitmap, ID2D1BitmapRenderTarget, ID2D1Brush, ID2D1EllipseGeometry,
     ID2D1Geometry, ID2D1GeometrySink, ID2D1GradientStopCollection, ID2D1Image, ID2D1Layer,
-    ID2D1PathGeometry, ID2D1RectangleGeometry, ID2D1RenderTarget, ID2D1RoundedRectangleGeometry,
-    ID2D1SolidColorBrush, ID2D1StrokeStyle, D2D1_ANTIALIAS_MODE_PER_PRIMITIVE, D2D1_BEZIER_SEGMENT,
-    D2D1_BITMAP_INTERPOLATION_MODE, D2D1_BRUSH_PROPERTIES, D2D1_COLOR_F,
-    D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE, D2D1_DEBUG_LEVEL_NONE, D2D1_DEBUG_LEVEL_WARNING,
-    D2D1_DRAW_TEXT_OPTIONS, D2D1_EXTEND_MODE_CLAMP, D2D1_FACTORY_OPTIONS,
-    D2D1_FACTORY_TYPE_MULTI_THREADED, D2D1_FIGURE_BEGIN_FILLED, D2D1_FIGURE_BEGIN_HOLLOW,
-    D2D1_FIGURE_END_CLOSED, D2D1_FIGURE_END_OPEN, D2D1_FILL_MODE_ALTERNATE, D2D1_FILL_MODE_WINDING,
-    D2D1_GAMMA_2_2, D2D1_GRADIENT_STOP, D2D1_LAYER_OPTIONS_NONE, D2D1_LAYER_PARAMETERS,
-    D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES, D2D1_MATRIX_3X2_F, D2D1_POINT_2F, D2D1_POINT_2U,
-    D2D1_QUADRATIC_BEZIER_SEGMENT, D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES, D2D1_RECT_F, D2D1_RECT_U,
-    D2D1_SIZE_F, D2D1_SIZE_U, D2D1_STROKE_STYLE_PROPERTIES,
+    ID2D1LinearGradientBrush, ID2D1PathGeometry, ID2D1RadialGradientBrush, ID2D1RectangleGeometry,
+    ID2D1RenderTarget, ID2D1RoundedRectangleGeometry, ID2D1SolidColorBrush, ID2D1StrokeStyle,
+    D2D1_ANTIALIAS_MODE_PER_PRIMITIVE, D2D1_ARC_SEGMENT, D2D1_ARC_SIZE_SMALL, D2D1_BEZIER_SEGMENT,
+    D2D1_BITMAP_BRUSH_PROPERTIES, D2D1_BITMAP_INTERPOLATION_MODE, D2D1_BRUSH_PROPERTIES,
+    D2D1_COLOR_F, D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE, D2D1_DEBUG_LEVEL_NONE,
+    D2D1_DEBUG_LEVEL_WARNING, D2D1_DRAW_TEXT_OPTIONS, D2D1_EXTEND_MODE, D2D1_EXTEND_MODE_CLAMP,
+    D2D1_FACTORY_OPTIONS, D2D1_FACTORY_TYPE_MULTI_THREADED, D2D1_FIGURE_BEGIN_FILLED,
+    D2D1_FIGURE_BEGIN_HOLLOW, D2D1_FIGURE_END_CLOSED, D2D1_FIGURE_END_OPEN,
+    D2D1_FILL_MODE_ALTERNATE, D2D1_FILL_MODE_WINDING, D2D1_GAMMA_2_2, D2D1_GRADIENT_STOP,
+    D2D1_LAYER_OPTIONS_NONE, D2D1_LAYER_PARAMETERS, D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES,
+    D2D1_MATRIX_3X2_F, D2D1_POINT_2F, D2D1_POINT_2U, D2D1_QUADRATIC_BEZIER_SEGMENT,
+    D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES, D2D1_RECT_F, D2D1_RECT_U, D2D1_SIZE_F, D2D1_SIZE_U,
+    D2D1_STROKE_STYLE_PROPERTIES, D2D1_SWEEP_DIRECTION_CLOCKWISE,
+    D2D1_SWEEP_DIRECTION_COUNTER_CLOCKWISE, D2D1_TEXT_ANTIALIAS_MODE,
+    D2D1_TEXT_ANTIALIAS_MODE_DEFAULT, D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE,
 };
 use winapi::um::d2d1_1::{
     ID2D1Bitmap1, ID2D1Device, ID2D1DeviceContext, ID2D1Effect, ID2D1Factory1,
@@ -46,7 +53,9 @@ use winapi::um::d2d1_1::{
 };
 use winapi::um::d2d1_1::{D2D1_PRIMITIVE_BLEND_COPY, D2D1_PRIMITIVE_BLEND_SOURCE_OVER};
 use winapi::um::d2d1effects::{CLSID_D2D1GaussianBlur, D2D1_GAUSSIANBLUR_PROP_STANDARD_DEVIATION};
-use winapi::um::dcommon::{D2D1_ALPHA_MODE, D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_PIXEL_FORMAT};
+use winapi::um::dcommon::{
+    D2D1_ALPHA_MODE, D2D1_ALPHA_MODE_IGNORE, D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_PIXEL_FORMAT,
+};
 use winapi::Interface;
 
 use crate::conv::{circle_to_d2d, rect_to_rectf, rounded_rect_to_d2d, to_point2f};
@@ -61,10 +70,28 @@ pub enum Error {
     WinapiError(HRESULT),
 }
 
+/// The text antialiasing to use on a target, depending on whether it is
+/// opaque.
+///
+/// ClearType relies on knowing the color behind the text, so on a target
+/// with an alpha channel, such as a transparent window, it is replaced with
+/// grayscale antialiasing. On an opaque target the system setting is used.
+pub fn text_antialias_mode_for(opaque: bool) -> D2D1_TEXT_ANTIALIAS_MODE {
+    if opaque {
+        D2D1_TEXT_ANTIALIAS_MODE_DEFAULT
+    } else {
+        D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE
+    }
+}
+
 /// A Direct2D factory object.
 ///
 /// This struct is public only to use for system integration in piet_common and druid-shell. It is not intended
-/// that end-users directly use this struct.
+/// that end-users directly use this struct, other than to clone it for use with a
+/// [`D2DResourceBuilder`] on another thread.
+///
+/// [`D2DResourceBuilder`]: crate::D2DResourceBuilder
+#[derive(Clone)]
 pub struct D2DFactory(ComPtr<ID2D1Factory1>);
 
 /// A Direct2D device.
@@ -75,6 +102,13 @@ pub struct D2DDevice(ComPtr<ID2D1Device>);
 // `Sync`.) https://docs.microsoft.com/en-us/windows/win32/direct2d/multi-threaded-direct2d-apps
 unsafe impl Send for D2DFactory {}
 unsafe impl Send for D2DDevice {}
+// Our factory is always created with D2D1_FACTORY_TYPE_MULTI_THREADED, which serializes access
+// internally, and geometries and stroke styles are device-independent resources.
+unsafe impl Sync for D2DFactory {}
+unsafe impl Send for Geometry {}
+unsafe impl Sync for Geometry {}
+unsafe impl Send for StrokeStyle {}
+unsafe impl Sync for StrokeStyle {}
 
 /// The main context that takes drawing operations.
 ///
@@ -94,6 +128,7 @@ pub struct RoundedRectangleGeometry(ComPtr<ID2D1RoundedRectangleGeometry>);
 
 pub struct EllipseGeometry(ComPtr<ID2D1EllipseGeometry>);
 
+#[derive(Clone)]
 pub struct Geometry(ComPtr<ID2D1Geometry>);
 
 pub struct GeometrySink<'a> {
@@ -110,6 +145,7 @@ pub struct GradientStopCollection(ComPtr<ID2D1GradientStopCollection>);
 // TODO: consider not building this at all, but just Brush.
 pub struct SolidColorBrush(ComPtr<ID2D1SolidColorBrush>);
 
+#[derive(Clone)]
 pub struct StrokeStyle(ComPtr<ID2D1StrokeStyle>);
 
 pub struct Layer(ComPtr<ID2D1Layer>);
@@ -179,7 +215,13 @@ impl std::error::Error for Error {
 
 impl From<Error> for piet::Error {
     fn from(e: Error) -> piet::Error {
-        piet::Error::BackendError(Box::new(e))
+        match e {
+            Error::WinapiError(
+                D2DERR_RECREATE_TARGET | DXGI_ERROR_DEVICE_REMOVED | DXGI_ERROR_DEVICE_RESET,
+            ) => piet::Error::DeviceLost,
+            Error::WinapiError(E_OUTOFMEMORY) => piet::Error::OutOfMemory,
+            e => piet::Error::BackendError(Box::new(e)),
+        }
     }
 }
 
@@ -241,15 +283,24 @@ impl D2DFactory {
     /// This requires Windows 7 platform update, and can also fail if
     /// resources are unavailable.
     pub fn new() -> Result<D2DFactory, Error> {
+        // The debug layer should never be active in the release version of an application.
+        // https://docs.microsoft.com/en-us/windows/win32/Direct2D/direct2ddebuglayer-overview
+        D2DFactory::new_with_debug_layer(cfg!(debug_assertions))
+    }
+
+    /// Create a new Direct2D factory, with the debug layer reporting
+    /// warnings and errors if `debug_layer` is `true`.
+    ///
+    /// The debug layer is only available where the Windows SDK is installed;
+    /// elsewhere this fails.
+    pub fn new_with_debug_layer(debug_layer: bool) -> Result<D2DFactory, Error> {
         unsafe {
             let mut ptr: *mut ID2D1Factory1 = null_mut();
             let hr = D2D1CreateFactory(
                 D2D1_FACTORY_TYPE_MULTI_THREADED,
                 &ID2D1Factory1::uuidof(),
                 &D2D1_FACTORY_OPTIONS {
-                    // The debug layer should never be active in the release version of an application.
-                    // https://docs.microsoft.com/en-us/windows/win32/Direct2D/direct2ddebuglayer-overview
-                    debugLevel: match cfg!(debug_assertions) {
+                    debugLevel: match debug_layer {
                         true => D2D1_DEBUG_LEVEL_WARNING,
                         false => D2D1_DEBUG_LEVEL_NONE,
                     },
@@ -406,12 +457,39 @@ impl DeviceContext {
         &self,
         dxgi: &ComPtr<IDXGISurface>,
         dpi_scale: f32,
+    ) -> Result<Bitmap, Error> {
+        self.bitmap_from_dxgi(dxgi, dpi_scale, D2D1_ALPHA_MODE_PREMULTIPLIED)
+    }
+
+    /// Create a bitmap from a DXGI surface, ignoring its alpha channel.
+    ///
+    /// This is for surfaces that are shown opaque, such as the buffers of a
+    /// swap chain that isn't transparent. Setting such a bitmap as the
+    /// target lets text be drawn with ClearType.
+    ///
+    /// # Safety
+    /// As for [`create_bitmap_from_dxgi`].
+    ///
+    /// [`create_bitmap_from_dxgi`]: DeviceContext::create_bitmap_from_dxgi
+    pub unsafe fn create_opaque_bitmap_from_dxgi(
+        &self,
+        dxgi: &ComPtr<IDXGISurface>,
+        dpi_scale: f32,
+    ) -> Result<Bitmap, Error> {
+        self.bitmap_from_dxgi(dxgi, dpi_scale, D2D1_ALPHA_MODE_IGNORE)
+    }
+
+    unsafe fn bitmap_from_dxgi(
+        &self,
+        dxgi: &ComPtr<IDXGISurface>,
+        dpi_scale: f32,
+        alpha_mode: D2D1_ALPHA_MODE,
     ) -> Result<Bitmap, Error> {
         let mut ptr = null_mut();
         let props = D2D1_BITMAP_PROPERTIES1 {
             pixelFormat: D2D1_PIXEL_FORMAT {
                 format: DXGI_FORMAT_R8G8B8A8_UNORM,
-                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+                alphaMode: alpha_mode,
             },
             dpiX: 96.0 * dpi_scale,
             dpiY: 96.0 * dpi_scale,
@@ -432,9 +510,26 @@ impl DeviceContext {
     /// Set the target for the device context.
     ///
     /// Useful for rendering into bitmaps.
+    ///
+    /// This also picks how text is antialiased, with
+    /// [`text_antialias_mode_for`]: ClearType leaves color fringes on a
+    /// target that is composited with what is behind it, so it is only used
+    /// if the target is opaque. Call [`set_text_antialias_mode`] afterwards
+    /// to choose otherwise.
+    ///
+    /// [`set_text_antialias_mode`]: DeviceContext::set_text_antialias_mode
     pub fn set_target(&mut self, target: &Bitmap) {
         assert!(!target.empty_image);
         unsafe { self.0.SetTarget(target.inner.as_raw() as *mut ID2D1Image) }
+        self.set_text_antialias_mode(text_antialias_mode_for(target.is_opaque()));
+    }
+
+    /// Clear the target of the device context.
+    ///
+    /// This releases the device context's reference to the previous target,
+    /// for example so that the buffers of a swap chain can be resized.
+    pub fn clear_target(&mut self) {
+        unsafe { self.0.SetTarget(null_mut()) }
     }
 
     /// Set the dpi scale.
@@ -703,6 +798,99 @@ impl DeviceContext {
         }
     }
 
+    /// Create a copy of a brush, with its opacity multiplied by `opacity`.
+    ///
+    /// Brushes are shared (solid brushes are cached), so the opacity of an
+    /// existing brush can't be changed in place.
+    pub(crate) fn create_faded_brush(
+        &mut self,
+        brush: &Brush,
+        opacity: f32,
+    ) -> Result<Brush, Error> {
+        unsafe {
+            let mut props = D2D1_BRUSH_PROPERTIES {
+                opacity: brush.0.GetOpacity() * opacity,
+                transform: IDENTITY_MATRIX_3X2_F,
+            };
+            brush.0.GetTransform(&mut props.transform);
+            if let Ok(solid) = brush.0.cast::<ID2D1SolidColorBrush>() {
+                let mut ptr = null_mut();
+                let hr = self
+                    .0
+                    .CreateSolidColorBrush(&solid.GetColor(), &props, &mut ptr);
+                wrap(hr, ptr, |p| Brush(p.up()))
+            } else if let Ok(linear) = brush.0.cast::<ID2D1LinearGradientBrush>() {
+                let linear_props = D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES {
+                    startPoint: linear.GetStartPoint(),
+                    endPoint: linear.GetEndPoint(),
+                };
+                let mut stops = null_mut();
+                linear.GetGradientStopCollection(&mut stops);
+                let stops = ComPtr::from_raw(stops);
+                let mut ptr = null_mut();
+                let hr = self.0.CreateLinearGradientBrush(
+                    &linear_props,
+                    &props,
+                    stops.as_raw(),
+                    &mut ptr,
+                );
+                wrap(hr, ptr, |p| Brush(p.up()))
+            } else {
+                // Only the brushes piet creates are supported.
+                let radial = brush.0.cast::<ID2D1RadialGradientBrush>()?;
+                let radial_props = D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES {
+                    center: radial.GetCenter(),
+                    gradientOriginOffset: radial.GetGradientOriginOffset(),
+                    radiusX: radial.GetRadiusX(),
+                    radiusY: radial.GetRadiusY(),
+                };
+                let mut stops = null_mut();
+                radial.GetGradientStopCollection(&mut stops);
+                let stops = ComPtr::from_raw(stops);
+                let mut ptr = null_mut();
+                let hr = self.0.CreateRadialGradientBrush(
+                    &radial_props,
+                    &props,
+                    stops.as_raw(),
+                    &mut ptr,
+                );
+                wrap(hr, ptr, |p| Brush(p.up()))
+            }
+        }
+    }
+
+    /// Create a brush that repeats a bitmap in both directions.
+    ///
+    /// `transform` maps the bitmap's pixels to the brush's coordinate space.
+    pub(crate) fn create_bitmap_brush(
+        &mut self,
+        bitmap: &Bitmap,
+        extend_mode: D2D1_EXTEND_MODE,
+        interp_mode: D2D1_BITMAP_INTERPOLATION_MODE,
+        transform: &D2D1_MATRIX_3X2_F,
+    ) -> Result<Brush, Error> {
+        let bitmap_props = D2D1_BITMAP_BRUSH_PROPERTIES {
+            extendModeX: extend_mode,
+            extendModeY: extend_mode,
+            interpolationMode: interp_mode,
+        };
+        let props = D2D1_BRUSH_PROPERTIES {
+            opacity: 1.0,
+            transform: *transform,
+        };
+        unsafe {
+            let mut ptr = null_mut();
+            // derefs are so we get the RenderTarget method, as in `draw_bitmap`.
+            let hr = self.0.deref().deref().CreateBitmapBrush(
+                bitmap.inner.as_raw() as *mut ID2D1Bitmap,
+                &bitmap_props,
+                &props,
+                &mut ptr,
+            );
+            wrap(hr, ptr, |p| Brush(p.up()))
+        }
+    }
+
     pub(crate) fn create_gradient_stops(
         &mut self,
         stops: &[D2D1_GRADIENT_STOP],
@@ -887,6 +1075,16 @@ impl DeviceContext {
         }
     }
 
+    /// How text is antialiased, unless a layout asks otherwise.
+    pub fn text_antialias_mode(&self) -> D2D1_TEXT_ANTIALIAS_MODE {
+        unsafe { self.0.GetTextAntialiasMode() }
+    }
+
+    /// Set how text is antialiased, unless a layout asks otherwise.
+    pub fn set_text_antialias_mode(&mut self, mode: D2D1_TEXT_ANTIALIAS_MODE) {
+        unsafe { self.0.SetTextAntialiasMode(mode) }
+    }
+
     #[allow(clippy::trivially_copy_pass_by_ref)]
     pub(crate) fn draw_bitmap(
         &mut self,
@@ -1009,6 +1207,33 @@ impl<'a> GeometrySink<'a> {
         }
     }
 
+    /// Add an elliptical arc to `point`.
+    ///
+    /// The arc is the shorter of the two that fit, so it should be no more
+    /// than half of the ellipse. The `rotation` of the ellipse's x axis is
+    /// in degrees.
+    pub fn add_arc(
+        &mut self,
+        point: D2D1_POINT_2F,
+        radii: D2D1_SIZE_F,
+        rotation: f32,
+        clockwise: bool,
+    ) {
+        let seg = D2D1_ARC_SEGMENT {
+            point,
+            size: radii,
+            rotationAngle: rotation,
+            sweepDirection: match clockwise {
+                true => D2D1_SWEEP_DIRECTION_CLOCKWISE,
+                false => D2D1_SWEEP_DIRECTION_COUNTER_CLOCKWISE,
+            },
+            arcSize: D2D1_ARC_SIZE_SMALL,
+        };
+        unsafe {
+            self.ptr.AddArc(&seg);
+        }
+    }
+
     pub fn add_bezier(
         &mut self,
         point1: D2D1_POINT_2F,
@@ -1072,6 +1297,31 @@ impl Bitmap {
         unsafe { self.inner.GetSize() }
     }
 
+    pub fn get_pixel_size(&self) -> D2D1_SIZE_U {
+        unsafe { self.inner.GetPixelSize() }
+    }
+
+    /// Whether the bitmap's alpha channel is ignored.
+    pub fn is_opaque(&self) -> bool {
+        unsafe { self.inner.GetPixelFormat().alphaMode == D2D1_ALPHA_MODE_IGNORE }
+    }
+
+    /// Replace the bitmap's pixels in `dest_rect` with rows of `pitch` bytes
+    /// from `buf`, in the bitmap's own format.
+    pub(crate) fn copy_from_memory(
+        &mut self,
+        dest_rect: D2D1_RECT_U,
+        buf: &[u8],
+        pitch: u32,
+    ) -> Result<(), Error> {
+        unsafe {
+            let hr = self
+                .inner
+                .CopyFromMemory(&dest_rect, buf.as_ptr() as *const c_void, pitch);
+            wrap_unit(hr)
+        }
+    }
+
     pub(crate) fn copy_from_render_target(
         &mut self,
         dest_point: D2D1_POINT_2U,
@@ -1128,6 +1378,12 @@ impl Brush {
     pub(crate) fn as_raw(&self) -> *mut ID2D1Brush {
         self.0.as_raw()
     }
+
+    pub(crate) fn set_transform(&self, transform: &D2D1_MATRIX_3X2_F) {
+        unsafe {
+            self.0.SetTransform(transform);
+        }
+    }
 }
 
 mod tests {
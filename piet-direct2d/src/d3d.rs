@@ -9,12 +9,12 @@ pub use winapi::shared::dxgi::DXGI_MAP_READ;
 use winapi::shared::dxgi::{IDXGIDevice, IDXGISurface};
 use winapi::shared::dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
 use winapi::shared::dxgitype::DXGI_SAMPLE_DESC;
-use winapi::shared::winerror::{HRESULT, SUCCEEDED};
+use winapi::shared::winerror::{HRESULT, SUCCEEDED, S_OK};
 use winapi::um::d3d11::{
     D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_BIND_FLAG,
     D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE, D3D11_CPU_ACCESS_FLAG,
-    D3D11_CPU_ACCESS_READ, D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_SDK_VERSION,
-    D3D11_TEXTURE2D_DESC, D3D11_USAGE, D3D11_USAGE_DEFAULT, D3D11_USAGE_STAGING,
+    D3D11_CPU_ACCESS_READ, D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_CREATE_DEVICE_DEBUG,
+    D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC, D3D11_USAGE, D3D11_USAGE_DEFAULT, D3D11_USAGE_STAGING,
 };
 use winapi::um::d3dcommon::D3D_DRIVER_TYPE_HARDWARE;
 use winapi::Interface;
@@ -85,6 +85,21 @@ impl D3D11Device {
 
     // This function only supports a fraction of available options.
     pub fn create() -> Result<(D3D11Device, D3D11DeviceContext), Error> {
+        D3D11Device::create_with_debug_layer(false)
+    }
+
+    /// Create a device, with the Direct3D debug layer if `debug_layer` is
+    /// `true`.
+    ///
+    /// The debug layer is only available where the Windows SDK is installed;
+    /// elsewhere this fails.
+    pub fn create_with_debug_layer(
+        debug_layer: bool,
+    ) -> Result<(D3D11Device, D3D11DeviceContext), Error> {
+        let mut flags = D3D11_CREATE_DEVICE_BGRA_SUPPORT;
+        if debug_layer {
+            flags |= D3D11_CREATE_DEVICE_DEBUG;
+        }
         unsafe {
             let mut ptr = null_mut();
             let mut ctx_ptr = null_mut();
@@ -92,7 +107,7 @@ impl D3D11Device {
                 null_mut(), /* adapter */
                 D3D_DRIVER_TYPE_HARDWARE,
                 null_mut(), /* module */
-                D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+                flags,
                 null_mut(), /* feature levels */
                 0,
                 D3D11_SDK_VERSION,
@@ -106,6 +121,14 @@ impl D3D11Device {
         }
     }
 
+    /// Whether the device was removed or reset, for instance by a driver
+    /// update or a GPU hang.
+    ///
+    /// A removed device can not be used again; it must be created anew.
+    pub fn is_removed(&self) -> bool {
+        unsafe { self.0.GetDeviceRemovedReason() != S_OK }
+    }
+
     pub fn as_dxgi(&self) -> Option<DxgiDevice> {
         self.0.cast().ok().map(DxgiDevice)
     }
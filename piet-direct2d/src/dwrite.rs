@@ -6,6 +6,7 @@
 // TODO: get rid of this when we actually do use everything
 #![allow(unused)]
 
+use std::borrow::Cow;
 use std::convert::TryInto;
 use std::ffi::OsString;
 use std::fmt::{Debug, Display, Formatter};
@@ -16,17 +17,23 @@ use std::sync::Arc;
 use dwrote::FontCollection as DWFontCollection;
 use winapi::shared::minwindef::{FALSE, TRUE};
 use winapi::shared::ntdef::LOCALE_NAME_MAX_LENGTH;
-use winapi::shared::winerror::{HRESULT, SUCCEEDED, S_OK};
+use winapi::shared::winerror::{
+    DWRITE_E_FILEACCESS, DWRITE_E_FILEFORMAT, DWRITE_E_FILENOTFOUND, E_OUTOFMEMORY, HRESULT,
+    SUCCEEDED, S_OK,
+};
 use winapi::um::dwrite::{
     DWriteCreateFactory, IDWriteFactory, IDWriteFontCollection, IDWriteFontFamily,
-    IDWriteLocalizedStrings, IDWriteTextFormat, IDWriteTextLayout, DWRITE_FACTORY_TYPE_SHARED,
+    IDWriteLocalizedStrings, IDWriteTextFormat, IDWriteTextLayout, IDWriteTypography,
+    DWRITE_CLUSTER_METRICS, DWRITE_FACTORY_TYPE_SHARED, DWRITE_FONT_FEATURE, DWRITE_FONT_STRETCH,
     DWRITE_FONT_STRETCH_NORMAL, DWRITE_FONT_STYLE, DWRITE_FONT_STYLE_ITALIC,
     DWRITE_FONT_STYLE_NORMAL, DWRITE_FONT_WEIGHT, DWRITE_FONT_WEIGHT_NORMAL,
     DWRITE_HIT_TEST_METRICS, DWRITE_LINE_METRICS, DWRITE_OVERHANG_METRICS,
+    DWRITE_READING_DIRECTION, DWRITE_READING_DIRECTION_LEFT_TO_RIGHT,
     DWRITE_READING_DIRECTION_RIGHT_TO_LEFT, DWRITE_TEXT_ALIGNMENT_CENTER,
     DWRITE_TEXT_ALIGNMENT_JUSTIFIED, DWRITE_TEXT_ALIGNMENT_LEADING, DWRITE_TEXT_ALIGNMENT_TRAILING,
     DWRITE_TEXT_METRICS, DWRITE_TEXT_RANGE,
 };
+use winapi::um::dwrite_2::{IDWriteFactory2, IDWriteFontFallback};
 use winapi::um::unknwnbase::IUnknown;
 use winapi::um::winnls::GetUserDefaultLocaleName;
 use winapi::Interface;
@@ -35,7 +42,10 @@ use wio::com::ComPtr;
 use wio::wide::{FromWide, ToWide};
 
 use piet::kurbo::Insets;
-use piet::{FontFamily as PietFontFamily, FontStyle, FontWeight, TextAlignment};
+use piet::{
+    FontDescription, FontFamily as PietFontFamily, FontFeature, FontStyle, FontWeight,
+    TextAlignment,
+};
 
 use crate::Brush;
 
@@ -72,6 +82,8 @@ struct FontFamily(ComPtr<IDWriteFontFamily>);
 
 pub struct FontCollection(ComPtr<IDWriteFontCollection>);
 
+pub(crate) struct FontFallback(ComPtr<IDWriteFontFallback>);
+
 #[derive(Clone)]
 pub struct TextLayout(ComPtr<IDWriteTextLayout>);
 
@@ -112,7 +124,13 @@ impl std::error::Error for Error {
 
 impl From<Error> for piet::Error {
     fn from(e: Error) -> piet::Error {
-        piet::Error::BackendError(Box::new(e))
+        match e {
+            Error::WinapiError(
+                DWRITE_E_FILEACCESS | DWRITE_E_FILEFORMAT | DWRITE_E_FILENOTFOUND,
+            ) => piet::Error::FontLoadingFailed,
+            Error::WinapiError(E_OUTOFMEMORY) => piet::Error::OutOfMemory,
+            e => piet::Error::BackendError(Box::new(e)),
+        }
     }
 }
 
@@ -145,6 +163,17 @@ impl DwriteFactory {
         self.0.as_raw()
     }
 
+    /// The system's font fallback, which is only available on Windows 8.1
+    /// and later.
+    pub(crate) fn system_font_fallback(&self) -> Option<FontFallback> {
+        let factory = self.0.cast::<IDWriteFactory2>().ok()?;
+        unsafe {
+            let mut ptr = null_mut();
+            let hr = factory.GetSystemFontFallback(&mut ptr);
+            wrap(hr, ptr, FontFallback).ok()
+        }
+    }
+
     pub(crate) fn system_font_collection(&self) -> Result<FontCollection, Error> {
         unsafe {
             let mut ptr = null_mut();
@@ -202,11 +231,8 @@ impl FontFamily {
 
             let mut index = 0_u32;
             let mut exists = 0_i32;
-            let mut locale_name = [0_u16; LOCALE_NAME_MAX_LENGTH];
 
-            let success =
-                GetUserDefaultLocaleName(locale_name.as_mut_ptr(), LOCALE_NAME_MAX_LENGTH as i32);
-            let mut hr = if SUCCEEDED(success) {
+            let mut hr = if let Some(locale_name) = user_default_locale() {
                 names.FindLocaleName(locale_name.as_ptr(), &mut index, &mut exists)
             } else {
                 // we reuse the previous success; we want  to run the next block
@@ -257,6 +283,11 @@ impl TextFormat {
         rtl: bool,
     ) -> Result<TextFormat, Error> {
         let family = family.as_ref();
+        let locale = user_default_locale();
+        let locale = locale
+            .as_ref()
+            .map(|l| l.as_slice())
+            .unwrap_or(DEFAULT_LOCALE);
 
         unsafe {
             let mut ptr = null_mut();
@@ -267,8 +298,7 @@ impl TextFormat {
                 DWRITE_FONT_STYLE_NORMAL,
                 DWRITE_FONT_STRETCH_NORMAL,
                 size,
-                //TODO: this should be the user's locale? It will influence font fallback behaviour?
-                DEFAULT_LOCALE.as_ptr(),
+                locale.as_ptr(),
                 &mut ptr,
             );
 
@@ -281,6 +311,16 @@ impl TextFormat {
     }
 }
 
+/// The user's default locale name, as a null-terminated wide string.
+fn user_default_locale() -> Option<[u16; LOCALE_NAME_MAX_LENGTH]> {
+    let mut locale_name = [0_u16; LOCALE_NAME_MAX_LENGTH];
+    // returns the length of the name, or 0 on failure
+    let len = unsafe {
+        GetUserDefaultLocaleName(locale_name.as_mut_ptr(), LOCALE_NAME_MAX_LENGTH as i32)
+    };
+    (len > 0).then_some(locale_name)
+}
+
 #[allow(overflowing_literals)]
 #[allow(clippy::unreadable_literal)]
 const E_NOT_SUFFICIENT_BUFFER: HRESULT = 0x8007007A;
@@ -389,6 +429,36 @@ impl TextLayout {
         }
     }
 
+    pub(crate) fn set_font_features(
+        &mut self,
+        factory: &DwriteFactory,
+        range: Utf16Range,
+        features: &[FontFeature],
+    ) {
+        unsafe {
+            let mut ptr: *mut IDWriteTypography = null_mut();
+            let hr = (*factory.get_raw()).CreateTypography(&mut ptr);
+            if !SUCCEEDED(hr) {
+                return;
+            }
+            let typography = ComPtr::from_raw(ptr);
+            for feature in features {
+                typography.AddFontFeature(DWRITE_FONT_FEATURE {
+                    nameTag: u32::from_le_bytes(feature.tag()),
+                    parameter: feature.value(),
+                });
+            }
+            self.0.SetTypography(typography.as_raw(), range.into());
+        }
+    }
+
+    /// Set the locale name; `locale` must be null-terminated.
+    pub(crate) fn set_locale(&mut self, range: Utf16Range, locale: &[u16]) {
+        unsafe {
+            self.0.SetLocaleName(locale.as_ptr(), range.into());
+        }
+    }
+
     pub(crate) fn set_size(&mut self, range: Utf16Range, size: f32) {
         unsafe {
             self.0.SetFontSize(size, range.into());
@@ -427,6 +497,28 @@ impl TextLayout {
         }
     }
 
+    /// Get the metrics of each cluster of the text, in text order.
+    ///
+    /// Returns an empty list if DirectWrite fails.
+    pub fn get_cluster_metrics(&self) -> Vec<DWRITE_CLUSTER_METRICS> {
+        let mut buf = Vec::new();
+        unsafe {
+            let mut actual_count = 0;
+            let hr = self.0.GetClusterMetrics(null_mut(), 0, &mut actual_count);
+            if hr != E_NOT_SUFFICIENT_BUFFER {
+                return buf;
+            }
+            buf.reserve(actual_count as usize);
+            let hr = self
+                .0
+                .GetClusterMetrics(buf.as_mut_ptr(), actual_count, &mut actual_count);
+            if SUCCEEDED(hr) {
+                buf.set_len(actual_count as usize);
+            }
+        }
+        buf
+    }
+
     pub fn get_raw(&self) -> *mut IDWriteTextLayout {
         self.0.as_raw()
     }
@@ -522,6 +614,194 @@ impl TextLayout {
             })
         }
     }
+
+    /// The font this layout asks for at the utf-16 position `pos`, before
+    /// any fallback, along with the end of the text that asks for the same
+    /// font.
+    fn requested_font(&self, pos: u32) -> Result<(RequestedFont, u32), Error> {
+        let range_end = |range: DWRITE_TEXT_RANGE| range.startPosition.saturating_add(range.length);
+        unsafe {
+            let mut range: DWRITE_TEXT_RANGE = std::mem::zeroed();
+            let mut len = 0;
+            let hr = self.0.GetFontFamilyNameLength(pos, &mut len, &mut range);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            let mut end = range_end(range);
+            let mut family = vec![0_u16; len as usize + 1];
+            let hr = self
+                .0
+                .GetFontFamilyName(pos, family.as_mut_ptr(), len + 1, null_mut());
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+
+            let mut weight = DWRITE_FONT_WEIGHT_NORMAL;
+            let hr = self.0.GetFontWeight(pos, &mut weight, &mut range);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            end = end.min(range_end(range));
+            let mut style = DWRITE_FONT_STYLE_NORMAL;
+            let hr = self.0.GetFontStyle(pos, &mut style, &mut range);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            end = end.min(range_end(range));
+            let mut stretch = DWRITE_FONT_STRETCH_NORMAL;
+            let hr = self.0.GetFontStretch(pos, &mut stretch, &mut range);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            end = end.min(range_end(range));
+            let mut size = 0.0;
+            let hr = self.0.GetFontSize(pos, &mut size, &mut range);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            end = end.min(range_end(range));
+            let mut collection = null_mut();
+            let hr = self.0.GetFontCollection(pos, &mut collection, &mut range);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            end = end.min(range_end(range));
+            let collection =
+                (!collection.is_null()).then(|| FontCollection(ComPtr::from_raw(collection)));
+
+            let font = RequestedFont {
+                family,
+                collection,
+                weight,
+                style,
+                stretch,
+                size,
+            };
+            Ok((font, end))
+        }
+    }
+
+    /// The fonts used to draw `text`, which this layout was made from,
+    /// including any fallback fonts, with the utf-16 range each draws.
+    ///
+    /// This is empty before Windows 8.1, which added the system font fallback.
+    pub(crate) fn resolved_fonts(
+        &self,
+        factory: &DwriteFactory,
+        text: &[u16],
+        rtl: bool,
+    ) -> Result<Vec<(Utf16Range, FontDescription)>, Error> {
+        let Some(fallback) = factory.system_font_fallback() else {
+            return Ok(Vec::new());
+        };
+        let system = factory.system_font_collection()?;
+        let len = text.len() as u32;
+        let source = dwrote::TextAnalysisSource::from_text(
+            Box::new(FallbackSource { len, rtl }),
+            Cow::Borrowed(text),
+        );
+
+        let mut fonts = Vec::new();
+        let mut pos = 0;
+        while pos < len {
+            let (requested, end) = self.requested_font(pos)?;
+            let end = end.clamp(pos + 1, len);
+            let collection = requested.collection.as_ref().unwrap_or(&system);
+            while pos < end {
+                let mut mapped_len = 0;
+                let mut mapped_font = null_mut();
+                let mut scale = 1.0;
+                let hr = unsafe {
+                    fallback.0.MapCharacters(
+                        source.as_ptr(),
+                        pos,
+                        end - pos,
+                        collection.0.as_raw(),
+                        requested.family.as_ptr() as *mut _,
+                        requested.weight,
+                        requested.style,
+                        requested.stretch,
+                        &mut mapped_len,
+                        &mut mapped_font,
+                        &mut scale,
+                    )
+                };
+                if !SUCCEEDED(hr) {
+                    return Err(hr.into());
+                }
+                let mapped_len = mapped_len.clamp(1, end - pos);
+                // no font is returned if no font has the characters
+                let font = if mapped_font.is_null() {
+                    requested.description()
+                } else {
+                    let font = dwrote::Font::take(unsafe { ComPtr::from_raw(mapped_font) });
+                    let style = match font.style() {
+                        dwrote::FontStyle::Normal => FontStyle::Regular,
+                        _ => FontStyle::Italic,
+                    };
+                    FontDescription {
+                        family: PietFontFamily::new_unchecked(font.family_name()),
+                        weight: FontWeight::new(font.weight().to_u32() as u16),
+                        style,
+                        size: (requested.size * scale) as f64,
+                    }
+                };
+                fonts.push((Utf16Range::new(pos as usize, mapped_len as usize), font));
+                pos += mapped_len;
+            }
+        }
+        Ok(fonts)
+    }
+}
+
+/// The font a layout asks for, before any fallback.
+struct RequestedFont {
+    /// The null-terminated family name.
+    family: Vec<u16>,
+    /// The collection to find the family in, if not the system collection.
+    collection: Option<FontCollection>,
+    weight: DWRITE_FONT_WEIGHT,
+    style: DWRITE_FONT_STYLE,
+    stretch: DWRITE_FONT_STRETCH,
+    size: f32,
+}
+
+impl RequestedFont {
+    fn description(&self) -> FontDescription {
+        let name = &self.family[..self.family.len() - 1];
+        let style = if self.style == DWRITE_FONT_STYLE_NORMAL {
+            FontStyle::Regular
+        } else {
+            FontStyle::Italic
+        };
+        FontDescription {
+            family: PietFontFamily::new_unchecked(String::from_utf16_lossy(name)),
+            weight: FontWeight::new(self.weight as u16),
+            style,
+            size: self.size as f64,
+        }
+    }
+}
+
+/// The text analysis source used for font fallback, which only needs to
+/// know the reading direction; the locale is left unspecified.
+struct FallbackSource {
+    len: u32,
+    rtl: bool,
+}
+
+impl dwrote::TextAnalysisSourceMethods for FallbackSource {
+    fn get_locale_name(&self, _text_position: u32) -> (Cow<'_, str>, u32) {
+        (Cow::Borrowed(""), self.len)
+    }
+
+    fn get_paragraph_reading_direction(&self) -> DWRITE_READING_DIRECTION {
+        if self.rtl {
+            DWRITE_READING_DIRECTION_RIGHT_TO_LEFT
+        } else {
+            DWRITE_READING_DIRECTION_LEFT_TO_RIGHT
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
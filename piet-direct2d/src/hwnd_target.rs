@@ -0,0 +1,377 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Drawing to a window through a DXGI swap chain.
+//!
+//! [`HwndTarget`] owns the Direct3D device, the swap chain for a window and
+//! the Direct2D device context bound to its back buffer, and hands out a
+//! [`D2DRenderContext`] for each frame.
+//!
+//! A target can be made transparent with [`HwndTargetOptions`], in which
+//! case the swap chain is shown through DirectComposition.
+
+use std::ptr::{null, null_mut};
+
+use winapi::shared::dxgi::{IDXGIAdapter, IDXGISurface, DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL};
+use winapi::shared::dxgi1_2::{
+    IDXGIFactory2, IDXGISwapChain1, DXGI_ALPHA_MODE_IGNORE, DXGI_ALPHA_MODE_PREMULTIPLIED,
+    DXGI_SCALING_STRETCH, DXGI_SWAP_CHAIN_DESC1,
+};
+use winapi::shared::dxgiformat::{DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_UNKNOWN};
+use winapi::shared::dxgitype::{DXGI_SAMPLE_DESC, DXGI_USAGE_RENDER_TARGET_OUTPUT};
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::shared::windef::{HWND, RECT};
+use winapi::um::dcomp::{
+    DCompositionCreateDevice, IDCompositionDevice, IDCompositionTarget, IDCompositionVisual,
+};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winuser::GetClientRect;
+use winapi::Interface;
+
+use wio::com::ComPtr;
+
+use piet::{Error, RenderContext};
+
+use crate::d2d::{wrap_unit, Bitmap, D2DDevice, D2DFactory, DeviceContext};
+use crate::d3d::{D3D11Device, DxgiDevice};
+use crate::{D2DLoadedFonts, D2DRenderContext, D2DText, DwriteFactory};
+
+/// A render target for a window, backed by a flip-model DXGI swap chain.
+///
+/// Call [`resize`] whenever the window's client area changes size, and
+/// [`render`] to draw and present a frame.
+///
+/// [`resize`]: HwndTarget::resize
+/// [`render`]: HwndTarget::render
+pub struct HwndTarget {
+    hwnd: HWND,
+    options: HwndTargetOptions,
+    d2d: D2DFactory,
+    text: D2DText,
+    dpi_scale: f32,
+    /// `None` if the device was lost and couldn't be recreated yet; the next
+    /// frame tries again.
+    device: Option<DeviceResources>,
+}
+
+/// Options for creating an [`HwndTarget`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HwndTargetOptions {
+    /// Whether the window shows through where nothing opaque is drawn.
+    ///
+    /// A transparent swap chain can't be presented to a window directly, so
+    /// it is shown through DirectComposition instead. The window should be
+    /// created with the `WS_EX_NOREDIRECTIONBITMAP` extended style, or what
+    /// is drawn is shown over the window's own opaque background.
+    ///
+    /// Text is drawn with grayscale antialiasing rather than ClearType on a
+    /// transparent target, as ClearType leaves color fringes where the text
+    /// is composited; layouts can still ask for ClearType with
+    /// [`TextAntialiasing::Subpixel`].
+    ///
+    /// By default, the target is opaque.
+    ///
+    /// [`TextAntialiasing::Subpixel`]: piet::TextAntialiasing::Subpixel
+    pub transparent: bool,
+}
+
+/// The resources that are lost along with the Direct3D device.
+struct DeviceResources {
+    swap_chain: ComPtr<IDXGISwapChain1>,
+    context: DeviceContext,
+    /// The back buffer of the swap chain, created on demand.
+    target: Option<Bitmap>,
+    /// Shows the swap chain in the window, if it is transparent.
+    composition: Option<Composition>,
+    // These are kept alive for as long as the device context.
+    _d3d: D3D11Device,
+    _device: D2DDevice,
+}
+
+/// The DirectComposition objects showing a transparent swap chain.
+struct Composition {
+    device: ComPtr<IDCompositionDevice>,
+    // These are kept alive for as long as the swap chain is shown.
+    _target: ComPtr<IDCompositionTarget>,
+    _visual: ComPtr<IDCompositionVisual>,
+}
+
+impl HwndTarget {
+    /// Create a new target drawing to the client area of `hwnd`.
+    ///
+    /// The swap chain is initially sized to the window's client area.
+    ///
+    /// The optional `loaded_fonts` are shared with the [`D2DText`] used
+    /// for every frame; see [`D2DText::new_with_shared_fonts`].
+    ///
+    /// # Safety
+    ///
+    /// `hwnd` must be a valid window handle, and the window must outlive
+    /// this target.
+    pub unsafe fn new(hwnd: HWND, loaded_fonts: Option<D2DLoadedFonts>) -> Result<Self, Error> {
+        HwndTarget::new_with_options(hwnd, loaded_fonts, HwndTargetOptions::default())
+    }
+
+    /// Create a new target drawing to the client area of `hwnd`, with the
+    /// given options.
+    ///
+    /// # Safety
+    ///
+    /// As for [`HwndTarget::new`].
+    pub unsafe fn new_with_options(
+        hwnd: HWND,
+        loaded_fonts: Option<D2DLoadedFonts>,
+        options: HwndTargetOptions,
+    ) -> Result<Self, Error> {
+        let d2d = D2DFactory::new()?;
+        let dwrite = DwriteFactory::new()?;
+        let text = D2DText::new_with_shared_fonts(dwrite, loaded_fonts);
+        let device = DeviceResources::new(&d2d, hwnd, options)?;
+        Ok(HwndTarget {
+            hwnd,
+            options,
+            d2d,
+            text,
+            dpi_scale: 1.0,
+            device: Some(device),
+        })
+    }
+
+    /// The Direct2D factory used by this target.
+    ///
+    /// This can be used with a [`D2DResourceBuilder`] to create resources
+    /// that can be drawn to this target.
+    ///
+    /// [`D2DResourceBuilder`]: crate::D2DResourceBuilder
+    pub fn factory(&self) -> &D2DFactory {
+        &self.d2d
+    }
+
+    /// Whether the target is transparent; see [`HwndTargetOptions`].
+    pub fn is_transparent(&self) -> bool {
+        self.options.transparent
+    }
+
+    /// The text factory used for every frame.
+    pub fn text(&mut self) -> &mut D2DText {
+        &mut self.text
+    }
+
+    /// Set the ratio of physical pixels to the logical units used for drawing.
+    pub fn set_dpi_scale(&mut self, dpi_scale: f64) {
+        self.dpi_scale = dpi_scale as f32;
+        if let Some(device) = &mut self.device {
+            device.target = None;
+        }
+    }
+
+    /// Resize the swap chain buffers, in physical pixels.
+    ///
+    /// This should be called when the window's client area changes size;
+    /// until it is, frames are stretched to fit the window.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Error> {
+        // a recreated swap chain is sized to the window anyway
+        let Some(device) = &mut self.device else {
+            return Ok(());
+        };
+        // All references to the back buffers must be released first.
+        device.target = None;
+        device.context.clear_target();
+        unsafe {
+            let hr = device.swap_chain.ResizeBuffers(
+                0,
+                width.max(1),
+                height.max(1),
+                DXGI_FORMAT_UNKNOWN,
+                0,
+            );
+            Ok(wrap_unit(hr)?)
+        }
+    }
+
+    /// Draw a frame with `f` and present it.
+    ///
+    /// `finish` is called on the render context after `f` returns. If `f`
+    /// returns an error, the frame is still presented and the error is
+    /// returned.
+    ///
+    /// If the Direct3D device was lost, it is recreated along with the swap
+    /// chain, and [`Error::DeviceLost`] is returned; the frame should be drawn
+    /// again.
+    pub fn render(
+        &mut self,
+        f: impl FnOnce(&mut D2DRenderContext) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.device.is_none() {
+            self.device =
+                Some(unsafe { DeviceResources::new(&self.d2d, self.hwnd, self.options)? });
+        }
+        let device = self.device.as_mut().unwrap();
+        device.ensure_target(self.dpi_scale)?;
+        let context = &mut device.context;
+        context.set_dpi_scale(self.dpi_scale);
+        context.begin_draw();
+        let (result, ended) = {
+            let mut rc = D2DRenderContext::new(&self.d2d, self.text.clone(), context);
+            let result = f(&mut rc);
+            let finished = rc.finish();
+            (result.and(finished), rc.end_draw())
+        };
+        // the device can also be lost while presenting
+        match ended.and_then(|()| device.present()) {
+            Err(Error::DeviceLost) => {
+                // DXGI allows only one flip-model swap chain per window, so
+                // the old one must be released before another is made.
+                self.device = None;
+                self.device =
+                    Some(unsafe { DeviceResources::new(&self.d2d, self.hwnd, self.options)? });
+                return Err(Error::DeviceLost);
+            }
+            other => other?,
+        }
+        result
+    }
+}
+
+impl DeviceResources {
+    unsafe fn new(d2d: &D2DFactory, hwnd: HWND, options: HwndTargetOptions) -> Result<Self, Error> {
+        let (d3d, _) = D3D11Device::create().map_err(|e| Error::BackendError(Box::new(e)))?;
+        let dxgi = d3d.as_dxgi().ok_or(Error::NotSupported)?;
+        let mut device = d2d.create_device(dxgi.as_raw())?;
+        let context = device.create_device_context()?;
+
+        let mut adapter: *mut IDXGIAdapter = null_mut();
+        wrap_unit((*dxgi.as_raw()).GetAdapter(&mut adapter))?;
+        let adapter = ComPtr::from_raw(adapter);
+        let mut factory: *mut IDXGIFactory2 = null_mut();
+        wrap_unit(adapter.GetParent(&IDXGIFactory2::uuidof(), &mut factory as *mut _ as *mut _))?;
+        let factory = ComPtr::from_raw(factory);
+
+        let mut desc = DXGI_SWAP_CHAIN_DESC1 {
+            // Zero means the size of the window's client area.
+            Width: 0,
+            Height: 0,
+            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            Stereo: FALSE,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+            BufferCount: 2,
+            Scaling: DXGI_SCALING_STRETCH,
+            SwapEffect: DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+            AlphaMode: DXGI_ALPHA_MODE_IGNORE,
+            Flags: 0,
+        };
+        let mut swap_chain: *mut IDXGISwapChain1 = null_mut();
+        if !options.transparent {
+            wrap_unit(factory.CreateSwapChainForHwnd(
+                d3d.inner().as_raw() as *mut IUnknown,
+                hwnd,
+                &desc,
+                null(),
+                null_mut(),
+                &mut swap_chain,
+            ))?;
+        } else {
+            // Composition swap chains aren't tied to a window, so they
+            // can't take its size.
+            let mut rect: RECT = std::mem::zeroed();
+            GetClientRect(hwnd, &mut rect);
+            desc.Width = (rect.right - rect.left).max(1) as u32;
+            desc.Height = (rect.bottom - rect.top).max(1) as u32;
+            desc.AlphaMode = DXGI_ALPHA_MODE_PREMULTIPLIED;
+            wrap_unit(factory.CreateSwapChainForComposition(
+                d3d.inner().as_raw() as *mut IUnknown,
+                &desc,
+                null_mut(),
+                &mut swap_chain,
+            ))?;
+        }
+        let swap_chain = ComPtr::from_raw(swap_chain);
+        let composition = match options.transparent {
+            true => Some(Composition::new(&dxgi, hwnd, &swap_chain)?),
+            false => None,
+        };
+
+        Ok(DeviceResources {
+            swap_chain,
+            context,
+            target: None,
+            composition,
+            _d3d: d3d,
+            _device: device,
+        })
+    }
+
+    /// Show the frame drawn to the back buffer.
+    fn present(&mut self) -> Result<(), Error> {
+        unsafe { wrap_unit(self.swap_chain.Present(1, 0))? };
+        if let Some(composition) = &self.composition {
+            unsafe { wrap_unit(composition.device.Commit())? };
+        }
+        Ok(())
+    }
+
+    /// Bind the current back buffer to the device context, if needed.
+    fn ensure_target(&mut self, dpi_scale: f32) -> Result<(), Error> {
+        if self.target.is_some() {
+            return Ok(());
+        }
+        let surface = unsafe {
+            let mut ptr: *mut IDXGISurface = null_mut();
+            wrap_unit(self.swap_chain.GetBuffer(
+                0,
+                &IDXGISurface::uuidof(),
+                &mut ptr as *mut _ as *mut _,
+            ))?;
+            ComPtr::from_raw(ptr)
+        };
+        let target = unsafe {
+            if self.composition.is_some() {
+                self.context.create_bitmap_from_dxgi(&surface, dpi_scale)?
+            } else {
+                self.context
+                    .create_opaque_bitmap_from_dxgi(&surface, dpi_scale)?
+            }
+        };
+        // this also picks the text antialiasing for the target
+        self.context.set_target(&target);
+        self.target = Some(target);
+        Ok(())
+    }
+}
+
+impl Composition {
+    /// Show `swap_chain` over the client area of `hwnd`.
+    unsafe fn new(
+        dxgi: &DxgiDevice,
+        hwnd: HWND,
+        swap_chain: &ComPtr<IDXGISwapChain1>,
+    ) -> Result<Self, Error> {
+        let mut device: *mut IDCompositionDevice = null_mut();
+        wrap_unit(DCompositionCreateDevice(
+            dxgi.as_raw(),
+            &IDCompositionDevice::uuidof(),
+            &mut device as *mut _ as *mut _,
+        ))?;
+        let device = ComPtr::from_raw(device);
+
+        let mut target: *mut IDCompositionTarget = null_mut();
+        wrap_unit(device.CreateTargetForHwnd(hwnd, TRUE, &mut target))?;
+        let target = ComPtr::from_raw(target);
+        let mut visual: *mut IDCompositionVisual = null_mut();
+        wrap_unit(device.CreateVisual(&mut visual))?;
+        let visual = ComPtr::from_raw(visual);
+
+        wrap_unit(visual.SetContent(swap_chain.as_raw() as *mut IUnknown))?;
+        wrap_unit(target.SetRoot(visual.as_raw()))?;
+        wrap_unit(device.Commit())?;
+        Ok(Composition {
+            device,
+            _target: target,
+            _visual: visual,
+        })
+    }
+}
@@ -8,30 +8,55 @@
 #![deny(clippy::trivially_copy_pass_by_ref)]
 
 //! The Direct2D backend for the Piet 2D graphics abstraction.
+//!
+//! Text is laid out and drawn by DirectWrite, which can't shift the baseline
+//! of a run or break underlines around glyphs, so
+//! [`TextAttribute::BaselineOffset`] and [`TextAttribute::UnderlineSkipInk`]
+//! are ignored.
+//!
+//! Render targets are sRGB, and brushes and images aren't tagged with an
+//! `ID2D1ColorContext`: colors and pixels given in another [`ColorSpace`]
+//! are converted to sRGB on the CPU, clipping colors outside its gamut, by
+//! the default [`solid_brush_with_color_space`] and
+//! [`make_image_with_color_space`].
+//!
+//! [`TextAttribute::BaselineOffset`]: piet::TextAttribute::BaselineOffset
+//! [`TextAttribute::UnderlineSkipInk`]: piet::TextAttribute::UnderlineSkipInk
+//! [`ColorSpace`]: piet::ColorSpace
+//! [`solid_brush_with_color_space`]: piet::RenderContext::solid_brush_with_color_space
+//! [`make_image_with_color_space`]: piet::RenderContext::make_image_with_color_space
 
 mod conv;
 pub mod d2d;
 pub mod d3d;
 pub mod dwrite;
+pub mod hwnd_target;
 mod text;
 
+use std::any::Any;
 use std::borrow::Cow;
+use std::f64::consts::{FRAC_PI_2, TAU};
 use std::ops::Deref;
 
-use associative_cache::{AssociativeCache, Capacity1024, HashFourWay, RoundRobinReplacement};
+use associative_cache::{
+    AssociativeCache, Capacity1024, Capacity64, HashFourWay, RoundRobinReplacement,
+};
 
 use winapi::um::d2d1::{
-    D2D1_BITMAP_INTERPOLATION_MODE_LINEAR, D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
-    D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES, D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES,
+    D2D1_BITMAP_INTERPOLATION_MODE, D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
+    D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR, D2D1_EXTEND_MODE_MIRROR,
+    D2D1_EXTEND_MODE_WRAP, D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES,
+    D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES, D2D1_SIZE_F,
 };
 use winapi::um::d2d1_1::{D2D1_COMPOSITE_MODE_SOURCE_OVER, D2D1_INTERPOLATION_MODE_LINEAR};
 use winapi::um::dcommon::{D2D1_ALPHA_MODE_IGNORE, D2D1_ALPHA_MODE_PREMULTIPLIED};
 
-use piet::kurbo::{Affine, PathEl, Point, Rect, Shape, Size};
+use piet::kurbo::{self, Affine, PathEl, Point, Rect, Shape, Size};
+use piet::util::premul;
 
 use piet::{
-    Color, Error, FixedGradient, Image, ImageFormat, InterpolationMode, IntoBrush, RenderContext,
-    StrokeStyle,
+    CachedShape, Color, Error, FixedGradient, Image, ImageArgsError, ImageFormat,
+    InterpolationMode, IntoBrush, RenderContext, StrokeStyle, StrokeStyleHandle, TileMode,
 };
 
 use crate::d2d::{wrap_unit, Layer};
@@ -41,7 +66,7 @@ pub use crate::text::{D2DLoadedFonts, D2DText, D2DTextLayout, D2DTextLayoutBuild
 
 use crate::conv::{
     affine_to_matrix3x2f, color_to_colorf, convert_stroke_style, gradient_stop_to_d2d,
-    matrix3x2f_to_affine, rect_to_rectf, rect_to_rectu, to_point2f, to_point2u,
+    matrix3x2f_to_affine, rect_to_rectf, rect_to_rectu, stroke_style_hash, to_point2f, to_point2u,
 };
 use crate::d2d::{Bitmap, Brush, DeviceContext, FillRule, Geometry};
 
@@ -57,7 +82,36 @@ pub struct D2DRenderContext<'a> {
 
     err: Result<(), Error>,
 
+    /// Set once an operation fails because the device was lost.
+    device_lost: bool,
+
     brush_cache: AssociativeCache<u32, Brush, Capacity1024, HashFourWay, RoundRobinReplacement>,
+
+    /// Converted stroke styles, with the style they were converted from.
+    stroke_style_cache: AssociativeCache<
+        StrokeStyleKey,
+        (StrokeStyle, crate::d2d::StrokeStyle),
+        Capacity64,
+        HashFourWay,
+        RoundRobinReplacement,
+    >,
+}
+
+/// Identifies a stroke style converted for a particular width.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct StrokeStyleKey {
+    style: StrokeStyleId,
+    /// The bits of the width, or zero for solid styles, which don't depend
+    /// on it.
+    width: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum StrokeStyleId {
+    /// A style passed directly, by its [`stroke_style_hash`].
+    Hash(u64),
+    /// A style registered with [`RenderContext::register_stroke_style`].
+    Handle(u64),
 }
 
 #[derive(Default)]
@@ -85,7 +139,9 @@ impl<'b, 'a: 'b> D2DRenderContext<'a> {
             layers: vec![],
             ctx_stack: vec![CtxState::default()],
             err: Ok(()),
+            device_lost: false,
             brush_cache: Default::default(),
+            stroke_style_cache: Default::default(),
         }
     }
 
@@ -98,6 +154,98 @@ impl<'b, 'a: 'b> D2DRenderContext<'a> {
         }
     }
 
+    /// Stroke a cached shape with a stroke style built ahead of time by a
+    /// [`D2DResourceBuilder`].
+    ///
+    /// The `width` should match the width the style was built for.
+    pub fn stroke_cached_with_style(
+        &mut self,
+        shape: &CachedShape<Geometry>,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &crate::d2d::StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.rt
+            .draw_geometry(shape.geometry(), &brush, width as f32, Some(style));
+    }
+
+    /// Convert a stroke style for `width`, or reuse an earlier conversion.
+    ///
+    /// Direct2D measures dashes in multiples of the stroke width, so dashed
+    /// styles are converted once for each width they are drawn at.
+    fn stroke_style_for(
+        &mut self,
+        id: StrokeStyleId,
+        style: &StrokeStyle,
+        width: f64,
+    ) -> Result<crate::d2d::StrokeStyle, Error> {
+        let width_bits = if style.dash_pattern.is_empty() {
+            0
+        } else {
+            width.to_bits()
+        };
+        let key = StrokeStyleKey {
+            style: id,
+            width: width_bits,
+        };
+        // different styles can have the same hash
+        if let Some((cached, converted)) = self.stroke_style_cache.get(&key) {
+            if cached == style {
+                return Ok(converted.clone());
+            }
+        }
+        let converted = convert_stroke_style(self.factory, style, width)?;
+        self.stroke_style_cache
+            .insert(key, (style.clone(), converted.clone()));
+        Ok(converted)
+    }
+
+    /// Whether an operation on this context failed because the Direct2D
+    /// device was lost.
+    ///
+    /// Once the device is lost nothing more will be drawn, and the render
+    /// target must be recreated, along with any [`Bitmap`]s, cached shapes
+    /// and brushes created from it. Brushes cached inside this context are
+    /// dropped when the loss is detected.
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost
+    }
+
+    /// End drawing on the render target, as [`D2DDeviceContext::end_draw`].
+    ///
+    /// Direct2D reports most failures only when drawing ends, so this is
+    /// usually where a lost device is first seen, and is recorded for
+    /// [`is_device_lost`].
+    ///
+    /// [`is_device_lost`]: D2DRenderContext::is_device_lost
+    pub fn end_draw(&mut self) -> Result<(), Error> {
+        let result = self.rt.end_draw();
+        self.check(result)
+    }
+
+    fn set_err(&mut self, err: Error) {
+        self.note_device_lost(&err);
+        self.err = Err(err);
+    }
+
+    /// Convert the result of a Direct2D call, noting whether it failed
+    /// because the device was lost.
+    fn check<T>(&mut self, result: Result<T, crate::d2d::Error>) -> Result<T, Error> {
+        result.map_err(|e| {
+            let err = e.into();
+            self.note_device_lost(&err);
+            err
+        })
+    }
+
+    fn note_device_lost(&mut self, err: &Error) {
+        if matches!(err, Error::DeviceLost) {
+            self.device_lost = true;
+            self.brush_cache = Default::default();
+        }
+    }
+
     /// Check whether drawing operations have finished.
     ///
     /// Clients should call this before extracting or presenting the contents of
@@ -148,6 +296,9 @@ fn path_from_shape(
     shape: impl Shape,
     fill_rule: FillRule,
 ) -> Result<Geometry, Error> {
+    if let Some(arc) = piet::util::as_arc(&shape) {
+        return path_from_arc(d2d, is_filled, &arc, fill_rule);
+    }
     let mut path = d2d.create_path_geometry()?;
     let mut sink = path.open()?;
     sink.set_fill_mode(fill_rule);
@@ -183,6 +334,113 @@ fn path_from_shape(
     Ok(path.into())
 }
 
+/// Build a path for an elliptical arc, from Direct2D's own arc segments
+/// rather than Béziers.
+fn path_from_arc(
+    d2d: &D2DFactory,
+    is_filled: bool,
+    arc: &kurbo::Arc,
+    fill_rule: FillRule,
+) -> Result<Geometry, Error> {
+    let rotation = Affine::rotate(arc.x_rotation);
+    let point = |angle: f64| {
+        let (sin, cos) = angle.sin_cos();
+        let p = rotation * Point::new(arc.radii.x * cos, arc.radii.y * sin);
+        to_point2f(arc.center + p.to_vec2())
+    };
+    let radii = D2D1_SIZE_F {
+        width: arc.radii.x as f32,
+        height: arc.radii.y as f32,
+    };
+    // Direct2D's arcs are drawn between two points, so a full ellipse
+    // needs more than one, and each should be less than half the ellipse.
+    let n = (arc.sweep_angle.abs() / FRAC_PI_2).ceil().max(1.0) as usize;
+    let mut path = d2d.create_path_geometry()?;
+    let mut sink = path.open()?;
+    sink.set_fill_mode(fill_rule);
+    sink.begin_figure(point(arc.start_angle), is_filled);
+    for i in 1..=n {
+        let angle = arc.start_angle + arc.sweep_angle * i as f64 / n as f64;
+        sink.add_arc(
+            point(angle),
+            radii,
+            arc.x_rotation.to_degrees() as f32,
+            arc.sweep_angle > 0.0,
+        );
+    }
+    sink.end_figure(arc.sweep_angle.abs() == TAU);
+    sink.close()?;
+    Ok(path.into())
+}
+
+/// Creates device-independent Direct2D resources without a render target.
+///
+/// Geometries and stroke styles only depend on the [`D2DFactory`], so they
+/// can be built ahead of time, on any thread, and then drawn by any
+/// [`D2DRenderContext`] using the same factory. Clone the factory to move it
+/// to another thread.
+///
+/// Text layouts are built with a [`D2DText`]; one can be created on any
+/// thread with [`D2DText::new_with_shared_fonts`] from a clone of the
+/// [`DwriteFactory`], but the resulting layouts must be drawn on that thread.
+///
+/// # Examples
+///
+/// ```no_run
+/// use piet::kurbo::Circle;
+/// use piet_direct2d::{D2DFactory, D2DResourceBuilder};
+///
+/// let factory = D2DFactory::new().unwrap();
+/// let for_worker = factory.clone();
+/// let shape = std::thread::spawn(move || {
+///     let builder = D2DResourceBuilder::new(&for_worker);
+///     builder.prepare_shape(Circle::new((50.0, 50.0), 20.0)).unwrap()
+/// })
+/// .join()
+/// .unwrap();
+/// // `shape` can now be passed to `RenderContext::fill_cached`.
+/// ```
+pub struct D2DResourceBuilder<'a> {
+    factory: &'a D2DFactory,
+}
+
+impl<'a> D2DResourceBuilder<'a> {
+    /// Create a new builder for resources from this factory.
+    pub fn new(factory: &'a D2DFactory) -> Self {
+        D2DResourceBuilder { factory }
+    }
+
+    /// Build the geometry for a shape, for use with the `_cached` drawing
+    /// methods of [`RenderContext`].
+    ///
+    /// This is equivalent to [`RenderContext::prepare_shape`].
+    pub fn prepare_shape(&self, shape: impl Shape) -> Result<CachedShape<Geometry>, Error> {
+        let geom = geometry_from_shape(self.factory, true, &shape, FillRule::NonZero)?;
+        Ok(CachedShape::new(shape.into_path(BEZ_TOLERANCE), geom))
+    }
+
+    /// Build a Direct2D geometry for a shape.
+    ///
+    /// Axis-aligned rectangles, rounded rectangles and circles get their
+    /// dedicated geometry types; anything else becomes a path geometry.
+    pub fn geometry(&self, shape: impl Shape, fill_rule: FillRule) -> Result<Geometry, Error> {
+        geometry_from_shape(self.factory, true, shape, fill_rule)
+    }
+
+    /// Build a Direct2D stroke style, for use with
+    /// [`D2DRenderContext::stroke_cached_with_style`].
+    ///
+    /// Direct2D measures dashes in multiples of the stroke width, so the
+    /// result is only correct for strokes of the given `width`.
+    pub fn stroke_style(
+        &self,
+        style: &StrokeStyle,
+        width: f64,
+    ) -> Result<crate::d2d::StrokeStyle, Error> {
+        convert_stroke_style(self.factory, style, width)
+    }
+}
+
 impl<'a> RenderContext for D2DRenderContext<'a> {
     type Brush = Brush;
 
@@ -192,6 +450,8 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
 
     type Image = Bitmap;
 
+    type CachedShape = CachedShape<Geometry>;
+
     fn status(&mut self) -> Result<(), Error> {
         std::mem::replace(&mut self.err, Ok(()))
     }
@@ -260,11 +520,62 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
                     radiusX: radial.radius as f32,
                     radiusY: radial.radius as f32,
                 };
-                let stops: Vec<_> = radial.stops.iter().map(gradient_stop_to_d2d).collect();
+                // D2D gradients always start from a point; squeezing the
+                // stops outwards is exact when the circles are concentric.
+                let inner = if radial.radius > 0.0 {
+                    (radial.start_radius / radial.radius).clamp(0.0, 1.0) as f32
+                } else {
+                    0.0
+                };
+                let stops: Vec<_> = radial
+                    .stops
+                    .iter()
+                    .map(|stop| {
+                        let mut stop = gradient_stop_to_d2d(stop);
+                        stop.position = inner + stop.position * (1.0 - inner);
+                        stop
+                    })
+                    .collect();
                 let stops = self.rt.create_gradient_stops(&stops)?;
                 let result = self.rt.create_radial_gradient(&props, &stops)?;
                 Ok(result)
             }
+            FixedGradient::Elliptical(elliptical) => {
+                let props = D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES {
+                    center: to_point2f(elliptical.center),
+                    gradientOriginOffset: to_point2f(elliptical.origin_offset),
+                    radiusX: elliptical.radii.x as f32,
+                    radiusY: elliptical.radii.y as f32,
+                };
+                let stops: Vec<_> = elliptical.stops.iter().map(gradient_stop_to_d2d).collect();
+                let stops = self.rt.create_gradient_stops(&stops)?;
+                let result = self.rt.create_radial_gradient(&props, &stops)?;
+                Ok(result)
+            }
+        }
+    }
+
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Brush, Error> {
+        if transform.determinant() == 0.0 {
+            return Err(Error::InvalidInput);
+        }
+        let brush = self.gradient(gradient)?;
+        brush.set_transform(&affine_to_matrix3x2f(transform));
+        Ok(brush)
+    }
+
+    fn brush_with_opacity(&mut self, brush: &Brush, opacity: f64) -> Brush {
+        let opacity = opacity.clamp(0.0, 1.0) as f32;
+        match self.rt.create_faded_brush(brush, opacity) {
+            Ok(brush) => brush,
+            Err(e) => {
+                self.set_err(e.into());
+                brush.clone()
+            }
         }
     }
 
@@ -287,30 +598,79 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         width: f64,
         style: &StrokeStyle,
     ) {
-        let style = convert_stroke_style(self.factory, style, width)
+        let id = StrokeStyleId::Hash(stroke_style_hash(style));
+        let style = self
+            .stroke_style_for(id, style, width)
+            .expect("stroke style conversion failed");
+        self.stroke_impl(shape, brush, width, Some(&style));
+    }
+
+    fn stroke_with_handle(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyleHandle,
+    ) {
+        let id = StrokeStyleId::Handle(style.id());
+        let style = self
+            .stroke_style_for(id, style.style(), width)
             .expect("stroke style conversion failed");
         self.stroke_impl(shape, brush, width, Some(&style));
     }
 
     fn clip(&mut self, shape: impl Shape) {
-        // TODO: set size based on bbox of shape.
-        let layer = match self.rt.create_layer(None) {
-            Ok(layer) => layer,
-            Err(e) => {
-                self.err = Err(e.into());
-                return;
-            }
-        };
-        let geom = match geometry_from_shape(self.factory, true, shape, FillRule::NonZero) {
-            Ok(geom) => geom,
-            Err(e) => {
-                self.err = Err(e);
-                return;
-            }
-        };
-        self.rt.push_layer_mask(&geom, &layer);
-        self.layers.push((geom, layer));
-        self.ctx_stack.last_mut().unwrap().n_layers_pop += 1;
+        match geometry_from_shape(self.factory, true, shape, FillRule::NonZero) {
+            Ok(geom) => self.clip_impl(geom),
+            Err(e) => self.set_err(e),
+        }
+    }
+
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        match geometry_from_shape(self.factory, true, shape, FillRule::EvenOdd) {
+            Ok(geom) => self.clip_impl(geom),
+            Err(e) => self.set_err(e),
+        }
+    }
+
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error> {
+        D2DResourceBuilder::new(self.factory).prepare_shape(shape)
+    }
+
+    fn fill_cached(&mut self, shape: &Self::CachedShape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.rt.fill_geometry(shape.geometry(), &brush, None);
+    }
+
+    fn stroke_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.rt
+            .draw_geometry(shape.geometry(), &brush, width as f32, None);
+    }
+
+    fn stroke_styled_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let id = StrokeStyleId::Hash(stroke_style_hash(style));
+        let style = self
+            .stroke_style_for(id, style, width)
+            .expect("stroke style conversion failed");
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.rt
+            .draw_geometry(shape.geometry(), &brush, width as f32, Some(&style));
+    }
+
+    fn clip_cached(&mut self, shape: &Self::CachedShape) {
+        self.clip_impl(shape.geometry().clone());
     }
 
     fn text(&mut self) -> &mut Self::Text {
@@ -365,6 +725,14 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         self.ctx_stack.last().unwrap().transform
     }
 
+    fn scale_factor(&self) -> f64 {
+        self.rt.get_dpi_scale().0 as f64
+    }
+
+    fn backend_handle(&self) -> Option<&dyn Any> {
+        Some(&*self.rt)
+    }
+
     fn make_image_with_stride(
         &mut self,
         width: usize,
@@ -373,21 +741,17 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
+        piet::util::validate_image_args(width, height, stride, format, buf.len())?;
         // CreateBitmap will fail if we try to make an empty image. To solve this, we change an
         // empty image into 1x1 transparent image. Not ideal, but prevents a crash. TODO find a
         // better solution.
         if width == 0 || height == 0 {
-            return Ok(self.rt.create_empty_bitmap()?);
+            let bitmap = self.rt.create_empty_bitmap();
+            return self.check(bitmap);
         }
-
-        if buf.len()
-            < piet::util::expected_image_buffer_size(
-                format.bytes_per_pixel() * width,
-                height,
-                stride,
-            )
-        {
-            return Err(Error::InvalidInput);
+        // Direct2D takes the size and the pitch of the pixels as `u32`s.
+        if u32::try_from(width * 4).is_err() || u32::try_from(height).is_err() {
+            return Err(ImageArgsError::TooLarge.into());
         }
 
         // TODO: this method _really_ needs error checking, so much can go wrong...
@@ -396,70 +760,40 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
             ImageFormat::RgbaPremul | ImageFormat::RgbaSeparate => D2D1_ALPHA_MODE_PREMULTIPLIED,
             _ => return Err(Error::NotSupported),
         };
-        let buf = match format {
-            ImageFormat::Rgb => {
-                let mut new_buf = vec![255; width * height * 4];
-                for y in 0..height {
-                    for x in 0..width {
-                        let src_offset = y * stride + x * 3;
-                        let dst_offset = (y * width + x) * 4;
-                        new_buf[dst_offset + 0] = buf[src_offset + 0];
-                        new_buf[dst_offset + 1] = buf[src_offset + 1];
-                        new_buf[dst_offset + 2] = buf[src_offset + 2];
-                    }
-                }
-                Cow::from(new_buf)
-            }
-            ImageFormat::RgbaSeparate => {
-                let mut new_buf = vec![255; width * height * 4];
-                // TODO (performance): this would be soooo much faster with SIMD
-                fn premul(x: u8, a: u8) -> u8 {
-                    let y = (x as u16) * (a as u16);
-                    ((y + (y >> 8) + 0x80) >> 8) as u8
-                }
-                for y in 0..height {
-                    for x in 0..width {
-                        let src_offset = y * stride + x * 4;
-                        let dst_offset = (y * width + x) * 4;
-                        let a = buf[src_offset + 3];
-                        new_buf[dst_offset + 0] = premul(buf[src_offset + 0], a);
-                        new_buf[dst_offset + 1] = premul(buf[src_offset + 1], a);
-                        new_buf[dst_offset + 2] = premul(buf[src_offset + 2], a);
-                        new_buf[dst_offset + 3] = a;
-                    }
-                }
-                Cow::from(new_buf)
-            }
-            ImageFormat::RgbaPremul => {
-                if stride == width * format.bytes_per_pixel() {
-                    Cow::from(buf)
-                } else {
-                    Cow::from(piet::util::image_buffer_to_tightly_packed(
-                        buf, width, height, stride, format,
-                    )?)
-                }
-            }
-            ImageFormat::Grayscale => {
-                // it seems like there's no good way to create a 1-channel bitmap
-                // here? I am not alone:
-                // https://stackoverflow.com/questions/44270215/direct2d-fails-when-drawing-a-single-channel-bitmap
-                let mut new_buf = vec![255; width * height * 4];
-                for y in 0..height {
-                    for x in 0..width {
-                        let src_offset = y * stride + x;
-                        let dst_offset = (y * width + x) * 4;
-                        new_buf[dst_offset + 0] = buf[src_offset];
-                        new_buf[dst_offset + 1] = buf[src_offset];
-                        new_buf[dst_offset + 2] = buf[src_offset];
-                    }
-                }
-                Cow::from(new_buf)
-            }
-            // This should be unreachable, we caught it above.
-            _ => return Err(Error::NotSupported),
-        };
-        let bitmap = self.rt.create_bitmap(width, height, &buf, alpha_mode)?;
-        Ok(bitmap)
+        let buf = bitmap_pixels(width, height, stride, buf, format)?;
+        let bitmap = self.rt.create_bitmap(width, height, &buf, alpha_mode);
+        self.check(bitmap)
+    }
+
+    fn update_image(
+        &mut self,
+        image: &mut Self::Image,
+        buf: &[u8],
+        format: ImageFormat,
+        region: Option<Rect>,
+    ) -> Result<(), Error> {
+        if image.empty_image {
+            return Ok(());
+        }
+        let opaque = matches!(format, ImageFormat::Rgb | ImageFormat::Grayscale);
+        if opaque != image.is_opaque() {
+            return Err(Error::InvalidInput);
+        }
+        let size = image.get_pixel_size();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let stride = width * format.bytes_per_pixel();
+        piet::util::validate_image_args(width, height, stride, format, buf.len())?;
+        let region = piet::util::image_region(region, width, height);
+        if region.area() == 0.0 {
+            return Ok(());
+        }
+        let (x0, y0) = (region.x0 as usize, region.y0 as usize);
+        let (region_width, region_height) = (region.width() as usize, region.height() as usize);
+        let buf = &buf[y0 * stride + x0 * format.bytes_per_pixel()..];
+        let pixels = bitmap_pixels(region_width, region_height, stride, buf, format)?;
+        let result =
+            image.copy_from_memory(rect_to_rectu(region), &pixels, region_width as u32 * 4);
+        self.check(result)
     }
 
     #[inline]
@@ -469,7 +803,7 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self.rt, image, None, dst_rect.into(), interp);
+        draw_image(self.rt, image, None, dst_rect.into(), interp, 1.0);
     }
 
     #[inline]
@@ -486,9 +820,55 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
             Some(src_rect.into()),
             dst_rect.into(),
             interp,
+            1.0,
         );
     }
 
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    ) {
+        draw_image(
+            self.rt,
+            image,
+            Some(src_rect.into()),
+            dst_rect.into(),
+            interp,
+            opacity.clamp(0.0, 1.0),
+        );
+    }
+
+    fn draw_image_tiled(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        tile_mode: TileMode,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into().abs();
+        if dst_rect.is_zero_area() || image.empty_image {
+            return;
+        }
+        let extend_mode = match tile_mode {
+            TileMode::Repeat => D2D1_EXTEND_MODE_WRAP,
+            TileMode::Mirror => D2D1_EXTEND_MODE_MIRROR,
+        };
+        let transform = affine_to_matrix3x2f(Affine::translate(dst_rect.origin().to_vec2()));
+        match self.rt.create_bitmap_brush(
+            image,
+            extend_mode,
+            convert_interpolation(interp),
+            &transform,
+        ) {
+            Ok(brush) => self.rt.fill_rect(dst_rect, &brush),
+            Err(e) => self.set_err(e.into()),
+        }
+    }
+
     fn capture_image_area(&mut self, rect: impl Into<Rect>) -> Result<Self::Image, Error> {
         let r = rect.into();
 
@@ -513,11 +893,12 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         // TODO: This transformation is untested with the current test pictures
         let device_origin = affine_transform * device_origin;
 
-        let mut target_bitmap = self.rt.create_blank_bitmap(
+        let target_bitmap = self.rt.create_blank_bitmap(
             device_size.width as usize,
             device_size.height as usize,
             dpi_scale as f32,
-        )?;
+        );
+        let mut target_bitmap = self.check(target_bitmap)?;
 
         let src_rect = Rect::from_origin_size(device_origin, device_size);
 
@@ -568,11 +949,25 @@ impl<'a> D2DRenderContext<'a> {
         } else {
             match path_from_shape(self.factory, true, shape, fill_rule) {
                 Ok(geom) => self.rt.fill_geometry(&geom, &brush, None),
-                Err(e) => self.err = Err(e),
+                Err(e) => self.set_err(e),
             }
         }
     }
 
+    fn clip_impl(&mut self, geom: Geometry) {
+        // TODO: set size based on bbox of shape.
+        let layer = match self.rt.create_layer(None) {
+            Ok(layer) => layer,
+            Err(e) => {
+                self.set_err(e.into());
+                return;
+            }
+        };
+        self.rt.push_layer_mask(&geom, &layer);
+        self.layers.push((geom, layer));
+        self.ctx_stack.last_mut().unwrap().n_layers_pop += 1;
+    }
+
     fn stroke_impl(
         &mut self,
         shape: impl Shape,
@@ -603,7 +998,7 @@ impl<'a> D2DRenderContext<'a> {
         let geom = match path_from_shape(self.factory, false, shape, FillRule::EvenOdd) {
             Ok(geom) => geom,
             Err(e) => {
-                self.err = Err(e);
+                self.set_err(e);
                 return;
             }
         };
@@ -674,25 +1069,98 @@ fn draw_image<'a>(
     src_rect: Option<Rect>,
     dst_rect: Rect,
     interp: InterpolationMode,
+    opacity: f64,
 ) {
     if dst_rect.is_zero_area() || image.empty_image {
         // source or destination are empty
         return;
     }
-    let interp = match interp {
-        InterpolationMode::NearestNeighbor => D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
-        InterpolationMode::Bilinear => D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
-    };
     let src_rect = src_rect.map(rect_to_rectf);
     rt.draw_bitmap(
         image,
         &rect_to_rectf(dst_rect),
-        1.0,
-        interp,
+        opacity as f32,
+        convert_interpolation(interp),
         src_rect.as_ref(),
     );
 }
 
+/// Convert pixels to the tightly packed, premultiplied RGBA that bitmaps are
+/// made from.
+fn bitmap_pixels(
+    width: usize,
+    height: usize,
+    stride: usize,
+    buf: &[u8],
+    format: ImageFormat,
+) -> Result<Cow<[u8]>, Error> {
+    let pixels = match format {
+        ImageFormat::Rgb => {
+            let mut new_buf = vec![255; width * height * 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let src_offset = y * stride + x * 3;
+                    let dst_offset = (y * width + x) * 4;
+                    new_buf[dst_offset + 0] = buf[src_offset + 0];
+                    new_buf[dst_offset + 1] = buf[src_offset + 1];
+                    new_buf[dst_offset + 2] = buf[src_offset + 2];
+                }
+            }
+            Cow::from(new_buf)
+        }
+        ImageFormat::RgbaSeparate => {
+            let mut new_buf = vec![255; width * height * 4];
+            // TODO (performance): this would be soooo much faster with SIMD
+            for y in 0..height {
+                for x in 0..width {
+                    let src_offset = y * stride + x * 4;
+                    let dst_offset = (y * width + x) * 4;
+                    let a = buf[src_offset + 3];
+                    new_buf[dst_offset + 0] = premul(buf[src_offset + 0], a);
+                    new_buf[dst_offset + 1] = premul(buf[src_offset + 1], a);
+                    new_buf[dst_offset + 2] = premul(buf[src_offset + 2], a);
+                    new_buf[dst_offset + 3] = a;
+                }
+            }
+            Cow::from(new_buf)
+        }
+        ImageFormat::RgbaPremul => {
+            if stride == width * format.bytes_per_pixel() {
+                Cow::from(buf)
+            } else {
+                Cow::from(piet::util::image_buffer_to_tightly_packed(
+                    buf, width, height, stride, format,
+                )?)
+            }
+        }
+        ImageFormat::Grayscale => {
+            // it seems like there's no good way to create a 1-channel bitmap
+            // here? I am not alone:
+            // https://stackoverflow.com/questions/44270215/direct2d-fails-when-drawing-a-single-channel-bitmap
+            let mut new_buf = vec![255; width * height * 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let src_offset = y * stride + x;
+                    let dst_offset = (y * width + x) * 4;
+                    new_buf[dst_offset + 0] = buf[src_offset];
+                    new_buf[dst_offset + 1] = buf[src_offset];
+                    new_buf[dst_offset + 2] = buf[src_offset];
+                }
+            }
+            Cow::from(new_buf)
+        }
+        _ => return Err(Error::NotSupported),
+    };
+    Ok(pixels)
+}
+
+fn convert_interpolation(interp: InterpolationMode) -> D2D1_BITMAP_INTERPOLATION_MODE {
+    match interp {
+        InterpolationMode::NearestNeighbor => D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+        InterpolationMode::Bilinear => D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
+    }
+}
+
 impl<'a> IntoBrush<D2DRenderContext<'a>> for Brush {
     fn make_brush<'b>(
         &'b self,
@@ -712,4 +1180,21 @@ impl Image for Bitmap {
             Size::new(inner.width.into(), inner.height.into())
         }
     }
+
+    fn format(&self) -> ImageFormat {
+        if self.is_opaque() {
+            ImageFormat::Rgb
+        } else {
+            ImageFormat::RgbaPremul
+        }
+    }
+
+    fn stride(&self) -> usize {
+        // bitmaps are created with four bytes per pixel, and no padding
+        if self.empty_image {
+            0
+        } else {
+            self.get_pixel_size().width as usize * 4
+        }
+    }
 }
@@ -5,7 +5,7 @@
 
 mod lines;
 
-use std::cell::{Cell, RefCell};
+use std::cell::{Cell, Ref, RefCell};
 use std::convert::TryInto;
 use std::fmt;
 use std::ops::{Range, RangeBounds};
@@ -14,15 +14,20 @@ use std::sync::Arc;
 
 pub use dwrite::DwriteFactory;
 use dwrote::{CustomFontCollectionLoaderImpl, FontCollection, FontFile};
-use winapi::um::d2d1::D2D1_DRAW_TEXT_OPTIONS_NONE;
+use winapi::um::d2d1::{
+    D2D1_DRAW_TEXT_OPTIONS_NONE, D2D1_TEXT_ANTIALIAS_MODE, D2D1_TEXT_ANTIALIAS_MODE_ALIASED,
+    D2D1_TEXT_ANTIALIAS_MODE_CLEARTYPE, D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE,
+};
 use wio::wide::ToWide;
 
-use piet::kurbo::{Insets, Point, Rect, Size};
-use piet::util;
+use piet::kurbo::{Affine, Insets, Point, Rect, Size};
+use piet::util::{self, UnderlineAttributes, UnderlineRun};
 use piet::{
-    Color, Error, FontFamily, HitTestPoint, HitTestPosition, LineMetric, RenderContext, Text,
-    TextAlignment, TextAttribute, TextLayout, TextLayoutBuilder, TextStorage,
+    Affinity, Color, CursorPosition, Error, FontDescription, FontFamily, HitTestPoint,
+    HitTestPosition, LineMetric, RenderContext, Text, TextAlignment, TextAntialiasing,
+    TextAttribute, TextLayout, TextLayoutBuilder, TextStorage, UnderlineStyle,
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::conv;
 use crate::dwrite::{self, TextFormat, Utf16Range};
@@ -32,6 +37,19 @@ use crate::D2DRenderContext;
 pub struct D2DText {
     dwrite: DwriteFactory,
     loaded_fonts: D2DLoadedFonts,
+    formats: TextFormats,
+}
+
+/// The text formats that layouts are created from, made once and shared by
+/// clones of a `D2DText`.
+///
+/// Every layout starts from a format with the default font and size, and
+/// its attributes are then set on ranges of the layout, so the reading
+/// direction is the only thing that differs between formats.
+#[derive(Clone, Default)]
+struct TextFormats {
+    ltr: Rc<RefCell<Option<TextFormat>>>,
+    rtl: Rc<RefCell<Option<TextFormat>>>,
 }
 
 /// The set of loaded fonts, shared between `D2DText` instances.
@@ -72,6 +90,10 @@ pub struct D2DTextLayout {
     inking_insets: Insets,
     // this is in a refcell because we need to mutate it to set colors on first draw
     layout: Rc<RefCell<dwrite::TextLayout>>,
+    max_width: f64,
+    /// The max width last set on `layout`, which is shared by layouts
+    /// created with `with_max_width`.
+    layout_width: Rc<Cell<f64>>,
     // these two are used when the layout is empty, so we can still correctly
     // draw the cursor
     default_line_height: f64,
@@ -80,16 +102,30 @@ pub struct D2DTextLayout {
     // in order to generate the brushes.
     colors: Rc<[(Utf16Range, Color)]>,
     needs_to_set_colors: Cell<bool>,
+    /// The colors last set on `layout`, which is shared by layouts created
+    /// with `with_color_override`.
+    layout_colors: Rc<RefCell<Rc<[(Utf16Range, Color)]>>>,
+    /// Underlines with a style DirectWrite can't draw, which we draw ourselves.
+    underlines: Rc<[UnderlineRun]>,
+    underline_position: f64,
+    underline_thickness: f64,
+    antialiasing: TextAntialiasing,
+    links: util::TextLinks,
 }
 
 pub struct D2DTextLayoutBuilder {
     text: Rc<dyn TextStorage>,
+    dwrite: DwriteFactory,
     layout: Result<dwrite::TextLayout, Error>,
+    max_width: f64,
     len_utf16: usize,
     loaded_fonts: D2DLoadedFonts,
     default_font: FontFamily,
     default_font_size: f64,
     colors: Vec<(Utf16Range, Color)>,
+    underlines: UnderlineAttributes,
+    links: util::TextLinks,
+    antialiasing: TextAntialiasing,
     // just used to assert api is used as expected
     last_range_start_pos: usize,
 }
@@ -106,7 +142,23 @@ impl D2DText {
         D2DText {
             dwrite,
             loaded_fonts: loaded_fonts.unwrap_or_default(),
+            formats: TextFormats::default(),
+        }
+    }
+
+    /// The text format for new layouts, created the first time it is needed.
+    fn text_format(&self, rtl: bool) -> Result<TextFormat, dwrite::Error> {
+        let cached = if rtl {
+            &self.formats.rtl
+        } else {
+            &self.formats.ltr
+        };
+        if let Some(format) = cached.borrow().as_ref() {
+            return Ok(format.clone());
         }
+        let format = TextFormat::new(&self.dwrite, [], util::DEFAULT_FONT_SIZE as f32, rtl)?;
+        *cached.borrow_mut() = Some(format.clone());
+        Ok(format)
     }
 
     #[cfg(test)]
@@ -148,15 +200,21 @@ impl Text for D2DText {
         let width = f32::INFINITY;
         let wide_str = ToWide::to_wide(&text.as_str());
         let is_rtl = util::first_strong_rtl(text.as_str());
-        let layout = TextFormat::new(&self.dwrite, [], util::DEFAULT_FONT_SIZE as f32, is_rtl)
+        let layout = self
+            .text_format(is_rtl)
             .and_then(|format| dwrite::TextLayout::new(&self.dwrite, format, width, &wide_str))
             .map_err(Into::into);
 
         D2DTextLayoutBuilder {
             layout,
             text,
+            dwrite: self.dwrite.clone(),
+            max_width: f64::INFINITY,
             len_utf16: wide_str.len(),
             colors: Vec::new(),
+            underlines: UnderlineAttributes::default(),
+            links: util::TextLinks::default(),
+            antialiasing: TextAntialiasing::default(),
             loaded_fonts: self.loaded_fonts.clone(),
             default_font: FontFamily::default(),
             default_font_size: piet::util::DEFAULT_FONT_SIZE,
@@ -170,6 +228,7 @@ impl TextLayoutBuilder for D2DTextLayoutBuilder {
 
     fn max_width(mut self, width: f64) -> Self {
         let width = width.max(0.0);
+        self.max_width = width;
         let result = match self.layout.as_mut() {
             Ok(layout) => layout.set_max_width(width),
             Err(_) => Ok(()),
@@ -187,6 +246,19 @@ impl TextLayoutBuilder for D2DTextLayoutBuilder {
         self
     }
 
+    fn locale(mut self, locale: &str) -> Self {
+        let locale = locale.to_wide_null();
+        if let Ok(layout) = self.layout.as_mut() {
+            layout.set_locale(Utf16Range::new(0, self.len_utf16), &locale);
+        }
+        self
+    }
+
+    fn antialiasing(mut self, antialiasing: TextAntialiasing) -> Self {
+        self.antialiasing = antialiasing;
+        self
+    }
+
     fn default_attribute(mut self, attribute: impl Into<TextAttribute>) -> Self {
         debug_assert!(
             self.last_range_start_pos == 0,
@@ -219,21 +291,31 @@ impl TextLayoutBuilder for D2DTextLayoutBuilder {
         self
     }
 
-    fn build(self) -> Result<Self::Out, Error> {
-        let (default_line_height, default_baseline) = self.get_default_line_height_and_baseline();
+    fn build(mut self) -> Result<Self::Out, Error> {
+        let metrics = self.get_default_metrics();
+        let underlines = self.resolve_underlines();
         let layout = self.layout?;
 
+        let colors: Rc<[_]> = self.colors.into();
         let mut layout = D2DTextLayout {
             text: self.text,
-            colors: self.colors.into(),
+            colors: colors.clone(),
             needs_to_set_colors: Cell::new(true),
+            layout_colors: Rc::new(RefCell::new(colors)),
             line_metrics: Rc::new([]),
             layout: Rc::new(RefCell::new(layout)),
+            max_width: self.max_width,
+            layout_width: Rc::new(Cell::new(self.max_width)),
             size: Size::ZERO,
             trailing_ws_width: 0.0,
             inking_insets: Insets::ZERO,
-            default_line_height,
-            default_baseline,
+            default_line_height: metrics.line_height,
+            default_baseline: metrics.baseline,
+            underlines: underlines.into(),
+            underline_position: metrics.underline_position,
+            underline_thickness: metrics.underline_thickness,
+            antialiasing: self.antialiasing,
+            links: self.links,
         };
         layout.rebuild_metrics();
         Ok(layout)
@@ -249,20 +331,10 @@ impl fmt::Debug for D2DTextLayoutBuilder {
 impl D2DTextLayoutBuilder {
     /// used for both range and default attributes
     fn add_attribute_shared(&mut self, attr: TextAttribute, range: Option<Range<usize>>) {
+        let range = range.unwrap_or(0..self.text.len());
+        self.underlines.add(range.clone(), &attr);
+        let utf16_range = self.utf16_range(range.clone());
         if let Ok(layout) = self.layout.as_mut() {
-            let utf16_range = match range {
-                Some(range) => {
-                    let start = util::count_utf16(&self.text[..range.start]);
-                    let len = if range.end == self.text.len() {
-                        self.len_utf16
-                    } else {
-                        util::count_utf16(&self.text[range])
-                    };
-                    Utf16Range::new(start, len)
-                }
-                None => Utf16Range::new(0, self.len_utf16),
-            };
-
             match attr {
                 TextAttribute::FontFamily(font) => {
                     let is_custom = self.loaded_fonts.inner.borrow().contains(&font);
@@ -283,11 +355,66 @@ impl D2DTextLayoutBuilder {
                 TextAttribute::Underline(flag) => layout.set_underline(utf16_range, flag),
                 TextAttribute::Strikethrough(flag) => layout.set_strikethrough(utf16_range, flag),
                 TextAttribute::TextColor(color) => self.colors.push((utf16_range, color)),
+                // resolved in `resolve_underlines`
+                TextAttribute::UnderlineColor(_)
+                | TextAttribute::UnderlineStyle(_)
+                | TextAttribute::UnderlineThickness(_) => (),
+                // Not supported: DirectWrite draws underlines straight through
+                // the glyphs, and has no way to shift the baseline of a run.
+                // See the crate docs.
+                TextAttribute::UnderlineSkipInk(_) | TextAttribute::BaselineOffset(_) => (),
+                TextAttribute::FontFeatures(features) => {
+                    layout.set_font_features(&self.dwrite, utf16_range, &features)
+                }
+                TextAttribute::Link(url) => self.links.add(range, &url),
             }
         }
     }
 
-    fn get_default_line_height_and_baseline(&self) -> (f64, f64) {
+    fn utf16_range(&self, range: Range<usize>) -> Utf16Range {
+        let start = util::count_utf16(&self.text[..range.start]);
+        let len = if range.start == 0 && range.end == self.text.len() {
+            self.len_utf16
+        } else {
+            util::count_utf16(&self.text[range])
+        };
+        Utf16Range::new(start, len)
+    }
+
+    /// Remove the underlines DirectWrite can't draw from the layout, and
+    /// return them.
+    ///
+    /// DirectWrite only draws solid underlines, with the font's thickness,
+    /// in the color of the text.
+    fn resolve_underlines(&mut self) -> Vec<UnderlineRun> {
+        let mut drawn = Vec::new();
+        for run in self.underlines.runs(self.text.len()) {
+            let utf16_range = self.utf16_range(run.range.clone());
+            let text_color = self
+                .colors
+                .iter()
+                .rev()
+                .find(|(range, _)| {
+                    range.start <= utf16_range.start
+                        && range.start + range.len >= utf16_range.start + utf16_range.len
+                })
+                .map(|(_, color)| *color)
+                .unwrap_or(Color::BLACK);
+            if run.style == UnderlineStyle::Solid
+                && run.thickness.is_none()
+                && run.color == text_color
+            {
+                continue;
+            }
+            if let Ok(layout) = self.layout.as_mut() {
+                layout.set_underline(utf16_range, false);
+            }
+            drawn.push(run);
+        }
+        drawn
+    }
+
+    fn get_default_metrics(&self) -> DefaultMetrics {
         let family_name = resolve_family_name(&self.default_font);
         let is_custom = self
             .loaded_fonts
@@ -304,7 +431,14 @@ impl D2DTextLayoutBuilder {
         let family = match family {
             Some(family) => family,
             // absolute fallback; use font size as line height
-            None => return (self.default_font_size, self.default_font_size * 0.8),
+            None => {
+                return DefaultMetrics {
+                    line_height: self.default_font_size,
+                    baseline: self.default_font_size * 0.8,
+                    underline_position: self.default_font_size * 0.1,
+                    underline_thickness: self.default_font_size * 0.05,
+                }
+            }
         };
 
         let font = family.get_first_matching_font(
@@ -315,16 +449,26 @@ impl D2DTextLayoutBuilder {
         let metrics = font.metrics().metrics0();
         let ascent = metrics.ascent as f64;
         let vert_metrics = ascent + metrics.descent as f64 + metrics.lineGap as f64;
-        let vert_fraction = vert_metrics / metrics.designUnitsPerEm as f64;
-        let ascent_fraction = ascent / metrics.designUnitsPerEm as f64;
+        let scale = self.default_font_size / metrics.designUnitsPerEm as f64;
 
-        let line_height = self.default_font_size * vert_fraction;
-        let baseline = self.default_font_size * ascent_fraction;
-
-        (line_height, baseline)
+        DefaultMetrics {
+            line_height: vert_metrics * scale,
+            baseline: ascent * scale,
+            underline_position: -(metrics.underlinePosition as f64) * scale,
+            underline_thickness: metrics.underlineThickness as f64 * scale,
+        }
     }
 }
 
+/// Metrics of the default font, used for empty layouts and for the
+/// underlines we draw ourselves.
+struct DefaultMetrics {
+    line_height: f64,
+    baseline: f64,
+    underline_position: f64,
+    underline_thickness: f64,
+}
+
 impl fmt::Debug for D2DTextLayout {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("D2DTextLayout").finish()
@@ -344,6 +488,26 @@ impl TextLayout for D2DTextLayout {
         self.size.to_rect() + self.inking_insets
     }
 
+    fn with_max_width(&self, width: f64) -> Self {
+        let mut layout = self.clone();
+        layout.max_width = width.max(0.0);
+        layout.rebuild_metrics();
+        layout
+    }
+
+    fn with_color_override(&self, range: impl RangeBounds<usize>, color: Color) -> Self {
+        let range = util::resolve_range(range, self.text.len());
+        let start = util::count_utf16(&self.text[..range.start]);
+        let len = util::count_utf16(&self.text[range]);
+        let mut colors = self.colors.to_vec();
+        colors.push((Utf16Range::new(start, len), color));
+
+        let mut layout = self.clone();
+        layout.colors = colors.into();
+        layout.needs_to_set_colors.set(true);
+        layout
+    }
+
     fn text(&self) -> &str {
         &self.text
     }
@@ -355,26 +519,39 @@ impl TextLayout for D2DTextLayout {
     }
 
     fn line_metric(&self, line_number: usize) -> Option<LineMetric> {
-        if line_number == 0 && self.text.is_empty() {
-            Some(LineMetric {
-                baseline: self.default_baseline,
-                height: self.default_line_height,
-                ..Default::default()
-            })
-        } else {
-            self.line_metrics.get(line_number).cloned()
-        }
+        self.line_metrics.get(line_number).cloned()
     }
 
     fn line_count(&self) -> usize {
         self.line_metrics.len()
     }
 
+    fn line_bounds(&self, line_number: usize) -> Option<Rect> {
+        let metric = self.line_metrics.get(line_number)?;
+        let end = metric.end_offset - metric.trailing_whitespace;
+        let x0 = self.hit_test_text_position(metric.start_offset).point.x;
+        // Measure to the trailing edge of the last character, rather than to
+        // the end position, which for a line broken without whitespace is the
+        // start of the next line.
+        let x1 = self.text[metric.start_offset..end]
+            .char_indices()
+            .next_back()
+            .and_then(|(idx, _)| {
+                let idx_16 = util::count_utf16(&self.text[..metric.start_offset + idx]);
+                let hit = self
+                    .dwrite_layout()
+                    .hit_test_text_position(idx_16.try_into().ok()?, true)?;
+                Some(hit.point_x as f64)
+            })
+            .unwrap_or(x0);
+        let y1 = metric.y_offset + metric.height;
+        Some(Rect::new(x0.min(x1), metric.y_offset, x0.max(x1), y1))
+    }
+
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
         // lossy from f64 to f32, but shouldn't have too much impact
         let htp = self
-            .layout
-            .borrow()
+            .dwrite_layout()
             .hit_test_point(point.x as f32, point.y as f32);
 
         // Round up to next grapheme cluster boundary if DirectWrite
@@ -389,7 +566,12 @@ impl TextLayout for D2DTextLayout {
         let text_position =
             util::count_until_utf16(&self.text, text_position_16).unwrap_or(self.text.len());
 
-        HitTestPoint::new(text_position, htp.is_inside)
+        let affinity = if htp.is_trailing_hit {
+            Affinity::Upstream
+        } else {
+            Affinity::Downstream
+        };
+        HitTestPoint::new(text_position, htp.is_inside).with_affinity(affinity)
     }
 
     // Can panic if text position is not at a code point boundary, or if it's out of bounds.
@@ -412,8 +594,7 @@ impl TextLayout for D2DTextLayout {
         let idx_16: u32 = idx_16.try_into().unwrap();
 
         let mut hit_point = self
-            .layout
-            .borrow()
+            .dwrite_layout()
             .hit_test_text_position(idx_16, trailing)
             .map(|hit| Point::new(hit.point_x as f64, hit.point_y as f64))
             // if DWrite fails we just return 0, 0
@@ -424,14 +605,115 @@ impl TextLayout for D2DTextLayout {
         }
         HitTestPosition::new(hit_point, line)
     }
+
+    // The advance of each cluster comes from DirectWrite's cluster metrics,
+    // so positions on a left-to-right line are found by adding these up from
+    // the start of the line, which is hit tested once. Lines with
+    // right-to-left text, and positions inside a cluster, are hit tested.
+    fn cursor_positions(&self) -> Vec<CursorPosition> {
+        // the utf-8 range, advance and direction of each cluster
+        let mut clusters = Vec::new();
+        let mut chars = self.text.char_indices();
+        let mut end = 0;
+        for cluster in self.dwrite_layout().get_cluster_metrics() {
+            let start = end;
+            let mut len_16 = cluster.length as usize;
+            while len_16 > 0 {
+                let Some((idx, c)) = chars.next() else {
+                    break;
+                };
+                end = idx + c.len_utf8();
+                len_16 = len_16.saturating_sub(c.len_utf16());
+            }
+            let is_rtl = cluster.isRightToLeft() != 0;
+            clusters.push((start..end, cluster.width as f64, is_rtl));
+        }
+
+        let mut result = Vec::new();
+        let mut next_cluster = 0;
+        for (line, metric) in self.line_metrics.iter().enumerate() {
+            let first_cluster = next_cluster;
+            while clusters
+                .get(next_cluster)
+                .is_some_and(|(range, _, _)| range.start < metric.end_offset)
+            {
+                next_cluster += 1;
+            }
+            let line_clusters = &clusters[first_cluster..next_cluster];
+            let has_rtl = line_clusters.iter().any(|&(_, _, is_rtl)| is_rtl);
+
+            let mut x = self.hit_test_text_position(metric.start_offset).point.x;
+            let mut summed_to = metric.start_offset;
+            let mut line_clusters = line_clusters.iter();
+            let is_last = line + 1 == self.line_metrics.len();
+            let boundaries = self.text[metric.range()]
+                .grapheme_indices(true)
+                .map(|(idx, _)| metric.start_offset + idx)
+                .chain(is_last.then_some(metric.end_offset));
+            for idx in boundaries {
+                while summed_to < idx {
+                    let Some((range, width, _)) = line_clusters.next() else {
+                        break;
+                    };
+                    x += width;
+                    summed_to = range.end;
+                }
+                let pos_x = if has_rtl || summed_to != idx {
+                    self.hit_test_text_position(idx).point.x
+                } else {
+                    x
+                };
+                result.push(CursorPosition::new(idx, line, pos_x));
+            }
+        }
+        result
+    }
+
+    fn resolved_fonts(&self) -> Vec<(Range<usize>, FontDescription)> {
+        let text = self.text.as_str();
+        let wide: Vec<u16> = text.encode_utf16().collect();
+        let is_rtl = util::first_strong_rtl(text);
+        let fonts = dwrite::DwriteFactory::new()
+            .and_then(|factory| self.dwrite_layout().resolved_fonts(&factory, &wide, is_rtl))
+            // if DWrite fails we can't tell which fonts are used
+            .unwrap_or_default();
+        let utf8_offset = |utf16| util::count_until_utf16(text, utf16).unwrap_or(text.len());
+        let fonts = fonts
+            .into_iter()
+            .map(|(range, font)| {
+                let start = utf8_offset(range.start);
+                (start..utf8_offset(range.start + range.len), font)
+            })
+            .collect();
+        util::merge_font_runs(fonts)
+    }
+
+    fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.links.links()
+    }
 }
 
 impl D2DTextLayout {
+    /// The DirectWrite layout, wrapped to this layout's width.
+    ///
+    /// Layouts created with `with_max_width` share their DirectWrite layout,
+    /// so each one sets its own width before using it.
+    fn dwrite_layout(&self) -> Ref<'_, dwrite::TextLayout> {
+        if self.layout_width.get().to_bits() != self.max_width.to_bits() {
+            // this can only fail for invalid widths, which `max_width` excludes
+            let _ = self.layout.borrow_mut().set_max_width(self.max_width);
+            self.layout_width.set(self.max_width);
+        }
+        self.layout.borrow()
+    }
+
     // must be called after build and after updating the width
     fn rebuild_metrics(&mut self) {
-        let line_metrics = lines::fetch_line_metrics(&self.text, &self.layout.borrow());
-        let text_metrics = self.layout.borrow().get_metrics();
-        let overhang = self.layout.borrow().get_overhang_metrics();
+        let layout = self.dwrite_layout();
+        let line_metrics = lines::fetch_line_metrics(&self.text, &layout);
+        let text_metrics = layout.get_metrics();
+        let overhang = layout.get_overhang_metrics();
+        drop(layout);
 
         let size = Size::new(text_metrics.width as f64, text_metrics.height as f64);
         let overhang_width = text_metrics.layoutWidth as f64 + overhang.x1;
@@ -446,10 +728,18 @@ impl D2DTextLayout {
 
         self.size = size;
         self.trailing_ws_width = text_metrics.widthIncludingTrailingWhitespace as f64;
-        if self.text.is_empty() {
+        // DirectWrite measures an empty layout with the text format's font,
+        // since there is no text for our attributes to apply to.
+        self.line_metrics = if self.text.is_empty() {
             self.size.height = self.default_line_height;
-        }
-        self.line_metrics = line_metrics.into();
+            Rc::new([LineMetric {
+                baseline: self.default_baseline,
+                height: self.default_line_height,
+                ..Default::default()
+            }])
+        } else {
+            line_metrics.into()
+        };
         self.inking_insets = inking_insets;
     }
 
@@ -459,13 +749,41 @@ impl D2DTextLayout {
             let pos = conv::to_point2f(pos);
             let black_brush = ctx.solid_brush(Color::BLACK);
             let text_options = D2D1_DRAW_TEXT_OPTIONS_NONE;
+            let mode = text_antialias_mode(self.antialiasing);
+            let old_mode = mode.map(|mode| {
+                let old_mode = ctx.rt.text_antialias_mode();
+                ctx.rt.set_text_antialias_mode(mode);
+                old_mode
+            });
             ctx.rt
-                .draw_text_layout(pos, &self.layout.borrow(), &black_brush, text_options);
+                .draw_text_layout(pos, &self.dwrite_layout(), &black_brush, text_options);
+            if let Some(old_mode) = old_mode {
+                ctx.rt.set_text_antialias_mode(old_mode);
+            }
+        }
+        for run in self.underlines.iter() {
+            let thickness = run.thickness.unwrap_or(self.underline_thickness);
+            let path = util::underline_path(self, run, self.underline_position, thickness);
+            ctx.stroke(
+                Affine::translate(pos.to_vec2()) * path,
+                &run.color,
+                thickness,
+            );
         }
     }
 
     fn resolve_colors_if_needed(&self, ctx: &mut D2DRenderContext) {
-        if self.needs_to_set_colors.replace(false) {
+        let colors_changed = !Rc::ptr_eq(&self.layout_colors.borrow(), &self.colors);
+        if self.needs_to_set_colors.replace(false) || colors_changed {
+            if colors_changed {
+                // clear any colors set by a layout sharing our DirectWrite layout
+                let len = util::count_utf16(&self.text);
+                let brush = ctx.solid_brush(Color::BLACK);
+                self.layout
+                    .borrow_mut()
+                    .set_foreground_brush(Utf16Range::new(0, len), brush);
+                self.layout_colors.replace(self.colors.clone());
+            }
             for (range, color) in self.colors.as_ref() {
                 let brush = ctx.solid_brush(*color);
                 self.layout.borrow_mut().set_foreground_brush(*range, brush)
@@ -474,6 +792,16 @@ impl D2DTextLayout {
     }
 }
 
+/// The mode to draw with, or `None` to leave the render target's mode alone.
+fn text_antialias_mode(antialiasing: TextAntialiasing) -> Option<D2D1_TEXT_ANTIALIAS_MODE> {
+    match antialiasing {
+        TextAntialiasing::Default => None,
+        TextAntialiasing::Grayscale => Some(D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE),
+        TextAntialiasing::Subpixel => Some(D2D1_TEXT_ANTIALIAS_MODE_CLEARTYPE),
+        TextAntialiasing::Aliased => Some(D2D1_TEXT_ANTIALIAS_MODE_ALIASED),
+    }
+}
+
 //  this is not especially robust, but all of these are preinstalled on win 7+
 fn resolve_family_name(family: &FontFamily) -> &str {
     match family {
@@ -503,6 +831,8 @@ impl LoadedFontsInner {
         let fam_name = FontFamily::new_unchecked(first_fam_name);
         self.files.push(font_file);
         self.names.push(fam_name.clone());
+        // rebuild the collection with the new font next time it's needed
+        self.collection = None;
         Ok(fam_name)
     }
 
@@ -534,6 +864,7 @@ impl LoadedFontsInner {
 #[cfg(test)]
 mod test {
     use super::*;
+    use piet::FontWeight;
 
     macro_rules! assert_close {
         ($val:expr, $target:expr, $tolerance:expr) => {{
@@ -588,6 +919,35 @@ mod test {
         assert_close!(line.height, 14.0, 3.0);
     }
 
+    #[test]
+    fn load_font_collection_selects_weights() {
+        let mut text = D2DText::new_for_test();
+        let regular = include_bytes!("../../piet/snapshots/resources/Anaheim-Regular.ttf");
+        let bold = include_bytes!("../../piet/snapshots/resources/Anaheim-Bold.ttf");
+        let mono = include_bytes!("../../piet/snapshots/resources/Inconsolata-variable.ttf");
+
+        let family = text.load_font_collection(&[regular, bold]).unwrap();
+        assert_eq!(family.name(), "Anaheim");
+        let mut width = |weight: FontWeight| {
+            text.new_text_layout("Anaheim")
+                .font(family.clone(), 16.0)
+                .default_attribute(weight)
+                .build()
+                .unwrap()
+                .size()
+                .width
+        };
+        assert_ne!(width(FontWeight::REGULAR), width(FontWeight::BOLD));
+
+        let mixed = text.load_font_collection(&[regular, mono]);
+        assert!(matches!(mixed, Err(Error::InvalidInput)));
+    }
+
+    #[test]
+    fn text_conformance() {
+        piet::conformance::check_all(&mut D2DText::new_for_test());
+    }
+
     #[test]
     fn newline_text() {
         let layout = D2DText::new_for_test()
@@ -599,6 +959,23 @@ mod test {
         assert_eq!(layout.line_text(1), Some("B"));
     }
 
+    #[test]
+    fn resolved_fonts_include_fallback() {
+        let text = "hi \u{1F600}";
+        let layout = D2DText::new_for_test()
+            .new_text_layout(text)
+            .font(FontFamily::new_unchecked("Segoe UI"), 14.0)
+            .build()
+            .unwrap();
+        let fonts = layout.resolved_fonts();
+        assert_eq!(fonts.len(), 2);
+        assert_eq!(fonts[0].0, 0..3);
+        assert_eq!(fonts[0].1.family.name(), "Segoe UI");
+        assert_eq!(fonts[0].1.size, 14.0);
+        assert_eq!(fonts[1].0, 3..text.len());
+        assert_eq!(fonts[1].1.family.name(), "Segoe UI Emoji");
+    }
+
     #[test]
     fn test_hit_test_text_position_basic() {
         let mut text_layout = D2DText::new_for_test();
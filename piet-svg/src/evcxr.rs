@@ -10,16 +10,83 @@
 //! [Jupyter Notebook]: https://jupyter-notebook.readthedocs.io/en/stable/notebook.html
 
 use piet::{kurbo::Size, RenderContext};
+use svg::node::Node;
 
 impl evcxr_runtime::Display for crate::RenderContext {
     fn evcxr_display(&self) {
-        evcxr_runtime::mime_type("text/html").text(format!(
-            r#"<div style="display:flex;justify-content:center;">{}</div>"#,
-            self.display()
-        ))
+        display_html(self.display())
     }
 }
 
+/// A finished drawing, shown at a scale.
+struct ScaledSvg {
+    ctx: crate::RenderContext,
+    scale: f64,
+}
+
+impl evcxr_runtime::Display for ScaledSvg {
+    fn evcxr_display(&self) {
+        let size = self.ctx.document_size() * self.scale;
+        let mut doc = self.ctx.doc.clone();
+        doc.assign(
+            "style",
+            format!("width:{}px;height:{}px;", size.width, size.height),
+        );
+        display_html(&doc)
+    }
+}
+
+/// A finished drawing, rasterized at a scale.
+#[cfg(feature = "evcxr_png")]
+struct Png {
+    ctx: crate::RenderContext,
+    scale: f64,
+}
+
+#[cfg(feature = "evcxr_png")]
+impl evcxr_runtime::Display for Png {
+    fn evcxr_display(&self) {
+        match rasterize(&self.ctx, self.scale) {
+            Ok(png) => evcxr_runtime::mime_type("image/png").bytes(&png),
+            Err(e) => evcxr_runtime::mime_type("text/plain")
+                .text(format!("Failed to rasterize the SVG: {e}")),
+        }
+    }
+}
+
+fn display_html(svg: impl std::fmt::Display) {
+    evcxr_runtime::mime_type("text/html").text(format!(
+        r#"<div style="display:flex;justify-content:center;">{}</div>"#,
+        svg
+    ))
+}
+
+/// Render the SVG of a finished context to PNG data.
+#[cfg(feature = "evcxr_png")]
+fn rasterize(
+    ctx: &crate::RenderContext,
+    scale: f64,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(&ctx.display().to_string(), &options)?;
+    let size = (ctx.document_size() * scale).ceil();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width as u32, size.height as u32)
+        .ok_or("the image is empty")?;
+    let transform = tiny_skia::Transform::from_scale(scale as f32, scale as f32);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+    Ok(pixmap.encode_png()?)
+}
+
+fn draw(size: Size, f: impl FnOnce(&mut crate::RenderContext)) -> crate::RenderContext {
+    let mut ctx = crate::RenderContext::new(size);
+    f(&mut ctx);
+    ctx.finish().unwrap();
+    ctx
+}
+
 /// Runs the function `f`, and displays the resulting `SVG`.
 ///
 /// For use within `evcxr_jupyter`.
@@ -27,8 +94,37 @@ pub fn draw_evcxr(
     size: Size,
     f: impl FnOnce(&mut crate::RenderContext),
 ) -> impl evcxr_runtime::Display {
-    let mut ctx = crate::RenderContext::new(size);
-    f(&mut ctx);
-    ctx.finish().unwrap();
-    ctx
+    draw_evcxr_scaled(size, 1.0, f)
+}
+
+/// Runs the function `f`, and displays the resulting `SVG` at `scale` times
+/// `size`.
+///
+/// `f` draws in a space of `size`, as with [`draw_evcxr`].
+pub fn draw_evcxr_scaled(
+    size: Size,
+    scale: f64,
+    f: impl FnOnce(&mut crate::RenderContext),
+) -> impl evcxr_runtime::Display {
+    ScaledSvg {
+        ctx: draw(size, f),
+        scale,
+    }
+}
+
+/// Runs the function `f`, and displays the result as a PNG of `scale` times
+/// `size`.
+///
+/// This is for notebook frontends that don't show SVG well. Text is drawn
+/// with the system's fonts.
+#[cfg(feature = "evcxr_png")]
+pub fn draw_evcxr_png(
+    size: Size,
+    scale: f64,
+    f: impl FnOnce(&mut crate::RenderContext),
+) -> impl evcxr_runtime::Display {
+    Png {
+        ctx: draw(size, f),
+        scale,
+    }
 }
@@ -15,8 +15,9 @@ use std::{borrow::Cow, fmt, fmt::Write, io, mem};
 use image::{DynamicImage, GenericImageView, ImageBuffer};
 use piet::kurbo::{Affine, Point, Rect, Shape, Size};
 use piet::{
-    Color, Error, FixedGradient, FontStyle, Image, ImageFormat, InterpolationMode, IntoBrush,
-    LineCap, LineJoin, StrokeStyle, TextAlignment, TextLayout as _,
+    CachedShape, Color, Error, FixedGradient, FontStyle, Image, ImageFormat, InterpolationMode,
+    IntoBrush, LineCap, LineJoin, StrokeStyle, TextAlignment, TextAntialiasing, TextLayout as _,
+    TileMode, UnderlineStyle,
 };
 use svg::node::Node;
 
@@ -24,8 +25,10 @@ pub use crate::text::{Text, TextLayout};
 // re-export piet
 pub use piet;
 
+#[cfg(feature = "evcxr_png")]
+pub use evcxr::draw_evcxr_png;
 #[cfg(feature = "evcxr")]
-pub use evcxr::draw_evcxr;
+pub use evcxr::{draw_evcxr, draw_evcxr_scaled};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -37,6 +40,82 @@ pub struct RenderContext {
     doc: svg::Document,
     next_id: u64,
     text: Text,
+    /// Where elements are written as they are drawn, in streaming mode.
+    stream: Option<Sink>,
+    /// The first error from writing to the stream.
+    stream_err: Option<io::Error>,
+    /// The pages finished by [`next_page`], and where the current page
+    /// starts in the document; see [`Pages`].
+    ///
+    /// [`next_page`]: RenderContext::next_page
+    pages: Pages,
+    /// The metadata for the next element drawn; see [`Annotation`].
+    annotation: Annotation,
+}
+
+/// Metadata set with [`set_next_element_id`] and [`annotate`], to be
+/// attached to the next element drawn.
+///
+/// [`set_next_element_id`]: RenderContext::set_next_element_id
+/// [`annotate`]: RenderContext::annotate
+#[derive(Debug, Clone, Default)]
+struct Annotation {
+    id: Option<String>,
+    title: Option<String>,
+    desc: Option<String>,
+}
+
+impl Annotation {
+    /// Attach the metadata to `node`, which has no children yet, so that the
+    /// title comes first as accessibility tools expect.
+    fn apply_to(&self, node: &mut impl Node) {
+        if let Some(id) = &self.id {
+            node.assign("id", id.as_str());
+        }
+        if let Some(title) = &self.title {
+            node.append(svg::node::element::Title::new(title.as_str()));
+        }
+        if let Some(desc) = &self.desc {
+            node.append(
+                svg::node::element::Description::new().add(svg::node::Text::new(desc.as_str())),
+            );
+        }
+    }
+
+    /// Like [`apply_to`], for a shape or image, which is given the `img` role
+    /// when titled. Text is left with its own role, so its content is still
+    /// read out.
+    ///
+    /// [`apply_to`]: Annotation::apply_to
+    fn apply_to_graphic(&self, node: &mut impl Node) {
+        if self.title.is_some() {
+            node.assign("role", "img");
+        }
+        self.apply_to(node);
+    }
+}
+
+/// The layout of a document with more than one page.
+#[derive(Default)]
+struct Pages {
+    /// The number of finished pages, which are the first children of the
+    /// document.
+    count: usize,
+    /// The top of the current page in the document.
+    top: f64,
+    /// The size of the document, with the finished pages.
+    size: Size,
+    /// Whether [`finish`] has already wrapped the current page.
+    ///
+    /// [`finish`]: piet::RenderContext::finish
+    closed: bool,
+}
+
+/// The writer of a streaming [`RenderContext`].
+enum Sink {
+    Plain(Box<dyn io::Write>),
+    #[cfg(feature = "svgz")]
+    Gzip(flate2::write::GzEncoder<Box<dyn io::Write>>),
 }
 
 impl RenderContext {
@@ -49,16 +128,156 @@ impl RenderContext {
             doc: svg::Document::new(),
             next_id: 0,
             text: Text::new(),
+            stream: None,
+            stream_err: None,
+            pages: Pages::default(),
+            annotation: Annotation::default(),
         }
     }
 
+    /// Construct a `RenderContext` that writes elements to `writer` as they
+    /// are drawn, rather than keeping the whole document in memory.
+    ///
+    /// The document is complete once [`finish`] has been called, and nothing
+    /// should be drawn after that. Errors from `writer` are returned by
+    /// [`status`] and [`finish`].
+    ///
+    /// Because elements are written out as they are drawn, [`write`] and
+    /// [`display`] are of no use in this mode.
+    ///
+    /// [`finish`]: piet::RenderContext::finish
+    /// [`status`]: piet::RenderContext::status
+    /// [`write`]: RenderContext::write
+    /// [`display`]: RenderContext::display
+    pub fn new_streaming(size: Size, writer: impl io::Write + 'static) -> Self {
+        Self::with_sink(size, Sink::Plain(Box::new(writer)))
+    }
+
+    /// Like [`new_streaming`], but compresses the output with gzip, as an
+    /// `.svgz` file.
+    ///
+    /// [`new_streaming`]: RenderContext::new_streaming
+    #[cfg(feature = "svgz")]
+    pub fn new_streaming_svgz(size: Size, writer: impl io::Write + 'static) -> Self {
+        let encoder = flate2::write::GzEncoder::new(
+            Box::new(writer) as Box<dyn io::Write>,
+            flate2::Compression::default(),
+        );
+        Self::with_sink(size, Sink::Gzip(encoder))
+    }
+
+    fn with_sink(size: Size, mut sink: Sink) -> Self {
+        let mut ctx = Self::new(size);
+        // the root element is written by hand, as it stays open until `finish`
+        ctx.stream_err = writeln!(
+            sink.writer(),
+            r#"<svg style="width:{w}px;height:{h}px;" viewBox="0 0 {w} {h}" xmlns="http://www.w3.org/2000/svg">"#,
+            w = size.width,
+            h = size.height,
+        )
+        .err();
+        ctx.stream = Some(sink);
+        ctx
+    }
+
     /// The size that the SVG will render at.
     ///
-    /// The size is used to set the view box for the svg.
+    /// The size is used to set the view box for the svg. In a document with
+    /// several pages, this is the size of the current page.
     pub fn size(&self) -> Size {
         self.size
     }
 
+    /// Finish the current page, and start a new one of the given size.
+    ///
+    /// Each page of the document is a nested `<svg>` element with its own
+    /// view box, and the pages are placed one below the other, so that the
+    /// document shows them all and each can be taken out on its own. The new
+    /// page starts with no transform or clip, as a new `RenderContext`
+    /// would, and the saved states of the old one are dropped.
+    ///
+    /// Pages are not supported in streaming mode, where the size of the
+    /// document is written before anything is drawn, and this returns
+    /// [`Error::NotSupported`].
+    pub fn next_page(&mut self, size: Size) -> Result<()> {
+        if self.stream.is_some() {
+            return Err(Error::NotSupported);
+        }
+        if !self.pages.closed {
+            self.end_page();
+        }
+        self.pages.closed = false;
+        self.size = size;
+        self.stack.clear();
+        self.state = State::default();
+        Ok(())
+    }
+
+    /// The number of pages, including the current one.
+    pub fn page_count(&self) -> usize {
+        self.pages.count + usize::from(!self.pages.closed)
+    }
+
+    /// The size of the whole document: that of the pages finished so far,
+    /// one below the other, or of the only page.
+    ///
+    /// Once [`finish`] has been called, every page is included.
+    ///
+    /// [`finish`]: piet::RenderContext::finish
+    fn document_size(&self) -> Size {
+        if self.pages.count > 0 {
+            self.pages.size
+        } else {
+            self.size
+        }
+    }
+
+    /// Wrap the elements of the current page in its own `<svg>` element.
+    fn end_page(&mut self) {
+        let Size { width, height } = self.size;
+        let mut page = svg::node::element::SVG::new()
+            .set("x", 0)
+            .set("y", self.pages.top)
+            .set("width", width)
+            .set("height", height)
+            .set("viewBox", (0, 0, width, height));
+        if let Some(children) = self.doc.get_children_mut() {
+            for node in children.drain(self.pages.count..) {
+                page.append(node);
+            }
+        }
+        self.doc.append(page);
+
+        let pages = &mut self.pages;
+        pages.count += 1;
+        pages.top += height;
+        pages.size = Size::new(pages.size.width.max(width), pages.top);
+    }
+
+    /// Set the `id` attribute of the next element drawn.
+    ///
+    /// This lets stylesheets, scripts and other tools find the element in
+    /// the document. The id applies to the element of the next fill, stroke,
+    /// text or image, and is then cleared. Ids starting with `piet-` are
+    /// used for the generated gradients and clip paths, so avoid them.
+    pub fn set_next_element_id(&mut self, id: &str) {
+        self.annotation.id = Some(id.to_owned());
+    }
+
+    /// Attach a title, and optionally a description, to the next element
+    /// drawn.
+    ///
+    /// These are written as `<title>` and `<desc>` children of the element,
+    /// which screen readers announce and browsers show as a tooltip. Shapes
+    /// and images are also given the `img` role. As with [`set_next_element_id`], the
+    /// annotation applies only to the next element.
+    ///
+    /// [`set_next_element_id`]: RenderContext::set_next_element_id
+    pub fn annotate(&mut self, title: &str, desc: Option<&str>) {
+        self.annotation.title = Some(title.to_owned());
+        self.annotation.desc = desc.map(str::to_owned);
+    }
+
     /// Write graphics rendered so far to an `std::io::Write` impl, such as `std::fs::File`
     ///
     /// Additional rendering can be done afterwards.
@@ -66,61 +285,76 @@ impl RenderContext {
         svg::write(writer, &self.doc)
     }
 
+    /// Write graphics rendered so far, compressed with gzip as an `.svgz`
+    /// file.
+    ///
+    /// Additional rendering can be done afterwards.
+    #[cfg(feature = "svgz")]
+    pub fn write_svgz(&self, writer: impl io::Write) -> io::Result<()> {
+        let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        self.write(&mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
     /// Returns an object that can write the svg somewhere.
     pub fn display(&self) -> &impl fmt::Display {
         &self.doc
     }
 
-    fn new_id(&mut self) -> Id {
-        let x = Id(self.next_id);
-        self.next_id += 1;
-        x
+    /// In streaming mode, write out the elements drawn since the last call.
+    fn flush_stream(&mut self) {
+        let (Some(sink), Some(children)) = (&mut self.stream, self.doc.get_children_mut()) else {
+            return;
+        };
+        for node in children.drain(..) {
+            if let Err(e) = writeln!(sink.writer(), "{node}") {
+                self.stream_err.get_or_insert(e);
+            }
+        }
     }
-}
-
-impl piet::RenderContext for RenderContext {
-    type Brush = Brush;
-
-    type Text = Text;
-    type TextLayout = TextLayout;
-
-    type Image = SvgImage;
 
-    fn status(&mut self) -> Result<()> {
-        Ok(())
+    fn take_stream_err(&mut self) -> Result<()> {
+        match self.stream_err.take() {
+            Some(e) => Err(Error::BackendError(Box::new(e))),
+            None => Ok(()),
+        }
     }
 
-    fn clear(&mut self, rect: impl Into<Option<Rect>>, color: Color) {
-        let rect = rect.into();
-        let mut rect = match rect {
-            Some(rect) => svg::node::element::Rectangle::new()
-                .set("width", rect.width())
-                .set("height", rect.height())
-                .set("x", rect.x0)
-                .set("y", rect.y0),
-            None => svg::node::element::Rectangle::new()
-                .set("width", "100%")
-                .set("height", "100%"),
-        }
-        .set("fill", fmt_color(color))
-        .set("fill-opacity", fmt_opacity(color));
-        //FIXME: I don't think we should be clipping, here?
-        if let Some(id) = self.state.clip {
-            rect.assign("clip-path", format!("url(#{})", id.to_string()));
-        }
-        self.doc.append(rect);
+    fn new_id(&mut self) -> Id {
+        let x = Id(self.next_id);
+        self.next_id += 1;
+        x
     }
 
-    fn solid_brush(&mut self, color: Color) -> Brush {
-        Brush {
-            kind: BrushKind::Solid(color),
+    /// Clip to `shape`, with the given `clip-rule`, or the default nonzero
+    /// rule if it is `None`.
+    fn clip_impl(&mut self, shape: impl Shape, rule: Option<&str>) {
+        let id = self.new_id();
+        let mut clip = svg::node::element::ClipPath::new().set("id", id);
+        // An enclosing clip is applied to the clip path as a whole, since a
+        // clip path can't contain the group `append_clipped` would add.
+        if let Some(outer) = self.state.clip {
+            clip.assign("clip-path", format!("url(#{})", outer.to_string()));
         }
+        add_shape(
+            &mut clip,
+            shape,
+            &Attrs {
+                xf: self.state.xf,
+                clip_rule: rule,
+                ..Attrs::default()
+            },
+        );
+        self.doc.append(clip);
+        self.flush_stream();
+        self.state.clip = Some(id);
     }
 
-    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Brush> {
-        let id = self.new_id();
-        match gradient.into() {
+    fn add_gradient(&mut self, gradient: FixedGradient, transform: Affine) -> Brush {
+        let id = match gradient {
             FixedGradient::Linear(x) => {
+                let id = self.new_id();
                 let mut gradient = svg::node::element::LinearGradient::new()
                     .set("gradientUnits", "userSpaceOnUse")
                     .set("id", id)
@@ -128,6 +362,9 @@ impl piet::RenderContext for RenderContext {
                     .set("y1", x.start.y)
                     .set("x2", x.end.x)
                     .set("y2", x.end.y);
+                if transform != Affine::IDENTITY {
+                    gradient.assign("gradientTransform", xf_val(&transform));
+                }
                 for stop in x.stops {
                     gradient.append(
                         svg::node::element::Stop::new()
@@ -137,8 +374,10 @@ impl piet::RenderContext for RenderContext {
                     );
                 }
                 self.doc.append(gradient);
+                id
             }
             FixedGradient::Radial(x) => {
+                let id = self.new_id();
                 let mut gradient = svg::node::element::RadialGradient::new()
                     .set("gradientUnits", "userSpaceOnUse")
                     .set("id", id)
@@ -147,6 +386,12 @@ impl piet::RenderContext for RenderContext {
                     .set("fx", x.center.x + x.origin_offset.x)
                     .set("fy", x.center.y + x.origin_offset.y)
                     .set("r", x.radius);
+                if x.start_radius != 0.0 {
+                    gradient.assign("fr", x.start_radius);
+                }
+                if transform != Affine::IDENTITY {
+                    gradient.assign("gradientTransform", xf_val(&transform));
+                }
                 for stop in x.stops {
                     gradient.append(
                         svg::node::element::Stop::new()
@@ -156,11 +401,105 @@ impl piet::RenderContext for RenderContext {
                     );
                 }
                 self.doc.append(gradient);
+                id
+            }
+            // a circle, stretched by the gradient transform
+            FixedGradient::Elliptical(x) => {
+                let (radial, radial_transform) = x.to_radial();
+                return self
+                    .add_gradient(FixedGradient::Radial(radial), transform * radial_transform);
             }
+        };
+        Brush {
+            kind: BrushKind::Ref(id, 1.0),
+        }
+    }
+}
+
+impl Sink {
+    fn writer(&mut self) -> &mut dyn io::Write {
+        match self {
+            Sink::Plain(writer) => writer,
+            #[cfg(feature = "svgz")]
+            Sink::Gzip(encoder) => encoder,
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Plain(mut writer) => writer.flush(),
+            #[cfg(feature = "svgz")]
+            Sink::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl piet::RenderContext for RenderContext {
+    type Brush = Brush;
+
+    type Text = Text;
+    type TextLayout = TextLayout;
+
+    type Image = SvgImage;
+    type CachedShape = CachedShape<()>;
+
+    fn status(&mut self) -> Result<()> {
+        self.take_stream_err()
+    }
+
+    fn clear(&mut self, rect: impl Into<Option<Rect>>, color: Color) {
+        let rect = rect.into();
+        let mut rect = match rect {
+            Some(rect) => svg::node::element::Rectangle::new()
+                .set("width", rect.width())
+                .set("height", rect.height())
+                .set("x", rect.x0)
+                .set("y", rect.y0),
+            None => svg::node::element::Rectangle::new()
+                .set("width", "100%")
+                .set("height", "100%"),
         }
-        Ok(Brush {
-            kind: BrushKind::Ref(id),
-        })
+        .set("fill", fmt_color(color))
+        .set("fill-opacity", fmt_opacity(color));
+        mem::take(&mut self.annotation).apply_to_graphic(&mut rect);
+        //FIXME: I don't think we should be clipping, here?
+        if let Some(id) = self.state.clip {
+            rect.assign("clip-path", format!("url(#{})", id.to_string()));
+        }
+        self.doc.append(rect);
+        self.flush_stream();
+    }
+
+    fn solid_brush(&mut self, color: Color) -> Brush {
+        Brush {
+            kind: BrushKind::Solid(color),
+        }
+    }
+
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Brush> {
+        Ok(self.add_gradient(gradient.into(), Affine::IDENTITY))
+    }
+
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Brush> {
+        if transform.determinant() == 0.0 {
+            return Err(Error::InvalidInput);
+        }
+        Ok(self.add_gradient(gradient.into(), transform))
+    }
+
+    fn brush_with_opacity(&mut self, brush: &Brush, opacity: f64) -> Brush {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let kind = match brush.kind {
+            BrushKind::Solid(color) => {
+                BrushKind::Solid(color.with_alpha(color.as_rgba().3 * opacity))
+            }
+            BrushKind::Ref(id, alpha) => BrushKind::Ref(id, alpha * opacity),
+        };
+        Brush { kind }
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
@@ -172,9 +511,11 @@ impl piet::RenderContext for RenderContext {
                 xf: self.state.xf,
                 clip: self.state.clip,
                 fill: Some((brush.into_owned(), None)),
+                annotation: mem::take(&mut self.annotation),
                 ..Attrs::default()
             },
         );
+        self.flush_stream();
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
@@ -186,25 +527,19 @@ impl piet::RenderContext for RenderContext {
                 xf: self.state.xf,
                 clip: self.state.clip,
                 fill: Some((brush.into_owned(), Some("evenodd"))),
+                annotation: mem::take(&mut self.annotation),
                 ..Attrs::default()
             },
         );
+        self.flush_stream();
     }
 
     fn clip(&mut self, shape: impl Shape) {
-        let id = self.new_id();
-        let mut clip = svg::node::element::ClipPath::new().set("id", id);
-        add_shape(
-            &mut clip,
-            shape,
-            &Attrs {
-                xf: self.state.xf,
-                clip: self.state.clip,
-                ..Attrs::default()
-            },
-        );
-        self.doc.append(clip);
-        self.state.clip = Some(id);
+        self.clip_impl(shape, None);
+    }
+
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        self.clip_impl(shape, Some("evenodd"));
     }
 
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
@@ -216,9 +551,11 @@ impl piet::RenderContext for RenderContext {
                 xf: self.state.xf,
                 clip: self.state.clip,
                 stroke: Some((brush.into_owned(), width, &StrokeStyle::new())),
+                annotation: mem::take(&mut self.annotation),
                 ..Attrs::default()
             },
         );
+        self.flush_stream();
     }
 
     fn stroke_styled(
@@ -236,9 +573,15 @@ impl piet::RenderContext for RenderContext {
                 xf: self.state.xf,
                 clip: self.state.clip,
                 stroke: Some((brush.into_owned(), width, style)),
+                annotation: mem::take(&mut self.annotation),
                 ..Attrs::default()
             },
         );
+        self.flush_stream();
+    }
+
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape> {
+        Ok(CachedShape::new(shape.into_path(1e-3), ()))
     }
 
     fn text(&mut self) -> &mut Self::Text {
@@ -253,16 +596,16 @@ impl piet::RenderContext for RenderContext {
             format!("rgba({}, {}, {}, {})", r, g, b, a as f64 * (100. / 255.))
         };
 
+        // SVG doesn't wrap text, so we emit a span for each line we laid out. Lines are aligned
+        // with text-anchor, within the max width or else the width of the widest line.
+        let width = layout.alignment_width();
         let mut x = pos.x;
-        // SVG doesn't do multiline text, and so doesn't have a concept of text width. We can do
-        // alignment though, using text-anchor. TODO eventually we should generate a separate text
-        // span for each line (having laid out the multiline text ourselves.
-        let anchor = match (layout.max_width, layout.alignment) {
-            (width, TextAlignment::End) if width.is_finite() && width > 0. => {
+        let anchor = match layout.alignment {
+            TextAlignment::End => {
                 x += width;
                 "text-anchor:end"
             }
-            (width, TextAlignment::Center) if width.is_finite() && width > 0. => {
+            TextAlignment::Center => {
                 x += width * 0.5;
                 "text-anchor:middle"
             }
@@ -282,10 +625,42 @@ impl piet::RenderContext for RenderContext {
         // `dominant-baseline` gets us most of the way (to the top of the ascender), so we add a
         // small fiddle factor in to cover the difference between the top of the line and the top
         // of the ascender (currently 6% of the font height, calculated by eye).
-        let y = pos.y + 0.06 * layout.size().height;
-        let mut text = svg::node::element::Text::new(layout.text())
-            .set("x", x)
-            .set("y", y)
+        let y = pos.y + 0.06 * layout.line_height();
+
+        // CSS applies these to every line of a decoration, but we only
+        // set them for underlines.
+        let mut extra_style = String::new();
+        if layout.underline {
+            match layout.underline_style {
+                UnderlineStyle::Solid => (),
+                UnderlineStyle::Dotted => extra_style.push_str("text-decoration-style:dotted;"),
+                UnderlineStyle::Dashed => extra_style.push_str("text-decoration-style:dashed;"),
+                UnderlineStyle::Wavy => extra_style.push_str("text-decoration-style:wavy;"),
+            }
+            if let Some(color) = layout.underline_color {
+                extra_style.push_str(&format!("text-decoration-color:{};", fmt_color(color)));
+            }
+            if let Some(thickness) = layout.underline_thickness {
+                extra_style.push_str(&format!("text-decoration-thickness:{thickness}pt;"));
+            }
+            if !layout.underline_skip_ink {
+                extra_style.push_str("text-decoration-skip-ink:none;");
+            }
+        }
+        if layout.baseline_offset != 0.0 {
+            extra_style.push_str(&format!("baseline-shift:{}pt;", layout.baseline_offset));
+        }
+        // SVG has no control over subpixel antialiasing, but renderers
+        // generally take `optimizeSpeed` as a hint to turn antialiasing off.
+        if layout.antialiasing == TextAntialiasing::Aliased {
+            extra_style.push_str("text-rendering:optimizeSpeed;");
+        }
+        if !layout.font_features.is_empty() {
+            let features = piet::util::css_font_features(&layout.font_features);
+            extra_style.push_str(&format!("font-feature-settings:{features};"));
+        }
+
+        let mut text = svg::node::element::Text::new("")
             .set("dominant-baseline", "hanging")
             .set(
                 "style",
@@ -295,6 +670,7 @@ impl piet::RenderContext for RenderContext {
                         font-weight:{};\
                         font-style:{};\
                         text-decoration:{};\
+                        {}\
                         fill:{};\
                         {}",
                     layout.font_size,
@@ -310,19 +686,34 @@ impl piet::RenderContext for RenderContext {
                         (true, false) => "underline",
                         (true, true) => "underline line-through",
                     },
+                    extra_style,
                     color,
                     anchor,
                 ),
             );
+        mem::take(&mut self.annotation).apply_to(&mut text);
+
+        for line in (0..layout.line_count()).filter_map(|i| layout.line_metric(i)) {
+            let line_text = &layout.text()[line.start_offset..line.end_offset];
+            if line_text.trim_end().is_empty() {
+                continue;
+            }
+            text.append(
+                svg::node::element::TSpan::new(line_text.trim_end())
+                    .set("x", x)
+                    .set("y", y + line.y_offset),
+            );
+        }
 
         let affine = self.current_transform();
         if affine != Affine::IDENTITY {
             text.assign("transform", xf_val(&affine));
         }
-        if let Some(id) = self.state.clip {
-            text.assign("clip-path", format!("url(#{})", id.to_string()));
+        if let Some(locale) = &layout.locale {
+            text.assign("xml:lang", locale.as_str());
         }
-        self.doc.append(text);
+        append_clipped(&mut self.doc, text, self.state.clip);
+        self.flush_stream();
     }
 
     fn save(&mut self) -> Result<()> {
@@ -337,11 +728,15 @@ impl piet::RenderContext for RenderContext {
     }
 
     fn finish(&mut self) -> Result<()> {
-        self.doc
-            .assign("viewBox", (0, 0, self.size.width, self.size.height));
+        if self.pages.count > 0 && !self.pages.closed {
+            self.end_page();
+            self.pages.closed = true;
+        }
+        let size = self.document_size();
+        self.doc.assign("viewBox", (0, 0, size.width, size.height));
         self.doc.assign(
             "style",
-            format!("width:{}px;height:{}px;", self.size.width, self.size.height),
+            format!("width:{}px;height:{}px;", size.width, size.height),
         );
 
         let text = (*self.text()).clone();
@@ -379,7 +774,16 @@ impl piet::RenderContext for RenderContext {
         }
 
         seen_fonts.clear();
-        Ok(())
+        drop(seen_fonts);
+
+        self.flush_stream();
+        if let Some(mut sink) = self.stream.take() {
+            let closed = writeln!(sink.writer(), "</svg>").and_then(|()| sink.finish());
+            if let Err(e) = closed {
+                self.stream_err.get_or_insert(e);
+            }
+        }
+        self.take_stream_err()
     }
 
     fn transform(&mut self, transform: Affine) {
@@ -398,6 +802,7 @@ impl piet::RenderContext for RenderContext {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image> {
+        piet::util::validate_image_args(width, height, stride, format, buf.len())?;
         let buf = piet::util::image_buffer_to_tightly_packed(buf, width, height, stride, format)?;
         Ok(SvgImage(match format {
             ImageFormat::Grayscale => {
@@ -440,7 +845,7 @@ impl piet::RenderContext for RenderContext {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, None, dst_rect.into(), interp);
+        draw_image(self, image, None, dst_rect.into(), interp, 1.0);
     }
 
     #[inline]
@@ -451,7 +856,89 @@ impl piet::RenderContext for RenderContext {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, Some(src_rect.into()), dst_rect.into(), interp);
+        draw_image(
+            self,
+            image,
+            Some(src_rect.into()),
+            dst_rect.into(),
+            interp,
+            1.0,
+        );
+    }
+
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    ) {
+        draw_image(
+            self,
+            image,
+            Some(src_rect.into()),
+            dst_rect.into(),
+            interp,
+            opacity.clamp(0.0, 1.0),
+        );
+    }
+
+    fn draw_image_tiled(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        tile_mode: TileMode,
+        _interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into().abs();
+        let size = image.size();
+        if dst_rect.is_zero_area() || size.is_zero_area() {
+            return;
+        }
+
+        let image_id = self.new_id();
+        let image_node = svg::node::element::Image::new()
+            .set("id", image_id)
+            .set("width", size.width)
+            .set("height", size.height)
+            .set("href", image_data_url(image));
+        let mut pattern = svg::node::element::Pattern::new()
+            .set("patternUnits", "userSpaceOnUse")
+            .set("x", dst_rect.x0)
+            .set("y", dst_rect.y0)
+            .add(image_node);
+        let tile_size = match tile_mode {
+            TileMode::Repeat => size,
+            TileMode::Mirror => {
+                // a mirrored pattern tile is four copies of the image, each
+                // flipped to meet its neighbours at matching edges
+                let (w, h) = (size.width, size.height);
+                let flips = [
+                    Affine::new([-1.0, 0.0, 0.0, 1.0, 2.0 * w, 0.0]),
+                    Affine::new([1.0, 0.0, 0.0, -1.0, 0.0, 2.0 * h]),
+                    Affine::new([-1.0, 0.0, 0.0, -1.0, 2.0 * w, 2.0 * h]),
+                ];
+                for flip in flips {
+                    pattern.append(
+                        svg::node::element::Use::new()
+                            .set("href", format!("#{}", image_id.to_string()))
+                            .set("transform", xf_val(&flip)),
+                    );
+                }
+                size * 2.0
+            }
+        };
+        let pattern_id = self.new_id();
+        pattern.assign("id", pattern_id);
+        pattern.assign("width", tile_size.width);
+        pattern.assign("height", tile_size.height);
+        self.doc.append(pattern);
+
+        let brush = Brush {
+            kind: BrushKind::Ref(pattern_id, 1.0),
+        };
+        self.fill(dst_rect, &brush);
     }
 
     fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image> {
@@ -462,6 +949,24 @@ impl piet::RenderContext for RenderContext {
         // TODO blur (perhaps using SVG filters)
         self.fill(rect, brush)
     }
+
+    fn annotate_link(&mut self, shape: impl Shape, url: &str) {
+        // The link is an invisible shape on top of what is drawn there, as
+        // `<a>` can't be wrapped around elements that are already written.
+        // It is filled, so that it takes pointer events.
+        let mut link = svg::node::element::Anchor::new().set("href", url);
+        add_shape(
+            &mut link,
+            shape,
+            &Attrs {
+                xf: self.state.xf,
+                fill: Some((self.solid_brush(Color::TRANSPARENT), None)),
+                ..Attrs::default()
+            },
+        );
+        append_clipped(&mut self.doc, link, self.state.clip);
+        self.flush_stream();
+    }
 }
 
 fn draw_image(
@@ -470,7 +975,33 @@ fn draw_image(
     _src_rect: Option<Rect>,
     dst_rect: Rect,
     _interp: InterpolationMode,
+    opacity: f64,
 ) {
+    let data_url = image_data_url(image);
+
+    // TODO when src_rect.is_some()
+    // TODO maybe we could use css 'image-rendering' to control interpolation?
+    let mut node = svg::node::element::Image::new()
+        .set("x", dst_rect.x0)
+        .set("y", dst_rect.y0)
+        .set("width", dst_rect.x1 - dst_rect.x0)
+        .set("height", dst_rect.y1 - dst_rect.y0)
+        .set("href", data_url);
+    if opacity < 1.0 {
+        node.assign("opacity", opacity);
+    }
+    mem::take(&mut ctx.annotation).apply_to_graphic(&mut node);
+
+    let affine = piet::RenderContext::current_transform(ctx);
+    if affine != Affine::IDENTITY {
+        node.assign("transform", xf_val(&affine));
+    }
+    append_clipped(&mut ctx.doc, node, ctx.state.clip);
+    ctx.flush_stream();
+}
+
+/// Encode an image as a PNG data URL.
+fn image_data_url(image: &SvgImage) -> String {
     use image::ImageEncoder as _;
 
     let mut writer = base64::write::EncoderStringWriter::from(
@@ -487,26 +1018,7 @@ fn draw_image(
         )
         .unwrap();
 
-    let data_url = writer.into_inner();
-
-    // TODO when src_rect.is_some()
-    // TODO maybe we could use css 'image-rendering' to control interpolation?
-    let mut node = svg::node::element::Image::new()
-        .set("x", dst_rect.x0)
-        .set("y", dst_rect.y0)
-        .set("width", dst_rect.x1 - dst_rect.x0)
-        .set("height", dst_rect.y1 - dst_rect.y0)
-        .set("href", data_url);
-
-    let affine = piet::RenderContext::current_transform(ctx);
-    if affine != Affine::IDENTITY {
-        node.assign("transform", xf_val(&affine));
-    }
-    if let Some(id) = ctx.state.clip {
-        node.assign("clip-path", format!("url(#{})", id.to_string()));
-    }
-
-    ctx.doc.append(node);
+    writer.into_inner()
 }
 
 #[derive(Default)]
@@ -515,6 +1027,8 @@ struct Attrs<'a> {
     clip: Option<Id>,
     fill: Option<(Brush, Option<&'a str>)>,
     stroke: Option<(Brush, f64, &'a StrokeStyle)>,
+    clip_rule: Option<&'a str>,
+    annotation: Annotation,
 }
 
 impl Attrs<'_> {
@@ -522,9 +1036,6 @@ impl Attrs<'_> {
     #[allow(clippy::float_cmp)]
     fn apply_to(&self, node: &mut impl Node) {
         node.assign("transform", xf_val(&self.xf));
-        if let Some(id) = self.clip {
-            node.assign("clip-path", format!("url(#{})", id.to_string()));
-        }
         if let Some((ref brush, rule)) = self.fill {
             node.assign("fill", brush.color());
             if let Some(opacity) = brush.opacity() {
@@ -536,6 +1047,9 @@ impl Attrs<'_> {
         } else {
             node.assign("fill", "none");
         }
+        if let Some(rule) = self.clip_rule {
+            node.assign("clip-rule", rule);
+        }
         if let Some((ref stroke, width, style)) = self.stroke {
             node.assign("stroke", stroke.color());
             if let Some(opacity) = stroke.opacity() {
@@ -572,6 +1086,7 @@ impl Attrs<'_> {
                 node.assign("stroke-dashoffset", style.dash_offset);
             }
         }
+        self.annotation.apply_to_graphic(node);
     }
 }
 
@@ -591,7 +1106,7 @@ fn add_shape(node: &mut impl Node, shape: impl Shape, attrs: &Attrs) {
             .set("cy", circle.center.y)
             .set("r", circle.radius);
         attrs.apply_to(&mut x);
-        node.append(x);
+        append_clipped(node, x, attrs.clip);
     } else if let Some(round_rect) = shape
         .as_rounded_rect()
         .filter(|r| r.radii().as_single_radius().is_some())
@@ -604,7 +1119,7 @@ fn add_shape(node: &mut impl Node, shape: impl Shape, attrs: &Attrs) {
             .set("rx", round_rect.radii().as_single_radius().unwrap())
             .set("ry", round_rect.radii().as_single_radius().unwrap());
         attrs.apply_to(&mut x);
-        node.append(x);
+        append_clipped(node, x, attrs.clip);
     } else if let Some(rect) = shape.as_rect() {
         let mut x = svg::node::element::Rectangle::new()
             .set("x", rect.origin().x)
@@ -612,11 +1127,27 @@ fn add_shape(node: &mut impl Node, shape: impl Shape, attrs: &Attrs) {
             .set("width", rect.width())
             .set("height", rect.height());
         attrs.apply_to(&mut x);
-        node.append(x);
+        append_clipped(node, x, attrs.clip);
     } else {
         let mut path = svg::node::element::Path::new().set("d", shape.into_path(1e-3).to_svg());
         attrs.apply_to(&mut path);
-        node.append(path);
+        append_clipped(node, path, attrs.clip);
+    }
+}
+
+/// Append `child` to `node`, clipped to the clip path `clip`.
+///
+/// A clip path is in the coordinates of the element it clips, including that
+/// element's own transform, but our clip paths are already transformed. So
+/// the clip goes on an untransformed group around the child instead.
+fn append_clipped(node: &mut impl Node, child: impl Node, clip: Option<Id>) {
+    match clip {
+        Some(id) => node.append(
+            svg::node::element::Group::new()
+                .set("clip-path", format!("url(#{})", id.to_string()))
+                .add(child),
+        ),
+        None => node.append(child),
     }
 }
 
@@ -635,21 +1166,23 @@ pub struct Brush {
 #[derive(Debug, Clone)]
 enum BrushKind {
     Solid(Color),
-    Ref(Id),
+    /// A reference to a paint server, with the opacity to draw it with.
+    Ref(Id, f64),
 }
 
 impl Brush {
     fn color(&self) -> svg::node::Value {
         match self.kind {
             BrushKind::Solid(color) => fmt_color(color).into(),
-            BrushKind::Ref(id) => format!("url(#{})", id.to_string()).into(),
+            BrushKind::Ref(id, _) => format!("url(#{})", id.to_string()).into(),
         }
     }
 
     fn opacity(&self) -> Option<svg::node::Value> {
         match self.kind {
             BrushKind::Solid(color) => Some(fmt_opacity(color).into()),
-            BrushKind::Ref(_) => None,
+            BrushKind::Ref(_, opacity) if opacity < 1.0 => Some(opacity.into()),
+            BrushKind::Ref(..) => None,
         }
     }
 }
@@ -685,8 +1218,26 @@ impl Image for SvgImage {
             height: height as _,
         }
     }
+
+    fn format(&self) -> ImageFormat {
+        match self.0 {
+            DynamicImage::ImageLuma8(_) => ImageFormat::Grayscale,
+            DynamicImage::ImageRgb8(_) => ImageFormat::Rgb,
+            _ => ImageFormat::RgbaSeparate,
+        }
+    }
+
+    fn stride(&self) -> usize {
+        self.0.width() as usize * self.format().bytes_per_pixel()
+    }
 }
 
+/// The start of every generated id, keeping them apart from the ids set
+/// with [`set_next_element_id`].
+///
+/// [`set_next_element_id`]: RenderContext::set_next_element_id
+const ID_PREFIX: &str = "piet-";
+
 #[derive(Debug, Copy, Clone)]
 struct Id(u64);
 
@@ -695,7 +1246,7 @@ impl Id {
     #[allow(clippy::inherent_to_string)]
     fn to_string(self) -> String {
         const ALPHABET: &[u8; 52] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
-        let mut out = String::with_capacity(4);
+        let mut out = String::from(ID_PREFIX);
         let mut x = self.0;
         loop {
             let digit = (x % ALPHABET.len() as u64) as usize;
@@ -714,3 +1265,174 @@ impl From<Id> for svg::node::Value {
         x.to_string().into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    use piet::kurbo::{Affine, Rect, Size};
+    use piet::{Color, Error, FontFamily, RenderContext as _, Text as _, TextLayoutBuilder as _};
+
+    use super::RenderContext;
+
+    /// A writer whose output can be read while the context still owns it.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuf {
+        fn contents(&self) -> Vec<u8> {
+            self.0.borrow().clone()
+        }
+    }
+
+    impl io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn render(ctx: &RenderContext) -> String {
+        let mut out = Vec::new();
+        ctx.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn pages_are_stacked() {
+        let mut ctx = RenderContext::new(Size::new(100.0, 50.0));
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+        ctx.next_page(Size::new(80.0, 40.0)).unwrap();
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+        assert_eq!(ctx.page_count(), 2);
+        ctx.finish().unwrap();
+
+        let svg = render(&ctx);
+        assert!(svg.contains(r#"viewBox="0 0 100 90""#));
+        assert!(svg.contains(r#"viewBox="0 0 100 50" width="100" x="0""#));
+        assert!(svg.contains(r#"viewBox="0 0 80 40" width="80" x="0""#));
+        assert!(svg.contains(r#"y="50">"#));
+        assert_eq!(ctx.document_size(), Size::new(100.0, 90.0));
+    }
+
+    #[test]
+    fn finishing_twice_keeps_the_pages() {
+        let mut ctx = RenderContext::new(Size::new(10.0, 10.0));
+        ctx.next_page(Size::new(10.0, 10.0)).unwrap();
+        ctx.finish().unwrap();
+        assert_eq!(ctx.page_count(), 2);
+        let svg = render(&ctx);
+
+        ctx.finish().unwrap();
+        assert_eq!(ctx.page_count(), 2);
+        assert_eq!(render(&ctx), svg);
+        assert!(svg.contains(r#"viewBox="0 0 10 20""#));
+    }
+
+    #[test]
+    fn streaming_writes_as_it_draws() {
+        let buf = SharedBuf::default();
+        let mut ctx = RenderContext::new_streaming(Size::new(100.0, 50.0), buf.clone());
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+        let drawn = String::from_utf8(buf.contents()).unwrap();
+        assert!(drawn.starts_with("<svg "));
+        assert!(drawn.contains("<rect"));
+        assert!(matches!(
+            ctx.next_page(Size::new(10.0, 10.0)),
+            Err(Error::NotSupported)
+        ));
+        ctx.finish().unwrap();
+        let svg = String::from_utf8(buf.contents()).unwrap();
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[cfg(feature = "svgz")]
+    #[test]
+    fn svgz_is_gzipped_svg() {
+        use std::io::Read;
+
+        let mut ctx = RenderContext::new(Size::new(100.0, 50.0));
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+        ctx.finish().unwrap();
+        let mut svgz = Vec::new();
+        ctx.write_svgz(&mut svgz).unwrap();
+        let mut svg = String::new();
+        flate2::read::GzDecoder::new(&svgz[..])
+            .read_to_string(&mut svg)
+            .unwrap();
+        assert_eq!(svg, render(&ctx));
+
+        let buf = SharedBuf::default();
+        let mut ctx = RenderContext::new_streaming_svgz(Size::new(100.0, 50.0), buf.clone());
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+        ctx.finish().unwrap();
+        let mut svg = String::new();
+        flate2::read::GzDecoder::new(&buf.contents()[..])
+            .read_to_string(&mut svg)
+            .unwrap();
+        assert!(svg.starts_with("<svg ") && svg.contains("<rect"));
+    }
+
+    #[test]
+    fn transformed_elements_are_clipped_by_a_group() {
+        let mut ctx = RenderContext::new(Size::new(100.0, 100.0));
+        ctx.clip(Rect::new(0.0, 0.0, 50.0, 50.0));
+        ctx.transform(Affine::rotate(0.5));
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+        ctx.finish().unwrap();
+
+        // the clip path is already transformed, so it goes on a group
+        // rather than on the rotated element
+        let svg = render(&ctx);
+        assert!(svg.contains(r#"<g clip-path="url(#piet-a)">"#));
+        let group = &svg[svg.find("<g ").unwrap()..svg.find("</g>").unwrap()];
+        assert!(group.contains("<rect") && group.contains("transform=\"matrix(0.87"));
+    }
+
+    #[test]
+    fn lines_are_tspans() {
+        let mut ctx = RenderContext::new(Size::new(100.0, 100.0));
+        let layout = ctx
+            .text()
+            .new_text_layout("first line\nsecond line")
+            .font(FontFamily::SANS_SERIF, 12.0)
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, (10.0, 10.0));
+        ctx.finish().unwrap();
+
+        let svg = render(&ctx);
+        let lines: Vec<_> = svg.match_indices("<tspan x=\"10\"").collect();
+        assert_eq!(lines.len(), 2);
+        let first = svg.find(">first line</tspan>").unwrap();
+        let second = svg.find(">second line</tspan>").unwrap();
+        assert!(lines[0].0 < first && first < lines[1].0 && lines[1].0 < second);
+    }
+
+    #[test]
+    fn only_titled_graphics_are_images() {
+        let mut ctx = RenderContext::new(Size::new(100.0, 100.0));
+        ctx.annotate("square", None);
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+        let layout = ctx
+            .text()
+            .new_text_layout("label")
+            .font(FontFamily::SANS_SERIF, 12.0)
+            .build()
+            .unwrap();
+        ctx.annotate("caption", None);
+        ctx.draw_text(&layout, (10.0, 10.0));
+        ctx.finish().unwrap();
+
+        let svg = render(&ctx);
+        assert_eq!(svg.matches(r#"role="img""#).count(), 1);
+        let rect = &svg[svg.find("<rect").unwrap()..];
+        assert!(rect[..rect.find('>').unwrap()].contains(r#"role="img""#));
+        assert!(svg.contains("<title>caption</title>"));
+    }
+}
@@ -7,7 +7,7 @@ use std::{
     cell::RefCell,
     collections::HashSet,
     fs, io,
-    ops::RangeBounds,
+    ops::{Range, RangeBounds},
     rc::Rc,
     sync::{Arc, Mutex},
 };
@@ -19,10 +19,12 @@ use font_kit::{
 };
 use piet::kurbo::{Point, Rect, Size};
 use piet::{
-    Color, Error, FontFamily, FontStyle, FontWeight, HitTestPoint, HitTestPosition, LineMetric,
-    TextAlignment, TextAttribute, TextStorage,
+    util, Color, Error, FontFamily, FontFeature, FontStyle, FontWeight, HitTestPoint,
+    HitTestPosition, LineMetric, TextAlignment, TextAntialiasing, TextAttribute, TextStorage,
+    UnderlineStyle,
 };
 use rustybuzz::{Face, UnicodeBuffer};
+use xi_unicode::LineBreakIterator;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -100,11 +102,20 @@ impl piet::Text for Text {
 pub struct TextLayoutBuilder {
     text: Arc<dyn TextStorage>,
     alignment: TextAlignment,
+    locale: Option<String>,
+    antialiasing: TextAntialiasing,
     font_face: FontFace,
     font_size: f64,
     text_color: Color,
     underline: bool,
     strikethrough: bool,
+    underline_color: Option<Color>,
+    underline_style: UnderlineStyle,
+    underline_thickness: Option<f64>,
+    underline_skip_ink: bool,
+    baseline_offset: f64,
+    font_features: Vec<FontFeature>,
+    links: util::TextLinks,
     max_width: f64,
     ctx: Text,
 }
@@ -114,11 +125,20 @@ impl TextLayoutBuilder {
         Self {
             text: Arc::new(text),
             alignment: TextAlignment::default(),
+            locale: None,
+            antialiasing: TextAntialiasing::default(),
             font_size: 12.,
             font_face: FontFace::default(),
             text_color: Color::BLACK,
             underline: false,
             strikethrough: false,
+            underline_color: None,
+            underline_style: UnderlineStyle::default(),
+            underline_thickness: None,
+            underline_skip_ink: true,
+            baseline_offset: 0.0,
+            font_features: Vec::new(),
+            links: util::TextLinks::default(),
             max_width: f64::INFINITY,
             ctx,
         }
@@ -139,6 +159,16 @@ impl piet::TextLayoutBuilder for TextLayoutBuilder {
         self
     }
 
+    fn locale(mut self, locale: &str) -> Self {
+        self.locale = Some(locale.to_owned());
+        self
+    }
+
+    fn antialiasing(mut self, antialiasing: TextAntialiasing) -> Self {
+        self.antialiasing = antialiasing;
+        self
+    }
+
     fn default_attribute(mut self, attribute: impl Into<TextAttribute>) -> Self {
         match attribute.into() {
             TextAttribute::FontFamily(font) => self.font_face.family = font,
@@ -148,6 +178,15 @@ impl piet::TextLayoutBuilder for TextLayoutBuilder {
             TextAttribute::Style(style) => self.font_face.style = style,
             TextAttribute::Underline(underline) => self.underline = underline,
             TextAttribute::Strikethrough(strikethrough) => self.strikethrough = strikethrough,
+            TextAttribute::UnderlineColor(color) => self.underline_color = Some(color),
+            TextAttribute::UnderlineStyle(style) => self.underline_style = style,
+            TextAttribute::UnderlineThickness(thickness) => {
+                self.underline_thickness = Some(thickness)
+            }
+            TextAttribute::UnderlineSkipInk(flag) => self.underline_skip_ink = flag,
+            TextAttribute::BaselineOffset(offset) => self.baseline_offset = offset,
+            TextAttribute::FontFeatures(features) => self.font_features = features,
+            TextAttribute::Link(url) => self.links.add(0..self.text.len(), &url),
         }
 
         self
@@ -158,7 +197,12 @@ impl piet::TextLayoutBuilder for TextLayoutBuilder {
         range: impl RangeBounds<usize>,
         attribute: impl Into<TextAttribute>,
     ) -> Self {
-        if range.contains(&0) && range.contains(&(self.text.len() - 1)) {
+        let attribute = attribute.into();
+        // links don't change how the text is drawn, so any range will do
+        if let TextAttribute::Link(url) = &attribute {
+            let range = util::resolve_range(range, self.text.len());
+            self.links.add(range, url);
+        } else if range.contains(&0) && range.contains(&(self.text.len() - 1)) {
             self = self.default_attribute(attribute)
         } else {
             // TODO non-full ranges are unsupported
@@ -177,11 +221,26 @@ pub struct TextLayout {
     text: Arc<dyn TextStorage>,
     pub(crate) max_width: f64,
     pub(crate) alignment: TextAlignment,
+    pub(crate) locale: Option<String>,
+    pub(crate) antialiasing: TextAntialiasing,
     pub(crate) font_size: f64,
     pub(crate) font_face: FontFace,
     pub(crate) text_color: Color,
     pub(crate) underline: bool,
     pub(crate) strikethrough: bool,
+    pub(crate) underline_color: Option<Color>,
+    pub(crate) underline_style: UnderlineStyle,
+    pub(crate) underline_thickness: Option<f64>,
+    pub(crate) underline_skip_ink: bool,
+    pub(crate) baseline_offset: f64,
+    pub(crate) font_features: Vec<FontFeature>,
+    links: util::TextLinks,
+    /// The advance of each glyph, in pixels, with the byte offset of its
+    /// cluster.
+    advances: Arc<[(usize, f64)]>,
+    line_height: f64,
+    baseline: f64,
+    lines: Vec<LineMetric>,
     size: Size,
 }
 
@@ -207,33 +266,133 @@ impl TextLayout {
 
         // shape the full text
         uni.push_str(builder.text.as_str());
+        if let Some(language) = builder.locale.as_deref().and_then(|l| l.parse().ok()) {
+            uni.set_language(language);
+        }
         let layout = rustybuzz::shape(&face, &[], uni);
-        let width = layout
-            .glyph_positions()
+        let advances = layout
+            .glyph_infos()
             .iter()
-            .map(|pos| pos.x_advance as f64)
-            .sum::<f64>()
-            * px_per_unit;
-        let height = face.height() as f64 * px_per_unit;
-        let size = Size { width, height };
+            .zip(layout.glyph_positions())
+            .map(|(info, pos)| (info.cluster as usize, pos.x_advance as f64 * px_per_unit))
+            .collect();
+        let line_height = face.height() as f64 * px_per_unit;
+        let baseline = face.ascender() as f64 * px_per_unit;
 
-        Ok(TextLayout {
+        let mut layout = TextLayout {
             text: builder.text,
             max_width: builder.max_width,
             alignment: builder.alignment,
+            locale: builder.locale,
+            antialiasing: builder.antialiasing,
             font_face: builder.font_face,
             font_size: builder.font_size,
             text_color: builder.text_color,
             underline: builder.underline,
             strikethrough: builder.strikethrough,
-            size,
-        })
+            underline_color: builder.underline_color,
+            underline_style: builder.underline_style,
+            underline_thickness: builder.underline_thickness,
+            underline_skip_ink: builder.underline_skip_ink,
+            baseline_offset: builder.baseline_offset,
+            font_features: builder.font_features,
+            links: builder.links,
+            advances,
+            line_height,
+            baseline,
+            lines: Vec::new(),
+            size: Size::ZERO,
+        };
+        layout.break_lines();
+        Ok(layout)
+    }
+
+    /// The width of the glyphs for the text in `range`.
+    fn range_width(&self, range: Range<usize>) -> f64 {
+        self.advances
+            .iter()
+            .filter(|(cluster, _)| range.contains(cluster))
+            .map(|(_, advance)| advance)
+            .sum()
+    }
+
+    /// The height of each line.
+    pub(crate) fn line_height(&self) -> f64 {
+        self.line_height
+    }
+
+    /// The width lines are aligned within: the max width, or else the width of
+    /// the widest line.
+    pub(crate) fn alignment_width(&self) -> f64 {
+        if self.max_width.is_finite() && self.max_width > 0. {
+            self.max_width
+        } else {
+            self.size.width
+        }
+    }
+
+    /// The width of a line, without its trailing whitespace.
+    fn line_width(&self, line: &LineMetric) -> f64 {
+        self.range_width(line.start_offset..line.end_offset - line.trailing_whitespace)
+    }
+
+    /// Break the text into lines no wider than `max_width`, where possible,
+    /// and compute the size of the layout.
+    fn break_lines(&mut self) {
+        let text = self.text.as_str();
+        let mut ends = Vec::new();
+        let mut line_start = 0;
+        let mut prev_break = 0;
+        for (offset, is_hard_break) in LineBreakIterator::new(text) {
+            let trimmed = line_start + text[line_start..offset].trim_end().len();
+            // a line is only broken if there is an earlier break to break at
+            if prev_break > line_start && self.range_width(line_start..trimmed) > self.max_width {
+                ends.push(prev_break);
+                line_start = prev_break;
+            }
+            if is_hard_break {
+                ends.push(offset);
+                line_start = offset;
+            }
+            prev_break = offset;
+        }
+        // the end of the text is not a hard break, but ends the last line;
+        // an empty text, or a final newline, still has a line
+        if ends.last() != Some(&text.len()) || util::trailing_nlf(text).is_some() {
+            ends.push(text.len());
+        }
+
+        let mut start = 0;
+        self.lines = ends
+            .into_iter()
+            .enumerate()
+            .map(|(i, end)| {
+                let line = &text[start..end];
+                let metric = LineMetric {
+                    start_offset: start,
+                    end_offset: end,
+                    trailing_whitespace: line.len() - line.trim_end().len(),
+                    baseline: self.baseline,
+                    height: self.line_height,
+                    y_offset: i as f64 * self.line_height,
+                };
+                start = end;
+                metric
+            })
+            .collect();
+
+        let width = self
+            .lines
+            .iter()
+            .map(|line| self.line_width(line))
+            .fold(0.0, f64::max);
+        let height = self.lines.len() as f64 * self.line_height;
+        self.size = Size { width, height };
     }
 }
 
 impl piet::TextLayout for TextLayout {
     fn size(&self) -> Size {
-        // TODO shape multiple rows
         self.size
     }
 
@@ -246,31 +405,50 @@ impl piet::TextLayout for TextLayout {
         self.size().to_rect()
     }
 
-    fn line_text(&self, line_number: usize) -> Option<&str> {
-        if line_number == 0 {
-            Some(&self.text)
+    fn with_max_width(&self, width: f64) -> Self {
+        let mut layout = TextLayout {
+            max_width: width,
+            ..self.clone()
+        };
+        layout.break_lines();
+        layout
+    }
+
+    fn with_color_override(&self, range: impl RangeBounds<usize>, color: Color) -> Self {
+        let range = util::resolve_range(range, self.text.len());
+        let mut layout = self.clone();
+        if range == (0..self.text.len()) {
+            layout.text_color = color;
         } else {
-            None
+            // TODO non-full ranges are unsupported
         }
+        layout
+    }
+
+    fn line_text(&self, line_number: usize) -> Option<&str> {
+        self.lines
+            .get(line_number)
+            .map(|line| &self.text[line.range()])
     }
 
     fn line_metric(&self, line_number: usize) -> Option<LineMetric> {
-        if line_number == 0 {
-            Some(LineMetric {
-                start_offset: 0,
-                end_offset: self.text.len(),
-                trailing_whitespace: self.text.len() - self.text.trim_end().len(),
-                baseline: 0.,
-                height: 0.,
-                y_offset: 0.,
-            })
-        } else {
-            None
-        }
+        self.lines.get(line_number).cloned()
     }
 
     fn line_count(&self) -> usize {
-        1
+        self.lines.len()
+    }
+
+    fn line_bounds(&self, line_number: usize) -> Option<Rect> {
+        let line = self.lines.get(line_number)?;
+        let width = self.line_width(line);
+        let x0 = match self.alignment {
+            TextAlignment::End => self.alignment_width() - width,
+            TextAlignment::Center => (self.alignment_width() - width) * 0.5,
+            _ => 0.,
+        };
+        let y1 = line.y_offset + line.height;
+        Some(Rect::new(x0, line.y_offset, x0 + width, y1))
     }
 
     fn hit_test_point(&self, _point: Point) -> HitTestPoint {
@@ -281,6 +459,10 @@ impl piet::TextLayout for TextLayout {
         HitTestPosition::default()
     }
 
+    fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.links.links()
+    }
+
     fn text(&self) -> &str {
         self.text.as_str()
     }
@@ -1,5 +1,11 @@
 const rust = import('./dist/piet_web_example');
 
 rust
-  .then(m => m.run())
+  .then(m => {
+    m.run();
+    // open the page with `?measure` to time drawing with and without the path cache
+    if (new URLSearchParams(window.location.search).has('measure')) {
+      m.measure(100);
+    }
+  })
   .catch(console.error);
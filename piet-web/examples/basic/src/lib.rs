@@ -5,7 +5,7 @@
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{window, HtmlCanvasElement};
+use web_sys::{window, CanvasRenderingContext2d, HtmlCanvasElement, Window};
 
 use piet::{samples, RenderContext};
 use piet_web::WebRenderContext;
@@ -18,6 +18,48 @@ pub fn run() {
     #[cfg(feature = "console_error_panic_hook")]
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 
+    let (window, canvas, context) = setup();
+    let sample = samples::get::<WebRenderContext>(SAMPLE_PICTURE_NO).unwrap();
+    let dpr = window.device_pixel_ratio();
+    canvas.set_width((canvas.offset_width() as f64 * dpr) as u32);
+    canvas.set_height((canvas.offset_height() as f64 * dpr) as u32);
+    let _ = context.scale(dpr, dpr);
+
+    let mut piet_context = WebRenderContext::new(context, window);
+    piet_context.set_scale(dpr);
+
+    sample.draw(&mut piet_context).unwrap();
+    piet_context.finish().unwrap();
+}
+
+/// Draw the sample `iterations` times with the path cache disabled, and then
+/// with it enabled, and log the time taken by each.
+#[wasm_bindgen]
+pub fn measure(iterations: usize) {
+    let (window, _, context) = setup();
+    let performance = window.performance().unwrap();
+    let sample = samples::get::<WebRenderContext>(SAMPLE_PICTURE_NO).unwrap();
+
+    for (label, cache_size) in [("uncached", 0), ("cached", 64)] {
+        let mut piet_context = WebRenderContext::new(context.clone(), window.clone());
+        piet_context.set_path_cache_size(cache_size);
+        let start = performance.now();
+        for _ in 0..iterations {
+            sample.draw(&mut piet_context).unwrap();
+        }
+        piet_context.finish().unwrap();
+        let elapsed = performance.now() - start;
+        web_sys::console::log_1(
+            &format!(
+                "{label}: {:.2}ms per draw over {iterations} draws",
+                elapsed / iterations as f64
+            )
+            .into(),
+        );
+    }
+}
+
+fn setup() -> (Window, HtmlCanvasElement, CanvasRenderingContext2d) {
     let window = window().unwrap();
     let canvas = window
         .document()
@@ -30,17 +72,7 @@ pub fn run() {
         .get_context("2d")
         .unwrap()
         .unwrap()
-        .dyn_into::<web_sys::CanvasRenderingContext2d>()
+        .dyn_into::<CanvasRenderingContext2d>()
         .unwrap();
-
-    let sample = samples::get::<WebRenderContext>(SAMPLE_PICTURE_NO).unwrap();
-    let dpr = window.device_pixel_ratio();
-    canvas.set_width((canvas.offset_width() as f64 * dpr) as u32);
-    canvas.set_height((canvas.offset_height() as f64 * dpr) as u32);
-    let _ = context.scale(dpr, dpr);
-
-    let mut piet_context = WebRenderContext::new(context, window);
-
-    sample.draw(&mut piet_context).unwrap();
-    piet_context.finish().unwrap();
+    (window, canvas, context)
 }
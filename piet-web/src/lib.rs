@@ -8,26 +8,29 @@
 
 //! The Web Canvas backend for the Piet 2D graphics abstraction.
 
+mod path;
 mod text;
 
+use std::any::Any;
 use std::borrow::Cow;
 use std::fmt;
+use std::future::Future;
 use std::marker::PhantomData;
-use std::ops::Deref;
 
 use js_sys::{Float64Array, Reflect};
 use wasm_bindgen::{Clamped, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
 use web_sys::{
     CanvasGradient, CanvasRenderingContext2d, CanvasWindingRule, DomMatrix, HtmlCanvasElement,
-    ImageData, Window,
+    ImageBitmap, ImageData, Path2d, Window,
 };
 
-use piet::kurbo::{Affine, PathEl, Point, Rect, Shape, Size};
+use piet::kurbo::{Affine, Point, Rect, Shape, Size};
 
-use piet::util::unpremul;
+use piet::util::{self, unpremul};
 use piet::{
-    Color, Error, FixedGradient, GradientStop, Image, ImageFormat, InterpolationMode, IntoBrush,
-    LineCap, LineJoin, RenderContext, StrokeDash, StrokeStyle,
+    CachedShape, Color, Error, FixedGradient, GradientStop, Image, ImageFormat, InterpolationMode,
+    IntoBrush, LineCap, LineJoin, RenderContext, StrokeDash, StrokeStyle, TileMode,
 };
 
 pub use text::{WebFont, WebTextLayout, WebTextLayoutBuilder};
@@ -39,6 +42,7 @@ pub struct WebRenderContext<'a> {
     text: WebText,
     err: Result<(), Error>,
     canvas_states: Vec<CanvasState>,
+    scale: f64,
     _phantom: PhantomData<&'a ()>,
 }
 
@@ -50,9 +54,63 @@ impl WebRenderContext<'_> {
             text: WebText::new(ctx),
             err: Ok(()),
             canvas_states: vec![CanvasState::default()],
+            scale: 1.0,
             _phantom: PhantomData,
         }
     }
+
+    /// Set the scale factor reported by [`RenderContext::scale_factor`].
+    ///
+    /// This does not change how anything is drawn; the caller is expected to
+    /// have applied the same scale to the canvas context, usually the
+    /// window's `devicePixelRatio`. The scale is then left out of
+    /// [`RenderContext::current_transform`], as on other backends.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+
+    /// Set the number of recently drawn paths that are kept, so that drawing
+    /// the same complex shape again doesn't need a new `Path2D`.
+    ///
+    /// The cache is shared by every context on the current thread. The
+    /// default size is 64; a size of zero disables the cache.
+    pub fn set_path_cache_size(&mut self, size: usize) {
+        path::set_cache_size(size);
+    }
+
+    /// Create a new image from a pixel buffer, backed by an `ImageBitmap`.
+    ///
+    /// Images created by [`make_image`] are each backed by a canvas element
+    /// of their own, because an `ImageBitmap` can only be created
+    /// asynchronously. An `ImageBitmap` is cheaper to keep around and to
+    /// draw, so prefer this where the image isn't needed immediately.
+    ///
+    /// The buffer is copied before this returns, and the future doesn't
+    /// borrow the context.
+    ///
+    /// [`make_image`]: RenderContext::make_image
+    pub fn make_image_async(
+        &self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> impl Future<Output = Result<WebImage, Error>> + 'static {
+        let stride = width * format.bytes_per_pixel();
+        let promise = image_data(width, height, stride, buf, format).and_then(|data| {
+            self.window
+                .create_image_bitmap_with_image_data(&data)
+                .wrap()
+        });
+        async move {
+            let bitmap = JsFuture::from(promise?).await.wrap()?;
+            Ok(WebImage {
+                source: ImageSource::Bitmap(bitmap.unchecked_into()),
+                width: width as u32,
+                height: height as u32,
+            })
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -97,18 +155,31 @@ impl WebText {
 #[derive(Clone)]
 pub enum Brush {
     Solid(u32),
-    Gradient(CanvasGradient),
+    /// A gradient, the opacity it is drawn with, and the transform from the
+    /// gradient's coordinates to user space.
+    ///
+    /// Canvas gradients can't be inspected, so fading one sets the global
+    /// alpha instead of changing its stops. They are also always circular,
+    /// so elliptical and skewed gradients are drawn as circular ones under
+    /// a transform.
+    Gradient(CanvasGradient, f64, Affine),
 }
 
 #[derive(Clone)]
 pub struct WebImage {
-    /// We use a canvas element for now, but could be ImageData or ImageBitmap,
-    /// so consider an enum.
-    inner: HtmlCanvasElement,
+    source: ImageSource,
     width: u32,
     height: u32,
 }
 
+#[derive(Clone)]
+enum ImageSource {
+    /// A canvas element, for images that must be created synchronously.
+    Canvas(HtmlCanvasElement),
+    /// An image bitmap, from [`WebRenderContext::make_image_async`].
+    Bitmap(ImageBitmap),
+}
+
 #[derive(Debug)]
 struct WrappedJs(JsValue);
 
@@ -151,6 +222,10 @@ fn convert_line_join(line_join: LineJoin) -> &'static str {
     }
 }
 
+fn supports_filter(ctx: &CanvasRenderingContext2d) -> bool {
+    Reflect::has(ctx.as_ref(), &JsValue::from_str("filter")).unwrap_or(false)
+}
+
 fn convert_dash_pattern(pattern: &[f64]) -> Float64Array {
     let len = pattern.len() as u32;
     let array = Float64Array::new_with_length(len);
@@ -173,6 +248,7 @@ impl RenderContext for WebRenderContext<'_> {
     type TextLayout = WebTextLayout;
 
     type Image = WebImage;
+    type CachedShape = CachedShape<Path2d>;
 
     fn status(&mut self) -> Result<(), Error> {
         std::mem::replace(&mut self.err, Ok(()))
@@ -203,50 +279,107 @@ impl RenderContext for WebRenderContext<'_> {
                 let (x1, y1) = (linear.end.x, linear.end.y);
                 let mut lg = self.ctx.create_linear_gradient(x0, y0, x1, y1);
                 set_gradient_stops(&mut lg, &linear.stops);
-                Ok(Brush::Gradient(lg))
+                Ok(Brush::Gradient(lg, 1.0, Affine::IDENTITY))
             }
             FixedGradient::Radial(radial) => {
                 let (xc, yc) = (radial.center.x, radial.center.y);
                 let (xo, yo) = (radial.origin_offset.x, radial.origin_offset.y);
-                let r = radial.radius;
+                let (r0, r) = (radial.start_radius, radial.radius);
                 let mut rg = self
                     .ctx
-                    .create_radial_gradient(xc + xo, yc + yo, 0.0, xc, yc, r)
+                    .create_radial_gradient(xc + xo, yc + yo, r0, xc, yc, r)
                     .wrap()?;
                 set_gradient_stops(&mut rg, &radial.stops);
-                Ok(Brush::Gradient(rg))
+                Ok(Brush::Gradient(rg, 1.0, Affine::IDENTITY))
+            }
+            // Canvas gradients are always circular.
+            FixedGradient::Elliptical(elliptical) => {
+                let (radial, transform) = elliptical.to_radial();
+                self.gradient_with_transform(radial, transform)
+            }
+        }
+    }
+
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Brush, Error> {
+        if transform.determinant() == 0.0 {
+            return Err(Error::InvalidInput);
+        }
+        let gradient = gradient.into();
+        if let Some(gradient) = util::transform_gradient(gradient.clone(), transform) {
+            return self.gradient(gradient);
+        }
+        let gradient = match gradient {
+            FixedGradient::Elliptical(elliptical) => {
+                let (radial, radial_transform) = elliptical.to_radial();
+                return self.gradient_with_transform(radial, transform * radial_transform);
+            }
+            gradient => gradient,
+        };
+        match self.gradient(gradient)? {
+            Brush::Gradient(gradient, alpha, inner) => {
+                Ok(Brush::Gradient(gradient, alpha, transform * inner))
+            }
+            brush => Ok(brush),
+        }
+    }
+
+    fn brush_with_opacity(&mut self, brush: &Brush, opacity: f64) -> Brush {
+        let opacity = opacity.clamp(0.0, 1.0);
+        match *brush {
+            Brush::Solid(rgba) => {
+                let alpha = ((rgba & 0xff) as f64 * opacity).round() as u32;
+                Brush::Solid((rgba & !0xff) | alpha)
+            }
+            Brush::Gradient(ref gradient, alpha, transform) => {
+                Brush::Gradient(gradient.clone(), alpha * opacity, transform)
             }
         }
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
         let brush = brush.make_brush(self, || shape.bounding_box());
-        self.set_path(shape);
-        self.set_brush(&brush, true);
-        self.ctx
-            .fill_with_canvas_winding_rule(CanvasWindingRule::Nonzero);
+        if let Some(transform) = gradient_transform(&brush) {
+            self.fill_transformed(shape, &brush, CanvasWindingRule::Nonzero, transform);
+        } else if let Some(path) = self.path(shape) {
+            self.fill_path(&path, &brush, CanvasWindingRule::Nonzero);
+        }
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
         let brush = brush.make_brush(self, || shape.bounding_box());
-        self.set_path(shape);
-        self.set_brush(&brush, true);
-        self.ctx
-            .fill_with_canvas_winding_rule(CanvasWindingRule::Evenodd);
+        if let Some(transform) = gradient_transform(&brush) {
+            self.fill_transformed(shape, &brush, CanvasWindingRule::Evenodd, transform);
+        } else if let Some(path) = self.path(shape) {
+            self.fill_path(&path, &brush, CanvasWindingRule::Evenodd);
+        }
     }
 
     fn clip(&mut self, shape: impl Shape) {
-        self.set_path(shape);
-        self.ctx
-            .clip_with_canvas_winding_rule(CanvasWindingRule::Nonzero);
+        if let Some(path) = self.path(shape) {
+            self.ctx
+                .clip_with_path_2d_and_winding(&path, CanvasWindingRule::Nonzero);
+        }
+    }
+
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        if let Some(path) = self.path(shape) {
+            self.ctx
+                .clip_with_path_2d_and_winding(&path, CanvasWindingRule::Evenodd);
+        }
     }
 
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
         let brush = brush.make_brush(self, || shape.bounding_box());
-        self.set_path(shape);
-        self.set_stroke(width, None);
-        self.set_brush(brush.deref(), false);
-        self.ctx.stroke();
+        if let Some(transform) = gradient_transform(&brush) {
+            let outline = util::expand_stroke(shape, width, &StrokeStyle::default());
+            self.fill_transformed(outline, &brush, CanvasWindingRule::Nonzero, transform);
+        } else if let Some(path) = self.path(shape) {
+            self.stroke_path(&path, &brush, width, None);
+        }
     }
 
     fn stroke_styled(
@@ -257,10 +390,67 @@ impl RenderContext for WebRenderContext<'_> {
         style: &StrokeStyle,
     ) {
         let brush = brush.make_brush(self, || shape.bounding_box());
-        self.set_path(shape);
-        self.set_stroke(width, Some(style));
-        self.set_brush(brush.deref(), false);
-        self.ctx.stroke();
+        if let Some(transform) = gradient_transform(&brush) {
+            let outline = util::expand_stroke(shape, width, style);
+            self.fill_transformed(outline, &brush, CanvasWindingRule::Nonzero, transform);
+        } else if let Some(path) = self.path(shape) {
+            self.stroke_path(&path, &brush, width, Some(style));
+        }
+    }
+
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error> {
+        let path = shape.into_path(1e-3);
+        let path_2d = match util::as_arc(&path) {
+            Some(arc) => path::arc_path_2d(&arc),
+            None => path::path_2d(path.elements().iter().copied()),
+        };
+        let path_2d = path_2d.wrap()?;
+        Ok(CachedShape::new(path, path_2d))
+    }
+
+    fn fill_cached(&mut self, shape: &Self::CachedShape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        if let Some(transform) = gradient_transform(&brush) {
+            self.fill_transformed(shape, &brush, CanvasWindingRule::Nonzero, transform);
+        } else {
+            self.fill_path(shape.geometry(), &brush, CanvasWindingRule::Nonzero);
+        }
+    }
+
+    fn stroke_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        if let Some(transform) = gradient_transform(&brush) {
+            let outline = util::expand_stroke(shape, width, &StrokeStyle::default());
+            self.fill_transformed(outline, &brush, CanvasWindingRule::Nonzero, transform);
+        } else {
+            self.stroke_path(shape.geometry(), &brush, width, None);
+        }
+    }
+
+    fn stroke_styled_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        if let Some(transform) = gradient_transform(&brush) {
+            let outline = util::expand_stroke(shape, width, style);
+            self.fill_transformed(outline, &brush, CanvasWindingRule::Nonzero, transform);
+        } else {
+            self.stroke_path(shape.geometry(), &brush, width, Some(style));
+        }
+    }
+
+    fn clip_cached(&mut self, shape: &Self::CachedShape) {
+        self.ctx
+            .clip_with_path_2d_and_winding(shape.geometry(), CanvasWindingRule::Nonzero);
     }
 
     fn text(&mut self) -> &mut Self::Text {
@@ -313,7 +503,17 @@ impl RenderContext for WebRenderContext<'_> {
     }
 
     fn current_transform(&self) -> Affine {
-        matrix_to_affine(self.ctx.get_transform().unwrap())
+        // the canvas's transform includes the scale set up by the embedder,
+        // which `scale_factor` reports separately
+        Affine::scale(self.scale.recip()) * self.canvas_transform()
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.scale
+    }
+
+    fn backend_handle(&self) -> Option<&dyn Any> {
+        Some(&self.ctx)
     }
 
     fn make_image_with_stride(
@@ -324,78 +524,12 @@ impl RenderContext for WebRenderContext<'_> {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
-        if buf.len()
-            < piet::util::expected_image_buffer_size(
-                format.bytes_per_pixel() * width,
-                height,
-                stride,
-            )
-        {
-            return Err(Error::InvalidInput);
-        }
+        let image_data = image_data(width, height, stride, buf, format)?;
         let document = self.window.document().unwrap();
         let element = document.create_element("canvas").unwrap();
         let canvas = element.dyn_into::<HtmlCanvasElement>().unwrap();
         canvas.set_width(width as u32);
         canvas.set_height(height as u32);
-        let mut new_buf: Vec<u8>;
-        let buf = match format {
-            ImageFormat::RgbaSeparate => {
-                if stride == width * format.bytes_per_pixel() {
-                    buf
-                } else {
-                    new_buf = piet::util::image_buffer_to_tightly_packed(
-                        buf, width, height, stride, format,
-                    )?;
-                    new_buf.as_slice()
-                }
-            }
-            ImageFormat::RgbaPremul => {
-                new_buf = vec![0; width * height * 4];
-                for y in 0..height {
-                    for x in 0..width {
-                        let src_offset = y * stride + x * 4;
-                        let dst_offset = (y * width + x) * 4;
-                        let a = buf[src_offset + 3];
-                        new_buf[dst_offset + 0] = unpremul(buf[src_offset + 0], a);
-                        new_buf[dst_offset + 1] = unpremul(buf[src_offset + 1], a);
-                        new_buf[dst_offset + 2] = unpremul(buf[src_offset + 2], a);
-                    }
-                }
-                new_buf.as_slice()
-            }
-            ImageFormat::Rgb => {
-                new_buf = vec![0; width * height * 4];
-                for y in 0..height {
-                    for x in 0..width {
-                        let src_offset = y * stride + x * 3;
-                        let dst_offset = (y * width + x) * 4;
-                        new_buf[dst_offset + 0] = buf[src_offset + 0];
-                        new_buf[dst_offset + 1] = buf[src_offset + 1];
-                        new_buf[dst_offset + 2] = buf[src_offset + 2];
-                        new_buf[dst_offset + 3] = 255;
-                    }
-                }
-                new_buf.as_slice()
-            }
-            ImageFormat::Grayscale => {
-                new_buf = vec![0; width * height * 4];
-                for y in 0..height {
-                    for x in 0..width {
-                        let src_offset = y * stride + x;
-                        let dst_offset = (y * width + x) * 4;
-                        new_buf[dst_offset + 0] = buf[src_offset];
-                        new_buf[dst_offset + 1] = buf[src_offset];
-                        new_buf[dst_offset + 2] = buf[src_offset];
-                        new_buf[dst_offset + 3] = 255;
-                    }
-                }
-                new_buf.as_slice()
-            }
-            _ => &[],
-        };
-
-        let image_data = ImageData::new_with_u8_clamped_array(Clamped(buf), width as u32).wrap()?;
         let context = canvas
             .get_context("2d")
             .unwrap()
@@ -404,12 +538,55 @@ impl RenderContext for WebRenderContext<'_> {
             .unwrap();
         context.put_image_data(&image_data, 0.0, 0.0).wrap()?;
         Ok(WebImage {
-            inner: canvas,
+            source: ImageSource::Canvas(canvas),
             width: width as u32,
             height: height as u32,
         })
     }
 
+    /// Images from [`WebRenderContext::make_image_async`] are backed by an
+    /// `ImageBitmap`, which can't be changed, and are replaced with a new
+    /// image.
+    fn update_image(
+        &mut self,
+        image: &mut Self::Image,
+        buf: &[u8],
+        format: ImageFormat,
+        region: Option<Rect>,
+    ) -> Result<(), Error> {
+        let (width, height) = (image.width as usize, image.height as usize);
+        let canvas = match &image.source {
+            ImageSource::Canvas(canvas) => canvas,
+            ImageSource::Bitmap(_) => {
+                *image = self.make_image(width, height, buf, format)?;
+                return Ok(());
+            }
+        };
+        let stride = width * format.bytes_per_pixel();
+        piet::util::validate_image_args(width, height, stride, format, buf.len())?;
+        let region = piet::util::image_region(region, width, height);
+        if region.area() == 0.0 {
+            return Ok(());
+        }
+        let (x0, y0) = (region.x0 as usize, region.y0 as usize);
+        let buf = &buf[y0 * stride + x0 * format.bytes_per_pixel()..];
+        let image_data = image_data(
+            region.width() as usize,
+            region.height() as usize,
+            stride,
+            buf,
+            format,
+        )?;
+        let context = canvas
+            .get_context("2d")
+            .wrap()?
+            .ok_or(Error::NotSupported)?
+            .unchecked_into::<CanvasRenderingContext2d>();
+        context
+            .put_image_data(&image_data, region.x0, region.y0)
+            .wrap()
+    }
+
     #[inline]
     fn draw_image(
         &mut self,
@@ -417,7 +594,8 @@ impl RenderContext for WebRenderContext<'_> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, None, dst_rect.into(), interp);
+        let src_rect = Rect::new(0.0, 0.0, image.width as f64, image.height as f64);
+        draw_images(self, image, &[(src_rect, dst_rect.into())], interp, 1.0);
     }
 
     #[inline]
@@ -428,7 +606,88 @@ impl RenderContext for WebRenderContext<'_> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, Some(src_rect.into()), dst_rect.into(), interp);
+        draw_images(
+            self,
+            image,
+            &[(src_rect.into(), dst_rect.into())],
+            interp,
+            1.0,
+        );
+    }
+
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    ) {
+        draw_images(
+            self,
+            image,
+            &[(src_rect.into(), dst_rect.into())],
+            interp,
+            opacity.clamp(0.0, 1.0),
+        );
+    }
+
+    fn draw_image_batch(
+        &mut self,
+        image: &Self::Image,
+        rects: &[(Rect, Rect)],
+        interp: InterpolationMode,
+    ) {
+        draw_images(self, image, rects, interp, 1.0);
+    }
+
+    fn draw_image_tiled(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        tile_mode: TileMode,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into().abs();
+        if tile_mode == TileMode::Mirror {
+            // canvas patterns can't mirror, so draw each copy that can be
+            // seen on the canvas
+            let visible = self.visible_rect();
+            let tiles = util::image_tiles(image.size(), dst_rect, visible, tile_mode);
+            for (src_rect, transform) in tiles {
+                let _ = self.with_save(|rc| {
+                    rc.transform(transform);
+                    draw_images(rc, image, &[(src_rect, src_rect)], interp, 1.0);
+                    Ok(())
+                });
+            }
+            return;
+        }
+        let result = self.with_save(|rc| {
+            let pattern = match &image.source {
+                ImageSource::Canvas(canvas) => rc
+                    .ctx
+                    .create_pattern_with_html_canvas_element(canvas, "repeat"),
+                ImageSource::Bitmap(bitmap) => {
+                    rc.ctx.create_pattern_with_image_bitmap(bitmap, "repeat")
+                }
+            }
+            .wrap()?;
+            // there is no pattern for an empty image
+            let Some(pattern) = pattern else {
+                return Ok(());
+            };
+            rc.ctx.set_global_alpha(1.0);
+            #[allow(deprecated)]
+            rc.ctx.set_fill_style(&pattern);
+            rc.ctx.translate(dst_rect.x0, dst_rect.y0).wrap()?;
+            rc.ctx
+                .fill_rect(0.0, 0.0, dst_rect.width(), dst_rect.height());
+            Ok(())
+        });
+        if let Err(e) = result {
+            self.err = Err(e);
+        }
     }
 
     fn capture_image_area(&mut self, _rect: impl Into<Rect>) -> Result<Self::Image, Error> {
@@ -437,46 +696,149 @@ impl RenderContext for WebRenderContext<'_> {
 
     fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl IntoBrush<Self>) {
         let brush = brush.make_brush(self, || rect);
-        self.ctx.set_shadow_blur(blur_radius);
-        let color = match *brush {
-            Brush::Solid(rgba) => format_color(rgba),
-            // Gradients not yet implemented.
-            Brush::Gradient(_) => "#f0f".into(),
-        };
-        self.ctx.set_shadow_color(&color);
-        self.ctx
-            .fill_rect(rect.x0, rect.y0, rect.width(), rect.height());
-        self.ctx.set_shadow_color("none");
+        let transform = self.canvas_transform();
+        // Filters and shadows are measured in canvas pixels, ignoring the
+        // current transform.
+        let scale = transform.determinant().abs().sqrt();
+        if scale == 0.0 {
+            return;
+        }
+        let result = self.with_save(|rc| {
+            rc.set_brush(&brush, true);
+            if supports_filter(&rc.ctx) {
+                rc.ctx
+                    .set_filter(&format!("blur({}px)", blur_radius * scale));
+                rc.ctx
+                    .fill_rect(rect.x0, rect.y0, rect.width(), rect.height());
+            } else if let Brush::Solid(rgba) = *brush {
+                rc.fill_rect_shadow(rect, blur_radius * scale, rgba, transform);
+            } else {
+                // without filters, we can only blur a shadow, and a shadow
+                // can't be a gradient.
+                rc.ctx
+                    .fill_rect(rect.x0, rect.y0, rect.width(), rect.height());
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            self.err = Err(e);
+        }
     }
 }
 
-fn draw_image(
+/// Convert an image buffer to unpremultiplied RGBA `ImageData`.
+fn image_data(
+    width: usize,
+    height: usize,
+    stride: usize,
+    buf: &[u8],
+    format: ImageFormat,
+) -> Result<ImageData, Error> {
+    piet::util::validate_image_args(width, height, stride, format, buf.len())?;
+    let mut new_buf: Vec<u8>;
+    let buf = match format {
+        ImageFormat::RgbaSeparate => {
+            if stride == width * format.bytes_per_pixel() {
+                buf
+            } else {
+                new_buf =
+                    piet::util::image_buffer_to_tightly_packed(buf, width, height, stride, format)?;
+                new_buf.as_slice()
+            }
+        }
+        ImageFormat::RgbaPremul => {
+            new_buf = vec![0; width * height * 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let src_offset = y * stride + x * 4;
+                    let dst_offset = (y * width + x) * 4;
+                    let a = buf[src_offset + 3];
+                    new_buf[dst_offset + 0] = unpremul(buf[src_offset + 0], a);
+                    new_buf[dst_offset + 1] = unpremul(buf[src_offset + 1], a);
+                    new_buf[dst_offset + 2] = unpremul(buf[src_offset + 2], a);
+                }
+            }
+            new_buf.as_slice()
+        }
+        ImageFormat::Rgb => {
+            new_buf = vec![0; width * height * 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let src_offset = y * stride + x * 3;
+                    let dst_offset = (y * width + x) * 4;
+                    new_buf[dst_offset + 0] = buf[src_offset + 0];
+                    new_buf[dst_offset + 1] = buf[src_offset + 1];
+                    new_buf[dst_offset + 2] = buf[src_offset + 2];
+                    new_buf[dst_offset + 3] = 255;
+                }
+            }
+            new_buf.as_slice()
+        }
+        ImageFormat::Grayscale => {
+            new_buf = vec![0; width * height * 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let src_offset = y * stride + x;
+                    let dst_offset = (y * width + x) * 4;
+                    new_buf[dst_offset + 0] = buf[src_offset];
+                    new_buf[dst_offset + 1] = buf[src_offset];
+                    new_buf[dst_offset + 2] = buf[src_offset];
+                    new_buf[dst_offset + 3] = 255;
+                }
+            }
+            new_buf.as_slice()
+        }
+        _ => &[],
+    };
+
+    ImageData::new_with_u8_clamped_array(Clamped(buf), width as u32).wrap()
+}
+
+/// Draw areas of an image, saving and restoring the canvas state once for
+/// the whole batch.
+fn draw_images(
     ctx: &mut WebRenderContext,
     image: &<WebRenderContext as RenderContext>::Image,
-    src_rect: Option<Rect>,
-    dst_rect: Rect,
+    rects: &[(Rect, Rect)],
     _interp: InterpolationMode,
+    opacity: f64,
 ) {
     let result = ctx.with_save(|rc| {
+        rc.ctx.set_global_alpha(opacity);
         // TODO: Implement InterpolationMode::NearestNeighbor in software
         //       See for inspiration http://phrogz.net/tmp/canvas_image_zoom.html
-        let src_rect = match src_rect {
-            Some(src_rect) => src_rect,
-            None => Rect::new(0.0, 0.0, image.width as f64, image.height as f64),
-        };
-        rc.ctx
-            .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
-                &image.inner,
-                src_rect.x0,
-                src_rect.y0,
-                src_rect.width(),
-                src_rect.height(),
-                dst_rect.x0,
-                dst_rect.y0,
-                dst_rect.width(),
-                dst_rect.height(),
-            )
-            .wrap()
+        for (src, dst) in rects {
+            match &image.source {
+                ImageSource::Canvas(canvas) => rc
+                    .ctx
+                    .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                        canvas,
+                        src.x0,
+                        src.y0,
+                        src.width(),
+                        src.height(),
+                        dst.x0,
+                        dst.y0,
+                        dst.width(),
+                        dst.height(),
+                    ),
+                ImageSource::Bitmap(bitmap) => rc
+                    .ctx
+                    .draw_image_with_image_bitmap_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                        bitmap,
+                        src.x0,
+                        src.y0,
+                        src.width(),
+                        src.height(),
+                        dst.x0,
+                        dst.y0,
+                        dst.width(),
+                        dst.height(),
+                    ),
+            }
+            .wrap()?;
+        }
+        Ok(())
     });
     if let Err(e) = result {
         ctx.err = Err(e);
@@ -497,6 +859,15 @@ impl Image for WebImage {
     fn size(&self) -> Size {
         Size::new(self.width.into(), self.height.into())
     }
+
+    fn format(&self) -> ImageFormat {
+        // canvas pixels are read back unpremultiplied
+        ImageFormat::RgbaSeparate
+    }
+
+    fn stride(&self) -> usize {
+        self.width as usize * 4
+    }
 }
 
 fn format_color(rgba: u32) -> String {
@@ -530,6 +901,11 @@ impl WebRenderContext<'_> {
     /// This is part of the impedance matching.
     fn set_brush(&mut self, brush: &Brush, is_fill: bool) {
         let value = self.brush_value(brush);
+        let alpha = match *brush {
+            Brush::Solid(_) => 1.0,
+            Brush::Gradient(_, alpha, _) => alpha,
+        };
+        self.ctx.set_global_alpha(alpha);
         if is_fill {
             #[allow(deprecated)]
             self.ctx.set_fill_style(&value);
@@ -539,10 +915,51 @@ impl WebRenderContext<'_> {
         }
     }
 
+    /// The transform from user space to canvas pixels, including the scale
+    /// factor.
+    fn canvas_transform(&self) -> Affine {
+        matrix_to_affine(self.ctx.get_transform().unwrap())
+    }
+
+    /// The bounds of the canvas, in user space.
+    fn visible_rect(&self) -> Rect {
+        let transform = self.canvas_transform();
+        let Some(canvas) = self.ctx.canvas() else {
+            return Rect::ZERO;
+        };
+        if transform.determinant() == 0.0 {
+            return Rect::ZERO;
+        }
+        let bounds = Rect::new(0.0, 0.0, canvas.width() as f64, canvas.height() as f64);
+        transform.inverse().transform_rect_bbox(bounds)
+    }
+
+    /// Draw only the blurred shadow of a rect, for browsers without canvas
+    /// filters.
+    ///
+    /// The rect is filled just off the right of the canvas, with its shadow
+    /// offset back to where the rect belongs.
+    fn fill_rect_shadow(&mut self, rect: Rect, std_dev: f64, rgba: u32, transform: Affine) {
+        let canvas_width = self.ctx.canvas().map(|c| c.width()).unwrap_or(0) as f64;
+        let bounds = transform.transform_rect_bbox(rect);
+        let offset = canvas_width - bounds.x0 + 1.0;
+        // the same offset, in the coordinates of the rect
+        let [a, b, c, d, _, _] = transform.as_coeffs();
+        let shift = Affine::new([a, b, c, d, 0.0, 0.0]).inverse() * Point::new(offset, 0.0);
+        let rect = rect + shift.to_vec2();
+        self.ctx.set_shadow_offset_x(-offset);
+        self.ctx.set_shadow_offset_y(0.0);
+        // the shadow blur is twice the standard deviation
+        self.ctx.set_shadow_blur(std_dev * 2.0);
+        self.ctx.set_shadow_color(&format_color(rgba));
+        self.ctx
+            .fill_rect(rect.x0, rect.y0, rect.width(), rect.height());
+    }
+
     fn brush_value(&self, brush: &Brush) -> JsValue {
         match *brush {
             Brush::Solid(rgba) => JsValue::from_str(&format_color(rgba)),
-            Brush::Gradient(ref gradient) => JsValue::from(gradient),
+            Brush::Gradient(ref gradient, _, _) => JsValue::from(gradient),
         }
     }
 
@@ -582,22 +999,65 @@ impl WebRenderContext<'_> {
         }
     }
 
-    fn set_path(&mut self, shape: impl Shape) {
-        // This shouldn't be necessary, we always leave the context in no-path
-        // state. But just in case, and it should be harmless.
-        self.ctx.begin_path();
-        for el in shape.path_elements(1e-3) {
-            match el {
-                PathEl::MoveTo(p) => self.ctx.move_to(p.x, p.y),
-                PathEl::LineTo(p) => self.ctx.line_to(p.x, p.y),
-                PathEl::QuadTo(p1, p2) => self.ctx.quadratic_curve_to(p1.x, p1.y, p2.x, p2.y),
-                PathEl::CurveTo(p1, p2, p3) => {
-                    self.ctx.bezier_curve_to(p1.x, p1.y, p2.x, p2.y, p3.x, p3.y)
-                }
-                PathEl::ClosePath => self.ctx.close_path(),
+    /// A `Path2D` for the shape, or `None` if one couldn't be created.
+    fn path(&mut self, shape: impl Shape) -> Option<Path2d> {
+        match path::cached_path_2d(shape).wrap() {
+            Ok(path) => Some(path),
+            Err(e) => {
+                self.err = Err(e);
+                None
             }
         }
     }
+
+    /// Fill a shape with a gradient that is drawn under `transform`.
+    ///
+    /// The canvas is transformed so that the circular gradient becomes the
+    /// one the brush describes, and the shape is drawn through the inverse
+    /// of that transform so that it stays where it is. Strokes are filled
+    /// as outlines, so that their width isn't transformed too.
+    fn fill_transformed(
+        &mut self,
+        shape: impl Shape,
+        brush: &Brush,
+        rule: CanvasWindingRule,
+        transform: Affine,
+    ) {
+        let path = transform.inverse() * shape.into_path(1e-3);
+        let Some(path) = self.path(path) else {
+            return;
+        };
+        self.ctx.save();
+        let a = transform.as_coeffs();
+        let _ = self.ctx.transform(a[0], a[1], a[2], a[3], a[4], a[5]);
+        self.fill_path(&path, brush, rule);
+        self.ctx.restore();
+    }
+
+    fn fill_path(&mut self, path: &Path2d, brush: &Brush, rule: CanvasWindingRule) {
+        self.set_brush(brush, true);
+        self.ctx.fill_with_path_2d_and_winding(path, rule);
+    }
+
+    fn stroke_path(
+        &mut self,
+        path: &Path2d,
+        brush: &Brush,
+        width: f64,
+        style: Option<&StrokeStyle>,
+    ) {
+        self.set_stroke(width, style);
+        self.set_brush(brush, false);
+        self.ctx.stroke_with_path(path);
+    }
+}
+
+/// The transform a brush's gradient is drawn under, if it isn't the identity.
+fn gradient_transform(brush: &Brush) -> Option<Affine> {
+    match *brush {
+        Brush::Gradient(_, _, transform) if transform != Affine::IDENTITY => Some(transform),
+        _ => None,
+    }
 }
 
 fn byte_to_frac(byte: u32) -> f64 {
@@ -614,3 +1074,88 @@ fn matrix_to_affine(matrix: DomMatrix) -> Affine {
         matrix.f(),
     ])
 }
+
+#[cfg(test)]
+mod tests {
+    use piet::kurbo::{Affine, Point, Rect, Vec2};
+    use piet::{Color, FixedEllipticalGradient, GradientStops, RenderContext};
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::*;
+    use web_sys::{window, CanvasRenderingContext2d, HtmlCanvasElement};
+
+    use crate::WebRenderContext;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    /// A context drawing to a new canvas, scaled by `scale` like piet-common
+    /// scales its targets.
+    fn scaled_context(scale: f64) -> WebRenderContext<'static> {
+        let window = window().unwrap();
+        let canvas = window
+            .document()
+            .unwrap()
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into::<HtmlCanvasElement>()
+            .unwrap();
+        canvas.set_width(100);
+        canvas.set_height(100);
+        let ctx = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()
+            .unwrap();
+        let _ = ctx.scale(scale, scale);
+        let mut rc = WebRenderContext::new(ctx, window);
+        rc.set_scale(scale);
+        rc
+    }
+
+    #[wasm_bindgen_test]
+    fn scale_factor_is_not_counted_twice() {
+        let mut rc = scaled_context(2.0);
+        rc.transform(Affine::translate((1.0, 0.0)));
+        assert_eq!(rc.current_transform(), Affine::translate((1.0, 0.0)));
+        // half a unit is a device pixel
+        assert_eq!(rc.snap_point(Point::new(0.3, 0.3)), Point::new(0.5, 0.5));
+    }
+
+    #[wasm_bindgen_test]
+    fn mesh_is_drawn_in_device_pixels() {
+        let mut rc = scaled_context(2.0);
+        let red = Color::rgb8(0xff, 0, 0);
+        let vertices =
+            [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)].map(|p| (Point::from(p), red));
+        rc.fill_mesh(&vertices, &[0, 1, 2, 0, 2, 3]);
+        rc.finish().unwrap();
+
+        // the square covers 20 device pixels, and its edge is crisp
+        let alpha = |x: f64, y: f64| rc.ctx.get_image_data(x, y, 1.0, 1.0).unwrap().data()[3];
+        assert_eq!(alpha(18.0, 18.0), 0xff);
+        assert_eq!(alpha(21.0, 21.0), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn elliptical_gradient_is_stretched() {
+        let mut rc = scaled_context(1.0);
+        let gradient = FixedEllipticalGradient {
+            center: Point::new(50.0, 50.0),
+            origin_offset: Vec2::ZERO,
+            radii: Vec2::new(40.0, 10.0),
+            stops: (Color::BLACK, Color::WHITE).to_vec(),
+        };
+        let brush = rc.gradient(gradient).unwrap();
+        rc.fill(Rect::new(0.0, 0.0, 100.0, 100.0), &brush);
+        rc.finish().unwrap();
+
+        // halfway along each radius is the same grey
+        let red = |x: f64, y: f64| rc.ctx.get_image_data(x, y, 1.0, 1.0).unwrap().data()[0];
+        let along = red(70.0, 50.0);
+        let across = red(50.0, 55.0);
+        assert!(along.abs_diff(across) <= 8, "{along} != {across}");
+        assert!((100..156).contains(&along));
+        // past the short radius is white
+        assert_eq!(red(50.0, 70.0), 0xff);
+    }
+}
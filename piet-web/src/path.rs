@@ -0,0 +1,153 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Building `Path2D` objects, and caching them for complex shapes.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use wasm_bindgen::JsValue;
+use web_sys::Path2d;
+
+use piet::kurbo::{Arc, PathEl, Point, Shape};
+use piet::util;
+
+/// The number of paths kept by the path cache, unless changed with
+/// [`WebRenderContext::set_path_cache_size`].
+///
+/// [`WebRenderContext::set_path_cache_size`]: crate::WebRenderContext::set_path_cache_size
+pub(crate) const DEFAULT_PATH_CACHE_SIZE: usize = 64;
+
+/// Shapes with fewer elements than this are cheaper to build again than to
+/// look up.
+const MIN_CACHED_ELEMENTS: usize = 16;
+
+thread_local! {
+    // A new render context is usually created for each frame, so the cache
+    // lives here, where it survives from frame to frame. A `Path2D` isn't
+    // tied to a canvas, so it can be shared by every context.
+    static PATH_CACHE: RefCell<PathCache> =
+        RefCell::new(PathCache::new(DEFAULT_PATH_CACHE_SIZE));
+}
+
+/// A least-recently-used cache of built paths.
+struct PathCache {
+    capacity: usize,
+    // Ordered from least to most recently used.
+    entries: Vec<(u64, Vec<PathEl>, Path2d)>,
+}
+
+impl PathCache {
+    fn new(capacity: usize) -> Self {
+        PathCache {
+            capacity,
+            entries: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, hash: u64, elements: &[PathEl]) -> Option<Path2d> {
+        let idx = self
+            .entries
+            .iter()
+            .rposition(|(h, els, _)| *h == hash && els == elements)?;
+        let entry = self.entries.remove(idx);
+        let path = entry.2.clone();
+        self.entries.push(entry);
+        Some(path)
+    }
+
+    fn insert(&mut self, hash: u64, elements: Vec<PathEl>, path: Path2d) {
+        if self.capacity > 0 {
+            self.entries.push((hash, elements, path));
+            self.evict();
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.capacity);
+        self.entries.drain(..excess);
+    }
+}
+
+/// Set the number of paths kept by the cache on the current thread.
+pub(crate) fn set_cache_size(size: usize) {
+    PATH_CACHE.with(|cache| cache.borrow_mut().set_capacity(size));
+}
+
+/// A `Path2D` for `shape`, from the cache if the same shape was drawn
+/// recently.
+pub(crate) fn cached_path_2d(shape: impl Shape) -> Result<Path2d, JsValue> {
+    if let Some(arc) = util::as_arc(&shape) {
+        return arc_path_2d(&arc);
+    }
+    let elements: Vec<PathEl> = shape.path_elements(1e-3).collect();
+    if elements.len() < MIN_CACHED_ELEMENTS {
+        return path_2d(elements);
+    }
+    let hash = hash_elements(&elements);
+    if let Some(path) = PATH_CACHE.with(|cache| cache.borrow_mut().get(hash, &elements)) {
+        return Ok(path);
+    }
+    let path = path_2d(elements.iter().copied())?;
+    PATH_CACHE.with(|cache| cache.borrow_mut().insert(hash, elements, path.clone()));
+    Ok(path)
+}
+
+/// Build a `Path2D` from path elements.
+pub(crate) fn path_2d(elements: impl IntoIterator<Item = PathEl>) -> Result<Path2d, JsValue> {
+    let path = Path2d::new()?;
+    for el in elements {
+        match el {
+            PathEl::MoveTo(p) => path.move_to(p.x, p.y),
+            PathEl::LineTo(p) => path.line_to(p.x, p.y),
+            PathEl::QuadTo(p1, p2) => path.quadratic_curve_to(p1.x, p1.y, p2.x, p2.y),
+            PathEl::CurveTo(p1, p2, p3) => path.bezier_curve_to(p1.x, p1.y, p2.x, p2.y, p3.x, p3.y),
+            PathEl::ClosePath => path.close_path(),
+        }
+    }
+    Ok(path)
+}
+
+/// Build a `Path2D` for an elliptical arc, with the canvas's own ellipse
+/// rather than Béziers.
+pub(crate) fn arc_path_2d(arc: &Arc) -> Result<Path2d, JsValue> {
+    let path = Path2d::new()?;
+    path.ellipse_with_anticlockwise(
+        arc.center.x,
+        arc.center.y,
+        arc.radii.x,
+        arc.radii.y,
+        arc.x_rotation,
+        arc.start_angle,
+        arc.start_angle + arc.sweep_angle,
+        arc.sweep_angle < 0.0,
+    )?;
+    if arc.sweep_angle.abs() == std::f64::consts::TAU {
+        path.close_path();
+    }
+    Ok(path)
+}
+
+fn hash_elements(elements: &[PathEl]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for el in elements {
+        std::mem::discriminant(el).hash(&mut hasher);
+        let points = match *el {
+            PathEl::MoveTo(p) | PathEl::LineTo(p) => [p, Point::ZERO, Point::ZERO],
+            PathEl::QuadTo(p1, p2) => [p1, p2, Point::ZERO],
+            PathEl::CurveTo(p1, p2, p3) => [p1, p2, p3],
+            PathEl::ClosePath => [Point::ZERO; 3],
+        };
+        for p in points {
+            p.x.to_bits().hash(&mut hasher);
+            p.y.to_bits().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
@@ -8,7 +8,7 @@ mod lines;
 
 use std::borrow::Cow;
 use std::fmt;
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
 use std::rc::Rc;
 
 use web_sys::CanvasRenderingContext2d;
@@ -16,8 +16,8 @@ use web_sys::CanvasRenderingContext2d;
 use piet::kurbo::{Point, Rect, Size};
 
 use piet::{
-    util, Color, Error, FontFamily, HitTestPoint, HitTestPosition, LineMetric, Text, TextAttribute,
-    TextLayout, TextLayoutBuilder, TextStorage,
+    util, Affinity, Color, Error, FontFamily, HitTestPoint, HitTestPosition, LineMetric, Text,
+    TextAttribute, TextLayout, TextLayoutBuilder, TextStorage,
 };
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -40,9 +40,12 @@ pub struct WebTextLayout {
 
     // Calculated on build
     pub(crate) line_metrics: Vec<LineMetric>,
+    /// The width of each line without trailing whitespace.
+    line_widths: Vec<f64>,
     size: Size,
     trailing_ws_width: f64,
     color: Color,
+    links: util::TextLinks,
 }
 
 pub struct WebTextLayoutBuilder {
@@ -50,6 +53,7 @@ pub struct WebTextLayoutBuilder {
     text: Rc<dyn TextStorage>,
     width: f64,
     defaults: util::LayoutDefaults,
+    links: util::TextLinks,
 }
 
 /// <https://developer.mozilla.org/en-US/docs/Web/CSS/font-style>
@@ -81,6 +85,7 @@ impl Text for WebText {
             text: Rc::new(text),
             width: f64::INFINITY,
             defaults: Default::default(),
+            links: Default::default(),
         }
     }
 }
@@ -152,17 +157,31 @@ impl TextLayoutBuilder for WebTextLayoutBuilder {
         self
     }
 
+    fn antialiasing(self, _antialiasing: piet::TextAntialiasing) -> Self {
+        // canvas gives no control over how text is antialiased.
+        self
+    }
+
     fn default_attribute(mut self, attribute: impl Into<TextAttribute>) -> Self {
-        self.defaults.set(attribute);
+        match attribute.into() {
+            TextAttribute::Link(url) => self.links.add(0..self.text.len(), &url),
+            attribute => self.defaults.set(attribute),
+        }
         self
     }
 
     fn range_attribute(
-        self,
-        _range: impl RangeBounds<usize>,
-        _attribute: impl Into<TextAttribute>,
+        mut self,
+        range: impl RangeBounds<usize>,
+        attribute: impl Into<TextAttribute>,
     ) -> Self {
-        web_sys::console::log_1(&"Text attributes not yet implemented for web".into());
+        // links don't change how the text is drawn, so they are supported
+        if let TextAttribute::Link(url) = attribute.into() {
+            let range = util::resolve_range(range, self.text.len());
+            self.links.add(range, &url);
+        } else {
+            web_sys::console::log_1(&"Text attributes not yet implemented for web".into());
+        }
         self
     }
 
@@ -177,9 +196,11 @@ impl TextLayoutBuilder for WebTextLayoutBuilder {
             font,
             text: self.text,
             line_metrics: Vec::new(),
+            line_widths: Vec::new(),
             size: Size::ZERO,
             trailing_ws_width: 0.0,
             color: self.defaults.fg_color,
+            links: self.links,
         };
 
         layout.update_width(self.width);
@@ -207,6 +228,27 @@ impl TextLayout for WebTextLayout {
         self.size.to_rect()
     }
 
+    fn with_max_width(&self, width: f64) -> Self {
+        let mut layout = self.clone();
+        layout.update_width(width);
+        layout
+    }
+
+    fn with_color_override(&self, range: impl RangeBounds<usize>, color: Color) -> Self {
+        let range = util::resolve_range(range, self.text.len());
+        let mut layout = self.clone();
+        if range == (0..self.text.len()) {
+            layout.color = color;
+        } else {
+            web_sys::console::log_1(&"Text attributes not yet implemented for web".into());
+        }
+        layout
+    }
+
+    fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.links.links()
+    }
+
     fn text(&self) -> &str {
         &self.text
     }
@@ -225,6 +267,14 @@ impl TextLayout for WebTextLayout {
         self.line_metrics.len()
     }
 
+    // Alignment is not supported, so every line starts at zero.
+    fn line_bounds(&self, line_number: usize) -> Option<Rect> {
+        let metric = self.line_metrics.get(line_number)?;
+        let width = self.line_widths[line_number];
+        let y1 = metric.y_offset + metric.height;
+        Some(Rect::new(0.0, metric.y_offset, width, y1))
+    }
+
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
         self.ctx.set_font(&self.font.get_font_string());
         // internal logic is using grapheme clusters, but return the text position associated
@@ -321,9 +371,10 @@ impl WebTextLayout {
             lines::calculate_line_metrics(&self.text, &self.ctx, new_width, self.font.size);
 
         if self.text.is_empty() {
+            let (height, baseline) = lines::line_height_and_baseline(self.font.size);
             line_metrics.push(LineMetric {
-                baseline: self.font.size * 0.2,
-                height: self.font.size * 1.2,
+                baseline,
+                height,
                 ..Default::default()
             })
         } else if util::trailing_nlf(&self.text).is_some() {
@@ -342,7 +393,7 @@ impl WebTextLayout {
             line_metrics.push(newline_eof);
         }
 
-        let (width, ws_width) = line_metrics
+        let widths: Vec<(f64, f64)> = line_metrics
             .iter()
             .map(|lm| {
                 let full_width = text_width(&self.text[lm.range()], &self.ctx);
@@ -354,6 +405,9 @@ impl WebTextLayout {
                 };
                 (non_ws_width, full_width)
             })
+            .collect();
+        let (width, ws_width) = widths
+            .iter()
             .fold((0.0, 0.0), |a: (f64, f64), b| (a.0.max(b.0), a.1.max(b.1)));
 
         let height = line_metrics
@@ -361,6 +415,7 @@ impl WebTextLayout {
             .map(|l| l.y_offset + l.height)
             .unwrap_or_default();
         self.line_metrics = line_metrics;
+        self.line_widths = widths.into_iter().map(|(width, _)| width).collect();
         self.trailing_ws_width = ws_width;
         self.size = Size::new(width, height);
     }
@@ -390,7 +445,7 @@ fn hit_test_line_point(ctx: &CanvasRenderingContext2d, text: &str, point: Point)
 
     // first test beyond ends
     if point.x > end_bounds.trailing {
-        return HitTestPoint::new(text.len(), false);
+        return HitTestPoint::new(text.len(), false).with_affinity(Affinity::Upstream);
     }
 
     if point.x <= start_bounds.leading {
@@ -518,6 +573,12 @@ pub(crate) mod test {
         assert!(x <= max && x >= min);
     }
 
+    #[wasm_bindgen_test]
+    pub fn text_conformance() {
+        let (_window, context) = setup_ctx();
+        piet::conformance::check_all(&mut WebText::new(context));
+    }
+
     #[wasm_bindgen_test]
     pub fn test_hit_test_text_position_basic() {
         let (_window, context) = setup_ctx();
@@ -1,7 +1,7 @@
 // Copyright 2019 the Piet Authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use piet::HitTestPoint;
+use piet::{Affinity, HitTestPoint};
 use unicode_segmentation::UnicodeSegmentation;
 use web_sys::CanvasRenderingContext2d;
 
@@ -50,12 +50,12 @@ pub(crate) fn point_x_in_grapheme(
         // Round up to next grapheme boundary if
         let midpoint = leading + ((trailing - leading) / 2.0);
         let is_inside = true;
-        let idx = if point_x >= midpoint {
-            next_idx
+        let (idx, affinity) = if point_x >= midpoint {
+            (next_idx, Affinity::Upstream)
         } else {
-            curr_idx
+            (curr_idx, Affinity::Downstream)
         };
-        Some(HitTestPoint::new(idx, is_inside))
+        Some(HitTestPoint::new(idx, is_inside).with_affinity(affinity))
     } else {
         None
     }
@@ -13,6 +13,15 @@ use xi_unicode::LineBreakIterator;
 
 use super::{text_width, LineMetric};
 
+/// The height and baseline of a line of text at this size.
+///
+/// These are heuristics, because we don't have access to font metrics
+/// through web-sys yet.
+pub(crate) fn line_height_and_baseline(font_size: f64) -> (f64, f64) {
+    let height = font_size * 1.2;
+    (height, height * 0.8)
+}
+
 // NOTE font_size is used only for heuristic purposes, prefer actual web-api for height and
 // baseline when available.
 #[allow(clippy::branches_sharing_code)] // clearer as written
@@ -54,9 +63,7 @@ pub(crate) fn calculate_line_metrics(
     let mut y_offset = 0.0;
 
     // Vertical measures constant across all lines for now (web text)
-    // We use heuristics because we don't have access to web apis through web-sys yet.
-    let height = font_size * 1.2;
-    let baseline = height * 0.8;
+    let (height, baseline) = line_height_and_baseline(font_size);
 
     for (line_break, is_hard_break) in LineBreakIterator::new(text) {
         if !is_hard_break {
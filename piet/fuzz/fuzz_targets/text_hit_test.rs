@@ -0,0 +1,99 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Build text layouts from random strings and widths, and check that hit
+//! testing them is consistent.
+//!
+//! The layouts are built with the platform's default backend. Run with:
+//!
+//! ```sh
+//! cd piet
+//! cargo +nightly fuzz run text_hit_test
+//! ```
+
+#![no_main]
+
+use std::cell::RefCell;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use piet::conformance::check_layout_invariants;
+use piet_common::{
+    Device, PietText, RenderContext, Text, TextAttribute, TextLayout, TextLayoutBuilder,
+};
+
+/// Pieces of text that have needed care in hit testing before.
+const PIECES: &[&str] = &[
+    " ",
+    "\n",
+    "\r\n",
+    "\t",
+    "word",
+    "fi",
+    "e\u{301}",
+    "\u{1f44d}\u{1f3fd}",
+    "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}",
+    "\u{5e9}\u{5dc}\u{5d5}\u{5dd}",
+    "\u{645}\u{631}\u{62d}\u{628}\u{627}",
+    "123",
+    "\u{200f}",
+];
+
+#[derive(Debug, Arbitrary)]
+enum Piece {
+    Known(u8),
+    Char(char),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    pieces: Vec<Piece>,
+    font_size: u8,
+    max_width: u16,
+    new_max_width: u16,
+}
+
+impl Input {
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Known(i) => text.push_str(PIECES[*i as usize % PIECES.len()]),
+                Piece::Char(c) => text.push(*c),
+            }
+        }
+        text
+    }
+}
+
+thread_local! {
+    static TEXT: RefCell<PietText> = RefCell::new(make_text());
+}
+
+fn make_text() -> PietText {
+    let mut device = Device::new().expect("failed to create device");
+    let mut target = device
+        .bitmap_target(1, 1, 1.0)
+        .expect("failed to create target");
+    let mut rc = target.render_context();
+    let text = rc.text().clone();
+    rc.finish().expect("failed to finish context");
+    text
+}
+
+fuzz_target!(|input: Input| {
+    if input.pieces.len() > 200 {
+        return;
+    }
+    TEXT.with(|text| {
+        let layout = text
+            .borrow_mut()
+            .new_text_layout(input.text())
+            .default_attribute(TextAttribute::FontSize(4.0 + input.font_size as f64 / 4.0))
+            .max_width(input.max_width as f64)
+            .build()
+            .expect("layout should build");
+        check_layout_invariants(&layout);
+        check_layout_invariants(&layout.with_max_width(input.new_max_width as f64));
+    });
+});
@@ -0,0 +1,593 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! A render context that tracks the area drawn to.
+
+use std::any::Any;
+use std::borrow::Cow;
+
+use kurbo::{Affine, Insets, Point, Rect, Shape};
+
+use crate::{
+    Color, ColorSpace, Error, FixedGradient, Image, ImageBuf, ImageFormat, InterpolationMode,
+    IntoBrush, LineCap, LineJoin, RenderContext, StrokeStyle, StrokeStyleHandle, TextLayout,
+    TileMode,
+};
+
+/// A render context that records the bounding box of everything drawn with
+/// it, and passes the drawing on to another context.
+///
+/// This lets an embedder find the damaged region of a window, for partial
+/// redraw, without tracking every drawing call itself.
+///
+/// The bounds are in the coordinate space of the wrapped context before any
+/// transforms were applied, and take clips into account. They are
+/// conservative: they always contain everything drawn, but may be larger.
+/// Clearing the whole canvas makes them infinite.
+///
+/// ```
+/// use piet::kurbo::{Affine, Rect};
+/// use piet::{BoundsTracker, Color, NullRenderContext, RenderContext};
+///
+/// let mut ctx = NullRenderContext::new();
+/// let mut tracker = BoundsTracker::new(&mut ctx);
+/// tracker.transform(Affine::translate((10.0, 0.0)));
+/// tracker.fill(Rect::new(0.0, 0.0, 20.0, 20.0), &Color::WHITE);
+/// assert_eq!(tracker.take_bounds(), Some(Rect::new(10.0, 0.0, 30.0, 20.0)));
+/// assert_eq!(tracker.bounds(), None);
+/// ```
+pub struct BoundsTracker<'a, R: RenderContext> {
+    inner: &'a mut R,
+    bounds: Option<Rect>,
+    /// The current clip, in the coordinate space of the bounds.
+    clip: Option<Rect>,
+    clip_stack: Vec<Option<Rect>>,
+}
+
+/// The brush type of a [`BoundsTracker`], wrapping a brush of the inner
+/// context.
+pub struct TrackedBrush<R: RenderContext>(R::Brush);
+
+impl<'a, R: RenderContext> BoundsTracker<'a, R> {
+    /// Start tracking drawing done with `inner`.
+    pub fn new(inner: &'a mut R) -> Self {
+        BoundsTracker {
+            inner,
+            bounds: None,
+            clip: None,
+            clip_stack: Vec::new(),
+        }
+    }
+
+    /// The bounding box of everything drawn since this tracker was created,
+    /// or since the last call to [`take_bounds`].
+    ///
+    /// Returns `None` if nothing was drawn.
+    ///
+    /// [`take_bounds`]: BoundsTracker::take_bounds
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    /// Return the bounding box of everything drawn so far, and start
+    /// tracking again from nothing, for instance at the end of a frame.
+    pub fn take_bounds(&mut self) -> Option<Rect> {
+        self.bounds.take()
+    }
+
+    /// The wrapped context.
+    pub fn inner(&mut self) -> &mut R {
+        self.inner
+    }
+
+    /// Record drawing to a rect in the current coordinate space.
+    fn record(&mut self, rect: Rect) {
+        let rect = self.inner.current_transform().transform_rect_bbox(rect);
+        self.record_untransformed(rect);
+    }
+
+    /// Record drawing to a rect that is already in the coordinate space of
+    /// the bounds.
+    fn record_untransformed(&mut self, rect: Rect) {
+        let rect = match self.clip {
+            Some(clip) => rect.intersect(clip),
+            None => rect,
+        };
+        if rect.is_zero_area() {
+            return;
+        }
+        self.bounds = Some(match self.bounds {
+            Some(bounds) => bounds.union(rect),
+            None => rect,
+        });
+    }
+
+    fn record_stroke(&mut self, shape: &impl Shape, width: f64, style: &StrokeStyle) {
+        let extent = stroke_extent(width, style);
+        self.record(shape.bounding_box().inflate(extent, extent));
+    }
+}
+
+/// How far a stroke can extend from the bounding box of its shape.
+fn stroke_extent(width: f64, style: &StrokeStyle) -> f64 {
+    let join = match style.line_join {
+        LineJoin::Miter { limit } => limit.max(1.0),
+        LineJoin::Round | LineJoin::Bevel => 1.0,
+    };
+    let cap = match style.line_cap {
+        LineCap::Square => std::f64::consts::SQRT_2,
+        LineCap::Butt | LineCap::Round => 1.0,
+    };
+    width.abs() * 0.5 * join.max(cap)
+}
+
+impl<R: RenderContext> Clone for TrackedBrush<R> {
+    fn clone(&self) -> Self {
+        TrackedBrush(self.0.clone())
+    }
+}
+
+impl<'a, R: RenderContext> IntoBrush<BoundsTracker<'a, R>> for TrackedBrush<R> {
+    fn make_brush<'b>(
+        &'b self,
+        _piet: &mut BoundsTracker<'a, R>,
+        _bbox: impl FnOnce() -> Rect,
+    ) -> Cow<'b, TrackedBrush<R>> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<'a, R: RenderContext> RenderContext for BoundsTracker<'a, R> {
+    type Brush = TrackedBrush<R>;
+    type Text = R::Text;
+    type TextLayout = R::TextLayout;
+    type Image = R::Image;
+    type CachedShape = R::CachedShape;
+
+    fn status(&mut self) -> Result<(), Error> {
+        self.inner.status()
+    }
+
+    fn solid_brush(&mut self, color: Color) -> Self::Brush {
+        TrackedBrush(self.inner.solid_brush(color))
+    }
+
+    fn solid_brush_with_color_space(
+        &mut self,
+        color: Color,
+        color_space: ColorSpace,
+    ) -> Self::Brush {
+        TrackedBrush(self.inner.solid_brush_with_color_space(color, color_space))
+    }
+
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
+        self.inner.gradient(gradient).map(TrackedBrush)
+    }
+
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Self::Brush, Error> {
+        self.inner
+            .gradient_with_transform(gradient, transform)
+            .map(TrackedBrush)
+    }
+
+    fn brush_with_opacity(&mut self, brush: &Self::Brush, opacity: f64) -> Self::Brush {
+        TrackedBrush(self.inner.brush_with_opacity(&brush.0, opacity))
+    }
+
+    fn clear(&mut self, region: impl Into<Option<Rect>>, color: Color) {
+        let region = region.into();
+        // clearing ignores the transform and the clip
+        let rect = region.unwrap_or(Rect::new(
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            f64::INFINITY,
+        ));
+        self.bounds = Some(match self.bounds {
+            Some(bounds) => bounds.union(rect),
+            None => rect,
+        });
+        self.inner.clear(region, color);
+    }
+
+    fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record_stroke(&shape, width, &StrokeStyle::default());
+        self.inner.stroke(shape, &brush.0, width);
+    }
+
+    fn stroke_styled(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record_stroke(&shape, width, style);
+        self.inner.stroke_styled(shape, &brush.0, width, style);
+    }
+
+    fn register_stroke_style(&mut self, style: &StrokeStyle) -> StrokeStyleHandle {
+        self.inner.register_stroke_style(style)
+    }
+
+    fn stroke_with_handle(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyleHandle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record_stroke(&shape, width, style.style());
+        self.inner.stroke_with_handle(shape, &brush.0, width, style);
+    }
+
+    fn stroke_hairline(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        // a hairline is a device pixel wide, whatever the transform
+        let width = self.inner.scale_factor().recip();
+        let extent = stroke_extent(width, &StrokeStyle::default());
+        let rect = self
+            .inner
+            .current_transform()
+            .transform_rect_bbox(shape.bounding_box());
+        self.record_untransformed(rect.inflate(extent, extent));
+        self.inner.stroke_hairline(shape, &brush.0);
+    }
+
+    fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record(shape.bounding_box());
+        self.inner.fill(shape, &brush.0);
+    }
+
+    fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record(shape.bounding_box());
+        self.inner.fill_even_odd(shape, &brush.0);
+    }
+
+    fn fill_mesh(&mut self, vertices: &[(Point, Color)], indices: &[u16]) {
+        let mut points = vertices.iter().map(|(point, _)| *point);
+        if let Some(first) = points.next() {
+            let rect = points.fold(Rect::from_points(first, first), |rect, point| {
+                rect.union_pt(point)
+            });
+            self.record(rect);
+        }
+        self.inner.fill_mesh(vertices, indices);
+    }
+
+    fn clip(&mut self, shape: impl Shape) {
+        let rect = self
+            .inner
+            .current_transform()
+            .transform_rect_bbox(shape.bounding_box());
+        self.clip = Some(match self.clip {
+            Some(clip) => clip.intersect(rect),
+            None => rect,
+        });
+        self.inner.clip(shape);
+    }
+
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        let rect = self
+            .inner
+            .current_transform()
+            .transform_rect_bbox(shape.bounding_box());
+        self.clip = Some(match self.clip {
+            Some(clip) => clip.intersect(rect),
+            None => rect,
+        });
+        self.inner.clip_even_odd(shape);
+    }
+
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error> {
+        self.inner.prepare_shape(shape)
+    }
+
+    fn fill_cached(&mut self, shape: &Self::CachedShape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record(shape.bounding_box());
+        self.inner.fill_cached(shape, &brush.0);
+    }
+
+    fn stroke_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record_stroke(shape, width, &StrokeStyle::default());
+        self.inner.stroke_cached(shape, &brush.0, width);
+    }
+
+    fn stroke_styled_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.record_stroke(shape, width, style);
+        self.inner
+            .stroke_styled_cached(shape, &brush.0, width, style);
+    }
+
+    fn clip_cached(&mut self, shape: &Self::CachedShape) {
+        let rect = self
+            .inner
+            .current_transform()
+            .transform_rect_bbox(shape.bounding_box());
+        self.clip = Some(match self.clip {
+            Some(clip) => clip.intersect(rect),
+            None => rect,
+        });
+        self.inner.clip_cached(shape);
+    }
+
+    fn text(&mut self) -> &mut Self::Text {
+        self.inner.text()
+    }
+
+    fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        let pos = pos.into();
+        // the ink can extend past the layout's size, and decorations past
+        // its ink
+        let rect = layout.size().to_rect().union(layout.image_bounds());
+        self.record(rect + pos.to_vec2());
+        self.inner.draw_text(layout, pos);
+    }
+
+    fn save(&mut self) -> Result<(), Error> {
+        self.clip_stack.push(self.clip);
+        self.inner.save()
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        if let Some(clip) = self.clip_stack.pop() {
+            self.clip = clip;
+        }
+        self.inner.restore()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.inner.finish()
+    }
+
+    fn transform(&mut self, transform: Affine) {
+        self.inner.transform(transform);
+    }
+
+    fn make_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        self.inner.make_image(width, height, buf, format)
+    }
+
+    fn make_image_with_stride(
+        &mut self,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        self.inner
+            .make_image_with_stride(width, height, stride, buf, format)
+    }
+
+    fn make_image_with_color_space(
+        &mut self,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buf: &[u8],
+        format: ImageFormat,
+        color_space: ColorSpace,
+    ) -> Result<Self::Image, Error> {
+        self.inner
+            .make_image_with_color_space(width, height, stride, buf, format, color_space)
+    }
+
+    fn upload_image(&mut self, buf: &ImageBuf) -> Result<Self::Image, Error> {
+        self.inner.upload_image(buf)
+    }
+
+    fn update_image(
+        &mut self,
+        image: &mut Self::Image,
+        buf: &[u8],
+        format: ImageFormat,
+        region: Option<Rect>,
+    ) -> Result<(), Error> {
+        self.inner.update_image(image, buf, format, region)
+    }
+
+    fn draw_image(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into();
+        self.record(dst_rect);
+        self.inner.draw_image(image, dst_rect, interp);
+    }
+
+    fn draw_image_area(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into();
+        self.record(dst_rect);
+        self.inner
+            .draw_image_area(image, src_rect, dst_rect, interp);
+    }
+
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    ) {
+        let dst_rect = dst_rect.into();
+        self.record(dst_rect);
+        self.inner
+            .draw_image_area_with_opacity(image, src_rect, dst_rect, interp, opacity);
+    }
+
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        src_insets: impl Into<Insets>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into();
+        self.record(dst_rect);
+        self.inner
+            .draw_image_nine(image, src_insets, dst_rect, interp);
+    }
+
+    fn draw_image_batch(
+        &mut self,
+        image: &Self::Image,
+        rects: &[(Rect, Rect)],
+        interp: InterpolationMode,
+    ) {
+        for (_, dst_rect) in rects {
+            self.record(*dst_rect);
+        }
+        self.inner.draw_image_batch(image, rects, interp);
+    }
+
+    fn draw_image_tiled(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        tile_mode: TileMode,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into();
+        if image.size().is_zero_area() {
+            return;
+        }
+        self.record(dst_rect);
+        self.inner
+            .draw_image_tiled(image, dst_rect, tile_mode, interp);
+    }
+
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        self.inner.capture_image_area(src_rect)
+    }
+
+    fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || rect);
+        let extent = crate::util::BLUR_EXTENT * blur_radius.abs();
+        self.record(rect.inflate(extent, extent));
+        self.inner.blurred_rect(rect, blur_radius, &brush.0);
+    }
+
+    fn annotate_link(&mut self, shape: impl Shape, url: &str) {
+        // a link draws nothing, so it doesn't add to the bounds
+        self.inner.annotate_link(shape, url);
+    }
+
+    fn current_transform(&self) -> Affine {
+        self.inner.current_transform()
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.inner.scale_factor()
+    }
+
+    fn snap_rect(&self, rect: Rect) -> Rect {
+        self.inner.snap_rect(rect)
+    }
+
+    fn snap_point(&self, point: Point) -> Point {
+        self.inner.snap_point(point)
+    }
+
+    fn backend_handle(&self) -> Option<&dyn Any> {
+        self.inner.backend_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NullRenderContext;
+
+    #[test]
+    fn bounds_are_clipped() {
+        let mut ctx = NullRenderContext::new();
+        let mut tracker = BoundsTracker::new(&mut ctx);
+        let brush = tracker.solid_brush(Color::BLACK);
+        tracker
+            .with_save(|rc| {
+                rc.clip(Rect::new(0.0, 0.0, 10.0, 10.0));
+                rc.fill(Rect::new(5.0, 5.0, 50.0, 50.0), &brush);
+                // entirely clipped out
+                rc.fill(Rect::new(20.0, 20.0, 30.0, 30.0), &brush);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(tracker.bounds(), Some(Rect::new(5.0, 5.0, 10.0, 10.0)));
+
+        // the clip was restored
+        tracker.fill(Rect::new(20.0, 20.0, 30.0, 30.0), &brush);
+        assert_eq!(tracker.take_bounds(), Some(Rect::new(5.0, 5.0, 30.0, 30.0)));
+        assert_eq!(tracker.bounds(), None);
+    }
+
+    #[test]
+    fn with_clip_and_transform_are_undone() {
+        let mut ctx = NullRenderContext::new();
+        let mut tracker = BoundsTracker::new(&mut ctx);
+        let brush = tracker.solid_brush(Color::BLACK);
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        tracker
+            .with_transform(Affine::translate((10.0, 0.0)), |rc| {
+                rc.with_clip(Rect::new(0.0, 0.0, 5.0, 5.0), |rc| {
+                    rc.fill(rect, &brush);
+                    Ok(())
+                })?;
+                assert_eq!(rc.bounds(), Some(Rect::new(10.0, 0.0, 15.0, 5.0)));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(tracker.current_transform(), Affine::IDENTITY);
+
+        tracker.fill(Rect::new(0.0, 20.0, 10.0, 30.0), &brush);
+        assert_eq!(tracker.bounds(), Some(Rect::new(0.0, 0.0, 15.0, 30.0)));
+    }
+
+    #[test]
+    fn strokes_include_their_width() {
+        let mut ctx = NullRenderContext::new();
+        let mut tracker = BoundsTracker::new(&mut ctx);
+        let style = StrokeStyle::new().line_join(LineJoin::Round);
+        tracker.transform(Affine::scale(2.0));
+        tracker.stroke_styled(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK, 2.0, &style);
+        assert_eq!(tracker.bounds(), Some(Rect::new(-2.0, -2.0, 22.0, 22.0)));
+    }
+}
@@ -5,11 +5,17 @@
 
 use std::fmt::{Debug, Formatter};
 
+use crate::InterpolationSpace;
+
 /// A datatype representing color.
 ///
 /// Currently this is only a 32 bit RGBA value, but it will likely
 /// extend to some form of wide-gamut colorspace, and in the meantime
 /// is useful for giving programs proper type.
+///
+/// With the `serde` feature enabled, colors are serialized as a hex string in
+/// the form `#rrggbbaa`, and can be deserialized from any of the forms
+/// accepted by [`Color::from_hex_str`].
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum Color {
@@ -17,6 +23,28 @@ pub enum Color {
     Rgba32(u32),
 }
 
+/// The color space that color components are expressed in.
+///
+/// [`Color`] values and image data are assumed to be sRGB unless tagged
+/// otherwise, for example with [`RenderContext::solid_brush_with_color_space`]
+/// or [`RenderContext::make_image_with_color_space`]. Backends that support
+/// color management pass the tag on to the platform; others convert to sRGB.
+///
+/// [`RenderContext::solid_brush_with_color_space`]: crate::RenderContext::solid_brush_with_color_space
+/// [`RenderContext::make_image_with_color_space`]: crate::RenderContext::make_image_with_color_space
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ColorSpace {
+    /// The standard sRGB color space.
+    #[default]
+    Srgb,
+    /// The Display P3 color space, with the sRGB transfer function and a
+    /// wider gamut.
+    DisplayP3,
+    /// sRGB primaries with a linear transfer function.
+    Linear,
+}
+
 /// Errors that can occur when parsing a hex color.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ColorParseError {
@@ -182,6 +210,119 @@ impl Color {
         Color::hlc(h, c, l).with_alpha(a)
     }
 
+    /// Create a color from hue, saturation, and lightness values.
+    ///
+    /// The `h` parameter is an angle in degrees, with 0 red, 120 green and
+    /// 240 blue. The `s` and `l` parameters are in the range 0.0 to 1.0.
+    /// This matches the CSS `hsl()` function.
+    pub fn hsl(h: f64, s: f64, l: f64) -> Color {
+        Color::hsla(h, s, l, 1.0)
+    }
+
+    /// Create a color from hue, saturation, and lightness values and alpha.
+    ///
+    /// See [`Color::hsl`]; the `a` value represents alpha in the range 0.0 to 1.0.
+    pub fn hsla(h: f64, s: f64, l: f64, a: f64) -> Color {
+        let (s, l) = (s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+        let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let (r, g, b) = hue_to_rgb(h, chroma);
+        let m = l - chroma / 2.0;
+        Color::rgba(r + m, g + m, b + m, a)
+    }
+
+    /// Create a color from hue, saturation, and value.
+    ///
+    /// The `h` parameter is an angle in degrees, with 0 red, 120 green and
+    /// 240 blue. The `s` and `v` parameters are in the range 0.0 to 1.0.
+    pub fn hsv(h: f64, s: f64, v: f64) -> Color {
+        Color::hsva(h, s, v, 1.0)
+    }
+
+    /// Create a color from hue, saturation, and value and alpha.
+    ///
+    /// See [`Color::hsv`]; the `a` value represents alpha in the range 0.0 to 1.0.
+    pub fn hsva(h: f64, s: f64, v: f64, a: f64) -> Color {
+        let (s, v) = (s.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+        let chroma = v * s;
+        let (r, g, b) = hue_to_rgb(h, chroma);
+        let m = v - chroma;
+        Color::rgba(r + m, g + m, b + m, a)
+    }
+
+    /// Create a color from an [Oklab] specification.
+    ///
+    /// The `l` parameter is perceptual lightness, with 0 black and 1 white.
+    /// The `a` and `b` parameters are the green-red and blue-yellow axes,
+    /// and for colors in the sRGB gamut are within about ±0.4.
+    ///
+    /// Out-of-gamut values are clipped to the nearest sRGB color.
+    ///
+    /// [Oklab]: https://bottosson.github.io/posts/oklab/
+    pub fn oklab(l: f64, a: f64, b: f64) -> Color {
+        let [r, g, b] = oklab_to_linear_srgb([l, a, b]);
+        Color::rgb(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+    }
+
+    /// Create a color from an Oklch specification, the polar form of [Oklab].
+    ///
+    /// The `l` parameter is perceptual lightness, with 0 black and 1 white.
+    /// The `c` parameter is chroma, with 0 grayscale and colors in the sRGB
+    /// gamut reaching at most about 0.32. The `h` parameter is a hue angle in
+    /// degrees. This matches the CSS `oklch()` function.
+    ///
+    /// Out-of-gamut values are clipped to the nearest sRGB color.
+    ///
+    /// [Oklab]: https://bottosson.github.io/posts/oklab/
+    pub fn oklch(l: f64, c: f64, h: f64) -> Color {
+        let (sin, cos) = h.to_radians().sin_cos();
+        Color::oklab(l, c * cos, c * sin)
+    }
+
+    /// Interpolate between two colors.
+    ///
+    /// A `t` of 0.0 returns `self` and a `t` of 1.0 returns `other`.
+    ///
+    /// The interpolation is done in the [Oklab] color space, which gives
+    /// perceptually even steps, using premultiplied alpha so that fading
+    /// to or from a transparent color doesn't shift the hue.
+    ///
+    /// [Oklab]: https://bottosson.github.io/posts/oklab/
+    pub fn lerp(self, other: Color, t: f64) -> Color {
+        let (r1, g1, b1, a1) = self.as_rgba();
+        let (r2, g2, b2, a2) = other.as_rgba();
+        let alpha = a1 + (a2 - a1) * t;
+        if alpha <= 0.0 {
+            return Color::TRANSPARENT;
+        }
+        let lab1 = linear_srgb_to_oklab([r1, g1, b1].map(srgb_to_linear));
+        let lab2 = linear_srgb_to_oklab([r2, g2, b2].map(srgb_to_linear));
+        let lab = [0, 1, 2].map(|i| (lab1[i] * a1 + (lab2[i] * a2 - lab1[i] * a1) * t) / alpha);
+        let [l, a, b] = lab;
+        Color::oklab(l, a, b).with_alpha(alpha)
+    }
+
+    /// Interpolate between two colors in the given [`InterpolationSpace`].
+    pub(crate) fn lerp_in(self, other: Color, t: f64, space: InterpolationSpace) -> Color {
+        let (r1, g1, b1, a1) = self.as_rgba();
+        let (r2, g2, b2, a2) = other.as_rgba();
+        let linear = match space {
+            InterpolationSpace::Oklab => return self.lerp(other, t),
+            InterpolationSpace::Srgb => false,
+            InterpolationSpace::LinearSrgb => true,
+        };
+        let decode = |u| if linear { srgb_to_linear(u) } else { u };
+        let encode = |u| if linear { linear_to_srgb(u) } else { u };
+        let alpha = a1 + (a2 - a1) * t;
+        if alpha <= 0.0 {
+            return Color::TRANSPARENT;
+        }
+        let mix = |c1: f64, c2: f64| {
+            let (c1, c2) = (decode(c1) * a1, decode(c2) * a2);
+            encode((c1 + (c2 - c1) * t) / alpha)
+        };
+        Color::rgba(mix(r1, r2), mix(g1, g2), mix(b1, b2), alpha)
+    }
+
     /// Change just the alpha value of a color.
     ///
     /// The `a` value represents alpha in the range 0.0 to 1.0.
@@ -349,6 +490,106 @@ impl Debug for Color {
     }
 }
 
+/// The red, green and blue components of a fully saturated color with the
+/// given hue (in degrees) and chroma, before adding the lightness offset.
+fn hue_to_rgb(h: f64, chroma: f64) -> (f64, f64, f64) {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    }
+}
+
+impl ColorSpace {
+    /// Identify the color space that an ICC profile describes, such as one
+    /// embedded in an image file.
+    ///
+    /// Piet doesn't transform colors through arbitrary profiles, so this only
+    /// recognizes profiles whose primaries and tone curves match one of its
+    /// color spaces, like the sRGB and Display P3 profiles that cameras and
+    /// image editors embed. Other profiles, and data that isn't an RGB or
+    /// grayscale profile, return `None`.
+    pub fn from_icc_profile(profile: &[u8]) -> Option<ColorSpace> {
+        crate::icc::color_space(profile)
+    }
+
+    /// Convert a color whose components are in this color space to sRGB.
+    ///
+    /// Colors outside of the sRGB gamut are clipped.
+    pub fn to_srgb(self, color: Color) -> Color {
+        if self == ColorSpace::Srgb {
+            return color;
+        }
+        let (r, g, b, a) = color.as_rgba();
+        let [r, g, b] = self.rgb_to_srgb([r, g, b]);
+        Color::rgba(r, g, b, a)
+    }
+
+    /// Convert the color components of one unpremultiplied pixel to sRGB.
+    pub(crate) fn rgb_to_srgb(self, rgb: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => rgb,
+            ColorSpace::Linear => rgb.map(|u| linear_to_srgb(u.clamp(0.0, 1.0))),
+            ColorSpace::DisplayP3 => {
+                let [r, g, b] = rgb.map(srgb_to_linear);
+                let rgb = [
+                    1.2249401 * r - 0.2249404 * g,
+                    -0.0420569 * r + 1.0420571 * g,
+                    -0.0196376 * r - 0.0786361 * g + 1.0982735 * b,
+                ];
+                rgb.map(|u| linear_to_srgb(u.clamp(0.0, 1.0)))
+            }
+        }
+    }
+}
+
+pub(crate) fn srgb_to_linear(u: f64) -> f64 {
+    if u <= 0.04045 {
+        u / 12.92
+    } else {
+        ((u + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(u: f64) -> f64 {
+    if u <= 0.0031308 {
+        12.92 * u
+    } else {
+        1.055 * u.powf(1. / 2.4) - 0.055
+    }
+}
+
+// The matrices for converting to and from Oklab are from
+// https://bottosson.github.io/posts/oklab/
+#[allow(clippy::unreadable_literal)]
+fn linear_srgb_to_oklab([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+#[allow(clippy::unreadable_literal)]
+fn oklab_to_linear_srgb([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+    ]
+}
+
 impl std::fmt::Display for ColorParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -361,6 +602,21 @@ impl std::fmt::Display for ColorParseError {
 }
 
 impl std::error::Error for ColorParseError {}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("#{:08x}", self.as_rgba_u32()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Color::from_hex_str(&hex).map_err(serde::de::Error::custom)
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -396,4 +652,71 @@ mod tests {
         assert_eq!(color.with_b8(0xff), Color::from_rgba32_u32(0x11aaffbb));
         assert_eq!(color.with_a8(0xff), Color::from_rgba32_u32(0x11aa22ff));
     }
+
+    #[test]
+    fn color_spaces() {
+        assert_eq!(Color::hsl(0.0, 1.0, 0.5), Color::rgb8(0xff, 0, 0));
+        assert_eq!(Color::hsl(120.0, 1.0, 0.25), Color::rgb8(0, 0x80, 0));
+        assert_eq!(Color::hsl(-120.0, 1.0, 0.5), Color::rgb8(0, 0, 0xff));
+        assert_eq!(
+            Color::hsla(60.0, 0.0, 1.0, 0.5),
+            Color::WHITE.with_alpha(0.5)
+        );
+        assert_eq!(Color::hsv(300.0, 1.0, 1.0), Color::rgb8(0xff, 0, 0xff));
+        assert_eq!(Color::hsv(30.0, 0.5, 1.0), Color::rgb8(0xff, 0xbf, 0x80));
+        assert_eq!(Color::oklab(1.0, 0.0, 0.0), Color::WHITE);
+        assert_eq!(Color::oklab(0.0, 0.0, 0.0), Color::BLACK);
+        // Oklab coordinates of sRGB red, from the reference implementation.
+        assert_eq!(
+            Color::oklab(0.627955, 0.224863, 0.125846),
+            Color::rgb8(0xff, 0, 0)
+        );
+        assert_eq!(
+            Color::oklch(0.627955, 0.0, 0.0),
+            Color::oklab(0.627955, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn color_space_to_srgb() {
+        let grey = Color::rgb8(128, 128, 128);
+        assert_eq!(ColorSpace::Srgb.to_srgb(grey), grey);
+        assert_eq!(ColorSpace::DisplayP3.to_srgb(grey), grey);
+        assert_eq!(ColorSpace::Linear.to_srgb(grey), Color::rgb8(188, 188, 188));
+
+        // Display P3 red is outside the sRGB gamut and gets clipped.
+        let red = Color::rgba8(255, 0, 0, 0x80);
+        assert_eq!(ColorSpace::DisplayP3.to_srgb(red), red);
+        let orange = Color::rgb8(255, 128, 0);
+        let (r, g, b, _) = ColorSpace::DisplayP3.to_srgb(orange).as_rgba8();
+        assert_eq!(r, 255);
+        assert!(g < 128);
+        assert_eq!(b, 0);
+    }
+
+    #[test]
+    fn color_lerp() {
+        let red = Color::rgb8(0xff, 0, 0);
+        let blue = Color::rgb8(0, 0, 0xff);
+        assert_eq!(red.lerp(blue, 0.0), red);
+        assert_eq!(red.lerp(blue, 1.0), blue);
+        assert_eq!(Color::BLACK.lerp(Color::WHITE, 0.5), Color::grey8(0x63));
+        // Fading out keeps the color, rather than blending towards black.
+        let faded = red.lerp(Color::TRANSPARENT, 0.5);
+        assert_eq!(faded, red.with_alpha(0.5));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn color_serde() {
+        let color = Color::rgba8(0x12, 0x34, 0xab, 0xcd);
+        let json = serde_json::to_string(&color).unwrap();
+        assert_eq!(json, "\"#1234abcd\"");
+        assert_eq!(serde_json::from_str::<Color>(&json).unwrap(), color);
+        assert_eq!(
+            serde_json::from_str::<Color>("\"#0f6\"").unwrap(),
+            Color::rgb8(0, 0xff, 0x66)
+        );
+        assert!(serde_json::from_str::<Color>("\"#0f\"").is_err());
+    }
 }
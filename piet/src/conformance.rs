@@ -0,0 +1,379 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Conformance checks for text backends.
+//!
+//! These check behaviour that all backends should share, but that is easy to
+//! get subtly wrong, such as where the caret goes in an empty layout or after
+//! a trailing newline. Each backend runs them from its own tests:
+//!
+//! ```no_run
+//! # fn make_text() -> piet::NullText { unimplemented!() }
+//! let mut text = make_text();
+//! piet::conformance::check_all(&mut text);
+//! ```
+//!
+//! The checks panic on failure, naming the property that does not hold.
+//!
+//! [`check_layout_invariants`] is different: rather than building layouts of
+//! its own, it checks properties that should hold for any layout, whatever
+//! its text. This makes it suitable for fuzzing, as the `text_hit_test`
+//! target in `piet/fuzz` does.
+
+use unic_bidi::bidi_class::BidiClass;
+
+use crate::kurbo::Point;
+use crate::{Affinity, Color, FontFamily, LineMetric, Text, TextLayout, TextLayoutBuilder};
+
+/// Positions from different methods are allowed to differ by this much.
+const TOLERANCE: f64 = 0.01;
+
+const FONT_SIZE: f64 = 14.0;
+
+/// Run every conformance check.
+pub fn check_all(text: &mut impl Text) {
+    check_empty_layout(text);
+    check_trailing_newline(text);
+    check_with_max_width(text);
+    check_with_color_override(text);
+    check_cursor_positions(text);
+    check_hit_test_affinity(text);
+    check_line_bounds(text);
+}
+
+/// An empty layout has a single empty line with the metrics of the default
+/// font, and the caret is at its start.
+pub fn check_empty_layout(text: &mut impl Text) {
+    let layout = build(text, "");
+
+    assert_eq!(layout.line_count(), 1, "an empty layout has one line");
+    let line = layout.line_metric(0).expect("line 0 of an empty layout");
+    assert_eq!(line.range(), 0..0, "the empty line is empty");
+    check_line_metric(&line, "the empty line");
+    assert_close(line.y_offset, 0.0, "y offset of the empty line");
+    assert_close(layout.size().height, line.height, "height of empty layout");
+
+    let pos = layout.hit_test_text_position(0);
+    assert_eq!(pos.line, 0, "caret line in an empty layout");
+    assert_close(pos.point.x, 0.0, "caret x in an empty layout");
+    assert_close(pos.point.y, line.baseline, "caret y in an empty layout");
+
+    let hit = layout.hit_test_point(Point::new(0.0, line.baseline));
+    assert_eq!(hit.idx, 0, "hit test in an empty layout");
+}
+
+/// A trailing newline starts an extra empty line, which is included in the
+/// layout's height and is where the caret goes at the end of the text.
+pub fn check_trailing_newline(text: &mut impl Text) {
+    let string = "hello\n";
+    let end = string.len();
+    let layout = build(text, string);
+
+    assert_eq!(layout.line_count(), 2, "a trailing newline adds a line");
+    let first = layout.line_metric(0).expect("line 0");
+    let last = layout.line_metric(1).expect("line after trailing newline");
+    assert_eq!(first.range(), 0..end, "the first line includes the newline");
+    assert_eq!(
+        last.range(),
+        end..end,
+        "the line after the newline is empty"
+    );
+    check_line_metric(&first, "the first line");
+    check_line_metric(&last, "the line after the newline");
+    assert_close(
+        last.y_offset,
+        first.y_offset + first.height,
+        "y offset of the line after the newline",
+    );
+    assert_close(
+        layout.size().height,
+        last.y_offset + last.height,
+        "height of layout with trailing newline",
+    );
+
+    let before = layout.hit_test_text_position(end - 1);
+    assert_eq!(before.line, 0, "caret line before the newline");
+    assert!(
+        before.point.x > 0.0,
+        "caret before the newline is after text"
+    );
+    assert_close(
+        before.point.y,
+        first.y_offset + first.baseline,
+        "caret y before the newline",
+    );
+
+    let after = layout.hit_test_text_position(end);
+    assert_eq!(after.line, 1, "caret line after the newline");
+    assert_close(after.point.x, 0.0, "caret x after the newline");
+    assert_close(
+        after.point.y,
+        last.y_offset + last.baseline,
+        "caret y after the newline",
+    );
+
+    let hit = layout.hit_test_point(Point::new(0.0, last.y_offset + last.baseline));
+    assert_eq!(hit.idx, end, "hit test on the line after the newline");
+}
+
+/// Rewrapping a layout with [`TextLayout::with_max_width`] gives the same
+/// lines as building it with that width, and leaves the original alone.
+pub fn check_with_max_width(text: &mut impl Text) {
+    let string = "the quick brown fox jumps over the lazy dog";
+    let width = 80.0;
+    let unwrapped = build(text, string);
+    let built = text
+        .new_text_layout(string)
+        .font(FontFamily::SANS_SERIF, FONT_SIZE)
+        .max_width(width)
+        .build()
+        .expect("layout should build");
+    let rewrapped = unwrapped.with_max_width(width);
+
+    assert_eq!(
+        unwrapped.line_count(),
+        1,
+        "an unwrapped layout has one line"
+    );
+    assert!(built.line_count() > 1, "a narrow layout wraps");
+    assert_eq!(
+        rewrapped.line_count(),
+        built.line_count(),
+        "a rewrapped layout has the same lines as a built one"
+    );
+    for i in 0..built.line_count() {
+        assert_eq!(
+            rewrapped.line_text(i),
+            built.line_text(i),
+            "text of rewrapped line {i}"
+        );
+    }
+    assert_close(
+        rewrapped.size().height,
+        built.size().height,
+        "height of rewrapped layout",
+    );
+
+    let unwrapped_again = rewrapped.with_max_width(f64::INFINITY);
+    assert_eq!(
+        unwrapped.line_count(),
+        1,
+        "the original layout is unchanged"
+    );
+    assert_eq!(unwrapped_again.line_count(), 1, "a layout can be unwrapped");
+}
+
+/// Overriding the color of a range with [`TextLayout::with_color_override`]
+/// doesn't change the layout's metrics.
+pub fn check_with_color_override(text: &mut impl Text) {
+    let string = "the quick brown fox jumps over the lazy dog";
+    let layout = build(text, string);
+    let overridden = layout
+        .with_color_override(4..9, Color::rgba8(0xff, 0, 0, 0x80))
+        .with_color_override(.., Color::BLUE);
+
+    assert_eq!(
+        overridden.line_count(),
+        layout.line_count(),
+        "line count of overridden layout"
+    );
+    assert_close(
+        overridden.size().width,
+        layout.size().width,
+        "width of overridden layout",
+    );
+    assert_close(
+        overridden.size().height,
+        layout.size().height,
+        "height of overridden layout",
+    );
+    let idx = string.find("fox").unwrap();
+    assert_close(
+        overridden.hit_test_text_position(idx).point.x,
+        layout.hit_test_text_position(idx).point.x,
+        "position of text in overridden layout",
+    );
+}
+
+/// [`TextLayout::cursor_positions`] agrees with
+/// [`TextLayout::hit_test_text_position`], and covers every line.
+pub fn check_cursor_positions(text: &mut impl Text) {
+    let string = "the quick brown fox\njumps over the lazy dog";
+    let layout = build(text, string);
+    let positions = layout.cursor_positions();
+
+    assert_eq!(
+        positions.len(),
+        string.chars().count() + 1,
+        "one cursor position per character, plus the end"
+    );
+    assert_eq!(positions[0].idx, 0, "the first position is the start");
+    assert_eq!(
+        positions.last().map(|pos| pos.idx),
+        Some(string.len()),
+        "the last position is the end"
+    );
+    for pos in &positions {
+        let hit = layout.hit_test_text_position(pos.idx);
+        assert_eq!(pos.line, hit.line, "line of cursor position {}", pos.idx);
+        assert_close(pos.x, hit.point.x, "x of cursor position");
+    }
+    assert!(
+        positions.windows(2).all(|w| w[0].idx < w[1].idx),
+        "cursor positions are in text order"
+    );
+}
+
+/// A hit past the end of a line is upstream of the end of the line, and a
+/// hit before its start is downstream of the start.
+pub fn check_hit_test_affinity(text: &mut impl Text) {
+    let string = "hello";
+    let layout = build(text, string);
+    let y = layout.line_metric(0).expect("line 0").baseline;
+
+    let after = layout.hit_test_point(Point::new(layout.size().width + 100.0, y));
+    assert_eq!(after.idx, string.len(), "hit past the end of the line");
+    assert_eq!(
+        after.affinity,
+        Affinity::Upstream,
+        "affinity of a hit past the end of the line"
+    );
+
+    let before = layout.hit_test_point(Point::new(-100.0, y));
+    assert_eq!(before.idx, 0, "hit before the start of the line");
+    assert_eq!(
+        before.affinity,
+        Affinity::Downstream,
+        "affinity of a hit before the start of the line"
+    );
+}
+
+/// [`TextLayout::line_bounds`] spans each line's text, without trailing
+/// whitespace, and covers the line's height.
+pub fn check_line_bounds(text: &mut impl Text) {
+    let string = "hello  \nworld";
+    let layout = build(text, string);
+
+    for (line_number, end) in [(0, 5), (1, string.len())] {
+        let metric = layout.line_metric(line_number).expect("line metric");
+        let bounds = layout.line_bounds(line_number).expect("line bounds");
+        let start = layout.hit_test_text_position(metric.start_offset);
+        let end = layout.hit_test_text_position(end);
+        assert_close(bounds.x0, start.point.x, "start of line bounds");
+        assert_close(bounds.x1, end.point.x, "end of line bounds");
+        assert_close(bounds.y0, metric.y_offset, "top of line bounds");
+        assert_close(bounds.height(), metric.height, "height of line bounds");
+    }
+    assert!(
+        layout.line_bounds(2).is_none(),
+        "no bounds past the last line"
+    );
+}
+
+/// Properties that hold for any layout, whatever its text:
+///
+/// - the lines cover the text, in order, breaking it at character
+///   boundaries;
+/// - the cursor positions are in text order, and hit testing the point of
+///   each position finds a position at the same place;
+/// - hit testing any point finds a character boundary in the text;
+/// - in text without right-to-left characters, hit testing points further
+///   along a line never finds an earlier position.
+pub fn check_layout_invariants(layout: &impl TextLayout) {
+    let text = layout.text();
+    let line_count = layout.line_count();
+    assert!(line_count > 0, "a layout has at least one line");
+
+    let mut end = 0;
+    let mut y_offset = f64::NEG_INFINITY;
+    for line_number in 0..line_count {
+        let line = layout.line_metric(line_number).expect("line metric");
+        assert_eq!(
+            line.start_offset, end,
+            "line {line_number} follows the last"
+        );
+        assert!(
+            line.end_offset >= line.start_offset && text.is_char_boundary(line.end_offset),
+            "line {line_number} ends at a character boundary"
+        );
+        assert!(
+            line.y_offset >= y_offset,
+            "line {line_number} is below the last"
+        );
+        end = line.end_offset;
+        y_offset = line.y_offset;
+    }
+    assert_eq!(end, text.len(), "the lines cover the text");
+
+    let positions = layout.cursor_positions();
+    assert!(
+        positions.windows(2).all(|w| w[0].idx < w[1].idx),
+        "cursor positions are in text order"
+    );
+    for pos in &positions {
+        let expected = layout.hit_test_text_position(pos.idx);
+        let hit = layout.hit_test_point(expected.point);
+        assert!(
+            text.is_char_boundary(hit.idx),
+            "hit at cursor position {} is a character boundary",
+            pos.idx
+        );
+        let found = layout.hit_test_text_position(hit.idx);
+        assert_eq!(
+            found.line, expected.line,
+            "line of hit at cursor position {}",
+            pos.idx
+        );
+        assert_close(
+            found.point.x,
+            expected.point.x,
+            "x of hit at cursor position",
+        );
+    }
+
+    let size = layout.size();
+    let has_rtl = text.chars().any(|c| BidiClass::of(c).is_rtl());
+    for line_number in 0..line_count {
+        let line = layout.line_metric(line_number).expect("line metric");
+        let y = line.y_offset + line.baseline;
+        let mut last_idx = 0;
+        for step in -2..=22 {
+            let x = size.width * step as f64 / 20.0;
+            let hit = layout.hit_test_point(Point::new(x, y));
+            assert!(
+                text.is_char_boundary(hit.idx),
+                "hit at ({x}, {y}) is a character boundary"
+            );
+            if !has_rtl {
+                assert!(
+                    hit.idx >= last_idx,
+                    "hits further along line {line_number} are later in the text"
+                );
+            }
+            last_idx = hit.idx;
+        }
+    }
+}
+
+fn build<T: Text>(text: &mut T, string: &'static str) -> T::TextLayout {
+    text.new_text_layout(string)
+        .font(FontFamily::SANS_SERIF, FONT_SIZE)
+        .build()
+        .expect("layout should build")
+}
+
+fn check_line_metric(line: &LineMetric, what: &str) {
+    assert!(line.height > 0.0, "{what} has a height");
+    assert!(
+        line.baseline > 0.0 && line.baseline <= line.height,
+        "{what} has a baseline inside the line"
+    );
+}
+
+#[track_caller]
+fn assert_close(val: f64, expected: f64, what: &str) {
+    assert!(
+        (val - expected).abs() <= TOLERANCE,
+        "{what}: expected {expected}, found {val}"
+    );
+}
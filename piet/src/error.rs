@@ -25,6 +25,26 @@ pub enum Error {
     MissingFont,
     /// Font data could not be loaded.
     FontLoadingFailed,
+    /// The device backing the render target was lost or reset.
+    ///
+    /// The target and any resources created with it must be recreated
+    /// before drawing again.
+    DeviceLost,
+    /// The backend ran out of memory.
+    OutOfMemory,
+    /// The size, stride or buffer passed to create or update an image
+    /// don't describe a valid image.
+    InvalidImage(ImageArgsError),
+    /// Drawing inside a scope such as [`RenderContext::with_save`] failed,
+    /// and so did restoring the state at the end of it.
+    ///
+    /// [`RenderContext::with_save`]: crate::RenderContext::with_save
+    RestoreFailed {
+        /// The error from drawing.
+        error: Box<Error>,
+        /// The error from restoring the state.
+        restore: Box<Error>,
+    },
     /// The arguments provided to the CLI were invalid.
     #[cfg(feature = "samples")]
     InvalidSampleArgs,
@@ -38,6 +58,12 @@ impl fmt::Display for Error {
             Error::StackUnbalance => write!(f, "Stack unbalanced"),
             Error::MissingFont => write!(f, "A font could not be found"),
             Error::FontLoadingFailed => write!(f, "A font could not be loaded"),
+            Error::DeviceLost => write!(f, "The rendering device was lost"),
+            Error::OutOfMemory => write!(f, "Out of memory"),
+            Error::InvalidImage(e) => write!(f, "Invalid image: {e}"),
+            Error::RestoreFailed { error, restore } => {
+                write!(f, "{error}; restoring the state also failed: {restore}")
+            }
             Error::Unimplemented => write!(
                 f,
                 "This functionality is not yet implemented for this backend"
@@ -55,8 +81,78 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /// Combine the result of drawing inside a scope with that of restoring
+    /// the state at its end, keeping both errors if both failed.
+    pub(crate) fn scoped(
+        result: Result<(), Error>,
+        restored: Result<(), Error>,
+    ) -> Result<(), Error> {
+        match (result, restored) {
+            (Err(error), Err(restore)) => Err(Error::RestoreFailed {
+                error: Box::new(error),
+                restore: Box::new(restore),
+            }),
+            (result, restored) => result.and(restored),
+        }
+    }
+}
+
 impl From<Box<dyn std::error::Error>> for Error {
     fn from(e: Box<dyn std::error::Error>) -> Error {
         Error::BackendError(e)
     }
 }
+
+/// The ways in which image arguments can be invalid.
+///
+/// This is returned by [`validate_image_args`], and is wrapped in
+/// [`Error::InvalidImage`] by the image methods of [`RenderContext`].
+///
+/// [`validate_image_args`]: crate::util::validate_image_args
+/// [`RenderContext`]: crate::RenderContext
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImageArgsError {
+    /// The size of the image, in bytes, doesn't fit in a `usize`.
+    TooLarge,
+    /// The stride is less than the size of a row of pixels, so rows would
+    /// overlap.
+    StrideTooSmall {
+        /// The stride that was passed.
+        stride: usize,
+        /// The number of bytes in a row of pixels.
+        row_size: usize,
+    },
+    /// The buffer is too small to hold the image.
+    BufferTooSmall {
+        /// The length of the buffer that was passed.
+        len: usize,
+        /// The number of bytes the image needs.
+        expected: usize,
+    },
+}
+
+impl fmt::Display for ImageArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageArgsError::TooLarge => write!(f, "image size overflows"),
+            ImageArgsError::StrideTooSmall { stride, row_size } => write!(
+                f,
+                "stride of {stride} bytes is less than the row size of {row_size} bytes"
+            ),
+            ImageArgsError::BufferTooSmall { len, expected } => write!(
+                f,
+                "buffer of {len} bytes is too small, {expected} bytes are needed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageArgsError {}
+
+impl From<ImageArgsError> for Error {
+    fn from(e: ImageArgsError) -> Error {
+        Error::InvalidImage(e)
+    }
+}
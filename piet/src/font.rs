@@ -44,10 +44,13 @@ pub enum FontFamilyInner {
 ///
 /// [CSS `font-weight`]: https://developer.mozilla.org/en-US/docs/Web/CSS/font-weight
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct FontWeight(u16);
 
 /// A font style, which may be italic or regular.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FontStyle {
     /// Prefer the regular style for the current font family, if available.
     #[default]
@@ -59,6 +62,40 @@ pub enum FontStyle {
     Italic,
 }
 
+/// An OpenType font feature setting, such as small caps or tabular numbers.
+///
+/// A feature is identified by its four-byte [tag]. Its value is usually `1`
+/// to enable the feature and `0` to disable it; some features, such as
+/// stylistic alternates, use the value to choose between variants.
+///
+/// [tag]: https://learn.microsoft.com/en-us/typography/opentype/spec/featuretags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FontFeature {
+    tag: [u8; 4],
+    value: u32,
+}
+
+/// A font used to draw a run of text, as reported by
+/// [`TextLayout::resolved_fonts`].
+///
+/// This is the font the backend actually chose, which may be a fallback for
+/// characters the requested font doesn't have; its family is a concrete
+/// family name where the backend knows one.
+///
+/// [`TextLayout::resolved_fonts`]: crate::TextLayout::resolved_fonts
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontDescription {
+    /// The font's family.
+    pub family: FontFamily,
+    /// The font's weight.
+    pub weight: FontWeight,
+    /// The font's style.
+    pub style: FontStyle,
+    /// The size the font is drawn at.
+    pub size: f64,
+}
+
 impl FontFamily {
     /// A san-serif font, such as Arial or Helvetica.
     pub const SANS_SERIF: FontFamily = FontFamily(FontFamilyInner::SansSerif);
@@ -81,6 +118,21 @@ impl FontFamily {
         FontFamily(FontFamilyInner::Named(s.into()))
     }
 
+    /// The generic family with the given CSS name, if there is one.
+    ///
+    /// This recognizes `"serif"`, `"sans-serif"`, `"monospace"` and
+    /// `"system-ui"`, ignoring ASCII case.
+    pub fn generic(name: &str) -> Option<FontFamily> {
+        [
+            FontFamily::SERIF,
+            FontFamily::SANS_SERIF,
+            FontFamily::MONOSPACE,
+            FontFamily::SYSTEM_UI,
+        ]
+        .into_iter()
+        .find(|family| family.name().eq_ignore_ascii_case(name))
+    }
+
     /// The name of the font family.
     pub fn name(&self) -> &str {
         match &self.0 {
@@ -155,6 +207,39 @@ impl FontWeight {
     }
 }
 
+impl FontFeature {
+    /// Small capitals, in place of lowercase letters.
+    pub const SMALL_CAPS: FontFeature = FontFeature::new(*b"smcp", 1);
+    /// Digits with equal widths, for aligning columns of numbers.
+    pub const TABULAR_NUMBERS: FontFeature = FontFeature::new(*b"tnum", 1);
+    /// Turn off standard ligatures, such as "fi".
+    pub const NO_LIGATURES: FontFeature = FontFeature::new(*b"liga", 0);
+    /// Turn off contextual alternates, including most programming ligatures.
+    pub const NO_CONTEXTUAL_ALTERNATES: FontFeature = FontFeature::new(*b"calt", 0);
+
+    /// Create a new feature setting from a tag and a value.
+    pub const fn new(tag: [u8; 4], value: u32) -> FontFeature {
+        FontFeature { tag, value }
+    }
+
+    /// The feature's tag, such as `*b"smcp"`.
+    pub const fn tag(self) -> [u8; 4] {
+        self.tag
+    }
+
+    /// The feature's tag as a string, or `None` if it is not ASCII.
+    pub fn tag_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.tag)
+            .ok()
+            .filter(|tag| tag.is_ascii())
+    }
+
+    /// The value of the feature; usually `0` or `1`.
+    pub const fn value(self) -> u32 {
+        self.value
+    }
+}
+
 impl Default for FontFamily {
     fn default() -> Self {
         FontFamily::SYSTEM_UI
@@ -24,9 +24,11 @@
 //! [unit square]: https://en.wikipedia.org/wiki/Unit_square
 
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
 use std::hash::{Hash, Hasher};
 
-use kurbo::{Point, Rect, Size, Vec2};
+use kurbo::{Affine, Point, Rect, Size, Vec2};
 
 use crate::{IntoBrush, RenderContext};
 
@@ -38,6 +40,7 @@ use crate::Color;
 /// cases, it is better to specify coordinates relative to the `Rect`
 /// of the item being drawn; for these, use [`LinearGradient`] instead.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FixedLinearGradient {
     /// The start point (corresponding to pos 0.0).
     pub start: Point,
@@ -55,6 +58,7 @@ pub struct FixedLinearGradient {
 /// cases, it is better to specify coordinates relative to the `Rect`
 /// of the item being drawn; for these, use [`RadialGradient`] instead.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FixedRadialGradient {
     /// The center.
     pub center: Point,
@@ -64,6 +68,35 @@ pub struct FixedRadialGradient {
     ///
     /// The circle with this radius from the center corresponds to pos 1.0.
     pub radius: f64,
+    /// The radius of the circle around the origin that corresponds to pos 0.0.
+    ///
+    /// This is usually zero, so that the gradient starts from a point; a
+    /// larger radius gives a cone between two circles, like the radial
+    /// gradients of CSS and the HTML canvas.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub start_radius: f64,
+    /// The stops (see similar field in [`LinearGradient`]).
+    pub stops: Vec<GradientStop>,
+}
+
+/// Specification of an elliptical gradient in image-space.
+///
+/// This is a radial gradient with separate horizontal and vertical radii, so
+/// that pos 1.0 is an axis-aligned ellipse rather than a circle. For an
+/// ellipse at an angle, use [`FixedRadialGradient`] with
+/// [`RenderContext::gradient_with_transform`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixedEllipticalGradient {
+    /// The center.
+    pub center: Point,
+    /// The offset of the origin relative to the center.
+    pub origin_offset: Vec2,
+    /// The horizontal and vertical radii.
+    ///
+    /// The ellipse with these radii around the center corresponds to pos 1.0.
+    /// Both must be positive.
+    pub radii: Vec2,
     /// The stops (see similar field in [`LinearGradient`]).
     pub stops: Vec<GradientStop>,
 }
@@ -71,19 +104,33 @@ pub struct FixedRadialGradient {
 /// Any fixed gradient.
 ///
 /// This is provided as a convenience, so that we can provide API that
-/// accept both [`FixedLinearGradient`] and [`FixedRadialGradient`].
-/// You should not construct this type directly; rather construct one of those
-/// types, both of which impl `Into<FixedGradient>`.
+/// accept [`FixedLinearGradient`], [`FixedRadialGradient`] and
+/// [`FixedEllipticalGradient`]. You should not construct this type directly;
+/// rather construct one of those types, all of which impl
+/// `Into<FixedGradient>`.
+///
+/// The conversion normalizes the stops, so that every backend draws the
+/// same gradient:
+///
+/// - stops are sorted by position; stops that share a position keep their
+///   order, and make a hard edge;
+/// - the gradient is cut to positions `0.0..=1.0`, with stops outside that
+///   range replaced by the color at its ends;
+/// - stops with a position that is not finite are dropped.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FixedGradient {
     /// A linear gradient.
     Linear(FixedLinearGradient),
     /// A radial gradient.
     Radial(FixedRadialGradient),
+    /// An elliptical gradient.
+    Elliptical(FixedEllipticalGradient),
 }
 
 /// Specification of a gradient stop.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GradientStop {
     /// The coordinate of the stop.
     pub pos: f32,
@@ -91,6 +138,52 @@ pub struct GradientStop {
     pub color: Color,
 }
 
+/// A list of gradient stops that is known to be valid.
+///
+/// Backends differ in how they draw stops that are out of order or outside
+/// `0.0..=1.0`; piet normalizes such stops (see [`FixedGradient`]), but the
+/// result may not be what was intended. Building the stops with
+/// [`CheckedStops::new`] instead reports these mistakes where they are made.
+///
+/// This implements [`GradientStops`], so it can be used anywhere stops are
+/// accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CheckedStops(Vec<GradientStop>);
+
+/// The reason a list of gradient stops is invalid.
+///
+/// See [`CheckedStops::new`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GradientError {
+    /// There are fewer than two stops.
+    TooFewStops,
+    /// The stop at this index has a position outside `0.0..=1.0`, or one
+    /// that is not a number.
+    OutOfRange(usize),
+    /// The stop at this index has a position before the previous stop.
+    OutOfOrder(usize),
+}
+
+/// The color space in which the colors between gradient stops are computed,
+/// for use with the `resample` methods on gradients.
+///
+/// Backends interpolate gradient stops in whatever space is native to the
+/// platform, which is usually sRGB. Resampling a gradient into many closely
+/// spaced stops in one of these spaces gives the same result on every backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterpolationSpace {
+    /// Interpolate the gamma-encoded sRGB components.
+    Srgb,
+    /// Interpolate in linear-light sRGB, which avoids the darkened midpoints
+    /// of sRGB interpolation.
+    LinearSrgb,
+    /// Interpolate in the perceptually uniform [Oklab] color space.
+    ///
+    /// [Oklab]: https://bottosson.github.io/posts/oklab/
+    Oklab,
+}
+
 /// A flexible, ergonomic way to describe gradient stops.
 pub trait GradientStops {
     /// Convert into a vector of gradient steps.
@@ -145,10 +238,53 @@ pub struct RadialGradient {
     center: UnitPoint,
     origin: UnitPoint,
     radius: f64,
+    start_radius: f64,
     stops: Vec<GradientStop>,
     scale_mode: ScaleMode,
 }
 
+/// A [`LinearGradient`] or [`RadialGradient`] that keeps the brush it was
+/// last resolved to.
+///
+/// Drawing with a relative gradient resolves it against the bounding box of
+/// the shape, and creates a new brush, every time. A `CachedGradient` keeps
+/// the brush, and only creates a new one when it is drawn with a different
+/// bounding box, so one kept from frame to frame for a widget that doesn't
+/// change size creates its brush only once.
+///
+/// `B` is the brush type of the render context it is drawn with. Brushes
+/// belong to the render target they were created for; if it is recreated,
+/// call [`clear`] to drop the brush.
+///
+/// ```
+/// # use piet::kurbo::Rect;
+/// # use piet::*;
+/// let gradient = LinearGradient::new(
+///     UnitPoint::TOP,
+///     UnitPoint::BOTTOM,
+///     (Color::WHITE, Color::BLACK),
+/// );
+/// let cached = CachedGradient::from(gradient);
+///
+/// let mut rc = NullRenderContext::new();
+/// for _frame in 0..3 {
+///     // the brush is created on the first frame, and reused after that
+///     rc.fill(Rect::new(0.0, 0.0, 100.0, 20.0), &cached);
+/// }
+/// ```
+///
+/// [`clear`]: CachedGradient::clear
+pub struct CachedGradient<B> {
+    gradient: RelativeGradient,
+    resolved: RefCell<Option<(Rect, B)>>,
+}
+
+#[derive(Debug, Clone)]
+enum RelativeGradient {
+    Linear(LinearGradient),
+    Radial(RadialGradient),
+}
+
 /// Mappings from the unit square into a non-square rectangle.
 #[derive(Debug, Clone)]
 pub enum ScaleMode {
@@ -237,6 +373,84 @@ impl GradientStops for (Color, Color, Color, Color, Color, Color) {
     }
 }
 
+impl GradientStops for CheckedStops {
+    fn to_vec(self) -> Vec<GradientStop> {
+        self.0
+    }
+}
+
+impl CheckedStops {
+    /// Check that `stops` make a valid gradient.
+    ///
+    /// There must be at least two stops, every position must be in
+    /// `0.0..=1.0`, and the positions must not decrease. Stops may share a
+    /// position, which makes a hard edge.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{CheckedStops, Color, GradientError, GradientStop};
+    ///
+    /// let stop = |pos, color| GradientStop { pos, color };
+    /// let stops = vec![stop(0.0, Color::BLACK), stop(1.5, Color::WHITE)];
+    /// assert_eq!(CheckedStops::new(stops), Err(GradientError::OutOfRange(1)));
+    /// ```
+    pub fn new(stops: Vec<GradientStop>) -> Result<CheckedStops, GradientError> {
+        if stops.len() < 2 {
+            return Err(GradientError::TooFewStops);
+        }
+        for (i, stop) in stops.iter().enumerate() {
+            if !(0.0..=1.0).contains(&stop.pos) {
+                return Err(GradientError::OutOfRange(i));
+            }
+            if i > 0 && stop.pos < stops[i - 1].pos {
+                return Err(GradientError::OutOfOrder(i));
+            }
+        }
+        Ok(CheckedStops(stops))
+    }
+
+    /// Stops for the given colors, spaced evenly from `0.0` to `1.0`.
+    ///
+    /// Returns an error if there are fewer than two colors.
+    pub fn evenly_spaced(colors: &[Color]) -> Result<CheckedStops, GradientError> {
+        CheckedStops::new(GradientStops::to_vec(colors))
+    }
+
+    /// The stops, in order.
+    pub fn as_slice(&self) -> &[GradientStop] {
+        &self.0
+    }
+}
+
+impl From<CheckedStops> for Vec<GradientStop> {
+    fn from(src: CheckedStops) -> Vec<GradientStop> {
+        src.0
+    }
+}
+
+impl fmt::Display for GradientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GradientError::TooFewStops => write!(f, "A gradient needs at least two stops"),
+            GradientError::OutOfRange(i) => {
+                write!(f, "Gradient stop {i} is outside the range 0.0..=1.0")
+            }
+            GradientError::OutOfOrder(i) => {
+                write!(f, "Gradient stop {i} is before the previous stop")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GradientError {}
+
+impl From<GradientError> for crate::Error {
+    fn from(_: GradientError) -> crate::Error {
+        crate::Error::InvalidInput
+    }
+}
+
 impl UnitPoint {
     /// `(0.0, 0.0)`
     pub const TOP_LEFT: UnitPoint = UnitPoint::new(0.0, 0.0);
@@ -303,6 +517,15 @@ impl LinearGradient {
         }
     }
 
+    /// A builder-style method for expanding the stops of this gradient into
+    /// roughly `n` stops, interpolated in the given [`InterpolationSpace`].
+    ///
+    /// See [`FixedGradient::resample`] for details.
+    pub fn resample(mut self, n: usize, space: InterpolationSpace) -> Self {
+        self.stops = resample_stops(&self.stops, n, space);
+        self
+    }
+
     // maybe these should be public API? that was my original intention but I'm not
     // sure there's a clear use, so keeping them private for now.
     /// Generate a [`FixedLinearGradient`] by mapping points in the unit square
@@ -330,6 +553,7 @@ impl RadialGradient {
             center: UnitPoint::CENTER,
             origin: UnitPoint::CENTER,
             radius,
+            start_radius: 0.0,
             stops: stops.to_vec(),
             scale_mode: ScaleMode::Fill,
         }
@@ -353,12 +577,30 @@ impl RadialGradient {
         self
     }
 
+    /// A builder-style method for changing the radius of the circle around
+    /// the origin where the gradient starts, which is zero by default.
+    ///
+    /// Like the radius, this is relative to the unit square.
+    pub fn with_start_radius(mut self, start_radius: f64) -> Self {
+        self.start_radius = start_radius;
+        self
+    }
+
     /// A builder-style method for changing the [`ScaleMode`] of the gradient.
     pub fn with_scale_mode(mut self, scale_mode: ScaleMode) -> Self {
         self.scale_mode = scale_mode;
         self
     }
 
+    /// A builder-style method for expanding the stops of this gradient into
+    /// roughly `n` stops, interpolated in the given [`InterpolationSpace`].
+    ///
+    /// See [`FixedGradient::resample`] for details.
+    pub fn resample(mut self, n: usize, space: InterpolationSpace) -> Self {
+        self.stops = resample_stops(&self.stops, n, space);
+        self
+    }
+
     /// Generate a [`FixedRadialGradient`] by mapping points in the unit square
     /// onto points in `rect`.
     fn resolve(&self, rect: Rect) -> FixedRadialGradient {
@@ -376,23 +618,218 @@ impl RadialGradient {
             center,
             origin_offset,
             radius,
+            start_radius: self.start_radius * scale_len,
             stops: self.stops.clone(),
         }
     }
 }
 
+impl FixedGradient {
+    /// Expand the stops of this gradient into roughly `n` stops, interpolated
+    /// in the given [`InterpolationSpace`].
+    ///
+    /// The new stops are spread across the gradient in proportion to the
+    /// length of each segment; the original stops, including hard edges where
+    /// two stops share a position, are preserved. Gradients with fewer than
+    /// two stops, or which already have at least `n` stops, are unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{Color, FixedLinearGradient, GradientStops, InterpolationSpace};
+    /// use piet::kurbo::Point;
+    ///
+    /// let gradient = FixedLinearGradient {
+    ///     start: Point::ZERO,
+    ///     end: Point::new(100.0, 0.0),
+    ///     stops: (Color::rgb8(255, 0, 0), Color::rgb8(0, 0, 255)).to_vec(),
+    /// }
+    /// .resample(16, InterpolationSpace::Oklab);
+    ///
+    /// assert_eq!(gradient.stops.len(), 16);
+    /// assert_eq!(gradient.stops[15].color, Color::rgb8(0, 0, 255));
+    /// ```
+    pub fn resample(self, n: usize, space: InterpolationSpace) -> FixedGradient {
+        match self {
+            FixedGradient::Linear(g) => FixedGradient::Linear(g.resample(n, space)),
+            FixedGradient::Radial(g) => FixedGradient::Radial(g.resample(n, space)),
+            FixedGradient::Elliptical(g) => FixedGradient::Elliptical(g.resample(n, space)),
+        }
+    }
+}
+
+impl FixedLinearGradient {
+    /// Create a new linear gradient from `start` to `end`, with stops that
+    /// have already been checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{CheckedStops, Color, FixedLinearGradient};
+    /// use piet::kurbo::Point;
+    ///
+    /// # fn main() -> Result<(), piet::GradientError> {
+    /// let stops = CheckedStops::evenly_spaced(&[Color::WHITE, Color::BLACK])?;
+    /// let gradient = FixedLinearGradient::new(Point::ZERO, Point::new(100.0, 0.0), stops);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(start: Point, end: Point, stops: CheckedStops) -> FixedLinearGradient {
+        FixedLinearGradient {
+            start,
+            end,
+            stops: stops.0,
+        }
+    }
+
+    /// Expand the stops of this gradient into roughly `n` stops, interpolated
+    /// in the given [`InterpolationSpace`].
+    ///
+    /// See [`FixedGradient::resample`] for details.
+    pub fn resample(mut self, n: usize, space: InterpolationSpace) -> Self {
+        self.stops = resample_stops(&self.stops, n, space);
+        self
+    }
+}
+
+impl FixedRadialGradient {
+    /// Create a new radial gradient around `center`, with stops that have
+    /// already been checked.
+    ///
+    /// The origin is at the center; it can be moved with
+    /// [`with_origin_offset`](FixedRadialGradient::with_origin_offset).
+    pub fn new(center: Point, radius: f64, stops: CheckedStops) -> FixedRadialGradient {
+        FixedRadialGradient {
+            center,
+            origin_offset: Vec2::ZERO,
+            radius,
+            start_radius: 0.0,
+            stops: stops.0,
+        }
+    }
+
+    /// A builder-style method for moving the origin of the gradient relative
+    /// to its center.
+    pub fn with_origin_offset(mut self, origin_offset: Vec2) -> Self {
+        self.origin_offset = origin_offset;
+        self
+    }
+
+    /// A builder-style method for changing the radius of the circle around
+    /// the origin where the gradient starts, which is zero by default.
+    pub fn with_start_radius(mut self, start_radius: f64) -> Self {
+        self.start_radius = start_radius;
+        self
+    }
+
+    /// Expand the stops of this gradient into roughly `n` stops, interpolated
+    /// in the given [`InterpolationSpace`].
+    ///
+    /// See [`FixedGradient::resample`] for details.
+    pub fn resample(mut self, n: usize, space: InterpolationSpace) -> Self {
+        self.stops = resample_stops(&self.stops, n, space);
+        self
+    }
+}
+
+impl FixedEllipticalGradient {
+    /// Create a new elliptical gradient around `center`, with stops that have
+    /// already been checked.
+    ///
+    /// The origin is at the center; it can be moved with
+    /// [`with_origin_offset`](FixedEllipticalGradient::with_origin_offset).
+    pub fn new(center: Point, radii: Vec2, stops: CheckedStops) -> FixedEllipticalGradient {
+        FixedEllipticalGradient {
+            center,
+            origin_offset: Vec2::ZERO,
+            radii,
+            stops: stops.0,
+        }
+    }
+
+    /// A builder-style method for moving the origin of the gradient relative
+    /// to its center.
+    pub fn with_origin_offset(mut self, origin_offset: Vec2) -> Self {
+        self.origin_offset = origin_offset;
+        self
+    }
+
+    /// Expand the stops of this gradient into roughly `n` stops, interpolated
+    /// in the given [`InterpolationSpace`].
+    ///
+    /// See [`FixedGradient::resample`] for details.
+    pub fn resample(mut self, n: usize, space: InterpolationSpace) -> Self {
+        self.stops = resample_stops(&self.stops, n, space);
+        self
+    }
+
+    /// A circular gradient, and the transform that maps it onto this one.
+    ///
+    /// Backends without native elliptical gradients draw the circular
+    /// gradient with this transform applied to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{Color, FixedEllipticalGradient, GradientStops};
+    /// use piet::kurbo::{Point, Vec2};
+    ///
+    /// let gradient = FixedEllipticalGradient {
+    ///     center: Point::new(50.0, 50.0),
+    ///     origin_offset: Vec2::ZERO,
+    ///     radii: Vec2::new(40.0, 20.0),
+    ///     stops: (Color::WHITE, Color::BLACK).to_vec(),
+    /// };
+    /// let (radial, transform) = gradient.to_radial();
+    /// let edge = Point::new(radial.radius, 0.0);
+    /// assert_eq!(transform * edge, Point::new(90.0, 50.0));
+    /// ```
+    pub fn to_radial(&self) -> (FixedRadialGradient, Affine) {
+        // a zero radius leaves the transform degenerate, which backends reject
+        let unscale = |offset: f64, radius: f64| {
+            if radius == 0.0 {
+                0.0
+            } else {
+                offset / radius
+            }
+        };
+        let radial = FixedRadialGradient {
+            center: Point::ZERO,
+            origin_offset: Vec2::new(
+                unscale(self.origin_offset.x, self.radii.x),
+                unscale(self.origin_offset.y, self.radii.y),
+            ),
+            radius: 1.0,
+            start_radius: 0.0,
+            stops: self.stops.clone(),
+        };
+        let transform = Affine::scale_non_uniform(self.radii.x, self.radii.y)
+            .then_translate(self.center.to_vec2());
+        (radial, transform)
+    }
+}
+
 impl From<FixedLinearGradient> for FixedGradient {
-    fn from(src: FixedLinearGradient) -> FixedGradient {
+    fn from(mut src: FixedLinearGradient) -> FixedGradient {
+        normalize_stops(&mut src.stops);
         FixedGradient::Linear(src)
     }
 }
 
 impl From<FixedRadialGradient> for FixedGradient {
-    fn from(src: FixedRadialGradient) -> FixedGradient {
+    fn from(mut src: FixedRadialGradient) -> FixedGradient {
+        normalize_stops(&mut src.stops);
         FixedGradient::Radial(src)
     }
 }
 
+impl From<FixedEllipticalGradient> for FixedGradient {
+    fn from(mut src: FixedEllipticalGradient) -> FixedGradient {
+        normalize_stops(&mut src.stops);
+        FixedGradient::Elliptical(src)
+    }
+}
+
 impl<P: RenderContext> IntoBrush<P> for FixedGradient {
     fn make_brush<'a>(&'a self, piet: &mut P, _bbox: impl FnOnce() -> Rect) -> Cow<'a, P::Brush> {
         // Also, at some point we might want to be smarter about the extra clone here.
@@ -421,6 +858,140 @@ impl<P: RenderContext> IntoBrush<P> for RadialGradient {
     }
 }
 
+impl<B> CachedGradient<B> {
+    /// Drop the resolved brush, so that a new one is created the next time
+    /// the gradient is drawn.
+    pub fn clear(&self) {
+        self.resolved.replace(None);
+    }
+}
+
+impl<B> From<LinearGradient> for CachedGradient<B> {
+    fn from(gradient: LinearGradient) -> Self {
+        CachedGradient {
+            gradient: RelativeGradient::Linear(gradient),
+            resolved: RefCell::new(None),
+        }
+    }
+}
+
+impl<B> From<RadialGradient> for CachedGradient<B> {
+    fn from(gradient: RadialGradient) -> Self {
+        CachedGradient {
+            gradient: RelativeGradient::Radial(gradient),
+            resolved: RefCell::new(None),
+        }
+    }
+}
+
+impl<B> fmt::Debug for CachedGradient<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let resolved = self.resolved.borrow().as_ref().map(|(rect, _)| *rect);
+        f.debug_struct("CachedGradient")
+            .field("gradient", &self.gradient)
+            .field("resolved_for", &resolved)
+            .finish()
+    }
+}
+
+impl<P: RenderContext<Brush = B>, B: Clone> IntoBrush<P> for CachedGradient<B> {
+    fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> Cow<'a, P::Brush> {
+        let rect = bbox();
+        let mut resolved = self.resolved.borrow_mut();
+        match &*resolved {
+            Some((resolved_rect, brush)) if *resolved_rect == rect => Cow::Owned(brush.clone()),
+            _ => {
+                let gradient: FixedGradient = match &self.gradient {
+                    RelativeGradient::Linear(gradient) => gradient.resolve(rect).into(),
+                    RelativeGradient::Radial(gradient) => gradient.resolve(rect).into(),
+                };
+                let brush = piet.gradient(gradient).expect("error creating gradient");
+                *resolved = Some((rect, brush.clone()));
+                Cow::Owned(brush)
+            }
+        }
+    }
+}
+
+/// Sort `stops`, and cut them to the range `0.0..=1.0`.
+///
+/// See [`FixedGradient`] for the rules.
+fn normalize_stops(stops: &mut Vec<GradientStop>) {
+    stops.retain(|stop| stop.pos.is_finite());
+    // a stable sort, so that stops at the same position stay in order
+    stops.sort_by(|a, b| a.pos.total_cmp(&b.pos));
+    if stops.iter().all(|stop| (0.0..=1.0).contains(&stop.pos)) {
+        return;
+    }
+
+    // the color of the gradient at `pos`, which the stops must span
+    let color_at = |stops: &[GradientStop], pos: f32| {
+        let after = stops.partition_point(|stop| stop.pos <= pos);
+        if after == 0 {
+            return stops[0].color;
+        } else if after == stops.len() {
+            return stops[after - 1].color;
+        }
+        let (a, b) = (&stops[after - 1], &stops[after]);
+        let t = (pos - a.pos) / (b.pos - a.pos);
+        a.color.lerp_in(b.color, t as f64, InterpolationSpace::Srgb)
+    };
+    let start = GradientStop {
+        pos: 0.0,
+        color: color_at(stops, 0.0),
+    };
+    let end = GradientStop {
+        pos: 1.0,
+        color: color_at(stops, 1.0),
+    };
+    let below_start = stops.iter().any(|stop| stop.pos < 0.0);
+    let above_end = stops.iter().any(|stop| stop.pos > 1.0);
+    stops.retain(|stop| (0.0..=1.0).contains(&stop.pos));
+    // if every stop was out of range, this leaves a solid color
+    if below_start || stops.is_empty() {
+        stops.insert(0, start);
+    }
+    if above_end || stops.len() < 2 {
+        stops.push(end);
+    }
+}
+
+/// Expand `stops` into roughly `n` stops, distributed across the segments
+/// in proportion to their length.
+fn resample_stops(
+    stops: &[GradientStop],
+    n: usize,
+    space: InterpolationSpace,
+) -> Vec<GradientStop> {
+    if stops.len() < 2 || stops.len() >= n {
+        return stops.to_vec();
+    }
+    let mut stops = stops.to_vec();
+    stops.sort_by(|a, b| a.pos.total_cmp(&b.pos));
+    let first = stops[0].pos;
+    let last = stops[stops.len() - 1].clone();
+    let span = last.pos - first;
+    if span <= 0.0 {
+        return stops;
+    }
+
+    let mut result = Vec::with_capacity(n + stops.len());
+    for pair in stops.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let len = b.pos - a.pos;
+        let steps = ((len / span) * (n - 1) as f32).round().max(1.0) as usize;
+        for i in 0..steps {
+            let t = i as f32 / steps as f32;
+            result.push(GradientStop {
+                pos: a.pos + len * t,
+                color: a.color.lerp_in(b.color, t as f64, space),
+            });
+        }
+    }
+    result.push(last);
+    result
+}
+
 fn equalize_sides_preserving_center(rect: Rect, new_len: f64) -> Rect {
     let size = Size::new(new_len, new_len);
     let origin = rect.center() - size.to_vec2() / 2.;
@@ -441,3 +1012,140 @@ impl Hash for GradientStop {
         self.pos.to_bits().hash(state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_preserves_stops() {
+        let red = Color::rgb8(255, 0, 0);
+        let green = Color::rgb8(0, 255, 0);
+        let blue = Color::rgb8(0, 0, 255);
+        let stops = vec![
+            GradientStop {
+                pos: 0.0,
+                color: red,
+            },
+            GradientStop {
+                pos: 0.5,
+                color: green,
+            },
+            GradientStop {
+                pos: 0.5,
+                color: blue,
+            },
+            GradientStop {
+                pos: 1.0,
+                color: red,
+            },
+        ];
+        let resampled = resample_stops(&stops, 11, InterpolationSpace::LinearSrgb);
+        assert_eq!(resampled.first(), stops.first());
+        assert_eq!(resampled.last(), stops.last());
+        for stop in &stops {
+            assert!(resampled.contains(stop));
+        }
+        assert!(resampled.windows(2).all(|w| w[0].pos <= w[1].pos));
+
+        // Midpoint of black to white is brighter in linear light than in sRGB.
+        let grey = (Color::BLACK, Color::WHITE).to_vec();
+        let srgb = resample_stops(&grey, 3, InterpolationSpace::Srgb);
+        let linear = resample_stops(&grey, 3, InterpolationSpace::LinearSrgb);
+        assert_eq!(srgb[1].color, Color::rgb8(128, 128, 128));
+        assert_eq!(linear[1].color, Color::rgb8(188, 188, 188));
+    }
+
+    #[test]
+    fn normalize_sorts_and_cuts_stops() {
+        let stop = |pos, color| GradientStop { pos, color };
+        let black = Color::BLACK;
+        let white = Color::WHITE;
+        let red = Color::rgb8(255, 0, 0);
+        let blue = Color::rgb8(0, 0, 255);
+
+        let mut stops = vec![
+            stop(2.0, white),
+            stop(0.5, red),
+            stop(f32::NAN, blue),
+            stop(-1.0, black),
+            stop(0.5, blue),
+        ];
+        normalize_stops(&mut stops);
+        let grey = black.lerp_in(red, 2.0 / 3.0, InterpolationSpace::Srgb);
+        let light = blue.lerp_in(white, 1.0 / 3.0, InterpolationSpace::Srgb);
+        assert_eq!(
+            stops,
+            vec![
+                stop(0.0, grey),
+                stop(0.5, red),
+                stop(0.5, blue),
+                stop(1.0, light),
+            ]
+        );
+
+        // a gradient entirely past the end is the color of its first stop
+        let mut stops = vec![stop(3.0, white), stop(2.0, red)];
+        normalize_stops(&mut stops);
+        assert_eq!(stops, vec![stop(0.0, red), stop(1.0, red)]);
+
+        // stops in range are only sorted
+        let mut stops = vec![stop(1.0, white), stop(0.0, black)];
+        normalize_stops(&mut stops);
+        assert_eq!(stops, vec![stop(0.0, black), stop(1.0, white)]);
+    }
+
+    #[test]
+    fn checked_stops() {
+        let stop = |pos, color| GradientStop { pos, color };
+        let black = Color::BLACK;
+        let white = Color::WHITE;
+
+        let hard_edge = vec![stop(0.0, black), stop(0.5, black), stop(0.5, white)];
+        assert!(CheckedStops::new(hard_edge).is_ok());
+        assert_eq!(
+            CheckedStops::new(vec![stop(0.0, black)]),
+            Err(GradientError::TooFewStops)
+        );
+        assert_eq!(
+            CheckedStops::new(vec![stop(0.0, black), stop(f32::NAN, white)]),
+            Err(GradientError::OutOfRange(1))
+        );
+        assert_eq!(
+            CheckedStops::new(vec![stop(-0.5, black), stop(1.0, white)]),
+            Err(GradientError::OutOfRange(0))
+        );
+        assert_eq!(
+            CheckedStops::new(vec![stop(0.6, black), stop(0.4, white), stop(1.0, black)]),
+            Err(GradientError::OutOfOrder(1))
+        );
+
+        let even = CheckedStops::evenly_spaced(&[black, white, black]).unwrap();
+        assert_eq!(
+            even.as_slice(),
+            &[stop(0.0, black), stop(0.5, white), stop(1.0, black)]
+        );
+        assert_eq!(
+            CheckedStops::evenly_spaced(&[black]),
+            Err(GradientError::TooFewStops)
+        );
+    }
+
+    #[test]
+    fn cached_gradient_follows_bbox() {
+        let mut rc = crate::NullRenderContext::new();
+        let gradient = RadialGradient::new(0.5, (Color::WHITE, Color::BLACK));
+        let cached = CachedGradient::from(gradient);
+        let resolved_for =
+            |cached: &CachedGradient<_>| cached.resolved.borrow().as_ref().map(|r| r.0);
+
+        let small = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let large = Rect::new(0.0, 0.0, 20.0, 10.0);
+        let _ = cached.make_brush(&mut rc, || small);
+        assert_eq!(resolved_for(&cached), Some(small));
+        let _ = cached.make_brush(&mut rc, || large);
+        assert_eq!(resolved_for(&cached), Some(large));
+        cached.clear();
+        assert_eq!(resolved_for(&cached), None);
+    }
+}
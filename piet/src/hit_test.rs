@@ -0,0 +1,133 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Testing whether a point is inside what would be drawn for a shape.
+//!
+//! These follow the same rules as drawing: the fill tests close any open
+//! subpaths, as [`RenderContext::fill`] does, and the stroke test uses the
+//! outline from [`util::expand_stroke`], with the dashes, caps and joins of
+//! the [`StrokeStyle`].
+//!
+//! Points are in the coordinate space of the shape; to test a point in
+//! device space, apply the inverse of the transform the shape is drawn with
+//! first.
+//!
+//! ```
+//! use piet::hit_test;
+//! use piet::kurbo::{Line, Rect};
+//! use piet::StrokeStyle;
+//!
+//! let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+//! assert!(hit_test::fill_contains(rect, (5.0, 5.0)));
+//!
+//! let style = StrokeStyle::new().dash_pattern(&[4.0, 4.0]);
+//! let line = Line::new((0.0, 0.0), (20.0, 0.0));
+//! assert!(hit_test::stroke_contains(line, &style, 2.0, (2.0, 0.5)));
+//! assert!(!hit_test::stroke_contains(line, &style, 2.0, (6.0, 0.5)));
+//! ```
+//!
+//! [`RenderContext::fill`]: crate::RenderContext::fill
+
+use kurbo::{BezPath, PathEl, Point, Shape};
+
+use crate::{util, StrokeStyle};
+
+/// Whether `point` is inside `shape` when it is filled with
+/// [`RenderContext::fill`], using the nonzero rule.
+///
+/// [`RenderContext::fill`]: crate::RenderContext::fill
+pub fn fill_contains(shape: impl Shape, point: impl Into<Point>) -> bool {
+    closed_path(shape).winding(point.into()) != 0
+}
+
+/// Whether `point` is inside `shape` when it is filled with
+/// [`RenderContext::fill_even_odd`], using the even-odd rule.
+///
+/// [`RenderContext::fill_even_odd`]: crate::RenderContext::fill_even_odd
+pub fn fill_even_odd_contains(shape: impl Shape, point: impl Into<Point>) -> bool {
+    closed_path(shape).winding(point.into()) % 2 != 0
+}
+
+/// Whether `point` is inside the stroke drawn for `shape` by
+/// [`RenderContext::stroke_styled`] with `style` and `width`.
+///
+/// [`RenderContext::stroke_styled`]: crate::RenderContext::stroke_styled
+pub fn stroke_contains(
+    shape: impl Shape,
+    style: &StrokeStyle,
+    width: f64,
+    point: impl Into<Point>,
+) -> bool {
+    let point = point.into();
+    // the stroke can't reach further than this from the path, even at a
+    // miter join; checking it first saves expanding the stroke.
+    let reach = width.abs() * style.miter_limit().unwrap_or(1.0).max(1.0);
+    if !shape.bounding_box().inflate(reach, reach).contains(point) {
+        return false;
+    }
+    util::expand_stroke(shape, width, style).winding(point) != 0
+}
+
+/// The path of `shape`, with every subpath closed, as it is when filled.
+fn closed_path(shape: impl Shape) -> BezPath {
+    let mut path = BezPath::new();
+    let mut open = false;
+    for el in shape.path_elements(1e-3) {
+        match el {
+            PathEl::MoveTo(_) => {
+                if open {
+                    path.close_path();
+                }
+                open = true;
+            }
+            PathEl::ClosePath => open = false,
+            _ => (),
+        }
+        path.push(el);
+    }
+    if open {
+        path.close_path();
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LineCap;
+    use kurbo::{Circle, Line, Rect};
+
+    #[test]
+    fn fill_closes_open_subpaths() {
+        let mut triangle = BezPath::new();
+        triangle.move_to((0.0, 0.0));
+        triangle.line_to((10.0, 0.0));
+        triangle.line_to((0.0, 10.0));
+        assert!(fill_contains(&triangle, (2.0, 2.0)));
+        assert!(!fill_contains(&triangle, (8.0, 8.0)));
+
+        let ring = [Circle::new((0.0, 0.0), 10.0), Circle::new((0.0, 0.0), 5.0)]
+            .iter()
+            .flat_map(|c| c.path_elements(1e-3))
+            .collect::<BezPath>();
+        assert!(fill_contains(&ring, (0.0, 0.0)));
+        assert!(!fill_even_odd_contains(&ring, (0.0, 0.0)));
+        assert!(fill_even_odd_contains(&ring, (7.0, 0.0)));
+    }
+
+    #[test]
+    fn stroke_follows_style() {
+        let line = Line::new((0.0, 0.0), (10.0, 0.0));
+        let butt = StrokeStyle::new();
+        assert!(stroke_contains(line, &butt, 4.0, (5.0, 1.5)));
+        assert!(!stroke_contains(line, &butt, 4.0, (5.0, 2.5)));
+        assert!(!stroke_contains(line, &butt, 4.0, (11.0, 0.0)));
+
+        let square = StrokeStyle::new().line_cap(LineCap::Square);
+        assert!(stroke_contains(line, &square, 4.0, (11.0, 0.0)));
+
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        assert!(!stroke_contains(rect, &butt, 2.0, (5.0, 5.0)));
+        assert!(stroke_contains(rect, &butt, 2.0, (10.5, 5.0)));
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Recognizing the color space described by an ICC profile.
+//!
+//! Piet has no color management module to transform pixels through
+//! arbitrary profiles. Instead, the colorants and tone curves of a profile
+//! are matched against the [`ColorSpace`]s that backends understand.
+
+use crate::color::srgb_to_linear;
+use crate::ColorSpace;
+
+/// The D50-adapted red, green and blue colorants of sRGB.
+const SRGB_COLORANTS: [[f64; 3]; 3] = [
+    [0.4361, 0.2225, 0.0139],
+    [0.3851, 0.7169, 0.0971],
+    [0.1431, 0.0606, 0.7141],
+];
+
+/// The D50-adapted red, green and blue colorants of Display P3.
+const DISPLAY_P3_COLORANTS: [[f64; 3]; 3] = [
+    [0.5151, 0.2412, -0.0011],
+    [0.2920, 0.6922, 0.0419],
+    [0.1571, 0.0666, 0.7841],
+];
+
+/// How far a value may be from a reference and still match it.
+///
+/// Profiles round their values differently, and a plain 2.2 gamma curve is
+/// close enough to count as the sRGB curve.
+const TOLERANCE: f64 = 0.01;
+
+/// The tone curves piet can represent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transfer {
+    Srgb,
+    Linear,
+}
+
+/// Returns the color space that `profile` describes, if piet has one.
+pub(crate) fn color_space(profile: &[u8]) -> Option<ColorSpace> {
+    if profile.get(36..40)? != b"acsp" {
+        return None;
+    }
+    match profile.get(16..20)? {
+        b"RGB " => {
+            let mut colorants = [[0.0; 3]; 3];
+            let mut transfers = [Transfer::Linear; 3];
+            let tags = [(b"rXYZ", b"rTRC"), (b"gXYZ", b"gTRC"), (b"bXYZ", b"bTRC")];
+            for (i, (xyz, trc)) in tags.into_iter().enumerate() {
+                colorants[i] = read_xyz(find_tag(profile, *xyz)?)?;
+                transfers[i] = transfer(find_tag(profile, *trc)?)?;
+            }
+            if transfers.iter().any(|t| *t != transfers[0]) {
+                return None;
+            }
+            match (transfers[0], close(&colorants, &SRGB_COLORANTS)) {
+                (Transfer::Srgb, true) => Some(ColorSpace::Srgb),
+                (Transfer::Linear, true) => Some(ColorSpace::Linear),
+                (Transfer::Srgb, false) if close(&colorants, &DISPLAY_P3_COLORANTS) => {
+                    Some(ColorSpace::DisplayP3)
+                }
+                _ => None,
+            }
+        }
+        // Gray has no gamut, so only the tone curve matters.
+        b"GRAY" => match transfer(find_tag(profile, *b"kTRC")?)? {
+            Transfer::Srgb => Some(ColorSpace::Srgb),
+            Transfer::Linear => Some(ColorSpace::Linear),
+        },
+        _ => None,
+    }
+}
+
+fn close(colorants: &[[f64; 3]; 3], reference: &[[f64; 3]; 3]) -> bool {
+    colorants
+        .iter()
+        .flatten()
+        .zip(reference.iter().flatten())
+        .all(|(a, b)| (a - b).abs() <= TOLERANCE)
+}
+
+/// Classify a tone curve by sampling it.
+fn transfer(curve: &[u8]) -> Option<Transfer> {
+    let samples = [0.25, 0.5, 0.75]
+        .into_iter()
+        .map(|x| Some((x, eval_curve(curve, x)?)))
+        .collect::<Option<Vec<_>>>()?;
+    if samples.iter().all(|(x, y)| (x - y).abs() <= TOLERANCE) {
+        Some(Transfer::Linear)
+    } else if samples
+        .iter()
+        .all(|(x, y)| (srgb_to_linear(*x) - y).abs() <= TOLERANCE)
+    {
+        Some(Transfer::Srgb)
+    } else {
+        None
+    }
+}
+
+/// Evaluate a `curv` or `para` tag at `x`, mapping an encoded value to
+/// linear light.
+fn eval_curve(curve: &[u8], x: f64) -> Option<f64> {
+    match curve.get(0..4)? {
+        b"curv" => match read_u32(curve, 8)? as usize {
+            0 => Some(x),
+            1 => Some(x.powf(read_u16(curve, 12)? as f64 / 256.0)),
+            n => {
+                let pos = x * (n - 1) as f64;
+                let i = (pos as usize).min(n - 2);
+                let t = pos - i as f64;
+                let y0 = read_u16(curve, 12 + 2 * i)? as f64;
+                let y1 = read_u16(curve, 14 + 2 * i)? as f64;
+                Some((y0 + (y1 - y0) * t) / 65535.0)
+            }
+        },
+        b"para" => {
+            let kind = read_u16(curve, 8)? as usize;
+            let count = *[1, 3, 4, 5, 7].get(kind)?;
+            let mut params = [0.0; 7];
+            for (i, param) in params.iter_mut().take(count).enumerate() {
+                *param = read_s15_fixed16(curve, 12 + 4 * i)?;
+            }
+            let [g, a, b, c, d, e, f] = params;
+            Some(match kind {
+                0 => x.powf(g),
+                1 if x >= -b / a => (a * x + b).powf(g),
+                1 => 0.0,
+                2 if x >= -b / a => (a * x + b).powf(g) + c,
+                2 => c,
+                3 if x >= d => (a * x + b).powf(g),
+                3 => c * x,
+                _ if x >= d => (a * x + b).powf(g) + e,
+                _ => c * x + f,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Returns the data of the tag with the signature `sig`.
+fn find_tag(profile: &[u8], sig: [u8; 4]) -> Option<&[u8]> {
+    let count = read_u32(profile, 128)? as usize;
+    (0..count.min(profile.len() / 12)).find_map(|i| {
+        let entry = 132 + 12 * i;
+        if profile.get(entry..entry + 4)? != sig {
+            return None;
+        }
+        let offset = read_u32(profile, entry + 4)? as usize;
+        let size = read_u32(profile, entry + 8)? as usize;
+        profile.get(offset..offset.checked_add(size)?)
+    })
+}
+
+fn read_xyz(tag: &[u8]) -> Option<[f64; 3]> {
+    if tag.get(0..4)? != b"XYZ " {
+        return None;
+    }
+    Some([
+        read_s15_fixed16(tag, 8)?,
+        read_s15_fixed16(tag, 12)?,
+        read_s15_fixed16(tag, 16)?,
+    ])
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_s15_fixed16(data: &[u8], offset: usize) -> Option<f64> {
+    Some(read_u32(data, offset)? as i32 as f64 / 65536.0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Build a minimal profile with the given color space and tags.
+    pub(crate) fn make_profile(space: [u8; 4], tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut profile = vec![0; 128];
+        profile[16..20].copy_from_slice(&space);
+        profile[36..40].copy_from_slice(b"acsp");
+        profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+        let mut offset = 132 + 12 * tags.len();
+        for (sig, data) in tags {
+            profile.extend_from_slice(*sig);
+            profile.extend_from_slice(&(offset as u32).to_be_bytes());
+            profile.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len();
+        }
+        for (_, data) in tags {
+            profile.extend_from_slice(data);
+        }
+        profile
+    }
+
+    fn fixed(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    fn xyz(value: [f64; 3]) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for v in value {
+            tag.extend_from_slice(&fixed(v));
+        }
+        tag
+    }
+
+    fn gamma(gamma: f64) -> Vec<u8> {
+        let mut tag = b"curv\0\0\0\0".to_vec();
+        tag.extend_from_slice(&1u32.to_be_bytes());
+        tag.extend_from_slice(&((gamma * 256.0) as u16).to_be_bytes());
+        tag
+    }
+
+    fn srgb_curve() -> Vec<u8> {
+        let mut tag = b"para\0\0\0\0\0\x03\0\0".to_vec();
+        let params = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045];
+        for p in params {
+            tag.extend_from_slice(&fixed(p));
+        }
+        tag
+    }
+
+    pub(crate) fn rgb_profile(colorants: [[f64; 3]; 3], curve: Vec<u8>) -> Vec<u8> {
+        make_profile(
+            *b"RGB ",
+            &[
+                (b"rXYZ", xyz(colorants[0])),
+                (b"gXYZ", xyz(colorants[1])),
+                (b"bXYZ", xyz(colorants[2])),
+                (b"rTRC", curve.clone()),
+                (b"gTRC", curve.clone()),
+                (b"bTRC", curve),
+            ],
+        )
+    }
+
+    pub(crate) fn display_p3_profile() -> Vec<u8> {
+        rgb_profile(DISPLAY_P3_COLORANTS, srgb_curve())
+    }
+
+    #[test]
+    fn recognize_profiles() {
+        let srgb = rgb_profile(SRGB_COLORANTS, srgb_curve());
+        assert_eq!(color_space(&srgb), Some(ColorSpace::Srgb));
+        let gamma_22 = rgb_profile(SRGB_COLORANTS, gamma(2.2));
+        assert_eq!(color_space(&gamma_22), Some(ColorSpace::Srgb));
+        let linear = rgb_profile(SRGB_COLORANTS, gamma(1.0));
+        assert_eq!(color_space(&linear), Some(ColorSpace::Linear));
+        assert_eq!(
+            color_space(&display_p3_profile()),
+            Some(ColorSpace::DisplayP3)
+        );
+        let gray = make_profile(*b"GRAY", &[(b"kTRC", srgb_curve())]);
+        assert_eq!(color_space(&gray), Some(ColorSpace::Srgb));
+    }
+
+    #[test]
+    fn reject_unknown_profiles() {
+        // Adobe RGB (1998)
+        let adobe_rgb = [
+            [0.6097, 0.3111, 0.0195],
+            [0.2053, 0.6257, 0.0609],
+            [0.1492, 0.0632, 0.7446],
+        ];
+        assert_eq!(color_space(&rgb_profile(adobe_rgb, gamma(2.2))), None);
+        assert_eq!(color_space(&rgb_profile(SRGB_COLORANTS, gamma(1.8))), None);
+        let mut truncated = display_p3_profile();
+        truncated.truncate(200);
+        assert_eq!(color_space(&truncated), None);
+        assert_eq!(color_space(b"not a profile"), None);
+    }
+}
@@ -6,30 +6,61 @@
 #[cfg(feature = "image")]
 use std::error::Error;
 #[cfg(feature = "image")]
+use std::io::{BufRead, Seek};
+#[cfg(feature = "image")]
 use std::path::Path;
 use std::sync::Arc;
 
-use crate::kurbo::Size;
-use crate::util::unpremul;
-use crate::{Color, ImageFormat, RenderContext};
+use crate::kurbo::{Point, Rect, Size};
+use crate::util::{premul, unpremul};
+use crate::{Color, ColorSpace, ImageFormat, RenderContext};
 
 /// A trait for a backend's bitmap image type.
 ///
 /// This is cheaply cloneable.
+///
+/// Whether an image can be shared between threads depends on the backend:
+///
+/// | Backend      | `Send + Sync` | Reason                                      |
+/// |--------------|---------------|---------------------------------------------|
+/// | CoreGraphics | yes           | `CGImage` is immutable                      |
+/// | SVG          | yes           | images are plain pixel buffers              |
+/// | Cairo        | no            | image surfaces are mutable and refcounted   |
+/// | Direct2D     | no            | bitmaps belong to the device that made them |
+/// | Web          | no            | images are DOM elements                     |
+///
+/// To prepare images off the render thread portably, decode them into an
+/// [`ImageBuf`], which is always `Send + Sync`, and upload it with
+/// [`RenderContext::upload_image`].
 pub trait Image: Clone {
     /// The size of the image
     fn size(&self) -> Size;
+
+    /// The format of the image's pixels, as stored by the backend.
+    ///
+    /// This may differ from the format the image was made from; for
+    /// instance, most backends premultiply alpha.
+    fn format(&self) -> ImageFormat;
+
+    /// The number of bytes between the start of one row of the image's
+    /// pixels and the start of the next, as stored by the backend.
+    ///
+    /// This is at least `width * format().bytes_per_pixel()`, and is larger
+    /// if the backend pads its rows or stores pixels in a wider layout.
+    fn stride(&self) -> usize;
 }
 
 /// An in-memory pixel buffer.
 ///
-/// Contains raw bytes, dimensions, and image format ([`ImageFormat`]).
+/// Contains raw bytes, dimensions, image format ([`ImageFormat`]), and the
+/// [`ColorSpace`] of the pixels.
 #[derive(Clone)]
 pub struct ImageBuf {
     pixels: Arc<[u8]>,
     width: usize,
     height: usize,
     format: ImageFormat,
+    color_space: ColorSpace,
 }
 
 impl ImageBuf {
@@ -40,17 +71,21 @@ impl ImageBuf {
             width: 0,
             height: 0,
             format: ImageFormat::RgbaSeparate,
+            color_space: ColorSpace::Srgb,
         }
     }
 
     /// Creates a new image buffer from an array of bytes.
     ///
     /// `format` specifies the pixel format of the pixel data, which must have length
-    /// `width * height * format.bytes_per_pixel()`.
+    /// `width * height * format.bytes_per_pixel()`. The pixels are taken to be
+    /// sRGB; use [`with_color_space`] to tag them otherwise.
     ///
     /// # Panics
     ///
     /// Panics if the pixel data has the wrong length.
+    ///
+    /// [`with_color_space`]: ImageBuf::with_color_space
     pub fn from_raw(
         pixels: impl Into<Arc<[u8]>>,
         format: ImageFormat,
@@ -64,9 +99,19 @@ impl ImageBuf {
             width,
             height,
             format,
+            color_space: ColorSpace::Srgb,
         }
     }
 
+    /// Returns this image buffer, with its pixels tagged as being in
+    /// `color_space`.
+    ///
+    /// The pixel data is not changed.
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> ImageBuf {
+        self.color_space = color_space;
+        self
+    }
+
     /// Returns the raw pixel data of this image buffer.
     pub fn raw_pixels(&self) -> &[u8] {
         &self.pixels[..]
@@ -82,6 +127,14 @@ impl ImageBuf {
         self.format
     }
 
+    /// Returns the color space of the pixel data.
+    ///
+    /// This is sRGB unless the buffer was tagged otherwise, for instance from
+    /// the ICC profile of a decoded image.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
     /// The width, in pixels, of this image.
     pub fn width(&self) -> usize {
         self.width
@@ -120,10 +173,73 @@ impl ImageBuf {
             })
     }
 
+    /// Returns a copy of the pixels inside `rect`, in the same format.
+    ///
+    /// `rect` is in pixels; it is expanded to whole pixels and clipped to the
+    /// bounds of this image.
+    pub fn sub_image(&self, rect: Rect) -> ImageBuf {
+        let rect = rect
+            .expand()
+            .intersect(Rect::from_origin_size(Point::ZERO, self.size()));
+        if rect.area() <= 0.0 {
+            return ImageBuf::from_raw(Vec::new(), self.format, 0, 0)
+                .with_color_space(self.color_space);
+        }
+        let (x0, y0) = (rect.x0 as usize, rect.y0 as usize);
+        let (width, height) = (rect.width() as usize, rect.height() as usize);
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let stride = self.width * bytes_per_pixel;
+        let mut pixels = Vec::with_capacity(width * height * bytes_per_pixel);
+        for row in self.pixels.chunks_exact(stride).skip(y0).take(height) {
+            pixels.extend_from_slice(&row[x0 * bytes_per_pixel..(x0 + width) * bytes_per_pixel]);
+        }
+        ImageBuf::from_raw(pixels, self.format, width, height).with_color_space(self.color_space)
+    }
+
+    /// Returns a copy of this image converted to `format`.
+    ///
+    /// Converting to a format without alpha drops the alpha channel, keeping
+    /// the unpremultiplied color. Color is converted to grayscale using the
+    /// Rec. 709 luma coefficients.
+    ///
+    /// If the image is already in `format`, the pixel data is shared rather
+    /// than copied.
+    pub fn convert(&self, format: ImageFormat) -> ImageBuf {
+        if format == self.format {
+            return self.clone();
+        }
+        let mut pixels = Vec::with_capacity(self.width * self.height * format.bytes_per_pixel());
+        for p in self.pixels.chunks_exact(self.format.bytes_per_pixel()) {
+            let [r, g, b, a] = match self.format {
+                ImageFormat::Grayscale => [p[0], p[0], p[0], 255],
+                ImageFormat::Rgb => [p[0], p[1], p[2], 255],
+                ImageFormat::RgbaSeparate => [p[0], p[1], p[2], p[3]],
+                ImageFormat::RgbaPremul => {
+                    let a = p[3];
+                    [unpremul(p[0], a), unpremul(p[1], a), unpremul(p[2], a), a]
+                }
+            };
+            match format {
+                ImageFormat::Grayscale => {
+                    let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+                    pixels.push(luma.round() as u8);
+                }
+                ImageFormat::Rgb => pixels.extend_from_slice(&[r, g, b]),
+                ImageFormat::RgbaSeparate => pixels.extend_from_slice(&[r, g, b, a]),
+                ImageFormat::RgbaPremul => {
+                    pixels.extend_from_slice(&[premul(r, a), premul(g, a), premul(b, a), a]);
+                }
+            }
+        }
+        ImageBuf::from_raw(pixels, format, self.width, self.height)
+            .with_color_space(self.color_space)
+    }
+
     /// Converts this buffer an image that is optimized for drawing into a [`RenderContext`].
+    ///
+    /// This is [`RenderContext::upload_image`], panicking on failure.
     pub fn to_image<Ctx: RenderContext>(&self, ctx: &mut Ctx) -> Ctx::Image {
-        ctx.make_image(self.width(), self.height(), &self.pixels, self.format)
-            .unwrap()
+        ctx.upload_image(self).unwrap()
     }
 
     /// Returns `true` if the two `ImageBuf`s refer to the same memory location.
@@ -180,16 +296,59 @@ impl ImageBuf {
 
     /// Attempt to load an image from raw bytes.
     ///
+    /// The image format is guessed from the data. Only the formats enabled
+    /// with piet's image features (`image_png`, `jpeg`, `webp` and so on)
+    /// can be decoded.
+    ///
+    /// If the image embeds an ICC profile that [`ColorSpace::from_icc_profile`]
+    /// recognizes, the buffer is tagged with that color space, so that
+    /// [`RenderContext::upload_image`] displays its colors correctly. Pixels
+    /// with other profiles are taken to be sRGB.
+    ///
     /// If the image crate can't decode an image from the data an error will be returned.
     pub fn from_data(raw_image: &[u8]) -> Result<ImageBuf, Box<dyn Error + Send + Sync>> {
-        let image_data = image::load_from_memory(raw_image)?;
-        Ok(ImageBuf::from_dynamic_image(image_data))
+        let reader = image::ImageReader::new(std::io::Cursor::new(raw_image));
+        ImageBuf::decode(reader.with_guessed_format()?)
+    }
+
+    /// Attempt to decode an image from a reader, without reading it into
+    /// memory first.
+    ///
+    /// As with [`from_data`], the format is guessed from the data, and must
+    /// be enabled with the matching image feature, and an embedded ICC
+    /// profile sets the color space.
+    ///
+    /// [`from_data`]: ImageBuf::from_data
+    pub fn from_reader<R: BufRead + Seek>(
+        reader: R,
+    ) -> Result<ImageBuf, Box<dyn Error + Send + Sync>> {
+        ImageBuf::decode(image::ImageReader::new(reader).with_guessed_format()?)
     }
 
     /// Attempt to load an image from the file at the provided path.
+    ///
+    /// As with [`from_data`], an embedded ICC profile sets the color space.
+    ///
+    /// [`from_data`]: ImageBuf::from_data
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ImageBuf, Box<dyn Error + Send + Sync>> {
-        let image_data = image::open(path)?;
-        Ok(ImageBuf::from_dynamic_image(image_data))
+        ImageBuf::decode(image::ImageReader::open(path)?)
+    }
+
+    fn decode<R: BufRead + Seek>(
+        reader: image::ImageReader<R>,
+    ) -> Result<ImageBuf, Box<dyn Error + Send + Sync>> {
+        use image::ImageDecoder;
+
+        let mut decoder = reader.into_decoder()?;
+        // A profile that can't be read shouldn't stop the pixels from loading.
+        let color_space = decoder
+            .icc_profile()
+            .ok()
+            .flatten()
+            .and_then(|profile| ColorSpace::from_icc_profile(&profile))
+            .unwrap_or_default();
+        let image_data = image::DynamicImage::from_decoder(decoder)?;
+        Ok(ImageBuf::from_dynamic_image(image_data).with_color_space(color_space))
     }
 }
 
@@ -200,6 +359,108 @@ impl std::fmt::Debug for ImageBuf {
             .field("width", &self.width)
             .field("height", &self.height)
             .field("format", &format_args!("{:?}", self.format))
+            .field("color_space", &self.color_space)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "image_png")]
+    #[test]
+    fn decode_from_reader() {
+        let pixels = vec![255, 0, 0, 255, 0, 0, 255, 128];
+        let source = image::RgbaImage::from_raw(2, 1, pixels.clone()).unwrap();
+        let mut png = std::io::Cursor::new(Vec::new());
+        source.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        png.set_position(0);
+        let image = ImageBuf::from_reader(&mut png).unwrap();
+        assert_eq!(image.format(), ImageFormat::RgbaSeparate);
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.raw_pixels(), &pixels[..]);
+        let image = ImageBuf::from_data(png.get_ref()).unwrap();
+        assert_eq!(image.raw_pixels(), &pixels[..]);
+    }
+
+    #[cfg(feature = "image_png")]
+    #[test]
+    fn decode_icc_profile() {
+        fn crc32(data: &[u8]) -> u32 {
+            let mut crc = !0u32;
+            for byte in data {
+                crc ^= *byte as u32;
+                for _ in 0..8 {
+                    crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+                }
+            }
+            !crc
+        }
+
+        let source = image::RgbImage::from_raw(1, 1, vec![10, 20, 30]).unwrap();
+        let mut png = std::io::Cursor::new(Vec::new());
+        source.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let mut png = png.into_inner();
+        let plain = ImageBuf::from_data(&png).unwrap();
+        assert_eq!(plain.color_space(), ColorSpace::Srgb);
+
+        // An iCCP chunk, with the profile in a zlib stream of one stored block.
+        let profile = crate::icc::tests::display_p3_profile();
+        let mut data = b"iCCPp3\0\0\x78\x01\x01".to_vec();
+        let len = profile.len() as u16;
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&(!len).to_le_bytes());
+        data.extend_from_slice(&profile);
+        let (a, b) = profile.iter().fold((1u32, 0u32), |(a, b), byte| {
+            let a = (a + *byte as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        data.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        let mut chunk = ((data.len() - 4) as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(&data);
+        chunk.extend_from_slice(&crc32(&data).to_be_bytes());
+        // after the signature and the IHDR chunk
+        png.splice(33..33, chunk);
+
+        let image = ImageBuf::from_data(&png).unwrap();
+        assert_eq!(image.color_space(), ColorSpace::DisplayP3);
+        assert_eq!(image.raw_pixels(), &[10, 20, 30]);
+        let converted = image.convert(ImageFormat::RgbaSeparate);
+        assert_eq!(converted.color_space(), ColorSpace::DisplayP3);
+    }
+
+    #[test]
+    fn image_buf_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ImageBuf>();
+    }
+
+    #[test]
+    fn sub_image_and_convert() {
+        #[rustfmt::skip]
+        let pixels = vec![
+            255, 0, 0, 255,   0, 255, 0, 128,   0, 0, 255, 0,
+            10, 20, 30, 255,  40, 50, 60, 255,  70, 80, 90, 255,
+        ];
+        let image = ImageBuf::from_raw(pixels, ImageFormat::RgbaSeparate, 3, 2);
+
+        let sub = image.sub_image(Rect::new(0.5, 0.0, 5.0, 1.0));
+        assert_eq!((sub.width(), sub.height()), (3, 1));
+        let sub = image.sub_image(Rect::new(1.0, 0.0, 3.0, 2.0));
+        assert_eq!((sub.width(), sub.height()), (2, 2));
+        assert_eq!(sub.raw_pixels()[..4], [0, 255, 0, 128]);
+        assert_eq!(sub.raw_pixels()[8..12], [40, 50, 60, 255]);
+        assert_eq!(image.sub_image(Rect::new(4.0, 0.0, 6.0, 1.0)).width(), 0);
+
+        let premul = image.convert(ImageFormat::RgbaPremul);
+        assert_eq!(premul.raw_pixels()[4..8], [0, 128, 0, 128]);
+        assert_eq!(premul.raw_pixels()[8..12], [0, 0, 0, 0]);
+        let rgb = premul.convert(ImageFormat::Rgb);
+        assert_eq!(rgb.raw_pixels()[..6], [255, 0, 0, 0, 255, 0]);
+        let grey = rgb.convert(ImageFormat::Grayscale);
+        assert_eq!(grey.raw_pixels()[..2], [54, 182]);
+        assert!(image.convert(ImageFormat::RgbaSeparate).ptr_eq(&image));
+    }
+}
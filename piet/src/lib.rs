@@ -18,11 +18,31 @@
 //! backend based on the compile target, and provides convenience
 //! types and methods for setting up a [`RenderContext`].
 //!
+//! # Other geometry crates
+//!
+//! Piet's geometry types all come from [`kurbo`], which is re-exported as
+//! `piet::kurbo`. Conversions between these and the types of other geometry
+//! crates can't be provided here, as neither side of such a conversion
+//! belongs to piet. Instead, kurbo can convert its `Point`, `Vec2` and
+//! `Affine` to and from the types of [`mint`], which `glam` and `euclid`
+//! also support behind their `mint` features. To use them, enable the
+//! `mint` feature of your own kurbo dependency; as long as it is the same
+//! version that piet uses, the feature applies to piet's kurbo too:
+//!
+//! ```toml
+//! kurbo = { version = "0.11", features = ["mint"] }
+//! ```
+//!
+//! Piet does not provide `From` conversions for `euclid` or `glam` types,
+//! and `mint` has no rectangle type, so a `Rect` has to be built from its
+//! corners, for example with [`kurbo::Rect::from_points`].
+//!
 //! [`PostScript`]: https://en.wikipedia.org/wiki/PostScript
 //! [`piet-common`]: https://docs.rs/piet-common
 //! [`piet-cairo`]: https://crates.io/crates/piet-cairo
 //! [`piet-coregraphics`]: https://crates.io/crates/piet-coregraphics
 //! [`piet-direct2d`]: https://crates.io/crates/piet-direct2d
+//! [`mint`]: https://docs.rs/mint
 
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 #![warn(missing_docs)]
@@ -33,23 +53,37 @@ pub use kurbo;
 #[cfg(feature = "image")]
 pub use ::image as image_crate;
 
+#[cfg(feature = "svg")]
+pub use usvg;
+
 /// utilities shared by various backends
 pub mod util;
 
+pub mod hit_test;
+
+mod bounds_tracker;
 mod color;
 mod conv;
 mod error;
 mod font;
 mod gradient;
+mod icc;
 mod image;
 mod null_renderer;
 mod render_context;
 mod shapes;
+mod stack_checker;
+#[cfg(feature = "svg")]
+mod svg;
 mod text;
 
 #[cfg(feature = "samples")]
 pub mod samples;
 
+#[cfg(feature = "conformance")]
+pub mod conformance;
+
+pub use crate::bounds_tracker::*;
 pub use crate::color::*;
 pub use crate::conv::*;
 pub use crate::error::*;
@@ -59,4 +93,5 @@ pub use crate::image::*;
 pub use crate::null_renderer::*;
 pub use crate::render_context::*;
 pub use crate::shapes::*;
+pub use crate::stack_checker::*;
 pub use crate::text::*;
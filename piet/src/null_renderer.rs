@@ -1,25 +1,76 @@
 // Copyright 2019 the Piet Authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-//! A render context that does nothing.
+//! A render context that does nothing, except keep count.
 
 use std::borrow::Cow;
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
+use std::rc::Rc;
 
 use kurbo::{Affine, Point, Rect, Shape, Size};
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::util::{self, DEFAULT_FONT_SIZE};
 use crate::{
-    Color, Error, FixedGradient, FontFamily, HitTestPoint, HitTestPosition, Image, ImageFormat,
-    InterpolationMode, IntoBrush, LineMetric, RenderContext, StrokeStyle, Text, TextAttribute,
-    TextLayout, TextLayoutBuilder, TextStorage,
+    Affinity, CachedShape, Color, Error, FixedGradient, FontDescription, FontFamily, FontStyle,
+    FontWeight, HitTestPoint, HitTestPosition, Image, ImageFormat, InterpolationMode, IntoBrush,
+    LineMetric, RenderContext, StrokeStyle, Text, TextAttribute, TextLayout, TextLayoutBuilder,
+    TextStorage,
 };
 
 /// A render context that doesn't render.
 ///
 /// This is useful largely for doc tests, but is made public in case
 /// it might come in handy.
+///
+/// While it doesn't draw anything, it does keep track of the work it is asked
+/// to do; see [`NullRenderContext::stats`].
 #[doc(hidden)]
-pub struct NullRenderContext(NullText);
+pub struct NullRenderContext {
+    text: NullText,
+    stats: RenderStats,
+    transform: Affine,
+    transform_stack: Vec<Affine>,
+}
+
+/// Statistics about the work requested of a [`NullRenderContext`].
+///
+/// This can be used to get a rough, backend-independent picture of how
+/// expensive some drawing code is.
+///
+/// ```
+/// use piet::kurbo::Rect;
+/// use piet::{Color, NullRenderContext, RenderContext};
+///
+/// let mut ctx = NullRenderContext::new();
+/// let brush = ctx.solid_brush(Color::WHITE);
+/// ctx.fill(Rect::new(0.0, 0.0, 10.0, 20.0), &brush);
+///
+/// let stats = ctx.stats();
+/// assert_eq!(stats.draw_calls, 1);
+/// assert_eq!(stats.brushes_created, 1);
+/// assert_eq!(stats.fill_area, 200.0);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct RenderStats {
+    /// The number of calls that draw something, including fills, strokes,
+    /// text, images, blurred rects, and clears.
+    pub draw_calls: usize,
+    /// The total number of path segments in filled, stroked, and clipped shapes.
+    pub path_segments: usize,
+    /// The number of brushes created, both solid and gradient.
+    pub brushes_created: usize,
+    /// The number of images created, either from pixel data or by capturing
+    /// an area of the context.
+    pub images_created: usize,
+    /// The estimated total area covered by fills, in device pixels.
+    ///
+    /// Overlapping fills are counted separately.
+    pub fill_area: f64,
+    /// The estimated total area covered by strokes, in device pixels.
+    pub stroke_area: f64,
+}
 
 #[derive(Clone)]
 #[doc(hidden)]
@@ -28,113 +79,250 @@ pub struct NullBrush;
 #[derive(Clone)]
 pub struct NullImage;
 
+/// Text with made up, but deterministic, metrics.
+///
+/// Every grapheme is half the font size wide, and every line is 1.2 times
+/// the font size tall, with the baseline at 0.8 times the font size. Lines
+/// break at newlines, and wrap at whitespace to fit the maximum width.
+/// Only the default font family and size are respected; other attributes
+/// are ignored.
+///
+/// This lets code that lays out text be tested without a real backend:
+///
+/// ```
+/// use piet::{NullRenderContext, RenderContext, Text, TextLayout, TextLayoutBuilder};
+///
+/// let mut ctx = NullRenderContext::new();
+/// let layout = ctx
+///     .text()
+///     .new_text_layout("hello world")
+///     .font(piet::FontFamily::SYSTEM_UI, 10.0)
+///     .max_width(40.0)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(layout.line_count(), 2);
+/// assert_eq!(layout.line_text(0), Some("hello "));
+/// assert_eq!(layout.size(), piet::kurbo::Size::new(25.0, 24.0));
+/// ```
 #[derive(Clone)]
 #[doc(hidden)]
 pub struct NullText;
 
 #[doc(hidden)]
 #[derive(Clone)]
-pub struct NullTextLayout;
+pub struct NullTextLayout {
+    text: Rc<dyn TextStorage>,
+    font: FontFamily,
+    font_size: f64,
+    lines: Rc<[LineMetric]>,
+    size: Size,
+    trailing_whitespace_width: f64,
+    links: util::TextLinks,
+}
+
 #[doc(hidden)]
-pub struct NullTextLayoutBuilder;
+pub struct NullTextLayoutBuilder {
+    text: Rc<dyn TextStorage>,
+    font: FontFamily,
+    font_size: f64,
+    max_width: f64,
+    links: util::TextLinks,
+}
 
 impl NullRenderContext {
     #[allow(clippy::new_without_default)]
     #[doc(hidden)]
     pub fn new() -> NullRenderContext {
-        NullRenderContext(NullText)
+        NullRenderContext {
+            text: NullText,
+            stats: RenderStats::default(),
+            transform: Affine::IDENTITY,
+            transform_stack: Vec::new(),
+        }
+    }
+
+    /// Statistics about the work done with this context so far.
+    pub fn stats(&self) -> &RenderStats {
+        &self.stats
+    }
+
+    /// Reset the statistics, for instance at the start of a new frame.
+    pub fn reset_stats(&mut self) {
+        self.stats = RenderStats::default();
+    }
+
+    /// The factor by which the current transform scales areas.
+    fn area_scale(&self) -> f64 {
+        self.transform.determinant().abs()
+    }
+
+    fn record_fill(&mut self, shape: impl Shape) {
+        self.stats.draw_calls += 1;
+        self.stats.path_segments += shape.path_segments(TOLERANCE).count();
+        self.stats.fill_area += shape.area().abs() * self.area_scale();
+    }
+
+    fn record_stroke(&mut self, shape: impl Shape, width: f64) {
+        self.stats.draw_calls += 1;
+        self.stats.path_segments += shape.path_segments(TOLERANCE).count();
+        self.stats.stroke_area += shape.perimeter(TOLERANCE) * width * self.area_scale();
+    }
+
+    fn record_rect(&mut self, rect: Rect) {
+        self.stats.draw_calls += 1;
+        self.stats.fill_area += rect.area().abs() * self.area_scale();
     }
 }
 
+/// The tolerance used when flattening shapes for statistics.
+const TOLERANCE: f64 = 0.1;
+
 impl RenderContext for NullRenderContext {
     type Brush = NullBrush;
     type Image = NullImage;
     type Text = NullText;
     type TextLayout = NullTextLayout;
+    type CachedShape = CachedShape<()>;
 
     fn status(&mut self) -> Result<(), Error> {
         Ok(())
     }
 
     fn solid_brush(&mut self, _color: Color) -> Self::Brush {
+        self.stats.brushes_created += 1;
         NullBrush
     }
 
     fn gradient(&mut self, _gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
+        self.stats.brushes_created += 1;
         Ok(NullBrush)
     }
 
-    fn clear(&mut self, _: impl Into<Option<Rect>>, _color: Color) {}
+    fn brush_with_opacity(&mut self, _brush: &Self::Brush, _opacity: f64) -> Self::Brush {
+        self.stats.brushes_created += 1;
+        NullBrush
+    }
 
-    fn stroke(&mut self, _shape: impl Shape, _brush: &impl IntoBrush<Self>, _width: f64) {}
+    fn clear(&mut self, region: impl Into<Option<Rect>>, _color: Color) {
+        // Clearing ignores the transform, and without a region we don't know
+        // how big the target is, so only the call itself is counted.
+        self.stats.draw_calls += 1;
+        if let Some(rect) = region.into() {
+            self.stats.fill_area += rect.area().abs();
+        }
+    }
+
+    fn stroke(&mut self, shape: impl Shape, _brush: &impl IntoBrush<Self>, width: f64) {
+        self.record_stroke(shape, width);
+    }
 
     fn stroke_styled(
         &mut self,
-        _shape: impl Shape,
+        shape: impl Shape,
         _brush: &impl IntoBrush<Self>,
-        _width: f64,
+        width: f64,
         _style: &StrokeStyle,
     ) {
+        self.record_stroke(shape, width);
+    }
+
+    fn fill(&mut self, shape: impl Shape, _brush: &impl IntoBrush<Self>) {
+        self.record_fill(shape);
     }
 
-    fn fill(&mut self, _shape: impl Shape, _brush: &impl IntoBrush<Self>) {}
+    fn fill_even_odd(&mut self, shape: impl Shape, _brush: &impl IntoBrush<Self>) {
+        self.record_fill(shape);
+    }
 
-    fn fill_even_odd(&mut self, _shape: impl Shape, _brush: &impl IntoBrush<Self>) {}
+    fn clip(&mut self, shape: impl Shape) {
+        self.stats.path_segments += shape.path_segments(TOLERANCE).count();
+    }
 
-    fn clip(&mut self, _shape: impl Shape) {}
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        self.stats.path_segments += shape.path_segments(TOLERANCE).count();
+    }
+
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error> {
+        Ok(CachedShape::new(shape.into_path(TOLERANCE), ()))
+    }
 
     fn text(&mut self) -> &mut Self::Text {
-        &mut self.0
+        &mut self.text
     }
 
-    fn draw_text(&mut self, _layout: &Self::TextLayout, _pos: impl Into<Point>) {}
+    fn draw_text(&mut self, _layout: &Self::TextLayout, _pos: impl Into<Point>) {
+        self.stats.draw_calls += 1;
+    }
 
     fn save(&mut self) -> Result<(), Error> {
+        self.transform_stack.push(self.transform);
         Ok(())
     }
     fn restore(&mut self) -> Result<(), Error> {
+        self.transform = self.transform_stack.pop().ok_or(Error::StackUnbalance)?;
         Ok(())
     }
     fn finish(&mut self) -> Result<(), Error> {
         Ok(())
     }
-    fn transform(&mut self, _transform: Affine) {}
+    fn transform(&mut self, transform: Affine) {
+        self.transform *= transform;
+    }
 
     fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        self.stats.images_created += 1;
         Ok(NullImage)
     }
 
     fn make_image_with_stride(
         &mut self,
-        _width: usize,
-        _height: usize,
-        _stride: usize,
-        _buf: &[u8],
-        _format: ImageFormat,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buf: &[u8],
+        format: ImageFormat,
     ) -> Result<Self::Image, Error> {
+        crate::util::validate_image_args(width, height, stride, format, buf.len())?;
+        self.stats.images_created += 1;
         Ok(NullImage)
     }
 
     fn draw_image(
         &mut self,
         _image: &Self::Image,
-        _dst_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
         _interp: InterpolationMode,
     ) {
+        self.record_rect(dst_rect.into());
     }
     fn draw_image_area(
         &mut self,
         _image: &Self::Image,
         _src_rect: impl Into<Rect>,
-        _dst_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        _interp: InterpolationMode,
+    ) {
+        self.record_rect(dst_rect.into());
+    }
+
+    fn draw_image_area_with_opacity(
+        &mut self,
+        _image: &Self::Image,
+        _src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
         _interp: InterpolationMode,
+        _opacity: f64,
     ) {
+        self.record_rect(dst_rect.into());
     }
 
-    fn blurred_rect(&mut self, _rect: Rect, _blur_radius: f64, _brush: &impl IntoBrush<Self>) {}
+    fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, _brush: &impl IntoBrush<Self>) {
+        self.record_rect(rect.inflate(blur_radius, blur_radius));
+    }
 
     fn current_transform(&self) -> Affine {
-        Affine::default()
+        self.transform
     }
 }
 
@@ -146,8 +334,14 @@ impl Text for NullText {
         Ok(FontFamily::default())
     }
 
-    fn new_text_layout(&mut self, _text: impl TextStorage) -> Self::TextLayoutBuilder {
-        NullTextLayoutBuilder
+    fn new_text_layout(&mut self, text: impl TextStorage) -> Self::TextLayoutBuilder {
+        NullTextLayoutBuilder {
+            text: Rc::new(text),
+            font: FontFamily::default(),
+            font_size: DEFAULT_FONT_SIZE,
+            max_width: f64::INFINITY,
+            links: util::TextLinks::default(),
+        }
     }
 
     fn font_family(&mut self, _family_name: &str) -> Option<FontFamily> {
@@ -158,7 +352,8 @@ impl Text for NullText {
 impl TextLayoutBuilder for NullTextLayoutBuilder {
     type Out = NullTextLayout;
 
-    fn max_width(self, _width: f64) -> Self {
+    fn max_width(mut self, width: f64) -> Self {
+        self.max_width = width;
         self
     }
 
@@ -166,58 +361,243 @@ impl TextLayoutBuilder for NullTextLayoutBuilder {
         self
     }
 
-    fn default_attribute(self, _attribute: impl Into<TextAttribute>) -> Self {
+    fn default_attribute(mut self, attribute: impl Into<TextAttribute>) -> Self {
+        match attribute.into() {
+            TextAttribute::FontFamily(font) => self.font = font,
+            TextAttribute::FontSize(size) => {
+                self.font_size = if size <= 0.0 { DEFAULT_FONT_SIZE } else { size };
+            }
+            TextAttribute::Link(url) => self.links.add(0..self.text.as_str().len(), &url),
+            _ => (),
+        }
         self
     }
 
     fn range_attribute(
-        self,
-        _range: impl RangeBounds<usize>,
-        _attribute: impl Into<TextAttribute>,
+        mut self,
+        range: impl RangeBounds<usize>,
+        attribute: impl Into<TextAttribute>,
     ) -> Self {
+        if let TextAttribute::Link(url) = attribute.into() {
+            let range = util::resolve_range(range, self.text.as_str().len());
+            self.links.add(range, &url);
+        }
         self
     }
 
     fn build(self) -> Result<Self::Out, Error> {
-        Ok(NullTextLayout)
+        let mut layout = NullTextLayout::new(self.text, self.font, self.font_size, self.max_width);
+        layout.links = self.links;
+        Ok(layout)
+    }
+}
+
+impl NullTextLayout {
+    fn new(text: Rc<dyn TextStorage>, font: FontFamily, font_size: f64, max_width: f64) -> Self {
+        let mut layout = NullTextLayout {
+            text,
+            font,
+            font_size,
+            lines: Rc::new([]),
+            size: Size::ZERO,
+            trailing_whitespace_width: 0.0,
+            links: util::TextLinks::default(),
+        };
+        layout.break_lines(max_width);
+        layout
+    }
+
+    fn advance(&self) -> f64 {
+        self.font_size * 0.5
+    }
+
+    fn line_height(&self) -> f64 {
+        self.font_size * 1.2
+    }
+
+    /// Break the text into lines at newlines, and at the last whitespace
+    /// before a grapheme that would not fit in `max_width`.
+    fn break_lines(&mut self, max_width: f64) {
+        let text = self.text.as_str();
+        let advance = self.advance();
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        // graphemes on the current line, and the last place it can wrap
+        let mut count = 0;
+        let mut wrap_at = None;
+        let mut after_whitespace = false;
+
+        for (idx, grapheme) in text.grapheme_indices(true) {
+            if is_newline(grapheme) {
+                ranges.push(start..idx + grapheme.len());
+                start = idx + grapheme.len();
+                count = 0;
+                wrap_at = None;
+                after_whitespace = false;
+                continue;
+            }
+            let is_whitespace = grapheme.chars().all(char::is_whitespace);
+            if !is_whitespace {
+                if after_whitespace {
+                    wrap_at = Some(idx);
+                }
+                if count > 0 && (count + 1) as f64 * advance > max_width {
+                    let end = wrap_at.unwrap_or(idx);
+                    ranges.push(start..end);
+                    start = end;
+                    count = text[start..idx].graphemes(true).count();
+                    wrap_at = None;
+                }
+            }
+            count += 1;
+            after_whitespace = is_whitespace;
+        }
+        ranges.push(start..text.len());
+
+        let height = self.line_height();
+        let mut width = 0.0f64;
+        let mut trailing_whitespace_width = 0.0f64;
+        let lines: Vec<_> = ranges
+            .into_iter()
+            .enumerate()
+            .map(|(i, range)| {
+                let line = &text[range.clone()];
+                let trimmed = line.trim_end();
+                width = width.max(trimmed.graphemes(true).count() as f64 * advance);
+                let full = line.graphemes(true).filter(|g| !is_newline(g)).count();
+                trailing_whitespace_width = trailing_whitespace_width.max(full as f64 * advance);
+                LineMetric {
+                    start_offset: range.start,
+                    end_offset: range.end,
+                    trailing_whitespace: line.len() - trimmed.len(),
+                    baseline: self.font_size * 0.8,
+                    height,
+                    y_offset: i as f64 * height,
+                }
+            })
+            .collect();
+
+        self.size = Size::new(width, lines.len() as f64 * height);
+        self.trailing_whitespace_width = trailing_whitespace_width;
+        self.lines = lines.into();
+    }
+
+    /// The number of graphemes on `line` before `idx`, which must not be
+    /// before the start of the line.
+    fn graphemes_before(&self, line: &LineMetric, idx: usize) -> usize {
+        let text = &self.text.as_str()[line.range()];
+        if idx >= line.end_offset {
+            return text.graphemes(true).filter(|g| !is_newline(g)).count();
+        }
+        text.grapheme_indices(true)
+            .take_while(|(i, _)| line.start_offset + i <= idx)
+            .count()
+            .saturating_sub(1)
     }
 }
 
+fn is_newline(grapheme: &str) -> bool {
+    matches!(grapheme, "\n" | "\r\n")
+}
+
 impl TextLayout for NullTextLayout {
     fn size(&self) -> Size {
-        Size::ZERO
+        self.size
     }
 
     fn trailing_whitespace_width(&self) -> f64 {
-        0.0
+        self.trailing_whitespace_width
     }
 
     fn image_bounds(&self) -> Rect {
-        Rect::ZERO
+        self.size.to_rect()
     }
 
-    fn line_text(&self, _line_number: usize) -> Option<&str> {
-        None
+    fn with_max_width(&self, width: f64) -> Self {
+        let mut layout = self.clone();
+        layout.break_lines(width);
+        layout
     }
 
-    fn line_metric(&self, _line_number: usize) -> Option<LineMetric> {
-        None
+    fn with_color_override(&self, _range: impl RangeBounds<usize>, _color: Color) -> Self {
+        self.clone()
     }
 
-    fn line_count(&self) -> usize {
-        0
+    fn line_text(&self, line_number: usize) -> Option<&str> {
+        self.lines
+            .get(line_number)
+            .map(|line| &self.text.as_str()[line.range()])
     }
 
-    fn hit_test_point(&self, _point: Point) -> HitTestPoint {
-        HitTestPoint::default()
+    fn line_metric(&self, line_number: usize) -> Option<LineMetric> {
+        self.lines.get(line_number).cloned()
     }
 
-    fn hit_test_text_position(&self, _text_position: usize) -> HitTestPosition {
-        HitTestPosition::default()
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        let line_number = self
+            .lines
+            .iter()
+            .position(|line| point.y < line.y_offset + line.height)
+            .unwrap_or(self.lines.len() - 1);
+        let line = &self.lines[line_number];
+        let is_last = line_number + 1 == self.lines.len();
+
+        // the positions on a line are the starts of its graphemes, and on
+        // the last line, the end of the text.
+        let text = &self.text.as_str()[line.range()];
+        let mut stops = text
+            .grapheme_indices(true)
+            .map(|(i, _)| line.start_offset + i)
+            .chain(is_last.then_some(line.end_offset));
+        let line_width = self.graphemes_before(line, line.end_offset) as f64 * self.advance();
+        let n = (point.x / self.advance()).round().max(0.0) as usize;
+        let idx = stops.clone().nth(n).or_else(|| stops.next_back());
+        let idx = idx.unwrap_or(line.start_offset);
+
+        let is_inside = point.x >= 0.0
+            && point.x <= line_width
+            && point.y >= 0.0
+            && point.y <= self.size.height;
+        let affinity = if point.x > line_width {
+            Affinity::Upstream
+        } else {
+            Affinity::Downstream
+        };
+        HitTestPoint::new(idx, is_inside).with_affinity(affinity)
+    }
+
+    fn hit_test_text_position(&self, idx: usize) -> HitTestPosition {
+        let mut idx = idx.min(self.text.as_str().len());
+        while !self.text.as_str().is_char_boundary(idx) {
+            idx -= 1;
+        }
+        let line_number = util::line_number_for_position(&self.lines, idx);
+        let line = &self.lines[line_number];
+        let x = self.graphemes_before(line, idx) as f64 * self.advance();
+        HitTestPosition::new(Point::new(x, line.y_offset + line.baseline), line_number)
     }
 
     fn text(&self) -> &str {
-        ""
+        self.text.as_str()
+    }
+
+    fn resolved_fonts(&self) -> Vec<(Range<usize>, FontDescription)> {
+        let font = FontDescription {
+            family: self.font.clone(),
+            weight: FontWeight::REGULAR,
+            style: FontStyle::Regular,
+            size: self.font_size,
+        };
+        let len = self.text.as_str().len();
+        util::merge_font_runs(vec![(0..len, font)])
+    }
+
+    fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.links.links()
     }
 }
 
@@ -235,4 +615,118 @@ impl Image for NullImage {
     fn size(&self) -> Size {
         Size::ZERO
     }
+
+    fn format(&self) -> ImageFormat {
+        ImageFormat::RgbaPremul
+    }
+
+    fn stride(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(text: &'static str, max_width: f64) -> NullTextLayout {
+        NullText
+            .new_text_layout(text)
+            .font(FontFamily::SYSTEM_UI, 10.0)
+            .max_width(max_width)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn with_save_keeps_both_errors() {
+        let mut ctx = NullRenderContext::new();
+        let result = ctx.with_save(|rc| {
+            rc.restore()?;
+            Err(Error::InvalidInput)
+        });
+        assert!(matches!(
+            result,
+            Err(Error::RestoreFailed { error, restore })
+                if matches!(*error, Error::InvalidInput) && matches!(*restore, Error::StackUnbalance)
+        ));
+
+        let result = ctx.with_save(|_| Err(Error::InvalidInput));
+        assert!(matches!(result, Err(Error::InvalidInput)));
+    }
+
+    #[test]
+    fn null_text_metrics() {
+        let layout = layout("one two\nthree", 30.0);
+        let lines: Vec<_> = (0..layout.line_count())
+            .map(|i| layout.line_text(i).unwrap())
+            .collect();
+        assert_eq!(lines, ["one ", "two\n", "three"]);
+        assert_eq!(layout.size(), Size::new(25.0, 36.0));
+        assert_eq!(layout.line_metric(1).unwrap().trailing_whitespace, 1);
+
+        let pos = layout.hit_test_text_position(6);
+        assert_eq!(pos.line, 1);
+        assert_eq!(pos.point, Point::new(10.0, 20.0));
+
+        let hit = layout.hit_test_point(Point::new(11.0, 20.0));
+        assert_eq!(hit.idx, 6);
+        assert!(hit.is_inside);
+        let hit = layout.hit_test_point(Point::new(100.0, 30.0));
+        assert_eq!(hit.idx, 13);
+        assert_eq!(hit.affinity, Affinity::Upstream);
+    }
+
+    #[test]
+    fn null_text_resolved_fonts() {
+        let fonts = layout("hello\nworld", f64::INFINITY).resolved_fonts();
+        assert_eq!(fonts.len(), 1);
+        assert_eq!(fonts[0].0, 0..11);
+        assert_eq!(fonts[0].1.family, FontFamily::SYSTEM_UI);
+        assert_eq!(fonts[0].1.size, 10.0);
+        assert!(layout("", f64::INFINITY).resolved_fonts().is_empty());
+    }
+
+    #[test]
+    fn null_text_links() {
+        let url = "https://example.com";
+        let layout = NullText
+            .new_text_layout("see the docs")
+            .font(FontFamily::SYSTEM_UI, 10.0)
+            .range_attribute(4.., TextAttribute::Link(url.into()))
+            .build()
+            .unwrap();
+        assert_eq!(layout.links(), [(4..12, url)]);
+        assert_eq!(
+            layout.link_rects(),
+            [(Rect::new(20.0, 0.0, 60.0, 12.0), url)]
+        );
+        assert_eq!(layout.link_at_point(Point::new(25.0, 6.0)), Some(url));
+        assert_eq!(layout.link_at_point(Point::new(5.0, 6.0)), None);
+
+        // "see the " and "docs" are on separate lines
+        let wrapped = layout.with_max_width(40.0);
+        assert_eq!(wrapped.links(), [(4..12, url)]);
+        assert_eq!(wrapped.link_rects().len(), 2);
+        assert_eq!(wrapped.link_at_point(Point::new(5.0, 18.0)), Some(url));
+    }
+
+    #[test]
+    #[cfg(feature = "conformance")]
+    fn null_text_layout_invariants() {
+        for text in [
+            "",
+            "a\n",
+            "wrapping without spaces",
+            "e\u{301}\r\n\u{1F600}  x",
+        ] {
+            crate::conformance::check_layout_invariants(&layout(text, 20.0));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "conformance")]
+    fn null_text_conformance() {
+        crate::conformance::check_all(&mut NullText);
+    }
 }
@@ -3,13 +3,15 @@
 
 //! The main render context trait.
 
+use std::any::Any;
 use std::borrow::Cow;
 
-use kurbo::{Affine, Point, Rect, Shape};
+use kurbo::{Affine, Insets, Point, Rect, Shape};
 
 use crate::{
-    Color, Error, FixedGradient, FixedLinearGradient, FixedRadialGradient, Image, LinearGradient,
-    RadialGradient, StrokeStyle, Text, TextLayout,
+    Color, ColorSpace, Error, FixedEllipticalGradient, FixedGradient, FixedLinearGradient,
+    FixedRadialGradient, Image, ImageBuf, LinearGradient, RadialGradient, StrokeStyle,
+    StrokeStyleHandle, Text, TextLayout,
 };
 
 /// A requested interpolation mode for drawing images.
@@ -21,6 +23,16 @@ pub enum InterpolationMode {
     Bilinear,
 }
 
+/// How an image is repeated to fill an area larger than itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileMode {
+    /// Repeat the image.
+    Repeat,
+    /// Repeat the image, mirroring every other copy, so that neighbouring
+    /// copies meet at matching edges.
+    Mirror,
+}
+
 /// The pixel format for bitmap images.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[non_exhaustive]
@@ -86,6 +98,13 @@ where
     /// The associated type of an image.
     type Image: Image;
 
+    /// The type of a shape prepared for repeated drawing.
+    ///
+    /// See [`prepare_shape`] for details.
+    ///
+    /// [`prepare_shape`]: RenderContext::prepare_shape
+    type CachedShape: Shape + Clone;
+
     /// Report an internal error.
     ///
     /// Drawing operations may cause internal errors, which may also occur
@@ -102,9 +121,54 @@ where
     /// other potentially retained objects will be heavier.
     fn solid_brush(&mut self, color: Color) -> Self::Brush;
 
+    /// Create a new brush resource for a color in the given [`ColorSpace`].
+    ///
+    /// Backends that support color management tag the brush with the color
+    /// space, so that wide-gamut colors are displayed correctly; the default
+    /// implementation converts the color to sRGB.
+    fn solid_brush_with_color_space(
+        &mut self,
+        color: Color,
+        color_space: ColorSpace,
+    ) -> Self::Brush {
+        self.solid_brush(color_space.to_srgb(color))
+    }
+
     /// Create a new gradient brush.
     fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error>;
 
+    /// Create a new gradient brush, with a transform applied to the gradient.
+    ///
+    /// `transform` maps the gradient's coordinates into user space, so that a
+    /// gradient can be rotated, scaled or skewed independently of the shapes
+    /// it is used to fill. Returns [`Error::InvalidInput`] if the transform is
+    /// not invertible.
+    ///
+    /// The default implementation transforms the gradient itself with
+    /// [`util::transform_gradient`]. This is exact for linear gradients, and
+    /// for radial and elliptical gradients that end up circular; others
+    /// return [`Error::NotSupported`].
+    ///
+    /// [`util::transform_gradient`]: crate::util::transform_gradient
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Self::Brush, Error> {
+        if transform.determinant() == 0.0 {
+            return Err(Error::InvalidInput);
+        }
+        let gradient = crate::util::transform_gradient(gradient.into(), transform)
+            .ok_or(Error::NotSupported)?;
+        self.gradient(gradient)
+    }
+
+    /// Create a copy of a brush with its opacity multiplied by `opacity`.
+    ///
+    /// This fades a whole brush, such as a gradient, without rebuilding it
+    /// with different colors. `opacity` is clamped to the range `0.0..=1.0`.
+    fn brush_with_opacity(&mut self, brush: &Self::Brush, opacity: f64) -> Self::Brush;
+
     /// Replace a region of the canvas with the provided [`Color`].
     ///
     /// The region can be omitted, in which case it will apply to the entire
@@ -128,13 +192,67 @@ where
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64);
 
     /// Stroke a [`Shape`], providing a custom [`StrokeStyle`].
+    ///
+    /// The default implementation fills the outline of the stroke from
+    /// [`util::expand_stroke`]; backends that can draw strokes natively should
+    /// override it.
+    ///
+    /// [`util::expand_stroke`]: crate::util::expand_stroke
     fn stroke_styled(
         &mut self,
         shape: impl Shape,
         brush: &impl IntoBrush<Self>,
         width: f64,
         style: &StrokeStyle,
-    );
+    ) {
+        let outline = crate::util::expand_stroke(shape, width, style);
+        self.fill(outline, brush);
+    }
+
+    /// Register a [`StrokeStyle`] for repeated use with [`stroke_with_handle`].
+    ///
+    /// Backends that convert stroke styles into their own objects can keep
+    /// the converted style for the handle, rather than converting the style
+    /// on every call to [`stroke_styled`]. This is worth doing for styles
+    /// used on hot paths.
+    ///
+    /// [`stroke_with_handle`]: RenderContext::stroke_with_handle
+    /// [`stroke_styled`]: RenderContext::stroke_styled
+    fn register_stroke_style(&mut self, style: &StrokeStyle) -> StrokeStyleHandle {
+        StrokeStyleHandle::new(style.clone())
+    }
+
+    /// Stroke a [`Shape`] with a style from [`register_stroke_style`].
+    ///
+    /// [`register_stroke_style`]: RenderContext::register_stroke_style
+    fn stroke_with_handle(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyleHandle,
+    ) {
+        self.stroke_styled(shape, brush, width, style.style())
+    }
+
+    /// Stroke a [`Shape`] with a hairline, using the default [`StrokeStyle`].
+    ///
+    /// A hairline is one device pixel wide, regardless of any transforms that
+    /// have been applied with [`transform`] and of the [`scale_factor`]. This
+    /// is useful for things like grid lines and outlines that should stay
+    /// thin at any zoom level.
+    ///
+    /// The default implementation divides the width by the scale factor and
+    /// the average scale of the current transform, which is exact for uniform
+    /// scales. Backends may do better for non-uniform scales.
+    ///
+    /// [`transform`]: RenderContext::transform
+    /// [`scale_factor`]: RenderContext::scale_factor
+    fn stroke_hairline(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let scale = self.scale_factor() * self.current_transform().determinant().abs().sqrt();
+        let width = if scale > 0.0 { scale.recip() } else { 1.0 };
+        self.stroke(shape, brush, width)
+    }
 
     /// Fill a [`Shape`], using the [non-zero fill rule].
     ///
@@ -146,6 +264,45 @@ where
     /// [even-odd fill rule]: https://en.wikipedia.org/wiki/Even–odd_rule
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>);
 
+    /// Fill a mesh of triangles, with colors interpolated between vertices.
+    ///
+    /// Each triple of `indices` is a triangle, made of the vertices at those
+    /// indices. Triangles with an index out of range are skipped, as are any
+    /// indices left over at the end.
+    ///
+    /// The default implementation rasterizes the mesh into an image at device
+    /// resolution with [`util::rasterize_mesh`] and draws that; backends that
+    /// can draw meshes directly do so instead. The device resolution is found
+    /// from the [`scale_factor`] and the [`current_transform`], so backends
+    /// relying on this must leave the scale factor out of their transform.
+    ///
+    /// [`scale_factor`]: RenderContext::scale_factor
+    /// [`current_transform`]: RenderContext::current_transform
+    ///
+    /// [`util::rasterize_mesh`]: crate::util::rasterize_mesh
+    fn fill_mesh(&mut self, vertices: &[(Point, Color)], indices: &[u16]) {
+        let to_device = Affine::scale(self.scale_factor()) * self.current_transform();
+        if to_device.determinant() == 0.0 {
+            return;
+        }
+        let vertices: Vec<_> = vertices
+            .iter()
+            .map(|&(point, color)| (to_device * point, color))
+            .collect();
+        let Some((rect, buf)) = crate::util::rasterize_mesh(&vertices, indices) else {
+            return;
+        };
+        let Ok(image) = self.make_image(buf.width(), buf.height(), buf.raw_pixels(), buf.format())
+        else {
+            return;
+        };
+        let _ = self.with_save(|rc| {
+            rc.transform(to_device.inverse());
+            rc.draw_image(&image, rect, InterpolationMode::Bilinear);
+            Ok(())
+        });
+    }
+
     /// Clip to a [`Shape`].
     ///
     /// All subsequent drawing operations up to the next [`restore`]
@@ -154,6 +311,76 @@ where
     /// [`restore`]: RenderContext::restore
     fn clip(&mut self, shape: impl Shape);
 
+    /// Clip to a [`Shape`], using the [even-odd fill rule].
+    ///
+    /// This is like [`clip`], except that where the shape overlaps itself an
+    /// even number of times, such as a hole cut by an inner subpath, is
+    /// clipped out whichever way the subpaths wind.
+    ///
+    /// [`clip`]: RenderContext::clip
+    /// [even-odd fill rule]: https://en.wikipedia.org/wiki/Even–odd_rule
+    fn clip_even_odd(&mut self, shape: impl Shape);
+
+    /// Prepare a [`Shape`] for repeated drawing.
+    ///
+    /// Drawing a shape usually involves converting it into a path and then
+    /// building some backend-specific geometry from that path. For shapes that
+    /// are drawn many times, this work can be done once up front; the returned
+    /// value can then be passed to [`fill_cached`], [`stroke_cached`],
+    /// [`stroke_styled_cached`], and [`clip_cached`] as often as needed.
+    ///
+    /// The shape is interpreted in the coordinate space in effect when it is
+    /// drawn, not when it is prepared, so a cached shape can be reused under
+    /// different transforms.
+    ///
+    /// A cached shape is only valid for use with the context that created it,
+    /// or with other contexts sharing the same underlying device.
+    ///
+    /// [`fill_cached`]: RenderContext::fill_cached
+    /// [`stroke_cached`]: RenderContext::stroke_cached
+    /// [`stroke_styled_cached`]: RenderContext::stroke_styled_cached
+    /// [`clip_cached`]: RenderContext::clip_cached
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error>;
+
+    /// Fill a shape created by [`prepare_shape`], using the non-zero fill rule.
+    ///
+    /// [`prepare_shape`]: RenderContext::prepare_shape
+    fn fill_cached(&mut self, shape: &Self::CachedShape, brush: &impl IntoBrush<Self>) {
+        self.fill(shape, brush)
+    }
+
+    /// Stroke a shape created by [`prepare_shape`], using the default [`StrokeStyle`].
+    ///
+    /// [`prepare_shape`]: RenderContext::prepare_shape
+    fn stroke_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+    ) {
+        self.stroke(shape, brush, width)
+    }
+
+    /// Stroke a shape created by [`prepare_shape`], providing a custom [`StrokeStyle`].
+    ///
+    /// [`prepare_shape`]: RenderContext::prepare_shape
+    fn stroke_styled_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        self.stroke_styled(shape, brush, width, style)
+    }
+
+    /// Clip to a shape created by [`prepare_shape`].
+    ///
+    /// [`prepare_shape`]: RenderContext::prepare_shape
+    fn clip_cached(&mut self, shape: &Self::CachedShape) {
+        self.clip(shape)
+    }
+
     /// Returns a reference to a shared [`Text`] object.
     ///
     /// This provides access to the text API.
@@ -164,6 +391,12 @@ where
     /// The `pos` parameter specifies the upper-left corner of the layout object
     /// (even for right-to-left text). To draw on a baseline, you can use
     /// [`TextLayout::line_metric`] to get the baseline position of a specific line.
+    ///
+    /// The layout is drawn under the current transform, like any other
+    /// drawing, and every backend applies all of it: rotation, skew and
+    /// reflection change the glyphs themselves, not only where they are
+    /// placed. The layout's metrics are in its own untransformed coordinates,
+    /// so they stay the same whatever the transform.
     fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>);
 
     /// Save the context state.
@@ -194,12 +427,55 @@ where
     /// Equivalent to [`save`], calling `f`, then
     /// [`restore`]. See those methods for more details.
     ///
+    /// The state is restored even if `f` fails. If only one of `f` and
+    /// restoring fails, its error is returned; if both do, they are returned
+    /// together as [`Error::RestoreFailed`].
+    ///
     /// [`restore`]: RenderContext::restore
     /// [`save`]: RenderContext::save
     fn with_save(&mut self, f: impl FnOnce(&mut Self) -> Result<(), Error>) -> Result<(), Error> {
         self.save()?;
         // Always try to restore the stack, even if `f` errored.
-        f(self).and(self.restore())
+        let result = f(self);
+        Error::scoped(result, self.restore())
+    }
+
+    /// Do graphics operations clipped to `shape`, and then remove the clip.
+    ///
+    /// Equivalent to [`with_save`], with a call to [`clip`] before `f`.
+    /// Backends may override this with something cheaper than saving the
+    /// whole state.
+    ///
+    /// [`clip`]: RenderContext::clip
+    /// [`with_save`]: RenderContext::with_save
+    fn with_clip(
+        &mut self,
+        shape: impl Shape,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.with_save(|rc| {
+            rc.clip(shape);
+            f(rc)
+        })
+    }
+
+    /// Do graphics operations with `transform` applied, and then undo it.
+    ///
+    /// Equivalent to [`with_save`], with a call to [`transform`] before `f`.
+    /// Backends may override this with something cheaper than saving the
+    /// whole state.
+    ///
+    /// [`transform`]: RenderContext::transform
+    /// [`with_save`]: RenderContext::with_save
+    fn with_transform(
+        &mut self,
+        transform: Affine,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.with_save(|rc| {
+            rc.transform(transform);
+            f(rc)
+        })
     }
 
     /// Finish any pending operations.
@@ -250,7 +526,8 @@ where
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
-        self.make_image_with_stride(width, height, width * format.bytes_per_pixel(), buf, format)
+        let stride = width.saturating_mul(format.bytes_per_pixel());
+        self.make_image_with_stride(width, height, stride, buf, format)
     }
 
     /// Create a new [`Image`] from a pixel buffer with a specified row stride.
@@ -269,7 +546,14 @@ where
     ///   be at least `stride * height`.
     /// * `format` - The format of the pixel data.
     ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidImage`] if the stride is less than the size of
+    /// a row of pixels, the buffer is too small, or the size of the image
+    /// overflows; see [`validate_image_args`].
+    ///
     /// [`make_image`]: RenderContext::make_image
+    /// [`validate_image_args`]: crate::util::validate_image_args
     fn make_image_with_stride(
         &mut self,
         width: usize,
@@ -279,6 +563,86 @@ where
         format: ImageFormat,
     ) -> Result<Self::Image, Error>;
 
+    /// Create a new [`Image`] from a pixel buffer in the given [`ColorSpace`].
+    ///
+    /// This has the same semantics as [`make_image_with_stride`], but the
+    /// pixel data is interpreted in `color_space` rather than sRGB. Backends
+    /// that support color management tag the image with the color space; the
+    /// default implementation converts the pixels to sRGB.
+    ///
+    /// [`make_image_with_stride`]: RenderContext::make_image_with_stride
+    fn make_image_with_color_space(
+        &mut self,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buf: &[u8],
+        format: ImageFormat,
+        color_space: ColorSpace,
+    ) -> Result<Self::Image, Error> {
+        if color_space == ColorSpace::Srgb {
+            return self.make_image_with_stride(width, height, stride, buf, format);
+        }
+        let buf =
+            crate::util::image_buffer_to_srgb(buf, width, height, stride, format, color_space)?;
+        let stride = width * format.bytes_per_pixel();
+        self.make_image_with_stride(width, height, stride, &buf, format)
+    }
+
+    /// Create a new [`Image`] from an [`ImageBuf`].
+    ///
+    /// [`ImageBuf`] is `Send` and `Sync` on every platform, so images can be
+    /// decoded on a worker thread and handed to the render thread, which
+    /// uploads them with this method. Backends may share the buffer's pixel
+    /// data rather than copying it.
+    ///
+    /// The pixels are interpreted in the buffer's [`ImageBuf::color_space`],
+    /// as with [`make_image_with_color_space`].
+    ///
+    /// See [`Image`] for which backends' images can themselves be sent
+    /// between threads.
+    ///
+    /// [`make_image_with_color_space`]: RenderContext::make_image_with_color_space
+    fn upload_image(&mut self, buf: &ImageBuf) -> Result<Self::Image, Error> {
+        let stride = buf.width() * buf.format().bytes_per_pixel();
+        self.make_image_with_color_space(
+            buf.width(),
+            buf.height(),
+            stride,
+            buf.raw_pixels(),
+            buf.format(),
+            buf.color_space(),
+        )
+    }
+
+    /// Replace the pixels of an [`Image`] made by this context.
+    ///
+    /// This is for images that change on every frame, such as video or the
+    /// glyph grid of a terminal. `buf` holds the whole image, as tightly
+    /// packed rows in `format`, the size of the image. If `region` is given,
+    /// backends that support partial updates copy only the pixels inside it
+    /// (in image pixels, rounded out to whole pixels), and leave the rest of
+    /// the image as it was.
+    ///
+    /// The default implementation ignores `region` and makes a new image
+    /// from the whole of `buf`, so pixels outside `region` are replaced too.
+    /// Backends that can write into an existing image override it to honor
+    /// `region`, without allocating new backend resources; clones of the
+    /// image may then see the new pixels too. Cairo, Direct2D and web do so,
+    /// except for web images made by `make_image_async`.
+    fn update_image(
+        &mut self,
+        image: &mut Self::Image,
+        buf: &[u8],
+        format: ImageFormat,
+        region: Option<Rect>,
+    ) -> Result<(), Error> {
+        let _ = region;
+        let size = image.size();
+        *image = self.make_image(size.width as usize, size.height as usize, buf, format)?;
+        Ok(())
+    }
+
     /// Draw an [`Image`] into the provided [`Rect`].
     ///
     /// The image is scaled to fit the provided [`Rect`]; it will be squashed
@@ -302,6 +666,110 @@ where
         interp: InterpolationMode,
     );
 
+    /// Draw a specified area of an [`Image`], faded by an opacity.
+    ///
+    /// This is the same as [`draw_image_area`], except that the image is
+    /// drawn with its alpha multiplied by `opacity`, which is clamped to the
+    /// range `0.0..=1.0`.
+    ///
+    /// [`draw_image_area`]: RenderContext::draw_image_area
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    );
+
+    /// Draw an [`Image`] as a nine-slice, such as the background of a button.
+    ///
+    /// `src_insets` divide the image into a three by three grid, giving the
+    /// widths of its borders in pixels. The corners are drawn at the corners
+    /// of `dst_rect` without scaling, the edges are stretched along their
+    /// length, and the center is stretched to fill the rest. If `dst_rect` is
+    /// too small for the corners, they are scaled down.
+    ///
+    /// The default implementation draws the pieces with
+    /// [`draw_image_batch`]; see [`util::nine_slices`].
+    ///
+    /// [`draw_image_batch`]: RenderContext::draw_image_batch
+    /// [`util::nine_slices`]: crate::util::nine_slices
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        src_insets: impl Into<Insets>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let slices = crate::util::nine_slices(image.size(), src_insets.into(), dst_rect.into());
+        self.draw_image_batch(image, &slices, interp);
+    }
+
+    /// Draw many areas of the same [`Image`], such as glyphs from an atlas
+    /// or the frames of a sprite sheet.
+    ///
+    /// Each pair in `rects` is an area of the image, in pixels, and the
+    /// rectangle to draw it into, as with [`draw_image_area`]. They are drawn
+    /// in order.
+    ///
+    /// Backends may draw the whole batch at once, which can be much faster
+    /// than calling [`draw_image_area`] for each one; the default
+    /// implementation just does that.
+    ///
+    /// [`draw_image_area`]: RenderContext::draw_image_area
+    fn draw_image_batch(
+        &mut self,
+        image: &Self::Image,
+        rects: &[(Rect, Rect)],
+        interp: InterpolationMode,
+    ) {
+        for &(src_rect, dst_rect) in rects {
+            self.draw_image_area(image, src_rect, dst_rect, interp);
+        }
+    }
+
+    /// Fill a rectangle by tiling an [`Image`], such as a texture.
+    ///
+    /// The image is drawn at its size in pixels, with the top-left corner of
+    /// one copy at the top-left corner of `dst_rect`, and repeated according
+    /// to `tile_mode`. Copies are clipped to `dst_rect`.
+    ///
+    /// The default implementation draws each copy with
+    /// [`draw_image_area`]; see [`util::image_tiles`]. It doesn't know the
+    /// bounds of the target, so it draws nothing if `dst_rect` holds more
+    /// than [`util::MAX_IMAGE_TILES`] copies.
+    ///
+    /// [`draw_image_area`]: RenderContext::draw_image_area
+    /// [`util::image_tiles`]: crate::util::image_tiles
+    /// [`util::MAX_IMAGE_TILES`]: crate::util::MAX_IMAGE_TILES
+    fn draw_image_tiled(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        tile_mode: TileMode,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into();
+        let tiles = crate::util::image_tiles(image.size(), dst_rect, dst_rect, tile_mode);
+        if tile_mode == TileMode::Repeat {
+            // every copy is only translated, so they can be drawn as a batch
+            let rects: Vec<_> = tiles
+                .into_iter()
+                .map(|(src_rect, transform)| (src_rect, transform.transform_rect_bbox(src_rect)))
+                .collect();
+            self.draw_image_batch(image, &rects, interp);
+            return;
+        }
+        for (src_rect, transform) in tiles {
+            let _ = self.with_save(|rc| {
+                rc.transform(transform);
+                rc.draw_image_area(image, src_rect, src_rect, interp);
+                Ok(())
+            });
+        }
+    }
+
     /// Create an [`Image`] of the specified region of the context.
     ///
     /// The `src_rect` area of the current render context will be captured
@@ -316,8 +784,143 @@ where
     /// the blur.
     fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl IntoBrush<Self>);
 
+    /// Make the area of `shape` a link to `url`.
+    ///
+    /// This is for output formats that are documents rather than pixels,
+    /// such as SVG, where the area becomes an `<a>` element that can be
+    /// clicked. Raster backends have nowhere to keep a link, and the default
+    /// implementation does nothing.
+    ///
+    /// Nothing is drawn either way, so draw the text or shape of the link as
+    /// usual. The shape is in the current coordinate space, and is clipped
+    /// like anything else drawn.
+    fn annotate_link(&mut self, shape: impl Shape, url: &str) {
+        let _ = (shape, url);
+    }
+
+    /// Draw an SVG document, such as an icon, parsed with [`usvg`].
+    ///
+    /// The document is drawn at its own size, with its top left at the
+    /// origin of the current coordinate space; apply a transform first to
+    /// place or scale it. It is drawn with the usual piet calls, so this
+    /// works on every backend.
+    ///
+    /// usvg lays out text and turns it into paths, using the fonts in the
+    /// [`usvg::Options`] the document was parsed with. Some of SVG can't be
+    /// expressed in piet:
+    ///
+    /// - group opacity is applied to each child separately, which differs
+    ///   where the children overlap;
+    /// - masks, filters, patterns and blend modes are ignored;
+    /// - reflected and repeated gradients are padded;
+    /// - raster images are only drawn with the `image` feature, and with the
+    ///   feature of their format enabled.
+    ///
+    /// ```
+    /// use piet::{usvg, NullRenderContext, RenderContext};
+    ///
+    /// let icon = r#"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16">
+    ///     <circle cx="8" cy="8" r="6" fill="teal"/>
+    /// </svg>"#;
+    /// let tree = usvg::Tree::from_str(icon, &usvg::Options::default()).unwrap();
+    ///
+    /// let mut ctx = NullRenderContext::new();
+    /// ctx.draw_svg(&tree).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a gradient or image in the document can't be
+    /// created, or if the context's state is invalid.
+    #[cfg(feature = "svg")]
+    fn draw_svg(&mut self, svg: &usvg::Tree) -> Result<(), Error> {
+        crate::svg::draw(self, svg)
+    }
+
     /// Returns the transformations currently applied to the context.
     fn current_transform(&self) -> Affine;
+
+    /// The number of device pixels per unit of the context's coordinate space.
+    ///
+    /// This is the scale applied by the embedder, for instance to account for
+    /// a high-DPI display, before any transforms applied with [`transform`].
+    /// It is not included in [`current_transform`].
+    ///
+    /// Backends that are not told the scale by their embedder return `1.0`,
+    /// which is the default.
+    ///
+    /// [`transform`]: RenderContext::transform
+    /// [`current_transform`]: RenderContext::current_transform
+    fn scale_factor(&self) -> f64 {
+        1.0
+    }
+
+    /// Snap a [`Rect`] to device pixel boundaries.
+    ///
+    /// Each edge of the rect is moved to the nearest pixel boundary, taking
+    /// the current transform and the [`scale_factor`] into account, so that filling the returned rect
+    /// produces crisp edges. A crisp line one pixel wide can be drawn by
+    /// filling a snapped rect, rather than by stroking a line offset by half
+    /// a pixel.
+    ///
+    /// If the current transform rotates or skews, pixel boundaries are not
+    /// parallel to the axes of the rect, and it is returned unchanged.
+    ///
+    /// [`scale_factor`]: RenderContext::scale_factor
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Rect};
+    /// use piet::{NullRenderContext, RenderContext};
+    ///
+    /// let mut rc = NullRenderContext::new();
+    /// rc.transform(Affine::scale(2.0));
+    /// let snapped = rc.snap_rect(Rect::new(0.3, 0.3, 10.1, 10.6));
+    /// assert_eq!(snapped, Rect::new(0.5, 0.5, 10.0, 10.5));
+    /// ```
+    fn snap_rect(&self, rect: Rect) -> Rect {
+        let transform = Affine::scale(self.scale_factor()) * self.current_transform();
+        let [a, b, c, d, _, _] = transform.as_coeffs();
+        let axis_aligned = (b == 0.0 && c == 0.0) || (a == 0.0 && d == 0.0);
+        if !axis_aligned || transform.determinant() == 0.0 {
+            return rect;
+        }
+        let device_rect = transform.transform_rect_bbox(rect).round();
+        transform.inverse().transform_rect_bbox(device_rect)
+    }
+
+    /// Snap a [`Point`] to the nearest device pixel corner.
+    ///
+    /// This takes the current transform and the [`scale_factor`] into
+    /// account; the returned point is in the current coordinate space.
+    ///
+    /// [`scale_factor`]: RenderContext::scale_factor
+    fn snap_point(&self, point: Point) -> Point {
+        let transform = Affine::scale(self.scale_factor()) * self.current_transform();
+        if transform.determinant() == 0.0 {
+            return point;
+        }
+        transform.inverse() * (transform * point).round()
+    }
+
+    /// Returns the platform object this context draws with, if there is one.
+    ///
+    /// This is an escape hatch for code that is otherwise portable but wants
+    /// to use a platform-specific feature when it is available. The handle
+    /// can be downcast to the concrete type for each backend:
+    ///
+    /// - `piet-cairo`: `cairo::Context`
+    /// - `piet-coregraphics`: `core_graphics::context::CGContextRef`
+    /// - `piet-direct2d`: `piet_direct2d::D2DDeviceContext`
+    /// - `piet-web`: `web_sys::CanvasRenderingContext2d`
+    ///
+    /// Backends without such an object return `None`, which is the default.
+    ///
+    /// Drawing through the handle bypasses piet's own state tracking, so any
+    /// changes to state such as the transform or clip should be undone
+    /// before drawing through the context again.
+    fn backend_handle(&self) -> Option<&dyn Any> {
+        None
+    }
 }
 
 /// A trait for various types that can be used as brushes.
@@ -427,3 +1030,9 @@ impl From<FixedRadialGradient> for PaintBrush {
         PaintBrush::Fixed(src.into())
     }
 }
+
+impl From<FixedEllipticalGradient> for PaintBrush {
+    fn from(src: FixedEllipticalGradient) -> PaintBrush {
+        PaintBrush::Fixed(src.into())
+    }
+}
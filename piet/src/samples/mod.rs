@@ -6,10 +6,13 @@
 #![allow(clippy::unnecessary_wraps)]
 
 use std::collections::BTreeMap;
+use std::fmt::Write as _;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write as _};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
 
 use crate::kurbo::Size;
 use crate::{Error, RenderContext};
@@ -32,6 +35,26 @@ mod picture_13;
 mod picture_14;
 mod picture_15;
 mod picture_16;
+mod picture_17;
+mod picture_18;
+mod picture_19;
+mod picture_20;
+mod picture_21;
+mod picture_22;
+mod picture_23;
+mod picture_24;
+mod picture_25;
+mod picture_26;
+mod picture_27;
+mod picture_28;
+mod picture_29;
+mod picture_30;
+mod picture_31;
+mod picture_32;
+mod picture_33;
+mod picture_34;
+mod picture_35;
+mod picture_36;
 
 type BoxErr = Box<dyn std::error::Error>;
 
@@ -40,14 +63,29 @@ type BoxErr = Box<dyn std::error::Error>;
 pub const DEFAULT_SCALE: f64 = 2.0;
 
 /// The total number of samples in this module.
-pub const SAMPLE_COUNT: usize = 17;
+pub const SAMPLE_COUNT: usize = 37;
 
 /// file we save an os fingerprint to
 pub const GENERATED_BY: &str = "GENERATED_BY";
 
+/// The sample size passed with `--size`, which replaces the size of every
+/// sample returned by [`get`].
+static SIZE_OVERRIDE: OnceLock<Size> = OnceLock::new();
+
+/// Return the tolerance used when comparing a sample perceptually.
+fn tolerance(number: usize) -> Tolerance {
+    match number {
+        0 | 5 | 7..=14 | 19..=21 | 31..=34 => Tolerance::TEXT,
+        _ => Tolerance::DEFAULT,
+    }
+}
+
 /// Return a specific sample for drawing.
+///
+/// If [`samples_main`] was passed `--size`, the sample has that size instead
+/// of its own.
 pub fn get<R: RenderContext>(number: usize) -> Result<SamplePicture<R>, BoxErr> {
-    Ok(match number {
+    let mut picture = match number {
         0 => SamplePicture::new(picture_0::SIZE, picture_0::draw),
         1 => SamplePicture::new(picture_1::SIZE, picture_1::draw),
         2 => SamplePicture::new(picture_2::SIZE, picture_2::draw),
@@ -65,8 +103,32 @@ pub fn get<R: RenderContext>(number: usize) -> Result<SamplePicture<R>, BoxErr>
         14 => SamplePicture::new(picture_14::SIZE, picture_14::draw),
         15 => SamplePicture::new(picture_15::SIZE, picture_15::draw),
         16 => SamplePicture::new(picture_16::SIZE, picture_16::draw),
+        17 => SamplePicture::new(picture_17::SIZE, picture_17::draw),
+        18 => SamplePicture::new(picture_18::SIZE, picture_18::draw),
+        19 => SamplePicture::new(picture_19::SIZE, picture_19::draw),
+        20 => SamplePicture::new(picture_20::SIZE, picture_20::draw),
+        21 => SamplePicture::new(picture_21::SIZE, picture_21::draw),
+        22 => SamplePicture::new(picture_22::SIZE, picture_22::draw),
+        23 => SamplePicture::new(picture_23::SIZE, picture_23::draw),
+        24 => SamplePicture::new(picture_24::SIZE, picture_24::draw),
+        25 => SamplePicture::new(picture_25::SIZE, picture_25::draw),
+        26 => SamplePicture::new(picture_26::SIZE, picture_26::draw),
+        27 => SamplePicture::new(picture_27::SIZE, picture_27::draw),
+        28 => SamplePicture::new(picture_28::SIZE, picture_28::draw),
+        29 => SamplePicture::new(picture_29::SIZE, picture_29::draw),
+        30 => SamplePicture::new(picture_30::SIZE, picture_30::draw),
+        31 => SamplePicture::new(picture_31::SIZE, picture_31::draw),
+        32 => SamplePicture::new(picture_32::SIZE, picture_32::draw),
+        33 => SamplePicture::new(picture_33::SIZE, picture_33::draw),
+        34 => SamplePicture::new(picture_34::SIZE, picture_34::draw),
+        35 => SamplePicture::new(picture_35::SIZE, picture_35::draw),
+        36 => SamplePicture::new(picture_36::SIZE, picture_36::draw),
         _ => return Err(format!("No sample #{number} exists").into()),
-    })
+    };
+    if let Some(size) = SIZE_OVERRIDE.get() {
+        picture.size = *size;
+    }
+    Ok(picture)
 }
 
 /// A pointer to a text drawing and associated info.
@@ -83,6 +145,57 @@ struct Args {
     number: Option<usize>,
     compare_dir: Option<PathBuf>,
     scale: f64,
+    jobs: usize,
+    stdout: bool,
+    size: Option<Size>,
+    report: ReportFormat,
+    compare_mode: CompareMode,
+    channel_tolerance: Option<u8>,
+}
+
+/// How pictures are compared with `--compare`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareMode {
+    /// Any difference in pixel data is a failure.
+    Exact,
+    /// Small differences, such as those caused by platform-specific
+    /// antialiasing, are tolerated; see [`Tolerance`].
+    Perceptual,
+}
+
+/// The amount of difference tolerated when comparing a picture perceptually.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tolerance {
+    /// The largest per-channel difference that is ignored entirely.
+    channel: u8,
+    /// The percentage of pixels that may differ by more than `channel`,
+    /// after discounting pixels that match a neighbouring pixel in the other
+    /// image.
+    max_differing_pct: f32,
+}
+
+impl Tolerance {
+    /// The tolerance used for pictures that only contain geometry.
+    const DEFAULT: Tolerance = Tolerance {
+        channel: 2,
+        max_differing_pct: 0.0,
+    };
+
+    /// The tolerance used for pictures that draw text, which is much more
+    /// sensitive to the platform's font rendering.
+    const TEXT: Tolerance = Tolerance {
+        channel: 8,
+        max_differing_pct: 0.5,
+    };
+}
+
+/// The format used to print the results of `--compare`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReportFormat {
+    /// Human readable text, one line per picture.
+    Text,
+    /// A single JSON object, suitable for consumption by CI tooling.
+    Json,
 }
 
 /// A shared `main` fn for different backends.
@@ -95,8 +208,11 @@ struct Args {
 /// - The `env_info` argument is optional additional information about the
 ///   testing environment, such as the versions of various dependencies; this
 ///   will be appended to the GENERATED_BY file.
+///
+/// When generating all samples, `f` is called concurrently from multiple
+/// threads; the number of threads can be controlled with `--jobs`.
 pub fn samples_main(
-    f: impl Fn(usize, f64, &Path) -> Result<(), BoxErr>,
+    f: impl Fn(usize, f64, &Path) -> Result<(), BoxErr> + Sync,
     prefix: &str,
     env_info: Option<&str>,
 ) -> ! {
@@ -109,6 +225,15 @@ pub fn samples_main(
             std::process::exit(1);
         }
 
+        if let Some(size) = args.size {
+            let _ = SIZE_OVERRIDE.set(size);
+        }
+
+        if args.stdout {
+            let number = args.number.ok_or("--stdout requires a sample number")?;
+            return write_to_stdout(|path| f(number, args.scale, path), prefix, number);
+        }
+
         if !args.out_dir.exists() {
             std::fs::create_dir_all(&args.out_dir)?;
         }
@@ -120,26 +245,33 @@ pub fn samples_main(
 
         if args.all {
             write_os_info(&args.out_dir, env_info)?;
-            run_all(call_f)?;
+            run_all(call_f, args.jobs)?;
         } else if let Some(number) = args.number {
             call_f(number)?;
         }
 
         if let Some(compare_dir) = args.compare_dir.as_ref() {
-            let results = compare_snapshots(compare_dir, &args.out_dir, prefix, args.scale)?;
-            if args.all {
-                let info_one = read_os_info(compare_dir)?;
-                let info_two = read_os_info(&args.out_dir)?;
-                println!("Compared {} snapshots", results.len());
-                print!("base:\n{info_one}");
-                println!("rev:\n{info_two}");
-            }
-
-            for (number, result) in results.iter() {
-                print!("Image {number:02}: ");
-                match result {
-                    Some(failure) => println!("{failure}"),
-                    None => println!("Ok"),
+            let results = compare_snapshots(compare_dir, &args.out_dir, prefix, &args)?;
+            match args.report {
+                ReportFormat::Text => {
+                    if args.all {
+                        let info_one = read_os_info(compare_dir)?;
+                        let info_two = read_os_info(&args.out_dir)?;
+                        println!("Compared {} snapshots", results.len());
+                        print!("base:\n{info_one}");
+                        println!("rev:\n{info_two}");
+                    }
+
+                    for (number, result) in results.iter() {
+                        print!("Image {number:02}: ");
+                        match result {
+                            Some(failure) => println!("{failure}"),
+                            None => println!("Ok"),
+                        }
+                    }
+                }
+                ReportFormat::Json => {
+                    println!("{}", make_json_report(prefix, args.scale, &results));
                 }
             }
 
@@ -188,17 +320,36 @@ impl Args {
         let mut args = pico_args::Arguments::from_env();
         let out_dir: Option<PathBuf> = args.opt_value_from_str("--out")?;
         let scale = args.opt_value_from_fn("--scale", f64::from_str)?;
+        let jobs = args.opt_value_from_fn("--jobs", usize::from_str)?;
+        let report = args.opt_value_from_fn("--report", ReportFormat::from_str)?;
+        let compare_mode = args.opt_value_from_fn("--compare-mode", CompareMode::from_str)?;
+        let size = args.opt_value_from_fn("--size", parse_size)?;
+        let compare_dir = args.opt_value_from_str("--compare")?;
+        let channel_tolerance = args.opt_value_from_str("--tolerance")?;
+        let help = args.contains("--help");
+        let all = args.contains("--all");
+        let stdout = args.contains("--stdout");
+        // the free argument has to be read last, after every flag is removed
+        let number = args.opt_free_from_str()?;
 
         let args = Args {
-            help: args.contains("--help"),
-            all: args.contains("--all"),
+            help,
+            all,
             out_dir: out_dir.unwrap_or_else(|| PathBuf::from(".")),
-            compare_dir: args.opt_value_from_str("--compare")?,
-            number: args.opt_free_from_str()?,
+            compare_dir,
+            number,
             scale: scale.unwrap_or(DEFAULT_SCALE),
+            jobs: jobs.unwrap_or_else(default_jobs).max(1),
+            stdout,
+            size,
+            report: report.unwrap_or(ReportFormat::Text),
+            compare_mode: compare_mode.unwrap_or(CompareMode::Exact),
+            channel_tolerance,
         };
 
-        if !(args.help || args.all || args.number.is_some() || args.compare_dir.is_some()) {
+        if !(args.help || args.all || args.number.is_some() || args.compare_dir.is_some())
+            || (args.stdout && (args.all || args.compare_dir.is_some()))
+        {
             Err(Box::new(Error::InvalidSampleArgs))
         } else {
             Ok(args)
@@ -206,25 +357,107 @@ impl Args {
     }
 }
 
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!(
+                "unknown report format '{s}', expected 'text' or 'json'"
+            )),
+        }
+    }
+}
+
+impl FromStr for CompareMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(CompareMode::Exact),
+            "perceptual" => Ok(CompareMode::Perceptual),
+            _ => Err(format!(
+                "unknown compare mode '{s}', expected 'exact' or 'perceptual'"
+            )),
+        }
+    }
+}
+
+/// Parse a size given as `<width>x<height>`.
+fn parse_size(s: &str) -> Result<Size, String> {
+    let err = || format!("invalid size '{s}', expected <width>x<height>");
+    let (width, height) = s.split_once('x').ok_or_else(err)?;
+    let width: f64 = width.parse().map_err(|_| err())?;
+    let height: f64 = height.parse().map_err(|_| err())?;
+    if width > 0.0 && height > 0.0 {
+        Ok(Size::new(width, height))
+    } else {
+        Err(err())
+    }
+}
+
+/// Generate a sample into a temporary file and copy it to stdout.
+///
+/// The backends can only save to a path, so the file is the simplest way to
+/// get at the encoded PNG.
+fn write_to_stdout(
+    f: impl FnOnce(&Path) -> Result<(), BoxErr>,
+    prefix: &str,
+    number: usize,
+) -> Result<(), BoxErr> {
+    let filename = format!("{prefix}-{number:0>2}-{}.png", std::process::id());
+    let path = std::env::temp_dir().join(filename);
+    let result = f(&path).and_then(|()| {
+        let data = std::fs::read(&path)?;
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&data)?;
+        stdout.flush().map_err(Into::into)
+    });
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// The number of threads to use when `--jobs` is not passed.
+fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, Into::into)
+}
+
 /// Run all samples, collecting and printing any errors encountered, without
 /// aborting.
 ///
+/// Samples are distributed across up to `jobs` threads.
+///
 /// If any errors are encountered, the first is returned on completion.
-fn run_all(f: impl Fn(usize) -> Result<(), BoxErr>) -> Result<(), BoxErr> {
-    let mut errs = Vec::new();
-    for sample in 0..SAMPLE_COUNT {
-        if let Err(e) = f(sample) {
-            errs.push((sample, e));
+fn run_all(f: impl Fn(usize) -> Result<(), BoxErr> + Sync, jobs: usize) -> Result<(), BoxErr> {
+    let next_sample = AtomicUsize::new(0);
+    // `BoxErr` isn't `Send`, so we only keep the message around.
+    let errs = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, SAMPLE_COUNT) {
+            scope.spawn(|| loop {
+                let sample = next_sample.fetch_add(1, Ordering::Relaxed);
+                if sample >= SAMPLE_COUNT {
+                    break;
+                }
+                if let Err(e) = f(sample) {
+                    errs.lock().unwrap().push((sample, e.to_string()));
+                }
+            });
         }
-    }
+    });
 
+    let mut errs = errs.into_inner().unwrap();
     if errs.is_empty() {
         Ok(())
     } else {
+        errs.sort_by_key(|(sample, _)| *sample);
         for (sample, err) in &errs {
             eprintln!("error in sample {sample}: '{err}'");
         }
-        Err(errs.remove(0).1)
+        Err(errs.remove(0).1.into())
     }
 }
 
@@ -243,8 +476,9 @@ fn compare_snapshots(
     base: &Path,
     revised: &Path,
     prefix: &str,
-    scale: f64,
+    args: &Args,
 ) -> Result<BTreeMap<usize, Option<FailureReason>>, BoxErr> {
+    let scale = args.scale;
     let mut failures = BTreeMap::new();
     let base_paths = get_sample_files(base, scale)?;
     let rev_paths = get_sample_files(revised, scale)?;
@@ -258,7 +492,17 @@ fn compare_snapshots(
             }
         };
 
-        let result = compare_files(*number, base_path, rev_path, prefix, scale)?;
+        let tolerance = match args.compare_mode {
+            CompareMode::Exact => None,
+            CompareMode::Perceptual => {
+                let mut tolerance = tolerance(*number);
+                if let Some(channel) = args.channel_tolerance {
+                    tolerance.channel = channel;
+                }
+                Some(tolerance)
+            }
+        };
+        let result = compare_files(*number, base_path, rev_path, prefix, scale, tolerance)?;
         failures.insert(*number, result);
     }
 
@@ -275,6 +519,7 @@ fn compare_files(
     p2: &Path,
     prefix: &str,
     scale: f64,
+    tolerance: Option<Tolerance>,
 ) -> Result<Option<FailureReason>, BoxErr> {
     let (one_info, one) = get_png_data(p1)?;
     let (two_info, two) = get_png_data(p2)?;
@@ -291,7 +536,7 @@ fn compare_files(
         "color types should always match"
     );
     let err_write_path = p2.with_file_name(get_filename(prefix, scale, number, true));
-    compare_pngs(one_info, &one, &two, err_write_path)
+    compare_pngs(one_info, &one, &two, err_write_path, tolerance)
 }
 
 fn get_png_data(path: &Path) -> Result<(png::OutputInfo, Vec<u8>), BoxErr> {
@@ -307,14 +552,16 @@ fn get_png_data(path: &Path) -> Result<(png::OutputInfo, Vec<u8>), BoxErr> {
 /// Compare two pngs; in the case of difference, write a visualization of that difference
 /// to `write_path`.
 ///
+/// If a `tolerance` is provided, differences within that tolerance are ignored.
+///
 /// Returns `Err` if there is an intermediate error; returns `Ok(None)` if the pngs
-/// are identical, and `Ok(Some(PathBuf))` if they are different, returning the path
-/// we were given.
+/// match, and `Ok(Some(FailureReason))` if they are different.
 fn compare_pngs(
     info: png::OutputInfo,
     one: &[u8],
     two: &[u8],
     write_path: PathBuf,
+    tolerance: Option<Tolerance>,
 ) -> Result<Option<FailureReason>, BoxErr> {
     if one == two {
         return Ok(None);
@@ -327,6 +574,17 @@ fn compare_pngs(
         "png buffer length should be divisible by number of samples"
     );
 
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let channel_tolerance = tolerance.map_or(0, |t| t.channel);
+    let differing = count_differing_pixels(width, height, samples, one, two, channel_tolerance);
+    let differing_pct = differing as f32 / (width * height) as f32 * 100.;
+    if let Some(tolerance) = tolerance {
+        if differing_pct <= tolerance.max_differing_pct {
+            return Ok(None);
+        }
+    }
+
     let file = File::create(&write_path)?;
     let mut w = BufWriter::new(file);
 
@@ -361,10 +619,59 @@ fn compare_pngs(
     writer.write_image_data(&buf)?;
     Ok(Some(FailureReason::DifferentData {
         avg_diff_pct: avg_perc,
+        differing_pixels_pct: differing_pct,
         diff_path: write_path,
     }))
 }
 
+/// Count the pixels that differ by more than `channel_tolerance` in any channel.
+///
+/// When a tolerance is in use, a pixel is not counted if each image has a
+/// matching pixel in the other's 3x3 neighbourhood; this forgives the one
+/// pixel shifts that are common with antialiased text.
+fn count_differing_pixels(
+    width: usize,
+    height: usize,
+    samples: usize,
+    one: &[u8],
+    two: &[u8],
+    channel_tolerance: u8,
+) -> usize {
+    let pixel_range = |x: usize, y: usize| {
+        let start = (y * width + x) * samples;
+        start..start + samples
+    };
+    let close = |a: &[u8], b: &[u8]| {
+        a.iter()
+            .zip(b)
+            .all(|(a, b)| a.abs_diff(*b) <= channel_tolerance)
+    };
+    let has_close_neighbour = |p: &[u8], other: &[u8], x: usize, y: usize| {
+        (y.saturating_sub(1)..=(y + 1).min(height - 1)).any(|ny| {
+            (x.saturating_sub(1)..=(x + 1).min(width - 1))
+                .any(|nx| close(p, &other[pixel_range(nx, ny)]))
+        })
+    };
+
+    let mut count = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let p1 = &one[pixel_range(x, y)];
+            let p2 = &two[pixel_range(x, y)];
+            if close(p1, p2) {
+                continue;
+            }
+            let shifted = channel_tolerance > 0
+                && has_close_neighbour(p1, two, x, y)
+                && has_close_neighbour(p2, one, x, y);
+            if !shifted {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
 fn get_sample_files(in_dir: &Path, scale: f64) -> Result<BTreeMap<usize, PathBuf>, BoxErr> {
     let mut out = BTreeMap::new();
     let stem_suffix = format!("-{scale:.2}");
@@ -421,6 +728,7 @@ enum FailureReason {
     },
     DifferentData {
         avg_diff_pct: f32,
+        differing_pixels_pct: f32,
         diff_path: PathBuf,
     },
 }
@@ -432,11 +740,13 @@ impl std::fmt::Display for FailureReason {
             FailureReason::MissingRevision => write!(f, "Revised file is missing"),
             FailureReason::DifferentData {
                 avg_diff_pct,
+                differing_pixels_pct,
                 diff_path,
             } => write!(
                 f,
-                "Data differs {:>5.2}%: {}",
+                "Data differs {:>5.2}% ({:>5.2}% of pixels): {}",
                 avg_diff_pct,
+                differing_pixels_pct,
                 diff_path.to_string_lossy(),
             ),
             FailureReason::WrongSize { base, rev } => {
@@ -446,11 +756,84 @@ impl std::fmt::Display for FailureReason {
     }
 }
 
+/// Build a JSON document describing the result of comparing each picture.
+///
+/// We don't want to pull in a JSON crate just for this, so it is written by hand.
+fn make_json_report(
+    prefix: &str,
+    scale: f64,
+    results: &BTreeMap<usize, Option<FailureReason>>,
+) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"prefix\":{},\"scale\":{scale},\"results\":[",
+        json_string(prefix)
+    );
+    for (i, (number, result)) in results.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let file_name = get_filename(prefix, scale, *number, false);
+        let _ = write!(
+            out,
+            "{{\"number\":{number},\"file\":{},",
+            json_string(&file_name)
+        );
+        let _ = match result {
+            None => write!(out, "\"status\":\"ok\",\"avg_diff_pct\":0"),
+            Some(FailureReason::MissingBase) => write!(out, "\"status\":\"missing_base\""),
+            Some(FailureReason::MissingRevision) => {
+                write!(out, "\"status\":\"missing_revision\"")
+            }
+            Some(FailureReason::WrongSize { base, rev }) => write!(
+                out,
+                "\"status\":\"wrong_size\",\"base_size\":[{},{}],\"rev_size\":[{},{}]",
+                base.width, base.height, rev.width, rev.height
+            ),
+            Some(FailureReason::DifferentData {
+                avg_diff_pct,
+                differing_pixels_pct,
+                diff_path,
+            }) => write!(
+                out,
+                "\"status\":\"different_data\",\"avg_diff_pct\":{avg_diff_pct},\"differing_pixels_pct\":{differing_pixels_pct},\"diff_path\":{}",
+                json_string(&diff_path.to_string_lossy())
+            ),
+        };
+        out.push('}');
+    }
+    out.push_str("]}");
+    out
+}
+
+/// Quote and escape a string for inclusion in a JSON document.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 fn print_help_text() {
     eprintln!(
         "Options:
 
-$ ./test_picture {{<number> | --all}} [--out=<dir>] [--compare=<dir>] [--help]
+$ ./test_picture {{<number> | --all}} [--out=<dir>] [--compare=<dir>] [--compare-mode=<mode>] [--report=<fmt>] [--help]
+$ ./test_picture <number> --stdout [--scale=<f64>] [--size=<w>x<h>]
 
 Required Args
     --all | <number> If 'all', generate all the example pictures. If a number,
@@ -464,8 +847,21 @@ Optional Args
                      differ, then print an explanation and exit with a non-zero
                      status.
     --scale=<f64>    Specify the pixel scaling multiplier. Defaults to {:.2}.
+    --jobs=<n>       The number of threads used to generate samples with --all.
+                     Defaults to the available parallelism.
+    --compare-mode=<mode>
+                     Either 'exact' or 'perceptual'. In perceptual mode small
+                     differences, such as in text antialiasing, are tolerated
+                     up to a per-picture threshold. Defaults to 'exact'.
+    --tolerance=<u8> Override the per-channel tolerance used in perceptual mode.
+    --report=<fmt>   The format of the --compare output, either 'text' or 'json'.
+                     Defaults to 'text'.
+    --size=<w>x<h>   Draw samples at this size, in display points, instead of
+                     their own size.
 
 Flags
+    --stdout         Write the PNG for the given sample to stdout instead of
+                     saving it to a file.
     --help           Print this help message and exit.
     ",
         SAMPLE_COUNT - 1,
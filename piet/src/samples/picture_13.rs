@@ -16,11 +16,12 @@ static TEXT: &str = r#"Philosophers often behave like little children who scribb
 pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
     rc.clear(None, Color::WHITE);
     let text = rc.text();
-    let _ = text.load_font(include_bytes!(
-        "../../snapshots/resources/Anaheim-Regular.ttf"
-    )); // SEEING AN ERROR? run `git submodule update --init`
+    // SEEING AN ERROR? run `git submodule update --init`
     let font = text
-        .load_font(include_bytes!("../../snapshots/resources/Anaheim-Bold.ttf"))
+        .load_font_collection(&[
+            include_bytes!("../../snapshots/resources/Anaheim-Regular.ttf"),
+            include_bytes!("../../snapshots/resources/Anaheim-Bold.ttf"),
+        ])
         .unwrap_or(FontFamily::SYSTEM_UI);
 
     let layout = text
@@ -0,0 +1,71 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Pixel snapping.
+//!
+//! Each row draws the same rects and lines under a transform with a
+//! fractional offset and scale; the left column is drawn as is, and the
+//! right column is snapped to pixel boundaries first.
+
+use crate::kurbo::{Affine, Circle, Rect, Size, Vec2};
+use crate::{Color, Error, RenderContext};
+
+pub const SIZE: Size = Size::new(200., 200.);
+
+const FILL: Color = Color::rgb8(0x20, 0x60, 0xc0);
+const LINE: Color = Color::BLACK;
+const MARKER: Color = Color::rgb8(0xc0, 0x20, 0x20);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let transforms = [
+        Affine::translate((0.3, 0.3)),
+        Affine::translate((0.5, 60.5)) * Affine::scale(1.3),
+        Affine::translate((0.25, 130.7)) * Affine::scale_non_uniform(0.7, 1.15),
+    ];
+
+    for transform in transforms {
+        for (column, snap) in [false, true].into_iter().enumerate() {
+            rc.with_save(|rc| {
+                rc.transform(Affine::translate((5.0 + column as f64 * 100.0, 5.0)) * transform);
+                draw_cell(rc, snap);
+                Ok(())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn draw_cell<R: RenderContext>(rc: &mut R, snap: bool) {
+    let snap_rect = |rc: &R, rect: Rect| if snap { rc.snap_rect(rect) } else { rect };
+
+    rc.fill(snap_rect(rc, Rect::new(0.0, 0.0, 30.3, 20.6)), &FILL);
+    rc.fill(snap_rect(rc, Rect::new(35.4, 2.2, 60.9, 18.1)), &FILL);
+
+    // Lines one unit wide, drawn as thin rects.
+    for i in 0..5 {
+        let x = 65.0 + i as f64 * 4.3;
+        rc.fill(snap_rect(rc, Rect::new(x, 0.0, x + 1.0, 20.0)), &LINE);
+    }
+    for i in 0..4 {
+        let y = 25.0 + i as f64 * 4.6;
+        rc.fill(snap_rect(rc, Rect::new(0.0, y, 85.0, y + 1.0)), &LINE);
+    }
+
+    // Points snapped to pixel corners.
+    for i in 0..6 {
+        let mut center = (5.0 + i as f64 * 13.7, 45.4).into();
+        if snap {
+            center = rc.snap_point(center);
+        }
+        rc.fill(Circle::new(center, 2.0), &MARKER);
+        rc.fill(
+            snap_rect(
+                rc,
+                Rect::from_center_size(center + Vec2::new(0.0, 1.0), (1.0, 1.0)),
+            ),
+            &LINE,
+        );
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Gradient strokes.
+//!
+//! Dashed and hairline strokes with gradient brushes, including a long
+//! spiral made of many segments. Backends that stroke a gradient by first
+//! converting the path to its outline tend to lose the dashes, or show seams
+//! where the segments overlap.
+
+use crate::kurbo::{Affine, BezPath, Line, Point, Rect, Size, Vec2};
+use crate::{
+    Color, Error, FixedLinearGradient, GradientStops, LineCap, LinearGradient, RenderContext,
+    StrokeStyle, UnitPoint,
+};
+
+pub const SIZE: Size = Size::new(200., 200.);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let stops = (
+        Color::rgb8(0xd0, 0x20, 0x40),
+        Color::rgb8(0xf0, 0xa0, 0x20),
+        Color::rgb8(0x20, 0x60, 0xc0),
+    );
+    let horizontal = LinearGradient::new(UnitPoint::LEFT, UnitPoint::RIGHT, stops);
+
+    let dashed = StrokeStyle::new()
+        .dash_pattern(&[8.0, 4.0, 2.0, 4.0])
+        .line_cap(LineCap::Round);
+    for i in 0..3 {
+        let y = 15.0 + i as f64 * 12.0;
+        let style = dashed.clone().dash_offset(i as f64 * 3.0);
+        rc.stroke_styled(
+            Line::new((10.0, y), (190.0, y)),
+            &horizontal,
+            2.0 + i as f64 * 2.0,
+            &style,
+        );
+    }
+
+    // A fixed gradient, so that every segment of the spiral samples the
+    // same gradient rather than one fitted to its own bounds.
+    let spiral_gradient = rc.gradient(FixedLinearGradient {
+        start: Point::new(40.0, 60.0),
+        end: Point::new(160.0, 180.0),
+        stops: stops.to_vec(),
+    })?;
+    let mut spiral = BezPath::new();
+    let center = Point::new(100.0, 120.0);
+    for i in 0..=600 {
+        let angle = i as f64 * 0.05;
+        let radius = 4.0 + i as f64 * 0.09;
+        let pt = center + radius * Vec2::from_angle(angle);
+        if i == 0 {
+            spiral.move_to(pt);
+        } else {
+            spiral.line_to(pt);
+        }
+    }
+    rc.stroke_styled(
+        &spiral,
+        &spiral_gradient,
+        3.0,
+        &StrokeStyle::new().dash_pattern(&[12.0, 3.0]),
+    );
+
+    // Hairlines under a scale; they should stay one device pixel wide.
+    rc.with_save(|rc| {
+        rc.transform(Affine::translate((10.0, 175.0)) * Affine::scale_non_uniform(3.0, 1.5));
+        for i in 0..3 {
+            let y = i as f64 * 5.0;
+            rc.stroke_hairline(Rect::new(0.0, y, 60.0, y + 3.0), &horizontal);
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,53 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Underline styles, colors and thicknesses.
+
+use crate::kurbo::Size;
+use crate::{
+    Color, Error, FontFamily, RenderContext, Text, TextAttribute, TextLayout, TextLayoutBuilder,
+    UnderlineStyle,
+};
+
+pub const SIZE: Size = Size::new(240., 200.);
+
+static TEXT: &str = "let x = undefined_name;";
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+    let red = Color::rgb8(0xdd, 0x22, 0x22);
+    let blue = Color::rgb8(0x22, 0x55, 0xcc);
+
+    let styles = [
+        (UnderlineStyle::Solid, None, None),
+        (UnderlineStyle::Solid, Some(blue), None),
+        (UnderlineStyle::Dotted, None, None),
+        (UnderlineStyle::Dashed, Some(blue), None),
+        (UnderlineStyle::Wavy, Some(red), None),
+        (UnderlineStyle::Solid, Some(red), Some(3.0)),
+        (UnderlineStyle::Wavy, None, Some(0.5)),
+    ];
+
+    let mut y = 10.0;
+    for (style, color, thickness) in styles {
+        let mut builder = rc
+            .text()
+            .new_text_layout(TEXT)
+            .font(FontFamily::MONOSPACE, 14.0)
+            .default_attribute(style);
+        if let Some(color) = color {
+            builder = builder.default_attribute(TextAttribute::UnderlineColor(color));
+        }
+        if let Some(thickness) = thickness {
+            builder = builder.default_attribute(TextAttribute::UnderlineThickness(thickness));
+        }
+        // only underline the identifier, as an editor marks an error
+        let layout = builder
+            .range_attribute(8..22, TextAttribute::Underline(true))
+            .build()?;
+        rc.draw_text(&layout, (10.0, y));
+        y += layout.size().height + 8.0;
+    }
+
+    Ok(())
+}
@@ -4,6 +4,7 @@
 //! A bunch of image test cases.
 
 use crate::kurbo::{Rect, Size};
+use crate::util::premul;
 use crate::{Color, Error, ImageFormat, InterpolationMode, RenderContext};
 
 pub const SIZE: Size = Size::new(200., 100.);
@@ -55,10 +56,6 @@ fn make_image_data(width: usize, height: usize, format: ImageFormat) -> Vec<u8>
                     result[ix + 3] = a;
                 }
                 ImageFormat::RgbaPremul => {
-                    fn premul(x: u8, a: u8) -> u8 {
-                        let y = (x as u16) * (a as u16);
-                        ((y + (y >> 8) + 0x80) >> 8) as u8
-                    }
                     result[ix + 0] = premul(r, a);
                     result[ix + 1] = premul(g, a);
                     result[ix + 2] = premul(b, a);
@@ -0,0 +1,44 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Superscripts and subscripts with baseline offsets.
+//!
+//! Direct2D and the web backend don't support baseline offsets, and draw
+//! the scripts on the baseline.
+
+use crate::kurbo::Size;
+use crate::{Color, Error, RenderContext, Text, TextAttribute, TextLayout, TextLayoutBuilder};
+
+pub const SIZE: Size = Size::new(200., 120.);
+
+const FONT_SIZE: f64 = 18.0;
+const SCRIPT_SIZE: f64 = 12.0;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    // (text, [(index of a script character, offset)])
+    let labels: [(&str, &[(usize, f64)]); 3] = [
+        ("H2O + CO2", &[(1, -4.0), (8, -4.0)]),
+        ("E = mc2", &[(6, 7.0)]),
+        ("xi2 + yi2", &[(1, -4.0), (2, 7.0), (7, -4.0), (8, 7.0)]),
+    ];
+
+    let mut y = 10.0;
+    for (text, scripts) in labels {
+        let mut builder = rc
+            .text()
+            .new_text_layout(text)
+            .default_attribute(TextAttribute::FontSize(FONT_SIZE));
+        for &(idx, offset) in scripts {
+            builder = builder
+                .range_attribute(idx..idx + 1, TextAttribute::FontSize(SCRIPT_SIZE))
+                .range_attribute(idx..idx + 1, TextAttribute::BaselineOffset(offset));
+        }
+        let layout = builder.build()?;
+        rc.draw_text(&layout, (10.0, y));
+        y += layout.size().height + 10.0;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! OpenType font features: small caps, tabular numbers and ligatures.
+
+use crate::kurbo::Size;
+use crate::{
+    Color, Error, FontFamily, FontFeature, RenderContext, Text, TextAttribute, TextLayout,
+    TextLayoutBuilder,
+};
+
+pub const SIZE: Size = Size::new(240., 180.);
+
+const FONT_SIZE: f64 = 18.0;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let lines: [(&str, &[FontFeature]); 4] = [
+        ("Small Caps", &[FontFeature::SMALL_CAPS]),
+        ("1111.11\n8888.88", &[FontFeature::TABULAR_NUMBERS]),
+        ("office affine", &[]),
+        ("office affine", &[FontFeature::NO_LIGATURES]),
+    ];
+
+    let mut y = 10.0;
+    for (text, features) in lines {
+        let layout = rc
+            .text()
+            .new_text_layout(text)
+            .font(FontFamily::SERIF, FONT_SIZE)
+            .default_attribute(TextAttribute::FontFeatures(features.to_vec()))
+            .build()?;
+        rc.draw_text(&layout, (10.0, y));
+        y += layout.size().height + 8.0;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! A heatmap drawn as a mesh of colored triangles.
+
+use crate::kurbo::{Point, Size};
+use crate::{Color, Error, RenderContext};
+
+pub const SIZE: Size = Size::new(200., 200.);
+
+const GRID: u16 = 6;
+const CELL: f64 = 30.0;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let mut vertices = Vec::new();
+    for row in 0..=GRID {
+        for col in 0..=GRID {
+            let point = Point::new(10.0 + col as f64 * CELL, 10.0 + row as f64 * CELL);
+            let x = col as f64 / GRID as f64 - 0.5;
+            let y = row as f64 / GRID as f64 - 0.5;
+            let heat = (1.0 - 2.0 * (x * x + y * y)).clamp(0.0, 1.0);
+            vertices.push((point, Color::BLUE.lerp(Color::RED, heat)));
+        }
+    }
+
+    let mut indices = Vec::new();
+    for row in 0..GRID {
+        for col in 0..GRID {
+            let i = row * (GRID + 1) + col;
+            let below = i + GRID + 1;
+            indices.extend_from_slice(&[i, i + 1, below + 1, i, below + 1, below]);
+        }
+    }
+
+    rc.fill_mesh(&vertices, &indices);
+    Ok(())
+}
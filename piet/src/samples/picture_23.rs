@@ -0,0 +1,55 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Gradients with their own transforms.
+//!
+//! The same gradients are used to fill fixed rects, rotated, scaled and
+//! skewed by the brush rather than by the shapes.
+
+use crate::kurbo::{Affine, Point, Rect, Size, Vec2};
+use crate::{Color, Error, FixedLinearGradient, FixedRadialGradient, GradientStops, RenderContext};
+
+pub const SIZE: Size = Size::new(200., 200.);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let stops = (
+        Color::rgb8(0xd0, 0x20, 0x40),
+        Color::rgb8(0xf0, 0xa0, 0x20),
+        Color::rgb8(0x20, 0x60, 0xc0),
+    )
+        .to_vec();
+    let linear = FixedLinearGradient {
+        start: Point::new(-40.0, 0.0),
+        end: Point::new(40.0, 0.0),
+        stops: stops.clone(),
+    };
+    let radial = FixedRadialGradient {
+        center: Point::ZERO,
+        origin_offset: Vec2::new(-10.0, -10.0),
+        radius: 40.0,
+        start_radius: 0.0,
+        stops,
+    };
+
+    // Each cell is 80 by 80, centered on the gradient's origin.
+    let cells = [
+        (Point::new(55.0, 55.0), Affine::rotate(0.6)),
+        (Point::new(145.0, 55.0), Affine::skew(0.8, 0.0)),
+        (Point::new(55.0, 145.0), Affine::scale(0.5)),
+        (Point::new(145.0, 145.0), Affine::rotate(-0.4)),
+    ];
+    for (i, (center, transform)) in cells.into_iter().enumerate() {
+        let transform = transform.then_translate(center.to_vec2());
+        let brush = if i < 2 {
+            rc.gradient_with_transform(linear.clone(), transform)?
+        } else {
+            rc.gradient_with_transform(radial.clone(), transform)?
+        };
+        let cell = Rect::from_center_size(center, (80.0, 80.0));
+        rc.fill(cell, &brush);
+    }
+
+    Ok(())
+}
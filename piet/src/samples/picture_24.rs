@@ -0,0 +1,73 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Brushes and images faded by an opacity.
+//!
+//! Each row draws the same gradient, solid color and image at decreasing
+//! opacity, over a checkerboard so the fading is visible.
+
+use crate::kurbo::{Point, Rect, Size};
+use crate::{
+    Color, Error, FixedLinearGradient, GradientStops, ImageFormat, InterpolationMode, RenderContext,
+};
+
+pub const SIZE: Size = Size::new(240., 160.);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let grey = rc.solid_brush(Color::grey8(0xc0));
+    for y in 0..8 {
+        for x in 0..12 {
+            if (x + y) % 2 == 0 {
+                let origin = Point::new(x as f64 * 20.0, y as f64 * 20.0);
+                rc.fill(Rect::from_origin_size(origin, (20.0, 20.0)), &grey);
+            }
+        }
+    }
+
+    let gradient = rc.gradient(FixedLinearGradient {
+        start: Point::new(10.0, 0.0),
+        end: Point::new(70.0, 0.0),
+        stops: (Color::rgb8(0xd0, 0x20, 0x40), Color::rgb8(0x20, 0x60, 0xc0)).to_vec(),
+    })?;
+    let solid = rc.solid_brush(Color::rgb8(0x20, 0xa0, 0x40));
+
+    let image_data = make_image_data(16, 16);
+    let image = rc.make_image(16, 16, &image_data, ImageFormat::RgbaSeparate)?;
+    let src_rect = Rect::new(0.0, 0.0, 16.0, 16.0);
+
+    for (i, opacity) in [1.0, 0.6, 0.3].into_iter().enumerate() {
+        let y = 10.0 + i as f64 * 50.0;
+
+        let faded = rc.brush_with_opacity(&gradient, opacity);
+        rc.fill(Rect::new(10.0, y, 70.0, y + 40.0), &faded);
+
+        let faded = rc.brush_with_opacity(&solid, opacity);
+        rc.fill(Rect::new(90.0, y, 150.0, y + 40.0), &faded);
+
+        rc.draw_image_area_with_opacity(
+            &image,
+            src_rect,
+            Rect::new(170.0, y, 230.0, y + 40.0),
+            InterpolationMode::NearestNeighbor,
+            opacity,
+        );
+    }
+
+    Ok(())
+}
+
+fn make_image_data(width: usize, height: usize) -> Vec<u8> {
+    let mut result = vec![0; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let ix = (y * width + x) * 4;
+            result[ix] = (x * 255 / (width - 1)) as u8;
+            result[ix + 1] = (y * 255 / (height - 1)) as u8;
+            result[ix + 2] = 0x80;
+            result[ix + 3] = 0xff;
+        }
+    }
+    result
+}
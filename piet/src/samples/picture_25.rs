@@ -0,0 +1,49 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Tiled images.
+//!
+//! A small image is repeated and mirrored to fill rects that don't fit a
+//! whole number of copies.
+
+use crate::kurbo::{Rect, Size};
+use crate::{Color, Error, ImageFormat, InterpolationMode, RenderContext, TileMode};
+
+pub const SIZE: Size = Size::new(200., 120.);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let image_data = make_image_data(16, 12);
+    let image = rc.make_image(16, 12, &image_data, ImageFormat::RgbaSeparate)?;
+
+    rc.draw_image_tiled(
+        &image,
+        Rect::new(10.0, 10.0, 90.0, 110.0),
+        TileMode::Repeat,
+        InterpolationMode::NearestNeighbor,
+    );
+    rc.draw_image_tiled(
+        &image,
+        Rect::new(110.0, 10.0, 190.0, 110.0),
+        TileMode::Mirror,
+        InterpolationMode::NearestNeighbor,
+    );
+
+    Ok(())
+}
+
+/// An asymmetric gradient, so that mirrored copies are easy to tell apart.
+fn make_image_data(width: usize, height: usize) -> Vec<u8> {
+    let mut result = vec![0; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let ix = (y * width + x) * 4;
+            result[ix] = (x * 255 / (width - 1)) as u8;
+            result[ix + 1] = (y * 255 / (height - 1)) as u8;
+            result[ix + 2] = if x < 4 && y < 4 { 0xff } else { 0x40 };
+            result[ix + 3] = 0xff;
+        }
+    }
+    result
+}
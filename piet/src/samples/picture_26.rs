@@ -0,0 +1,154 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Stress test for paths.
+//!
+//! Paths with tens of thousands of segments, very sharp miter joins, and
+//! degenerate segments, which backends handle with differing speed and
+//! robustness. The paths are generated procedurally, and are the same on
+//! every run.
+
+use std::f64::consts::TAU;
+
+use crate::kurbo::{BezPath, Point, Size};
+use crate::{Color, Error, LineCap, LineJoin, RenderContext, StrokeStyle};
+
+pub const SIZE: Size = Size::new(300., 300.);
+
+/// The number of segments in the long paths.
+const SEGMENTS: usize = 20_000;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let lissajous = lissajous(Point::new(75.0, 75.0), 65.0);
+    rc.stroke(&lissajous, &Color::rgb8(0x20, 0x60, 0xc0), 0.5);
+
+    let scribble = scribble(Point::new(160.0, 10.0), Size::new(130.0, 130.0));
+    rc.fill_even_odd(&scribble, &Color::rgba8(0xc0, 0x40, 0x20, 0x80));
+    rc.stroke(&scribble, &Color::BLACK, 0.25);
+
+    let miter = StrokeStyle::new()
+        .line_join(LineJoin::Miter { limit: 1000.0 })
+        .line_cap(LineCap::Butt);
+    let spikes = spikes(Point::new(75.0, 225.0), 60.0, 8.0, 40);
+    rc.stroke_styled(&spikes, &Color::rgb8(0x20, 0x80, 0x40), 1.0, &miter);
+
+    let round = StrokeStyle::new()
+        .line_join(LineJoin::Round)
+        .line_cap(LineCap::Round);
+    let degenerate = degenerate(Point::new(160.0, 160.0));
+    rc.stroke_styled(&degenerate, &Color::rgb8(0x60, 0x20, 0xa0), 6.0, &round);
+    rc.stroke_styled(&degenerate, &Color::BLACK, 1.0, &miter);
+
+    Ok(())
+}
+
+/// A long closed curve of short line segments, which crosses itself many
+/// times.
+fn lissajous(center: Point, radius: f64) -> BezPath {
+    let point = |i: usize| {
+        let t = i as f64 / SEGMENTS as f64 * TAU;
+        center + (radius * (13.0 * t).sin(), radius * (17.0 * t).cos())
+    };
+    let mut path = BezPath::new();
+    path.move_to(point(0));
+    for i in 1..SEGMENTS {
+        path.line_to(point(i));
+    }
+    path.close_path();
+    path
+}
+
+/// A random walk of lines and curves, confined to a box.
+fn scribble(origin: Point, size: Size) -> BezPath {
+    let mut rng = Lcg(0x2545_f491);
+    let mut point = || origin + (rng.next() * size.width, rng.next() * size.height);
+    let mut path = BezPath::new();
+    path.move_to(point());
+    for i in 0..SEGMENTS / 4 {
+        match i % 3 {
+            0 => path.line_to(point()),
+            1 => path.quad_to(point(), point()),
+            _ => path.curve_to(point(), point(), point()),
+        }
+    }
+    path.close_path();
+    path
+}
+
+/// A star with very sharp points, so that miter joins extend far past the
+/// stroke.
+fn spikes(center: Point, outer: f64, inner: f64, count: usize) -> BezPath {
+    let mut path = BezPath::new();
+    for i in 0..count * 2 {
+        let radius = if i % 2 == 0 { outer } else { inner };
+        let angle = i as f64 / (count * 2) as f64 * TAU;
+        let point = center + (radius * angle.cos(), radius * angle.sin());
+        if i == 0 {
+            path.move_to(point);
+        } else {
+            path.line_to(point);
+        }
+    }
+    path.close_path();
+    path
+}
+
+/// Zero-length segments, repeated points, collinear segments, segments that
+/// reverse on themselves, and degenerate curves.
+fn degenerate(origin: Point) -> BezPath {
+    let p = |x: f64, y: f64| origin + (x, y);
+    let mut path = BezPath::new();
+
+    // a lone zero-length line, which only shows with round caps
+    path.move_to(p(10.0, 10.0));
+    path.line_to(p(10.0, 10.0));
+
+    // a run of collinear segments, with a repeated point in the middle
+    path.move_to(p(30.0, 10.0));
+    path.line_to(p(60.0, 10.0));
+    path.line_to(p(60.0, 10.0));
+    path.line_to(p(90.0, 10.0));
+    path.line_to(p(120.0, 10.0));
+
+    // a line that turns back on itself, giving an infinitely sharp join
+    path.move_to(p(10.0, 40.0));
+    path.line_to(p(120.0, 40.0));
+    path.line_to(p(40.0, 40.0));
+
+    // curves whose control points coincide with their end points
+    path.move_to(p(10.0, 70.0));
+    path.quad_to(p(10.0, 70.0), p(60.0, 70.0));
+    path.curve_to(p(60.0, 70.0), p(120.0, 70.0), p(120.0, 70.0));
+
+    // a curve collapsed to a point
+    path.move_to(p(60.0, 100.0));
+    path.curve_to(p(60.0, 100.0), p(60.0, 100.0), p(60.0, 100.0));
+
+    // a closed path with no area
+    path.move_to(p(10.0, 120.0));
+    path.line_to(p(120.0, 120.0));
+    path.close_path();
+
+    // many tiny segments, well below a pixel
+    path.move_to(p(10.0, 130.0));
+    for i in 1..=SEGMENTS / 10 {
+        let x = 10.0 + 110.0 * i as f64 / (SEGMENTS / 10) as f64;
+        let y = 130.0 + if i % 2 == 0 { 0.01 } else { -0.01 };
+        path.line_to(p(x, y));
+    }
+    path
+}
+
+/// A minimal linear congruential generator, so that the sample is the same
+/// on every run without depending on a random number crate.
+struct Lcg(u32);
+
+impl Lcg {
+    /// Returns a value in `0.0..1.0`.
+    fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (self.0 >> 8) as f64 / (1u32 << 24) as f64
+    }
+}
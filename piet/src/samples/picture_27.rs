@@ -0,0 +1,138 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Nested save, clip, transform and clear.
+//!
+//! Each cell draws one sequence of nested operations, pinning down the
+//! intended semantics:
+//!
+//! 1. restore undoes a clip added since the matching save, but not earlier ones
+//! 2. a clip stays where it was when added, whatever the transform does later
+//! 3. clear ignores both the clip and the transform, even when nested
+//! 4. restore after clear still restores the clip
+//! 5. a clear region is in device space, not scaled by the transform
+//! 6. restoring part of a stack of saves keeps the outer clips
+
+use crate::kurbo::{Affine, Circle, Point, Rect, Size, Vec2};
+use crate::{Color, Error, RenderContext};
+
+pub const SIZE: Size = Size::new(300., 200.);
+
+const CELL: f64 = 100.0;
+
+const BACKGROUND: Color = Color::grey8(0xe0);
+const FILL: Color = Color::rgba8(0x20, 0x60, 0xc0, 0xc0);
+const CLEAR: Color = Color::rgb8(0xf0, 0xc0, 0x40);
+const MARKER: Color = Color::rgb8(0xc0, 0x20, 0x20);
+
+type Cell<R> = fn(&mut R, Vec2) -> Result<(), Error>;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let cells: [Cell<R>; 6] = [
+        restore_undoes_inner_clip,
+        clip_ignores_later_transform,
+        clear_ignores_nested_state,
+        restore_after_clear,
+        clear_region_in_device_space,
+        partial_restore,
+    ];
+    for (i, cell) in cells.into_iter().enumerate() {
+        let origin = Vec2::new((i % 3) as f64 * CELL, (i / 3) as f64 * CELL);
+        rc.clear(cell_rect(origin), BACKGROUND);
+        cell(rc, origin)?;
+    }
+    // every cell should leave the state as it found it
+    rc.fill(Circle::new((150.0, 100.0), 4.0), &MARKER);
+
+    Ok(())
+}
+
+/// The area of a cell, less a margin.
+fn cell_rect(origin: Vec2) -> Rect {
+    Rect::from_origin_size(Point::ORIGIN + origin, (CELL, CELL)).inset(-5.0)
+}
+
+fn restore_undoes_inner_clip(rc: &mut impl RenderContext, origin: Vec2) -> Result<(), Error> {
+    rc.save()?;
+    rc.transform(Affine::translate(origin));
+    rc.clip(Rect::new(10.0, 10.0, 90.0, 90.0));
+    rc.save()?;
+    rc.transform(Affine::scale(2.0));
+    rc.clip(Rect::new(5.0, 5.0, 20.0, 20.0));
+    rc.restore()?;
+    // clipped to the outer rect only
+    rc.fill(Rect::new(0.0, 0.0, CELL, CELL), &FILL);
+    rc.restore()
+}
+
+fn clip_ignores_later_transform(rc: &mut impl RenderContext, origin: Vec2) -> Result<(), Error> {
+    rc.save()?;
+    rc.transform(Affine::translate(origin + Vec2::new(50.0, 50.0)));
+    rc.transform(Affine::rotate(std::f64::consts::FRAC_PI_4));
+    rc.clip(Rect::new(-25.0, -25.0, 25.0, 25.0));
+    rc.transform(Affine::scale(0.25));
+    // a diamond, although the rect is drawn under a different transform
+    rc.fill(Rect::new(-400.0, -400.0, 400.0, 400.0), &FILL);
+    rc.restore()
+}
+
+fn clear_ignores_nested_state(rc: &mut impl RenderContext, origin: Vec2) -> Result<(), Error> {
+    rc.save()?;
+    rc.transform(Affine::translate(origin));
+    rc.clip(Circle::new((50.0, 50.0), 40.0));
+    rc.save()?;
+    rc.transform(Affine::scale(0.5));
+    rc.clip(Rect::new(0.0, 0.0, 100.0, 100.0));
+    // a square, unclipped and unscaled
+    rc.clear(cell_rect(origin).inset(-5.0), CLEAR);
+    rc.fill(Rect::new(0.0, 0.0, 200.0, 200.0), &FILL);
+    rc.restore()?;
+    rc.restore()
+}
+
+fn restore_after_clear(rc: &mut impl RenderContext, origin: Vec2) -> Result<(), Error> {
+    rc.save()?;
+    rc.transform(Affine::translate(origin));
+    rc.save()?;
+    rc.clip(Rect::new(40.0, 40.0, 60.0, 60.0));
+    rc.clear(
+        Rect::from_origin_size(Point::ORIGIN + origin, (50.0, 50.0)),
+        CLEAR,
+    );
+    rc.restore()?;
+    // not clipped
+    rc.fill(Rect::new(10.0, 60.0, 90.0, 90.0), &FILL);
+    rc.restore()
+}
+
+fn clear_region_in_device_space(rc: &mut impl RenderContext, origin: Vec2) -> Result<(), Error> {
+    rc.save()?;
+    rc.transform(Affine::translate(origin) * Affine::scale(3.0));
+    rc.clip(Rect::new(0.0, 0.0, 20.0, 20.0));
+    // a 30x30 square at the top left of the cell, not 90x90
+    rc.clear(
+        Rect::from_origin_size(Point::ORIGIN + origin, (30.0, 30.0)),
+        CLEAR,
+    );
+    rc.fill(Rect::new(10.0, 10.0, 30.0, 30.0), &FILL);
+    rc.restore()
+}
+
+fn partial_restore(rc: &mut impl RenderContext, origin: Vec2) -> Result<(), Error> {
+    rc.save()?;
+    rc.transform(Affine::translate(origin));
+    rc.clip(Rect::new(10.0, 10.0, 90.0, 50.0));
+    rc.save()?;
+    rc.clip(Rect::new(10.0, 10.0, 50.0, 90.0));
+    rc.save()?;
+    rc.transform(Affine::translate((40.0, 40.0)));
+    rc.clip(Rect::new(-30.0, -30.0, 0.0, 0.0));
+    rc.fill(Rect::new(-40.0, -40.0, 60.0, 60.0), &MARKER);
+    rc.restore()?;
+    rc.restore()?;
+    // clipped to the first rect, and drawn under its transform
+    rc.fill(Rect::new(0.0, 30.0, CELL, CELL), &FILL);
+    rc.restore()
+}
@@ -0,0 +1,98 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Gradient stops that need normalizing.
+//!
+//! Stops that share a position, stops outside `0.0..=1.0`, and stops out of
+//! order, which piet normalizes before they reach the backend; see
+//! [`FixedGradient`](crate::FixedGradient). The left column is drawn with the
+//! unusual stops, and the right column with the equivalent normalized stops,
+//! so each row should show two identical rects.
+
+use crate::kurbo::{Point, Rect, Size};
+use crate::{Color, Error, FixedLinearGradient, FixedRadialGradient, GradientStop, RenderContext};
+
+pub const SIZE: Size = Size::new(200., 250.);
+
+const RED: Color = Color::rgb8(0xff, 0x00, 0x00);
+const GREEN: Color = Color::rgb8(0x00, 0xc0, 0x00);
+const BLUE: Color = Color::rgb8(0x00, 0x00, 0xff);
+
+type Stops<'a> = &'a [(f32, Color)];
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let rows: [(Stops, Stops); 4] = [
+        // shared positions make hard edges, in the order given
+        (
+            &[
+                (0.0, RED),
+                (0.5, RED),
+                (0.5, BLUE),
+                (0.5, GREEN),
+                (1.0, GREEN),
+            ],
+            &[(0.0, RED), (0.5, RED), (0.5, GREEN), (1.0, GREEN)],
+        ),
+        // out of order
+        (
+            &[(1.0, BLUE), (0.0, RED), (0.5, GREEN)],
+            &[(0.0, RED), (0.5, GREEN), (1.0, BLUE)],
+        ),
+        // out of range, cut at the ends of the gradient
+        (
+            &[(-1.0, Color::BLACK), (0.5, RED), (2.0, Color::WHITE)],
+            &[
+                (0.0, Color::rgb8(0xaa, 0x00, 0x00)),
+                (0.5, RED),
+                (1.0, Color::rgb8(0xff, 0x55, 0x55)),
+            ],
+        ),
+        // entirely out of range
+        (&[(1.5, GREEN), (3.0, BLUE)], &[(0.0, GREEN), (1.0, GREEN)]),
+    ];
+
+    for (i, (unusual, normal)) in rows.iter().enumerate() {
+        let y = 10.0 + i as f64 * 45.0;
+        for (column, stops) in [unusual, normal].into_iter().enumerate() {
+            let rect = Rect::from_origin_size((10.0 + column as f64 * 95.0, y), (85.0, 35.0));
+            let gradient = FixedLinearGradient {
+                start: Point::new(rect.x0, y),
+                end: Point::new(rect.x1, y),
+                stops: to_stops(stops),
+            };
+            let brush = rc.gradient(gradient)?;
+            rc.fill(rect, &brush);
+        }
+    }
+
+    // a radial gradient with stops out of range and out of order
+    let unusual = [(1.5, Color::WHITE), (-0.5, BLUE), (0.5, RED)];
+    let normal = [
+        (0.0, Color::rgb8(0x80, 0x00, 0x80)),
+        (0.5, RED),
+        (1.0, Color::rgb8(0xff, 0x80, 0x80)),
+    ];
+    for (column, stops) in [&unusual[..], &normal[..]].into_iter().enumerate() {
+        let center = Point::new(52.5 + column as f64 * 95.0, 207.5);
+        let gradient = FixedRadialGradient {
+            center,
+            origin_offset: Default::default(),
+            radius: 30.0,
+            start_radius: 0.0,
+            stops: to_stops(stops),
+        };
+        let brush = rc.gradient(gradient)?;
+        rc.fill(Rect::from_center_size(center, (85.0, 65.0)), &brush);
+    }
+
+    Ok(())
+}
+
+fn to_stops(stops: &[(f32, Color)]) -> Vec<GradientStop> {
+    stops
+        .iter()
+        .map(|&(pos, color)| GradientStop { pos, color })
+        .collect()
+}
@@ -0,0 +1,56 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Elliptical gradients.
+//!
+//! Wide and tall ellipses, one with its origin moved off center, and one
+//! rotated by a gradient transform. Each is drawn over a thin outline of the
+//! ellipse at pos 1.0, which the edge of the gradient should follow.
+
+use crate::kurbo::{Affine, Ellipse, Point, Rect, Size, Vec2};
+use crate::{Color, Error, FixedEllipticalGradient, GradientStops, RenderContext};
+
+pub const SIZE: Size = Size::new(200., 200.);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let stops = (
+        Color::rgb8(0xf0, 0xe0, 0x60),
+        Color::rgb8(0xd0, 0x40, 0x20),
+        Color::rgb8(0x40, 0x20, 0x60),
+    )
+        .to_vec();
+    let gradient = |center: Point, radii: Vec2, origin_offset: Vec2| FixedEllipticalGradient {
+        center,
+        origin_offset,
+        radii,
+        stops: stops.clone(),
+    };
+    let outline = Color::rgba8(0x00, 0x00, 0x00, 0x80);
+
+    let cells = [
+        (Point::new(50.0, 50.0), Vec2::new(40.0, 15.0), Vec2::ZERO),
+        (Point::new(150.0, 50.0), Vec2::new(15.0, 40.0), Vec2::ZERO),
+        (
+            Point::new(50.0, 150.0),
+            Vec2::new(40.0, 25.0),
+            Vec2::new(-20.0, -10.0),
+        ),
+    ];
+    for (center, radii, origin_offset) in cells {
+        let brush = rc.gradient(gradient(center, radii, origin_offset))?;
+        rc.fill(Rect::from_center_size(center, (90.0, 90.0)), &brush);
+        rc.stroke(Ellipse::new(center, radii, 0.0), &outline, 1.0);
+    }
+
+    // rotated about its center by the brush
+    let center = Point::new(150.0, 150.0);
+    let radii = Vec2::new(40.0, 15.0);
+    let rotate = Affine::rotate_about(0.6, center);
+    let brush = rc.gradient_with_transform(gradient(center, radii, Vec2::ZERO), rotate)?;
+    rc.fill(Rect::from_center_size(center, (90.0, 90.0)), &brush);
+    rc.stroke(Ellipse::new(center, radii, 0.6), &outline, 1.0);
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Radial gradients with a start radius.
+//!
+//! The gradient runs between two circles rather than from a point: on the
+//! left the circles share a center, giving a ring, and on the right the
+//! start circle is moved off center. The start circle is outlined.
+
+use crate::kurbo::{Circle, Point, Rect, Size, Vec2};
+use crate::{Color, Error, FixedRadialGradient, GradientStops, RenderContext};
+
+pub const SIZE: Size = Size::new(200., 100.);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let stops = (
+        Color::rgb8(0x20, 0x60, 0xc0),
+        Color::rgb8(0xf0, 0xf0, 0xf0),
+        Color::rgb8(0xc0, 0x20, 0x40),
+    )
+        .to_vec();
+    let outline = Color::rgba8(0x00, 0x00, 0x00, 0x80);
+
+    let cells = [
+        (Point::new(50.0, 50.0), Vec2::ZERO),
+        (Point::new(150.0, 50.0), Vec2::new(-15.0, -10.0)),
+    ];
+    for (center, origin_offset) in cells {
+        let gradient = FixedRadialGradient {
+            center,
+            origin_offset,
+            radius: 40.0,
+            start_radius: 15.0,
+            stops: stops.clone(),
+        };
+        let brush = rc.gradient(gradient)?;
+        rc.fill(Rect::from_center_size(center, (90.0, 90.0)), &brush);
+        rc.stroke(Circle::new(center + origin_offset, 15.0), &outline, 1.0);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Underlines that skip ink.
+//!
+//! Each line is drawn with the underline skipping the descenders, which is
+//! the default, and then drawn straight through them.
+//!
+//! Direct2D and the web backend don't support skipping ink, and draw both
+//! underlines straight through the descenders.
+
+use crate::kurbo::Size;
+use crate::{
+    Color, Error, FontFamily, RenderContext, Text, TextAttribute, TextLayout, TextLayoutBuilder,
+    UnderlineStyle,
+};
+
+pub const SIZE: Size = Size::new(240., 200.);
+
+static TEXT: &str = "Jumpy gryphons, quietly";
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+    let blue = Color::rgb8(0x22, 0x55, 0xcc);
+
+    let styles = [
+        (FontFamily::SERIF, UnderlineStyle::Solid),
+        (FontFamily::SANS_SERIF, UnderlineStyle::Solid),
+        (FontFamily::SANS_SERIF, UnderlineStyle::Wavy),
+    ];
+
+    let mut y = 10.0;
+    for (font, style) in styles {
+        for skip_ink in [true, false] {
+            let layout = rc
+                .text()
+                .new_text_layout(TEXT)
+                .font(font.clone(), 16.0)
+                .default_attribute(style)
+                .default_attribute(TextAttribute::UnderlineColor(blue))
+                .default_attribute(TextAttribute::UnderlineSkipInk(skip_ink))
+                .default_attribute(TextAttribute::Underline(true))
+                .build()?;
+            rc.draw_text(&layout, (10.0, y));
+            y += layout.size().height + 8.0;
+        }
+    }
+
+    Ok(())
+}
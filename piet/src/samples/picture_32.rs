@@ -0,0 +1,44 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Text antialiasing modes.
+//!
+//! The same line drawn with each [`TextAntialiasing`] mode, on a light and
+//! on a dark background. Subpixel antialiasing may fall back to grayscale
+//! where the platform or target does not support it.
+
+use crate::kurbo::{Rect, Size};
+use crate::{
+    Color, Error, FontFamily, RenderContext, Text, TextAntialiasing, TextLayout, TextLayoutBuilder,
+};
+
+pub const SIZE: Size = Size::new(400., 200.);
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+    rc.fill(Rect::new(200.0, 0.0, 400.0, 200.0), &Color::grey8(0x30));
+
+    let modes = [
+        ("Default", TextAntialiasing::Default),
+        ("Grayscale", TextAntialiasing::Grayscale),
+        ("Subpixel", TextAntialiasing::Subpixel),
+        ("Aliased", TextAntialiasing::Aliased),
+    ];
+
+    for (column, color) in [Color::BLACK, Color::WHITE].into_iter().enumerate() {
+        let mut y = 10.0;
+        for (name, antialiasing) in modes {
+            let layout = rc
+                .text()
+                .new_text_layout(format!("{name}: Hamburgefonstiv"))
+                .font(FontFamily::SANS_SERIF, 14.0)
+                .text_color(color)
+                .antialiasing(antialiasing)
+                .build()?;
+            rc.draw_text(&layout, (10.0 + column as f64 * 200.0, y));
+            y += layout.size().height + 8.0;
+        }
+    }
+
+    Ok(())
+}
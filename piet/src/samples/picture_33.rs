@@ -0,0 +1,80 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Text under rotated and scaled transforms, over gradients.
+//!
+//! Each cell fills a gradient, draws a layout and its bounds, and then fills
+//! a small gradient swatch, all under the same transform. The text should sit
+//! inside its outline, and the swatch should sit at the bottom right of the
+//! outline, however the cell is transformed.
+
+use crate::kurbo::{Affine, Rect, Size, Vec2};
+use crate::{
+    Color, Error, FixedLinearGradient, FontFamily, GradientStops, RenderContext, Text, TextLayout,
+    TextLayoutBuilder,
+};
+
+pub const SIZE: Size = Size::new(300., 300.);
+
+const CELL: f64 = 150.0;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let layout = rc
+        .text()
+        .new_text_layout("Rotated text")
+        .font(FontFamily::SANS_SERIF, 12.0)
+        .text_color(Color::BLACK)
+        .build()?;
+    let text_size = layout.size();
+
+    let background = (Color::rgb8(0xc0, 0xe0, 0xff), Color::rgb8(0xff, 0xe0, 0xc0)).to_vec();
+    let swatch = (Color::rgb8(0xc0, 0x20, 0x40), Color::rgb8(0x20, 0x40, 0xc0)).to_vec();
+    let outline = Color::rgba8(0x00, 0x00, 0x00, 0x80);
+
+    let transforms = [
+        Affine::IDENTITY,
+        Affine::rotate(0.5),
+        Affine::scale_non_uniform(1.25, 0.75),
+        Affine::rotate(-2.0) * Affine::scale(1.25),
+    ];
+    for (i, transform) in transforms.into_iter().enumerate() {
+        let center = Vec2::new(
+            (i % 2) as f64 * CELL + CELL / 2.0,
+            (i / 2) as f64 * CELL + CELL / 2.0,
+        );
+        rc.with_save(|rc| {
+            rc.transform(Affine::translate(center) * transform);
+            let text_rect = text_size.to_rect() - text_size.to_vec2() / 2.0;
+            let area = text_rect.inflate(6.0, 6.0);
+            let gradient = FixedLinearGradient {
+                start: area.origin(),
+                end: (area.x1, area.y1).into(),
+                stops: background.clone(),
+            };
+            let brush = rc.gradient(gradient)?;
+            rc.fill(area, &brush);
+
+            rc.draw_text(&layout, text_rect.origin());
+            rc.stroke(text_rect, &outline, 1.0);
+
+            let corner = Rect::new(
+                text_rect.x1 - 8.0,
+                text_rect.y1 - 8.0,
+                text_rect.x1,
+                text_rect.y1,
+            );
+            let gradient = FixedLinearGradient {
+                start: corner.origin(),
+                end: (corner.x1, corner.y1).into(),
+                stops: swatch.clone(),
+            };
+            let brush = rc.gradient(gradient)?;
+            rc.fill(corner, &brush);
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
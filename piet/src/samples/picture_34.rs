@@ -0,0 +1,57 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Text under arbitrary affine transforms.
+//!
+//! Each cell draws the same layout, and an outline of its bounds, under a
+//! different transform: none, a quarter turn, a skew, a horizontal flip, a
+//! vertical flip, and a rotation with a clip that should cut the text in
+//! half. The text should stay inside its outline, transformed with it.
+
+use crate::kurbo::{Affine, Rect, Size, Vec2};
+use crate::{Color, Error, FontFamily, RenderContext, Text, TextLayout, TextLayoutBuilder};
+
+pub const SIZE: Size = Size::new(360., 240.);
+
+const CELL: f64 = 120.0;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let layout = rc
+        .text()
+        .new_text_layout("Transform")
+        .font(FontFamily::SERIF, 16.0)
+        .text_color(Color::rgb8(0x20, 0x20, 0x60))
+        .build()?;
+    let size = layout.size();
+    // the layout's bounds, centered on the origin
+    let bounds = size.to_rect() - size.to_vec2() / 2.0;
+    let outline = Color::rgba8(0xc0, 0x20, 0x20, 0x80);
+
+    let transforms = [
+        Affine::IDENTITY,
+        Affine::rotate(std::f64::consts::FRAC_PI_2),
+        Affine::skew(-0.5, 0.2),
+        Affine::FLIP_X,
+        Affine::FLIP_Y,
+        Affine::rotate(-0.4),
+    ];
+    for (i, transform) in transforms.into_iter().enumerate() {
+        let center = Vec2::new(
+            (i % 3) as f64 * CELL + CELL / 2.0,
+            (i / 3) as f64 * CELL + CELL / 2.0,
+        );
+        rc.with_save(|rc| {
+            rc.transform(Affine::translate(center) * transform);
+            if i == 5 {
+                rc.clip(Rect::new(bounds.x0, bounds.y0, 0.0, bounds.y1));
+            }
+            rc.draw_text(&layout, bounds.origin());
+            rc.stroke(bounds, &outline, 1.0);
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
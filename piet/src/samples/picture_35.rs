@@ -0,0 +1,78 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Clips with the nonzero and even-odd fill rules, alone and nested.
+//!
+//! The shapes are a ring made of two circles wound the same way, and a
+//! five-pointed star that crosses itself. From left to right: the ring
+//! clipped with the nonzero rule, which fills the hole; the ring clipped
+//! with the even-odd rule, which leaves it; the star clipped with the
+//! nonzero rule inside the even-odd ring; and the ring clipped with the
+//! nonzero rule inside the even-odd star, which has a hole in its middle.
+
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+use crate::kurbo::{BezPath, Circle, Point, Rect, Shape, Size, Vec2};
+use crate::{Color, Error, RenderContext};
+
+pub const SIZE: Size = Size::new(400., 100.);
+
+const CELL: f64 = 100.0;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+    let fill = Color::rgb8(0x20, 0x70, 0xb0);
+    let outline = Color::grey(0.6);
+
+    for cell in 0..4 {
+        let center = Point::new(CELL * (cell as f64 + 0.5), CELL / 2.0);
+        let ring = ring(center);
+        let star = star(center);
+        rc.with_save(|rc| {
+            match cell {
+                0 => rc.clip(&ring),
+                1 => rc.clip_even_odd(&ring),
+                2 => {
+                    rc.clip_even_odd(&ring);
+                    rc.clip(&star);
+                }
+                _ => {
+                    rc.clip_even_odd(&star);
+                    rc.clip(&ring);
+                }
+            }
+            rc.fill(Rect::from_center_size(center, (CELL, CELL)), &fill);
+            Ok(())
+        })?;
+        if cell >= 2 {
+            rc.stroke(&star, &outline, 0.5);
+        }
+        rc.stroke(&ring, &outline, 0.5);
+    }
+
+    Ok(())
+}
+
+/// Two circles around `center`, both wound the same way.
+fn ring(center: Point) -> BezPath {
+    let mut path = Circle::new(center, 40.0).to_path(1e-3);
+    path.extend(Circle::new(center, 20.0).path_elements(1e-3));
+    path
+}
+
+/// A five-pointed star around `center`, drawn as one line that crosses
+/// itself.
+fn star(center: Point) -> BezPath {
+    let mut path = BezPath::new();
+    for i in 0..5 {
+        let angle = i as f64 * 2.0 * TAU / 5.0 - FRAC_PI_2;
+        let point = center + Vec2::from_angle(angle) * 45.0;
+        if i == 0 {
+            path.move_to(point);
+        } else {
+            path.line_to(point);
+        }
+    }
+    path.close_path();
+    path
+}
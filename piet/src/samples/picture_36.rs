@@ -0,0 +1,98 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Gradients with unevenly spaced stops.
+//!
+//! Each linear gradient is drawn twice: in one piece, and below that as a
+//! row of two-stop gradients, one between each pair of neighbouring stops.
+//! The radial gradient on the right is drawn as a disc of its inner stops
+//! over its outer stops. If a backend ignores the positions of the stops,
+//! the two versions differ.
+
+use crate::kurbo::{Circle, Point, Rect, Size};
+use crate::{Color, Error, FixedLinearGradient, FixedRadialGradient, GradientStop, RenderContext};
+
+pub const SIZE: Size = Size::new(200., 240.);
+
+const RED: Color = Color::rgb8(0xff, 0x00, 0x00);
+const GREEN: Color = Color::rgb8(0x00, 0xc0, 0x00);
+const BLUE: Color = Color::rgb8(0x00, 0x00, 0xff);
+
+const WIDTH: f64 = 180.0;
+
+pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
+    rc.clear(None, Color::WHITE);
+
+    let rows: [&[(f64, Color)]; 2] = [
+        &[(0.0, RED), (0.25, GREEN), (0.9, BLUE), (1.0, Color::WHITE)],
+        // the last stop is padded to the end of the gradient
+        &[(0.0, GREEN), (0.6, RED), (0.8, BLUE)],
+    ];
+
+    for (i, stops) in rows.iter().enumerate() {
+        let y = 10.0 + i as f64 * 70.0;
+        let rect = Rect::from_origin_size((10.0, y), (WIDTH, 25.0));
+        let gradient = FixedLinearGradient {
+            start: Point::new(rect.x0, y),
+            end: Point::new(rect.x1, y),
+            stops: to_stops(stops),
+        };
+        let brush = rc.gradient(gradient)?;
+        rc.fill(rect, &brush);
+
+        let y = y + 30.0;
+        let x = |pos: f64| 10.0 + pos * WIDTH;
+        for pair in stops.windows(2) {
+            let rect = Rect::new(x(pair[0].0), y, x(pair[1].0), y + 25.0);
+            let gradient = FixedLinearGradient {
+                start: Point::new(rect.x0, y),
+                end: Point::new(rect.x1, y),
+                stops: to_stops(&[(0.0, pair[0].1), (1.0, pair[1].1)]),
+            };
+            let brush = rc.gradient(gradient)?;
+            rc.fill(rect, &brush);
+        }
+        let (last_pos, last_color) = stops[stops.len() - 1];
+        if last_pos < 1.0 {
+            rc.fill(Rect::new(x(last_pos), y, x(1.0), y + 25.0), &last_color);
+        }
+    }
+
+    let stops = [(0.0, RED), (0.2, GREEN), (1.0, BLUE)];
+    let radius = 50.0;
+    let left = Point::new(52.5, 185.0);
+    let right = Point::new(147.5, 185.0);
+    let gradient = |center, radius, stops: &[(f64, Color)]| FixedRadialGradient {
+        center,
+        origin_offset: Default::default(),
+        radius,
+        start_radius: 0.0,
+        stops: to_stops(stops),
+    };
+
+    let brush = rc.gradient(gradient(left, radius, &stops))?;
+    rc.fill(Rect::from_center_size(left, (85.0, 85.0)), &brush);
+
+    // the outer stops, from the edge of the inner disc
+    let outer = FixedRadialGradient {
+        start_radius: radius * 0.2,
+        ..gradient(right, radius, &[(0.0, GREEN), (1.0, BLUE)])
+    };
+    let brush = rc.gradient(outer)?;
+    rc.fill(Rect::from_center_size(right, (85.0, 85.0)), &brush);
+    let inner = [(0.0, RED), (1.0, GREEN)];
+    let brush = rc.gradient(gradient(right, radius * 0.2, &inner))?;
+    rc.fill(Circle::new(right, radius * 0.2), &brush);
+
+    Ok(())
+}
+
+fn to_stops(stops: &[(f64, Color)]) -> Vec<GradientStop> {
+    stops
+        .iter()
+        .map(|&(pos, color)| GradientStop {
+            pos: pos as f32,
+            color,
+        })
+        .collect()
+}
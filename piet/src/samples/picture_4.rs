@@ -27,6 +27,7 @@ pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
         center: Point::new(30.0, 30.0),
         origin_offset: Vec2::new(10.0, 10.0),
         radius: 30.0,
+        start_radius: 0.0,
         stops,
     }))?;
     rc.fill(Rect::new(0.0, 0.0, 60.0, 60.0), &gradient);
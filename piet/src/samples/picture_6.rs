@@ -24,6 +24,7 @@ pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
         center: Point::new(30.0, 30.0),
         origin_offset: Vec2::new(10.0, 10.0),
         radius: 40.0,
+        start_radius: 0.0,
         stops: create_gradient_stops(),
     }))?;
     rc.stroke(
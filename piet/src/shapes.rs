@@ -3,8 +3,11 @@
 
 //! Options for drawing paths.
 
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+use kurbo::{BezPath, PathEl, Rect, Shape};
+
 /// Options for drawing stroked lines.
 ///
 /// You may configure particular aspects of the style by using the
@@ -37,6 +40,8 @@ use std::sync::Arc;
 ///
 /// [PLRMv3]: https://www.adobe.com/content/dam/acom/en/devnet/actionscript/articles/PLRM.pdf
 #[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct StrokeStyle {
     /// How to join segments of the path.
     ///
@@ -70,7 +75,7 @@ pub struct StrokeStyle {
 /// We use our own type as a way of making this work in `const` contexts.
 ///
 /// This type `Deref`s to `&[f64]`.
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone)]
 pub struct StrokeDash {
     slice: &'static [f64],
     alloc: Option<Arc<[f64]>>,
@@ -78,6 +83,7 @@ pub struct StrokeDash {
 
 /// Options for angled joins in strokes.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LineJoin {
     /// The outer edges of the two paths are extended until they intersect.
     ///
@@ -112,6 +118,7 @@ impl LineJoin {
 
 /// Options for the cap of stroked lines.
 #[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LineCap {
     /// The stroke is squared off at the endpoint of the path.
     #[default]
@@ -232,9 +239,186 @@ impl Default for LineJoin {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for StrokeDash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StrokeDash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let lengths = Vec::<f64>::deserialize(deserializer)?;
+        Ok(StrokeDash {
+            slice: &[],
+            alloc: Some(lengths.into()),
+        })
+    }
+}
+
+// Compare the lengths, rather than how they are stored.
+impl PartialEq for StrokeDash {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
 impl std::ops::Deref for StrokeDash {
     type Target = [f64];
     fn deref(&self) -> &Self::Target {
         self.alloc.as_deref().unwrap_or(self.slice)
     }
 }
+
+/// A [`StrokeStyle`] registered for repeated use.
+///
+/// Converting a [`StrokeStyle`] into a backend's own representation can mean
+/// allocating; a handle lets the backend do that once and keep the result,
+/// using the handle's [`id`] to find it again. It is created by
+/// [`RenderContext::register_stroke_style`] and used with
+/// [`RenderContext::stroke_with_handle`].
+///
+/// Cloning a handle is cheap, and clones share the same id.
+///
+/// [`id`]: StrokeStyleHandle::id
+/// [`RenderContext::register_stroke_style`]: crate::RenderContext::register_stroke_style
+/// [`RenderContext::stroke_with_handle`]: crate::RenderContext::stroke_with_handle
+#[derive(Clone, Debug)]
+pub struct StrokeStyleHandle {
+    id: u64,
+    style: Arc<StrokeStyle>,
+}
+
+impl StrokeStyleHandle {
+    /// Create a handle for `style`, with an id that no other handle has.
+    pub fn new(style: StrokeStyle) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        StrokeStyleHandle {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            style: Arc::new(style),
+        }
+    }
+
+    /// A number identifying this handle and its clones.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The style this handle was created for.
+    pub fn style(&self) -> &StrokeStyle {
+        &self.style
+    }
+}
+
+/// A [`Shape`] that has been prepared for repeated drawing.
+///
+/// This pairs the shape's path with a backend-specific representation of its
+/// geometry (for instance an `ID2D1PathGeometry` or a `CGPath`), so that the
+/// backend does not need to rebuild that geometry each time the shape is
+/// drawn. It is created by [`RenderContext::prepare_shape`].
+///
+/// `CachedShape` implements [`Shape`] itself, so it can also be passed to
+/// any method that takes a shape, at the cost of losing the cached geometry.
+///
+/// [`RenderContext::prepare_shape`]: crate::RenderContext::prepare_shape
+#[derive(Clone, Debug)]
+pub struct CachedShape<G> {
+    path: BezPath,
+    bounding_box: Rect,
+    geometry: G,
+}
+
+impl<G> CachedShape<G> {
+    /// Create a new `CachedShape` from a path and its backend geometry.
+    ///
+    /// This is intended for use by backends.
+    pub fn new(path: BezPath, geometry: G) -> Self {
+        let bounding_box = path.bounding_box();
+        CachedShape {
+            path,
+            bounding_box,
+            geometry,
+        }
+    }
+
+    /// The path this shape was built from.
+    pub fn path(&self) -> &BezPath {
+        &self.path
+    }
+
+    /// The backend-specific geometry.
+    pub fn geometry(&self) -> &G {
+        &self.geometry
+    }
+}
+
+impl<G> Shape for CachedShape<G> {
+    type PathElementsIter<'iter>
+        = std::iter::Copied<std::slice::Iter<'iter, PathEl>>
+    where
+        G: 'iter;
+
+    fn path_elements(&self, _tolerance: f64) -> Self::PathElementsIter<'_> {
+        self.path.elements().iter().copied()
+    }
+
+    fn to_path(&self, _tolerance: f64) -> BezPath {
+        self.path.clone()
+    }
+
+    fn area(&self) -> f64 {
+        self.path.area()
+    }
+
+    fn perimeter(&self, accuracy: f64) -> f64 {
+        self.path.perimeter(accuracy)
+    }
+
+    fn winding(&self, pt: kurbo::Point) -> i32 {
+        self.path.winding(pt)
+    }
+
+    fn bounding_box(&self) -> Rect {
+        self.bounding_box
+    }
+
+    fn as_path_slice(&self) -> Option<&[PathEl]> {
+        Some(self.path.elements())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn stroke_style_handle_ids() {
+        use super::*;
+
+        let style = StrokeStyle::new().dash_pattern(&[4.0, 2.0]);
+        let handle = StrokeStyleHandle::new(style.clone());
+        let other = StrokeStyleHandle::new(style.clone());
+        assert_eq!(handle.clone().id(), handle.id());
+        assert_ne!(handle.id(), other.id());
+        assert_eq!(handle.style(), &style);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn stroke_style_serde() {
+        use super::*;
+
+        let style = StrokeStyle::new()
+            .line_join(LineJoin::Round)
+            .dash_pattern(&[4.0, 2.0])
+            .dash_offset(1.0);
+        let json = serde_json::to_string(&style).unwrap();
+        assert_eq!(
+            json,
+            r#"{"line_join":"Round","line_cap":"Butt","dash_pattern":[4.0,2.0],"dash_offset":1.0}"#
+        );
+        assert_eq!(serde_json::from_str::<StrokeStyle>(&json).unwrap(), style);
+
+        let partial: StrokeStyle = serde_json::from_str(r#"{"line_cap":"Round"}"#).unwrap();
+        assert_eq!(partial, StrokeStyle::new().line_cap(LineCap::Round));
+    }
+}
@@ -0,0 +1,525 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! A render context that checks that saves and restores are balanced.
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::panic::Location;
+
+use kurbo::{Affine, Insets, Point, Rect, Shape};
+
+use crate::{
+    Color, ColorSpace, Error, FixedGradient, ImageBuf, ImageFormat, InterpolationMode, IntoBrush,
+    RenderContext, StrokeStyle, StrokeStyleHandle, TileMode,
+};
+
+/// A render context that keeps track of the states saved with it, and passes
+/// the drawing on to another context.
+///
+/// A [`save`] without a matching [`restore`] is easy to write and hard to
+/// find: the drawing after it is clipped or transformed in ways it shouldn't
+/// be, and some backends panic when the context is dropped. This records
+/// where each state was saved, and [`finish`] panics if any of them were
+/// not restored, with the place each of them was saved, and its label if
+/// it was saved with [`save_labeled`].
+///
+/// It is meant for debugging, and costs a little on every save.
+///
+/// ```should_panic
+/// use piet::{NullRenderContext, RenderContext, StackChecker};
+///
+/// let mut ctx = NullRenderContext::new();
+/// let mut checker = StackChecker::new(&mut ctx);
+/// checker.save_labeled("header").unwrap();
+/// // ...draw the header, and forget to restore
+/// checker.finish().unwrap(); // panics, naming the "header" save
+/// ```
+///
+/// [`save`]: RenderContext::save
+/// [`restore`]: RenderContext::restore
+/// [`finish`]: RenderContext::finish
+/// [`save_labeled`]: StackChecker::save_labeled
+pub struct StackChecker<'a, R: RenderContext> {
+    inner: &'a mut R,
+    saves: Vec<Save>,
+}
+
+/// A saved state that has not been restored yet.
+struct Save {
+    label: Option<Cow<'static, str>>,
+    location: &'static Location<'static>,
+}
+
+/// The brush type of a [`StackChecker`], wrapping a brush of the inner
+/// context.
+pub struct CheckedBrush<R: RenderContext>(R::Brush);
+
+impl<'a, R: RenderContext> StackChecker<'a, R> {
+    /// Start checking drawing done with `inner`.
+    pub fn new(inner: &'a mut R) -> Self {
+        StackChecker {
+            inner,
+            saves: Vec::new(),
+        }
+    }
+
+    /// Save the context state, as [`save`] does, with a label that is shown
+    /// if it is never restored.
+    ///
+    /// [`save`]: RenderContext::save
+    #[track_caller]
+    pub fn save_labeled(&mut self, label: impl Into<Cow<'static, str>>) -> Result<(), Error> {
+        self.push(Some(label.into()), Location::caller())
+    }
+
+    /// The number of saved states that have not been restored.
+    pub fn depth(&self) -> usize {
+        self.saves.len()
+    }
+
+    /// Panic if any saved states have not been restored.
+    ///
+    /// The message lists each of them, innermost first, with where it was
+    /// saved. This is called by [`finish`].
+    ///
+    /// [`finish`]: RenderContext::finish
+    #[track_caller]
+    pub fn assert_balanced(&self) {
+        if self.saves.is_empty() {
+            return;
+        }
+        let mut msg = format!("{} saved state(s) not restored:", self.saves.len());
+        for save in self.saves.iter().rev() {
+            let _ = write!(msg, "\n    saved at {}", save.location);
+            if let Some(label) = &save.label {
+                let _ = write!(msg, " ({label:?})");
+            }
+        }
+        panic!("{msg}");
+    }
+
+    /// The wrapped context.
+    pub fn inner(&mut self) -> &mut R {
+        self.inner
+    }
+
+    fn push(
+        &mut self,
+        label: Option<Cow<'static, str>>,
+        location: &'static Location<'static>,
+    ) -> Result<(), Error> {
+        self.inner.save()?;
+        self.saves.push(Save { label, location });
+        Ok(())
+    }
+}
+
+impl<R: RenderContext> Clone for CheckedBrush<R> {
+    fn clone(&self) -> Self {
+        CheckedBrush(self.0.clone())
+    }
+}
+
+impl<'a, R: RenderContext> IntoBrush<StackChecker<'a, R>> for CheckedBrush<R> {
+    fn make_brush<'b>(
+        &'b self,
+        _piet: &mut StackChecker<'a, R>,
+        _bbox: impl FnOnce() -> Rect,
+    ) -> Cow<'b, CheckedBrush<R>> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<'a, R: RenderContext> RenderContext for StackChecker<'a, R> {
+    type Brush = CheckedBrush<R>;
+    type Text = R::Text;
+    type TextLayout = R::TextLayout;
+    type Image = R::Image;
+    type CachedShape = R::CachedShape;
+
+    fn status(&mut self) -> Result<(), Error> {
+        self.inner.status()
+    }
+
+    fn solid_brush(&mut self, color: Color) -> Self::Brush {
+        CheckedBrush(self.inner.solid_brush(color))
+    }
+
+    fn solid_brush_with_color_space(
+        &mut self,
+        color: Color,
+        color_space: ColorSpace,
+    ) -> Self::Brush {
+        CheckedBrush(self.inner.solid_brush_with_color_space(color, color_space))
+    }
+
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
+        self.inner.gradient(gradient).map(CheckedBrush)
+    }
+
+    fn gradient_with_transform(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        transform: Affine,
+    ) -> Result<Self::Brush, Error> {
+        self.inner
+            .gradient_with_transform(gradient, transform)
+            .map(CheckedBrush)
+    }
+
+    fn brush_with_opacity(&mut self, brush: &Self::Brush, opacity: f64) -> Self::Brush {
+        CheckedBrush(self.inner.brush_with_opacity(&brush.0, opacity))
+    }
+
+    fn clear(&mut self, region: impl Into<Option<Rect>>, color: Color) {
+        self.inner.clear(region, color);
+    }
+
+    fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.stroke(shape, &brush.0, width);
+    }
+
+    fn stroke_styled(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.stroke_styled(shape, &brush.0, width, style);
+    }
+
+    fn register_stroke_style(&mut self, style: &StrokeStyle) -> StrokeStyleHandle {
+        self.inner.register_stroke_style(style)
+    }
+
+    fn stroke_with_handle(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyleHandle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.stroke_with_handle(shape, &brush.0, width, style);
+    }
+
+    fn stroke_hairline(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.stroke_hairline(shape, &brush.0);
+    }
+
+    fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.fill(shape, &brush.0);
+    }
+
+    fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.fill_even_odd(shape, &brush.0);
+    }
+
+    fn fill_mesh(&mut self, vertices: &[(Point, Color)], indices: &[u16]) {
+        self.inner.fill_mesh(vertices, indices);
+    }
+
+    fn clip(&mut self, shape: impl Shape) {
+        self.inner.clip(shape);
+    }
+
+    fn clip_even_odd(&mut self, shape: impl Shape) {
+        self.inner.clip_even_odd(shape);
+    }
+
+    fn prepare_shape(&mut self, shape: impl Shape) -> Result<Self::CachedShape, Error> {
+        self.inner.prepare_shape(shape)
+    }
+
+    fn fill_cached(&mut self, shape: &Self::CachedShape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.fill_cached(shape, &brush.0);
+    }
+
+    fn stroke_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner.stroke_cached(shape, &brush.0, width);
+    }
+
+    fn stroke_styled_cached(
+        &mut self,
+        shape: &Self::CachedShape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+        self.inner
+            .stroke_styled_cached(shape, &brush.0, width, style);
+    }
+
+    fn clip_cached(&mut self, shape: &Self::CachedShape) {
+        self.inner.clip_cached(shape);
+    }
+
+    fn text(&mut self) -> &mut Self::Text {
+        self.inner.text()
+    }
+
+    fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        self.inner.draw_text(layout, pos);
+    }
+
+    #[track_caller]
+    fn save(&mut self) -> Result<(), Error> {
+        self.push(None, Location::caller())
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        if self.saves.pop().is_none() {
+            return Err(Error::StackUnbalance);
+        }
+        self.inner.restore()
+    }
+
+    // These save directly, so that the save is recorded where they are
+    // called rather than in the default implementations.
+    #[track_caller]
+    fn with_save(&mut self, f: impl FnOnce(&mut Self) -> Result<(), Error>) -> Result<(), Error> {
+        self.push(None, Location::caller())?;
+        let result = f(self);
+        Error::scoped(result, self.restore())
+    }
+
+    #[track_caller]
+    fn with_clip(
+        &mut self,
+        shape: impl Shape,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.push(None, Location::caller())?;
+        self.clip(shape);
+        let result = f(self);
+        Error::scoped(result, self.restore())
+    }
+
+    #[track_caller]
+    fn with_transform(
+        &mut self,
+        transform: Affine,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.push(None, Location::caller())?;
+        self.transform(transform);
+        let result = f(self);
+        Error::scoped(result, self.restore())
+    }
+
+    #[track_caller]
+    fn finish(&mut self) -> Result<(), Error> {
+        self.assert_balanced();
+        self.inner.finish()
+    }
+
+    fn transform(&mut self, transform: Affine) {
+        self.inner.transform(transform);
+    }
+
+    fn make_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        self.inner.make_image(width, height, buf, format)
+    }
+
+    fn make_image_with_stride(
+        &mut self,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        self.inner
+            .make_image_with_stride(width, height, stride, buf, format)
+    }
+
+    fn make_image_with_color_space(
+        &mut self,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buf: &[u8],
+        format: ImageFormat,
+        color_space: ColorSpace,
+    ) -> Result<Self::Image, Error> {
+        self.inner
+            .make_image_with_color_space(width, height, stride, buf, format, color_space)
+    }
+
+    fn upload_image(&mut self, buf: &ImageBuf) -> Result<Self::Image, Error> {
+        self.inner.upload_image(buf)
+    }
+
+    fn update_image(
+        &mut self,
+        image: &mut Self::Image,
+        buf: &[u8],
+        format: ImageFormat,
+        region: Option<Rect>,
+    ) -> Result<(), Error> {
+        self.inner.update_image(image, buf, format, region)
+    }
+
+    fn draw_image(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        self.inner.draw_image(image, dst_rect, interp);
+    }
+
+    fn draw_image_area(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        self.inner
+            .draw_image_area(image, src_rect, dst_rect, interp);
+    }
+
+    fn draw_image_area_with_opacity(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        opacity: f64,
+    ) {
+        self.inner
+            .draw_image_area_with_opacity(image, src_rect, dst_rect, interp, opacity);
+    }
+
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        src_insets: impl Into<Insets>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        self.inner
+            .draw_image_nine(image, src_insets, dst_rect, interp);
+    }
+
+    fn draw_image_batch(
+        &mut self,
+        image: &Self::Image,
+        rects: &[(Rect, Rect)],
+        interp: InterpolationMode,
+    ) {
+        self.inner.draw_image_batch(image, rects, interp);
+    }
+
+    fn draw_image_tiled(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        tile_mode: TileMode,
+        interp: InterpolationMode,
+    ) {
+        self.inner
+            .draw_image_tiled(image, dst_rect, tile_mode, interp);
+    }
+
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        self.inner.capture_image_area(src_rect)
+    }
+
+    fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || rect);
+        self.inner.blurred_rect(rect, blur_radius, &brush.0);
+    }
+
+    fn annotate_link(&mut self, shape: impl Shape, url: &str) {
+        self.inner.annotate_link(shape, url);
+    }
+
+    fn current_transform(&self) -> Affine {
+        self.inner.current_transform()
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.inner.scale_factor()
+    }
+
+    fn snap_rect(&self, rect: Rect) -> Rect {
+        self.inner.snap_rect(rect)
+    }
+
+    fn snap_point(&self, point: Point) -> Point {
+        self.inner.snap_point(point)
+    }
+
+    fn backend_handle(&self) -> Option<&dyn Any> {
+        self.inner.backend_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NullRenderContext;
+
+    #[test]
+    fn balanced_stack_finishes() {
+        let mut ctx = NullRenderContext::new();
+        let mut checker = StackChecker::new(&mut ctx);
+        checker.save_labeled("outer").unwrap();
+        checker
+            .with_clip(Rect::new(0.0, 0.0, 10.0, 10.0), |rc| {
+                assert_eq!(rc.depth(), 2);
+                Ok(())
+            })
+            .unwrap();
+        checker.restore().unwrap();
+        assert_eq!(checker.depth(), 0);
+        assert!(checker.restore().is_err());
+        checker.finish().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "stack_checker.rs")]
+    fn scoped_saves_are_recorded_at_the_caller() {
+        let mut ctx = NullRenderContext::new();
+        let mut checker = StackChecker::new(&mut ctx);
+        let _ = checker.with_transform(Affine::scale(2.0), |rc| {
+            rc.assert_balanced();
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "(\"header\")")]
+    fn unbalanced_stack_names_the_save() {
+        let mut ctx = NullRenderContext::new();
+        let mut checker = StackChecker::new(&mut ctx);
+        checker.save().unwrap();
+        checker.restore().unwrap();
+        checker.save_labeled("header").unwrap();
+        let _ = checker.finish();
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright 2026 the Piet Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Drawing SVG documents parsed by [`usvg`].
+//!
+//! usvg resolves styles, units, `use` elements and text layout, leaving a
+//! tree of groups and paths that maps onto piet's drawing calls. What piet
+//! can't express is approximated or skipped; see [`RenderContext::draw_svg`].
+
+use kurbo::{Affine, BezPath, Point};
+use usvg::tiny_skia_path::{self, PathSegment};
+
+use crate::{
+    Color, Error, FixedGradient, FixedLinearGradient, FixedRadialGradient, GradientStop, LineCap,
+    LineJoin, RenderContext, StrokeStyle,
+};
+
+/// Draw `tree` with `rc`, with the top left of the document at the origin.
+pub(crate) fn draw<R: RenderContext + ?Sized>(rc: &mut R, tree: &usvg::Tree) -> Result<(), Error> {
+    draw_group(rc, tree.root(), 1.0)
+}
+
+fn draw_group<R: RenderContext + ?Sized>(
+    rc: &mut R,
+    group: &usvg::Group,
+    opacity: f64,
+) -> Result<(), Error> {
+    // piet has no layers, so group opacity is applied to each child instead,
+    // which differs where the children overlap.
+    let opacity = opacity * group.opacity().get() as f64;
+    if opacity == 0.0 {
+        return Ok(());
+    }
+    rc.save()?;
+    rc.transform(to_affine(group.transform()));
+    if let Some(clip_path) = group.clip_path() {
+        clip(rc, clip_path);
+    }
+    let result = group
+        .children()
+        .iter()
+        .try_for_each(|node| draw_node(rc, node, opacity));
+    rc.restore()?;
+    result
+}
+
+fn draw_node<R: RenderContext + ?Sized>(
+    rc: &mut R,
+    node: &usvg::Node,
+    opacity: f64,
+) -> Result<(), Error> {
+    match node {
+        usvg::Node::Group(group) => draw_group(rc, group, opacity),
+        usvg::Node::Path(path) => draw_path(rc, path, opacity),
+        usvg::Node::Image(image) => draw_svg_image(rc, image, opacity),
+        // usvg lays text out and converts the glyphs to paths
+        usvg::Node::Text(text) => draw_group(rc, text.flattened(), opacity),
+    }
+}
+
+fn draw_path<R: RenderContext + ?Sized>(
+    rc: &mut R,
+    path: &usvg::Path,
+    opacity: f64,
+) -> Result<(), Error> {
+    if !path.is_visible() {
+        return Ok(());
+    }
+    let shape = to_bez_path(path.data());
+    if path.paint_order() == usvg::PaintOrder::StrokeAndFill {
+        stroke(rc, &shape, path.stroke(), opacity)?;
+        fill(rc, &shape, path.fill(), opacity)
+    } else {
+        fill(rc, &shape, path.fill(), opacity)?;
+        stroke(rc, &shape, path.stroke(), opacity)
+    }
+}
+
+fn fill<R: RenderContext + ?Sized>(
+    rc: &mut R,
+    shape: &BezPath,
+    fill: Option<&usvg::Fill>,
+    opacity: f64,
+) -> Result<(), Error> {
+    let Some(fill) = fill else {
+        return Ok(());
+    };
+    let opacity = opacity * fill.opacity().get() as f64;
+    let Some(brush) = brush(rc, fill.paint(), opacity)? else {
+        return Ok(());
+    };
+    match fill.rule() {
+        usvg::FillRule::NonZero => rc.fill(shape, &brush),
+        usvg::FillRule::EvenOdd => rc.fill_even_odd(shape, &brush),
+    }
+    Ok(())
+}
+
+fn stroke<R: RenderContext + ?Sized>(
+    rc: &mut R,
+    shape: &BezPath,
+    stroke: Option<&usvg::Stroke>,
+    opacity: f64,
+) -> Result<(), Error> {
+    let Some(stroke) = stroke else {
+        return Ok(());
+    };
+    let opacity = opacity * stroke.opacity().get() as f64;
+    let Some(brush) = brush(rc, stroke.paint(), opacity)? else {
+        return Ok(());
+    };
+    let mut style = StrokeStyle::new();
+    style.set_line_join(match stroke.linejoin() {
+        usvg::LineJoin::Miter | usvg::LineJoin::MiterClip => LineJoin::Miter {
+            limit: stroke.miterlimit().get() as f64,
+        },
+        usvg::LineJoin::Round => LineJoin::Round,
+        usvg::LineJoin::Bevel => LineJoin::Bevel,
+    });
+    style.set_line_cap(match stroke.linecap() {
+        usvg::LineCap::Butt => LineCap::Butt,
+        usvg::LineCap::Round => LineCap::Round,
+        usvg::LineCap::Square => LineCap::Square,
+    });
+    if let Some(dashes) = stroke.dasharray() {
+        style.set_dash_pattern(dashes.iter().map(|&d| d as f64).collect::<Vec<_>>());
+        style.set_dash_offset(stroke.dashoffset() as f64);
+    }
+    rc.stroke_styled(shape, &brush, stroke.width().get() as f64, &style);
+    Ok(())
+}
+
+/// Returns the brush for `paint`, or `None` for paints piet can't draw.
+fn brush<R: RenderContext + ?Sized>(
+    rc: &mut R,
+    paint: &usvg::Paint,
+    opacity: f64,
+) -> Result<Option<R::Brush>, Error> {
+    // usvg resolves gradients to user space, so only their own transform is
+    // left to apply. Reflected and repeated gradients are padded instead.
+    let (gradient, transform) = match paint {
+        usvg::Paint::Color(color) => {
+            return Ok(Some(rc.solid_brush(to_color(*color, opacity))));
+        }
+        usvg::Paint::LinearGradient(linear) => {
+            let gradient = FixedLinearGradient {
+                start: Point::new(linear.x1() as f64, linear.y1() as f64),
+                end: Point::new(linear.x2() as f64, linear.y2() as f64),
+                stops: to_stops(linear.stops(), opacity),
+            };
+            (FixedGradient::Linear(gradient), linear.transform())
+        }
+        usvg::Paint::RadialGradient(radial) => {
+            let center = Point::new(radial.cx() as f64, radial.cy() as f64);
+            let focus = Point::new(radial.fx() as f64, radial.fy() as f64);
+            let gradient = FixedRadialGradient {
+                center,
+                origin_offset: focus - center,
+                radius: radial.r().get() as f64,
+                start_radius: 0.0,
+                stops: to_stops(radial.stops(), opacity),
+            };
+            (FixedGradient::Radial(gradient), radial.transform())
+        }
+        usvg::Paint::Pattern(_) => return Ok(None),
+    };
+    let brush = if transform.is_identity() {
+        rc.gradient(gradient)?
+    } else {
+        rc.gradient_with_transform(gradient, to_affine(transform))?
+    };
+    Ok(Some(brush))
+}
+
+/// Clip to the union of the shapes in `clip_path`.
+///
+/// piet clips with a single fill rule, so the paths of a clip are joined
+/// into one, clipped even-odd only if all of them ask for it.
+fn clip<R: RenderContext + ?Sized>(rc: &mut R, clip_path: &usvg::ClipPath) {
+    if let Some(outer) = clip_path.clip_path() {
+        clip(rc, outer);
+    }
+    let mut shape = BezPath::new();
+    let mut even_odd = true;
+    add_clip_group(
+        &mut shape,
+        &mut even_odd,
+        clip_path.root(),
+        to_affine(clip_path.transform()),
+    );
+    if even_odd && !shape.elements().is_empty() {
+        rc.clip_even_odd(shape);
+    } else {
+        rc.clip(shape);
+    }
+}
+
+fn add_clip_group(
+    shape: &mut BezPath,
+    even_odd: &mut bool,
+    group: &usvg::Group,
+    transform: Affine,
+) {
+    let transform = transform * to_affine(group.transform());
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(group) => add_clip_group(shape, even_odd, group, transform),
+            usvg::Node::Path(path) if path.is_visible() => {
+                let rule = path.fill().map(usvg::Fill::rule);
+                *even_odd &= rule == Some(usvg::FillRule::EvenOdd);
+                shape.extend(transform * to_bez_path(path.data()));
+            }
+            usvg::Node::Text(text) => add_clip_group(shape, even_odd, text.flattened(), transform),
+            _ => (),
+        }
+    }
+}
+
+/// Draw an image element, at its size with its top left at the origin.
+fn draw_svg_image<R: RenderContext + ?Sized>(
+    rc: &mut R,
+    image: &usvg::Image,
+    opacity: f64,
+) -> Result<(), Error> {
+    if !image.is_visible() {
+        return Ok(());
+    }
+    match image.kind() {
+        usvg::ImageKind::SVG(tree) => draw_group(rc, tree.root(), opacity),
+        #[cfg(feature = "image")]
+        usvg::ImageKind::JPEG(data)
+        | usvg::ImageKind::PNG(data)
+        | usvg::ImageKind::GIF(data)
+        | usvg::ImageKind::WEBP(data) => {
+            // formats whose features aren't enabled are skipped
+            let Ok(buf) = crate::ImageBuf::from_data(data) else {
+                return Ok(());
+            };
+            let image_ref = rc.upload_image(&buf)?;
+            let size = image.size();
+            let rect = kurbo::Rect::new(0.0, 0.0, size.width() as f64, size.height() as f64);
+            let interp = match image.rendering_mode() {
+                usvg::ImageRendering::OptimizeQuality => crate::InterpolationMode::Bilinear,
+                usvg::ImageRendering::OptimizeSpeed => crate::InterpolationMode::NearestNeighbor,
+            };
+            let src_rect = kurbo::Rect::from_origin_size(Point::ZERO, buf.size());
+            rc.draw_image_area_with_opacity(&image_ref, src_rect, rect, interp, opacity);
+            Ok(())
+        }
+        #[cfg(not(feature = "image"))]
+        _ => Ok(()),
+    }
+}
+
+fn to_bez_path(path: &tiny_skia_path::Path) -> BezPath {
+    let point = |p: tiny_skia_path::Point| Point::new(p.x as f64, p.y as f64);
+    let mut bez_path = BezPath::new();
+    for segment in path.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => bez_path.move_to(point(p)),
+            PathSegment::LineTo(p) => bez_path.line_to(point(p)),
+            PathSegment::QuadTo(p1, p2) => bez_path.quad_to(point(p1), point(p2)),
+            PathSegment::CubicTo(p1, p2, p3) => {
+                bez_path.curve_to(point(p1), point(p2), point(p3));
+            }
+            PathSegment::Close => bez_path.close_path(),
+        }
+    }
+    bez_path
+}
+
+fn to_affine(ts: usvg::Transform) -> Affine {
+    Affine::new([ts.sx, ts.ky, ts.kx, ts.sy, ts.tx, ts.ty].map(f64::from))
+}
+
+fn to_color(color: usvg::Color, opacity: f64) -> Color {
+    Color::rgb8(color.red, color.green, color.blue).with_alpha(opacity)
+}
+
+fn to_stops(stops: &[usvg::Stop], opacity: f64) -> Vec<GradientStop> {
+    stops
+        .iter()
+        .map(|stop| GradientStop {
+            pos: stop.offset().get(),
+            color: to_color(stop.color(), opacity * stop.opacity().get() as f64),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kurbo::Rect;
+    use crate::{BoundsTracker, NullRenderContext};
+
+    fn bounds_of(svg: &str) -> Option<Rect> {
+        let tree = usvg::Tree::from_str(svg, &usvg::Options::default()).unwrap();
+        let mut ctx = NullRenderContext::new();
+        let mut tracker = BoundsTracker::new(&mut ctx);
+        tracker.draw_svg(&tree).unwrap();
+        tracker.bounds()
+    }
+
+    #[test]
+    fn groups_are_transformed() {
+        let bounds = bounds_of(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+                <g transform="translate(10 20) scale(2)">
+                    <rect width="10" height="5" fill="red" stroke="blue" stroke-linejoin="round"/>
+                </g>
+                <rect x="50" y="50" width="10" height="10" fill="none"/>
+                <rect x="50" y="50" width="10" height="10" fill="red" opacity="0"/>
+            </svg>"#,
+        );
+        assert_eq!(bounds, Some(Rect::new(9.0, 19.0, 31.0, 31.0)));
+    }
+
+    #[test]
+    fn clip_paths_are_applied() {
+        let bounds = bounds_of(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+                <clipPath id="clip">
+                    <rect x="10" y="10" width="20" height="20"/>
+                </clipPath>
+                <linearGradient id="fade">
+                    <stop offset="0" stop-color="red"/>
+                    <stop offset="1" stop-color="blue" stop-opacity="0.5"/>
+                </linearGradient>
+                <circle cx="30" cy="30" r="25" fill="url(#fade)" clip-path="url(#clip)"/>
+            </svg>"##,
+        );
+        assert_eq!(bounds, Some(Rect::new(10.0, 10.0, 30.0, 30.0)));
+    }
+
+    #[test]
+    fn transforms_convert() {
+        let ts = usvg::Transform::from_row(1.0, 2.0, 3.0, 4.0, 5.0, 6.0);
+        let affine = to_affine(ts);
+        let mut p = tiny_skia_path::Point::from_xy(7.0, 8.0);
+        ts.map_point(&mut p);
+        assert_eq!(
+            affine * Point::new(7.0, 8.0),
+            Point::new(p.x as f64, p.y as f64)
+        );
+    }
+}
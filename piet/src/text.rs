@@ -5,8 +5,10 @@
 
 use std::ops::{Range, RangeBounds};
 
+use unicode_segmentation::UnicodeSegmentation;
+
 use crate::kurbo::{Point, Rect, Size};
-use crate::{Color, Error, FontFamily, FontStyle, FontWeight};
+use crate::{Color, Error, FontDescription, FontFamily, FontFeature, FontStyle, FontWeight};
 
 /// The Piet text API.
 ///
@@ -36,6 +38,31 @@ pub trait Text: Clone {
     /// ```
     fn font_family(&mut self, family_name: &str) -> Option<FontFamily>;
 
+    /// Return the first available font family from a list of preferences.
+    ///
+    /// Each entry is either a family name, which is looked up as with
+    /// [`font_family`], or a CSS generic family name such as `"monospace"`,
+    /// which always matches. If nothing matches, this returns
+    /// [`FontFamily::SYSTEM_UI`]; ending the list with a generic family
+    /// gives a better fallback.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use piet::*;
+    /// # let mut ctx = NullRenderContext::new();
+    /// # let text = ctx.text();
+    /// let code_font = text.best_family(&["Fira Code", "Menlo", "Consolas", "monospace"]);
+    /// ```
+    ///
+    /// [`font_family`]: Text::font_family
+    fn best_family(&mut self, families: &[&str]) -> FontFamily {
+        families
+            .iter()
+            .find_map(|name| FontFamily::generic(name).or_else(|| self.font_family(name)))
+            .unwrap_or_default()
+    }
+
     /// Load the provided font data and make it available for use.
     ///
     /// This method takes font data (such as the contents of a file on disk) and
@@ -86,6 +113,45 @@ pub trait Text: Clone {
     /// ```
     fn load_font(&mut self, data: &[u8]) -> Result<FontFamily, Error>;
 
+    /// Load several fonts from the same family, such as its regular, bold
+    /// and italic variants, and return the family.
+    ///
+    /// This is equivalent to calling [`load_font`] with each font in turn; the
+    /// variants can then be selected with [`FontWeight`] and [`FontStyle`]
+    /// attributes. It is an error if `fonts` is empty or the fonts belong to
+    /// different families; in that case, or if a font fails to load, fonts
+    /// loaded before the failure remain loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use piet::*;
+    /// # let mut ctx = NullRenderContext::new();
+    /// # let text = ctx.text();
+    /// # fn get_font_data(name: &str) -> Vec<u8> { Vec::new() }
+    /// let regular = get_font_data("Helvetica-Regular");
+    /// let bold = get_font_data("Helvetica-Bold");
+    ///
+    /// let helvetica = text.load_font_collection(&[&regular, &bold]).unwrap();
+    /// let layout = text.new_text_layout("Custom Fonts")
+    ///     .font(helvetica, 12.0)
+    ///     .range_attribute(6.., FontWeight::BOLD);
+    /// ```
+    ///
+    /// [`load_font`]: Text::load_font
+    fn load_font_collection(&mut self, fonts: &[&[u8]]) -> Result<FontFamily, Error> {
+        let mut family: Option<FontFamily> = None;
+        for data in fonts {
+            let loaded = self.load_font(data)?;
+            match &family {
+                Some(family) if family != &loaded => return Err(Error::InvalidInput),
+                Some(_) => (),
+                None => family = Some(loaded),
+            }
+        }
+        family.ok_or(Error::InvalidInput)
+    }
+
     /// Create a new layout object to display the provided `text`.
     ///
     /// The returned object is a [`TextLayoutBuilder`]; methods on that type
@@ -132,7 +198,7 @@ impl std::ops::Deref for dyn TextStorage {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// Attributes that can be applied to text.
 pub enum TextAttribute {
     /// The font family.
@@ -149,6 +215,36 @@ pub enum TextAttribute {
     Underline(bool),
     /// Strikethrough.
     Strikethrough(bool),
+    /// The color of underlines; by default this is the text color.
+    UnderlineColor(crate::Color),
+    /// The [`UnderlineStyle`] used to draw underlines.
+    UnderlineStyle(UnderlineStyle),
+    /// The thickness of underlines, in points; by default this comes from
+    /// the font.
+    UnderlineThickness(f64),
+    /// Whether underlines leave gaps where they would cross the glyphs, as
+    /// with descenders; by default they do, as in browsers.
+    ///
+    /// This is not supported by the Direct2D and web backends.
+    UnderlineSkipInk(bool),
+    /// A vertical offset from the baseline, in points. Positive values raise
+    /// the text, as for superscripts, and negative values lower it.
+    ///
+    /// This is not supported by the Direct2D and web backends.
+    BaselineOffset(f64),
+    /// OpenType [`FontFeature`] settings.
+    ///
+    /// This is not supported by the web backend.
+    FontFeatures(Vec<FontFeature>),
+    /// Marks the text as a link to a URL.
+    ///
+    /// This doesn't change how the text looks, so style links with other
+    /// attributes, such as [`Underline`]. The layout records the links, for
+    /// handling hovers and clicks with [`TextLayout::links`] and
+    /// [`TextLayout::link_at_point`].
+    ///
+    /// [`Underline`]: TextAttribute::Underline
+    Link(String),
 }
 
 /// A trait for laying out text.
@@ -168,6 +264,32 @@ pub trait TextLayoutBuilder: Sized {
     /// Set the [`TextAlignment`] to be used for this layout.
     fn alignment(self, alignment: TextAlignment) -> Self;
 
+    /// Set the locale used to shape this layout, as a [BCP 47] language tag
+    /// such as `"tr"` or `"zh-Hant"`.
+    ///
+    /// The locale affects things like case mapping and which glyph variants
+    /// are chosen for CJK text. If it is not set, the system locale is used.
+    ///
+    /// The default implementation does nothing, for backends that can't
+    /// choose the locale of a layout.
+    ///
+    /// [BCP 47]: https://www.rfc-editor.org/info/bcp47
+    fn locale(self, _locale: &str) -> Self {
+        self
+    }
+
+    /// Set the [`TextAntialiasing`] used when this layout is drawn.
+    ///
+    /// Subpixel antialiasing assumes the text is drawn onto an opaque
+    /// background; text drawn into a transparent layer or surface should use
+    /// [`TextAntialiasing::Grayscale`] instead.
+    ///
+    /// The default implementation does nothing, for backends that can't
+    /// choose how text is antialiased.
+    fn antialiasing(self, _antialiasing: TextAntialiasing) -> Self {
+        self
+    }
+
     /// A convenience method for setting the default font family and size.
     ///
     /// # Examples
@@ -265,8 +387,28 @@ pub trait TextLayoutBuilder: Sized {
     fn build(self) -> Result<Self::Out, Error>;
 }
 
+/// How the glyphs of a [`TextLayout`] are antialiased when drawn.
+///
+/// Backends that cannot control this, such as the web canvas, ignore it.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextAntialiasing {
+    /// Whatever the platform or render target would use by default.
+    #[default]
+    Default,
+    /// Antialiased using coverage alone.
+    Grayscale,
+    /// Antialiased using the individual color subpixels of the display, where
+    /// the platform supports it. This is also known as LCD or ClearType
+    /// antialiasing.
+    Subpixel,
+    /// Not antialiased; each pixel is either fully covered or not at all.
+    Aliased,
+}
+
 /// The alignment of text in a [`TextLayout`].
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TextAlignment {
     /// Text is aligned to the left edge in left-to-right scripts, and the
     /// right edge in right-to-left scripts.
@@ -284,6 +426,24 @@ pub enum TextAlignment {
     Justified,
 }
 
+/// The line style of an underline.
+///
+/// Backends whose text engines cannot draw a given style natively draw the
+/// underline themselves, so every style is available everywhere.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnderlineStyle {
+    /// A single solid line.
+    #[default]
+    Solid,
+    /// A line of dots.
+    Dotted,
+    /// A line of dashes.
+    Dashed,
+    /// A wavy line, as used to mark spelling errors.
+    Wavy,
+}
+
 /// A drawable text object.
 ///
 /// ## Line Breaks
@@ -341,6 +501,31 @@ pub trait TextLayout: Clone {
     /// during scrolling) and thus needs to be drawn.
     fn image_bounds(&self) -> Rect;
 
+    /// Return a copy of this layout, wrapped to a new maximum width.
+    ///
+    /// This is the same as building the layout again with a different
+    /// [`max_width`], but is usually much cheaper, since the text does not
+    /// need to be shaped again. Passing `f64::INFINITY` disables wrapping.
+    ///
+    /// This layout is not changed.
+    ///
+    /// [`max_width`]: TextLayoutBuilder::max_width
+    fn with_max_width(&self, width: f64) -> Self;
+
+    /// Return a copy of this layout, with the text in `range` drawn in `color`.
+    ///
+    /// This is the same as building the layout again with an added
+    /// [`TextAttribute::TextColor`] for `range`, but is usually much cheaper,
+    /// which makes it suitable for animating the color or opacity of a span
+    /// of text, such as a cursor or a search match. Unlike other text colors,
+    /// the alpha of `color` is always respected.
+    ///
+    /// Decorations that are drawn by piet rather than by the platform keep
+    /// their original color.
+    ///
+    /// This layout is not changed.
+    fn with_color_override(&self, range: impl RangeBounds<usize>, color: Color) -> Self;
+
     /// The text used to create this layout.
     fn text(&self) -> &str;
 
@@ -363,6 +548,37 @@ pub trait TextLayout: Clone {
     /// string is considered to have a single line.
     fn line_count(&self) -> usize;
 
+    /// Returns the bounds of the line, in the layout's coordinate space.
+    ///
+    /// The rectangle spans the line's text horizontally, without trailing
+    /// whitespace, and covers the line vertically as given by its
+    /// [`LineMetric`]. Its `x0` is where the line starts after alignment, which
+    /// is useful for drawing per-line backgrounds or placing a caret at the
+    /// start of a line.
+    ///
+    /// Returns `None` if the line does not exist.
+    ///
+    /// The default implementation measures the line with
+    /// [`hit_test_text_position`]; backends that know their line positions
+    /// override it.
+    ///
+    /// [`hit_test_text_position`]: TextLayout::hit_test_text_position
+    fn line_bounds(&self, line_number: usize) -> Option<Rect> {
+        let metric = self.line_metric(line_number)?;
+        let end = metric.end_offset - metric.trailing_whitespace;
+        let x0 = self.hit_test_text_position(metric.start_offset).point.x;
+        //HACK: without a way to ask for the upstream position, the end of a
+        //line broken without whitespace is the start of the next line; see
+        //`rects_for_range`.
+        let x1 = if end == metric.end_offset && line_number + 1 < self.line_count() {
+            self.size().width
+        } else {
+            self.hit_test_text_position(end).point.x
+        };
+        let y1 = metric.y_offset + metric.height;
+        Some(Rect::new(x0.min(x1), metric.y_offset, x0.max(x1), y1))
+    }
+
     /// Given a `Point`, return a [`HitTestPoint`] describing the corresponding
     /// text position.
     ///
@@ -449,6 +665,123 @@ pub trait TextLayout: Clone {
         }
         result
     }
+
+    /// Returns every position the caret can be placed at, in text order.
+    ///
+    /// There is one [`CursorPosition`] for each grapheme boundary in each
+    /// line, including the start of the line; the end of the text is included
+    /// as a position on the last line. This is what an editor needs to move
+    /// the caret or snap a click to a position, and it is cheaper than calling
+    /// [`hit_test_text_position`] for each position in turn.
+    ///
+    /// The default implementation calls [`hit_test_text_position`] for each
+    /// position; backends can override it with something faster.
+    ///
+    /// [`hit_test_text_position`]: TextLayout::hit_test_text_position
+    fn cursor_positions(&self) -> Vec<CursorPosition> {
+        let text = self.text();
+        let line_count = self.line_count();
+        let mut result = Vec::new();
+
+        for line in 0..line_count {
+            let metrics = self.line_metric(line).unwrap();
+            let is_last = line + 1 == line_count;
+            let boundaries = text[metrics.range()]
+                .grapheme_indices(true)
+                .map(|(idx, _)| metrics.start_offset + idx)
+                .chain(is_last.then_some(metrics.end_offset));
+            for idx in boundaries {
+                let x = self.hit_test_text_position(idx).point.x;
+                result.push(CursorPosition::new(idx, line, x));
+            }
+        }
+        result
+    }
+
+    /// The fonts used to draw the text, with the range of text each draws.
+    ///
+    /// These include any fallback fonts chosen for characters the requested
+    /// font doesn't have, which is what embedders that draw glyphs themselves,
+    /// such as from a glyph atlas, need to know. The ranges are in text order,
+    /// with adjacent ranges drawn in the same font merged. Text that isn't
+    /// drawn with a font, such as a newline, may not be covered.
+    ///
+    /// The default implementation returns an empty list, for backends that
+    /// can't tell which fonts were used.
+    fn resolved_fonts(&self) -> Vec<(Range<usize>, FontDescription)> {
+        Vec::new()
+    }
+
+    /// The links in the text, set with [`TextAttribute::Link`], with the
+    /// range of text each covers.
+    ///
+    /// The ranges are in text order and don't overlap; where link attributes
+    /// overlap, the one added last wins, as with other attributes.
+    ///
+    /// The default implementation returns an empty list, for backends that
+    /// don't record links.
+    fn links(&self) -> Vec<(Range<usize>, &str)> {
+        Vec::new()
+    }
+
+    /// The areas of the layout covered by links, with the URL of each.
+    ///
+    /// A link that is broken across lines has a rect on each line, as from
+    /// [`rects_for_range`]. These are what an interactive frontend needs to
+    /// draw hover highlights or set the cursor over links.
+    ///
+    /// [`rects_for_range`]: TextLayout::rects_for_range
+    fn link_rects(&self) -> Vec<(Rect, &str)> {
+        self.links()
+            .into_iter()
+            .flat_map(|(range, url)| {
+                self.rects_for_range(range)
+                    .into_iter()
+                    .map(move |rect| (rect, url))
+            })
+            .collect()
+    }
+
+    /// Returns the URL of the link at `point`, if there is one.
+    ///
+    /// `point` is relative to the layout's origin, as with
+    /// [`hit_test_point`].
+    ///
+    /// [`hit_test_point`]: TextLayout::hit_test_point
+    fn link_at_point(&self, point: Point) -> Option<&str> {
+        self.link_rects()
+            .into_iter()
+            .find(|(rect, _)| rect.contains(point))
+            .map(|(_, url)| url)
+    }
+
+    /// Returns the rectangle to draw a caret in, at the text position `idx`.
+    ///
+    /// The caret is [`CARET_WIDTH`] wide, with one edge at the `x` given by
+    /// [`hit_test_text_position`]. It lies on the side of that edge the text
+    /// flows towards at `idx`: to the right of it in left-to-right text and to
+    /// the left of it in right-to-left text, and it is moved inside the
+    /// layout's [`trailing_whitespace_width`] if it would stick out. `height`
+    /// chooses whether it spans the line or the text at the caret; see
+    /// [`CaretHeight`].
+    ///
+    /// The default implementation doesn't know the font at the caret, so it
+    /// spans the line for either [`CaretHeight`]; backends that do know it
+    /// override it.
+    ///
+    /// [`hit_test_text_position`]: TextLayout::hit_test_text_position
+    /// [`trailing_whitespace_width`]: TextLayout::trailing_whitespace_width
+    fn caret_rect(&self, idx: usize, _height: CaretHeight) -> Rect {
+        let position = self.hit_test_text_position(idx);
+        let metric = self.line_metric(position.line).unwrap_or_default();
+        let is_rtl = crate::util::is_rtl_at(self.text(), idx);
+        crate::util::caret_rect(
+            position.point.x,
+            metric.y_offset..metric.y_offset + metric.height,
+            is_rtl,
+            self.trailing_whitespace_width(),
+        )
+    }
 }
 
 /// Metadata about each line in a text layout.
@@ -517,6 +850,44 @@ pub struct HitTestPoint {
     /// end of that line, and a click below the last line will resolve to a
     /// position in that line.
     pub is_inside: bool,
+    /// Whether the point was on the text before `idx` or the text after it.
+    ///
+    /// The end of a soft-wrapped line and the start of the next line are the
+    /// same text position; a click past the end of the first line is
+    /// [`Affinity::Upstream`], so the caret can be drawn on that line rather
+    /// than at the start of the next.
+    pub affinity: Affinity,
+}
+
+/// Which side of a text position something is associated with.
+///
+/// A hit on the trailing half of a grapheme resolves to the position after
+/// it, with upstream affinity; a hit on the leading half resolves to the
+/// position before it, with downstream affinity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Affinity {
+    /// The position belongs to the text before it, as at the end of a line.
+    Upstream,
+    /// The position belongs to the text after it.
+    #[default]
+    Downstream,
+}
+
+/// The width of the rectangle returned by [`TextLayout::caret_rect`].
+pub const CARET_WIDTH: f64 = 1.0;
+
+/// How much of a line a caret covers, for [`TextLayout::caret_rect`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CaretHeight {
+    /// The whole height of the line, as given by its [`LineMetric`], so that
+    /// the carets of adjacent lines meet, and a caret is as tall as a
+    /// selection on the same line.
+    #[default]
+    Line,
+    /// From the ascent to the descent of the font at the caret, so that the
+    /// caret is as tall as the text it is next to, even on a line that also
+    /// has larger text.
+    Glyph,
 }
 
 /// Result of hit testing a text position in a [`TextLayout`].
@@ -539,11 +910,35 @@ pub struct HitTestPosition {
     pub line: usize,
 }
 
+/// A position the caret can be placed at in a [`TextLayout`].
+///
+/// This type is returned by [`TextLayout::cursor_positions`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub struct CursorPosition {
+    /// The text position, which is a grapheme boundary.
+    pub idx: usize,
+    /// The number of the line containing this position.
+    pub line: usize,
+    /// The `x` position of the caret, as in [`HitTestPosition::point`].
+    pub x: f64,
+}
+
 impl HitTestPoint {
     /// Only for use by backends
     #[doc(hidden)]
     pub fn new(idx: usize, is_inside: bool) -> HitTestPoint {
-        HitTestPoint { idx, is_inside }
+        HitTestPoint {
+            idx,
+            is_inside,
+            affinity: Affinity::Downstream,
+        }
+    }
+
+    /// Only for use by backends
+    #[doc(hidden)]
+    pub fn with_affinity(self, affinity: Affinity) -> HitTestPoint {
+        HitTestPoint { affinity, ..self }
     }
 }
 
@@ -555,6 +950,14 @@ impl HitTestPosition {
     }
 }
 
+impl CursorPosition {
+    /// Only for use by backends
+    #[doc(hidden)]
+    pub fn new(idx: usize, line: usize, x: f64) -> CursorPosition {
+        CursorPosition { idx, line, x }
+    }
+}
+
 impl From<FontFamily> for TextAttribute {
     fn from(t: FontFamily) -> TextAttribute {
         TextAttribute::FontFamily(t)
@@ -573,6 +976,18 @@ impl From<FontStyle> for TextAttribute {
     }
 }
 
+impl From<FontFeature> for TextAttribute {
+    fn from(src: FontFeature) -> TextAttribute {
+        TextAttribute::FontFeatures(vec![src])
+    }
+}
+
+impl From<UnderlineStyle> for TextAttribute {
+    fn from(src: UnderlineStyle) -> TextAttribute {
+        TextAttribute::UnderlineStyle(src)
+    }
+}
+
 impl TextStorage for std::sync::Arc<str> {
     fn as_str(&self) -> &str {
         self
@@ -4,11 +4,20 @@
 //! Code useful for multiple backends
 
 use std::ops::{Bound, Range, RangeBounds};
+use std::sync::Arc;
 
-use crate::kurbo::{Rect, Size};
-use crate::{Color, Error, FontFamily, FontStyle, FontWeight, LineMetric, TextAttribute};
+use crate::kurbo::{
+    self, Affine, BezPath, Insets, Line, ParamCurve, PathEl, PathSeg, Point, Rect, Shape, Size,
+    Vec2,
+};
+use crate::{
+    Color, Error, FixedGradient, FontDescription, FontFamily, FontFeature, FontStyle, FontWeight,
+    ImageArgsError, ImageBuf, ImageFormat, LineCap, LineJoin, LineMetric, StrokeStyle,
+    TextAttribute, TextLayout, TileMode, UnderlineStyle, CARET_WIDTH,
+};
 
 use unic_bidi::bidi_class::{BidiClass, BidiClassCategory};
+use unic_bidi::BidiInfo;
 
 /// The default point size for text in piet.
 pub const DEFAULT_FONT_SIZE: f64 = 12.0;
@@ -103,7 +112,7 @@ pub fn resolve_range(range: impl RangeBounds<usize>, len: usize) -> Range<usize>
 }
 
 /// Extent to which to expand the blur.
-const BLUR_EXTENT: f64 = 2.5;
+pub(crate) const BLUR_EXTENT: f64 = 2.5;
 
 /// Calculate the size required paint a blurred rect.
 pub fn size_for_blurred_rect(rect: Rect, radius: f64) -> Size {
@@ -143,6 +152,160 @@ pub fn compute_blurred_rect(rect: Rect, radius: f64, stride: usize, buf: &mut [u
     rect_exp
 }
 
+/// Generate an alpha mask for an arbitrary blurred shape.
+///
+/// The shape is filled with the nonzero rule and blurred with the same
+/// falloff as [`compute_blurred_rect`], approximated by three box blurs.
+///
+/// Returns the mask, one byte per pixel with a stride equal to the width,
+/// along with the rect it covers in the shape's coordinate space.
+pub fn compute_blurred_mask(shape: impl Shape, radius: f64) -> (Vec<u8>, Rect) {
+    let padding = BLUR_EXTENT * radius;
+    let rect_exp = shape.bounding_box().inflate(padding, padding).expand();
+    let width = rect_exp.width() as usize;
+    let height = rect_exp.height() as usize;
+    if width == 0 || height == 0 {
+        return (Vec::new(), rect_exp);
+    }
+
+    let mut mask = rasterize(&shape, rect_exp.origin(), width, height);
+    // Three box blurs of width w have a variance of (w² - 1) / 4, which we
+    // match to the variance of the erf falloff, radius² / 2.
+    let k = (((2.0 * radius * radius + 1.0).sqrt() - 1.0) * 0.5).round() as usize;
+    if k > 0 {
+        let mut line = Vec::with_capacity(width.max(height));
+        for _ in 0..3 {
+            for j in 0..height {
+                box_blur_line(&mut mask, j * width, 1, width, k, &mut line);
+            }
+            for i in 0..width {
+                box_blur_line(&mut mask, i, width, height, k, &mut line);
+            }
+        }
+    }
+    let buf = mask
+        .iter()
+        .map(|a| (a.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    (buf, rect_exp)
+}
+
+/// Compute the coverage of `shape` for each pixel of a `width` by `height`
+/// grid with its top-left corner at `origin`.
+fn rasterize(shape: &impl Shape, origin: Point, width: usize, height: usize) -> Vec<f64> {
+    // Signed area accumulation, as in font-rs; the extra cells catch
+    // coverage to the right of the last pixel.
+    let mut acc = vec![0.0; width * height + 2];
+    let to_local = |p: Point| {
+        Point::new(
+            (p.x - origin.x).clamp(0.0, width as f64),
+            (p.y - origin.y).clamp(0.0, height as f64),
+        )
+    };
+    let mut start = Point::ZERO;
+    let mut last = Point::ZERO;
+    kurbo::flatten(shape.path_elements(0.1), 0.1, |el| match el {
+        PathEl::MoveTo(p) => {
+            accumulate_line(&mut acc, width, height, last, start);
+            start = to_local(p);
+            last = start;
+        }
+        PathEl::LineTo(p) => {
+            let p = to_local(p);
+            accumulate_line(&mut acc, width, height, last, p);
+            last = p;
+        }
+        PathEl::ClosePath => {
+            accumulate_line(&mut acc, width, height, last, start);
+            last = start;
+        }
+        _ => unreachable!("flatten only emits lines"),
+    });
+    accumulate_line(&mut acc, width, height, last, start);
+
+    let mut sum = 0.0;
+    acc.truncate(width * height);
+    for a in &mut acc {
+        sum += *a;
+        *a = f64::abs(sum).min(1.0);
+    }
+    acc
+}
+
+fn accumulate_line(acc: &mut [f64], width: usize, height: usize, p0: Point, p1: Point) {
+    if p0.y == p1.y {
+        return;
+    }
+    let (dir, p0, p1) = if p0.y < p1.y {
+        (1.0, p0, p1)
+    } else {
+        (-1.0, p1, p0)
+    };
+    let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+    let mut x = p0.x;
+    for y in (p0.y as usize)..height.min(p1.y.ceil() as usize) {
+        let line_start = y * width;
+        let dy = ((y + 1) as f64).min(p1.y) - (y as f64).max(p0.y);
+        let x_next = x + dxdy * dy;
+        let d = dy * dir;
+        let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+        let x0_floor = x0.floor();
+        let x0i = x0_floor as usize;
+        let x1_ceil = x1.ceil();
+        let x1i = x1_ceil as usize;
+        if x1i <= x0i + 1 {
+            let xmf = 0.5 * (x + x_next) - x0_floor;
+            acc[line_start + x0i] += d - d * xmf;
+            acc[line_start + x0i + 1] += d * xmf;
+        } else {
+            let s = (x1 - x0).recip();
+            let x0f = x0 - x0_floor;
+            let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+            let x1f = x1 - x1_ceil + 1.0;
+            let am = 0.5 * s * x1f * x1f;
+            acc[line_start + x0i] += d * a0;
+            if x1i == x0i + 2 {
+                acc[line_start + x0i + 1] += d * (1.0 - a0 - am);
+            } else {
+                let a1 = s * (1.5 - x0f);
+                acc[line_start + x0i + 1] += d * (a1 - a0);
+                for xi in x0i + 2..x1i - 1 {
+                    acc[line_start + xi] += d * s;
+                }
+                let a2 = a1 + (x1i - x0i - 3) as f64 * s;
+                acc[line_start + x1i - 1] += d * (1.0 - a2 - am);
+            }
+            acc[line_start + x1i] += d * am;
+        }
+        x = x_next;
+    }
+}
+
+/// Blur `n` values spaced `step` apart with a box of width `2 * k + 1`,
+/// treating values outside the line as zero.
+fn box_blur_line(
+    data: &mut [f64],
+    start: usize,
+    step: usize,
+    n: usize,
+    k: usize,
+    line: &mut Vec<f64>,
+) {
+    line.clear();
+    line.extend((0..n).map(|i| data[start + i * step]));
+    let scale = ((2 * k + 1) as f64).recip();
+    let mut sum: f64 = line[..k.min(n)].iter().sum();
+    for i in 0..n {
+        if i + k < n {
+            sum += line[i + k];
+        }
+        if i > k {
+            sum -= line[i - k - 1];
+        }
+        data[start + i * step] = sum * scale;
+    }
+}
+
 // See https://raphlinus.github.io/audio/2018/09/05/sigmoid.html for a little
 // explanation of this approximation to the erf function.
 fn compute_erf7(x: f64) -> f64 {
@@ -152,6 +315,367 @@ fn compute_erf7(x: f64) -> f64 {
     x / (1.0 + x * x).sqrt()
 }
 
+/// Split the outline of a shape into dashes.
+///
+/// This follows the same rules as [`StrokeStyle::dash_pattern`] and
+/// [`StrokeStyle::dash_offset`]: an odd-length pattern is repeated, and an
+/// empty pattern leaves the shape undashed. Stroking the result with a plain
+/// style gives the same result as stroking the shape with the dashed style,
+/// which is useful where a backend cannot draw dashes itself.
+///
+/// See [`kurbo::dash`] for an iterator-based version.
+///
+/// [`StrokeStyle::dash_pattern`]: crate::StrokeStyle::dash_pattern
+/// [`StrokeStyle::dash_offset`]: crate::StrokeStyle::dash_offset
+pub fn dash_path(shape: impl Shape, pattern: &[f64], offset: f64) -> BezPath {
+    let path = shape.path_elements(1e-3);
+    let mut period: f64 = pattern.iter().sum();
+    if pattern.len() % 2 == 1 {
+        period *= 2.0;
+    }
+    if pattern.iter().any(|d| *d < 0.0) || !period.is_finite() || period <= 0.0 {
+        return path.collect();
+    }
+    // `kurbo::dash` only walks forward through the pattern.
+    let offset = if offset.is_finite() {
+        offset.rem_euclid(period)
+    } else {
+        0.0
+    };
+    kurbo::dash(path, offset, pattern).collect()
+}
+
+/// The outline of a stroke, as a path to be filled with the nonzero rule.
+///
+/// This applies the dashes, caps and joins of `style`, so that filling the
+/// result draws the same thing as stroking `shape` with `style`. It is used
+/// by the default [`RenderContext::stroke_styled`], for backends that can only
+/// fill paths or can't draw some part of a [`StrokeStyle`] themselves.
+///
+/// Curves in the outline are accurate to within a hundredth of a unit, in the
+/// coordinate space of `shape`.
+///
+/// [`RenderContext::stroke_styled`]: crate::RenderContext::stroke_styled
+pub fn expand_stroke(shape: impl Shape, width: f64, style: &StrokeStyle) -> BezPath {
+    const TOLERANCE: f64 = 0.01;
+    let (join, miter_limit) = match style.line_join {
+        LineJoin::Miter { limit } => (kurbo::Join::Miter, limit),
+        LineJoin::Round => (kurbo::Join::Round, LineJoin::DEFAULT_MITER_LIMIT),
+        LineJoin::Bevel => (kurbo::Join::Bevel, LineJoin::DEFAULT_MITER_LIMIT),
+    };
+    let cap = match style.line_cap {
+        LineCap::Butt => kurbo::Cap::Butt,
+        LineCap::Round => kurbo::Cap::Round,
+        LineCap::Square => kurbo::Cap::Square,
+    };
+    let stroke = kurbo::Stroke::new(width.abs())
+        .with_join(join)
+        .with_miter_limit(miter_limit)
+        .with_caps(cap);
+    // dashing here rather than in kurbo treats odd and degenerate patterns
+    // the way the backends do.
+    let dashed = dash_path(shape, &style.dash_pattern, style.dash_offset);
+    kurbo::stroke(dashed, &stroke, &Default::default(), TOLERANCE)
+}
+
+/// Round the corners of a shape's outline.
+///
+/// Each corner where two straight segments meet is replaced by a circular
+/// arc that starts `radius` before the corner and ends `radius` after it,
+/// like [Skia's corner path effect]. Where a segment is too short for that,
+/// the arc is made smaller so that it takes at most half of the segment.
+/// Corners involving curves, and the ends of open subpaths, are left alone.
+///
+/// [Skia's corner path effect]: https://api.skia.org/classSkCornerPathEffect.html
+pub fn round_corners(shape: impl Shape, radius: f64) -> BezPath {
+    let mut result = BezPath::new();
+    let mut segments = Vec::new();
+    let mut start = Point::ZERO;
+    let mut current = Point::ZERO;
+    for el in shape.path_elements(1e-3) {
+        match el {
+            PathEl::MoveTo(p) => {
+                round_subpath(&mut result, &segments, false, radius);
+                segments.clear();
+                start = p;
+                current = p;
+            }
+            PathEl::ClosePath => {
+                if current != start {
+                    segments.push(PathSeg::Line(Line::new(current, start)));
+                }
+                round_subpath(&mut result, &segments, true, radius);
+                segments.clear();
+                current = start;
+            }
+            PathEl::LineTo(p) => {
+                segments.push(PathSeg::Line(Line::new(current, p)));
+                current = p;
+            }
+            PathEl::QuadTo(p1, p2) => {
+                segments.push(PathSeg::Quad(kurbo::QuadBez::new(current, p1, p2)));
+                current = p2;
+            }
+            PathEl::CurveTo(p1, p2, p3) => {
+                segments.push(PathSeg::Cubic(kurbo::CubicBez::new(current, p1, p2, p3)));
+                current = p3;
+            }
+        }
+    }
+    round_subpath(&mut result, &segments, false, radius);
+    result
+}
+
+fn round_subpath(path: &mut BezPath, segments: &[PathSeg], closed: bool, radius: f64) {
+    let Some(first) = segments.first() else {
+        return;
+    };
+    let direction = |seg: &PathSeg| match seg {
+        PathSeg::Line(line) if line.p0 != line.p1 => {
+            let v = line.p1 - line.p0;
+            Some((v / v.hypot(), v.hypot()))
+        }
+        _ => None,
+    };
+    // For each segment, the length and control distance of the rounding at
+    // its end, if any.
+    let corners: Vec<Option<(f64, f64)>> = (0..segments.len())
+        .map(|i| {
+            let next = match segments.get(i + 1) {
+                Some(next) => next,
+                None if closed => first,
+                None => return None,
+            };
+            let (dir_in, len_in) = direction(&segments[i])?;
+            let (dir_out, len_out) = direction(next)?;
+            let angle = dir_in.dot(dir_out).clamp(-1.0, 1.0).acos();
+            let dist = radius.min(len_in / 2.0).min(len_out / 2.0);
+            if radius.is_nan() || dist <= 0.0 || angle < 1e-6 {
+                return None;
+            }
+            // the handle length of a cubic approximating the circular arc
+            let handle = dist * 4.0 / 3.0 * (angle / 4.0).tan() / (angle / 2.0).tan();
+            Some((dist, handle))
+        })
+        .collect();
+    let trim = |i: usize| -> Option<(f64, f64)> {
+        if i == 0 {
+            closed.then(|| corners[segments.len() - 1]).flatten()
+        } else {
+            corners[i - 1]
+        }
+    };
+    let start_of = |i: usize| {
+        let seg = &segments[i];
+        let p0 = seg.start();
+        match (trim(i), direction(seg)) {
+            (Some((dist, _)), Some((dir, _))) => p0 + dir * dist,
+            _ => p0,
+        }
+    };
+
+    path.move_to(start_of(0));
+    for (i, seg) in segments.iter().enumerate() {
+        match seg {
+            PathSeg::Line(line) => match (corners[i], direction(seg)) {
+                (Some((dist, handle)), Some((dir_in, _))) => {
+                    let next = (i + 1) % segments.len();
+                    let (dir_out, _) = direction(&segments[next]).unwrap_or((Vec2::ZERO, 0.0));
+                    let from = line.p1 - dir_in * dist;
+                    let to = line.p1 + dir_out * dist;
+                    path.line_to(from);
+                    path.curve_to(from + dir_in * handle, to - dir_out * handle, to);
+                }
+                _ => path.line_to(line.p1),
+            },
+            PathSeg::Quad(quad) => path.quad_to(quad.p1, quad.p2),
+            PathSeg::Cubic(cubic) => path.curve_to(cubic.p1, cubic.p2, cubic.p3),
+        }
+    }
+    if closed {
+        path.close_path();
+    }
+}
+
+/// The elliptical arc that `shape` is drawn as, if it is one.
+///
+/// kurbo's [`Ellipse`] and [`Arc`] are drawn as a series of cubic Béziers,
+/// and can't be told apart from other shapes. This recognizes those curves,
+/// so that backends with arc primitives can draw them with those instead,
+/// for better quality and fewer path segments. A full ellipse is returned
+/// as an arc with a sweep of exactly `2π`, and [`Circle`]s are recognized
+/// too; draw these as closed paths, so that strokes join at the seam.
+///
+/// Only shapes made of a single subpath of cubic Béziers are checked, with
+/// a closing segment only after a full ellipse; for anything else this
+/// returns `None` quickly.
+///
+/// [`Ellipse`]: kurbo::Ellipse
+/// [`Arc`]: kurbo::Arc
+/// [`Circle`]: kurbo::Circle
+pub fn as_arc(shape: &impl Shape) -> Option<kurbo::Arc> {
+    use std::f64::consts::TAU;
+
+    if let Some(circle) = shape.as_circle() {
+        let radii = Vec2::new(circle.radius, circle.radius);
+        return Some(kurbo::Arc::new(circle.center, radii, 0.0, TAU, 0.0));
+    }
+
+    let mut elements = shape.path_elements(1e-3);
+    let Some(PathEl::MoveTo(p0)) = elements.next() else {
+        return None;
+    };
+    let Some(PathEl::CurveTo(p1, p2, p3)) = elements.next() else {
+        return None;
+    };
+
+    // Each segment of a kurbo arc is the usual cubic approximation of a
+    // circular arc of the same angle, mapped onto the ellipse by an affine
+    // transform. Taking the first segment to start at angle zero, find its
+    // angle from where its tangents meet, and then the transform.
+    let (d0, d3) = (p1 - p0, p2 - p3);
+    let cross = d0.cross(d3);
+    if cross.abs() < 1e-12 * d0.hypot2().max(d3.hypot2()) {
+        return None;
+    }
+    // the tangents meet at `p0 + d0 / ratio`
+    let ratio = cross / (p3 - p0).cross(d3);
+    // `ratio` is `(2 / 3) * (1 - tan²(angle / 4))`
+    let tan2 = 1.0 - 1.5 * ratio;
+    if !(1e-12..1.0).contains(&tan2) {
+        return None;
+    }
+    let tan = tan2.sqrt();
+    let step = 4.0 * tan.atan();
+    let arm = 4.0 / 3.0 * tan;
+    let (sin, cos) = step.sin_cos();
+    let axis_y = d0 / arm;
+    let axis_x = (p3 - p0 - axis_y * sin) / (cos - 1.0);
+    let center = p0 - axis_x;
+    let radius = axis_x.hypot().max(axis_y.hypot());
+    if axis_x.cross(axis_y).abs() < 1e-9 * radius * radius {
+        return None;
+    }
+
+    // check that the whole shape, including the second control point of the
+    // first segment, follows the same ellipse
+    let point = |angle: f64| center + axis_x * angle.cos() + axis_y * angle.sin();
+    let tangent = |angle: f64| axis_y * angle.cos() - axis_x * angle.sin();
+    let eps = 1e-6 * (1.0 + radius);
+    let mut segments = 0;
+    let mut closed = false;
+    for el in std::iter::once(PathEl::CurveTo(p1, p2, p3)).chain(elements) {
+        let PathEl::CurveTo(p1, p2, p3) = el else {
+            if el == PathEl::ClosePath && !closed {
+                closed = true;
+                continue;
+            }
+            return None;
+        };
+        if closed {
+            return None;
+        }
+        let (a0, a1) = (segments as f64 * step, (segments + 1) as f64 * step);
+        let expected = [
+            point(a0) + tangent(a0) * arm,
+            point(a1) - tangent(a1) * arm,
+            point(a1),
+        ];
+        if [p1, p2, p3]
+            .iter()
+            .zip(expected)
+            .any(|(p, q)| p.distance(q) > eps)
+        {
+            return None;
+        }
+        segments += 1;
+    }
+
+    let mut sweep = segments as f64 * step;
+    if (sweep - TAU).abs() < 1e-9 {
+        sweep = TAU;
+    } else if sweep > TAU || closed {
+        return None;
+    }
+    let ellipse = kurbo::Ellipse::from_affine(Affine::new([
+        axis_x.x, axis_x.y, axis_y.x, axis_y.y, center.x, center.y,
+    ]));
+    let (radii, x_rotation) = ellipse.radii_and_rotation();
+    // the start point and direction, on the unit circle of the ellipse's axes
+    let to_unit =
+        Affine::scale_non_uniform(1.0 / radii.x, 1.0 / radii.y) * Affine::rotate(-x_rotation);
+    let start = (to_unit * (p0 - center).to_point()).to_vec2();
+    let direction = (to_unit * d0.to_point()).to_vec2();
+    if start.cross(direction) < 0.0 {
+        sweep = -sweep;
+    }
+    Some(kurbo::Arc::new(
+        center,
+        radii,
+        start.atan2(),
+        sweep,
+        x_rotation,
+    ))
+}
+
+/// Apply a transform to the geometry of a gradient.
+///
+/// Linear gradients can always be transformed, as long as the transform is
+/// invertible. Radial gradients can only be transformed by a combination of
+/// translation, rotation, reflection and uniform scaling, since otherwise
+/// their circles would become ellipses; for other transforms this returns
+/// `None`. Elliptical gradients can be transformed when the result is a
+/// circle, and are then returned as radial gradients.
+///
+/// This is used by the default implementation of
+/// [`RenderContext::gradient_with_transform`].
+///
+/// [`RenderContext::gradient_with_transform`]: crate::RenderContext::gradient_with_transform
+pub fn transform_gradient(gradient: FixedGradient, transform: Affine) -> Option<FixedGradient> {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    let det = transform.determinant();
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+    let linear = |v: Vec2| Vec2::new(a * v.x + c * v.y, b * v.x + d * v.y);
+    match gradient {
+        FixedGradient::Linear(mut linear_gradient) => {
+            let dir = linear_gradient.end - linear_gradient.start;
+            let start = transform * linear_gradient.start;
+            let len2 = dir.hypot2();
+            if len2 > 0.0 {
+                // The position in the gradient is a linear function of the
+                // point; its gradient is the transpose of the inverse of the
+                // transform's linear part applied to `dir / len2`.
+                let grad = Vec2::new(d * dir.x - b * dir.y, -c * dir.x + a * dir.y) / (det * len2);
+                linear_gradient.end = start + grad / grad.hypot2();
+            } else {
+                linear_gradient.end = start;
+            }
+            linear_gradient.start = start;
+            Some(FixedGradient::Linear(linear_gradient))
+        }
+        FixedGradient::Radial(mut radial) => {
+            let scale = det.abs().sqrt();
+            let tolerance = 1e-9 * scale;
+            let rotation = (a - d).abs() <= tolerance && (b + c).abs() <= tolerance;
+            let reflection = (a + d).abs() <= tolerance && (b - c).abs() <= tolerance;
+            if !(rotation || reflection) {
+                return None;
+            }
+            radial.center = transform * radial.center;
+            radial.origin_offset = linear(radial.origin_offset);
+            radial.radius *= scale;
+            radial.start_radius *= scale;
+            Some(FixedGradient::Radial(radial))
+        }
+        FixedGradient::Elliptical(elliptical) => {
+            let (radial, radial_transform) = elliptical.to_radial();
+            transform_gradient(FixedGradient::Radial(radial), transform * radial_transform)
+        }
+    }
+}
+
 /// A type backends can use to represent the default values for a `TextLayout`
 #[non_exhaustive]
 #[allow(missing_docs)]
@@ -163,6 +687,13 @@ pub struct LayoutDefaults {
     pub style: FontStyle,
     pub underline: bool,
     pub strikethrough: bool,
+    pub underline_color: Option<Color>,
+    pub underline_style: UnderlineStyle,
+    pub underline_thickness: Option<f64>,
+    pub underline_skip_ink: bool,
+    pub baseline_offset: f64,
+    pub font_features: Vec<FontFeature>,
+    pub link: Option<String>,
 }
 
 impl LayoutDefaults {
@@ -178,6 +709,15 @@ impl LayoutDefaults {
             TextAttribute::Underline(flag) => self.underline = flag,
             TextAttribute::TextColor(color) => self.fg_color = color,
             TextAttribute::Strikethrough(flag) => self.strikethrough = flag,
+            TextAttribute::UnderlineColor(color) => self.underline_color = Some(color),
+            TextAttribute::UnderlineStyle(style) => self.underline_style = style,
+            TextAttribute::UnderlineThickness(thickness) => {
+                self.underline_thickness = Some(thickness)
+            }
+            TextAttribute::UnderlineSkipInk(flag) => self.underline_skip_ink = flag,
+            TextAttribute::BaselineOffset(offset) => self.baseline_offset = offset,
+            TextAttribute::FontFeatures(features) => self.font_features = features,
+            TextAttribute::Link(url) => self.link = Some(url),
         }
     }
 }
@@ -192,6 +732,615 @@ impl Default for LayoutDefaults {
             style: FontStyle::default(),
             underline: false,
             strikethrough: false,
+            underline_color: None,
+            underline_style: UnderlineStyle::default(),
+            underline_thickness: None,
+            underline_skip_ink: true,
+            baseline_offset: 0.0,
+            font_features: Vec::new(),
+            link: None,
+        }
+    }
+}
+
+/// The links of a text layout, set with [`TextAttribute::Link`].
+///
+/// Backends add the link attributes of a layout in the order they were
+/// given, starting with the default, and keep the result in the layout to
+/// implement [`TextLayout::links`]. A link replaces the parts of earlier
+/// links it overlaps, as with other attributes.
+#[derive(Debug, Clone, Default)]
+pub struct TextLinks {
+    /// Non-overlapping, in text order.
+    spans: Vec<(Range<usize>, Arc<str>)>,
+}
+
+impl TextLinks {
+    /// Create the links of a layout of `text_len` bytes, starting from the
+    /// default link, if there is one.
+    pub fn new(defaults: &LayoutDefaults, text_len: usize) -> Self {
+        let mut links = TextLinks::default();
+        if let Some(url) = &defaults.link {
+            links.add(0..text_len, url);
+        }
+        links
+    }
+
+    /// Make `range` a link to `url`.
+    pub fn add(&mut self, range: Range<usize>, url: &str) {
+        if range.is_empty() {
+            return;
+        }
+        let mut spans = Vec::with_capacity(self.spans.len() + 2);
+        for (span, span_url) in self.spans.drain(..) {
+            if span.start < range.start {
+                spans.push((span.start..span.end.min(range.start), span_url.clone()));
+            }
+            if span.end > range.end {
+                spans.push((span.start.max(range.end)..span.end, span_url));
+            }
+        }
+        spans.push((range, url.into()));
+        spans.sort_by_key(|(range, _)| range.start);
+        self.spans = spans;
+    }
+
+    /// The links, with the range of text each covers, in text order.
+    pub fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.spans
+            .iter()
+            .map(|(range, url)| (range.clone(), &**url))
+            .collect()
+    }
+}
+
+/// Split an image and a destination rect into the pieces of a nine-slice.
+///
+/// `insets` are the widths of the image's borders, in pixels. Each returned
+/// pair is an area of the image and the area of `dst_rect` it is drawn to:
+/// corners keep their size, edges are stretched along their length, and the
+/// center is stretched in both directions. Borders that don't fit are
+/// scaled down, and empty pieces are skipped.
+///
+/// This is used by [`RenderContext::draw_image_nine`].
+///
+/// [`RenderContext::draw_image_nine`]: crate::RenderContext::draw_image_nine
+pub fn nine_slices(image_size: Size, insets: Insets, dst_rect: Rect) -> Vec<(Rect, Rect)> {
+    // scale a pair of borders down to fit in a length, if needed
+    fn fit(a: f64, b: f64, len: f64) -> (f64, f64) {
+        let (a, b) = (a.max(0.0), b.max(0.0));
+        let scale = if a + b > len { len / (a + b) } else { 1.0 };
+        (a * scale, b * scale)
+    }
+
+    let dst_rect = dst_rect.abs();
+    let (left, right) = fit(insets.x0, insets.x1, image_size.width);
+    let (top, bottom) = fit(insets.y0, insets.y1, image_size.height);
+    let (dst_left, dst_right) = fit(left, right, dst_rect.width());
+    let (dst_top, dst_bottom) = fit(top, bottom, dst_rect.height());
+
+    let src_x = [0.0, left, image_size.width - right, image_size.width];
+    let src_y = [0.0, top, image_size.height - bottom, image_size.height];
+    let dst_x = [
+        dst_rect.x0,
+        dst_rect.x0 + dst_left,
+        dst_rect.x1 - dst_right,
+        dst_rect.x1,
+    ];
+    let dst_y = [
+        dst_rect.y0,
+        dst_rect.y0 + dst_top,
+        dst_rect.y1 - dst_bottom,
+        dst_rect.y1,
+    ];
+
+    let mut slices = Vec::with_capacity(9);
+    for row in 0..3 {
+        for col in 0..3 {
+            let src = Rect::new(src_x[col], src_y[row], src_x[col + 1], src_y[row + 1]);
+            let dst = Rect::new(dst_x[col], dst_y[row], dst_x[col + 1], dst_y[row + 1]);
+            if src.area() > 0.0 && dst.area() > 0.0 {
+                slices.push((src, dst));
+            }
+        }
+    }
+    slices
+}
+
+/// The most copies [`image_tiles`] returns; larger tilings draw nothing.
+pub const MAX_IMAGE_TILES: usize = 1 << 16;
+
+/// Split a destination rect into the copies of a tiled image.
+///
+/// The image is tiled at its size, starting at the top-left corner of
+/// `dst_rect`. Each returned pair is the area of the image that is visible
+/// in one copy, in pixels, and the transform from the image's pixels to the
+/// copy's place in `dst_rect`. With [`TileMode::Mirror`], the transform
+/// flips every other copy. Copies at the far edges are cut short, so that
+/// none of them extend past `dst_rect`.
+///
+/// Only copies that overlap `visible`, such as the bounds of the render
+/// target in the same coordinates as `dst_rect`, are returned. Nothing is
+/// returned if the image or `dst_rect` aren't finite, or if there would be
+/// more than [`MAX_IMAGE_TILES`] copies.
+///
+/// This is used by [`RenderContext::draw_image_tiled`].
+///
+/// [`TileMode::Mirror`]: crate::TileMode::Mirror
+/// [`RenderContext::draw_image_tiled`]: crate::RenderContext::draw_image_tiled
+pub fn image_tiles(
+    image_size: Size,
+    dst_rect: Rect,
+    visible: Rect,
+    tile_mode: TileMode,
+) -> Vec<(Rect, Affine)> {
+    // the visible source range, scale and offset of each copy along an axis
+    fn axis(
+        size: f64,
+        (start, end): (f64, f64),
+        (visible_start, visible_end): (f64, f64),
+        tile_mode: TileMode,
+    ) -> Vec<(f64, f64, f64, f64)> {
+        let mut copies = Vec::new();
+        let first = ((visible_start - start) / size).floor().max(0.0);
+        let last = ((visible_end.min(end) - start) / size).ceil();
+        if size <= 0.0 || first >= last || last - first > MAX_IMAGE_TILES as f64 {
+            return copies;
+        }
+        for i in first as usize..last as usize {
+            let origin = start + i as f64 * size;
+            let visible = size.min(end - origin);
+            if tile_mode == TileMode::Mirror && i % 2 == 1 {
+                copies.push((size - visible, size, -1.0, origin + size));
+            } else {
+                copies.push((0.0, visible, 1.0, origin));
+            }
+        }
+        copies
+    }
+
+    let dst_rect = dst_rect.abs();
+    let visible = visible.abs();
+    if !dst_rect.is_finite() || !image_size.is_finite() || visible.is_nan() {
+        return Vec::new();
+    }
+    let columns = axis(
+        image_size.width,
+        (dst_rect.x0, dst_rect.x1),
+        (visible.x0, visible.x1),
+        tile_mode,
+    );
+    let rows = axis(
+        image_size.height,
+        (dst_rect.y0, dst_rect.y1),
+        (visible.y0, visible.y1),
+        tile_mode,
+    );
+    if columns.len().saturating_mul(rows.len()) > MAX_IMAGE_TILES {
+        return Vec::new();
+    }
+    let mut tiles = Vec::with_capacity(columns.len() * rows.len());
+    for &(y0, y1, scale_y, offset_y) in &rows {
+        for &(x0, x1, scale_x, offset_x) in &columns {
+            let src = Rect::new(x0, y0, x1, y1);
+            let transform = Affine::new([scale_x, 0.0, 0.0, scale_y, offset_x, offset_y]);
+            tiles.push((src, transform));
+        }
+    }
+    tiles
+}
+
+/// Rasterize a mesh of triangles with per-vertex colors.
+///
+/// This is the fallback used by [`RenderContext::fill_mesh`] on backends
+/// that can't draw meshes themselves. `vertices` should already be in device
+/// space; each triple of `indices` is a triangle, and triangles with an index
+/// out of range are skipped. Colors are interpolated linearly across each
+/// triangle, and later triangles are drawn over earlier ones.
+///
+/// Returns the image, premultiplied, along with the rect it covers, or
+/// `None` if there is nothing to draw. Edges are antialiased with four
+/// samples per pixel. Very large meshes are rasterized at a lower
+/// resolution, so the rect may be larger than the image.
+///
+/// [`RenderContext::fill_mesh`]: crate::RenderContext::fill_mesh
+pub fn rasterize_mesh(vertices: &[(Point, Color)], indices: &[u16]) -> Option<(Rect, ImageBuf)> {
+    const MAX_SIZE: f64 = 4096.0;
+    const SAMPLES: [(f64, f64); 4] = [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)];
+
+    let triangles: Vec<[(Point, Color); 3]> = indices
+        .chunks_exact(3)
+        .filter_map(|tri| {
+            Some([
+                *vertices.get(tri[0] as usize)?,
+                *vertices.get(tri[1] as usize)?,
+                *vertices.get(tri[2] as usize)?,
+            ])
+        })
+        .collect();
+    let bounds = triangles
+        .iter()
+        .flatten()
+        .map(|&(p, _)| Rect::from_points(p, p))
+        .reduce(|a, b| a.union(b))?
+        .expand();
+    if !(bounds.area() > 0.0 && bounds.area().is_finite()) {
+        return None;
+    }
+
+    let scale = (MAX_SIZE / bounds.width().max(bounds.height())).min(1.0);
+    let width = (bounds.width() * scale).ceil() as usize;
+    let height = (bounds.height() * scale).ceil() as usize;
+    let to_raster = |p: Point| ((p - bounds.origin()) * scale).to_point();
+    // premultiplied rgba for each sample
+    let mut samples = vec![[0.0f64; 4]; width * height * SAMPLES.len()];
+
+    for tri in &triangles {
+        let [a, b, c] = tri.map(|(p, _)| to_raster(p));
+        let colors = tri.map(|(_, color)| {
+            let (r, g, b, a) = color.as_rgba();
+            [r, g, b, a]
+        });
+        let area = (b - a).cross(c - a);
+        if area.abs() < 1e-12 {
+            continue;
+        }
+        let tri_bounds = Rect::from_points(a, b).union_pt(c);
+        let x_range =
+            (tri_bounds.x0.floor().max(0.0) as usize)..(tri_bounds.x1.ceil() as usize).min(width);
+        let y_range =
+            (tri_bounds.y0.floor().max(0.0) as usize)..(tri_bounds.y1.ceil() as usize).min(height);
+        for y in y_range {
+            for x in x_range.clone() {
+                for (i, (dx, dy)) in SAMPLES.iter().enumerate() {
+                    let p = Point::new(x as f64 + dx, y as f64 + dy);
+                    let wa = (c - b).cross(p - b) / area;
+                    let wb = (a - c).cross(p - c) / area;
+                    let wc = 1.0 - wa - wb;
+                    if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                        continue;
+                    }
+                    let mut color = [0.0; 4];
+                    for (j, channel) in color.iter_mut().enumerate() {
+                        *channel = wa * colors[0][j] + wb * colors[1][j] + wc * colors[2][j];
+                    }
+                    let alpha = color[3];
+                    let sample = &mut samples[(y * width + x) * SAMPLES.len() + i];
+                    for j in 0..3 {
+                        sample[j] = color[j] * alpha + sample[j] * (1.0 - alpha);
+                    }
+                    sample[3] = alpha + sample[3] * (1.0 - alpha);
+                }
+            }
+        }
+    }
+
+    let pixels: Vec<u8> = samples
+        .chunks_exact(SAMPLES.len())
+        .flat_map(|pixel| {
+            (0..4).map(move |j| {
+                let sum: f64 = pixel.iter().map(|sample| sample[j]).sum();
+                (sum / SAMPLES.len() as f64 * 255.0)
+                    .round()
+                    .clamp(0.0, 255.0) as u8
+            })
+        })
+        .collect();
+    let rect = Rect::from_origin_size(
+        bounds.origin(),
+        (width as f64 / scale, height as f64 / scale),
+    );
+    Some((
+        rect,
+        ImageBuf::from_raw(pixels, ImageFormat::RgbaPremul, width, height),
+    ))
+}
+
+/// Sort runs of fonts into text order and merge adjacent runs that use the
+/// same font, for [`TextLayout::resolved_fonts`].
+///
+/// Backends usually find the runs line by line, in visual order, so a font
+/// that continues across a line break or a change of direction is split
+/// into several runs.
+///
+/// [`TextLayout::resolved_fonts`]: crate::TextLayout::resolved_fonts
+pub fn merge_font_runs(
+    mut runs: Vec<(Range<usize>, FontDescription)>,
+) -> Vec<(Range<usize>, FontDescription)> {
+    runs.retain(|(range, _)| !range.is_empty());
+    runs.sort_by_key(|(range, _)| range.start);
+    let mut merged: Vec<(Range<usize>, FontDescription)> = Vec::with_capacity(runs.len());
+    for (range, font) in runs {
+        match merged.last_mut() {
+            Some((last, last_font)) if last.end == range.start && *last_font == font => {
+                last.end = range.end;
+            }
+            _ => merged.push((range, font)),
+        }
+    }
+    merged
+}
+
+/// Format font features with the syntax of CSS `font-feature-settings`,
+/// such as `"smcp" 1, "liga" 0`.
+///
+/// Features whose tags are not ASCII are skipped.
+pub fn css_font_features(features: &[FontFeature]) -> String {
+    let mut css = String::new();
+    for feature in features {
+        if let Some(tag) = feature.tag_str() {
+            if !css.is_empty() {
+                css.push_str(", ");
+            }
+            css.push_str(&format!("\"{tag}\" {}", feature.value()));
+        }
+    }
+    css
+}
+
+/// The underline of a run of text, with all of its attributes resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnderlineRun {
+    /// The range of the run, in UTF-8 code units.
+    pub range: Range<usize>,
+    /// The style of the underline.
+    pub style: UnderlineStyle,
+    /// The color of the underline.
+    pub color: Color,
+    /// The thickness of the underline, if one was set.
+    pub thickness: Option<f64>,
+    /// Whether the underline should leave gaps where it crosses the glyphs.
+    pub skip_ink: bool,
+}
+
+/// A type backends can use to resolve the attributes that affect underlines.
+///
+/// Text engines usually treat the style of an underline as part of a single
+/// attribute, while piet sets whether text is underlined and how the
+/// underline looks separately, possibly for different ranges. This collects
+/// those attributes and splits the text into [`UnderlineRun`]s.
+#[derive(Debug, Clone)]
+pub struct UnderlineAttributes {
+    defaults: UnderlineState,
+    spans: Vec<(Range<usize>, TextAttribute)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct UnderlineState {
+    underline: bool,
+    style: UnderlineStyle,
+    color: Option<Color>,
+    thickness: Option<f64>,
+    skip_ink: bool,
+    text_color: Color,
+}
+
+impl UnderlineState {
+    fn apply(&mut self, attr: &TextAttribute) {
+        match *attr {
+            TextAttribute::Underline(flag) => self.underline = flag,
+            TextAttribute::UnderlineStyle(style) => self.style = style,
+            TextAttribute::UnderlineColor(color) => self.color = Some(color),
+            TextAttribute::UnderlineThickness(thickness) => self.thickness = Some(thickness),
+            TextAttribute::UnderlineSkipInk(flag) => self.skip_ink = flag,
+            TextAttribute::TextColor(color) => self.text_color = color,
+            _ => (),
+        }
+    }
+}
+
+impl UnderlineAttributes {
+    /// Create a new set of attributes, starting from the given defaults.
+    pub fn new(defaults: &LayoutDefaults) -> Self {
+        UnderlineAttributes {
+            defaults: UnderlineState {
+                underline: defaults.underline,
+                style: defaults.underline_style,
+                color: defaults.underline_color,
+                thickness: defaults.underline_thickness,
+                skip_ink: defaults.underline_skip_ink,
+                text_color: defaults.fg_color,
+            },
+            spans: Vec::new(),
+        }
+    }
+
+    /// Record an attribute applied to a range of the text.
+    ///
+    /// Attributes that do not affect underlines are ignored. As with range
+    /// attributes, later attributes take precedence over earlier ones.
+    pub fn add(&mut self, range: Range<usize>, attr: &TextAttribute) {
+        if matches!(
+            attr,
+            TextAttribute::Underline(_)
+                | TextAttribute::UnderlineStyle(_)
+                | TextAttribute::UnderlineColor(_)
+                | TextAttribute::UnderlineThickness(_)
+                | TextAttribute::UnderlineSkipInk(_)
+                | TextAttribute::TextColor(_)
+        ) {
+            self.spans.push((range, attr.clone()));
+        }
+    }
+
+    /// Return the underlined runs of a text of length `len`, in order.
+    ///
+    /// Adjacent runs always differ in at least one attribute.
+    pub fn runs(&self, len: usize) -> Vec<UnderlineRun> {
+        let mut bounds = self
+            .spans
+            .iter()
+            .flat_map(|(range, _)| [range.start.min(len), range.end.min(len)])
+            .chain([0, len])
+            .collect::<Vec<_>>();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut runs: Vec<UnderlineRun> = Vec::new();
+        for segment in bounds.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+            let mut state = self.defaults;
+            for (range, attr) in &self.spans {
+                if range.start <= start && range.end >= end {
+                    state.apply(attr);
+                }
+            }
+            if !state.underline {
+                continue;
+            }
+            let run = UnderlineRun {
+                range: start..end,
+                style: state.style,
+                color: state.color.unwrap_or(state.text_color),
+                thickness: state.thickness,
+                skip_ink: state.skip_ink,
+            };
+            match runs.last_mut() {
+                Some(last)
+                    if last.range.end == start
+                        && last.style == run.style
+                        && last.color == run.color
+                        && last.thickness == run.thickness
+                        && last.skip_ink == run.skip_ink =>
+                {
+                    last.range.end = end;
+                }
+                _ => runs.push(run),
+            }
+        }
+        runs
+    }
+}
+
+impl Default for UnderlineAttributes {
+    fn default() -> Self {
+        UnderlineAttributes::new(&LayoutDefaults::default())
+    }
+}
+
+/// Compute the path of the underline for a run of text in a layout.
+///
+/// This is for backends that draw underlines their text engine can't.
+/// `position` is the distance from the baseline down to the top of the
+/// underline, and the path should be stroked with a width of `thickness`.
+pub fn underline_path(
+    layout: &impl TextLayout,
+    run: &UnderlineRun,
+    position: f64,
+    thickness: f64,
+) -> BezPath {
+    underline_path_skipping_ink(layout, run, position, thickness, &[])
+}
+
+/// Compute the path of an underline, as with [`underline_path`], leaving
+/// gaps where it crosses the ink of the glyphs.
+///
+/// `ink` is the ink bounds of the glyphs of the layout, in layout
+/// coordinates. A gap is cut wherever one of these crosses the underline,
+/// with a margin the size of the underline's thickness; this is coarser than
+/// following the outlines of the glyphs, but needs only their bounds.
+pub fn underline_path_skipping_ink(
+    layout: &impl TextLayout,
+    run: &UnderlineRun,
+    position: f64,
+    thickness: f64,
+    ink: &[Rect],
+) -> BezPath {
+    let mut path = BezPath::new();
+    for rect in layout.rects_for_range(run.range.clone()) {
+        let center = rect.center().y;
+        let line = (0..layout.line_count())
+            .filter_map(|i| layout.line_metric(i))
+            .find(|line| line.y_offset + line.height >= center);
+        if let Some(line) = line {
+            let top = line.y_offset + line.baseline + position;
+            let underline = Line::new((rect.x0, top), (rect.x1, top));
+            append_underline_skipping_ink(&mut path, run.style, underline, thickness, ink);
+        }
+    }
+    path
+}
+
+/// Append an underline from the start to the end of `underline`, whose
+/// points are at its top, cutting gaps around the `ink` that crosses it.
+fn append_underline_skipping_ink(
+    path: &mut BezPath,
+    style: UnderlineStyle,
+    underline: Line,
+    thickness: f64,
+    ink: &[Rect],
+) {
+    let (x0, x1, top) = (underline.p0.x, underline.p1.x, underline.p0.y);
+    let margin = thickness.max(1.0);
+    // wavy underlines are taller than they are thick
+    let height = match style {
+        UnderlineStyle::Wavy => margin * 2.0,
+        _ => thickness,
+    };
+    let mut gaps: Vec<_> = ink
+        .iter()
+        .filter(|glyph| glyph.y0 < top + height + margin && glyph.y1 > top - margin)
+        .map(|glyph| (glyph.x0 - margin, glyph.x1 + margin))
+        .filter(|&(gap0, gap1)| gap0 < x1 && gap1 > x0)
+        .collect();
+    gaps.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut x = x0;
+    for (gap0, gap1) in gaps {
+        append_underline(path, style, x, gap0.min(x1), top, thickness);
+        x = x.max(gap1);
+    }
+    append_underline(path, style, x, x1, top, thickness);
+}
+
+fn append_underline(
+    path: &mut BezPath,
+    style: UnderlineStyle,
+    x0: f64,
+    x1: f64,
+    top: f64,
+    thickness: f64,
+) {
+    if x1 <= x0 {
+        return;
+    }
+    let y = top + thickness * 0.5;
+    let line = Line::new((x0, y), (x1, y));
+    match style {
+        UnderlineStyle::Solid => path.extend(line.path_elements(0.1)),
+        UnderlineStyle::Dotted => path.extend(dash_path(line, &[thickness, thickness], 0.0)),
+        UnderlineStyle::Dashed => {
+            path.extend(dash_path(line, &[thickness * 3.0, thickness * 2.0], 0.0))
+        }
+        UnderlineStyle::Wavy => {
+            // Each half wave is a quadratic whose control point is twice as
+            // far from the center line as its peak.
+            let amplitude = thickness.max(1.0);
+            let y = top + amplitude;
+            let n = ((x1 - x0) / (amplitude * 2.0)).ceil();
+            let half = (x1 - x0) / n;
+            path.move_to((x0, y));
+            for i in 0..n as usize {
+                let x = x0 + half * i as f64;
+                let dy = if i % 2 == 0 { -2.0 } else { 2.0 } * amplitude;
+                path.quad_to((x + half * 0.5, y + dy), (x + half, y));
+            }
+        }
+    }
+}
+
+/// If `x` is a single (non-alpha) channel of a color with separate alpha and `a` is the alpha
+/// channel, returns the corresponding channel of the premultiplied version of the color.
+pub fn premul(x: u8, a: u8) -> u8 {
+    let y = (x as u16) * (a as u16);
+    ((y + (y >> 8) + 0x80) >> 8) as u8
+}
+
+/// Takes a buffer of RGBA pixels with separate alpha and premultiplies them in place.
+pub fn premultiply_rgba(data: &mut [u8]) {
+    for px in data.chunks_exact_mut(4) {
+        let a = px[3];
+        for x in &mut px[..3] {
+            *x = premul(*x, a);
         }
     }
 }
@@ -236,6 +1385,32 @@ pub fn first_strong_rtl(text: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// Whether the text at `idx` is laid out right-to-left.
+///
+/// This is the resolved direction of the character at `idx`, or of the last
+/// character when `idx` is the end of the text, under the Unicode
+/// bidirectional algorithm.
+pub fn is_rtl_at(text: &str, idx: usize) -> bool {
+    if text.is_empty() {
+        return false;
+    }
+    let idx = idx.min(text.len() - 1);
+    BidiInfo::new(text, None).levels[idx].is_rtl()
+}
+
+/// The rect for a caret at `x`, spanning `y`, as returned by
+/// [`TextLayout::caret_rect`].
+///
+/// The caret is [`CARET_WIDTH`] wide, on the side of `x` that text in the
+/// given direction flows towards, and is kept within `0.0..=width` when it
+/// fits. `width` should include trailing whitespace, so that a caret after
+/// it stays where it is.
+pub fn caret_rect(x: f64, y: Range<f64>, is_rtl: bool, width: f64) -> Rect {
+    let x0 = if is_rtl { x - CARET_WIDTH } else { x };
+    let x0 = x0.min(width - CARET_WIDTH).max(0.0);
+    Rect::new(x0, y.start, x0 + CARET_WIDTH, y.end)
+}
+
 /// Returns the number of bytes needed to be read from the image buffer.
 pub fn expected_image_buffer_size(row_size: usize, height: usize, stride: usize) -> usize {
     if height == 0 {
@@ -245,6 +1420,67 @@ pub fn expected_image_buffer_size(row_size: usize, height: usize, stride: usize)
     stride * (height - 1) + row_size
 }
 
+/// Checks the arguments describing an image buffer, as passed to
+/// [`RenderContext::make_image_with_stride`], and returns the number of bytes
+/// of the buffer that the image uses.
+///
+/// Sizes are computed with checked arithmetic, so huge dimensions are reported
+/// as [`ImageArgsError::TooLarge`] rather than overflowing. The check also
+/// covers a tightly packed RGBA copy of the image, which several backends make.
+/// Rows may not overlap, so for images of more than one row `stride` must be
+/// at least `width * format.bytes_per_pixel()`. Images with no pixels are
+/// valid and use no bytes.
+///
+/// [`RenderContext::make_image_with_stride`]: crate::RenderContext::make_image_with_stride
+pub fn validate_image_args(
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: crate::ImageFormat,
+    buf_len: usize,
+) -> Result<usize, ImageArgsError> {
+    let row_size = width
+        .checked_mul(format.bytes_per_pixel())
+        .ok_or(ImageArgsError::TooLarge)?;
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(ImageArgsError::TooLarge)?;
+    if row_size == 0 || height == 0 {
+        return Ok(0);
+    }
+    if height > 1 && stride < row_size {
+        return Err(ImageArgsError::StrideTooSmall { stride, row_size });
+    }
+    let expected = stride
+        .checked_mul(height - 1)
+        .and_then(|size| size.checked_add(row_size))
+        .ok_or(ImageArgsError::TooLarge)?;
+    if buf_len < expected {
+        return Err(ImageArgsError::BufferTooSmall {
+            len: buf_len,
+            expected,
+        });
+    }
+    Ok(expected)
+}
+
+/// The pixels of a `width` by `height` image that are inside `region`, or the
+/// whole image if `region` is `None`.
+///
+/// The region is rounded out to whole pixels and clipped to the image, so
+/// the result has integer coordinates. This is for implementing
+/// [`RenderContext::update_image`].
+///
+/// [`RenderContext::update_image`]: crate::RenderContext::update_image
+pub fn image_region(region: Option<Rect>, width: usize, height: usize) -> Rect {
+    let bounds = Rect::new(0.0, 0.0, width as f64, height as f64);
+    match region {
+        Some(region) => region.abs().expand().intersect(bounds),
+        None => bounds,
+    }
+}
+
 /// Converts an image buffer to a tightly packed owned buffer.
 ///
 /// # Notes
@@ -292,10 +1528,589 @@ pub fn image_buffer_to_tightly_packed(
     Ok(new_buff)
 }
 
+/// Converts an image buffer in the given [`ColorSpace`] to a tightly packed
+/// sRGB buffer of the same format.
+///
+/// This is used by backends that can't tag images with a color space.
+/// Premultiplied pixels are unpremultiplied for the conversion; grayscale
+/// pixels are only affected by the transfer function.
+///
+/// [`ColorSpace`]: crate::ColorSpace
+pub fn image_buffer_to_srgb(
+    buff: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: crate::ImageFormat,
+    color_space: crate::ColorSpace,
+) -> Result<Vec<u8>, Error> {
+    use crate::{ColorSpace, ImageFormat};
+
+    let mut data = image_buffer_to_tightly_packed(buff, width, height, stride, format)?;
+    if color_space == ColorSpace::Srgb {
+        return Ok(data);
+    }
+    let to_unit = |x: u8| x as f64 / 255.0;
+    let to_u8 = |x: f64| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match format {
+        ImageFormat::Grayscale => {
+            for px in &mut data {
+                let [grey, _, _] = color_space.rgb_to_srgb([to_unit(*px); 3]);
+                *px = to_u8(grey);
+            }
+        }
+        ImageFormat::Rgb => {
+            for px in data.chunks_exact_mut(3) {
+                let rgb = color_space.rgb_to_srgb([px[0], px[1], px[2]].map(to_unit));
+                px.copy_from_slice(&rgb.map(to_u8));
+            }
+        }
+        ImageFormat::RgbaSeparate | ImageFormat::RgbaPremul => {
+            let premul = format == ImageFormat::RgbaPremul;
+            for px in data.chunks_exact_mut(4) {
+                let a = px[3];
+                if premul && a == 0 {
+                    continue;
+                }
+                let straight = |x: u8| if premul { unpremul(x, a) } else { x };
+                let rgb = [px[0], px[1], px[2]].map(|x| to_unit(straight(x)));
+                let rgb = color_space.rgb_to_srgb(rgb);
+                let alpha = if premul { to_unit(a) } else { 1.0 };
+                px[..3].copy_from_slice(&rgb.map(|x| to_u8(x * alpha)));
+            }
+        }
+    }
+    Ok(data)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn blurred_mask_matches_blurred_rect() {
+        let rect = Rect::new(3.0, 5.0, 23.0, 17.0);
+        let radius = 3.0;
+        let (mask, mask_rect) = compute_blurred_mask(rect, radius);
+        let size = size_for_blurred_rect(rect, radius);
+        let width = size.width as usize;
+        let mut expected = vec![0; width * size.height as usize];
+        let rect_exp = compute_blurred_rect(rect, radius, width, &mut expected);
+        assert_eq!(mask_rect, rect_exp);
+        assert_eq!(mask.len(), expected.len());
+        // `compute_blurred_rect` samples at pixel corners rather than
+        // centers, so only compare the plateau and the total coverage.
+        let center = (width * 11) + 18;
+        assert!(mask[center].abs_diff(expected[center]) <= 1);
+        let area = mask.iter().map(|&a| a as f64 / 255.0).sum::<f64>();
+        assert!((area - rect.area()).abs() < 1.0, "{area}");
+
+        // Without a blur this is just the coverage of the shape.
+        let circle = crate::kurbo::Circle::new((4.0, 4.0), 4.0);
+        let (mask, mask_rect) = compute_blurred_mask(circle, 0.0);
+        assert_eq!(mask_rect, Rect::new(0.0, 0.0, 8.0, 8.0));
+        assert_eq!(mask[3 * 8 + 3], 255);
+        assert_eq!(mask[0], 0);
+        let area = mask.iter().map(|&a| a as f64 / 255.0).sum::<f64>();
+        // The flattened circle is slightly smaller than the true one.
+        assert!(area > 48.0 && area < 16.0 * std::f64::consts::PI, "{area}");
+    }
+
+    #[test]
+    fn dash_path_splits_lines() {
+        use crate::kurbo::{Line, ParamCurve, ParamCurveArclen};
+
+        let line = Line::new((0.0, 0.0), (10.0, 0.0));
+        // kurbo emits the first dash last, so that it can be joined to the
+        // end of a closed subpath.
+        let dashes = |path: BezPath| {
+            let mut dashes = path
+                .segments()
+                .map(|seg| {
+                    let start = seg.eval(0.0).x;
+                    let end = start + seg.arclen(1e-6);
+                    ((start * 1e6).round() / 1e6, (end * 1e6).round() / 1e6)
+                })
+                .collect::<Vec<_>>();
+            dashes.sort_by(|a, b| a.0.total_cmp(&b.0));
+            dashes
+        };
+        assert_eq!(
+            dashes(dash_path(line, &[3.0, 1.0], 0.0)),
+            [(0.0, 3.0), (4.0, 7.0), (8.0, 10.0)]
+        );
+        // A negative offset shifts the pattern along the path.
+        assert_eq!(
+            dashes(dash_path(line, &[3.0, 1.0], -1.5)),
+            [(0.0, 0.5), (1.5, 4.5), (5.5, 8.5), (9.5, 10.0)]
+        );
+        // An odd-length pattern alternates between dashes and gaps.
+        assert_eq!(
+            dashes(dash_path(line, &[2.0], 0.0)),
+            [(0.0, 2.0), (4.0, 6.0), (8.0, 10.0)]
+        );
+        // Degenerate patterns leave the shape alone.
+        assert_eq!(dashes(dash_path(line, &[], 0.0)), [(0.0, 10.0)]);
+        assert_eq!(dashes(dash_path(line, &[0.0, 0.0], 0.0)), [(0.0, 10.0)]);
+    }
+
+    #[test]
+    fn expand_stroke_applies_style() {
+        let line = Line::new((0.0, 0.0), (10.0, 0.0));
+        let area = |style: &StrokeStyle| expand_stroke(line, 2.0, style).area().abs();
+
+        assert!((area(&StrokeStyle::new()) - 20.0).abs() < 1e-6);
+        // square caps add half the width at each end
+        let square = StrokeStyle::new().line_cap(LineCap::Square);
+        assert!((area(&square) - 24.0).abs() < 1e-6);
+        // round caps add a circle, split between the two ends
+        let round = StrokeStyle::new().line_cap(LineCap::Round);
+        assert!((area(&round) - (20.0 + std::f64::consts::PI)).abs() < 1e-2);
+        // three dashes, covering 8 of the 10 units
+        let dashed = StrokeStyle::new().dash_pattern(&[3.0, 1.0]);
+        assert!((area(&dashed) - 16.0).abs() < 1e-6);
+
+        // a miter join extends the corner of a square out to a point
+        let square = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let outline = expand_stroke(square, 2.0, &StrokeStyle::new());
+        assert_eq!(outline.bounding_box(), Rect::new(-1.0, -1.0, 11.0, 11.0));
+        let bevel = StrokeStyle::new().line_join(LineJoin::Bevel);
+        let outline = expand_stroke(square, 2.0, &bevel);
+        assert!(outline.contains(Point::new(-0.5, 5.0)));
+        assert!(!outline.contains(Point::new(-0.9, -0.9)));
+    }
+
+    #[test]
+    fn underline_runs() {
+        let red = Color::rgb8(255, 0, 0);
+        let mut defaults = LayoutDefaults::default();
+        defaults.set(UnderlineStyle::Wavy);
+        let mut attrs = UnderlineAttributes::new(&defaults);
+        attrs.add(2..8, &TextAttribute::Underline(true));
+        attrs.add(4..10, &TextAttribute::UnderlineColor(red));
+        attrs.add(6..7, &TextAttribute::FontSize(20.0));
+        attrs.add(7..12, &TextAttribute::Underline(true));
+
+        let run = |range, color| UnderlineRun {
+            range,
+            style: UnderlineStyle::Wavy,
+            color,
+            thickness: None,
+            skip_ink: true,
+        };
+        assert_eq!(
+            attrs.runs(12),
+            [
+                run(2..4, DEFAULT_TEXT_COLOR),
+                run(4..10, red),
+                run(10..12, DEFAULT_TEXT_COLOR)
+            ]
+        );
+        assert!(UnderlineAttributes::default().runs(12).is_empty());
+    }
+
+    #[test]
+    fn wavy_underline_fills_range() {
+        let mut path = BezPath::new();
+        append_underline(&mut path, UnderlineStyle::Wavy, 3.0, 20.0, 10.0, 1.0);
+        let bounds = path.bounding_box();
+        assert_eq!(bounds.x0, 3.0);
+        assert_eq!(bounds.x1, 20.0);
+        assert!(bounds.y0 >= 10.0 && bounds.y1 <= 12.0);
+
+        let mut path = BezPath::new();
+        append_underline(&mut path, UnderlineStyle::Dashed, 0.0, 10.0, 0.0, 1.0);
+        assert_eq!(path.segments().count(), 2);
+    }
+
+    #[test]
+    fn underline_skips_ink() {
+        let underline = Line::new((0.0, 10.0), (100.0, 10.0));
+        let ink = [
+            // a descender, crossing the underline
+            Rect::new(20.0, 0.0, 30.0, 14.0),
+            // ink clear of the underline
+            Rect::new(50.0, 0.0, 60.0, 8.0),
+            // overlapping descenders leave a single gap
+            Rect::new(70.0, 0.0, 80.0, 14.0),
+            Rect::new(78.0, 0.0, 85.0, 14.0),
+        ];
+        let mut path = BezPath::new();
+        append_underline_skipping_ink(&mut path, UnderlineStyle::Solid, underline, 1.0, &ink);
+        let spans: Vec<_> = path
+            .segments()
+            .map(|seg| (seg.start().x, seg.end().x))
+            .collect();
+        assert_eq!(spans, [(0.0, 19.0), (31.0, 69.0), (86.0, 100.0)]);
+
+        let mut path = BezPath::new();
+        append_underline_skipping_ink(&mut path, UnderlineStyle::Solid, underline, 1.0, &[]);
+        assert_eq!(path.segments().count(), 1);
+    }
+
+    #[test]
+    fn rtl_at_follows_runs() {
+        let text = "abc \u{5d0}\u{5d1}\u{5d2} def";
+        assert!(!is_rtl_at(text, 0));
+        assert!(is_rtl_at(text, 4));
+        assert!(is_rtl_at(text, 6));
+        assert!(!is_rtl_at(text, text.len() - 1));
+        assert!(!is_rtl_at(text, text.len()));
+        assert!(is_rtl_at("\u{5d0}\u{5d1}", 4));
+        assert!(!is_rtl_at("", 0));
+    }
+
+    #[test]
+    fn caret_rect_follows_direction() {
+        // on the side of the edge the text flows towards
+        assert_eq!(
+            caret_rect(10.0, 0.0..12.0, false, 50.0),
+            Rect::new(10.0, 0.0, 11.0, 12.0)
+        );
+        assert_eq!(
+            caret_rect(10.0, 0.0..12.0, true, 50.0),
+            Rect::new(9.0, 0.0, 10.0, 12.0)
+        );
+        // and kept inside the layout at its ends
+        assert_eq!(
+            caret_rect(50.0, 0.0..12.0, false, 50.0),
+            Rect::new(49.0, 0.0, 50.0, 12.0)
+        );
+        assert_eq!(
+            caret_rect(0.0, 0.0..12.0, true, 50.0),
+            Rect::new(0.0, 0.0, 1.0, 12.0)
+        );
+        // an empty layout still has a caret
+        assert_eq!(
+            caret_rect(0.0, 4.0..16.0, false, 0.0),
+            Rect::new(0.0, 4.0, 1.0, 16.0)
+        );
+    }
+
+    #[test]
+    fn css_font_features_syntax() {
+        let features = [
+            FontFeature::SMALL_CAPS,
+            FontFeature::new([0xff, b'a', b'b', b'c'], 1),
+            FontFeature::NO_LIGATURES,
+        ];
+        assert_eq!(css_font_features(&features), r#""smcp" 1, "liga" 0"#);
+        assert_eq!(css_font_features(&[]), "");
+    }
+
+    #[test]
+    fn merge_font_runs_in_text_order() {
+        let font = |family: &str| FontDescription {
+            family: FontFamily::new_unchecked(family),
+            weight: FontWeight::REGULAR,
+            style: FontStyle::Regular,
+            size: 12.0,
+        };
+        let runs = vec![
+            (6..10, font("Sans")),
+            (10..10, font("Emoji")),
+            (0..4, font("Sans")),
+            (4..6, font("Sans")),
+            (10..14, font("Emoji")),
+        ];
+        assert_eq!(
+            merge_font_runs(runs),
+            [(0..10, font("Sans")), (10..14, font("Emoji"))]
+        );
+    }
+
+    #[test]
+    fn later_links_replace_earlier_ones() {
+        let mut defaults = LayoutDefaults::default();
+        defaults.set(TextAttribute::Link("default".into()));
+        let mut links = TextLinks::new(&defaults, 20);
+        links.add(4..8, "a");
+        links.add(6..12, "b");
+        links.add(15..15, "empty");
+        assert_eq!(
+            links.links(),
+            [
+                (0..4, "default"),
+                (4..6, "a"),
+                (6..12, "b"),
+                (12..20, "default")
+            ]
+        );
+    }
+
+    #[test]
+    fn nine_slices_tile_destination() {
+        let size = Size::new(30.0, 30.0);
+        let insets = Insets::uniform(10.0);
+        let dst = Rect::new(5.0, 5.0, 105.0, 55.0);
+        let slices = nine_slices(size, insets, dst);
+        assert_eq!(slices.len(), 9);
+        let area: f64 = slices.iter().map(|(_, d)| d.area()).sum();
+        assert!((area - dst.area()).abs() < 1e-9);
+        // corners are not scaled
+        assert_eq!(
+            slices[0],
+            (Rect::new(0., 0., 10., 10.), Rect::new(5., 5., 15., 15.))
+        );
+        assert_eq!(slices[8].1, Rect::new(95., 45., 105., 55.));
+
+        // a destination narrower than the borders drops the middle column
+        let slices = nine_slices(size, insets, Rect::new(0.0, 0.0, 10.0, 40.0));
+        assert_eq!(slices.len(), 6);
+        assert_eq!(slices[0].1, Rect::new(0., 0., 5., 10.));
+    }
+
+    #[test]
+    fn image_tiles_cover_destination() {
+        let size = Size::new(20.0, 10.0);
+        let dst = Rect::new(5.0, 5.0, 55.0, 20.0);
+        let tiles = image_tiles(size, dst, dst, TileMode::Repeat);
+        assert_eq!(tiles.len(), 6);
+        let area: f64 = tiles.iter().map(|(src, _)| src.area()).sum();
+        assert!((area - dst.area()).abs() < 1e-9);
+        // the last copy in each row is cut short
+        let (src, transform) = tiles[2];
+        assert_eq!(src, Rect::new(0., 0., 10., 10.));
+        assert_eq!(
+            transform.transform_rect_bbox(src),
+            Rect::new(45., 5., 55., 15.)
+        );
+
+        // mirrored copies are flipped, so they meet their neighbours at
+        // matching edges
+        let tiles = image_tiles(size, dst, dst, TileMode::Mirror);
+        assert_eq!(tiles.len(), 6);
+        let (src, transform) = tiles[1];
+        assert_eq!(src, Rect::new(0., 0., 20., 10.));
+        assert_eq!(transform * Point::new(0.0, 0.0), Point::new(45.0, 5.0));
+        let (src, transform) = tiles[4];
+        assert_eq!(src, Rect::new(0., 5., 20., 10.));
+        assert_eq!(transform * Point::new(0.0, 10.0), Point::new(45.0, 15.0));
+
+        assert!(image_tiles(Size::ZERO, dst, dst, TileMode::Repeat).is_empty());
+    }
+
+    #[test]
+    fn image_tiles_are_limited() {
+        let size = Size::new(20.0, 10.0);
+        let dst = Rect::new(5.0, 5.0, 55.0, 20.0);
+
+        // only the copies that can be seen, keeping their place and flips
+        let visible = Rect::new(30.0, 0.0, 50.0, 12.0);
+        let tiles = image_tiles(size, dst, visible, TileMode::Mirror);
+        assert_eq!(tiles.len(), 2);
+        let (src, transform) = tiles[0];
+        assert_eq!(src, Rect::new(0., 0., 20., 10.));
+        assert_eq!(transform * Point::new(0.0, 0.0), Point::new(45.0, 5.0));
+
+        let huge = Rect::new(0.0, 0.0, 1e12, 1e12);
+        assert!(image_tiles(size, huge, huge, TileMode::Repeat).is_empty());
+        let tiles = image_tiles(size, huge, dst, TileMode::Repeat);
+        assert_eq!(tiles.len(), 6);
+
+        let infinite = Rect::new(0.0, 0.0, f64::INFINITY, 10.0);
+        assert!(image_tiles(size, infinite, dst, TileMode::Repeat).is_empty());
+        let nan = Rect::new(0.0, 0.0, f64::NAN, 10.0);
+        assert!(image_tiles(size, nan, dst, TileMode::Repeat).is_empty());
+    }
+
+    #[test]
+    fn rasterize_mesh_interpolates_colors() {
+        let vertices = [
+            (Point::new(0.0, 0.0), Color::RED),
+            (Point::new(10.0, 0.0), Color::BLUE),
+            (Point::new(10.0, 10.0), Color::BLUE),
+            (Point::new(0.0, 10.0), Color::RED),
+        ];
+        let (rect, image) = rasterize_mesh(&vertices, &[0, 1, 2, 0, 2, 3, 0, 1, 9]).unwrap();
+        assert_eq!(rect, Rect::new(0.0, 0.0, 10.0, 10.0));
+        assert_eq!((image.width(), image.height()), (10, 10));
+
+        let pixel = |x: usize, y: usize| {
+            let i = (y * 10 + x) * 4;
+            &image.raw_pixels()[i..i + 4]
+        };
+        // the shared diagonal leaves no seam
+        for i in 0..10 {
+            assert_eq!(pixel(i, i)[3], 255);
+        }
+        assert!(pixel(0, 5)[0] > 230 && pixel(0, 5)[2] < 25);
+        assert!(pixel(9, 5)[2] > 230 && pixel(9, 5)[0] < 25);
+
+        assert!(rasterize_mesh(&vertices, &[0, 1]).is_none());
+        assert!(rasterize_mesh(&vertices, &[0, 0, 1]).is_none());
+    }
+
+    #[test]
+    fn round_corners_of_rect_match_rounded_rect() {
+        let rect = Rect::new(0.0, 0.0, 40.0, 20.0);
+        let rounded = round_corners(rect, 5.0);
+        let expected = rect.to_rounded_rect(5.0);
+        assert!((rounded.area() / expected.area() - 1.0).abs() < 1e-3);
+        assert_eq!(rounded.bounding_box(), rect);
+
+        // the radius is limited to half of the shortest side
+        let rounded = round_corners(rect, 100.0);
+        let expected = rect.to_rounded_rect(10.0);
+        assert!((rounded.area() / expected.area() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn round_corners_keeps_ends_and_curves() {
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.0));
+        path.line_to((10.0, 0.0));
+        path.line_to((10.0, 10.0));
+        path.quad_to((0.0, 20.0), (0.0, 10.0));
+        let rounded = round_corners(&path, 2.0);
+        let els = rounded.elements();
+        assert_eq!(els.first(), Some(&PathEl::MoveTo(Point::new(0.0, 0.0))));
+        assert_eq!(els[1], PathEl::LineTo(Point::new(8.0, 0.0)));
+        assert!(matches!(els[2], PathEl::CurveTo(_, _, p) if p == Point::new(10.0, 2.0)));
+        // the corner between the line and the curve is not rounded
+        assert_eq!(els[3], PathEl::LineTo(Point::new(10.0, 10.0)));
+        assert_eq!(
+            els.last(),
+            Some(&PathEl::QuadTo((0.0, 20.0).into(), (0.0, 10.0).into()))
+        );
+
+        assert_eq!(round_corners(&path, 0.0), path);
+    }
+
+    #[test]
+    fn as_arc_recognizes_ellipses_and_arcs() {
+        use kurbo::{Arc, Ellipse};
+        use std::f64::consts::{PI, TAU};
+
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+        let ellipse = Ellipse::new((20.0, 30.0), (40.0, 10.0), 0.5);
+        let arc = as_arc(&ellipse).unwrap();
+        assert!(arc.center.distance((20.0, 30.0).into()) < 1e-6);
+        assert!(close(arc.radii.x, 40.0) && close(arc.radii.y, 10.0));
+        assert!(close(arc.x_rotation, 0.5));
+        assert_eq!(arc.sweep_angle, TAU);
+
+        let partial = Arc::new((5.0, 5.0), (30.0, 30.0), 1.0, -2.5, 0.0);
+        let arc = as_arc(&partial).unwrap();
+        assert!(close(arc.radii.x, 30.0) && close(arc.radii.y, 30.0));
+        assert!(close(arc.sweep_angle, -2.5));
+        let points = |arc: &Arc| arc.path_elements(1e-3).map(|el| el.end_point().unwrap());
+        for (p, q) in points(&arc).zip(points(&partial)) {
+            assert!(p.distance(q) < 1e-6);
+        }
+
+        let mut wave = BezPath::new();
+        wave.move_to((0.0, 0.0));
+        wave.curve_to((10.0, 10.0), (20.0, -10.0), (30.0, 0.0));
+        assert!(as_arc(&wave).is_none());
+        // an arc followed by anything else isn't one
+        let mut path: BezPath = Arc::new((0.0, 0.0), (10.0, 10.0), 0.0, PI, 0.0)
+            .path_elements(1e-3)
+            .collect();
+        assert!(as_arc(&path).is_some());
+        path.line_to((0.0, 0.0));
+        assert!(as_arc(&path).is_none());
+
+        // a closing segment is only allowed after a full ellipse
+        let mut closed: BezPath = ellipse.path_elements(1e-3).collect();
+        closed.close_path();
+        assert_eq!(as_arc(&closed).unwrap().sweep_angle, TAU);
+        let mut closed: BezPath = partial.path_elements(1e-3).collect();
+        closed.close_path();
+        assert!(as_arc(&closed).is_none());
+    }
+
+    #[test]
+    fn as_arc_checks_every_control_point() {
+        // the tangents of this curve meet, as an arc's would, but its
+        // second control point is off the matching ellipse
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.0));
+        path.curve_to((0.0, 15.0), (28.0, 6.0), (30.0, 0.0));
+        assert!(as_arc(&path).is_none());
+    }
+
+    #[test]
+    fn transform_linear_gradient() {
+        let gradient = FixedGradient::Linear(crate::FixedLinearGradient {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(10.0, 0.0),
+            stops: Vec::new(),
+        });
+        let transforms = [
+            Affine::rotate(0.5).then_translate(Vec2::new(3.0, 4.0)),
+            Affine::scale_non_uniform(2.0, 0.5),
+            Affine::skew(0.5, 0.0),
+            Affine::new([1.0, 2.0, -0.5, 1.5, 7.0, -3.0]),
+        ];
+        for transform in transforms {
+            let Some(FixedGradient::Linear(transformed)) =
+                transform_gradient(gradient.clone(), transform)
+            else {
+                panic!("linear gradients can always be transformed");
+            };
+            // the position of a point is the same as the position of the
+            // point it came from in the original gradient
+            for p in [
+                Point::new(2.0, 1.0),
+                Point::new(-5.0, 8.0),
+                Point::new(13.0, -2.0),
+            ] {
+                let q = transform * p;
+                let dir = transformed.end - transformed.start;
+                let t = (q - transformed.start).dot(dir) / dir.hypot2();
+                assert!((t - p.x / 10.0).abs() < 1e-9, "{transform:?} {p:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn transform_radial_gradient() {
+        let gradient = FixedGradient::Radial(crate::FixedRadialGradient {
+            center: Point::new(10.0, 10.0),
+            origin_offset: Vec2::new(2.0, 0.0),
+            radius: 5.0,
+            start_radius: 1.0,
+            stops: Vec::new(),
+        });
+        let transform = Affine::rotate(std::f64::consts::FRAC_PI_2).then_scale(2.0);
+        let Some(FixedGradient::Radial(transformed)) =
+            transform_gradient(gradient.clone(), transform)
+        else {
+            panic!("similarities keep radial gradients circular");
+        };
+        assert!((transformed.center - Point::new(-20.0, 20.0)).hypot() < 1e-9);
+        assert!((transformed.origin_offset - Vec2::new(0.0, 4.0)).hypot() < 1e-9);
+        assert!((transformed.radius - 10.0).abs() < 1e-9);
+        assert!((transformed.start_radius - 2.0).abs() < 1e-9);
+
+        assert!(transform_gradient(gradient.clone(), Affine::FLIP_X).is_some());
+        assert!(
+            transform_gradient(gradient.clone(), Affine::scale_non_uniform(2.0, 1.0)).is_none()
+        );
+        assert!(transform_gradient(gradient, Affine::scale(0.0)).is_none());
+    }
+
+    #[test]
+    fn transform_elliptical_gradient() {
+        let gradient = FixedGradient::Elliptical(crate::FixedEllipticalGradient {
+            center: Point::new(10.0, 10.0),
+            origin_offset: Vec2::new(2.0, 0.0),
+            radii: Vec2::new(5.0, 10.0),
+            stops: Vec::new(),
+        });
+        let Some(FixedGradient::Radial(transformed)) =
+            transform_gradient(gradient.clone(), Affine::scale_non_uniform(2.0, 1.0))
+        else {
+            panic!("the ellipse is stretched into a circle");
+        };
+        assert!((transformed.center - Point::new(20.0, 10.0)).hypot() < 1e-9);
+        assert!((transformed.origin_offset - Vec2::new(4.0, 0.0)).hypot() < 1e-9);
+        assert!((transformed.radius - 10.0).abs() < 1e-9);
+
+        assert!(transform_gradient(gradient, Affine::IDENTITY).is_none());
+    }
+
     #[test]
     fn test_count_until_utf16() {
         // Notes on this input:
@@ -315,6 +2130,18 @@ mod tests {
         assert_eq!(count_until_utf16("", 0), None);
     }
 
+    #[test]
+    fn image_region_rounds_out_and_clips() {
+        assert_eq!(image_region(None, 8, 4), Rect::new(0.0, 0.0, 8.0, 4.0));
+        let region = Rect::new(6.5, 3.2, 1.5, 10.0);
+        assert_eq!(
+            image_region(Some(region), 8, 4),
+            Rect::new(1.0, 3.0, 7.0, 4.0)
+        );
+        let outside = image_region(Some(Rect::new(10.0, 0.0, 12.0, 2.0)), 8, 4);
+        assert_eq!(outside.area(), 0.0);
+    }
+
     #[test]
     fn test_image_buffer_to_tightly_packed() {
         let w: u16 = 7;
@@ -385,4 +2212,56 @@ mod tests {
         let result = result.unwrap_err();
         assert_eq!(result.to_string(), Error::InvalidInput.to_string());
     }
+
+    #[test]
+    fn validate_image_args_checks_sizes() {
+        let rgba = ImageFormat::RgbaSeparate;
+        assert_eq!(validate_image_args(3, 2, 12, rgba, 24), Ok(24));
+        // the last row doesn't need padding
+        assert_eq!(validate_image_args(3, 2, 16, rgba, 28), Ok(28));
+        assert_eq!(validate_image_args(0, 5, 0, rgba, 0), Ok(0));
+        assert_eq!(validate_image_args(5, 0, 20, rgba, 0), Ok(0));
+        assert_eq!(
+            validate_image_args(3, 2, 16, rgba, 27),
+            Err(ImageArgsError::BufferTooSmall {
+                len: 27,
+                expected: 28
+            })
+        );
+        assert_eq!(
+            validate_image_args(3, 2, 8, rgba, 100),
+            Err(ImageArgsError::StrideTooSmall {
+                stride: 8,
+                row_size: 12
+            })
+        );
+        assert_eq!(
+            validate_image_args(usize::MAX / 2, 1, usize::MAX, rgba, 0),
+            Err(ImageArgsError::TooLarge)
+        );
+        assert_eq!(
+            validate_image_args(1, 4, usize::MAX / 2, ImageFormat::Grayscale, 0),
+            Err(ImageArgsError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn test_image_buffer_to_srgb() {
+        use crate::{ColorSpace, ImageFormat};
+
+        let buf = [128, 128, 128, 255, 64, 64, 64, 128, 0, 0, 0, 0];
+        let srgb = image_buffer_to_srgb(&buf, 3, 1, 12, ImageFormat::RgbaPremul, ColorSpace::Srgb);
+        assert_eq!(srgb.unwrap(), buf);
+
+        let linear =
+            image_buffer_to_srgb(&buf, 3, 1, 12, ImageFormat::RgbaPremul, ColorSpace::Linear);
+        assert_eq!(
+            linear.unwrap(),
+            [188, 188, 188, 255, 94, 94, 94, 128, 0, 0, 0, 0]
+        );
+
+        let grey =
+            image_buffer_to_srgb(&[128], 1, 1, 1, ImageFormat::Grayscale, ColorSpace::Linear);
+        assert_eq!(grey.unwrap(), [188]);
+    }
 }